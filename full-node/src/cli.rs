@@ -67,6 +67,9 @@ pub struct CliOptionsRun {
     /// Output to stdout: auto, none, informant, logs, logs-json.
     #[arg(long, default_value = "auto")]
     pub output: Output,
+    /// Format of the logs: text, json. `--log-format json` is equivalent to `--output logs-json`.
+    #[arg(long, default_value = "text")]
+    pub log_format: LogFormat,
     /// Log filter. Example: `foo=trace`
     #[arg(long)]
     pub log: Vec<String>,
@@ -85,16 +88,38 @@ pub struct CliOptionsRun {
     /// Bind point of the JSON-RPC server ("none" or `<ip>:<port>`).
     #[arg(long, default_value = "127.0.0.1:9944", value_parser = parse_json_rpc_address)]
     pub json_rpc_address: JsonRpcAddress,
+    /// Allow calling JSON-RPC functions that modify the state of the node or reveal information
+    /// about its keys, such as `author_rotateKeys`, even when the JSON-RPC server isn't bound to
+    /// a loopback address.
+    #[arg(long)]
+    pub json_rpc_unsafe_methods: bool,
     /// List of secret phrases to insert in the keystore of the node. Used to author blocks.
     #[arg(long, value_parser = decode_sr25519_private_key)]
     // TODO: also automatically add the same keys through ed25519?
     pub keystore_memory: Vec<[u8; 64]>,
+    /// File containing the passphrase used to encrypt the keys that the node saves on disk.
+    /// A trailing new line, if any, is ignored.
+    #[arg(long)]
+    pub keystore_passphrase_file: Option<PathBuf>,
     /// Address of a Jaeger agent to send traces to (hint: port is typically 6831).
     #[arg(long)]
     pub jaeger: Option<SocketAddr>,
     /// Do not load or store anything on disk.
     #[arg(long)]
     pub tmp: bool,
+    /// Number of finalized blocks below the latest finalized block whose storage is kept.
+    #[arg(long, default_value = "0")]
+    pub finalized_storage_history: u64,
+    /// Run the off-chain worker of the runtime after each new best block.
+    #[arg(long)]
+    pub offchain_worker: bool,
+    /// Maximum size, in bytes, that the Yamux receive window of each substream can grow to.
+    #[arg(long, default_value = "16777216")]
+    pub yamux_max_receive_window: u64,
+    /// Maximum number of substreams that each peer can have simultaneously opened on a
+    /// connection. Derived from the number of chains and protocols if not provided.
+    #[arg(long)]
+    pub max_inbound_substreams: Option<usize>,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -166,6 +191,12 @@ pub enum Output {
     LogsJson,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
 #[derive(Debug, Clone)]
 pub struct JsonRpcAddress(pub Option<SocketAddr>);
 
@@ -190,7 +221,7 @@ pub struct Bootnode {
 fn parse_bootnode(string: &str) -> Result<Bootnode, String> {
     let mut address = string.parse::<Multiaddr>().map_err(|err| err.to_string())?;
     let Some(ProtocolRef::P2p(peer_id)) = address.iter().last() else {
-        return Err("Bootnode address must end with /p2p/...".into())
+        return Err("Bootnode address must end with /p2p/...".into());
     };
     let peer_id = PeerId::from_bytes(peer_id.to_vec())
         .map_err(|(err, _)| format!("Failed to parse PeerId in bootnode: {err}"))?;
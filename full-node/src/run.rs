@@ -31,91 +31,92 @@ use smoldot::{
 };
 use std::{
     borrow::Cow,
-    fs, io, iter,
+    fs, iter,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, OnceLock},
     thread,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::Duration,
 };
 
 mod consensus_service;
 mod database_thread;
 mod jaeger_service;
+mod json_logs;
 mod json_rpc_service;
+mod log_filter;
 mod network_service;
 
 /// Runs the node using the given configuration. Catches `SIGINT` signals and stops if one is
 /// detected.
 pub async fn run(cli_options: cli::CliOptionsRun) {
-    // Determine the actual CLI output by replacing `Auto` with the actual value.
-    let cli_output = if let cli::Output::Auto = cli_options.output {
-        if atty::is(atty::Stream::Stderr) && cli_options.log.is_empty() {
-            cli::Output::Informant
-        } else {
-            cli::Output::Logs
+    // Determine the actual CLI output by replacing `Auto` with the actual value, and `Logs` with
+    // `LogsJson` if the JSON log format has been requested.
+    let cli_output = match (cli_options.output, cli_options.log_format) {
+        (cli::Output::Auto | cli::Output::Logs, cli::LogFormat::Json) => cli::Output::LogsJson,
+        (cli::Output::Auto, cli::LogFormat::Text) => {
+            if atty::is(atty::Stream::Stderr) && cli_options.log.is_empty() {
+                cli::Output::Informant
+            } else {
+                cli::Output::Logs
+            }
         }
-    } else {
-        cli_options.output
+        (output, _) => output,
     };
     debug_assert!(!matches!(cli_output, cli::Output::Auto));
 
+    // Identifiers of the chains, indexed by the `chain_index` found in the log messages. Filled
+    // once the chain specifications have been loaded, and used when printing JSON logs.
+    let log_chain_names = Arc::new(OnceLock::<Vec<String>>::new());
+
     // Setup the logging system of the binary.
-    if !matches!(cli_output, cli::Output::None) {
-        let mut builder = env_logger::Builder::new();
-        builder.parse_filters("cranelift=error"); // TODO: temporary work around for https://github.com/smol-dot/smoldot/issues/263
-        if matches!(cli_output, cli::Output::Informant) {
-            // TODO: display infos/warnings in a nicer way ; in particular, immediately put the informant on top of warnings
-            builder.filter_level(log::LevelFilter::Info);
-        } else {
-            builder.filter_level(log::LevelFilter::Debug);
-            for filter in &cli_options.log {
+    // The filtering directives can later be modified through the JSON-RPC server.
+    let logger = if !matches!(cli_output, cli::Output::None) {
+        let is_informant = matches!(cli_output, cli::Output::Informant);
+        let is_json = matches!(cli_output, cli::Output::LogsJson);
+        let cli_filters = cli_options.log.clone();
+        let color = cli_options.color.clone();
+        let log_chain_names = log_chain_names.clone();
+
+        let logger = log_filter::ReloadableLogger::new(move |additional_filters| {
+            let mut builder = env_logger::Builder::new();
+            builder.parse_filters("cranelift=error"); // TODO: temporary work around for https://github.com/smol-dot/smoldot/issues/263
+            if is_informant {
+                // TODO: display infos/warnings in a nicer way ; in particular, immediately put the informant on top of warnings
+                builder.filter_level(log::LevelFilter::Info);
+            } else {
+                builder.filter_level(log::LevelFilter::Debug);
+                for filter in &cli_filters {
+                    builder.parse_filters(filter);
+                }
+            }
+
+            for filter in additional_filters {
                 builder.parse_filters(filter);
             }
-        }
 
-        if matches!(cli_output, cli::Output::LogsJson) {
-            builder.write_style(env_logger::WriteStyle::Never);
-            builder.format(|mut formatter, record| {
-                // TODO: consider using the "kv" feature of he "logs" crate and output individual fields
-                #[derive(serde::Serialize)]
-                struct Record<'a> {
-                    timestamp: u128,
-                    target: &'a str,
-                    level: &'static str,
-                    message: String,
-                }
+            if is_json {
+                builder.write_style(env_logger::WriteStyle::Never);
+                builder.format({
+                    let log_chain_names = log_chain_names.clone();
+                    move |formatter, record| {
+                        json_logs::write_record(formatter, record, &log_chain_names)
+                    }
+                });
+            } else {
+                builder.write_style(match color {
+                    cli::ColorChoice::Always => env_logger::WriteStyle::Always,
+                    cli::ColorChoice::Never => env_logger::WriteStyle::Never,
+                });
+            }
 
-                serde_json::to_writer(
-                    &mut formatter,
-                    &Record {
-                        timestamp: SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .map(|d| d.as_millis())
-                            .unwrap_or(0),
-                        target: record.target(),
-                        level: match record.level() {
-                            log::Level::Trace => "trace",
-                            log::Level::Debug => "debug",
-                            log::Level::Info => "info",
-                            log::Level::Warn => "warn",
-                            log::Level::Error => "error",
-                        },
-                        message: format!("{}", record.args()),
-                    },
-                )
-                .map_err(|err| io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
-                io::Write::write_all(formatter, b"\n")?;
-                Ok(())
-            });
-        } else {
-            builder.write_style(match cli_options.color {
-                cli::ColorChoice::Always => env_logger::WriteStyle::Always,
-                cli::ColorChoice::Never => env_logger::WriteStyle::Never,
-            });
-        }
+            builder.build()
+        });
 
-        builder.init();
-    }
+        logger.install();
+        Some(logger)
+    } else {
+        None
+    };
 
     log::info!("smoldot full node");
     log::info!("Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.");
@@ -184,6 +185,13 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
             (None, None)
         };
 
+    // Chains are indexed in the same order as the chains of the network service.
+    let _ = log_chain_names.set(
+        iter::once(chain_spec.id().to_owned())
+            .chain(relay_chain_spec.as_ref().map(|spec| spec.id().to_owned()))
+            .collect(),
+    );
+
     // TODO: don't unwrap?
     let relay_genesis_chain_information = relay_chain_spec
         .as_ref()
@@ -219,6 +227,7 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
             &chain_spec,
             genesis_chain_information.as_ref(),
             db_path,
+            cli_options.finalized_storage_history,
             matches!(cli_output, cli::Output::Informant),
         )
         .await;
@@ -236,6 +245,7 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                 relay_chain_spec,
                 relay_genesis_chain_information.as_ref().unwrap().as_ref(),
                 relay_db_path,
+                cli_options.finalized_storage_history,
                 matches!(cli_output, cli::Output::Informant),
             )
             .await
@@ -245,6 +255,43 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         None
     };
 
+    // Periodically remove from the databases the storage of the finalized blocks that are too
+    // far below the finalized block.
+    if cli_options.finalized_storage_history != 0 {
+        for database in iter::once(&database).chain(relay_chain_database.as_ref()) {
+            // A weak reference is used so that this task doesn't prevent the database from
+            // being closed.
+            let database = Arc::downgrade(database);
+            threads_pool.spawn_ok(async move {
+                loop {
+                    futures_timer::Delay::new(Duration::from_secs(30)).await;
+                    let Some(database) = database.upgrade() else {
+                        break;
+                    };
+
+                    // Blocks are pruned in small groups, in order to not prevent other accesses
+                    // to the database for too long.
+                    loop {
+                        match database
+                            .with_database(|db| db.prune_finalized_storage_history(64))
+                            .await
+                        {
+                            Ok(true) => {}
+                            Ok(false) => break,
+                            Err(err) => {
+                                // The database is corrupted or inaccessible. Other accesses to
+                                // the database will run into the same problem, and there is no
+                                // point in trying again.
+                                log::warn!("Failed to prune the storage of old blocks: {err}");
+                                return;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    }
+
     let database_finalized_block_hash = database
         .with_database(|db| db.finalized_block_hash().unwrap())
         .await;
@@ -436,21 +483,34 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
             noise_key,
             tasks_executor: &mut |task| threads_pool.spawn_ok(task),
             jaeger_service: jaeger_service.clone(),
+            yamux_max_receive_window: cli_options.yamux_max_receive_window,
+            max_inbound_substreams: cli_options.max_inbound_substreams,
         })
         .await
         .unwrap();
 
     let mut network_events_receivers = network_events_receivers.into_iter();
 
+    // The passphrase is read from a file rather than passed on the command line, as the
+    // command line of a process is visible to all the users of the machine.
+    let keystore_passphrase = cli_options.keystore_passphrase_file.as_ref().map(|path| {
+        let mut passphrase =
+            fs::read_to_string(path).expect("failed to read keystore passphrase file");
+        let trimmed_len = passphrase.trim_end_matches(['\r', '\n']).len();
+        passphrase.truncate(trimmed_len);
+        passphrase
+    });
+
     let keystore = Arc::new({
         let mut keystore = keystore::Keystore::new(
             base_storage_directory
                 .as_ref()
                 .map(|path| path.join(chain_spec.id()).join("keys")),
+            keystore_passphrase.clone(),
             rand::random(),
         )
         .await
-        .unwrap();
+        .unwrap_or_else(|err| panic!("failed to load the keystore: {err}"));
         for private_key in cli_options.keystore_memory {
             keystore.insert_sr25519_memory(keystore::KeyNamespace::all(), &private_key);
         }
@@ -462,11 +522,12 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         genesis_block_hash,
         network_events_receiver: network_events_receivers.next().unwrap(),
         network_service: (network_service.clone(), 0),
-        database,
+        database: database.clone(),
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-        keystore,
+        keystore: keystore.clone(),
         jaeger_service: jaeger_service.clone(),
         slot_duration_author_ratio: 43691_u16,
+        offchain_worker: cli_options.offchain_worker,
     })
     .await;
 
@@ -493,13 +554,16 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                         base_storage_directory
                             .as_ref()
                             .map(|path| path.join(chain_spec.id()).join("keys")),
+                        keystore_passphrase,
                         rand::random(),
                     )
                     .await
-                    .unwrap(),
+                    .unwrap_or_else(|err| panic!("failed to load the keystore: {err}")),
                 ),
                 jaeger_service, // TODO: consider passing a different jaeger service with a different service name
                 slot_duration_author_ratio: 43691_u16,
+                // Off-chain workers are only run for the chain the node is primarily connected to.
+                offchain_worker: false,
             })
             .await,
         )
@@ -518,6 +582,12 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         let result = json_rpc_service::JsonRpcService::new(json_rpc_service::Config {
             tasks_executor: { &mut move |task| threads_pool.spawn_ok(task) },
             bind_address,
+            allow_unsafe_methods: bind_address.ip().is_loopback()
+                || cli_options.json_rpc_unsafe_methods,
+            keystore,
+            database,
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+            logger: logger.clone(),
         })
         .await;
 
@@ -715,12 +785,14 @@ async fn open_database(
     chain_spec: &chain_spec::ChainSpec,
     genesis_chain_information: chain::chain_information::ChainInformationRef<'_>,
     db_path: Option<PathBuf>,
+    finalized_storage_history: u64,
     show_progress: bool,
 ) -> (full_sqlite::SqliteFullDatabase, bool) {
     // The `unwrap()` here can panic for example in case of access denied.
     match background_open_database(
         db_path.clone(),
         chain_spec.block_number_bytes().into(),
+        finalized_storage_history,
         show_progress,
     )
     .await
@@ -754,6 +826,7 @@ async fn open_database(
                 .unwrap(),
                 exec_hint: executor::vm::ExecHint::Oneshot,
                 allow_unresolved_imports: true,
+                fuel_limit: None,
             })
             .unwrap()
             .runtime_version()
@@ -785,6 +858,7 @@ async fn open_database(
 async fn background_open_database(
     path: Option<PathBuf>,
     block_number_bytes: usize,
+    finalized_storage_history: u64,
     show_progress: bool,
 ) -> Result<full_sqlite::DatabaseOpen, full_sqlite::InternalError> {
     let (tx, rx) = oneshot::channel();
@@ -795,6 +869,7 @@ async fn background_open_database(
         move || {
             let result = full_sqlite::open(full_sqlite::Config {
                 block_number_bytes,
+                finalized_storage_history,
                 ty: if let Some(path) = &path {
                     full_sqlite::ConfigTy::Disk(path)
                 } else {
@@ -809,6 +884,7 @@ async fn background_open_database(
     if thread_spawn_result.is_err() {
         return full_sqlite::open(full_sqlite::Config {
             block_number_bytes,
+            finalized_storage_history,
             ty: if let Some(path) = &path {
                 full_sqlite::ConfigTy::Disk(path)
             } else {
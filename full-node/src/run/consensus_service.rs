@@ -24,10 +24,13 @@
 // TODO: doc
 // TODO: re-review this once finished
 
+mod offchain_http;
+mod offchain_worker;
+
 use crate::run::{database_thread, jaeger_service, network_service};
 
 use core::{num::NonZeroU32, ops};
-use futures::{lock::Mutex, prelude::*};
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
 use hashbrown::HashSet;
 use smoldot::{
     author,
@@ -96,6 +99,10 @@ pub struct Config<'a> {
     /// Note that this value doesn't determine the moment when creating the block has ended, but
     /// the moment when creating the block should start its final phase.
     pub slot_duration_author_ratio: u16,
+
+    /// If `true`, the off-chain worker of the runtime is executed every time a new best block
+    /// has been verified.
+    pub offchain_worker: bool,
 }
 
 /// Identifier for a blocks request to be performed.
@@ -212,12 +219,31 @@ impl ConsensusService {
             finalized_block_hash,
         }));
 
+        // The off-chain worker runs in a separate task in order to not block the syncing.
+        let offchain_worker = if config.offchain_worker {
+            let (tx, rx) = mpsc::channel(0);
+            (config.tasks_executor)(Box::pin(offchain_worker::run(
+                offchain_worker::Config {
+                    database: config.database.clone(),
+                    keystore: config.keystore.clone(),
+                    network_service: config.network_service.0.clone(),
+                    network_chain_index: config.network_service.1,
+                    block_number_bytes: config.block_number_bytes,
+                },
+                rx,
+            )));
+            Some(tx)
+        } else {
+            None
+        };
+
         // Spawn the background task that synchronizes blocks and updates the database.
         (config.tasks_executor)({
             let mut sync = all::AllSync::new(all::Config {
                 chain_information: finalized_chain_information,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: false,
+                best_block_policy: all::BestBlockPolicy::MostPrimarySlots,
                 sources_capacity: 32,
                 blocks_capacity: {
                     // This is the maximum number of blocks between two consecutive justifications.
@@ -225,6 +251,9 @@ impl ConsensusService {
                 },
                 max_disjoint_headers: 1024,
                 max_requests_per_block: NonZeroU32::new(3).unwrap(),
+                // The full node doesn't warp sync.
+                max_parallel_warp_sync_requests: NonZeroU32::new(1).unwrap(),
+                warp_sync_resume_progress: None,
                 download_ahead_blocks: {
                     // Assuming a verification speed of 1k blocks/sec and a 99th download time
                     // percentile of two second, the number of blocks to download ahead of time
@@ -233,6 +262,10 @@ impl ConsensusService {
                     // the chain and the machine of the user.
                     NonZeroU32::new(2000).unwrap()
                 },
+                // Full nodes always use the optimistic syncing.
+                sync_mode: all::SyncMode::Optimistic,
+                // Ignored when the blocks bodies and storage are synchronized.
+                probabilistic_finality_depth: None,
                 full: Some(all::ConfigFull {
                     finalized_runtime: {
                         // Builds the runtime of the finalized block.
@@ -250,6 +283,7 @@ impl ConsensusService {
                             heap_pages,
                             exec_hint: executor::vm::ExecHint::CompileAheadOfTime, // TODO: probably should be decided by the optimisticsync
                             allow_unresolved_imports: false,
+                            fuel_limit: None,
                         })
                         .unwrap()
                     },
@@ -265,8 +299,9 @@ impl ConsensusService {
                 block_authoring: None,
                 authored_block: None,
                 slot_duration_author_ratio: config.slot_duration_author_ratio,
+                offchain_worker,
                 keystore: config.keystore,
-                finalized_block_storage,
+                finalized_block_storage: Arc::new(finalized_block_storage),
                 sync_state: sync_state.clone(),
                 network_service: config.network_service.0,
                 network_chain_index: config.network_service.1,
@@ -330,6 +365,13 @@ struct SyncBackground {
     /// See [`Config::slot_duration_author_ratio`].
     slot_duration_author_ratio: u16,
 
+    /// Sending side of the channel connected to the task running the off-chain worker. `None`
+    /// if [`Config::offchain_worker`] was `false`.
+    ///
+    /// The channel has a capacity of zero, meaning that at most one block waits for the
+    /// off-chain worker to be run while it is running against a previous block.
+    offchain_worker: Option<mpsc::Sender<offchain_worker::Job>>,
+
     /// After a block has been authored, it is inserted here while waiting for the `sync` to
     /// import it. Contains the block height, the block hash, the SCALE-encoded block header, and
     /// the list of SCALE-encoded extrinsics of the block.
@@ -344,7 +386,10 @@ struct SyncBackground {
     // While reading the storage from the database is an option, doing so considerably slows down
     /// the verification, and also makes it impossible to insert blocks in the database in
     /// parallel of this verification.
-    finalized_block_storage: BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>,
+    ///
+    /// Wrapped within an `Arc` in order for the off-chain worker to be able to access a snapshot
+    /// of it. Modifying it while the off-chain worker is running clones it.
+    finalized_block_storage: Arc<BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>>,
 
     sync_state: Arc<Mutex<SyncState>>,
 
@@ -439,7 +484,9 @@ impl SyncBackground {
                         .keys()
                         .await
                         .filter(|(namespace, _)| namespace_filter.map_or(true, |n| *namespace == n))
-                        .map(|(_, key)| key)
+                        // Authorities are identified by 32 bytes public keys, which excludes
+                        // ECDSA keys.
+                        .filter_map(|(_, key)| <[u8; 32]>::try_from(key).ok())
                         .collect::<Vec<_>>() // TODO: collect overhead :-/
                 };
 
@@ -457,6 +504,12 @@ impl SyncBackground {
                                 author::build::Builder::new(author::build::Config {
                                     consensus: author::build::ConfigConsensus::Aura {
                                         current_authorities: finalized_authorities_list,
+                                        parent_slot_number: self
+                                            .sync
+                                            .best_block_header()
+                                            .digest
+                                            .aura_pre_runtime()
+                                            .map(|digest| digest.slot_number),
                                         local_authorities: local_authorities.iter(),
                                         now_from_unix_epoch: SystemTime::now()
                                             .duration_since(SystemTime::UNIX_EPOCH)
@@ -574,7 +627,7 @@ impl SyncBackground {
 
                             let id = *self.peers_source_id_map.get(&peer_id).unwrap();
                             // TODO: log the outcome
-                            match self.sync.block_announce(id, header.scale_encoding_vec(self.sync.block_number_bytes()), is_best) {
+                            match self.sync.block_announce(id, &header.scale_encoding_vec(self.sync.block_number_bytes()), is_best) {
                                 all::BlockAnnounceOutcome::HeaderVerify => {},
                                 all::BlockAnnounceOutcome::TooOld { .. } => {},
                                 all::BlockAnnounceOutcome::AlreadyInChain => {},
@@ -640,8 +693,6 @@ impl SyncBackground {
             _ => panic!(),
         };
 
-        // TODO: it is possible that the current best block is already the same authoring slot as the slot we want to claim ; unclear how to solve this
-
         let parent_number = self.sync.best_block_number();
         log::debug!(
             "block-author-start; parent_hash={}; parent_number={}",
@@ -777,8 +828,28 @@ impl SyncBackground {
                             get.inject_value(value.map(|(val, vers)| (iter::once(val), vers)));
                         continue;
                     }
-                    author::build::BuilderAuthoring::NextKey(_) => {
-                        todo!() // TODO: implement
+                    author::build::BuilderAuthoring::NextKey(next_key) => {
+                        // Access the storage of the best block. Can return `̀None` if not syncing
+                        // in full mode, in which case we shouldn't have reached this code.
+                        let best_block_storage_access = self.sync.best_block_storage().unwrap();
+
+                        let found = {
+                            let key = next_key.key();
+                            best_block_storage_access
+                                .next_key(key.as_ref(), |k| {
+                                    self.finalized_block_storage
+                                        .range::<[u8], _>((
+                                            ops::Bound::Excluded(k),
+                                            ops::Bound::Unbounded,
+                                        ))
+                                        .next()
+                                        .map(|(k, _)| &k[..])
+                                })
+                                .map(|k| k.to_vec()) // TODO: overhead
+                        };
+
+                        block_authoring = next_key.inject_key(found);
+                        continue;
                     }
                     author::build::BuilderAuthoring::PrefixKeys(prefix_key) => {
                         // Access the storage of the best block. Can return `̀None` if not syncing
@@ -844,7 +915,7 @@ impl SyncBackground {
         // the local node is a source of block similar to networking peers.
         match self.sync.block_announce(
             self.block_author_sync_source,
-            block.scale_encoded_header.clone(),
+            &block.scale_encoded_header,
             true, // Since the new block is a child of the current best block, it always becomes the new best.
         ) {
             all::BlockAnnounceOutcome::HeaderVerify
@@ -865,6 +936,37 @@ impl SyncBackground {
         ));
     }
 
+    /// Sends the current best block to the task running the off-chain worker, if any.
+    ///
+    /// `scale_encoded_header` must be the header of the current best block. Does nothing if the
+    /// off-chain worker is still busy with previous blocks.
+    fn start_offchain_worker(&mut self, scale_encoded_header: &[u8]) {
+        let Some(offchain_worker) = &mut self.offchain_worker else {
+            return;
+        };
+
+        // Access the storage of the best block. Can return `̀None` if not syncing
+        // in full mode, in which case we shouldn't have reached this code.
+        let best_block_storage_access = self.sync.best_block_storage().unwrap();
+        let job = offchain_worker::Job {
+            scale_encoded_header: scale_encoded_header.to_vec(),
+            runtime: best_block_storage_access.runtime().clone(), // TODO: overhead here with cloning
+            storage: offchain_worker::StorageSnapshot {
+                storage_diff: best_block_storage_access.storage_diff().clone(),
+                finalized_block_storage: self.finalized_block_storage.clone(),
+            },
+        };
+
+        if offchain_worker.try_send(job).is_err() {
+            log::debug!(
+                "offchain-worker-busy; skipped_hash={}",
+                HashDisplay(&header::hash_from_scale_encoded_header(
+                    scale_encoded_header
+                ))
+            );
+        }
+    }
+
     /// Starts all the new network requests that should be started.
     // TODO: handle obsolete requests
     async fn start_network_requests(&mut self) {
@@ -1092,6 +1194,10 @@ impl SyncBackground {
 
                                 self.sync = sync_out;
 
+                                if is_new_best {
+                                    self.start_offchain_worker(&scale_encoded_header_to_verify);
+                                }
+
                                 // Announce the newly-verified block to all the sources that might
                                 // not be aware of it. We can never be guaranteed that a certain
                                 // source does *not* know about a block, however it is not a big
@@ -1228,6 +1334,8 @@ impl SyncBackground {
                             }
 
                             // TODO: maybe write in a separate task? but then we can't access the finalized storage immediately after?
+                            let finalized_block_storage =
+                                Arc::make_mut(&mut self.finalized_block_storage);
                             for block in &finalized_blocks {
                                 for (key, value, ()) in block
                                     .full
@@ -1237,7 +1345,7 @@ impl SyncBackground {
                                     .diff_iter_unordered()
                                 {
                                     if let Some(value) = value {
-                                        self.finalized_block_storage.insert(
+                                        finalized_block_storage.insert(
                                             key.to_owned(),
                                             (
                                                 value.to_owned(),
@@ -1245,7 +1353,7 @@ impl SyncBackground {
                                             ),
                                         );
                                     } else {
-                                        let _was_there = self.finalized_block_storage.remove(key);
+                                        let _was_there = finalized_block_storage.remove(key);
                                         // TODO: if a block inserts a new value, then removes it in the next block, the key will remain in `finalized_block_storage`; either solve this or document this
                                         // assert!(_was_there.is_some());
                                     }
@@ -1359,6 +1467,20 @@ async fn database_blocks(
                     Err(full_sqlite::InsertError::Duplicate) => {} // TODO: this should be an error ; right now we silence them because non-finalized blocks aren't loaded from the database at startup, resulting in them being downloaded again
                     Err(err) => panic!("{}", err),
                 }
+
+                // Changes to the off-chain storage performed through off-chain indexing are only
+                // applied once the block is finalized.
+                database
+                    .offchain_storage_apply(
+                        block
+                            .full
+                            .as_ref()
+                            .unwrap()
+                            .offchain_storage_changes
+                            .diff_iter_unordered()
+                            .map(|(k, v, ())| (k, v)),
+                    )
+                    .unwrap();
             }
         })
         .await
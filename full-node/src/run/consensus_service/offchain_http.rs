@@ -0,0 +1,634 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! HTTP requests started by the off-chain worker.
+//!
+//! Only plain-text HTTP/1.1 is supported, as the full node doesn't include any TLS
+//! implementation. Each request opens a new TCP connection, which is closed once the response
+//! has been read.
+//!
+//! The body of a request is buffered in memory and sent at once when the runtime indicates that
+//! the body is complete or starts waiting for the response. The body of a response, however, is
+//! read from the connection while the runtime reads it.
+
+use async_std::net::TcpStream;
+use futures::{io::BufReader, prelude::*};
+use futures_timer::Delay;
+use smoldot::executor::host::{HttpError, HttpRequestStatus};
+use std::{
+    cmp, io, mem,
+    pin::Pin,
+    str,
+    task::Poll,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum number of requests that can exist at the same time.
+const MAX_REQUESTS: usize = 64;
+
+/// Maximum size, in bytes, of the status line and headers of a response.
+const MAX_RESPONSE_HEADERS_SIZE: usize = 64 * 1024;
+
+/// Maximum size, in bytes, of a line that delimits the chunks of a chunked response body.
+const MAX_CHUNK_LINE_SIZE: usize = 1024;
+
+/// Collection of HTTP requests started during an execution of the off-chain worker.
+///
+/// Requests that are still in progress are aborted when this collection is destroyed.
+pub(super) struct HttpRequests {
+    /// Identifier to try to assign to the next request.
+    next_request_id: u16,
+
+    /// List of requests, indexed by their identifier.
+    requests: hashbrown::HashMap<u16, Request, fnv::FnvBuildHasher>,
+}
+
+enum Request {
+    /// Request hasn't been sent yet. Headers can be added as long as no body has been written.
+    Building {
+        method: String,
+        /// Host and port to connect to.
+        host: String,
+        port: u16,
+        /// Value to put in the `Host` header if the runtime doesn't provide one.
+        authority: String,
+        /// Path and query of the request.
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        body_started: bool,
+    },
+    /// Request has been sent. Waiting for the status line and headers of the response.
+    Sending(Pin<Box<dyn Future<Output = Result<Response, io::Error>> + Send>>),
+    /// Headers of the response have been received.
+    Response(Response),
+    /// An error happened on the connection.
+    Failed,
+}
+
+impl HttpRequests {
+    /// Initializes a new empty collection.
+    pub(super) fn new() -> Self {
+        HttpRequests {
+            next_request_id: 0,
+            requests: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+        }
+    }
+
+    /// Starts a new request. Returns `None` if the method or the URI is invalid or unsupported,
+    /// or if too many requests exist.
+    pub(super) fn start(&mut self, method: &[u8], uri: &[u8]) -> Option<u16> {
+        if self.requests.len() >= MAX_REQUESTS {
+            return None;
+        }
+
+        let method = str::from_utf8(method)
+            .ok()
+            .filter(|m| !m.is_empty() && m.bytes().all(|b| b.is_ascii_alphabetic()))?;
+
+        // Only plain-text HTTP is supported.
+        let uri = str::from_utf8(uri).ok()?;
+        let uri = uri.strip_prefix("http://")?;
+        let uri = uri.split('#').next().unwrap();
+        let (authority, path) = match uri.find(['/', '?']) {
+            Some(pos) if uri[pos..].starts_with('?') => (&uri[..pos], format!("/{}", &uri[pos..])),
+            Some(pos) => (&uri[..pos], uri[pos..].to_owned()),
+            None => (uri, "/".to_owned()),
+        };
+        if path
+            .bytes()
+            .any(|b| b.is_ascii_whitespace() || b.is_ascii_control())
+        {
+            return None;
+        }
+        let (host, port) = parse_authority(authority)?;
+
+        let request_id = loop {
+            let id = self.next_request_id;
+            self.next_request_id = self.next_request_id.wrapping_add(1);
+            if !self.requests.contains_key(&id) {
+                break id;
+            }
+        };
+
+        self.requests.insert(
+            request_id,
+            Request::Building {
+                method: method.to_owned(),
+                host,
+                port,
+                authority: authority.to_owned(),
+                path,
+                headers: Vec::new(),
+                body: Vec::new(),
+                body_started: false,
+            },
+        );
+
+        Some(request_id)
+    }
+
+    /// Adds a header to a request. Returns `false` if the request ID is invalid, if the body of
+    /// the request has started being written, or if the header is malformed.
+    ///
+    /// The `Connection`, `Content-Length` and `Transfer-Encoding` headers are silently ignored,
+    /// as they are always determined by this module.
+    pub(super) fn add_header(&mut self, request_id: u16, name: &[u8], value: &[u8]) -> bool {
+        let Some(Request::Building {
+            headers,
+            body_started: false,
+            ..
+        }) = self.requests.get_mut(&request_id)
+        else {
+            return false;
+        };
+
+        let (Ok(name), Ok(value)) = (str::from_utf8(name), str::from_utf8(value)) else {
+            return false;
+        };
+        if name.is_empty()
+            || !name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+            || value.bytes().any(|b| b == b'\r' || b == b'\n')
+        {
+            return false;
+        }
+
+        if !["connection", "content-length", "transfer-encoding"]
+            .iter()
+            .any(|h| name.eq_ignore_ascii_case(h))
+        {
+            headers.push((name.to_owned(), value.trim().to_owned()));
+        }
+
+        true
+    }
+
+    /// Writes a chunk of the body of a request. An empty chunk indicates that the body is
+    /// complete, in which case the request is sent.
+    pub(super) fn write_body(&mut self, request_id: u16, chunk: &[u8]) -> Result<(), HttpError> {
+        let Some(Request::Building {
+            body, body_started, ..
+        }) = self.requests.get_mut(&request_id)
+        else {
+            return Err(HttpError::Invalid);
+        };
+
+        if chunk.is_empty() {
+            self.send(request_id);
+        } else {
+            *body_started = true;
+            body.extend_from_slice(chunk);
+        }
+
+        Ok(())
+    }
+
+    /// Waits until either the responses of all the given requests have been received or the
+    /// deadline is reached, then returns the status of each request.
+    ///
+    /// Requests whose body isn't complete yet are sent.
+    pub(super) async fn wait(
+        &mut self,
+        request_ids: &[u16],
+        deadline: Option<u64>,
+    ) -> Vec<HttpRequestStatus> {
+        for request_id in request_ids {
+            if matches!(
+                self.requests.get(request_id),
+                Some(Request::Building { .. })
+            ) {
+                self.send(*request_id);
+            }
+        }
+
+        {
+            let requests = &mut self.requests;
+            let all_finished = future::poll_fn(move |cx| {
+                let mut all_finished = true;
+                for request_id in request_ids {
+                    let Some(request) = requests.get_mut(request_id) else {
+                        continue;
+                    };
+                    let Request::Sending(sending) = request else {
+                        continue;
+                    };
+
+                    match sending.as_mut().poll(cx) {
+                        Poll::Ready(Ok(response)) => *request = Request::Response(response),
+                        Poll::Ready(Err(_)) => *request = Request::Failed,
+                        Poll::Pending => all_finished = false,
+                    }
+                }
+
+                if all_finished {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            });
+
+            future::select(all_finished, deadline_timer(deadline)).await;
+        }
+
+        request_ids
+            .iter()
+            .map(|request_id| match self.requests.get(request_id) {
+                None => HttpRequestStatus::Invalid,
+                Some(Request::Building { .. }) => unreachable!(),
+                Some(Request::Sending(_)) => HttpRequestStatus::DeadlineReached,
+                Some(Request::Response(response)) => {
+                    HttpRequestStatus::Finished(response.status_code)
+                }
+                Some(Request::Failed) => HttpRequestStatus::IoError,
+            })
+            .collect()
+    }
+
+    /// Returns the headers of the response of a request. The list is empty if the request ID is
+    /// invalid or if the response hasn't been received yet.
+    pub(super) fn response_headers(&self, request_id: u16) -> Vec<(Vec<u8>, Vec<u8>)> {
+        match self.requests.get(&request_id) {
+            Some(Request::Response(response)) => response
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Reads the next chunk of the body of the response of a request, waiting for the response
+    /// if necessary. An empty chunk indicates that the end of the body has been reached, in
+    /// which case the request is removed.
+    pub(super) async fn read_body(
+        &mut self,
+        request_id: u16,
+        max_size: usize,
+        deadline: Option<u64>,
+    ) -> Result<Vec<u8>, HttpError> {
+        match self.wait(&[request_id], deadline).await[0] {
+            HttpRequestStatus::Finished(_) => {}
+            HttpRequestStatus::DeadlineReached => return Err(HttpError::DeadlineReached),
+            HttpRequestStatus::IoError => return Err(HttpError::IoError),
+            HttpRequestStatus::Invalid => return Err(HttpError::Invalid),
+        }
+
+        let outcome = {
+            let Some(Request::Response(response)) = self.requests.get_mut(&request_id) else {
+                unreachable!()
+            };
+            // Reading the body is cancel-safe, meaning that the request can continue to be used
+            // if the deadline is reached.
+            let read = response.body.read(max_size);
+            futures::pin_mut!(read);
+            match future::select(read, deadline_timer(deadline)).await {
+                future::Either::Left((result, _)) => Some(result),
+                future::Either::Right(_) => None,
+            }
+        };
+
+        match outcome {
+            Some(Ok(chunk)) => {
+                if chunk.is_empty() {
+                    self.requests.remove(&request_id);
+                }
+                Ok(chunk)
+            }
+            Some(Err(_)) => {
+                self.requests.insert(request_id, Request::Failed);
+                Err(HttpError::IoError)
+            }
+            None => Err(HttpError::DeadlineReached),
+        }
+    }
+
+    /// Sends the request with the given ID, which must be in the [`Request::Building`] state.
+    fn send(&mut self, request_id: u16) {
+        let request = self.requests.get_mut(&request_id).unwrap();
+        let Request::Building {
+            method,
+            host,
+            port,
+            authority,
+            path,
+            headers,
+            body,
+            ..
+        } = mem::replace(request, Request::Failed)
+        else {
+            unreachable!()
+        };
+
+        let mut encoded = format!("{method} {path} HTTP/1.1\r\n");
+        if !headers.iter().any(|(n, _)| n.eq_ignore_ascii_case("host")) {
+            encoded.push_str(&format!("Host: {authority}\r\n"));
+        }
+        encoded.push_str("Connection: close\r\n");
+        for (name, value) in &headers {
+            encoded.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !body.is_empty() || (method != "GET" && method != "HEAD") {
+            encoded.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        encoded.push_str("\r\n");
+        let mut encoded = encoded.into_bytes();
+        encoded.extend_from_slice(&body);
+
+        let is_head = method == "HEAD";
+        *request = Request::Sending(Box::pin(async move {
+            let mut stream = TcpStream::connect((&host[..], port)).await?;
+            stream.write_all(&encoded).await?;
+            stream.flush().await?;
+            Response::read_headers(BufReader::new(stream), is_head).await
+        }));
+    }
+}
+
+/// Response to a request whose headers have been received.
+struct Response {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: BodyReader,
+}
+
+impl Response {
+    /// Reads the status line and headers of a response from the given stream.
+    async fn read_headers(
+        mut stream: BufReader<TcpStream>,
+        is_head: bool,
+    ) -> Result<Self, io::Error> {
+        let mut line_buffer = Vec::new();
+        let mut headers_size_remaining = MAX_RESPONSE_HEADERS_SIZE;
+
+        loop {
+            let status_line =
+                read_header_line(&mut stream, &mut line_buffer, &mut headers_size_remaining)
+                    .await?;
+            let status_code = status_line
+                .split(' ')
+                .nth(1)
+                .filter(|_| status_line.starts_with("HTTP/1."))
+                .and_then(|code| code.parse::<u16>().ok())
+                .filter(|code| (100..1000).contains(code))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad status line"))?;
+
+            let mut headers = Vec::new();
+            loop {
+                let line =
+                    read_header_line(&mut stream, &mut line_buffer, &mut headers_size_remaining)
+                        .await?;
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad header"))?;
+                headers.push((name.trim().to_owned(), value.trim().to_owned()));
+            }
+
+            // Informational responses are followed with the actual response.
+            if status_code < 200 {
+                continue;
+            }
+
+            let header_value = |name: &str| {
+                headers
+                    .iter()
+                    .rev()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| &v[..])
+            };
+
+            let framing = if is_head || status_code == 204 || status_code == 304 {
+                Framing::Length(0)
+            } else if header_value("transfer-encoding")
+                .map_or(false, |v| v.to_ascii_lowercase().contains("chunked"))
+            {
+                Framing::ChunkHeader
+            } else if let Some(length) = header_value("content-length") {
+                Framing::Length(length.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "bad content-length")
+                })?)
+            } else {
+                Framing::UntilEof
+            };
+
+            return Ok(Response {
+                status_code,
+                headers,
+                body: BodyReader {
+                    stream,
+                    framing,
+                    line_buffer: Vec::new(),
+                },
+            });
+        }
+    }
+}
+
+/// Reads the body of a response from its connection.
+struct BodyReader {
+    stream: BufReader<TcpStream>,
+    framing: Framing,
+    /// Partially-read line. Kept here in order for reading to be cancel-safe.
+    line_buffer: Vec<u8>,
+}
+
+enum Framing {
+    /// Body has the given number of bytes remaining.
+    Length(u64),
+    /// Body is chunked, and the next line contains the size of the next chunk.
+    ChunkHeader,
+    /// Body is chunked, and the given number of bytes remain in the current chunk.
+    Chunk(u64),
+    /// Body ends when the connection is closed.
+    UntilEof,
+    /// Body has been entirely read.
+    Finished,
+}
+
+impl BodyReader {
+    /// Reads at most `max_size` bytes of body. Returns an empty buffer if the end of the body has
+    /// been reached.
+    ///
+    /// This function is cancel-safe.
+    async fn read(&mut self, max_size: usize) -> Result<Vec<u8>, io::Error> {
+        if max_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        loop {
+            match self.framing {
+                Framing::Finished | Framing::Length(0) => {
+                    self.framing = Framing::Finished;
+                    return Ok(Vec::new());
+                }
+                Framing::Chunk(0) => {
+                    // Each chunk is followed with an empty line.
+                    let line =
+                        read_line(&mut self.stream, &mut self.line_buffer, MAX_CHUNK_LINE_SIZE)
+                            .await?;
+                    if !line.is_empty() {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad chunk"));
+                    }
+                    self.framing = Framing::ChunkHeader;
+                }
+                Framing::Length(remaining) | Framing::Chunk(remaining) => {
+                    let mut buffer =
+                        vec![0; usize::try_from(cmp::min(remaining, max_size as u64)).unwrap()];
+                    let num_read = self.stream.read(&mut buffer).await?;
+                    if num_read == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    buffer.truncate(num_read);
+                    let remaining = remaining - u64::try_from(num_read).unwrap();
+                    self.framing = match self.framing {
+                        Framing::Length(_) => Framing::Length(remaining),
+                        _ => Framing::Chunk(remaining),
+                    };
+                    return Ok(buffer);
+                }
+                Framing::UntilEof => {
+                    let mut buffer = vec![0; max_size];
+                    let num_read = self.stream.read(&mut buffer).await?;
+                    if num_read == 0 {
+                        self.framing = Framing::Finished;
+                    }
+                    buffer.truncate(num_read);
+                    return Ok(buffer);
+                }
+                Framing::ChunkHeader => {
+                    let line =
+                        read_line(&mut self.stream, &mut self.line_buffer, MAX_CHUNK_LINE_SIZE)
+                            .await?;
+                    let size = line.split(';').next().unwrap().trim();
+                    let size = u64::from_str_radix(size, 16)
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad chunk"))?;
+                    if size != 0 {
+                        self.framing = Framing::Chunk(size);
+                        continue;
+                    }
+
+                    // The last chunk is followed with optional trailers and an empty line.
+                    loop {
+                        let line =
+                            read_line(&mut self.stream, &mut self.line_buffer, MAX_CHUNK_LINE_SIZE)
+                                .await?;
+                        if line.is_empty() {
+                            break;
+                        }
+                    }
+                    self.framing = Framing::Finished;
+                }
+            }
+        }
+    }
+}
+
+/// Reads a line terminated with `\n` from `stream`, and returns it without its terminator.
+///
+/// The bytes that are read are first appended to `buffer`, making this function cancel-safe as
+/// long as the same buffer is passed again. Returns an error if the line is longer than
+/// `max_len` bytes.
+async fn read_line(
+    stream: &mut BufReader<TcpStream>,
+    buffer: &mut Vec<u8>,
+    max_len: usize,
+) -> Result<String, io::Error> {
+    let limit = u64::try_from(max_len.saturating_sub(buffer.len())).unwrap();
+    (&mut *stream).take(limit).read_until(b'\n', buffer).await?;
+
+    if buffer.last() != Some(&b'\n') {
+        return Err(if buffer.len() >= max_len {
+            io::Error::new(io::ErrorKind::InvalidData, "line too long")
+        } else {
+            io::ErrorKind::UnexpectedEof.into()
+        });
+    }
+
+    let mut line = mem::take(buffer);
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad line"))
+}
+
+/// Similar to [`read_line`], but reads a line of the status line and headers of a response and
+/// subtracts its size from `size_remaining`.
+async fn read_header_line(
+    stream: &mut BufReader<TcpStream>,
+    buffer: &mut Vec<u8>,
+    size_remaining: &mut usize,
+) -> Result<String, io::Error> {
+    let line = read_line(stream, buffer, *size_remaining).await?;
+    // The `+ 2` accounts for the line terminator, and guarantees that reading the headers ends.
+    *size_remaining = size_remaining.saturating_sub(line.len() + 2);
+    Ok(line)
+}
+
+/// Splits the authority part of a URI into a host and a port.
+fn parse_authority(authority: &str) -> Option<(String, u16)> {
+    // User information isn't supported.
+    if authority.contains('@') {
+        return None;
+    }
+
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        match rest.strip_prefix(':') {
+            Some(port) => (host, Some(port)),
+            None if rest.is_empty() => (host, None),
+            None => return None,
+        }
+    } else {
+        match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => 80,
+    };
+
+    Some((host.to_owned(), port))
+}
+
+/// Returns a future that is ready when the given deadline, in milliseconds since the UNIX epoch,
+/// is reached. Never ready if `None`.
+fn deadline_timer(deadline: Option<u64>) -> future::Either<Delay, future::Pending<()>> {
+    match deadline {
+        Some(deadline) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::max_value()))
+                .unwrap_or(0);
+            future::Either::Left(Delay::new(Duration::from_millis(
+                deadline.saturating_sub(now),
+            )))
+        }
+        None => future::Either::Right(future::pending()),
+    }
+}
@@ -0,0 +1,486 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background task that runs the off-chain worker of the runtime.
+//!
+//! The syncing sends a [`Job`] to this task every time a new best block has been verified. A
+//! [`Job`] contains a snapshot of the storage of the block, so that the syncing can continue
+//! while the off-chain worker runs.
+
+use super::offchain_http;
+use crate::run::{database_thread, network_service};
+
+use core::ops;
+use futures::{channel::mpsc, prelude::*};
+use smoldot::{
+    executor::{self, storage_diff},
+    header,
+    identity::keystore,
+    informant::HashDisplay,
+    sync::all::TrieEntryVersion,
+    transactions::validate,
+};
+use std::{
+    collections::BTreeMap,
+    iter,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Configuration of the off-chain worker task.
+pub(super) struct Config {
+    /// Database where the off-chain storage is found.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Keystore to use to generate keys and to determine whether the node is a validator.
+    pub keystore: Arc<keystore::Keystore>,
+
+    /// Service used to gossip the transactions submitted by the off-chain worker.
+    pub network_service: Arc<network_service::NetworkService>,
+
+    /// Index, within the [`Config::network_service`], of the chain.
+    pub network_chain_index: usize,
+
+    /// Number of bytes of the block number in the header of the blocks of the chain.
+    pub block_number_bytes: usize,
+}
+
+/// Block to run the off-chain worker against.
+pub(super) struct Job {
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: Vec<u8>,
+
+    /// Runtime of the block.
+    pub runtime: executor::host::HostVmPrototype,
+
+    /// Storage of the block.
+    pub storage: StorageSnapshot,
+}
+
+/// Snapshot of the storage of a non-finalized block.
+pub(super) struct StorageSnapshot {
+    /// Changes to apply to [`StorageSnapshot::finalized_block_storage`] in order to obtain the
+    /// storage of the block.
+    pub storage_diff: storage_diff::TrieDiff<TrieEntryVersion>,
+
+    /// Storage of the latest finalized block at the time when the snapshot has been created.
+    pub finalized_block_storage: Arc<BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>>,
+}
+
+impl StorageSnapshot {
+    /// Returns the storage value of the block at the given key.
+    fn get(&self, key: &[u8]) -> Option<(&[u8], TrieEntryVersion)> {
+        match self.storage_diff.diff_get(key) {
+            Some((None, _)) => None,
+            Some((Some(value), version)) => Some((value, *version)),
+            None => self
+                .finalized_block_storage
+                .get(key)
+                .map(|(value, version)| (&value[..], *version)),
+        }
+    }
+
+    /// Returns the key that immediately follows `key` in the storage of the block.
+    fn next_key<'a>(&'a self, key: &'a [u8]) -> Option<&'a [u8]> {
+        let mut search = key;
+        loop {
+            let in_finalized = self
+                .finalized_block_storage
+                .range::<[u8], _>((ops::Bound::Excluded(search), ops::Bound::Unbounded))
+                .next()
+                .map(|(k, _)| &k[..]);
+            match self.storage_diff.storage_next_key(search, in_finalized) {
+                storage_diff::StorageNextKey::Found(found) => return found,
+                storage_diff::StorageNextKey::NextOf(next) => search = next,
+            }
+        }
+    }
+
+    /// Returns the list of keys of the storage of the block that start with the given prefix,
+    /// ordered lexicographically.
+    fn prefix_keys(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        self.storage_diff
+            .storage_prefix_keys_ordered(
+                prefix,
+                self.finalized_block_storage
+                    .range::<[u8], _>((ops::Bound::Included(prefix), ops::Bound::Unbounded))
+                    .take_while(|(k, _)| k.starts_with(prefix))
+                    .map(|(k, _)| &k[..]),
+            )
+            .map(|k| k.as_ref().to_vec())
+            .collect()
+    }
+}
+
+/// Runs the off-chain worker against each job received on `jobs`, one after the other, until
+/// the sending side is closed.
+pub(super) async fn run(config: Config, mut jobs: mpsc::Receiver<Job>) {
+    while let Some(job) = jobs.next().await {
+        run_job(&config, job).await;
+    }
+}
+
+/// Runs the off-chain worker against the block of the given job.
+async fn run_job(config: &Config, job: Job) {
+    let Job {
+        scale_encoded_header,
+        runtime,
+        storage,
+    } = job;
+    let block_hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+
+    // Versions 1 of the API accept a block number rather than a header. It is no longer in
+    // use in practice and isn't supported.
+    match runtime
+        .runtime_version()
+        .decode()
+        .apis
+        .find_version("OffchainWorkerApi")
+    {
+        Some(version) if version >= 2 => {}
+        _ => return,
+    }
+
+    let is_validator = config.keystore.keys().await.next().is_some();
+
+    // Transactions submitted by the off-chain worker are validated using a second instance of
+    // the runtime, as the first one is in use by the off-chain worker itself.
+    let mut validation_runtime = Some(runtime.clone());
+
+    let mut http_requests = offchain_http::HttpRequests::new();
+
+    let mut runtime_call = match executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine: runtime,
+        function_to_call: "OffchainWorkerApi_offchain_worker",
+        parameter: iter::once(&scale_encoded_header),
+        main_trie_root_calculation_cache: None,
+        storage_main_trie_changes: Default::default(),
+        offchain_storage_changes: Default::default(),
+        max_log_level: 0,
+        offchain_behavior: executor::runtime_host::OffchainBehavior::Forward,
+        tracing: false,
+    }) {
+        Ok(vm) => vm,
+        Err((error, _)) => {
+            log::warn!(
+                "offchain-worker-start-error; hash={}; error={}",
+                HashDisplay(&block_hash),
+                error
+            );
+            return;
+        }
+    };
+
+    loop {
+        match runtime_call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(_)) => {
+                log::debug!("offchain-worker-success; hash={}", HashDisplay(&block_hash));
+                break;
+            }
+            executor::runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                log::warn!(
+                    "offchain-worker-error; hash={}; error={}",
+                    HashDisplay(&block_hash),
+                    error.detail
+                );
+                break;
+            }
+            executor::runtime_host::RuntimeHostVm::StorageGet(get) => {
+                let value = storage.get(get.key().as_ref());
+                runtime_call = get.inject_value(value.map(|(val, vers)| (iter::once(val), vers)));
+            }
+            executor::runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                let found = storage
+                    .next_key(next_key.key().as_ref())
+                    .map(|k| k.to_vec()); // TODO: overhead
+                runtime_call = next_key.inject_key(found);
+            }
+            executor::runtime_host::RuntimeHostVm::PrefixKeys(prefix_key) => {
+                let keys = storage.prefix_keys(prefix_key.prefix().as_ref());
+                runtime_call = prefix_key.inject_keys_ordered(keys.into_iter());
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                runtime_call = sig.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::StorageGet(get),
+            ) => {
+                let key = get.key().as_ref().to_vec();
+                let result = config
+                    .database
+                    .with_database(move |database| database.offchain_storage_get(&key))
+                    .await;
+                match result {
+                    Ok(value) => runtime_call = get.inject_value(value.as_deref()),
+                    Err(error) => {
+                        log::warn!(
+                            "offchain-worker-error; hash={}; error={}",
+                            HashDisplay(&block_hash),
+                            error
+                        );
+                        break;
+                    }
+                }
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::StorageSet(set),
+            ) => {
+                let key = set.key().as_ref().to_vec();
+                let value = set.value().map(|v| v.as_ref().to_vec());
+                let old_value = set.old_value().map(|v| v.map(|v| v.as_ref().to_vec()));
+                let result = config
+                    .database
+                    .with_database(move |database| {
+                        database.offchain_storage_set(
+                            &key,
+                            value.as_deref(),
+                            old_value.as_ref().map(|v| v.as_deref()),
+                        )
+                    })
+                    .await;
+                match result {
+                    Ok(replaced) => runtime_call = set.resume(replaced),
+                    Err(error) => {
+                        log::warn!(
+                            "offchain-worker-error; hash={}; error={}",
+                            HashDisplay(&block_hash),
+                            error
+                        );
+                        break;
+                    }
+                }
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::Timestamp(timestamp),
+            ) => {
+                let value = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::max_value()))
+                    .unwrap_or(0);
+                runtime_call = timestamp.inject_timestamp(value);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::RandomSeed(seed),
+            ) => {
+                runtime_call = seed.inject_random_seed(rand::random());
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::HttpRequestStart(req),
+            ) => {
+                let request_id = http_requests.start(req.method().as_ref(), req.uri().as_ref());
+                log::debug!(
+                    "offchain-worker-http-request-start; hash={}; uri={:?}; request_id={:?}",
+                    HashDisplay(&block_hash),
+                    String::from_utf8_lossy(req.uri().as_ref()),
+                    request_id
+                );
+                runtime_call = req.resume(request_id);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::HttpRequestAddHeader(req),
+            ) => {
+                let success = http_requests.add_header(
+                    req.request_id(),
+                    req.name().as_ref(),
+                    req.value().as_ref(),
+                );
+                runtime_call = req.resume(success);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::HttpRequestWriteBody(req),
+            ) => {
+                let result = http_requests.write_body(req.request_id(), req.chunk().as_ref());
+                runtime_call = req.resume(result);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::HttpResponseWait(req),
+            ) => {
+                let statuses = http_requests.wait(req.request_ids(), req.deadline()).await;
+                runtime_call = req.resume(statuses);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::HttpResponseHeaders(req),
+            ) => {
+                let headers = http_requests.response_headers(req.request_id());
+                runtime_call = req.resume(headers.into_iter());
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::HttpResponseReadBody(req),
+            ) => {
+                let result = http_requests
+                    .read_body(req.request_id(), req.max_size(), req.deadline())
+                    .await;
+                runtime_call =
+                    req.resume(result.as_ref().map(|chunk| &chunk[..]).map_err(|err| *err));
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::IsValidator(req),
+            ) => {
+                runtime_call = req.inject_is_validator(is_validator);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::SubmitTransaction(tx),
+            ) => {
+                let transaction = tx.transaction().as_ref().to_vec();
+
+                // The transaction is validated against the block the off-chain worker runs
+                // against. The full node doesn't have a transactions pool, and valid transactions
+                // are instead directly gossiped to the peers.
+                let (runtime, validation) = validate_transaction(
+                    &storage,
+                    &scale_encoded_header,
+                    config.block_number_bytes,
+                    validation_runtime.take().unwrap(),
+                    &transaction,
+                );
+                validation_runtime = Some(runtime);
+
+                let success = match validation {
+                    Ok(Ok(valid)) if valid.propagate => {
+                        let sent_peers = config
+                            .network_service
+                            .announce_transaction(config.network_chain_index, &transaction)
+                            .await;
+                        log::debug!(
+                            "offchain-worker-transaction-submit; hash={}; num_peers={}",
+                            HashDisplay(&block_hash),
+                            sent_peers.len()
+                        );
+                        true
+                    }
+                    Ok(Ok(_)) => {
+                        // Since there is no transactions pool, a transaction that must not be
+                        // propagated can't be included in a block.
+                        log::debug!(
+                            "offchain-worker-transaction-not-propagated; hash={}",
+                            HashDisplay(&block_hash)
+                        );
+                        false
+                    }
+                    Ok(Err(error)) => {
+                        log::debug!(
+                            "offchain-worker-transaction-invalid; hash={}; error={}",
+                            HashDisplay(&block_hash),
+                            error
+                        );
+                        false
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "offchain-worker-transaction-validation-error; hash={}; error={}",
+                            HashDisplay(&block_hash),
+                            error
+                        );
+                        false
+                    }
+                };
+
+                runtime_call = tx.resume(success);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::KeyGeneration(req),
+            ) => {
+                let Some(namespace) = keystore::KeyNamespace::from_key_type_id(&req.key_type_id())
+                else {
+                    log::warn!(
+                        "offchain-worker-error; hash={}; error=unsupported key type {:?}",
+                        HashDisplay(&block_hash),
+                        req.key_type_id()
+                    );
+                    break;
+                };
+                let algorithm = match req.algorithm() {
+                    executor::host::KeyGenerationAlgorithm::Ed25519 => {
+                        keystore::KeyAlgorithm::Ed25519
+                    }
+                    executor::host::KeyGenerationAlgorithm::Sr25519 => {
+                        keystore::KeyAlgorithm::Sr25519
+                    }
+                    executor::host::KeyGenerationAlgorithm::Ecdsa => keystore::KeyAlgorithm::Ecdsa,
+                };
+                let Ok(seed) = req.seed().map(std::str::from_utf8).transpose() else {
+                    log::warn!(
+                        "offchain-worker-error; hash={}; error=invalid key generation seed",
+                        HashDisplay(&block_hash)
+                    );
+                    break;
+                };
+                match config
+                    .keystore
+                    .generate(namespace, algorithm, seed, true)
+                    .await
+                {
+                    Ok(public_key) => runtime_call = req.resume(&public_key),
+                    Err(error) => {
+                        log::warn!(
+                            "offchain-worker-error; hash={}; error={}",
+                            HashDisplay(&block_hash),
+                            error
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Validates a transaction submitted by the off-chain worker against the given block.
+///
+/// Returns back the runtime, which must be the runtime of the block.
+fn validate_transaction(
+    storage: &StorageSnapshot,
+    scale_encoded_header: &[u8],
+    block_number_bytes: usize,
+    runtime: executor::host::HostVmPrototype,
+    transaction: &[u8],
+) -> (
+    executor::host::HostVmPrototype,
+    Result<Result<validate::ValidTransaction, validate::TransactionValidityError>, validate::Error>,
+) {
+    let mut validation = validate::validate_transaction(validate::Config {
+        runtime,
+        scale_encoded_header,
+        block_number_bytes,
+        scale_encoded_transaction: iter::once(transaction),
+        source: validate::TransactionSource::Local,
+        max_log_level: 0,
+    });
+
+    loop {
+        match validation {
+            validate::Query::Finished {
+                result,
+                virtual_machine,
+            } => return (virtual_machine, result),
+            validate::Query::StorageGet(get) => {
+                let value = storage.get(get.key().as_ref());
+                validation = get.inject_value(value.map(|(val, vers)| (iter::once(val), vers)));
+            }
+            validate::Query::NextKey(next_key) => {
+                let found = storage
+                    .next_key(next_key.key().as_ref())
+                    .map(|k| k.to_vec()); // TODO: overhead
+                validation = next_key.inject_key(found);
+            }
+            validate::Query::PrefixKeys(prefix_keys) => {
+                let keys = storage.prefix_keys(prefix_keys.prefix().as_ref());
+                validation = prefix_keys.inject_keys_ordered(keys.into_iter());
+            }
+        }
+    }
+}
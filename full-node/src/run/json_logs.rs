@@ -0,0 +1,140 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Formatting of log records as JSON objects, one per line.
+//!
+//! The log messages of the full node follow the convention `event-name; key=value; key=value`.
+//! When a message follows this convention, the event name and the fields are output as separate
+//! JSON fields, and the `peer_id` and `chain_index` fields are additionally promoted to top-level
+//! `peer` and `chain` fields.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Writes the given log record as a JSON object followed with a new line.
+///
+/// `chain_names` contains, once known, the identifier of each chain indexed by the value of
+/// `chain_index` found in the log messages.
+pub fn write_record(
+    mut out: impl io::Write,
+    record: &log::Record,
+    chain_names: &OnceLock<Vec<String>>,
+) -> io::Result<()> {
+    #[derive(serde::Serialize)]
+    struct Record<'a> {
+        timestamp: u128,
+        target: &'a str,
+        level: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        chain: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        peer: Option<&'a str>,
+        message: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        event: Option<&'a str>,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        fields: BTreeMap<&'a str, &'a str>,
+    }
+
+    let message = record.args().to_string();
+    let (event, fields) = match parse_message(&message) {
+        Some((event, fields)) => (Some(event), fields),
+        None => (None, BTreeMap::new()),
+    };
+
+    let chain = fields.get("chain_index").map(|index| {
+        index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| chain_names.get()?.get(index))
+            .map_or(*index, |name| &name[..])
+    });
+    let peer = fields.get("peer_id").copied();
+
+    serde_json::to_writer(
+        &mut out,
+        &Record {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0),
+            target: record.target(),
+            level: match record.level() {
+                log::Level::Trace => "trace",
+                log::Level::Debug => "debug",
+                log::Level::Info => "info",
+                log::Level::Warn => "warn",
+                log::Level::Error => "error",
+            },
+            chain,
+            peer,
+            message: &message,
+            event,
+            fields,
+        },
+    )
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+    out.write_all(b"\n")
+}
+
+/// Parses a message of the form `event-name; key=value; key=value`.
+///
+/// Returns `None` if the message doesn't follow this convention. Values are allowed to contain
+/// `; `, in which case whatever follows is considered as part of the value as long as it doesn't
+/// look like a `key=value` pair.
+fn parse_message(message: &str) -> Option<(&str, BTreeMap<&str, &str>)> {
+    let (event, rest) = message.split_once("; ").unwrap_or((message, ""));
+    if event.is_empty() || !event.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+        return None;
+    }
+
+    let mut fields = BTreeMap::new();
+    if rest.is_empty() {
+        return Some((event, fields));
+    }
+
+    let mut current: Option<(&str, usize)> = None;
+    let mut offset = message.len() - rest.len();
+
+    for part in rest.split("; ") {
+        let is_field = part.split_once('=').map_or(false, |(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+        });
+
+        if is_field {
+            if let Some((key, start)) = current.take() {
+                fields.insert(key, &message[start..offset - 2]);
+            }
+            let (key, _) = part.split_once('=').unwrap();
+            current = Some((key, offset + key.len() + 1));
+        } else if current.is_none() {
+            return None;
+        }
+
+        offset += part.len() + 2;
+    }
+
+    if let Some((key, start)) = current {
+        fields.insert(key, &message[start..]);
+    }
+
+    Some((event, fields))
+}
@@ -15,9 +15,21 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use futures::{channel::oneshot, prelude::*};
-use smoldot::json_rpc::{self, methods, websocket_server};
-use std::{io, net::SocketAddr};
+use crate::run::{database_thread, log_filter};
+
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+    stream,
+};
+use smoldot::{
+    database::full_sqlite,
+    executor, header,
+    identity::keystore,
+    json_rpc::{self, methods, session_keys, trace_block, websocket_server},
+    trie,
+};
+use std::{io, iter, net::SocketAddr, str, sync::Arc};
 
 /// Configuration for a [`JsonRpcService`].
 pub struct Config<'a> {
@@ -26,6 +38,23 @@ pub struct Config<'a> {
 
     /// Where to bind the WebSocket server.
     pub bind_address: SocketAddr,
+
+    /// If `false`, the JSON-RPC functions that modify the state of the node or reveal information
+    /// about its keys, such as `author_rotateKeys`, return an error.
+    pub allow_unsafe_methods: bool,
+
+    /// Keystore into which the keys generated by `author_rotateKeys` are inserted.
+    pub keystore: Arc<keystore::Keystore>,
+
+    /// Database to access blocks from.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Number of bytes of the block number in the networking protocol.
+    pub block_number_bytes: usize,
+
+    /// Logger whose directives are modified by `system_addLogFilter` and `system_resetLogFilter`.
+    /// `None` if logging is disabled.
+    pub logger: Option<log_filter::ReloadableLogger>,
 }
 
 /// Running JSON-RPC service. Holds a server open for as long as it is alive.
@@ -59,9 +88,43 @@ impl JsonRpcService {
 
         let (_server_keep_alive, client_still_alive) = oneshot::channel();
 
+        // Blocks are traced in a separate task, as re-executing a block can take a long time.
+        // The requests that don't fit in the channel are refused.
+        let (trace_block_requests, trace_block_requests_rx) = mpsc::channel(4);
+        (config.tasks_executor)(
+            run_block_traces(
+                config.database.clone(),
+                config.block_number_bytes,
+                trace_block_requests_rx,
+            )
+            .boxed(),
+        );
+
+        // Similarly, the runtime calls related to session keys are performed in a separate task
+        // in order to not block the other JSON-RPC requests.
+        let (session_keys_requests, session_keys_requests_rx) = mpsc::channel(4);
+        (config.tasks_executor)(
+            run_session_keys_requests(
+                config.database.clone(),
+                config.keystore,
+                session_keys_requests_rx,
+            )
+            .boxed(),
+        );
+
         let background = JsonRpcBackground {
             server,
             client_still_alive: client_still_alive.fuse(),
+            allow_unsafe_methods: config.allow_unsafe_methods,
+            logger: config.logger,
+            trace_block_requests,
+            session_keys_requests,
+            requests_in_progress: stream::FuturesUnordered::new(),
+            requests_destinations: hashbrown::HashMap::with_capacity_and_hasher(
+                8,
+                Default::default(),
+            ),
+            next_request_id: 0,
         };
 
         (config.tasks_executor)(async move { background.run().await }.boxed());
@@ -88,6 +151,65 @@ struct JsonRpcBackground {
 
     /// As long as this channel is pending, the frontend of the JSON-RPC server is still alive.
     client_still_alive: future::Fuse<oneshot::Receiver<()>>,
+
+    /// See [`Config::allow_unsafe_methods`].
+    allow_unsafe_methods: bool,
+
+    /// See [`Config::logger`].
+    logger: Option<log_filter::ReloadableLogger>,
+
+    /// Sends the `state_traceBlock` requests to the task that executes them.
+    trace_block_requests: mpsc::Sender<TraceBlockRequest>,
+
+    /// Sends the `author_rotateKeys` and `author_hasSessionKeys` requests to the task that
+    /// executes them.
+    session_keys_requests: mpsc::Sender<SessionKeysRequest>,
+
+    /// Requests being executed by a separate task. Each future yields the key of the request in
+    /// [`JsonRpcBackground::requests_destinations`] and either the response or an error message.
+    requests_in_progress: stream::FuturesUnordered<
+        future::BoxFuture<'static, (u64, Result<methods::Response<'static>, String>)>,
+    >,
+
+    /// Connection and JSON-RPC request identifier to send the outcome of each request in progress
+    /// to. Entries are removed when their connection is closed, as the connection identifier
+    /// might later be reused.
+    requests_destinations:
+        hashbrown::HashMap<u64, (websocket_server::ConnectionId, String), fnv::FnvBuildHasher>,
+
+    /// Key to use for the next entry in [`JsonRpcBackground::requests_destinations`].
+    next_request_id: u64,
+}
+
+enum WakeUpReason<'a> {
+    Event(websocket_server::Event<'a, SocketAddr>),
+    RequestFinished(u64, Result<methods::Response<'static>, String>),
+}
+
+/// Request sent to the task that traces blocks.
+struct TraceBlockRequest {
+    /// Hash of the block to trace.
+    block_hash: [u8; 32],
+    /// Recorder built from the parameters of the request.
+    recorder: trace_block::Recorder,
+    /// Sender of the outcome of the execution.
+    result: oneshot::Sender<Result<methods::BlockTrace, String>>,
+}
+
+/// Request sent to the task that performs the runtime calls related to session keys.
+struct SessionKeysRequest {
+    /// Function that was called.
+    call: SessionKeysCall,
+    /// Sender of the response.
+    result: oneshot::Sender<Result<methods::Response<'static>, String>>,
+}
+
+/// See [`SessionKeysRequest::call`].
+enum SessionKeysCall {
+    /// `author_rotateKeys`.
+    RotateKeys,
+    /// `author_hasSessionKeys`, with the SCALE-encoded session keys passed as parameter.
+    HasSessionKeys(Vec<u8>),
 }
 
 impl JsonRpcBackground {
@@ -95,7 +217,32 @@ impl JsonRpcBackground {
         loop {
             let event = futures::select! {
                 _ = &mut self.client_still_alive => return,
-                event = self.server.next_event().fuse() => event,
+                (id, result) = self.requests_in_progress.select_next_some() => {
+                    WakeUpReason::RequestFinished(id, result)
+                },
+                event = self.server.next_event().fuse() => WakeUpReason::Event(event),
+            };
+
+            let event = match event {
+                WakeUpReason::Event(event) => event,
+                WakeUpReason::RequestFinished(id, result) => {
+                    // The connection might have been closed in the meanwhile.
+                    let Some((connection_id, request_id)) = self.requests_destinations.remove(&id)
+                    else {
+                        continue;
+                    };
+
+                    let response = match result {
+                        Ok(response) => response.to_json_response(&request_id),
+                        Err(error) => json_rpc::parse::build_error_response(
+                            &request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                            None,
+                        ),
+                    };
+                    self.server.queue_send(connection_id, response);
+                    continue;
+                }
             };
 
             let (connection_id, message) = match event {
@@ -105,9 +252,12 @@ impl JsonRpcBackground {
                     continue;
                 }
                 websocket_server::Event::ConnectionError {
-                    user_data: address, ..
+                    connection_id,
+                    user_data: address,
                 } => {
                     log::debug!("connection-closed; address={}", address);
+                    self.requests_destinations
+                        .retain(|_, (c, _)| *c != connection_id);
                     continue;
                 }
                 websocket_server::Event::TextFrame {
@@ -117,20 +267,150 @@ impl JsonRpcBackground {
                 } => (connection_id, message),
             };
 
-            let (request_id, _method) = match methods::parse_json_call(&message) {
+            let (request_id, method) = match methods::parse_json_call(&message) {
                 Ok(v) => v,
                 Err(error) => {
                     log::debug!("bad-request; error={:?}; message={:?}", error, message);
                     self.server.close(connection_id);
+                    self.requests_destinations
+                        .retain(|_, (c, _)| *c != connection_id);
                     continue;
                 }
             };
 
-            log::debug!("request; request_id={:?}; method={:?}", request_id, _method);
+            log::debug!("request; request_id={:?}; method={:?}", request_id, method);
 
-            self.server.queue_send(
-                connection_id,
-                json_rpc::parse::build_error_response(
+            if !self.allow_unsafe_methods && is_unsafe_method(&method) {
+                log::debug!("unsafe-request-denied; request_id={:?}", request_id);
+                let response = json_rpc::parse::build_error_response(
+                    request_id,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        "RPC call is unsafe to be called externally",
+                    ),
+                    None,
+                );
+                self.server.queue_send(connection_id, response);
+                continue;
+            }
+
+            let response = match method {
+                methods::MethodCall::author_rotateKeys {}
+                | methods::MethodCall::author_hasSessionKeys { .. } => {
+                    let call = match method {
+                        methods::MethodCall::author_hasSessionKeys { session_keys } => {
+                            SessionKeysCall::HasSessionKeys(session_keys.0)
+                        }
+                        _ => SessionKeysCall::RotateKeys,
+                    };
+                    let (result, result_rx) = oneshot::channel();
+                    match self
+                        .session_keys_requests
+                        .try_send(SessionKeysRequest { call, result })
+                    {
+                        Ok(()) => {
+                            self.push_request_in_progress(
+                                connection_id,
+                                request_id,
+                                result_rx.map(|result| {
+                                    result.unwrap_or_else(|_| {
+                                        Err("Session keys task has stopped".to_owned())
+                                    })
+                                }),
+                            );
+                            continue;
+                        }
+                        Err(_) => json_rpc::parse::build_error_response(
+                            request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Too many session keys requests",
+                            ),
+                            None,
+                        ),
+                    }
+                }
+                methods::MethodCall::state_traceBlock {
+                    block,
+                    targets,
+                    storage_keys,
+                    methods: methods_filter,
+                } => {
+                    let recorder = trace_block::Recorder::new(
+                        targets.as_deref(),
+                        storage_keys.as_deref(),
+                        methods_filter.as_deref(),
+                    );
+                    let (result, result_rx) = oneshot::channel();
+                    match self.trace_block_requests.try_send(TraceBlockRequest {
+                        block_hash: block.0,
+                        recorder,
+                        result,
+                    }) {
+                        Ok(()) => {
+                            self.push_request_in_progress(
+                                connection_id,
+                                request_id,
+                                result_rx.map(|result| {
+                                    let response = match result {
+                                        Ok(Ok(trace)) => {
+                                            methods::TraceBlockResponse::BlockTrace(trace)
+                                        }
+                                        Ok(Err(error)) => methods::TraceBlockResponse::TraceError(
+                                            methods::TraceError { error },
+                                        ),
+                                        Err(_) => methods::TraceBlockResponse::TraceError(
+                                            methods::TraceError {
+                                                error: "Block tracing task has stopped".to_owned(),
+                                            },
+                                        ),
+                                    };
+                                    Ok(methods::Response::state_traceBlock(response))
+                                }),
+                            );
+                            continue;
+                        }
+                        Err(_) => json_rpc::parse::build_error_response(
+                            request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Too many blocks being traced",
+                            ),
+                            None,
+                        ),
+                    }
+                }
+                methods::MethodCall::system_addLogFilter { directives } => {
+                    if let Some(logger) = &self.logger {
+                        logger.add_directives(&directives);
+                        methods::Response::system_addLogFilter(()).to_json_response(request_id)
+                    } else {
+                        json_rpc::parse::build_error_response(
+                            request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Logging is disabled",
+                            ),
+                            None,
+                        )
+                    }
+                }
+                methods::MethodCall::system_resetLogFilter {} => {
+                    if let Some(logger) = &self.logger {
+                        logger.reset();
+                        methods::Response::system_resetLogFilter(()).to_json_response(request_id)
+                    } else {
+                        json_rpc::parse::build_error_response(
+                            request_id,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Logging is disabled",
+                            ),
+                            None,
+                        )
+                    }
+                }
+                _ => json_rpc::parse::build_error_response(
                     request_id,
                     json_rpc::parse::ErrorResponse::ServerError(
                         -32000,
@@ -138,7 +418,427 @@ impl JsonRpcBackground {
                     ),
                     None,
                 ),
-            );
+            };
+
+            self.server.queue_send(connection_id, response);
         }
     }
+
+    /// Adds a request whose response is yielded by the given future to the requests in
+    /// progress.
+    fn push_request_in_progress(
+        &mut self,
+        connection_id: websocket_server::ConnectionId,
+        request_id: &str,
+        response: impl Future<Output = Result<methods::Response<'static>, String>> + Send + 'static,
+    ) {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        self.requests_destinations
+            .insert(id, (connection_id, request_id.to_owned()));
+        self.requests_in_progress
+            .push(Box::pin(response.map(move |result| (id, result))));
+    }
+}
+
+/// Executes the `author_rotateKeys` and `author_hasSessionKeys` requests one after the other.
+async fn run_session_keys_requests(
+    database: Arc<database_thread::DatabaseThread>,
+    keystore: Arc<keystore::Keystore>,
+    mut requests: mpsc::Receiver<SessionKeysRequest>,
+) {
+    let mut runtime_cache = None;
+
+    while let Some(request) = requests.next().await {
+        // The requester might have lost interest in the meanwhile.
+        if request.result.is_canceled() {
+            continue;
+        }
+
+        let result = match request.call {
+            SessionKeysCall::RotateKeys => rotate_keys(&database, &keystore, &mut runtime_cache)
+                .await
+                .map(|keys| methods::Response::author_rotateKeys(methods::HexString(keys))),
+            SessionKeysCall::HasSessionKeys(session_keys) => {
+                has_session_keys(&database, &keystore, &mut runtime_cache, &session_keys)
+                    .await
+                    .map(methods::Response::author_hasSessionKeys)
+            }
+        };
+        let _ = request.result.send(result);
+    }
+}
+
+/// Generates new session keys by calling the runtime of the latest finalized block, and
+/// returns the concatenation of their public keys.
+///
+/// The keys are inserted in the keystore and saved on disk, as the user is expected to
+/// register them on chain and use them for a long time.
+async fn rotate_keys(
+    database: &database_thread::DatabaseThread,
+    keystore: &keystore::Keystore,
+    runtime_cache: &mut Option<CachedRuntime>,
+) -> Result<Vec<u8>, String> {
+    let output = finalized_runtime_call(
+        database,
+        keystore,
+        runtime_cache,
+        session_keys::GENERATE_SESSION_KEYS_FUNCTION_NAME,
+        session_keys::generate_session_keys_parameters(None),
+    )
+    .await?;
+    let session_keys = session_keys::decode_generate_session_keys_output(&output)
+        .map_err(|err| err.to_string())?;
+    Ok(session_keys.to_vec())
+}
+
+/// Decodes the given session keys by calling the runtime of the latest finalized block, and
+/// returns whether the keystore contains all the corresponding private keys.
+async fn has_session_keys(
+    database: &database_thread::DatabaseThread,
+    keystore: &keystore::Keystore,
+    runtime_cache: &mut Option<CachedRuntime>,
+    session_keys: &[u8],
+) -> Result<bool, String> {
+    let output = finalized_runtime_call(
+        database,
+        keystore,
+        runtime_cache,
+        session_keys::DECODE_SESSION_KEYS_FUNCTION_NAME,
+        session_keys::decode_session_keys_parameters(session_keys),
+    )
+    .await?;
+    let Some(keys) =
+        session_keys::decode_decode_session_keys_output(&output).map_err(|err| err.to_string())?
+    else {
+        return Ok(false);
+    };
+
+    let mut namespaced_keys = Vec::with_capacity(keys.len());
+    for (public_key, key_type_id) in keys {
+        // Keys of a type that the keystore doesn't support can't be in the keystore.
+        let Some(namespace) = keystore::KeyNamespace::from_key_type_id(&key_type_id) else {
+            return Ok(false);
+        };
+        namespaced_keys.push((namespace, public_key));
+    }
+
+    Ok(keystore.has_session_keys(namespaced_keys.into_iter()).await)
+}
+
+/// Calls the given function of the runtime of the latest finalized block, and returns its
+/// output.
+///
+/// Keys that the runtime generates through the key generation host functions are inserted
+/// in the keystore. The other off-chain host functions aren't supported.
+async fn finalized_runtime_call(
+    database: &database_thread::DatabaseThread,
+    keystore: &keystore::Keystore,
+    runtime_cache: &mut Option<CachedRuntime>,
+    function_to_call: &str,
+    parameter: impl Iterator<Item = impl AsRef<[u8]>> + Clone,
+) -> Result<Vec<u8>, String> {
+    let block_hash = database
+        .with_database(|database| database.finalized_block_hash())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut runtime_call = executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine: runtime_at(database, runtime_cache, block_hash).await?,
+        function_to_call,
+        parameter,
+        main_trie_root_calculation_cache: None,
+        storage_main_trie_changes: Default::default(),
+        offchain_storage_changes: Default::default(),
+        max_log_level: 0,
+        offchain_behavior: executor::runtime_host::OffchainBehavior::Forward,
+        tracing: false,
+    })
+    .map_err(|(err, _)| err.to_string())?;
+
+    loop {
+        match runtime_call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                break Ok(success.virtual_machine.value().as_ref().to_vec());
+            }
+            executor::runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                break Err(error.detail.to_string());
+            }
+            executor::runtime_host::RuntimeHostVm::StorageGet(get) => {
+                let key = get.key().as_ref().to_vec();
+                let value = database
+                    .with_database(move |database| {
+                        database.block_storage_main_trie_get(&block_hash, &key)
+                    })
+                    .await
+                    .map_err(|err| err.to_string())?;
+                let value = value
+                    .map(|(value, version)| {
+                        trie::TrieEntryVersion::try_from(version)
+                            .map(|version| (value, version))
+                            .map_err(|_| "Invalid trie entry version in database".to_owned())
+                    })
+                    .transpose()?;
+                runtime_call =
+                    get.inject_value(value.as_ref().map(|(v, vers)| (iter::once(v), *vers)));
+            }
+            executor::runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                let key = next_key.key().as_ref().to_vec();
+                let found = database
+                    .with_database(move |database| {
+                        database.block_storage_main_trie_next_key(&block_hash, &key)
+                    })
+                    .await
+                    .map_err(|err| err.to_string())?;
+                runtime_call = next_key.inject_key(found);
+            }
+            executor::runtime_host::RuntimeHostVm::PrefixKeys(prefix_keys) => {
+                let prefix = prefix_keys.prefix().as_ref().to_vec();
+                let keys = database
+                    .with_database(move |database| {
+                        database.block_storage_main_trie_keys(&block_hash, &prefix)
+                    })
+                    .await
+                    .map_err(|err| err.to_string())?;
+                runtime_call = prefix_keys.inject_keys_ordered(keys.into_iter());
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                runtime_call = sig.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(
+                executor::runtime_host::OffchainContext::KeyGeneration(req),
+            ) => {
+                let namespace = keystore::KeyNamespace::from_key_type_id(&req.key_type_id())
+                    .ok_or_else(|| format!("Unsupported key type: {:?}", req.key_type_id()))?;
+                let algorithm = match req.algorithm() {
+                    executor::host::KeyGenerationAlgorithm::Ed25519 => {
+                        keystore::KeyAlgorithm::Ed25519
+                    }
+                    executor::host::KeyGenerationAlgorithm::Sr25519 => {
+                        keystore::KeyAlgorithm::Sr25519
+                    }
+                    executor::host::KeyGenerationAlgorithm::Ecdsa => keystore::KeyAlgorithm::Ecdsa,
+                };
+                let seed = req
+                    .seed()
+                    .map(str::from_utf8)
+                    .transpose()
+                    .map_err(|_| "Invalid key generation seed".to_owned())?;
+                let public_key = keystore
+                    .generate(namespace, algorithm, seed, true)
+                    .await
+                    .map_err(|err| err.to_string())?;
+                runtime_call = req.resume(&public_key);
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(_) => {
+                break Err("Unsupported off-chain host function".to_owned());
+            }
+        }
+    }
+}
+
+/// Executes the `state_traceBlock` requests one after the other.
+async fn run_block_traces(
+    database: Arc<database_thread::DatabaseThread>,
+    block_number_bytes: usize,
+    mut requests: mpsc::Receiver<TraceBlockRequest>,
+) {
+    let mut runtime_cache = None;
+
+    while let Some(request) = requests.next().await {
+        // The requester might have lost interest in the meanwhile.
+        if request.result.is_canceled() {
+            continue;
+        }
+
+        let result = trace_block(
+            &database,
+            &mut runtime_cache,
+            block_number_bytes,
+            request.block_hash,
+            request.recorder,
+        )
+        .await;
+        let _ = request.result.send(result);
+    }
+}
+
+/// Re-executes the given block on top of the storage of its parent, and returns the trace
+/// of this execution.
+///
+/// Returns an error message if the block or the storage of its parent isn't available, or
+/// if the execution fails.
+async fn trace_block(
+    database: &database_thread::DatabaseThread,
+    runtime_cache: &mut Option<CachedRuntime>,
+    block_number_bytes: usize,
+    block_hash: [u8; 32],
+    mut recorder: trace_block::Recorder,
+) -> Result<methods::BlockTrace, String> {
+    let (scale_encoded_header, body) = database
+        .with_database(move |database| {
+            let header = database.block_scale_encoded_header(&block_hash)?;
+            let body = database
+                .block_extrinsics(&block_hash)?
+                .map(|body| body.collect::<Vec<_>>());
+            Ok::<_, full_sqlite::AccessError>(header.zip(body))
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Unknown block".to_owned())?;
+
+    let parent_hash = *header::decode(&scale_encoded_header, block_number_bytes)
+        .map_err(|err| err.to_string())?
+        .parent_hash;
+
+    let virtual_machine = runtime_at(database, runtime_cache, parent_hash).await?;
+
+    let parameter = trace_block::execute_block_parameters(
+        &scale_encoded_header,
+        block_number_bytes,
+        body.iter(),
+    )
+    .map_err(|err| err.to_string())?;
+
+    let mut runtime_call = executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine,
+        function_to_call: trace_block::EXECUTE_BLOCK_FUNCTION_NAME,
+        parameter: iter::once(&parameter),
+        main_trie_root_calculation_cache: None,
+        storage_main_trie_changes: Default::default(),
+        offchain_storage_changes: Default::default(),
+        max_log_level: 0,
+        // Blocks aren't supposed to access the off-chain context.
+        offchain_behavior: executor::runtime_host::OffchainBehavior::Deny,
+        tracing: true,
+    })
+    .map_err(|(err, _)| err.to_string())?;
+
+    loop {
+        match runtime_call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                recorder.runtime_tracing(&success.tracing);
+                recorder.storage_changes(&success.storage_main_trie_changes);
+                break Ok(recorder.finish(block_hash, parent_hash));
+            }
+            executor::runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                break Err(error.detail.to_string());
+            }
+            executor::runtime_host::RuntimeHostVm::StorageGet(get) => {
+                let key = get.key().as_ref().to_vec();
+                let value = database
+                    .with_database(move |database| {
+                        database.block_storage_main_trie_get(&parent_hash, &key)
+                    })
+                    .await
+                    .map_err(|err| err.to_string())?;
+                let value = value
+                    .map(|(value, version)| {
+                        trie::TrieEntryVersion::try_from(version)
+                            .map(|version| (value, version))
+                            .map_err(|_| "Invalid trie entry version in database".to_owned())
+                    })
+                    .transpose()?;
+                recorder.storage_get(get.key().as_ref(), value.as_ref().map(|(v, _)| &v[..]));
+                runtime_call =
+                    get.inject_value(value.as_ref().map(|(v, vers)| (iter::once(v), *vers)));
+            }
+            executor::runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                let key = next_key.key().as_ref().to_vec();
+                let found = database
+                    .with_database(move |database| {
+                        database.block_storage_main_trie_next_key(&parent_hash, &key)
+                    })
+                    .await
+                    .map_err(|err| err.to_string())?;
+                recorder.storage_next_key(next_key.key().as_ref(), found.as_deref());
+                runtime_call = next_key.inject_key(found);
+            }
+            executor::runtime_host::RuntimeHostVm::PrefixKeys(prefix_keys) => {
+                let prefix = prefix_keys.prefix().as_ref().to_vec();
+                let keys = database
+                    .with_database(move |database| {
+                        database.block_storage_main_trie_keys(&parent_hash, &prefix)
+                    })
+                    .await
+                    .map_err(|err| err.to_string())?;
+                recorder.storage_prefix_keys(prefix_keys.prefix().as_ref(), keys.len());
+                runtime_call = prefix_keys.inject_keys_ordered(keys.into_iter());
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                runtime_call = sig.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(_) => {
+                // Off-chain host functions are configured to be denied.
+                unreachable!()
+            }
+        }
+    }
+}
+
+/// Runtime most recently built by [`runtime_at`].
+struct CachedRuntime {
+    /// Value of `:code` the runtime was built from.
+    code: Vec<u8>,
+    /// Number of heap pages the runtime was built with.
+    heap_pages: executor::vm::HeapPages,
+    /// The runtime itself.
+    runtime: executor::host::HostVmPrototype,
+}
+
+/// Builds the runtime of the given block from its storage.
+///
+/// The runtime isn't compiled again if its code and heap pages are the same as the ones of the
+/// runtime in `cache`, which is updated otherwise.
+async fn runtime_at(
+    database: &database_thread::DatabaseThread,
+    cache: &mut Option<CachedRuntime>,
+    block_hash: [u8; 32],
+) -> Result<executor::host::HostVmPrototype, String> {
+    let (code, heap_pages) = database
+        .with_database(move |database| {
+            let code = database.block_storage_main_trie_get(&block_hash, b":code")?;
+            let heap_pages = database.block_storage_main_trie_get(&block_hash, b":heappages")?;
+            Ok::<_, full_sqlite::StorageAccessError>((code, heap_pages))
+        })
+        .await
+        .map_err(|err| err.to_string())?;
+    let (code, _) = code.ok_or_else(|| "Missing :code in storage".to_owned())?;
+    let heap_pages =
+        executor::storage_heap_pages_to_value(heap_pages.as_ref().map(|(v, _)| &v[..]))
+            .map_err(|err| err.to_string())?;
+
+    if let Some(cached) = cache {
+        if cached.code == code && cached.heap_pages == heap_pages {
+            return Ok(cached.runtime.clone());
+        }
+    }
+
+    let runtime = executor::host::HostVmPrototype::new(executor::host::Config {
+        module: &code,
+        heap_pages,
+        exec_hint: executor::vm::ExecHint::CompileAheadOfTime,
+        allow_unresolved_imports: false,
+        fuel_limit: None,
+    })
+    .map_err(|err| err.to_string())?;
+    *cache = Some(CachedRuntime {
+        code,
+        heap_pages,
+        runtime: runtime.clone(),
+    });
+    Ok(runtime)
+}
+
+/// Returns `true` if the given JSON-RPC function modifies the state of the node or reveals
+/// information about its keys, in which case it is only accepted if
+/// [`Config::allow_unsafe_methods`] is `true`.
+fn is_unsafe_method(method: &methods::MethodCall) -> bool {
+    matches!(
+        method,
+        methods::MethodCall::author_rotateKeys {}
+            | methods::MethodCall::author_hasSessionKeys { .. }
+            | methods::MethodCall::system_addLogFilter { .. }
+            | methods::MethodCall::system_resetLogFilter {}
+    )
 }
@@ -0,0 +1,102 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Logger whose filtering directives can be modified at runtime.
+//!
+//! The [`ReloadableLogger`] wraps around an `env_logger` logger. Whenever directives are added
+//! or reset, for example through the `system_addLogFilter` and `system_resetLogFilter` JSON-RPC
+//! functions, the inner logger is built again by passing the list of all the directives added
+//! since the start-up to the function provided at initialization.
+
+use std::sync::{Arc, RwLock};
+
+/// Implementation of [`log::Log`] whose filtering directives can be modified at runtime.
+///
+/// Cloning a [`ReloadableLogger`] is cheap, and all clones share the same configuration.
+#[derive(Clone)]
+pub struct ReloadableLogger {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// Function that builds the logger given the list of additional directives.
+    build: Box<dyn Fn(&[String]) -> env_logger::Logger + Send + Sync>,
+    /// List of directives added through [`ReloadableLogger::add_directives`], and logger built
+    /// from these directives.
+    state: RwLock<(Vec<String>, env_logger::Logger)>,
+}
+
+impl ReloadableLogger {
+    /// Initializes a new [`ReloadableLogger`].
+    ///
+    /// `build` is called immediately with an empty list, then every time the list of additional
+    /// directives is modified. The directives must be applied on top of the default ones, in
+    /// order.
+    pub fn new(build: impl Fn(&[String]) -> env_logger::Logger + Send + Sync + 'static) -> Self {
+        let logger = build(&[]);
+        ReloadableLogger {
+            inner: Arc::new(Inner {
+                build: Box::new(build),
+                state: RwLock::new((Vec::new(), logger)),
+            }),
+        }
+    }
+
+    /// Installs this logger as the global logger of the [`log`] crate.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a global logger has already been installed.
+    ///
+    pub fn install(&self) {
+        log::set_max_level(self.inner.state.read().unwrap().1.filter());
+        log::set_logger(Box::leak(Box::new(self.clone()))).unwrap();
+    }
+
+    /// Adds the given comma-separated directives, for example `sync=trace,network=debug`, on top
+    /// of the current ones.
+    ///
+    /// Invalid directives are ignored.
+    pub fn add_directives(&self, directives: &str) {
+        let mut state = self.inner.state.write().unwrap();
+        state.0.push(directives.to_owned());
+        state.1 = (self.inner.build)(&state.0);
+        log::set_max_level(state.1.filter());
+    }
+
+    /// Removes all the directives that have been added with [`ReloadableLogger::add_directives`].
+    pub fn reset(&self) {
+        let mut state = self.inner.state.write().unwrap();
+        state.0.clear();
+        state.1 = (self.inner.build)(&state.0);
+        log::set_max_level(state.1.filter());
+    }
+}
+
+impl log::Log for ReloadableLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.state.read().unwrap().1.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.inner.state.read().unwrap().1.log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.state.read().unwrap().1.flush()
+    }
+}
@@ -81,6 +81,12 @@ pub struct Config<'a> {
 
     /// Service to use to report traces.
     pub jaeger_service: Arc<jaeger_service::JaegerService>,
+
+    /// See [`service::Config::yamux_max_receive_window`].
+    pub yamux_max_receive_window: u64,
+
+    /// See [`service::Config::max_inbound_substreams`].
+    pub max_inbound_substreams: Option<usize>,
 }
 
 /// Configuration for one chain.
@@ -234,7 +240,13 @@ impl NetworkService {
                 } else {
                     None
                 },
+                // BEEFY isn't supported by the full node.
+                has_beefy_protocol: false,
                 allow_inbound_block_requests: true,
+                allow_inbound_kademlia_requests: true,
+                // The full node doesn't keep track of the blocks where the GrandPa authorities
+                // change, which is necessary in order to build warp sync proofs.
+                allow_inbound_grandpa_warp_sync_requests: false,
             });
 
             databases.push(chain.database.clone());
@@ -248,6 +260,8 @@ impl NetworkService {
             noise_key: config.noise_key,
             handshake_timeout: Duration::from_secs(8),
             max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
+            max_inbound_substreams: config.max_inbound_substreams,
+            yamux_max_receive_window: config.yamux_max_receive_window,
             randomness_seed: rand::random(),
         });
 
@@ -555,6 +569,40 @@ impl NetworkService {
         result
     }
 
+    /// Sends a transaction to all the peers we have a transactions substream with.
+    ///
+    /// Returns the list of peers the transaction has been sent to.
+    pub async fn announce_transaction(
+        &self,
+        chain_index: usize,
+        transaction: &[u8],
+    ) -> Vec<PeerId> {
+        let mut sent_peers = Vec::with_capacity(16); // TODO: capacity?
+
+        // TODO: keep track of which peer knows about which transaction, and don't send it again
+
+        let mut guarded = self.inner.guarded.lock().await;
+
+        // TODO: collecting in a Vec :-/
+        for peer in guarded
+            .network
+            .opened_transactions_substream(chain_index)
+            .cloned()
+            .collect::<Vec<_>>()
+        {
+            if guarded
+                .network
+                .announce_transaction(&peer, chain_index, transaction)
+                .is_ok()
+            {
+                sent_peers.push(peer);
+            };
+        }
+
+        self.inner.wake_up_main_background_task.notify(1);
+        sent_peers
+    }
+
     /// Sends a blocks request to the given peer.
     // TODO: more docs
     // TODO: proper error type
@@ -565,7 +613,7 @@ impl NetworkService {
         config: protocol::BlocksRequestConfig,
     ) -> Result<Vec<protocol::BlockData>, BlocksRequestError> {
         log::debug!(
-            "blocks-request-start; perr_id={}; chain_index={}; start={}; desired_count={}; direction={}",
+            "blocks-request-start; peer_id={}; chain_index={}; start={}; desired_count={}; direction={}",
             target, chain_index,
             match &config.start {
                 protocol::BlocksRequestConfigStart::Hash(h) => either::Left(HashDisplay(h)),
@@ -944,6 +992,20 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         },
                     );
                 }
+                service::Event::KademliaFindNodeRequestIn {
+                    peer_id,
+                    chain_index,
+                    request_id,
+                } => {
+                    log::debug!(
+                        "incoming-kademlia-find-node-request; peer_id={}; chain_index={}",
+                        peer_id,
+                        chain_index
+                    );
+                    guarded.network.respond_kademlia_find_node(request_id);
+                }
+                service::Event::GrandpaWarpSyncRequestIn { .. } => unreachable!(),
+                service::Event::BeefyFinalityProof { .. } => unreachable!(),
                 service::Event::GrandpaNeighborPacket {
                     chain_index,
                     peer_id,
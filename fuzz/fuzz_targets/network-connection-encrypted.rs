@@ -142,6 +142,7 @@ libfuzzer_sys::fuzz_target!(|data: &[u8]| {
             .into_connection::<_, (), ()>(Config {
                 first_out_ping: Duration::new(60, 0),
                 max_inbound_substreams: 10,
+                yamux_max_receive_window: 1024 * 1024,
                 notifications_protocols: Vec::new(),
                 request_protocols: vec![ConfigRequestResponse {
                     inbound_allowed: true,
@@ -35,6 +35,7 @@ libfuzzer_sys::fuzz_target!(|data: &[u8]| {
             randomness_seed: [0; 32],
             capacity: 0,
             max_inbound_substreams: 10,
+            yamux_max_receive_window: 1024 * 1024,
             notification_protocols: Vec::new(),
             request_response_protocols: Vec::new(),
             // This timeout doesn't matter as we pass dummy time values.
@@ -25,5 +25,6 @@ libfuzzer_sys::fuzz_target!(|data: &[u8]| {
         heap_pages: smoldot::executor::DEFAULT_HEAP_PAGES,
         exec_hint: smoldot::executor::vm::ExecHint::ForceWasmtime,
         allow_unresolved_imports: true,
+        fuel_limit: None,
     });
 });
@@ -32,6 +32,13 @@ pub struct Config<'a, TLocAuth> {
     /// authorities list change digest item.
     pub current_authorities: header::AuraAuthoritiesIter<'a>,
 
+    /// Slot number of the parent of the block to produce, or `None` if the parent doesn't have
+    /// any Aura pre-runtime digest (which is the case for the genesis block).
+    ///
+    /// Slots inferior or equal to this one are never claimed, as producing two blocks in the
+    /// same slot isn't allowed.
+    pub parent_slot_number: Option<u64>,
+
     /// Iterator to the list of Sr25519 public keys available locally.
     ///
     /// Must implement `Iterator<Item = &[u8; 32]>`.
@@ -62,6 +69,14 @@ pub fn next_slot_claim<'a>(
     )
     .unwrap();
 
+    // The parent might have been produced in the current slot, or even in a slot in the future
+    // if the local clock is late. In that situation, claiming starts from the slot that follows
+    // the one of the parent.
+    let current_slot = match config.parent_slot_number {
+        Some(parent_slot) if parent_slot >= current_slot => parent_slot.checked_add(1).unwrap(),
+        _ => current_slot,
+    };
+
     let current_slot_index =
         usize::try_from(current_slot.checked_rem(u64::try_from(num_current_authorities).unwrap())?)
             .unwrap();
@@ -97,7 +112,7 @@ pub fn next_slot_claim<'a>(
         let slot_start_from_unix_epoch =
             Duration::from_millis(slot_number.checked_mul(config.slot_duration.get()).unwrap());
         let slot_end_from_unix_epoch =
-            slot_start_from_unix_epoch + Duration::from_secs(config.slot_duration.get());
+            slot_start_from_unix_epoch + Duration::from_millis(config.slot_duration.get());
         debug_assert!(slot_end_from_unix_epoch > config.now_from_unix_epoch);
 
         Some(SlotClaim {
@@ -52,6 +52,10 @@ pub enum ConfigConsensus<'a, TLocAuth> {
         /// an authorities list change digest item.
         current_authorities: header::AuraAuthoritiesIter<'a>,
 
+        /// Slot number of the parent of the block to produce, or `None` if the parent doesn't
+        /// have any Aura pre-runtime digest. See [`aura::Config::parent_slot_number`].
+        parent_slot_number: Option<u64>,
+
         /// Iterator to the list of Sr25519 public keys available locally.
         ///
         /// Must implement `Iterator<Item = &[u8; 32]>`.
@@ -83,6 +87,7 @@ impl Builder {
         let (slot, ready): (WaitSlotConsensus, bool) = match config.consensus {
             ConfigConsensus::Aura {
                 current_authorities,
+                parent_slot_number,
                 local_authorities,
                 now_from_unix_epoch,
                 slot_duration,
@@ -91,6 +96,7 @@ impl Builder {
                     now_from_unix_epoch,
                     slot_duration,
                     current_authorities,
+                    parent_slot_number,
                     local_authorities,
                 }) {
                     Some(c) => c,
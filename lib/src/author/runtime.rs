@@ -194,6 +194,8 @@ pub fn build_block(config: Config) -> BlockBuild {
         storage_main_trie_changes: Default::default(),
         offchain_storage_changes: Default::default(),
         max_log_level: config.max_log_level,
+        offchain_behavior: runtime_host::OffchainBehavior::Deny,
+        tracing: false,
     });
 
     let vm = match init_result {
@@ -333,6 +335,8 @@ impl BlockBuild {
                         storage_main_trie_changes: success.storage_main_trie_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         max_log_level: shared.max_log_level,
+                        offchain_behavior: runtime_host::OffchainBehavior::Deny,
+                        tracing: false,
                     });
 
                     inner = Inner::Runtime(match init_result {
@@ -529,6 +533,8 @@ impl InherentExtrinsics {
             storage_main_trie_changes: self.storage_main_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
             max_log_level: self.shared.max_log_level,
+            offchain_behavior: runtime_host::OffchainBehavior::Deny,
+            tracing: false,
         });
 
         let vm = match init_result {
@@ -563,6 +569,8 @@ impl ApplyExtrinsic {
             storage_main_trie_changes: self.storage_main_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
             max_log_level: self.shared.max_log_level,
+            offchain_behavior: runtime_host::OffchainBehavior::Deny,
+            tracing: false,
         });
 
         self.shared.stage = Stage::ApplyExtrinsic(extrinsic);
@@ -587,6 +595,8 @@ impl ApplyExtrinsic {
             storage_main_trie_changes: self.storage_main_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
             max_log_level: self.shared.max_log_level,
+            offchain_behavior: runtime_host::OffchainBehavior::Deny,
+            tracing: false,
         });
 
         let vm = match init_result {
@@ -64,6 +64,7 @@
 use crate::{
     chain::{chain_information, fork_tree},
     header,
+    verify::precheck,
 };
 
 use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
@@ -72,6 +73,7 @@ use hashbrown::HashMap;
 
 mod best_block;
 mod finality;
+mod tests;
 mod verify;
 
 pub use self::finality::*;
@@ -98,6 +100,31 @@ pub struct Config {
     /// Consequently, both `true` and `false` guarantee that the number of authorable blocks over
     /// the network is bounded.
     pub allow_unknown_consensus_engines: bool,
+
+    /// Rule used to determine which block among the non-finalized blocks is the best block.
+    pub best_block_policy: BestBlockPolicy,
+}
+
+/// Rule used to determine which block of the chain is the best block.
+///
+/// The best block is the block that is reported to the user as being the head of the chain
+/// before finality. Regardless of the policy, all non-finalized blocks are still verified and
+/// tracked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BestBlockPolicy {
+    /// The best block is the one with the highest number of ancestors since the latest finalized
+    /// block.
+    LongestChain,
+
+    /// For chains using Babe, the best block is the one with the highest number of ancestors with
+    /// a primary slot claim since the latest finalized block. For other consensus algorithms,
+    /// equivalent to [`BestBlockPolicy::LongestChain`].
+    ///
+    /// This is the rule used by Substrate.
+    MostPrimarySlots,
+
+    /// The best block is always the latest finalized block.
+    FollowFinalityOnly,
 }
 
 /// Holds state about the current state of the chain for the purpose of verifying headers.
@@ -173,6 +200,10 @@ impl<T> NonFinalizedTree<T> {
                 current_best: None,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                best_block_policy: config.best_block_policy,
+                prechecked_signatures: precheck::PrecheckedSignatures::with_capacity(
+                    config.blocks_capacity,
+                ),
             })),
         }
     }
@@ -303,7 +334,7 @@ impl<T> NonFinalizedTree<T> {
     /// Returns the header of the best block.
     pub fn best_block_header(&self) -> header::HeaderRef {
         let inner = self.inner.as_ref().unwrap();
-        if let Some(index) = inner.current_best {
+        if let Some(index) = inner.reported_best() {
             (&inner.blocks.get(index).unwrap().header).into()
         } else {
             (&inner.finalized_block_header).into()
@@ -312,6 +343,21 @@ impl<T> NonFinalizedTree<T> {
 
     /// Returns the hash of the best block.
     pub fn best_block_hash(&self) -> [u8; 32] {
+        let inner = self.inner.as_ref().unwrap();
+        if let Some(index) = inner.reported_best() {
+            inner.blocks.get(index).unwrap().hash
+        } else {
+            inner.finalized_block_hash
+        }
+    }
+
+    /// Returns the hash of the head of the chain that is tracked in order to determine the best
+    /// block.
+    ///
+    /// Identical to [`NonFinalizedTree::best_block_hash`], except when the policy is
+    /// [`BestBlockPolicy::FollowFinalityOnly`], in which case this function returns the block
+    /// that would be the best block with [`BestBlockPolicy::MostPrimarySlots`].
+    pub fn best_chain_head_hash(&self) -> [u8; 32] {
         let inner = self.inner.as_ref().unwrap();
         if let Some(index) = inner.current_best {
             inner.blocks.get(index).unwrap().hash
@@ -323,65 +369,43 @@ impl<T> NonFinalizedTree<T> {
     /// Returns consensus information about the current best block of the chain.
     pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
         let inner = self.inner.as_ref().unwrap();
-        match (
-            &inner.finalized_consensus,
-            inner
-                .current_best
-                .map(|idx| &inner.blocks.get(idx).unwrap().consensus),
-        ) {
-            (FinalizedConsensus::Unknown, _) => {
-                chain_information::ChainInformationConsensusRef::Unknown
-            }
-            (
-                FinalizedConsensus::Aura {
-                    authorities_list,
-                    slot_duration,
-                },
-                None,
-            )
-            | (
-                FinalizedConsensus::Aura { slot_duration, .. },
-                Some(BlockConsensus::Aura { authorities_list }),
-            ) => chain_information::ChainInformationConsensusRef::Aura {
-                finalized_authorities_list: header::AuraAuthoritiesIter::from_slice(
-                    authorities_list,
-                ),
-                slot_duration: *slot_duration,
-            },
-            (
-                FinalizedConsensus::Babe {
-                    block_epoch_information,
-                    next_epoch_transition,
-                    slots_per_epoch,
-                },
-                None,
-            ) => chain_information::ChainInformationConsensusRef::Babe {
-                slots_per_epoch: *slots_per_epoch,
-                finalized_block_epoch_information: block_epoch_information
-                    .as_ref()
-                    .map(|info| From::from(&**info)),
-                finalized_next_epoch_transition: next_epoch_transition.as_ref().into(),
-            },
-            (
-                FinalizedConsensus::Babe {
-                    slots_per_epoch, ..
-                },
-                Some(BlockConsensus::Babe {
-                    current_epoch,
-                    next_epoch,
-                }),
-            ) => chain_information::ChainInformationConsensusRef::Babe {
-                slots_per_epoch: *slots_per_epoch,
-                finalized_block_epoch_information: current_epoch
-                    .as_ref()
-                    .map(|info| From::from(&**info)),
-                finalized_next_epoch_transition: next_epoch.as_ref().into(),
-            },
+        inner.block_consensus(inner.reported_best())
+    }
 
-            // Any mismatch of consensus engine between the finalized and best block is not
-            // supported at the moment.
-            _ => unreachable!(),
+    /// Builds the [`precheck::SignaturesCheck`] of the given header, in order for its signatures
+    /// to be verified ahead of time. The result of the verification can then be passed to
+    /// [`NonFinalizedTree::insert_prechecked_signatures`].
+    ///
+    /// The author of the block is determined based on the consensus of the head of the chain.
+    ///
+    /// Returns `None` if the signatures of this header have already been verified ahead of time,
+    /// or if they can't be determined. See [`precheck::SignaturesCheck::from_header`].
+    pub fn signatures_check(&self, header: header::HeaderRef) -> Option<precheck::SignaturesCheck> {
+        let inner = self.inner.as_ref().unwrap();
+        let check = precheck::SignaturesCheck::from_header(
+            header,
+            inner.block_number_bytes,
+            inner.block_consensus(inner.current_best),
+        )?;
+
+        if inner.prechecked_signatures.contains(&check) {
+            return None;
         }
+
+        Some(check)
+    }
+
+    /// Stores signatures that have been verified ahead of time. They are no longer verified when
+    /// verifying the header they belong to.
+    ///
+    /// Only a limited number of signatures are kept in memory. Inserting too many signatures
+    /// discards the ones inserted the earliest.
+    pub fn insert_prechecked_signatures(&mut self, verified: precheck::VerifiedSignatures) {
+        self.inner
+            .as_mut()
+            .unwrap()
+            .prechecked_signatures
+            .insert(verified);
     }
 
     /// Returns true if the block with the given hash is in the [`NonFinalizedTree`].
@@ -474,6 +498,90 @@ struct NonFinalizedTreeInner<T> {
     block_number_bytes: usize,
     /// See [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// See [`Config::best_block_policy`].
+    best_block_policy: BestBlockPolicy,
+    /// Signatures of headers that have been verified ahead of time through
+    /// [`NonFinalizedTree::insert_prechecked_signatures`]. Passed to the header verification.
+    prechecked_signatures: precheck::PrecheckedSignatures,
+}
+
+impl<T> NonFinalizedTreeInner<T> {
+    /// Returns the index within [`NonFinalizedTreeInner::blocks`] of the best block as reported
+    /// to the API user, or `None` if it is the finalized block.
+    ///
+    /// [`NonFinalizedTreeInner::current_best`] is still tracked when the policy is
+    /// [`BestBlockPolicy::FollowFinalityOnly`], as it is needed when finalizing blocks.
+    fn reported_best(&self) -> Option<fork_tree::NodeIndex> {
+        match self.best_block_policy {
+            BestBlockPolicy::FollowFinalityOnly => None,
+            BestBlockPolicy::LongestChain | BestBlockPolicy::MostPrimarySlots => self.current_best,
+        }
+    }
+
+    /// Returns consensus information about the given block, or about the finalized block if
+    /// `None`.
+    fn block_consensus(
+        &self,
+        block_index: Option<fork_tree::NodeIndex>,
+    ) -> chain_information::ChainInformationConsensusRef {
+        match (
+            &self.finalized_consensus,
+            block_index.map(|idx| &self.blocks.get(idx).unwrap().consensus),
+        ) {
+            (FinalizedConsensus::Unknown, _) => {
+                chain_information::ChainInformationConsensusRef::Unknown
+            }
+            (
+                FinalizedConsensus::Aura {
+                    authorities_list,
+                    slot_duration,
+                },
+                None,
+            )
+            | (
+                FinalizedConsensus::Aura { slot_duration, .. },
+                Some(BlockConsensus::Aura { authorities_list }),
+            ) => chain_information::ChainInformationConsensusRef::Aura {
+                finalized_authorities_list: header::AuraAuthoritiesIter::from_slice(
+                    authorities_list,
+                ),
+                slot_duration: *slot_duration,
+            },
+            (
+                FinalizedConsensus::Babe {
+                    block_epoch_information,
+                    next_epoch_transition,
+                    slots_per_epoch,
+                },
+                None,
+            ) => chain_information::ChainInformationConsensusRef::Babe {
+                slots_per_epoch: *slots_per_epoch,
+                finalized_block_epoch_information: block_epoch_information
+                    .as_ref()
+                    .map(|info| From::from(&**info)),
+                finalized_next_epoch_transition: next_epoch_transition.as_ref().into(),
+            },
+            (
+                FinalizedConsensus::Babe {
+                    slots_per_epoch, ..
+                },
+                Some(BlockConsensus::Babe {
+                    current_epoch,
+                    next_epoch,
+                }),
+            ) => chain_information::ChainInformationConsensusRef::Babe {
+                slots_per_epoch: *slots_per_epoch,
+                finalized_block_epoch_information: current_epoch
+                    .as_ref()
+                    .map(|info| From::from(&**info)),
+                finalized_next_epoch_transition: next_epoch.as_ref().into(),
+            },
+
+            // Any mismatch of consensus engine between the finalized and best block is not
+            // supported at the moment.
+            _ => unreachable!(),
+        }
+    }
 }
 
 /// State of the consensus of the finalized block.
@@ -17,7 +17,7 @@
 
 //! Extension module containing the best block determination.
 
-use super::{fork_tree, header, Block};
+use super::{fork_tree, header, BestBlockPolicy, Block};
 use core::{cmp::Ordering, iter};
 
 /// Accepts as parameter a container of blocks and indices within this container.
@@ -30,7 +30,12 @@ use core::{cmp::Ordering, iter};
 ///
 /// The implementation assumes that all blocks of the chain use the same consensus algorithm. No
 /// output is guaranteed if this is not the case.
+///
+/// [`BestBlockPolicy::FollowFinalityOnly`] is treated the same way as
+/// [`BestBlockPolicy::MostPrimarySlots`]. It is the responsibility of the caller to not report
+/// the block as the best block.
 pub(super) fn is_better_block<T>(
+    policy: BestBlockPolicy,
     blocks: &fork_tree::ForkTree<Block<T>>,
     old_best: fork_tree::NodeIndex,
     maybe_new_best_parent: Option<fork_tree::NodeIndex>,
@@ -55,8 +60,8 @@ pub(super) fn is_better_block<T>(
     //   block's parent. Add one if the new block has a Babe primary slot claim.
     // - If the number for the new block is strictly superior, then the new block is our new best.
     //
-    // For algorithms other than Babe, all blocks simply count as one, such that the longest
-    // chain is the preferred one.
+    // For algorithms other than Babe, or if the policy is `LongestChain`, all blocks simply count
+    // as one, such that the longest chain is the preferred one.
     //
     // The code below assumes that all blocks use the same consensus algorithm. It is not
     // meaningful to compare the score of an Aura chain and the score of a Babe chain, for
@@ -72,16 +77,20 @@ pub(super) fn is_better_block<T>(
     };
 
     let curr_best_chain_score: usize = ascend
-        .map(|i| block_score(&blocks.get(i).unwrap().header))
+        .map(|i| block_score(policy, &blocks.get(i).unwrap().header))
         .sum();
-    let candidate_score = block_score(maybe_new_best);
+    let candidate_score = block_score(policy, maybe_new_best);
     let candidate_chain_score: usize = descend
-        .map(|i| block_score(&blocks.get(i).unwrap().header))
+        .map(|i| block_score(policy, &blocks.get(i).unwrap().header))
         .sum();
     (candidate_chain_score + candidate_score).cmp(&curr_best_chain_score)
 }
 
-fn block_score<'a>(header: impl Into<header::HeaderRef<'a>>) -> usize {
+fn block_score<'a>(policy: BestBlockPolicy, header: impl Into<header::HeaderRef<'a>>) -> usize {
+    if matches!(policy, BestBlockPolicy::LongestChain) {
+        return 1;
+    }
+
     let header = header.into();
     if let Some(pr) = header.digest.babe_pre_runtime() {
         if pr.is_primary() {
@@ -425,6 +425,7 @@ impl<T> NonFinalizedTreeInner<T> {
 
                 let replace = if let Some(new_best_block) = new_best_block {
                     best_block::is_better_block(
+                        self.best_block_policy,
                         &self.blocks,
                         new_best_block,
                         self.blocks.parent(idx),
@@ -443,7 +444,9 @@ impl<T> NonFinalizedTreeInner<T> {
             self.current_best = new_best_block;
             true
         } else {
-            false
+            // When following finality only, the reported best block is the finalized block and
+            // thus always changes.
+            matches!(self.best_block_policy, BestBlockPolicy::FollowFinalityOnly)
         };
 
         let new_finalized_block = self.blocks.get_mut(block_index_to_finalize).unwrap();
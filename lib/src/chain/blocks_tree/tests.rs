@@ -0,0 +1,246 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{
+    chain_information, header, BestBlockPolicy, Block, BlockConsensus, BlockFinality, Config,
+    NonFinalizedTree,
+};
+
+use alloc::{sync::Arc, vec::Vec};
+use core::num::NonZeroU64;
+
+fn new_tree(best_block_policy: BestBlockPolicy) -> NonFinalizedTree<()> {
+    NonFinalizedTree::new(Config {
+        chain_information: chain_information::ChainInformation {
+            finalized_block_header: header::Header {
+                parent_hash: [0; 32],
+                number: 0,
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                digest: header::DigestRef::empty().into(),
+            },
+            consensus: chain_information::ChainInformationConsensus::Aura {
+                finalized_authorities_list: Vec::new(),
+                slot_duration: NonZeroU64::new(6000).unwrap(),
+            },
+            finality: chain_information::ChainInformationFinality::Outsourced,
+        }
+        .try_into()
+        .unwrap(),
+        block_number_bytes: 4,
+        blocks_capacity: 16,
+        allow_unknown_consensus_engines: false,
+        best_block_policy,
+    })
+}
+
+/// Inserts in the tree a child of the given block, bypassing the verification, and returns its
+/// hash. The best block is determined and updated by the same code as when a block is verified
+/// then inserted.
+///
+/// The header contains a Babe pre-runtime digest with a primary or secondary slot claim, which
+/// is what the best block determination looks at.
+fn insert(
+    tree: &mut NonFinalizedTree<()>,
+    parent_hash: [u8; 32],
+    slot_number: u64,
+    primary: bool,
+) -> [u8; 32] {
+    let inner = tree.inner.as_mut().unwrap();
+
+    let parent_tree_index = inner.blocks_by_hash.get(&parent_hash).copied();
+    let parent_number = match parent_tree_index {
+        Some(idx) => inner.blocks.get(idx).unwrap().header.number,
+        None => {
+            assert_eq!(parent_hash, inner.finalized_block_hash);
+            inner.finalized_block_header.number
+        }
+    };
+
+    let pre_digest = if primary {
+        header::BabePreDigest::Primary(header::BabePrimaryPreDigest {
+            authority_index: 0,
+            slot_number,
+            vrf_output: [0; 32],
+            vrf_proof: [0; 64],
+        })
+    } else {
+        header::BabePreDigest::SecondaryPlain(header::BabeSecondaryPlainPreDigest {
+            authority_index: 0,
+            slot_number,
+        })
+    };
+    let digest_items = [header::DigestItem::BabePreDigest(pre_digest)];
+
+    let header = header::Header {
+        parent_hash,
+        number: parent_number + 1,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::from_slice(&digest_items).unwrap().into(),
+    };
+    let hash = header.hash(inner.block_number_bytes);
+
+    let is_new_best = inner.is_better_than_best(parent_tree_index, (&header).into());
+    let reported_new_best = inner.reported_is_new_best(is_new_best);
+
+    inner.insert_verified(
+        parent_tree_index,
+        Block {
+            header,
+            hash,
+            consensus: BlockConsensus::Aura {
+                authorities_list: Arc::new(Vec::new()),
+            },
+            finality: BlockFinality::Outsourced,
+            user_data: (),
+        },
+        is_new_best,
+    );
+
+    // The block is reported as the new best block if and only if it is now the best block.
+    assert_eq!(reported_new_best, tree.best_block_hash() == hash);
+
+    hash
+}
+
+#[test]
+fn longest_chain() {
+    let mut tree = new_tree(BestBlockPolicy::LongestChain);
+    let genesis = tree.finalized_block_hash();
+
+    let a1 = insert(&mut tree, genesis, 1, false);
+    let a2 = insert(&mut tree, a1, 2, false);
+    assert_eq!(tree.best_block_hash(), a2);
+
+    // Primary slot claims don't matter.
+    let b1 = insert(&mut tree, genesis, 3, true);
+    assert_eq!(tree.best_block_hash(), a2);
+
+    // In case of equality, the earliest block is kept.
+    let b2 = insert(&mut tree, b1, 4, true);
+    assert_eq!(tree.best_block_hash(), a2);
+
+    let b3 = insert(&mut tree, b2, 5, false);
+    assert_eq!(tree.best_block_hash(), b3);
+    assert_eq!(tree.best_chain_head_hash(), b3);
+}
+
+#[test]
+fn most_primary_slots() {
+    let mut tree = new_tree(BestBlockPolicy::MostPrimarySlots);
+    let genesis = tree.finalized_block_hash();
+
+    let a1 = insert(&mut tree, genesis, 1, false);
+    let a2 = insert(&mut tree, a1, 2, false);
+    assert_eq!(tree.best_block_hash(), a2);
+
+    // A shorter chain with more primary slot claims is preferred.
+    let b1 = insert(&mut tree, genesis, 3, true);
+    assert_eq!(tree.best_block_hash(), b1);
+
+    let a3 = insert(&mut tree, a2, 4, false);
+    assert_eq!(tree.best_block_hash(), b1);
+
+    // A descendant is always preferred to its ancestor, even without a primary slot claim.
+    let b2 = insert(&mut tree, b1, 5, false);
+    assert_eq!(tree.best_block_hash(), b2);
+
+    let a4 = insert(&mut tree, a3, 6, true);
+    assert_eq!(tree.best_block_hash(), b2);
+    let a5 = insert(&mut tree, a4, 7, true);
+    assert_eq!(tree.best_block_hash(), a5);
+    assert_eq!(tree.best_chain_head_hash(), a5);
+}
+
+#[test]
+fn follow_finality_only() {
+    let mut tree = new_tree(BestBlockPolicy::FollowFinalityOnly);
+    let genesis = tree.finalized_block_hash();
+
+    let a1 = insert(&mut tree, genesis, 1, false);
+    let a2 = insert(&mut tree, a1, 2, false);
+    let b1 = insert(&mut tree, genesis, 3, true);
+
+    // The chain head is still tracked as with `MostPrimarySlots`, but isn't reported.
+    assert_eq!(tree.best_block_hash(), genesis);
+    assert_eq!(tree.best_chain_head_hash(), b1);
+
+    // Finalizing a block changes the reported best block, even though the chain head that is
+    // being tracked is a descendant of the finalized block.
+    let b2 = insert(&mut tree, b1, 4, false);
+    assert_eq!(tree.best_chain_head_hash(), b2);
+    assert!(tree.set_finalized_block(&b1).unwrap().updates_best_block());
+    assert_eq!(tree.best_block_hash(), b1);
+    assert_eq!(tree.best_chain_head_hash(), b2);
+    assert!(tree.set_finalized_block(&a2).is_err());
+}
+
+#[test]
+fn finalizing_ancestor_of_best_keeps_best() {
+    let mut tree = new_tree(BestBlockPolicy::MostPrimarySlots);
+    let genesis = tree.finalized_block_hash();
+
+    let a1 = insert(&mut tree, genesis, 1, false);
+    let a2 = insert(&mut tree, a1, 2, false);
+    let _b1 = insert(&mut tree, genesis, 3, false);
+    assert_eq!(tree.best_block_hash(), a2);
+
+    assert!(!tree.set_finalized_block(&a1).unwrap().updates_best_block());
+    assert_eq!(tree.best_block_hash(), a2);
+}
+
+#[test]
+fn finalizing_other_fork_updates_best() {
+    for policy in [
+        BestBlockPolicy::LongestChain,
+        BestBlockPolicy::MostPrimarySlots,
+        BestBlockPolicy::FollowFinalityOnly,
+    ] {
+        let mut tree = new_tree(policy);
+        let genesis = tree.finalized_block_hash();
+
+        let a1 = insert(&mut tree, genesis, 1, true);
+        let a2 = insert(&mut tree, a1, 2, true);
+        let a3 = insert(&mut tree, a2, 3, true);
+        let a4 = insert(&mut tree, a3, 4, true);
+        let b1 = insert(&mut tree, genesis, 5, false);
+        let b2 = insert(&mut tree, b1, 6, false);
+        let b3 = insert(&mut tree, b2, 7, true);
+        let b3bis = insert(&mut tree, b2, 8, false);
+        let b4bis = insert(&mut tree, b3bis, 9, false);
+        assert_eq!(tree.best_chain_head_hash(), a4);
+
+        // The new best block is searched among the descendants of the finalized block. `b3` has
+        // a primary slot claim while the longer chain ending with `b4bis` doesn't have any.
+        assert!(tree.set_finalized_block(&b2).unwrap().updates_best_block());
+        let expected = match policy {
+            BestBlockPolicy::LongestChain => b4bis,
+            BestBlockPolicy::MostPrimarySlots | BestBlockPolicy::FollowFinalityOnly => b3,
+        };
+        assert_eq!(tree.best_chain_head_hash(), expected);
+        assert_eq!(
+            tree.best_block_hash(),
+            match policy {
+                BestBlockPolicy::FollowFinalityOnly => b2,
+                _ => expected,
+            }
+        );
+    }
+}
@@ -29,8 +29,8 @@ use crate::{
 };
 
 use super::{
-    best_block, fmt, Arc, Block, BlockAccess, BlockConsensus, BlockFinality, Duration, Finality,
-    FinalizedConsensus, NonFinalizedTree, NonFinalizedTreeInner, Vec,
+    best_block, fmt, Arc, BestBlockPolicy, Block, BlockAccess, BlockConsensus, BlockFinality,
+    Duration, Finality, FinalizedConsensus, NonFinalizedTree, NonFinalizedTreeInner, Vec,
 };
 
 use alloc::boxed::Box;
@@ -62,9 +62,10 @@ impl<T> NonFinalizedTree<T> {
             }
             VerifyOut::HeaderOk(context, is_new_best, consensus, finality) => {
                 let hash = context.header.hash(context.chain.block_number_bytes);
+                let reported_new_best = context.chain.reported_is_new_best(is_new_best);
                 Ok(HeaderVerifySuccess::Insert {
                     block_height: context.header.number,
-                    is_new_best,
+                    is_new_best: reported_new_best,
                     insert: HeaderInsert {
                         chain: self,
                         context: Some(context),
@@ -292,6 +293,7 @@ impl<T> NonFinalizedTreeInner<T> {
                 block_header: (&*context.header).into(), // TODO: inefficiency ; in case of header only verify we do an extra allocation to build the context above
                 block_number_bytes: context.chain.block_number_bytes,
                 parent_block_header: parent_block_header.into(),
+                prechecked_signatures: Some(&context.chain.prechecked_signatures),
             })
             .map_err(HeaderVerifyError::VerificationFailed);
 
@@ -321,6 +323,59 @@ struct VerifyContext<T> {
     finality: BlockFinality,
 }
 
+impl<T> NonFinalizedTreeInner<T> {
+    /// Returns `true` if a block with the given header, child of the given block (or of the
+    /// finalized block if `None`), must become the new best block once inserted.
+    pub(super) fn is_better_than_best(
+        &self,
+        parent_tree_index: Option<fork_tree::NodeIndex>,
+        header: header::HeaderRef,
+    ) -> bool {
+        if let Some(current_best) = self.current_best {
+            best_block::is_better_block(
+                self.best_block_policy,
+                &self.blocks,
+                current_best,
+                parent_tree_index,
+                header,
+            ) == Ordering::Greater
+        } else {
+            true
+        }
+    }
+
+    /// Returns the value of `is_new_best` to report to the API user after a block has been
+    /// verified.
+    ///
+    /// The best block as tracked internally is still updated when the policy is
+    /// [`BestBlockPolicy::FollowFinalityOnly`], but isn't reported.
+    pub(super) fn reported_is_new_best(&self, is_new_best: bool) -> bool {
+        is_new_best && !matches!(self.best_block_policy, BestBlockPolicy::FollowFinalityOnly)
+    }
+
+    /// Inserts in the tree a block whose verification has succeeded, and makes it the best
+    /// block if `is_new_best` is `true`.
+    pub(super) fn insert_verified(
+        &mut self,
+        parent_tree_index: Option<fork_tree::NodeIndex>,
+        block: Block<T>,
+        is_new_best: bool,
+    ) {
+        debug_assert_eq!(self.blocks.len(), self.blocks_by_hash.len());
+
+        let hash = block.hash;
+        let new_node_index = self.blocks.insert(parent_tree_index, block);
+
+        let _prev_value = self.blocks_by_hash.insert(hash, new_node_index);
+        // A bug here would be serious enough that it is worth being an `assert!`
+        assert!(_prev_value.is_none());
+
+        if is_new_best {
+            self.current_best = Some(new_node_index);
+        }
+    }
+}
+
 impl<T> VerifyContext<T> {
     fn apply_success_header(
         &mut self,
@@ -346,16 +401,9 @@ impl<T> VerifyContext<T> {
         &mut self,
         success_consensus: verify::header_body::SuccessConsensus,
     ) -> (bool, BlockConsensus, BlockFinality) {
-        let is_new_best = if let Some(current_best) = self.chain.current_best {
-            best_block::is_better_block(
-                &self.chain.blocks,
-                current_best,
-                self.parent_tree_index,
-                (&*self.header).into(),
-            ) == Ordering::Greater
-        } else {
-            true
-        };
+        let is_new_best = self
+            .chain
+            .is_better_than_best(self.parent_tree_index, (&*self.header).into());
 
         let consensus = match (
             success_consensus,
@@ -373,10 +421,25 @@ impl<T> VerifyContext<T> {
                 _,
             ) => {
                 if authorities_change {
-                    todo!() // TODO: fetch from header
-                            /*BlockConsensus::Aura {
-                                authorities_list:
-                            }*/
+                    // The verification has already made sure that the header contains a change
+                    // of authorities.
+                    let new_list = self
+                        .header
+                        .digest
+                        .logs()
+                        .filter_map(|item| match item {
+                            header::DigestItemRef::AuraConsensus(
+                                header::AuraConsensusLogRef::AuthoritiesChange(list),
+                            ) => Some(list),
+                            _ => None,
+                        })
+                        .last()
+                        .unwrap();
+                    BlockConsensus::Aura {
+                        authorities_list: Arc::new(
+                            new_list.map(header::AuraAuthority::from).collect(),
+                        ),
+                    }
                 } else {
                     BlockConsensus::Aura {
                         authorities_list: parent_authorities.clone(),
@@ -807,6 +870,7 @@ impl<T> BodyVerifyRuntimeRequired<T> {
             block_header: (&*self.context.header).into(),
             block_number_bytes: self.context.chain.block_number_bytes,
             parent_block_header: parent_block_header.into(),
+            prechecked_signatures: Some(&self.context.chain.prechecked_signatures),
             block_body,
             main_trie_root_calculation_cache,
             max_log_level: 0,
@@ -1123,12 +1187,7 @@ impl<'c, T> HeaderInsert<'c, T> {
     pub fn insert(mut self, user_data: T) {
         let mut context = self.context.take().unwrap();
 
-        debug_assert_eq!(
-            context.chain.blocks.len(),
-            context.chain.blocks_by_hash.len()
-        );
-
-        let new_node_index = context.chain.blocks.insert(
+        context.chain.insert_verified(
             context.parent_tree_index,
             Block {
                 header: *context.header,
@@ -1137,19 +1196,9 @@ impl<'c, T> HeaderInsert<'c, T> {
                 finality: self.finality.take().unwrap(),
                 user_data,
             },
+            self.is_new_best,
         );
 
-        let _prev_value = context
-            .chain
-            .blocks_by_hash
-            .insert(self.hash, new_node_index);
-        // A bug here would be serious enough that it is worth being an `assert!`
-        assert!(_prev_value.is_none());
-
-        if self.is_new_best {
-            context.chain.current_best = Some(new_node_index);
-        }
-
         self.chain.inner = Some(context.chain);
     }
 
@@ -1223,12 +1272,7 @@ impl<T> BodyInsert<T> {
 
     /// Inserts the block with the given user data.
     pub fn insert(mut self, user_data: T) -> NonFinalizedTree<T> {
-        debug_assert_eq!(
-            self.context.chain.blocks.len(),
-            self.context.chain.blocks_by_hash.len()
-        );
-
-        let new_node_index = self.context.chain.blocks.insert(
+        self.context.chain.insert_verified(
             self.context.parent_tree_index,
             Block {
                 header: *self.context.header,
@@ -1237,20 +1281,9 @@ impl<T> BodyInsert<T> {
                 finality: self.finality,
                 user_data,
             },
+            self.is_new_best,
         );
 
-        let _prev_value = self
-            .context
-            .chain
-            .blocks_by_hash
-            .insert(self.hash, new_node_index);
-        // A bug here would be serious enough that it is worth being an `assert!`
-        assert!(_prev_value.is_none());
-
-        if self.is_new_best {
-            self.context.chain.current_best = Some(new_node_index);
-        }
-
         NonFinalizedTree {
             inner: Some(self.context.chain),
         }
@@ -43,6 +43,7 @@ use crate::{
 };
 
 use alloc::{
+    borrow::ToOwned as _,
     string::{String, ToString as _},
     vec::Vec,
 };
@@ -55,6 +56,11 @@ mod structs;
 #[derive(Clone)]
 pub struct ChainSpec {
     client_spec: structs::ClientSpec,
+
+    /// If the genesis storage has been provided through [`ChainSpec::set_genesis_storage`],
+    /// contains the hash of the root of the genesis storage trie that was originally found in
+    /// the chain specification.
+    expected_genesis_state_root: Option<[u8; 32]>,
 }
 
 impl ChainSpec {
@@ -80,7 +86,59 @@ impl ChainSpec {
             light_sync_state.decode(client_spec.block_number_bytes.unwrap_or(4).into())?;
         }
 
-        Ok(ChainSpec { client_spec })
+        Ok(ChainSpec {
+            client_spec,
+            expected_genesis_state_root: None,
+        })
+    }
+
+    /// Provides the storage of the genesis block, for chain specifications that only contain the
+    /// hash of the root of the genesis storage trie.
+    ///
+    /// This makes it possible to not embed the genesis storage, which can be very large, in the
+    /// chain specification. The storage is verified against the trie root hash found in the
+    /// chain specification when [`ChainSpec::as_chain_information`] is called.
+    pub fn set_genesis_storage(
+        &mut self,
+        items: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<(), SetGenesisStorageError> {
+        self.set_raw_genesis(structs::RawGenesis {
+            top: items
+                .into_iter()
+                .map(|(key, value)| (structs::HexString(key), structs::HexString(value)))
+                .collect(),
+            children_default: Default::default(),
+        })
+    }
+
+    /// Same as [`ChainSpec::set_genesis_storage`], but the storage is provided as JSON, in the
+    /// same format as the `genesis.raw` field of chain specifications.
+    pub fn set_genesis_storage_json(
+        &mut self,
+        json: impl AsRef<[u8]>,
+    ) -> Result<(), SetGenesisStorageError> {
+        let raw_genesis: structs::RawGenesis =
+            serde_json::from_slice(json.as_ref()).map_err(|err| {
+                SetGenesisStorageError::Decode(ParseError(ParseErrorInner::Serde(err)))
+            })?;
+        self.set_raw_genesis(raw_genesis)
+    }
+
+    fn set_raw_genesis(
+        &mut self,
+        raw_genesis: structs::RawGenesis,
+    ) -> Result<(), SetGenesisStorageError> {
+        if !raw_genesis.children_default.is_empty() {
+            return Err(SetGenesisStorageError::ChildTriesUnsupported);
+        }
+
+        let structs::Genesis::StateRootHash(state_root) = &self.client_spec.genesis else {
+            return Err(SetGenesisStorageError::AlreadyPresent);
+        };
+
+        self.expected_genesis_state_root = Some(state_root.0);
+        self.client_spec.genesis = structs::Genesis::Raw(raw_genesis);
+        Ok(())
     }
 
     /// Builds the [`ChainInformation`] corresponding to the genesis block contained in this chain
@@ -110,46 +168,53 @@ impl ChainSpec {
             heap_pages,
             exec_hint: executor::vm::ExecHint::Oneshot,
             allow_unresolved_imports: true,
+            fuel_limit: None,
         })
         .map_err(FromGenesisStorageError::VmInitialization)?;
 
-        let mut chain_information_build = build::ChainInformationBuild::new(build::Config {
-            finalized_block_header: build::ConfigFinalizedBlockHeader::Genesis {
-                state_trie_root_hash: {
-                    let state_version = vm_prototype
-                        .runtime_version()
-                        .decode()
-                        .state_version
-                        .unwrap_or(trie::TrieEntryVersion::V0);
-
-                    match self.genesis_storage() {
-                        GenesisStorage::TrieRootHash(hash) => *hash,
-                        GenesisStorage::Items(genesis_storage) => {
-                            let mut calculation = trie::calculate_root::root_merkle_value(None);
-
-                            loop {
-                                match calculation {
-                                trie::calculate_root::RootMerkleValueCalculation::Finished {
-                                    hash,
-                                    ..
-                                } => break hash,
-                                trie::calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
-                                    calculation = keys.inject(
-                                        genesis_storage.iter().map(|(k, _)| k.iter().copied()),
-                                    );
-                                }
-                                trie::calculate_root::RootMerkleValueCalculation::StorageValue(
-                                    val,
-                                ) => {
-                                    let key: alloc::vec::Vec<u8> = val.key().collect();
-                                    let value = genesis_storage.value(&key[..]);
-                                    calculation = val.inject(value.map(move |v| (v, state_version)));
-                                }
+        let state_trie_root_hash = {
+            let state_version = vm_prototype
+                .runtime_version()
+                .decode()
+                .state_version
+                .unwrap_or(trie::TrieEntryVersion::V0);
+
+            match self.genesis_storage() {
+                GenesisStorage::TrieRootHash(hash) => *hash,
+                GenesisStorage::Items(genesis_storage) => {
+                    let mut calculation = trie::calculate_root::root_merkle_value(None);
+
+                    loop {
+                        match calculation {
+                            trie::calculate_root::RootMerkleValueCalculation::Finished {
+                                hash,
+                                ..
+                            } => break hash,
+                            trie::calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                                calculation = keys
+                                    .inject(genesis_storage.iter().map(|(k, _)| k.iter().copied()));
                             }
+                            trie::calculate_root::RootMerkleValueCalculation::StorageValue(val) => {
+                                let key: alloc::vec::Vec<u8> = val.key().collect();
+                                let value = genesis_storage.value(&key[..]);
+                                calculation = val.inject(value.map(move |v| (v, state_version)));
                             }
                         }
                     }
-                },
+                }
+            }
+        };
+
+        if self
+            .expected_genesis_state_root
+            .map_or(false, |expected| expected != state_trie_root_hash)
+        {
+            return Err(FromGenesisStorageError::StateRootMismatch);
+        }
+
+        let mut chain_information_build = build::ChainInformationBuild::new(build::Config {
+            finalized_block_header: build::ConfigFinalizedBlockHeader::Genesis {
+                state_trie_root_hash,
             },
             runtime: vm_prototype,
         });
@@ -324,6 +389,95 @@ impl ChainSpec {
     }
 }
 
+/// Adds the given bootnode addresses to the `bootNodes` field of the given JSON chain
+/// specification, and returns the updated JSON chain specification.
+///
+/// Contrary to [`ChainSpec::boot_nodes`], each address in `new_boot_nodes` must be a valid
+/// multiaddress ending with a `/p2p/` component, otherwise an error is returned. Addresses that
+/// are already present in the chain specification, or that appear multiple times in
+/// `new_boot_nodes`, are only added once. The bootnodes already present in the chain
+/// specification are kept as-is, even if they can't be parsed.
+///
+/// All the other fields of the chain specification are left untouched. Note that this function
+/// doesn't verify whether the chain specification is otherwise valid.
+pub fn merge_boot_nodes<'a>(
+    chain_spec_json: impl AsRef<[u8]>,
+    new_boot_nodes: impl IntoIterator<Item = &'a str>,
+) -> Result<String, MergeBootNodesError> {
+    let mut chain_spec = serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(
+        chain_spec_json.as_ref(),
+    )
+    .map_err(MergeBootNodesError::InvalidJson)?;
+
+    let boot_nodes = match chain_spec
+        .entry("bootNodes")
+        .or_insert_with(|| serde_json::Value::Array(Vec::new()))
+    {
+        serde_json::Value::Array(list) => list,
+        _ => return Err(MergeBootNodesError::InvalidBootNodesField),
+    };
+
+    // Normalizing the addresses makes it possible to detect duplicates that are written
+    // differently.
+    let normalize = |addr: &str| -> Option<String> {
+        let addr = addr.parse::<libp2p::Multiaddr>().ok()?;
+        match addr.iter().last() {
+            Some(libp2p::multiaddr::ProtocolRef::P2p(peer_id))
+                if libp2p::peer_id::PeerId::from_bytes(peer_id.to_vec()).is_ok() =>
+            {
+                Some(addr.to_string())
+            }
+            _ => None,
+        }
+    };
+
+    let mut known = Vec::with_capacity(boot_nodes.len());
+    for existing in boot_nodes.iter() {
+        let serde_json::Value::String(existing) = existing else {
+            return Err(MergeBootNodesError::InvalidBootNodesField);
+        };
+        known.push(normalize(existing).unwrap_or_else(|| existing.clone()));
+    }
+
+    for new_boot_node in new_boot_nodes {
+        let normalized = match new_boot_node.parse::<libp2p::Multiaddr>() {
+            Ok(_) => normalize(new_boot_node)
+                .ok_or_else(|| MergeBootNodesError::MissingPeerId(new_boot_node.to_owned()))?,
+            Err(_) => {
+                return Err(MergeBootNodesError::InvalidMultiaddr(
+                    new_boot_node.to_owned(),
+                ))
+            }
+        };
+
+        if known.iter().any(|k| *k == normalized) {
+            continue;
+        }
+
+        boot_nodes.push(serde_json::Value::String(normalized.clone()));
+        known.push(normalized);
+    }
+
+    Ok(serde_json::to_string(&chain_spec).unwrap())
+}
+
+/// Error potentially returned by [`merge_boot_nodes`].
+#[derive(Debug, derive_more::Display)]
+pub enum MergeBootNodesError {
+    /// The chain specification isn't a valid JSON object.
+    #[display(fmt = "Failed to parse chain spec: {_0}")]
+    InvalidJson(serde_json::Error),
+    /// The `bootNodes` field of the chain specification isn't a list of strings.
+    #[display(fmt = "The bootNodes field of the chain spec isn't a list of strings")]
+    InvalidBootNodesField,
+    /// One of the bootnode addresses couldn't be parsed as a multiaddress.
+    #[display(fmt = "Invalid bootnode multiaddress: {_0:?}")]
+    InvalidMultiaddr(String),
+    /// One of the bootnode addresses doesn't end with a valid `/p2p/` component.
+    #[display(fmt = "Bootnode multiaddress doesn't end with a valid /p2p/ component: {_0:?}")]
+    MissingPeerId(String),
+}
+
 /// See [`ChainSpec::boot_nodes`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Bootnode<'a> {
@@ -419,7 +573,14 @@ fn convert_epoch(epoch: &light_sync_state::BabeEpoch) -> BabeEpochInformation {
 }
 
 impl LightSyncState {
-    pub fn as_chain_information(&self) -> ChainInformation {
+    /// Builds the [`ChainInformation`] corresponding to this checkpoint.
+    ///
+    /// Returns an error if the checkpoint lacks information necessary in order to build the
+    /// chain information. Note that the returned [`ChainInformation`] isn't verified to be
+    /// valid.
+    pub fn as_chain_information(
+        &self,
+    ) -> Result<ChainInformation, CheckpointToChainInformationError> {
         // Create a sorted list of all regular epochs that haven't been pruned from the sync state.
         let mut epochs: Vec<_> = self
             .inner
@@ -441,13 +602,15 @@ impl LightSyncState {
         epochs.dedup_by_key(|(_, epoch)| epoch.epoch_index);
 
         // Get the latest two epochs.
-        let current_epoch = &epochs[epochs.len() - 2].1;
-        let next_epoch = &epochs[epochs.len() - 1].1;
+        let [.., (_, current_epoch), (_, next_epoch)] = &epochs[..] else {
+            return Err(CheckpointToChainInformationError::MissingBabeEpochs);
+        };
 
-        ChainInformation {
+        Ok(ChainInformation {
             finalized_block_header: self.inner.finalized_block_header.clone(),
             consensus: ChainInformationConsensus::Babe {
-                slots_per_epoch: NonZeroU64::new(current_epoch.duration).unwrap(),
+                slots_per_epoch: NonZeroU64::new(current_epoch.duration)
+                    .ok_or(CheckpointToChainInformationError::ZeroSlotsPerEpoch)?,
                 finalized_block_epoch_information: Some(convert_epoch(current_epoch)),
                 finalized_next_epoch_transition: convert_epoch(next_epoch),
             },
@@ -458,15 +621,18 @@ impl LightSyncState {
                         .grandpa_authority_set
                         .current_authorities
                         .iter()
-                        .map(|authority| crate::header::GrandpaAuthority {
-                            public_key: authority.public_key,
-                            weight: NonZeroU64::new(authority.weight).unwrap(),
+                        .map(|authority| {
+                            Some(crate::header::GrandpaAuthority {
+                                public_key: authority.public_key,
+                                weight: NonZeroU64::new(authority.weight)?,
+                            })
                         })
-                        .collect()
+                        .collect::<Option<_>>()
+                        .ok_or(CheckpointToChainInformationError::ZeroGrandpaWeight)?
                 },
                 finalized_scheduled_change: None, // TODO: unimplemented
             },
-        }
+        })
     }
 }
 
@@ -481,6 +647,20 @@ enum ParseErrorInner {
     Other,
 }
 
+/// Error potentially returned by [`LightSyncState::as_chain_information`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub enum CheckpointToChainInformationError {
+    /// The checkpoint doesn't contain the current and next Babe epochs.
+    #[display(fmt = "Checkpoint doesn't contain the current and next Babe epochs")]
+    MissingBabeEpochs,
+    /// The number of slots per epoch of the current Babe epoch is zero.
+    #[display(fmt = "Babe epoch with zero slots")]
+    ZeroSlotsPerEpoch,
+    /// One of the GrandPa authorities has a weight of zero.
+    #[display(fmt = "GrandPa authority with a weight of zero")]
+    ZeroGrandpaWeight,
+}
+
 /// Error when building the chain information from the genesis storage.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
@@ -497,11 +677,33 @@ pub enum FromGenesisStorageError {
     VmInitialization(executor::host::NewErr),
     /// Chain specification doesn't contain the list of storage items.
     UnknownStorageItems,
+    /// The genesis storage provided with [`ChainSpec::set_genesis_storage`] doesn't match the
+    /// hash of the root of the genesis storage trie found in the chain specification.
+    #[display(fmt = "Genesis storage doesn't match the state root of the chain specification")]
+    StateRootMismatch,
+}
+
+/// Error potentially returned by [`ChainSpec::set_genesis_storage`] and
+/// [`ChainSpec::set_genesis_storage_json`].
+#[derive(Debug, derive_more::Display)]
+pub enum SetGenesisStorageError {
+    /// The chain specification already contains the genesis storage.
+    #[display(fmt = "Chain specification already contains the genesis storage")]
+    AlreadyPresent,
+    /// Failed to decode the JSON genesis storage.
+    #[display(fmt = "{_0}")]
+    Decode(ParseError),
+    /// The genesis storage contains child tries, which aren't supported.
+    #[display(fmt = "Child tries in the genesis storage aren't supported")]
+    ChildTriesUnsupported,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Bootnode, ChainSpec};
+    use super::{
+        merge_boot_nodes, Bootnode, ChainSpec, FromGenesisStorageError, GenesisStorage,
+        MergeBootNodesError, SetGenesisStorageError,
+    };
 
     #[test]
     fn can_decode_polkadot_genesis() {
@@ -626,4 +828,102 @@ mod tests {
         )
         .is_err());
     }
+
+    #[test]
+    fn merge_boot_nodes_appends_and_deduplicates() {
+        let spec = &include_bytes!("chain_spec/example.json")[..];
+        let merged = merge_boot_nodes(
+            spec,
+            [
+                "/dns4/cc1-1.parity.tech/tcp/30333/p2p/12D3KooWFN2mhgpkJsDBuNuE5427AcDrsib8EoqGMZmkxWwx3Md4",
+                "/dns4/example.com/tcp/30333/p2p/12D3KooWEdsXX9657ppNqqrRuaCHFvuNemasgU5msLDwSJ6WqsKc",
+                "/dns4/example.com/tcp/30333/p2p/12D3KooWEdsXX9657ppNqqrRuaCHFvuNemasgU5msLDwSJ6WqsKc",
+            ],
+        )
+        .unwrap();
+
+        let specs = ChainSpec::from_json_bytes(&merged).unwrap();
+        assert_eq!(specs.boot_nodes().len(), 4);
+        assert_eq!(
+            specs.boot_nodes().last().unwrap(),
+            Bootnode::Parsed {
+                multiaddr: "/dns4/example.com/tcp/30333".into(),
+                peer_id: vec![
+                    0, 36, 8, 1, 18, 32, 71, 154, 61, 188, 212, 39, 215, 192, 217, 22, 168, 87,
+                    162, 148, 234, 176, 0, 195, 4, 31, 109, 123, 175, 185, 26, 169, 218, 92, 192,
+                    0, 126, 111
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn merge_boot_nodes_rejects_invalid_addresses() {
+        let spec = &include_bytes!("chain_spec/example.json")[..];
+        assert!(matches!(
+            merge_boot_nodes(spec, ["/some/wrong/multiaddress"]),
+            Err(MergeBootNodesError::InvalidMultiaddr(_))
+        ));
+        assert!(matches!(
+            merge_boot_nodes(spec, ["/dns4/example.com/tcp/30333"]),
+            Err(MergeBootNodesError::MissingPeerId(_))
+        ));
+    }
+
+    #[test]
+    fn genesis_storage_injection() {
+        let spec_with_root = r#"{
+            "name": "Test",
+            "id": "test",
+            "bootNodes": [],
+            "genesis": {
+              "stateRootHash": "0x0000000000000000000000000000000000000000000000000000000000000000"
+            }
+          }"#;
+
+        let mut spec = ChainSpec::from_json_bytes(spec_with_root).unwrap();
+        assert!(matches!(
+            spec.as_chain_information(),
+            Err(FromGenesisStorageError::UnknownStorageItems)
+        ));
+        assert!(matches!(
+            spec.set_genesis_storage_json(
+                r#"{"top":{},"childrenDefault":{"0x00":{"childInfo":[],"childType":1}}}"#
+            ),
+            Err(SetGenesisStorageError::ChildTriesUnsupported)
+        ));
+        assert!(matches!(
+            spec.set_genesis_storage_json("foo"),
+            Err(SetGenesisStorageError::Decode(_))
+        ));
+
+        spec.set_genesis_storage_json(r#"{"top":{"0x1234":"0x5678"},"childrenDefault":{}}"#)
+            .unwrap();
+        match spec.genesis_storage() {
+            GenesisStorage::Items(items) => {
+                assert_eq!(items.value(&[0x12, 0x34]), Some(&[0x56, 0x78][..]))
+            }
+            GenesisStorage::TrieRootHash(_) => panic!(),
+        }
+        assert!(matches!(
+            spec.set_genesis_storage([(vec![0x12, 0x34], vec![0x56, 0x78])]),
+            Err(SetGenesisStorageError::AlreadyPresent)
+        ));
+
+        // The storage of another chain doesn't match the state root.
+        let polkadot =
+            ChainSpec::from_json_bytes(&include_bytes!("chain_spec/example.json")[..]).unwrap();
+        let polkadot_storage = polkadot.genesis_storage().into_genesis_items().unwrap();
+        let mut spec = ChainSpec::from_json_bytes(spec_with_root).unwrap();
+        spec.set_genesis_storage(
+            polkadot_storage
+                .iter()
+                .map(|(key, value)| (key.to_vec(), value.to_vec())),
+        )
+        .unwrap();
+        assert!(matches!(
+            spec.as_chain_information(),
+            Err(FromGenesisStorageError::StateRootMismatch)
+        ));
+    }
 }
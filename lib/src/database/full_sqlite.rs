@@ -36,9 +36,12 @@
 //! not supported.
 //!
 //! In order to minimize disk usage, it is not possible to efficiently retrieve the storage items
-//! of blocks that are ancestors of the finalized block. When a block is finalized, the storage of
-//! its ancestors is lost, and the only way to reconstruct it is to execute all blocks starting
-//! from the genesis to the desired one.
+//! of blocks that are too far below the finalized block. Only the storage of the
+//! [`Config::finalized_storage_history`] most recent ancestors of the finalized block can be
+//! accessed, using [`SqliteFullDatabase::block_storage_main_trie_get`]. The storage of older
+//! blocks is removed by [`SqliteFullDatabase::prune_finalized_storage_history`], which is meant
+//! to be called periodically, and the only way to reconstruct it is to execute all blocks
+//! starting from the genesis to the desired one.
 //!
 //! # About errors handling
 //!
@@ -72,8 +75,9 @@
 
 use crate::{chain::chain_information, header, util};
 
-use core::{fmt, iter, num::NonZeroU64};
+use core::{cmp, fmt, iter, num::NonZeroU64};
 use parking_lot::Mutex;
+use std::collections::BTreeSet;
 
 pub use open::{open, Config, ConfigTy, DatabaseEmpty, DatabaseOpen};
 
@@ -91,6 +95,9 @@ pub struct SqliteFullDatabase {
 
     /// Number of bytes used to encode the block number.
     block_number_bytes: usize,
+
+    /// See [`Config::finalized_storage_history`].
+    finalized_storage_history: u64,
 }
 
 impl SqliteFullDatabase {
@@ -454,6 +461,25 @@ impl SqliteFullDatabase {
         // Update the finalized block in meta.
         meta_set_number(&connection, "finalized", new_finalized_header.number)?;
 
+        // If the storage of past finalized blocks must be kept, the changes that each newly
+        // finalized block performs are reverted into `finalized_storage_main_trie_history` below.
+        // Otherwise, only the storage of the new finalized block remains accessible.
+        // Databases created by older versions lack `finalized_storage_history_oldest`, in which
+        // case the history starts at the current finalized block.
+        if self.finalized_storage_history == 0 {
+            meta_set_number(
+                &connection,
+                "finalized_storage_history_oldest",
+                new_finalized_header.number,
+            )?;
+        } else if meta_get_number(&connection, "finalized_storage_history_oldest")?.is_none() {
+            meta_set_number(
+                &connection,
+                "finalized_storage_history_oldest",
+                current_finalized,
+            )?;
+        }
+
         // Take each block height between `header.number` and `current_finalized + 1`
         // and remove blocks that aren't an ancestor of the new finalized block.
         {
@@ -538,6 +564,23 @@ impl SqliteFullDatabase {
                     CorruptedError::MissingBlockHeader,
                 )))?;
 
+            if self.finalized_storage_history != 0 {
+                let mut statement = connection
+                    .prepare(
+                        "INSERT INTO finalized_storage_main_trie_history(number, key, value, trie_entry_version)
+                    SELECT ?, non_finalized_changes.key, finalized_storage_main_trie.value, finalized_storage_main_trie.trie_entry_version
+                    FROM non_finalized_changes
+                    LEFT JOIN finalized_storage_main_trie ON non_finalized_changes.key = finalized_storage_main_trie.key
+                    WHERE non_finalized_changes.hash = ?",
+                    )
+                    .unwrap()
+                    .bind(1, i64::try_from(height).unwrap())
+                    .unwrap()
+                    .bind(2, &block_hash[..])
+                    .unwrap();
+                statement.next().unwrap();
+            }
+
             let mut statement = connection
                 .prepare(
                     "DELETE FROM finalized_storage_main_trie
@@ -850,6 +893,326 @@ impl SqliteFullDatabase {
 
         Ok(out)
     }
+
+    /// Returns the value associated to a key in the storage of the given block, and the trie
+    /// entry version.
+    ///
+    /// The block can be a non-finalized block, the finalized block, or one of the ancestors of
+    /// the finalized block whose storage hasn't been pruned yet. See
+    /// [`Config::finalized_storage_history`] and
+    /// [`SqliteFullDatabase::prune_finalized_storage_history`].
+    pub fn block_storage_main_trie_get(
+        &self,
+        block_hash: &[u8; 32],
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, u8)>, StorageAccessError> {
+        let connection = self.database.lock();
+        block_storage_main_trie_get(&connection, self.block_number_bytes, block_hash, key)
+    }
+
+    /// Returns the key in the storage of the given block that immediately follows the key
+    /// passed as parameter.
+    ///
+    /// Similar to [`SqliteFullDatabase::block_storage_main_trie_get`], the block can be a
+    /// non-finalized block, the finalized block, or one of the ancestors of the finalized block
+    /// whose storage hasn't been pruned yet.
+    pub fn block_storage_main_trie_next_key(
+        &self,
+        block_hash: &[u8; 32],
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageAccessError> {
+        let connection = self.database.lock();
+        let (block_number, non_finalized_ancestors) =
+            block_storage_ancestry(&connection, self.block_number_bytes, block_hash)?;
+
+        // Every key in the storage of the block is either in the storage of the finalized block,
+        // or has been modified by a finalized descendant of the block, or has been modified by
+        // a non-finalized ancestor of the block. We iterate over the union of these keys, and
+        // skip the ones that aren't in the storage of the block.
+        let mut cursor = key.to_vec();
+        loop {
+            let mut candidate = None::<Vec<u8>>;
+
+            let mut candidates_statements = vec![
+                connection
+                    .prepare(r#"SELECT key FROM finalized_storage_main_trie WHERE key > ? ORDER BY key ASC LIMIT 1"#)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?
+                    .bind(1, &cursor[..])
+                    .unwrap(),
+                connection
+                    .prepare(r#"SELECT key FROM finalized_storage_main_trie_history WHERE key > ? AND number > ? ORDER BY key ASC LIMIT 1"#)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?
+                    .bind(1, &cursor[..])
+                    .unwrap()
+                    .bind(2, i64::try_from(block_number).unwrap())
+                    .unwrap(),
+            ];
+            for ancestor in &non_finalized_ancestors {
+                candidates_statements.push(
+                    connection
+                        .prepare(r#"SELECT key FROM non_finalized_changes WHERE hash = ? AND key > ? ORDER BY key ASC LIMIT 1"#)
+                        .map_err(InternalError)
+                        .map_err(CorruptedError::Internal)
+                        .map_err(AccessError::Corrupted)?
+                        .bind(1, &ancestor[..])
+                        .unwrap()
+                        .bind(2, &cursor[..])
+                        .unwrap(),
+                );
+            }
+
+            for mut statement in candidates_statements {
+                if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+                    continue;
+                }
+
+                let key = statement
+                    .read::<Vec<u8>>(0)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?;
+                if candidate.as_ref().map_or(true, |c| key < *c) {
+                    candidate = Some(key);
+                }
+            }
+
+            let Some(candidate) = candidate else {
+                return Ok(None);
+            };
+
+            if block_storage_main_trie_get(
+                &connection,
+                self.block_number_bytes,
+                block_hash,
+                &candidate,
+            )?
+            .is_some()
+            {
+                return Ok(Some(candidate));
+            }
+
+            cursor = candidate;
+        }
+    }
+
+    /// Returns the list of keys of the storage of the given block that start with the given
+    /// prefix. Pass `&[]` for the prefix to get the list of all keys.
+    ///
+    /// Similar to [`SqliteFullDatabase::block_storage_main_trie_get`], the block can be a
+    /// non-finalized block, the finalized block, or one of the ancestors of the finalized block
+    /// whose storage hasn't been pruned yet.
+    pub fn block_storage_main_trie_keys(
+        &self,
+        block_hash: &[u8; 32],
+        prefix: &[u8],
+    ) -> Result<Vec<Vec<u8>>, StorageAccessError> {
+        let connection = self.database.lock();
+        let (block_number, non_finalized_ancestors) =
+            block_storage_ancestry(&connection, self.block_number_bytes, block_hash)?;
+
+        // See the comment in `block_storage_main_trie_next_key`. We gather the union of all the
+        // keys that might be in the storage of the block, then filter out the ones that aren't.
+        let mut candidates_statements = vec![
+            connection
+                .prepare(r#"SELECT key FROM finalized_storage_main_trie WHERE key >= ?"#)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?
+                .bind(1, prefix)
+                .unwrap(),
+            connection
+                .prepare(r#"SELECT key FROM finalized_storage_main_trie_history WHERE key >= ? AND number > ?"#)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?
+                .bind(1, prefix)
+                .unwrap()
+                .bind(2, i64::try_from(block_number).unwrap())
+                .unwrap(),
+        ];
+        for ancestor in &non_finalized_ancestors {
+            candidates_statements.push(
+                connection
+                    .prepare(r#"SELECT key FROM non_finalized_changes WHERE hash = ? AND key >= ?"#)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?
+                    .bind(1, &ancestor[..])
+                    .unwrap()
+                    .bind(2, prefix)
+                    .unwrap(),
+            );
+        }
+
+        let mut candidates = BTreeSet::new();
+        for mut statement in candidates_statements {
+            while matches!(statement.next().unwrap(), sqlite::State::Row) {
+                let key = statement
+                    .read::<Vec<u8>>(0)
+                    .map_err(InternalError)
+                    .map_err(CorruptedError::Internal)
+                    .map_err(AccessError::Corrupted)?;
+
+                // TODO: hack because I don't know how to ask sqlite to do that
+                if !(key.starts_with(prefix)) {
+                    continue;
+                }
+
+                candidates.insert(key);
+            }
+        }
+
+        let mut out = Vec::with_capacity(candidates.len());
+        for key in candidates {
+            // TODO: checking each key individually is slow, but there's no easy way around this
+            if block_storage_main_trie_get(&connection, self.block_number_bytes, block_hash, &key)?
+                .is_some()
+            {
+                out.push(key);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Returns the number of the oldest finalized block whose storage can be accessed using
+    /// [`SqliteFullDatabase::block_storage_main_trie_get`].
+    pub fn finalized_storage_history_oldest(&self) -> Result<u64, AccessError> {
+        let connection = self.database.lock();
+        match meta_get_number(&connection, "finalized_storage_history_oldest")? {
+            Some(n) => Ok(n),
+            None => finalized_num(&connection),
+        }
+    }
+
+    /// Removes from the database the information necessary to access the storage of the
+    /// finalized blocks that are more than [`Config::finalized_storage_history`] blocks below
+    /// the finalized block.
+    ///
+    /// At most `max_blocks` blocks are processed by each call, in order to not block the
+    /// database for too long. Returns `true` if there remains blocks to prune, in which case this
+    /// function should be called again.
+    pub fn prune_finalized_storage_history(&self, max_blocks: u64) -> Result<bool, AccessError> {
+        let connection = self.database.lock();
+
+        let finalized_number = finalized_num(&connection)?;
+        let Some(oldest) = meta_get_number(&connection, "finalized_storage_history_oldest")? else {
+            return Ok(false);
+        };
+
+        let target = finalized_number.saturating_sub(self.finalized_storage_history);
+        if oldest >= target {
+            return Ok(false);
+        }
+
+        // The changes performed by block `N` are necessary in order to reconstruct the storage
+        // of block `N - 1`. Pruning the storage of all the blocks strictly below `new_oldest`
+        // thus consists in removing the changes of all the blocks up to `new_oldest` included.
+        let new_oldest = cmp::min(oldest.saturating_add(max_blocks), target);
+        let mut statement = connection
+            .prepare(r#"DELETE FROM finalized_storage_main_trie_history WHERE number <= ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, i64::try_from(new_oldest).unwrap())
+            .unwrap();
+        statement.next().unwrap();
+
+        meta_set_number(&connection, "finalized_storage_history_oldest", new_oldest)?;
+        flush(&connection)?;
+
+        Ok(new_oldest < target)
+    }
+
+    /// Returns the value associated to a key in the off-chain storage.
+    pub fn offchain_storage_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, AccessError> {
+        let connection = self.database.lock();
+
+        let mut statement = connection
+            .prepare(r#"SELECT value FROM offchain_storage WHERE key = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, key)
+            .unwrap();
+
+        if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+            return Ok(None);
+        }
+
+        let value = statement
+            .read::<Vec<u8>>(0)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+        Ok(Some(value))
+    }
+
+    /// Sets or removes (if `value` is `None`) the value associated to a key in the off-chain
+    /// storage.
+    ///
+    /// If `old_value` is `Some`, the value is only modified if the current value of the entry is
+    /// equal to the inner value, where `Some(None)` means that the entry must currently be
+    /// absent. Returns `true` if the value has been modified.
+    pub fn offchain_storage_set(
+        &self,
+        key: &[u8],
+        value: Option<&[u8]>,
+        old_value: Option<Option<&[u8]>>,
+    ) -> Result<bool, AccessError> {
+        let connection = self.database.lock();
+
+        if let Some(old_value) = old_value {
+            let mut statement = connection
+                .prepare(r#"SELECT value FROM offchain_storage WHERE key = ?"#)
+                .map_err(InternalError)
+                .map_err(CorruptedError::Internal)
+                .map_err(AccessError::Corrupted)?
+                .bind(1, key)
+                .unwrap();
+
+            let current_value = if matches!(statement.next().unwrap(), sqlite::State::Row) {
+                Some(
+                    statement
+                        .read::<Vec<u8>>(0)
+                        .map_err(InternalError)
+                        .map_err(CorruptedError::Internal)
+                        .map_err(AccessError::Corrupted)?,
+                )
+            } else {
+                None
+            };
+
+            if current_value.as_deref() != old_value {
+                return Ok(false);
+            }
+        }
+
+        offchain_storage_write(&connection, key, value)?;
+        flush(&connection)?;
+        Ok(true)
+    }
+
+    /// Applies the given changes to the off-chain storage. Each change consists in a key and
+    /// a value, where `None` means that the key must be removed.
+    ///
+    /// This is typically used in order to write the off-chain storage changes performed by
+    /// blocks through off-chain indexing.
+    pub fn offchain_storage_apply<'a>(
+        &self,
+        changes: impl Iterator<Item = (&'a [u8], Option<&'a [u8]>)>,
+    ) -> Result<(), AccessError> {
+        let connection = self.database.lock();
+        for (key, value) in changes {
+            offchain_storage_write(&connection, key, value)?;
+        }
+        flush(&connection)?;
+        Ok(())
+    }
 }
 
 impl fmt::Debug for SqliteFullDatabase {
@@ -921,6 +1284,17 @@ pub enum FinalizedAccessError {
     Obsolete,
 }
 
+/// Error while calling [`SqliteFullDatabase::block_storage_main_trie_get`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum StorageAccessError {
+    /// Error accessing the database.
+    Access(AccessError),
+    /// Requested block isn't in the database.
+    UnknownBlock,
+    /// The storage of the requested block has been pruned.
+    StoragePruned,
+}
+
 /// Error in the content of the database.
 // TODO: document and see if any entry is unused
 #[derive(Debug, derive_more::Display)]
@@ -1037,6 +1411,141 @@ fn meta_set_number(
     Ok(())
 }
 
+/// Walks down the ancestry of the given block. Returns the number of its highest ancestor that
+/// isn't above the finalized block (which is the block itself if it isn't above the finalized
+/// block), and the list of its non-finalized ancestors, including the block itself.
+///
+/// Returns an error if the storage of the returned ancestor has been pruned.
+fn block_storage_ancestry(
+    connection: &sqlite::Connection,
+    block_number_bytes: usize,
+    block_hash: &[u8; 32],
+) -> Result<(u64, Vec<[u8; 32]>), StorageAccessError> {
+    let finalized_number = finalized_num(connection)?;
+
+    let mut non_finalized_ancestors = Vec::new();
+    let mut iter_hash = *block_hash;
+    let block_number = loop {
+        let header = block_header(connection, &iter_hash, block_number_bytes)?
+            .ok_or(StorageAccessError::UnknownBlock)?;
+        if header.number <= finalized_number {
+            break header.number;
+        }
+        non_finalized_ancestors.push(iter_hash);
+        iter_hash = header.parent_hash;
+    };
+
+    let oldest = meta_get_number(connection, "finalized_storage_history_oldest")?
+        .unwrap_or(finalized_number);
+    if block_number < oldest {
+        return Err(StorageAccessError::StoragePruned);
+    }
+
+    Ok((block_number, non_finalized_ancestors))
+}
+
+fn block_storage_main_trie_get(
+    connection: &sqlite::Connection,
+    block_number_bytes: usize,
+    block_hash: &[u8; 32],
+    key: &[u8],
+) -> Result<Option<(Vec<u8>, u8)>, StorageAccessError> {
+    let finalized_number = finalized_num(connection)?;
+
+    // Walk down the non-finalized ancestors of the block, if any, looking for a change to
+    // this key.
+    let mut iter_hash = *block_hash;
+    let block_number = loop {
+        let header = block_header(connection, &iter_hash, block_number_bytes)?
+            .ok_or(StorageAccessError::UnknownBlock)?;
+        if header.number <= finalized_number {
+            break header.number;
+        }
+
+        let mut statement = connection
+            .prepare(r#"SELECT value IS NOT NULL, COALESCE(value, X''), COALESCE(trie_entry_version, 0) FROM non_finalized_changes WHERE hash = ? AND key = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, &iter_hash[..])
+            .unwrap()
+            .bind(2, key)
+            .unwrap();
+        if matches!(statement.next().unwrap(), sqlite::State::Row) {
+            return storage_value_from_row(&statement);
+        }
+
+        iter_hash = header.parent_hash;
+    };
+
+    // Blocks whose number is inferior or equal to the finalized block number are all
+    // ancestors of the finalized block, as the other ones have been purged.
+    let oldest = meta_get_number(connection, "finalized_storage_history_oldest")?
+        .unwrap_or(finalized_number);
+    if block_number < oldest {
+        return Err(StorageAccessError::StoragePruned);
+    }
+
+    // The value in the storage of the block is the value before the first change to this
+    // key performed by one of its finalized descendants, or the value in the storage of the
+    // finalized block if there isn't any such change.
+    let mut statement = connection
+        .prepare(r#"SELECT value IS NOT NULL, COALESCE(value, X''), COALESCE(trie_entry_version, 0) FROM finalized_storage_main_trie_history WHERE key = ? AND number > ? ORDER BY number ASC LIMIT 1"#)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?
+        .bind(1, key)
+        .unwrap()
+        .bind(2, i64::try_from(block_number).unwrap())
+        .unwrap();
+    if matches!(statement.next().unwrap(), sqlite::State::Row) {
+        return storage_value_from_row(&statement);
+    }
+
+    let mut statement = connection
+        .prepare(
+            r#"SELECT 1, value, trie_entry_version FROM finalized_storage_main_trie WHERE key = ?"#,
+        )
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?
+        .bind(1, key)
+        .unwrap();
+    if matches!(statement.next().unwrap(), sqlite::State::Row) {
+        return storage_value_from_row(&statement);
+    }
+
+    Ok(None)
+}
+
+fn offchain_storage_write(
+    database: &sqlite::Connection,
+    key: &[u8],
+    value: Option<&[u8]>,
+) -> Result<(), AccessError> {
+    let mut statement = if let Some(value) = value {
+        database
+            .prepare(r#"INSERT OR REPLACE INTO offchain_storage(key, value) VALUES (?, ?)"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, key)
+            .unwrap()
+            .bind(2, value)
+            .unwrap()
+    } else {
+        database
+            .prepare(r#"DELETE FROM offchain_storage WHERE key = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?
+            .bind(1, key)
+            .unwrap()
+    };
+    statement.next().unwrap();
+    Ok(())
+}
+
 fn has_block(database: &sqlite::Connection, hash: &[u8]) -> Result<bool, AccessError> {
     let mut statement = database
         .prepare(r#"SELECT COUNT(*) FROM blocks WHERE hash = ?"#)
@@ -1150,6 +1659,29 @@ fn block_header(
     }
 }
 
+/// Reads the storage value of the current row of the given statement. The first column must
+/// contain a non-zero value if there is a storage value, and the second and third columns the
+/// storage value and trie entry version.
+fn storage_value_from_row(
+    statement: &sqlite::Statement,
+) -> Result<Option<(Vec<u8>, u8)>, StorageAccessError> {
+    if statement.read::<i64>(0).unwrap() == 0 {
+        return Ok(None);
+    }
+
+    let value = statement
+        .read::<Vec<u8>>(1)
+        .map_err(InternalError)
+        .map_err(CorruptedError::Internal)
+        .map_err(AccessError::Corrupted)?;
+
+    let trie_entry_version = u8::try_from(statement.read::<i64>(2).unwrap())
+        .map_err(|_| CorruptedError::InvalidTrieEntryVersion)
+        .map_err(AccessError::Corrupted)?;
+
+    Ok(Some((value, trie_entry_version)))
+}
+
 fn flush(database: &sqlite::Connection) -> Result<(), AccessError> {
     database.execute("COMMIT; BEGIN TRANSACTION;").unwrap();
     Ok(())
@@ -1371,3 +1903,239 @@ fn decode_babe_epoch_information(
         .map_err(|()| CorruptedError::InvalidBabeEpochInformation)
         .map_err(AccessError::Corrupted)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{open, Config, ConfigTy, DatabaseOpen, SqliteFullDatabase, StorageAccessError};
+    use crate::{chain::chain_information, header};
+
+    const BLOCK_NUMBER_BYTES: usize = 4;
+
+    /// Builds a database whose genesis block storage contains `a` and `b`.
+    fn database(finalized_storage_history: u64) -> (SqliteFullDatabase, [u8; 32]) {
+        let DatabaseOpen::Empty(empty) = open(Config {
+            ty: ConfigTy::Memory,
+            block_number_bytes: BLOCK_NUMBER_BYTES,
+            finalized_storage_history,
+        })
+        .unwrap() else {
+            panic!()
+        };
+
+        let genesis = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        let genesis_hash = genesis.hash(BLOCK_NUMBER_BYTES);
+
+        let database = empty
+            .initialize(
+                &chain_information::ChainInformation {
+                    finalized_block_header: genesis,
+                    consensus: chain_information::ChainInformationConsensus::Unknown,
+                    finality: chain_information::ChainInformationFinality::Outsourced,
+                },
+                [].into_iter(),
+                None,
+                [(&b"a"[..], &b"genesis"[..]), (&b"b"[..], &b"genesis"[..])].into_iter(),
+                0,
+            )
+            .unwrap();
+
+        (database, genesis_hash)
+    }
+
+    /// Inserts a child of `parent` performing the given storage changes, and returns its hash.
+    fn insert(
+        database: &SqliteFullDatabase,
+        parent: &[u8; 32],
+        number: u64,
+        changes: &[(&[u8], Option<&[u8]>)],
+    ) -> [u8; 32] {
+        let header = header::Header {
+            parent_hash: *parent,
+            number,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        database
+            .insert(
+                &header.scale_encoding_vec(BLOCK_NUMBER_BYTES),
+                true,
+                core::iter::empty::<Vec<u8>>(),
+                changes.iter().copied(),
+                0,
+            )
+            .unwrap();
+        header.hash(BLOCK_NUMBER_BYTES)
+    }
+
+    fn get(database: &SqliteFullDatabase, block: &[u8; 32], key: &[u8]) -> Option<Vec<u8>> {
+        database
+            .block_storage_main_trie_get(block, key)
+            .unwrap()
+            .map(|(value, _)| value)
+    }
+
+    /// Builds a database containing the genesis block and three finalized blocks, and returns
+    /// the hashes of these four blocks.
+    fn finalized_chain(finalized_storage_history: u64) -> (SqliteFullDatabase, [[u8; 32]; 4]) {
+        let (database, block0) = database(finalized_storage_history);
+        let block1 = insert(&database, &block0, 1, &[(b"a", Some(b"block1"))]);
+        let block2 = insert(
+            &database,
+            &block1,
+            2,
+            &[(b"b", None), (b"c", Some(b"block2"))],
+        );
+        let block3 = insert(&database, &block2, 3, &[(b"a", Some(b"block3"))]);
+        database.set_finalized(&block3).unwrap();
+        (database, [block0, block1, block2, block3])
+    }
+
+    #[test]
+    fn history_reverts_changes() {
+        let (database, [block0, block1, block2, block3]) = finalized_chain(16);
+
+        assert_eq!(
+            get(&database, &block0, b"a").as_deref(),
+            Some(&b"genesis"[..])
+        );
+        assert_eq!(
+            get(&database, &block0, b"b").as_deref(),
+            Some(&b"genesis"[..])
+        );
+        assert_eq!(get(&database, &block0, b"c"), None);
+
+        assert_eq!(
+            get(&database, &block1, b"a").as_deref(),
+            Some(&b"block1"[..])
+        );
+        assert_eq!(
+            get(&database, &block1, b"b").as_deref(),
+            Some(&b"genesis"[..])
+        );
+        assert_eq!(get(&database, &block1, b"c"), None);
+
+        assert_eq!(
+            get(&database, &block2, b"a").as_deref(),
+            Some(&b"block1"[..])
+        );
+        assert_eq!(get(&database, &block2, b"b"), None);
+        assert_eq!(
+            get(&database, &block2, b"c").as_deref(),
+            Some(&b"block2"[..])
+        );
+
+        assert_eq!(
+            get(&database, &block3, b"a").as_deref(),
+            Some(&b"block3"[..])
+        );
+        assert_eq!(get(&database, &block3, b"b"), None);
+        assert_eq!(
+            get(&database, &block3, b"c").as_deref(),
+            Some(&b"block2"[..])
+        );
+
+        assert_eq!(
+            database.block_storage_main_trie_keys(&block0, b"").unwrap(),
+            vec![b"a".to_vec(), b"b".to_vec()]
+        );
+        assert_eq!(
+            database.block_storage_main_trie_keys(&block2, b"").unwrap(),
+            vec![b"a".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(
+            database
+                .block_storage_main_trie_next_key(&block0, b"a")
+                .unwrap(),
+            Some(b"b".to_vec())
+        );
+        assert_eq!(
+            database
+                .block_storage_main_trie_next_key(&block2, b"a")
+                .unwrap(),
+            Some(b"c".to_vec())
+        );
+        assert_eq!(
+            database
+                .block_storage_main_trie_next_key(&block0, b"b")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn history_and_non_finalized_blocks() {
+        let (database, [_, _, block2, block3]) = finalized_chain(16);
+        let block4 = insert(&database, &block3, 4, &[(b"c", None)]);
+
+        assert_eq!(
+            get(&database, &block4, b"a").as_deref(),
+            Some(&b"block3"[..])
+        );
+        assert_eq!(get(&database, &block4, b"c"), None);
+        assert_eq!(
+            get(&database, &block2, b"c").as_deref(),
+            Some(&b"block2"[..])
+        );
+    }
+
+    #[test]
+    fn prune_history() {
+        let (database, [block0, block1, block2, block3]) = finalized_chain(1);
+        assert_eq!(database.finalized_storage_history_oldest().unwrap(), 0);
+
+        // The storage of the blocks below block 2 must be pruned, one block at a time.
+        assert!(database.prune_finalized_storage_history(1).unwrap());
+        assert_eq!(database.finalized_storage_history_oldest().unwrap(), 1);
+        assert!(matches!(
+            database.block_storage_main_trie_get(&block0, b"a"),
+            Err(StorageAccessError::StoragePruned)
+        ));
+        assert_eq!(
+            get(&database, &block1, b"a").as_deref(),
+            Some(&b"block1"[..])
+        );
+
+        assert!(!database.prune_finalized_storage_history(1).unwrap());
+        assert_eq!(database.finalized_storage_history_oldest().unwrap(), 2);
+        assert!(matches!(
+            database.block_storage_main_trie_get(&block1, b"a"),
+            Err(StorageAccessError::StoragePruned)
+        ));
+
+        // The storage of the blocks that are kept is still correct.
+        assert_eq!(
+            get(&database, &block2, b"a").as_deref(),
+            Some(&b"block1"[..])
+        );
+        assert_eq!(get(&database, &block2, b"b"), None);
+        assert_eq!(
+            get(&database, &block3, b"a").as_deref(),
+            Some(&b"block3"[..])
+        );
+
+        // Nothing more to prune.
+        assert!(!database.prune_finalized_storage_history(1).unwrap());
+    }
+
+    #[test]
+    fn no_history() {
+        let (database, [block0, _, _, block3]) = finalized_chain(0);
+        assert_eq!(database.finalized_storage_history_oldest().unwrap(), 3);
+        assert!(matches!(
+            database.block_storage_main_trie_get(&block0, b"a"),
+            Err(StorageAccessError::StoragePruned)
+        ));
+        assert_eq!(
+            get(&database, &block3, b"a").as_deref(),
+            Some(&b"block3"[..])
+        );
+        assert!(!database.prune_finalized_storage_history(64).unwrap());
+    }
+}
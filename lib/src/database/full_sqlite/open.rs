@@ -74,6 +74,11 @@ Keys in that table:
 
  - `finalized` (number): Height of the finalized block, as a 64bits big endian number.
 
+ - `finalized_storage_history_oldest` (number): Height of the oldest finalized block whose storage
+ can be reconstructed from `finalized_storage_main_trie` and
+ `finalized_storage_main_trie_history`. Always inferior or equal to `finalized`. If missing, only
+ the storage of the finalized block is accessible.
+
  - `grandpa_authorities_set_id` (number): Id of the authorities set that must finalize the block
  right after the finalized block. The value is 0 at the genesis block, and increased by 1 at every
  authorities change. Missing if and only if the chain doesn't use Grandpa.
@@ -145,6 +150,27 @@ CREATE TABLE IF NOT EXISTS finalized_storage_main_trie(
     trie_entry_version INTEGER NOT NULL
 );
 
+/*
+For finalized blocks whose height is strictly superior to `finalized_storage_history_oldest` (see
+`meta`), contains the changes that must be reverted in order to obtain the storage of the parent
+of the block from the storage of the block. In other words, for each key modified by the block,
+contains the value at this key in the storage of the parent of the block.
+The storage of a finalized block can be obtained by starting from `finalized_storage_main_trie`
+then reverting the changes of all the blocks between the finalized block and the desired one.
+*/
+CREATE TABLE IF NOT EXISTS finalized_storage_main_trie_history(
+    -- Height of the block that has performed the change.
+    number INTEGER NOT NULL,
+    key BLOB NOT NULL,
+    -- `value` is NULL if the key is absent from the storage of the parent of the block.
+    value BLOB,
+    -- Same NULL-ness remark as for `value`
+    trie_entry_version INTEGER,
+    UNIQUE(number, key),
+    CHECK((trie_entry_version IS NULL AND value IS NULL) OR (trie_entry_version IS NOT NULL AND value IS NOT NULL))
+);
+CREATE INDEX IF NOT EXISTS finalized_storage_main_trie_history_by_key ON finalized_storage_main_trie_history(key, number);
+
 /*
 For non-finalized blocks (i.e. blocks that descend from the finalized block), contains changes
 that this block performs on the storage.
@@ -195,6 +221,15 @@ CREATE TABLE IF NOT EXISTS aura_finalized_authorities(
     CHECK(length(public_key) == 32)
 );
 
+/*
+Off-chain storage. Contains the entries written by off-chain workers in order to persist data
+between calls, and the entries written by finalized blocks through off-chain indexing.
+*/
+CREATE TABLE IF NOT EXISTS offchain_storage(
+    key BLOB NOT NULL PRIMARY KEY,
+    value BLOB NOT NULL
+);
+
     "#,
         )
         .map_err(super::InternalError)?;
@@ -216,11 +251,13 @@ CREATE TABLE IF NOT EXISTS aura_finalized_authorities(
         DatabaseOpen::Open(SqliteFullDatabase {
             database: parking_lot::Mutex::new(database),
             block_number_bytes: config.block_number_bytes, // TODO: consider storing this value in the DB and check it when opening
+            finalized_storage_history: config.finalized_storage_history,
         })
     } else {
         DatabaseOpen::Empty(DatabaseEmpty {
             database,
             block_number_bytes: config.block_number_bytes,
+            finalized_storage_history: config.finalized_storage_history,
         })
     })
 }
@@ -233,6 +270,13 @@ pub struct Config<'a> {
 
     /// Number of bytes used to encode the block number.
     pub block_number_bytes: usize,
+
+    /// Number of finalized blocks below the latest finalized block whose storage is kept in the
+    /// database. If 0, only the storage of the latest finalized block is accessible.
+    ///
+    /// The storage of older blocks isn't immediately removed when a block is finalized. Instead,
+    /// it must be removed by calling [`SqliteFullDatabase::prune_finalized_storage_history`].
+    pub finalized_storage_history: u64,
 }
 
 /// Type of database.
@@ -264,6 +308,9 @@ pub struct DatabaseEmpty {
 
     /// See the similar field in [`SqliteFullDatabase`].
     block_number_bytes: usize,
+
+    /// See the similar field in [`SqliteFullDatabase`].
+    finalized_storage_history: u64,
 }
 
 impl DatabaseEmpty {
@@ -362,6 +409,12 @@ impl DatabaseEmpty {
             chain_information.finalized_block_header.number,
         )
         .unwrap();
+        super::meta_set_number(
+            &self.database,
+            "finalized_storage_history_oldest",
+            chain_information.finalized_block_header.number,
+        )
+        .unwrap();
 
         match &chain_information.finality {
             chain_information::ChainInformationFinalityRef::Outsourced => {}
@@ -470,6 +523,7 @@ impl DatabaseEmpty {
         Ok(SqliteFullDatabase {
             database: parking_lot::Mutex::new(self.database),
             block_number_bytes: self.block_number_bytes,
+            finalized_storage_history: self.finalized_storage_history,
         })
     }
 }
@@ -33,6 +33,11 @@
 //! >           could theoretically be handled directly by this module, it might be useful for
 //! >           testing purposes to have the possibility to return a deterministic value.
 //!
+//! > **Note**: HTTP requests made by the runtime through the `ext_offchain_http_*` functions
+//! >           must be performed by the user, starting with
+//! >           [`HostVm::OffchainHttpRequestStart`]. This module doesn't keep track of the
+//! >           requests that are in progress.
+//!
 //! Contrary to most programs, runtime code doesn't have a singe `main` or `start` function.
 //! Instead, it exposes several entry points. Which one to call indicates which action it has to
 //! perform. Not all entry points are necessarily available on all runtimes.
@@ -153,7 +158,8 @@
 //!         module: &wasm_binary_code,
 //!         heap_pages: HeapPages::from(2048),
 //!         exec_hint: smoldot::executor::vm::ExecHint::Oneshot,
-//!         allow_unresolved_imports: false
+//!         allow_unresolved_imports: false,
+//!         fuel_limit: None,
 //!     }).unwrap();
 //!     prototype.run_no_param("Core_version").unwrap().into()
 //! };
@@ -207,11 +213,13 @@ use tiny_keccak::Hasher as _;
 pub mod runtime_version;
 
 pub use runtime_version::{CoreVersion, CoreVersionError, CoreVersionRef};
+pub use tracing::{TracingEntry, TracingLevel};
 pub use trie::TrieEntryVersion;
 pub use vm::HeapPages;
 pub use zstd::Error as ModuleFormatError;
 
 mod tests;
+mod tracing;
 mod zstd;
 
 /// Configuration for [`HostVmPrototype::new`].
@@ -234,6 +242,14 @@ pub struct Config<TModule> {
     /// a [`Error::UnresolvedFunctionCalled`] error will be generated if the module tries to call
     /// an unresolved function.
     pub allow_unresolved_imports: bool,
+
+    /// Maximum amount of fuel that each call started from the [`HostVmPrototype`] is allowed to
+    /// consume. If the limit is reached, the execution fails with an [`Error::Trap`] containing
+    /// a [`vm::Trap::OutOfFuel`]. `None` means no limit, in which case fuel metering is disabled.
+    ///
+    /// The amount of fuel consumed is deterministic, contrary to a timeout based on the time
+    /// that has elapsed. See [`vm::VirtualMachinePrototype::new`] for more details.
+    pub fuel_limit: Option<u64>,
 }
 
 /// Prototype for an [`HostVm`].
@@ -289,6 +305,7 @@ impl HostVmPrototype {
             let vm_proto = vm::VirtualMachinePrototype::new(
                 module,
                 config.exec_hint,
+                config.fuel_limit,
                 // This closure is called back for each function that the runtime imports.
                 |mod_name, f_name, signature| {
                     if mod_name != "env" {
@@ -372,6 +389,9 @@ impl HostVmPrototype {
                     }
                     HostVm::LogEmit(log) => vm = log.resume(),
 
+                    // Tracing is disabled.
+                    HostVm::TracingEnabled(req) => vm = req.resume(false),
+
                     HostVm::Error { error, .. } => {
                         return Err(NewErr::CoreVersion(CoreVersionError::Run(error)))
                     }
@@ -549,9 +569,48 @@ pub enum HostVm {
     /// Must the set value of an off-chain storage entry.
     #[from]
     ExternalOffchainStorageSet(ExternalOffchainStorageSet),
+    /// Must load a value from the local off-chain storage.
+    #[from]
+    OffchainStorageGet(OffchainStorageGet),
+    /// Must set or clear a value of the local off-chain storage.
+    #[from]
+    OffchainStorageSet(OffchainStorageSet),
+    /// Need to provide the current timestamp.
+    #[from]
+    OffchainTimestamp(OffchainTimestamp),
+    /// Need to provide a random seed.
+    #[from]
+    OffchainRandomSeed(OffchainRandomSeed),
+    /// Runtime would like to start an HTTP request.
+    #[from]
+    OffchainHttpRequestStart(OffchainHttpRequestStart),
+    /// Runtime would like to add a header to an HTTP request.
+    #[from]
+    OffchainHttpRequestAddHeader(OffchainHttpRequestAddHeader),
+    /// Runtime would like to write a chunk of the body of an HTTP request.
+    #[from]
+    OffchainHttpRequestWriteBody(OffchainHttpRequestWriteBody),
+    /// Runtime would like to wait for the responses of HTTP requests.
+    #[from]
+    OffchainHttpResponseWait(OffchainHttpResponseWait),
+    /// Need to provide the headers of the response of an HTTP request.
+    #[from]
+    OffchainHttpResponseHeaders(OffchainHttpResponseHeaders),
+    /// Need to provide a chunk of the body of the response of an HTTP request.
+    #[from]
+    OffchainHttpResponseReadBody(OffchainHttpResponseReadBody),
+    /// Need to indicate whether the local node is a validator.
+    #[from]
+    OffchainIsValidator(OffchainIsValidator),
+    /// Runtime would like to submit a transaction.
+    #[from]
+    OffchainSubmitTransaction(OffchainSubmitTransaction),
     /// Need to verify whether a signature is valid.
     #[from]
     SignatureVerification(SignatureVerification),
+    /// Need to generate a new key pair and store it in the keystore.
+    #[from]
+    KeyGeneration(KeyGeneration),
     /// Need to call `Core_version` on the given Wasm code and return the raw output (i.e.
     /// still SCALE-encoded), or an error if the call has failed.
     #[from]
@@ -578,6 +637,19 @@ pub enum HostVm {
     /// Runtime has emitted a log entry.
     #[from]
     LogEmit(LogEmit),
+    /// Runtime would like to know whether the spans and events with the given metadata should
+    /// be reported.
+    #[from]
+    TracingEnabled(TracingEnabled),
+    /// Runtime has entered a span.
+    #[from]
+    TracingEnterSpan(TracingEnterSpan),
+    /// Runtime has emitted an event.
+    #[from]
+    TracingEvent(TracingEvent),
+    /// Runtime has exited a span.
+    #[from]
+    TracingExitSpan(TracingExitSpan),
 }
 
 impl HostVm {
@@ -595,12 +667,29 @@ impl HostVm {
             HostVm::ExternalStorageNextKey(inner) => inner.inner.into_prototype(),
             HostVm::ExternalStorageNextChildTrie(inner) => inner.inner.into_prototype(),
             HostVm::ExternalOffchainStorageSet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainStorageGet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainStorageSet(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainTimestamp(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainRandomSeed(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestStart(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestAddHeader(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestWriteBody(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseWait(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseHeaders(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseReadBody(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainIsValidator(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainSubmitTransaction(inner) => inner.inner.into_prototype(),
             HostVm::SignatureVerification(inner) => inner.inner.into_prototype(),
+            HostVm::KeyGeneration(inner) => inner.inner.into_prototype(),
             HostVm::CallRuntimeVersion(inner) => inner.inner.into_prototype(),
             HostVm::StartStorageTransaction(inner) => inner.inner.into_prototype(),
             HostVm::EndStorageTransaction { resume, .. } => resume.inner.into_prototype(),
             HostVm::GetMaxLogLevel(inner) => inner.inner.into_prototype(),
             HostVm::LogEmit(inner) => inner.inner.into_prototype(),
+            HostVm::TracingEnabled(inner) => inner.inner.into_prototype(),
+            HostVm::TracingEnterSpan(inner) => inner.inner.into_prototype(),
+            HostVm::TracingEvent(inner) => inner.inner.into_prototype(),
+            HostVm::TracingExitSpan(inner) => inner.inner.into_prototype(),
         }
     }
 }
@@ -849,6 +938,80 @@ impl ReadyToRun {
             }};
         }
 
+        macro_rules! expect_u64 {
+            ($num:expr) => {{
+                match &params[$num] {
+                    vm::WasmValue::I64(v) => u64::from_ne_bytes(v.to_ne_bytes()),
+                    // The signatures are checked at initialization and the Wasm VM ensures that
+                    // the proper parameter types are provided.
+                    _ => unreachable!(),
+                }
+            }};
+        }
+
+        // Request IDs of HTTP requests are 16 bits integers passed as 32 bits integers.
+        macro_rules! expect_http_request_id {
+            ($num:expr) => {{
+                match u16::try_from(expect_u32!($num)) {
+                    Ok(id) => id,
+                    Err(_) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
+        // Deadlines of HTTP operations are SCALE-encoded `Option<u64>`s.
+        macro_rules! expect_http_deadline {
+            ($num:expr) => {{
+                let deadline = {
+                    let encoded = expect_pointer_size!($num);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            nom::number::complete::le_u64,
+                        ))(encoded.as_ref())
+                        .map(|(_, deadline)| deadline);
+                    parsing_result.map_err(|_| ())
+                };
+
+                match deadline {
+                    Ok(deadline) => deadline,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
+        // Spans and events are SCALE-encoded `WasmEntryAttributes`.
+        macro_rules! expect_tracing_entry {
+            ($num:expr) => {{
+                let entry = {
+                    let encoded = expect_pointer_size!($num);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(tracing::decode_entry)(encoded.as_ref())
+                            .map(|(_, entry)| entry);
+                    parsing_result.map_err(|_| ())
+                };
+
+                match entry {
+                    Ok(entry) => entry,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
         macro_rules! expect_state_version {
             ($num:expr) => {{
                 match &params[$num] {
@@ -1503,7 +1666,35 @@ impl ReadyToRun {
                 })
             }
             HostFunction::ext_crypto_ed25519_public_keys_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_crypto_ed25519_generate_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_crypto_ed25519_generate_version_1 => {
+                let key_type_ptr = expect_pointer_constant_size_raw!(0, 4);
+                let seed = {
+                    let input = expect_pointer_size!(1);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_bytes_decode,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result.map(|seed| seed.to_vec()));
+                    parsing_result.map_err(|_| ())
+                };
+
+                let seed = match seed {
+                    Ok(seed) => seed,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                HostVm::KeyGeneration(KeyGeneration {
+                    algorithm: KeyGenerationAlgorithm::Ed25519,
+                    key_type_ptr,
+                    seed,
+                    inner: self.inner,
+                })
+            }
             HostFunction::ext_crypto_ed25519_sign_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_ed25519_verify_version_1 => {
                 let (message_ptr, message_size) = expect_pointer_size_raw!(1);
@@ -1518,7 +1709,35 @@ impl ReadyToRun {
             }
             HostFunction::ext_crypto_ed25519_batch_verify_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_sr25519_public_keys_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_crypto_sr25519_generate_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_crypto_sr25519_generate_version_1 => {
+                let key_type_ptr = expect_pointer_constant_size_raw!(0, 4);
+                let seed = {
+                    let input = expect_pointer_size!(1);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_bytes_decode,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result.map(|seed| seed.to_vec()));
+                    parsing_result.map_err(|_| ())
+                };
+
+                let seed = match seed {
+                    Ok(seed) => seed,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                HostVm::KeyGeneration(KeyGeneration {
+                    algorithm: KeyGenerationAlgorithm::Sr25519,
+                    key_type_ptr,
+                    seed,
+                    inner: self.inner,
+                })
+            }
             HostFunction::ext_crypto_sr25519_sign_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_sr25519_verify_version_1 => {
                 let (message_ptr, message_size) = expect_pointer_size_raw!(1);
@@ -1543,7 +1762,35 @@ impl ReadyToRun {
                 })
             }
             HostFunction::ext_crypto_sr25519_batch_verify_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_crypto_ecdsa_generate_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_crypto_ecdsa_generate_version_1 => {
+                let key_type_ptr = expect_pointer_constant_size_raw!(0, 4);
+                let seed = {
+                    let input = expect_pointer_size!(1);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_bytes_decode,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result.map(|seed| seed.to_vec()));
+                    parsing_result.map_err(|_| ())
+                };
+
+                let seed = match seed {
+                    Ok(seed) => seed,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                HostVm::KeyGeneration(KeyGeneration {
+                    algorithm: KeyGenerationAlgorithm::Ecdsa,
+                    key_type_ptr,
+                    seed,
+                    inner: self.inner,
+                })
+            }
             HostFunction::ext_crypto_ecdsa_sign_version_1 => {
                 // NOTE: safe to unwrap here because we supply the nn to blake2b fn
                 let data = <[u8; 32]>::try_from(
@@ -1856,31 +2103,180 @@ impl ReadyToRun {
                     inner: self.inner,
                 })
             }
-            HostFunction::ext_offchain_is_validator_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_submit_transaction_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_offchain_is_validator_version_1 => {
+                HostVm::OffchainIsValidator(OffchainIsValidator { inner: self.inner })
+            }
+            HostFunction::ext_offchain_submit_transaction_version_1 => {
+                let (tx_ptr, tx_size) = expect_pointer_size_raw!(0);
+                HostVm::OffchainSubmitTransaction(OffchainSubmitTransaction {
+                    tx_ptr,
+                    tx_size,
+                    inner: self.inner,
+                })
+            }
             HostFunction::ext_offchain_network_state_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_timestamp_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_offchain_timestamp_version_1 => {
+                HostVm::OffchainTimestamp(OffchainTimestamp { inner: self.inner })
+            }
             HostFunction::ext_offchain_sleep_until_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_random_seed_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_local_storage_set_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_offchain_random_seed_version_1 => {
+                HostVm::OffchainRandomSeed(OffchainRandomSeed { inner: self.inner })
+            }
+            HostFunction::ext_offchain_local_storage_set_version_1 => {
+                // The storage kind (first parameter) is ignored. Substrate treats the "local"
+                // kind the same way as the "persistent" kind.
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                let (value_ptr, value_size) = expect_pointer_size_raw!(2);
+                HostVm::OffchainStorageSet(OffchainStorageSet {
+                    key_ptr,
+                    key_size,
+                    value: Some((value_ptr, value_size)),
+                    old_value: None,
+                    inner: self.inner,
+                })
+            }
             HostFunction::ext_offchain_local_storage_compare_and_set_version_1 => {
-                host_fn_not_implemented!()
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                let (old_value_ptr, old_value_size) = expect_pointer_size_raw!(2);
+                let (value_ptr, value_size) = expect_pointer_size_raw!(3);
+
+                // The old value is a SCALE-encoded `Option<Vec<u8>>`. We decode it here in order
+                // to only have to store a pointer to the actual value.
+                let old_value = {
+                    let encoded = self
+                        .inner
+                        .vm
+                        .read_memory(old_value_ptr, old_value_size)
+                        .unwrap();
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_bytes_decode,
+                        ))(encoded.as_ref())
+                        .map(|(_, value)| value.map(|v| u32::try_from(v.len()).unwrap()));
+                    parsing_result.map_err(|_| ())
+                };
+
+                let old_value = match old_value {
+                    Ok(Some(len)) => Some(Some((old_value_ptr + old_value_size - len, len))),
+                    Ok(None) => Some(None),
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
+
+                HostVm::OffchainStorageSet(OffchainStorageSet {
+                    key_ptr,
+                    key_size,
+                    value: Some((value_ptr, value_size)),
+                    old_value,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_offchain_local_storage_get_version_1 => {
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::OffchainStorageGet(OffchainStorageGet {
+                    key_ptr,
+                    key_size,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_offchain_local_storage_clear_version_1 => {
+                let (key_ptr, key_size) = expect_pointer_size_raw!(1);
+                HostVm::OffchainStorageSet(OffchainStorageSet {
+                    key_ptr,
+                    key_size,
+                    value: None,
+                    old_value: None,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_offchain_http_request_start_version_1 => {
+                let (method_ptr, method_size) = expect_pointer_size_raw!(0);
+                let (uri_ptr, uri_size) = expect_pointer_size_raw!(1);
+                HostVm::OffchainHttpRequestStart(OffchainHttpRequestStart {
+                    method_ptr,
+                    method_size,
+                    uri_ptr,
+                    uri_size,
+                    inner: self.inner,
+                })
             }
-            HostFunction::ext_offchain_local_storage_get_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_local_storage_clear_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_offchain_http_request_start_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_offchain_http_request_add_header_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                let (name_ptr, name_size) = expect_pointer_size_raw!(1);
+                let (value_ptr, value_size) = expect_pointer_size_raw!(2);
+                HostVm::OffchainHttpRequestAddHeader(OffchainHttpRequestAddHeader {
+                    request_id,
+                    name_ptr,
+                    name_size,
+                    value_ptr,
+                    value_size,
+                    inner: self.inner,
+                })
             }
             HostFunction::ext_offchain_http_request_write_body_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                let (chunk_ptr, chunk_size) = expect_pointer_size_raw!(1);
+                let deadline = expect_http_deadline!(2);
+                HostVm::OffchainHttpRequestWriteBody(OffchainHttpRequestWriteBody {
+                    request_id,
+                    chunk_ptr,
+                    chunk_size,
+                    deadline,
+                    inner: self.inner,
+                })
+            }
+            HostFunction::ext_offchain_http_response_wait_version_1 => {
+                // The input is a SCALE-encoded `Vec<u16>` of request IDs.
+                let request_ids = {
+                    let encoded = expect_pointer_size!(0);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(nom::multi::length_count(
+                            util::nom_scale_compact_usize,
+                            nom::number::complete::le_u16,
+                        ))(encoded.as_ref())
+                        .map(|(_, ids)| ids);
+                    parsing_result.map_err(|_| ())
+                };
+
+                let request_ids = match request_ids {
+                    Ok(ids) => ids,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
+
+                let deadline = expect_http_deadline!(1);
+                HostVm::OffchainHttpResponseWait(OffchainHttpResponseWait {
+                    request_ids,
+                    deadline,
+                    inner: self.inner,
+                })
             }
-            HostFunction::ext_offchain_http_response_wait_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_offchain_http_response_headers_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                HostVm::OffchainHttpResponseHeaders(OffchainHttpResponseHeaders {
+                    request_id,
+                    inner: self.inner,
+                })
             }
             HostFunction::ext_offchain_http_response_read_body_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                let (buffer_ptr, buffer_size) = expect_pointer_size_raw!(1);
+                let deadline = expect_http_deadline!(2);
+                HostVm::OffchainHttpResponseReadBody(OffchainHttpResponseReadBody {
+                    request_id,
+                    buffer_ptr,
+                    buffer_size,
+                    deadline,
+                    inner: self.inner,
+                })
             }
             HostFunction::ext_trie_blake2_256_root_version_1
             | HostFunction::ext_trie_blake2_256_root_version_2 => {
@@ -2141,6 +2537,53 @@ impl ReadyToRun {
             HostFunction::ext_logging_max_level_version_1 => {
                 HostVm::GetMaxLogLevel(GetMaxLogLevel { inner: self.inner })
             }
+            HostFunction::ext_wasm_tracing_enabled_version_1 => {
+                let metadata = {
+                    let encoded = expect_pointer_size!(0);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(tracing::decode_metadata)(encoded.as_ref())
+                            .map(|(_, metadata)| metadata);
+                    parsing_result.map_err(|_| ())
+                };
+
+                let metadata = match metadata {
+                    Ok(metadata) => metadata,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
+
+                HostVm::TracingEnabled(TracingEnabled {
+                    inner: self.inner,
+                    name: metadata.name,
+                    target: metadata.target,
+                    level: metadata.level,
+                })
+            }
+            HostFunction::ext_wasm_tracing_enter_span_version_1 => {
+                let entry = expect_tracing_entry!(0);
+                HostVm::TracingEnterSpan(TracingEnterSpan {
+                    inner: self.inner,
+                    entry,
+                })
+            }
+            HostFunction::ext_wasm_tracing_event_version_1 => {
+                let entry = expect_tracing_entry!(0);
+                HostVm::TracingEvent(TracingEvent {
+                    inner: self.inner,
+                    entry,
+                })
+            }
+            HostFunction::ext_wasm_tracing_exit_version_1 => {
+                let span_id = expect_u64!(0);
+                HostVm::TracingExitSpan(TracingExitSpan {
+                    inner: self.inner,
+                    span_id,
+                })
+            }
         }
     }
 }
@@ -3177,27 +3620,109 @@ impl fmt::Debug for SignatureVerification {
     }
 }
 
-/// Must provide the runtime version obtained by calling the `Core_version` entry point of a Wasm
-/// blob.
-pub struct CallRuntimeVersion {
+/// Must generate a new key pair, store it in the keystore, and provide its public key.
+pub struct KeyGeneration {
     inner: Inner,
+    /// Which cryptographic algorithm.
+    algorithm: KeyGenerationAlgorithm,
+    /// Pointer to the 4 bytes key type identifier. Guaranteed to be in range.
+    key_type_ptr: u32,
+    /// Seed provided by the runtime, if any.
+    seed: Option<Vec<u8>>,
+}
 
-    /// Pointer to the wasm code whose runtime version must be provided. Guaranteed to be in range.
-    wasm_blob_ptr: u32,
-    /// Size of the wasm code whose runtime version must be provided. Guaranteed to be in range.
-    wasm_blob_size: u32,
+/// Cryptographic algorithm of a key to generate. See [`KeyGeneration::algorithm`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum KeyGenerationAlgorithm {
+    Ed25519,
+    Sr25519,
+    /// Public keys are 33 bytes compressed Secp256k1 public keys.
+    Ecdsa,
 }
 
-impl CallRuntimeVersion {
-    /// Returns the Wasm code whose runtime version must be provided.
-    pub fn wasm_code(&'_ self) -> impl AsRef<[u8]> + '_ {
-        self.inner
-            .vm
-            .read_memory(self.wasm_blob_ptr, self.wasm_blob_size)
-            .unwrap()
+impl KeyGeneration {
+    /// Returns the cryptographic algorithm of the key to generate.
+    pub fn algorithm(&self) -> KeyGenerationAlgorithm {
+        self.algorithm
     }
 
-    /// Writes the SCALE-encoded runtime version to the memory and prepares for execution.
+    /// Returns the identifier of the type of key to generate, for example `b"gran"` for a
+    /// GrandPa key.
+    pub fn key_type_id(&self) -> [u8; 4] {
+        <[u8; 4]>::try_from(
+            self.inner
+                .vm
+                .read_memory(self.key_type_ptr, 4)
+                .unwrap()
+                .as_ref(),
+        )
+        .unwrap()
+    }
+
+    /// Returns the seed to generate the key from, if any.
+    ///
+    /// If `Some`, the seed is expected to be a UTF-8 secret phrase, such as `//Alice`. If `None`,
+    /// the key must be generated randomly.
+    pub fn seed(&self) -> Option<&[u8]> {
+        self.seed.as_deref()
+    }
+
+    /// Writes the public key of the generated key pair to the memory and prepares for execution.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the public key isn't 32 bytes long, or 33 bytes long in the case of
+    /// [`KeyGenerationAlgorithm::Ecdsa`].
+    ///
+    pub fn resume(self, public_key: &[u8]) -> HostVm {
+        let (host_fn, public_key_len) = match self.algorithm {
+            KeyGenerationAlgorithm::Ed25519 => {
+                (HostFunction::ext_crypto_ed25519_generate_version_1, 32)
+            }
+            KeyGenerationAlgorithm::Sr25519 => {
+                (HostFunction::ext_crypto_sr25519_generate_version_1, 32)
+            }
+            KeyGenerationAlgorithm::Ecdsa => {
+                (HostFunction::ext_crypto_ecdsa_generate_version_1, 33)
+            }
+        };
+        assert_eq!(public_key.len(), public_key_len);
+
+        self.inner
+            .alloc_write_and_return_pointer(host_fn.name(), iter::once(public_key))
+    }
+}
+
+impl fmt::Debug for KeyGeneration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyGeneration")
+            .field("algorithm", &self.algorithm)
+            .field("key_type_id", &self.key_type_id())
+            .finish()
+    }
+}
+
+/// Must provide the runtime version obtained by calling the `Core_version` entry point of a Wasm
+/// blob.
+pub struct CallRuntimeVersion {
+    inner: Inner,
+
+    /// Pointer to the wasm code whose runtime version must be provided. Guaranteed to be in range.
+    wasm_blob_ptr: u32,
+    /// Size of the wasm code whose runtime version must be provided. Guaranteed to be in range.
+    wasm_blob_size: u32,
+}
+
+impl CallRuntimeVersion {
+    /// Returns the Wasm code whose runtime version must be provided.
+    pub fn wasm_code(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.wasm_blob_ptr, self.wasm_blob_size)
+            .unwrap()
+    }
+
+    /// Writes the SCALE-encoded runtime version to the memory and prepares for execution.
     ///
     /// If an error happened during the execution (such as an invalid Wasm binary code), pass
     /// an `Err`.
@@ -3276,6 +3801,651 @@ impl fmt::Debug for ExternalOffchainStorageSet {
     }
 }
 
+/// Must load a value from the local off-chain storage.
+///
+/// Contrary to [`ExternalOffchainStorageSet`], which concerns the off-chain storage entries
+/// written during the execution of blocks, this concerns the local storage that off-chain
+/// workers use in order to persist data between calls.
+pub struct OffchainStorageGet {
+    inner: Inner,
+
+    /// Pointer to the key whose value must be loaded. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be loaded. Guaranteed to be in range.
+    key_size: u32,
+}
+
+impl OffchainStorageGet {
+    /// Returns the key whose value must be loaded.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Writes the value in the Wasm VM's memory and prepares the virtual machine to resume
+    /// execution.
+    pub fn resume(self, value: Option<&[u8]>) -> HostVm {
+        let host_fn = HostFunction::ext_offchain_local_storage_get_version_1;
+
+        if let Some(value) = value {
+            // Writing `Some(value)`.
+            let value_len_enc = util::encode_scale_compact_usize(value.len());
+            self.inner.alloc_write_and_return_pointer_size(
+                host_fn.name(),
+                iter::once(&[1][..])
+                    .chain(iter::once(value_len_enc.as_ref()))
+                    .chain(iter::once(value)),
+            )
+        } else {
+            // Write a SCALE-encoded `None`.
+            self.inner
+                .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(&[0]))
+        }
+    }
+}
+
+impl fmt::Debug for OffchainStorageGet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainStorageGet").finish()
+    }
+}
+
+/// Must set or clear a value of the local off-chain storage.
+///
+/// See also [`OffchainStorageGet`].
+pub struct OffchainStorageSet {
+    inner: Inner,
+
+    /// Pointer to the key whose value must be set. Guaranteed to be in range.
+    key_ptr: u32,
+    /// Size of the key whose value must be set. Guaranteed to be in range.
+    key_size: u32,
+
+    /// Pointer and size of the value to set. `None` for clearing. Guaranteed to be in range.
+    value: Option<(u32, u32)>,
+
+    /// `None` if the value must be set unconditionally. `Some` if the value must only be set if
+    /// the current value matches this one, in which case `Some(None)` means that there must not
+    /// be any current value. Guaranteed to be in range.
+    old_value: Option<Option<(u32, u32)>>,
+}
+
+impl OffchainStorageSet {
+    /// Returns the key whose value must be set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.key_ptr, self.key_size)
+            .unwrap()
+    }
+
+    /// Returns the value to set.
+    ///
+    /// If `None` is returned, the key should be removed from the storage entirely.
+    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        if let Some((ptr, size)) = self.value {
+            Some(self.inner.vm.read_memory(ptr, size).unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// If `Some`, the value must only be set if the current value of the entry is equal to the
+    /// inner value, where `Some(None)` means that the entry must currently be absent.
+    ///
+    /// If `None`, the value must always be set.
+    pub fn old_value(&'_ self) -> Option<Option<impl AsRef<[u8]> + '_>> {
+        match self.old_value {
+            Some(Some((ptr, size))) => Some(Some(self.inner.vm.read_memory(ptr, size).unwrap())),
+            Some(None) => Some(None),
+            None => None,
+        }
+    }
+
+    /// Resumes execution after having set the value or not.
+    ///
+    /// `replaced` must be `true` if the value has been written. It is ignored if
+    /// [`OffchainStorageSet::old_value`] returns `None`.
+    pub fn resume(self, replaced: bool) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: if self.old_value.is_some() {
+                Some(vm::WasmValue::I32(if replaced { 1 } else { 0 }))
+            } else {
+                None
+            },
+        })
+    }
+}
+
+impl fmt::Debug for OffchainStorageSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainStorageSet").finish()
+    }
+}
+
+/// Must provide the current UNIX timestamp, in milliseconds.
+pub struct OffchainTimestamp {
+    inner: Inner,
+}
+
+impl OffchainTimestamp {
+    /// Resumes execution after having provided the number of milliseconds since the UNIX epoch.
+    pub fn resume(self, value: u64) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I64(i64::from_ne_bytes(value.to_ne_bytes()))),
+        })
+    }
+}
+
+impl fmt::Debug for OffchainTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainTimestamp").finish()
+    }
+}
+
+/// Must provide a random seed.
+pub struct OffchainRandomSeed {
+    inner: Inner,
+}
+
+impl OffchainRandomSeed {
+    /// Writes the random seed in the Wasm VM's memory and prepares the virtual machine to resume
+    /// execution.
+    pub fn resume(self, seed: [u8; 32]) -> HostVm {
+        self.inner.alloc_write_and_return_pointer(
+            HostFunction::ext_offchain_random_seed_version_1.name(),
+            iter::once(&seed),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainRandomSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainRandomSeed").finish()
+    }
+}
+
+/// Runtime would like to start an HTTP request.
+///
+/// If the request is started, the runtime then refers to it through the request ID passed to
+/// [`OffchainHttpRequestStart::resume`] when calling the other HTTP-related host functions. It
+/// is the responsibility of the user to keep track of the requests that are in progress and to
+/// reply with [`HttpError::Invalid`] or [`HttpRequestStatus::Invalid`] when passed an unknown
+/// request ID.
+pub struct OffchainHttpRequestStart {
+    inner: Inner,
+
+    /// Pointer to the HTTP method. Guaranteed to be in range.
+    method_ptr: u32,
+    /// Size of the HTTP method. Guaranteed to be in range.
+    method_size: u32,
+    /// Pointer to the URI of the request. Guaranteed to be in range.
+    uri_ptr: u32,
+    /// Size of the URI of the request. Guaranteed to be in range.
+    uri_size: u32,
+}
+
+impl OffchainHttpRequestStart {
+    /// Returns the HTTP method of the request (e.g. `GET`). Not guaranteed to be valid UTF-8.
+    pub fn method(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.method_ptr, self.method_size)
+            .unwrap()
+    }
+
+    /// Returns the URI of the request. Not guaranteed to be valid UTF-8.
+    pub fn uri(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.uri_ptr, self.uri_size)
+            .unwrap()
+    }
+
+    /// Resumes execution after indicating to the runtime that the request has been started and
+    /// is identified by the given ID.
+    pub fn resume(self, request_id: u16) -> HostVm {
+        // Write a SCALE-encoded `Ok(request_id)`.
+        let request_id = request_id.to_le_bytes();
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_request_start_version_1.name(),
+            [&[0][..], &request_id[..]].into_iter(),
+        )
+    }
+
+    /// Resumes execution after indicating to the runtime that the request couldn't be started.
+    pub fn resume_failed(self) -> HostVm {
+        // Write a SCALE-encoded `Err(())`.
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_request_start_version_1.name(),
+            iter::once(&[1]),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestStart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestStart").finish()
+    }
+}
+
+/// Runtime would like to add a header to an HTTP request whose body hasn't started being
+/// written yet.
+pub struct OffchainHttpRequestAddHeader {
+    inner: Inner,
+
+    /// Identifier of the request, as provided by the runtime.
+    request_id: u16,
+    /// Pointer to the name of the header. Guaranteed to be in range.
+    name_ptr: u32,
+    /// Size of the name of the header. Guaranteed to be in range.
+    name_size: u32,
+    /// Pointer to the value of the header. Guaranteed to be in range.
+    value_ptr: u32,
+    /// Size of the value of the header. Guaranteed to be in range.
+    value_size: u32,
+}
+
+impl OffchainHttpRequestAddHeader {
+    /// Returns the identifier of the request, as provided by the runtime. Not guaranteed to
+    /// correspond to a request that has been started.
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the name of the header. Not guaranteed to be valid UTF-8.
+    pub fn name(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.name_ptr, self.name_size)
+            .unwrap()
+    }
+
+    /// Returns the value of the header. Not guaranteed to be valid UTF-8.
+    pub fn value(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.value_ptr, self.value_size)
+            .unwrap()
+    }
+
+    /// Resumes execution after having added the header or not.
+    ///
+    /// `success` must be `false` if the request ID is invalid or if the body of the request has
+    /// already started being written.
+    pub fn resume(self, success: bool) -> HostVm {
+        // Write a SCALE-encoded `Result<(), ()>`.
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_request_add_header_version_1.name(),
+            iter::once(if success { &[0] } else { &[1] }),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestAddHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestAddHeader")
+            .field(&self.request_id)
+            .finish()
+    }
+}
+
+/// Runtime would like to write a chunk of the body of an HTTP request.
+pub struct OffchainHttpRequestWriteBody {
+    inner: Inner,
+
+    /// Identifier of the request, as provided by the runtime.
+    request_id: u16,
+    /// Pointer to the chunk to write. Guaranteed to be in range.
+    chunk_ptr: u32,
+    /// Size of the chunk to write. Guaranteed to be in range.
+    chunk_size: u32,
+    /// Timestamp, in milliseconds since the UNIX epoch, after which the operation must fail.
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpRequestWriteBody {
+    /// Returns the identifier of the request, as provided by the runtime. Not guaranteed to
+    /// correspond to a request that has been started.
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the chunk of body to write.
+    ///
+    /// An empty chunk indicates that the body is complete and that the request must be
+    /// finalized.
+    pub fn chunk(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.chunk_ptr, self.chunk_size)
+            .unwrap()
+    }
+
+    /// Returns the timestamp, in milliseconds since the UNIX epoch, after which the operation
+    /// must fail with [`HttpError::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having written the chunk or not.
+    pub fn resume(self, result: Result<(), HttpError>) -> HostVm {
+        // Write a SCALE-encoded `Result<(), HttpError>`.
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_request_write_body_version_1.name(),
+            iter::once(match result {
+                Ok(()) => either::Left([0]),
+                Err(err) => either::Right([1, err.scale_encoded()]),
+            }),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestWriteBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestWriteBody")
+            .field(&self.request_id)
+            .finish()
+    }
+}
+
+/// Runtime would like to wait for the responses of a list of HTTP requests.
+pub struct OffchainHttpResponseWait {
+    inner: Inner,
+
+    /// Identifiers of the requests, as provided by the runtime.
+    request_ids: Vec<u16>,
+    /// Timestamp, in milliseconds since the UNIX epoch, after which waiting must stop.
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpResponseWait {
+    /// Returns the identifiers of the requests, as provided by the runtime. Not guaranteed to
+    /// correspond to requests that have been started.
+    pub fn request_ids(&self) -> &[u16] {
+        &self.request_ids
+    }
+
+    /// Returns the timestamp, in milliseconds since the UNIX epoch, after which waiting must
+    /// stop and the requests that aren't finished yet must be reported as
+    /// [`HttpRequestStatus::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having provided the status of each request, in the same order
+    /// as [`OffchainHttpResponseWait::request_ids`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number of statuses isn't equal to the number of request IDs.
+    ///
+    pub fn resume(self, statuses: impl IntoIterator<Item = HttpRequestStatus>) -> HostVm {
+        // Write a SCALE-encoded `Vec<HttpRequestStatus>`.
+        let mut encoded = util::encode_scale_compact_usize(self.request_ids.len())
+            .as_ref()
+            .to_vec();
+        let mut num_statuses = 0;
+        for status in statuses {
+            num_statuses += 1;
+            match status {
+                HttpRequestStatus::DeadlineReached => encoded.push(0),
+                HttpRequestStatus::IoError => encoded.push(1),
+                HttpRequestStatus::Invalid => encoded.push(2),
+                HttpRequestStatus::Finished(status_code) => {
+                    encoded.push(3);
+                    encoded.extend_from_slice(&status_code.to_le_bytes());
+                }
+            }
+        }
+        assert_eq!(num_statuses, self.request_ids.len());
+
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_response_wait_version_1.name(),
+            iter::once(&encoded),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpResponseWait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseWait")
+            .field(&self.request_ids)
+            .finish()
+    }
+}
+
+/// Runtime would like to obtain the headers of the response of an HTTP request.
+pub struct OffchainHttpResponseHeaders {
+    inner: Inner,
+
+    /// Identifier of the request, as provided by the runtime.
+    request_id: u16,
+}
+
+impl OffchainHttpResponseHeaders {
+    /// Returns the identifier of the request, as provided by the runtime. Not guaranteed to
+    /// correspond to a request that has been started.
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Resumes execution after having provided the list of headers of the response, as
+    /// `(name, value)` tuples.
+    ///
+    /// The list must be empty if the request ID is invalid or if the response hasn't been
+    /// received yet.
+    pub fn resume(
+        self,
+        headers: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>,
+    ) -> HostVm {
+        // Write a SCALE-encoded `Vec<(Vec<u8>, Vec<u8>)>`.
+        let mut num_headers = 0;
+        let mut encoded_headers = Vec::new();
+        for (name, value) in headers {
+            num_headers += 1;
+            for item in [name.as_ref(), value.as_ref()] {
+                encoded_headers
+                    .extend_from_slice(util::encode_scale_compact_usize(item.len()).as_ref());
+                encoded_headers.extend_from_slice(item);
+            }
+        }
+
+        let num_headers_encoded = util::encode_scale_compact_usize(num_headers);
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_response_headers_version_1.name(),
+            [
+                either::Left(num_headers_encoded),
+                either::Right(encoded_headers),
+            ]
+            .into_iter(),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpResponseHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseHeaders")
+            .field(&self.request_id)
+            .finish()
+    }
+}
+
+/// Runtime would like to read a chunk of the body of the response of an HTTP request.
+pub struct OffchainHttpResponseReadBody {
+    inner: Inner,
+
+    /// Identifier of the request, as provided by the runtime.
+    request_id: u16,
+    /// Pointer to the buffer where to write the body. Guaranteed to be in range.
+    buffer_ptr: u32,
+    /// Size of the buffer where to write the body. Guaranteed to be in range.
+    buffer_size: u32,
+    /// Timestamp, in milliseconds since the UNIX epoch, after which the operation must fail.
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpResponseReadBody {
+    /// Returns the identifier of the request, as provided by the runtime. Not guaranteed to
+    /// correspond to a request that has been started.
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the maximum number of bytes of body that can be provided.
+    pub fn max_size(&self) -> usize {
+        usize::try_from(self.buffer_size).unwrap()
+    }
+
+    /// Returns the timestamp, in milliseconds since the UNIX epoch, after which the operation
+    /// must fail with [`HttpError::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Writes the next chunk of the body in the Wasm VM's memory and prepares the virtual
+    /// machine to resume execution.
+    ///
+    /// An empty chunk indicates that the end of the body has been reached.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chunk is longer than what [`OffchainHttpResponseReadBody::max_size`]
+    /// returns.
+    ///
+    pub fn resume(mut self, result: Result<&[u8], HttpError>) -> HostVm {
+        let result = match result {
+            Ok(chunk) => {
+                assert!(chunk.len() <= self.max_size());
+                self.inner.vm.write_memory(self.buffer_ptr, chunk).unwrap();
+                Ok(u32::try_from(chunk.len()).unwrap())
+            }
+            Err(err) => Err(err),
+        };
+
+        // Write a SCALE-encoded `Result<u32, HttpError>`.
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_http_response_read_body_version_1.name(),
+            iter::once(match result {
+                Ok(num_read) => {
+                    let [b0, b1, b2, b3] = num_read.to_le_bytes();
+                    either::Left([0, b0, b1, b2, b3])
+                }
+                Err(err) => either::Right([1, err.scale_encoded()]),
+            }),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpResponseReadBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseReadBody")
+            .field(&self.request_id)
+            .finish()
+    }
+}
+
+/// Error that can be reported when writing the body of an HTTP request or reading the body of
+/// its response.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HttpError {
+    /// The deadline passed by the runtime has been reached.
+    DeadlineReached,
+    /// An error happened on the underlying connection, for example the connection has been
+    /// closed.
+    IoError,
+    /// The request ID is invalid, or the operation isn't possible in the current state of the
+    /// request.
+    Invalid,
+}
+
+impl HttpError {
+    fn scale_encoded(&self) -> u8 {
+        match self {
+            HttpError::DeadlineReached => 1,
+            HttpError::IoError => 2,
+            HttpError::Invalid => 3,
+        }
+    }
+}
+
+/// Status of an HTTP request, as reported to the runtime when it waits for responses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HttpRequestStatus {
+    /// The deadline passed by the runtime has been reached before the response was received.
+    DeadlineReached,
+    /// An error happened on the underlying connection, for example the connection has been
+    /// closed.
+    IoError,
+    /// The request ID is invalid.
+    Invalid,
+    /// The headers of the response have been received. Contains the HTTP status code of the
+    /// response.
+    Finished(u16),
+}
+
+/// Must indicate whether the local node is a validator.
+pub struct OffchainIsValidator {
+    inner: Inner,
+}
+
+impl OffchainIsValidator {
+    /// Resumes execution after having indicated whether the local node is a validator.
+    pub fn resume(self, is_validator: bool) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I32(if is_validator { 1 } else { 0 })),
+        })
+    }
+}
+
+impl fmt::Debug for OffchainIsValidator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainIsValidator").finish()
+    }
+}
+
+/// Runtime would like to submit a transaction to the transactions pool.
+pub struct OffchainSubmitTransaction {
+    inner: Inner,
+
+    /// Pointer to the transaction. Guaranteed to be in range.
+    tx_ptr: u32,
+    /// Size of the transaction. Guaranteed to be in range.
+    tx_size: u32,
+}
+
+impl OffchainSubmitTransaction {
+    /// Returns the SCALE-encoded transaction that the runtime would like to submit.
+    pub fn transaction(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.tx_ptr, self.tx_size)
+            .unwrap()
+    }
+
+    /// Resumes execution after having submitted the transaction or not.
+    ///
+    /// `success` must be `true` if the transaction has been accepted for inclusion.
+    pub fn resume(self, success: bool) -> HostVm {
+        // Write a SCALE-encoded `Result<(), ()>`.
+        self.inner.alloc_write_and_return_pointer_size(
+            HostFunction::ext_offchain_submit_transaction_version_1.name(),
+            iter::once(if success { &[0] } else { &[1] }),
+        )
+    }
+}
+
+impl fmt::Debug for OffchainSubmitTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainSubmitTransaction").finish()
+    }
+}
+
 /// Report about a log entry being emitted.
 ///
 /// Use the implementation of [`fmt::Display`] to obtain the log entry. For example, you can
@@ -3390,6 +4560,141 @@ impl fmt::Debug for GetMaxLogLevel {
     }
 }
 
+/// Runtime would like to know whether the spans and events with the given metadata should be
+/// reported.
+///
+/// If the answer is `false`, the runtime doesn't emit them.
+pub struct TracingEnabled {
+    inner: Inner,
+    name: String,
+    target: String,
+    level: TracingLevel,
+}
+
+impl TracingEnabled {
+    /// Returns the name of the span or event.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the target of the span or event, typically the name of the module that emits it.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the verbosity level of the span or event.
+    pub fn level(&self) -> TracingLevel {
+        self.level
+    }
+
+    /// Resumes execution after indicating whether the span or event should be reported.
+    pub fn resume(self, enabled: bool) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I32(if enabled { 1 } else { 0 })),
+        })
+    }
+}
+
+impl fmt::Debug for TracingEnabled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TracingEnabled")
+            .field("name", &self.name)
+            .field("target", &self.target)
+            .field("level", &self.level)
+            .finish()
+    }
+}
+
+/// Runtime has entered a span.
+pub struct TracingEnterSpan {
+    inner: Inner,
+    entry: TracingEntry,
+}
+
+impl TracingEnterSpan {
+    /// Returns the span that the runtime has entered.
+    pub fn span(&self) -> &TracingEntry {
+        &self.entry
+    }
+
+    /// Resumes execution after having assigned an identifier to the span. This identifier is
+    /// later passed back through [`TracingExitSpan::span_id`] and [`TracingEntry::parent_id`].
+    pub fn resume(self, span_id: u64) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: Some(vm::WasmValue::I64(i64::from_ne_bytes(
+                span_id.to_ne_bytes(),
+            ))),
+        })
+    }
+}
+
+impl fmt::Debug for TracingEnterSpan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TracingEnterSpan")
+            .field(&self.entry)
+            .finish()
+    }
+}
+
+/// Runtime has emitted an event.
+pub struct TracingEvent {
+    inner: Inner,
+    entry: TracingEntry,
+}
+
+impl TracingEvent {
+    /// Returns the event that the runtime has emitted.
+    pub fn event(&self) -> &TracingEntry {
+        &self.entry
+    }
+
+    /// Resumes execution.
+    pub fn resume(self) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: None,
+        })
+    }
+}
+
+impl fmt::Debug for TracingEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TracingEvent").field(&self.entry).finish()
+    }
+}
+
+/// Runtime has exited a span.
+pub struct TracingExitSpan {
+    inner: Inner,
+    span_id: u64,
+}
+
+impl TracingExitSpan {
+    /// Returns the identifier of the span, as passed to [`TracingEnterSpan::resume`]. Not
+    /// guaranteed to correspond to a span that has been entered.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Resumes execution.
+    pub fn resume(self) -> HostVm {
+        HostVm::ReadyToRun(ReadyToRun {
+            inner: self.inner,
+            resume_value: None,
+        })
+    }
+}
+
+impl fmt::Debug for TracingExitSpan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TracingExitSpan")
+            .field(&self.span_id)
+            .finish()
+    }
+}
+
 /// Declares the start of a transaction.
 pub struct StartStorageTransaction {
     inner: Inner,
@@ -3904,6 +5209,10 @@ externalities! {
     ext_allocator_free_version_1,
     ext_logging_log_version_1,
     ext_logging_max_level_version_1,
+    ext_wasm_tracing_enabled_version_1,
+    ext_wasm_tracing_enter_span_version_1,
+    ext_wasm_tracing_event_version_1,
+    ext_wasm_tracing_exit_version_1,
 }
 
 impl HostFunction {
@@ -4132,7 +5441,7 @@ impl HostFunction {
                 crate::signature!((vm::ValueType::I64, vm::ValueType::I64) => vm::ValueType::I64)
             }
             HostFunction::ext_offchain_http_response_headers_version_1 => {
-                crate::signature!((vm::ValueType::I64) => vm::ValueType::I64)
+                crate::signature!((vm::ValueType::I32) => vm::ValueType::I64)
             }
             HostFunction::ext_offchain_http_response_read_body_version_1 => {
                 crate::signature!((vm::ValueType::I32, vm::ValueType::I64, vm::ValueType::I64) => vm::ValueType::I64)
@@ -4197,6 +5506,18 @@ impl HostFunction {
             HostFunction::ext_logging_max_level_version_1 => {
                 crate::signature!(() => vm::ValueType::I32)
             }
+            HostFunction::ext_wasm_tracing_enabled_version_1 => {
+                crate::signature!((vm::ValueType::I64) => vm::ValueType::I32)
+            }
+            HostFunction::ext_wasm_tracing_enter_span_version_1 => {
+                crate::signature!((vm::ValueType::I64) => vm::ValueType::I64)
+            }
+            HostFunction::ext_wasm_tracing_event_version_1 => {
+                crate::signature!((vm::ValueType::I64) => ())
+            }
+            HostFunction::ext_wasm_tracing_exit_version_1 => {
+                crate::signature!((vm::ValueType::I64) => ())
+            }
         }
     }
 }
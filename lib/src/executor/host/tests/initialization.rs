@@ -27,6 +27,7 @@ fn invalid_wasm() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -44,6 +45,7 @@ fn invalid_zstd() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -70,6 +72,7 @@ fn valid_zstd() {
     for exec_hint in ExecHint::available_engines() {
         HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -94,6 +97,7 @@ fn no_heap_base() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -121,6 +125,7 @@ fn memory_max_size_too_low() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -149,6 +154,7 @@ fn unresolved_host_functions_setting() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: true,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -159,6 +165,7 @@ fn unresolved_host_functions_setting() {
 
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -189,6 +196,7 @@ fn host_function_bad_signature() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: true,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -199,6 +207,7 @@ fn host_function_bad_signature() {
 
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -16,7 +16,8 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::super::{
-    vm, vm::ExecHint, Config, Error, HeapPages, HostVm, HostVmPrototype, NewErr, StartErr,
+    vm, vm::ExecHint, Config, Error, HeapPages, HostVm, HostVmPrototype, HttpRequestStatus, NewErr,
+    StartErr, TracingLevel,
 };
 use super::with_core_version_custom_sections;
 
@@ -37,6 +38,7 @@ fn function_to_run_doesnt_exist() {
     for exec_hint in ExecHint::available_engines() {
         let host_vm = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -68,6 +70,7 @@ fn function_to_run_invalid_params() {
     for exec_hint in ExecHint::available_engines() {
         let host_vm = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -197,6 +200,7 @@ fn input_provided_correctly() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -219,6 +223,7 @@ fn input_provided_correctly() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -246,6 +251,7 @@ fn input_provided_correctly() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -352,6 +358,7 @@ fn large_input_provided_correctly() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -415,6 +422,7 @@ fn return_value_works() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -469,6 +477,7 @@ fn bad_return_value() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -528,6 +537,7 @@ fn returned_ptr_out_of_range() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -587,6 +597,7 @@ fn returned_size_out_of_range() {
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -653,6 +664,7 @@ fn unresolved_host_function_called() {
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
             allow_unresolved_imports: false,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -663,6 +675,7 @@ fn unresolved_host_function_called() {
 
         let proto = HostVmPrototype::new(Config {
             allow_unresolved_imports: true,
+            fuel_limit: None,
             exec_hint,
             heap_pages: HeapPages::new(1024),
             module: &module_bytes,
@@ -686,3 +699,355 @@ fn unresolved_host_function_called() {
 }
 
 // TODO: consider more tests for the other errors here, or add them on a host-function case-by-case basis
+
+#[test]
+fn offchain_timestamp_provided_correctly() {
+    /* Source code:
+        extern {
+            fn ext_offchain_timestamp_version_1() -> u64;
+        }
+
+        #[no_mangle]
+        extern "C" fn test(_: i32, _: i32) -> i64 {
+            if unsafe { ext_offchain_timestamp_version_1() } != 1234 {
+                core::arch::wasm32::unreachable()
+            }
+
+            0
+        }
+    */
+    let module_bytes = with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (type (;0;) (func (result i64)))
+        (type (;1;) (func (param i32 i32) (result i64)))
+        (import "env" "ext_offchain_timestamp_version_1" (func (;0;) (type 0)))
+        (func (;1;) (type 1) (param i32 i32) (result i64)
+          block  ;; label = @1
+            call 0
+            i64.const 1234
+            i64.ne
+            br_if 0 (;@1;)
+            i64.const 0
+            return
+          end
+          unreachable
+          unreachable)
+        (table (;0;) 1 1 funcref)
+        (memory (;0;) 16)
+        (global (;0;) (mut i32) (i32.const 1048576))
+        (global (;1;) i32 (i32.const 1048576))
+        (global (;2;) i32 (i32.const 1048576))
+        (export "memory" (memory 0))
+        (export "test" (func 1))
+        (export "__data_end" (global 1))
+        (export "__heap_base" (global 2))
+    )
+    "#,
+        )
+        .unwrap(),
+    );
+
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            fuel_limit: None,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(proto.run("test", &[]).unwrap());
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::OffchainTimestamp(req) => vm = req.resume(1234),
+                HostVm::Finished(_) => break,
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[test]
+fn offchain_is_validator_provided_correctly() {
+    /* Source code:
+        extern {
+            fn ext_offchain_is_validator_version_1() -> i32;
+        }
+
+        #[no_mangle]
+        extern "C" fn test(_: i32, _: i32) -> i64 {
+            if unsafe { ext_offchain_is_validator_version_1() } != 1 {
+                core::arch::wasm32::unreachable()
+            }
+
+            0
+        }
+    */
+    let module_bytes = with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (type (;0;) (func (result i32)))
+        (type (;1;) (func (param i32 i32) (result i64)))
+        (import "env" "ext_offchain_is_validator_version_1" (func (;0;) (type 0)))
+        (func (;1;) (type 1) (param i32 i32) (result i64)
+          block  ;; label = @1
+            call 0
+            i32.const 1
+            i32.ne
+            br_if 0 (;@1;)
+            i64.const 0
+            return
+          end
+          unreachable
+          unreachable)
+        (table (;0;) 1 1 funcref)
+        (memory (;0;) 16)
+        (global (;0;) (mut i32) (i32.const 1048576))
+        (global (;1;) i32 (i32.const 1048576))
+        (global (;2;) i32 (i32.const 1048576))
+        (export "memory" (memory 0))
+        (export "test" (func 1))
+        (export "__data_end" (global 1))
+        (export "__heap_base" (global 2))
+    )
+    "#,
+        )
+        .unwrap(),
+    );
+
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            fuel_limit: None,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(proto.run("test", &[]).unwrap());
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::OffchainIsValidator(req) => vm = req.resume(true),
+                HostVm::Finished(_) => break,
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[test]
+fn offchain_http_request_read_body() {
+    let module_bytes = with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (type (;0;) (func (param i64 i64 i64) (result i64)))
+        (type (;1;) (func (param i32 i64 i64) (result i64)))
+        (type (;2;) (func (param i32 i32) (result i64)))
+        (import "env" "ext_offchain_http_request_start_version_1" (func (;0;) (type 0)))
+        (import "env" "ext_offchain_http_response_read_body_version_1" (func (;1;) (type 1)))
+        (func (;2;) (type 2) (param i32 i32) (result i64)
+          i64.const 0x0000000300000000
+          i64.const 0x0000001100000008
+          i64.const 0x0000000100000020
+          call 0
+          drop
+          i32.const 5
+          i64.const 0x0000001000000040
+          i64.const 0x0000000900000028
+          call 1)
+        (table (;0;) 1 1 funcref)
+        (memory (;0;) 16)
+        (global (;0;) (mut i32) (i32.const 1048576))
+        (global (;1;) i32 (i32.const 1048576))
+        (global (;2;) i32 (i32.const 1048576))
+        (export "memory" (memory 0))
+        (export "test" (func 2))
+        (export "__data_end" (global 1))
+        (export "__heap_base" (global 2))
+        (data (i32.const 0) "GET")
+        (data (i32.const 8) "http://localhost/")
+        (data (i32.const 32) "\00")
+        (data (i32.const 40) "\01\e8\03\00\00\00\00\00\00")
+    )
+    "#,
+        )
+        .unwrap(),
+    );
+
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            fuel_limit: None,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(proto.run("test", &[]).unwrap());
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::OffchainHttpRequestStart(req) => {
+                    assert_eq!(req.method().as_ref(), b"GET");
+                    assert_eq!(req.uri().as_ref(), b"http://localhost/");
+                    vm = req.resume(5);
+                }
+                HostVm::OffchainHttpResponseReadBody(req) => {
+                    assert_eq!(req.request_id(), 5);
+                    assert_eq!(req.max_size(), 16);
+                    assert_eq!(req.deadline(), Some(1000));
+                    vm = req.resume(Ok(b"hello"));
+                }
+                HostVm::Finished(out) => {
+                    assert_eq!(out.value().as_ref(), &[0, 5, 0, 0, 0]);
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[test]
+fn offchain_http_response_wait() {
+    let module_bytes = with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (type (;0;) (func (param i64 i64) (result i64)))
+        (type (;1;) (func (param i32 i32) (result i64)))
+        (import "env" "ext_offchain_http_response_wait_version_1" (func (;0;) (type 0)))
+        (func (;1;) (type 1) (param i32 i32) (result i64)
+          i64.const 0x0000000500000000
+          i64.const 0x0000000100000008
+          call 0)
+        (table (;0;) 1 1 funcref)
+        (memory (;0;) 16)
+        (global (;0;) (mut i32) (i32.const 1048576))
+        (global (;1;) i32 (i32.const 1048576))
+        (global (;2;) i32 (i32.const 1048576))
+        (export "memory" (memory 0))
+        (export "test" (func 1))
+        (export "__data_end" (global 1))
+        (export "__heap_base" (global 2))
+        (data (i32.const 0) "\08\05\00\06\00")
+        (data (i32.const 8) "\00")
+    )
+    "#,
+        )
+        .unwrap(),
+    );
+
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            fuel_limit: None,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(proto.run("test", &[]).unwrap());
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::OffchainHttpResponseWait(req) => {
+                    assert_eq!(req.request_ids(), &[5, 6]);
+                    assert_eq!(req.deadline(), None);
+                    vm = req.resume([
+                        HttpRequestStatus::Finished(200),
+                        HttpRequestStatus::DeadlineReached,
+                    ]);
+                }
+                HostVm::Finished(out) => {
+                    assert_eq!(out.value().as_ref(), &[8, 3, 200, 0, 0]);
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[test]
+fn tracing_span() {
+    let module_bytes = with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (type (;0;) (func (param i64) (result i64)))
+        (type (;1;) (func (param i64)))
+        (type (;2;) (func (param i32 i32) (result i64)))
+        (import "env" "ext_wasm_tracing_enter_span_version_1" (func (;0;) (type 0)))
+        (import "env" "ext_wasm_tracing_exit_version_1" (func (;1;) (type 1)))
+        (func (;2;) (type 2) (param i32 i32) (result i64)
+          i64.const 0x0000000f00000000
+          call 0
+          call 1
+          i64.const 0)
+        (table (;0;) 1 1 funcref)
+        (memory (;0;) 16)
+        (global (;0;) (mut i32) (i32.const 1048576))
+        (global (;1;) i32 (i32.const 1048576))
+        (global (;2;) i32 (i32.const 1048576))
+        (export "memory" (memory 0))
+        (export "test" (func 2))
+        (export "__data_end" (global 1))
+        (export "__heap_base" (global 2))
+        (data (i32.const 0) "\00\04a\04t\02\00\00\00\00\00\00\01\00\00")
+    )
+    "#,
+        )
+        .unwrap(),
+    );
+
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            fuel_limit: None,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(proto.run("test", &[]).unwrap());
+        let mut span_entered = false;
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::TracingEnterSpan(req) => {
+                    assert!(!span_entered);
+                    span_entered = true;
+                    assert_eq!(req.span().parent_id, None);
+                    assert_eq!(req.span().name, "a");
+                    assert_eq!(req.span().target, "t");
+                    assert_eq!(req.span().level, TracingLevel::Info);
+                    assert!(req.span().fields.is_empty());
+                    vm = req.resume(7);
+                }
+                HostVm::TracingExitSpan(req) => {
+                    assert!(span_entered);
+                    assert_eq!(req.span_id(), 7);
+                    vm = req.resume();
+                }
+                HostVm::Finished(out) => {
+                    assert!(span_entered);
+                    assert!(out.value().as_ref().is_empty());
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
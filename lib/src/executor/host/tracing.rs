@@ -0,0 +1,251 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding of the parameters of the `ext_wasm_tracing_*` host functions.
+//!
+//! Runtimes compiled with tracing support report their spans and events through these host
+//! functions, by passing SCALE-encoded `WasmMetadata` and `WasmEntryAttributes` structures as
+//! defined in Substrate.
+
+use crate::util;
+
+use alloc::{
+    format,
+    string::{String, ToString as _},
+    vec::Vec,
+};
+
+/// Verbosity level of a span or event emitted by the runtime.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TracingLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Span or event emitted by the runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracingEntry {
+    /// Identifier of the parent span, as provided by the runtime. If `None`, the parent is the
+    /// span that the runtime has most recently entered and not exited yet, if any.
+    pub parent_id: Option<u64>,
+    /// Name of the span or event.
+    pub name: String,
+    /// Target of the span or event, typically the name of the module that has emitted it.
+    pub target: String,
+    /// Verbosity level of the span or event.
+    pub level: TracingLevel,
+    /// List of `(name, value)` fields attached to the span or event. Binary values are
+    /// hexadecimal-encoded and prefixed with `0x`.
+    pub fields: Vec<(String, String)>,
+}
+
+/// Decoded `WasmMetadata`.
+pub(super) struct Metadata {
+    pub name: String,
+    pub target: String,
+    pub level: TracingLevel,
+}
+
+/// Decodes a SCALE-encoded `WasmMetadata`.
+pub(super) fn decode_metadata<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], Metadata, E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            util::nom_bytes_decode,
+            util::nom_bytes_decode,
+            level,
+            // File.
+            util::nom_bytes_decode,
+            // Line.
+            nom::number::complete::le_u32,
+            // Module path.
+            util::nom_bytes_decode,
+            // Is span.
+            util::nom_bool_decode,
+            // Names of the fields. Their values are passed separately.
+            nom::combinator::flat_map(util::nom_scale_compact_usize, |num_fields| {
+                nom::multi::fold_many_m_n(
+                    num_fields,
+                    num_fields,
+                    util::nom_bytes_decode,
+                    || (),
+                    |(), _| (),
+                )
+            }),
+        )),
+        |(name, target, level, _, _, _, _, ())| Metadata {
+            name: String::from_utf8_lossy(name).into_owned(),
+            target: String::from_utf8_lossy(target).into_owned(),
+            level,
+        },
+    )(bytes)
+}
+
+/// Decodes a SCALE-encoded `WasmEntryAttributes`.
+pub(super) fn decode_entry<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], TracingEntry, E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            util::nom_option_decode(nom::number::complete::le_u64),
+            decode_metadata,
+            nom::combinator::flat_map(util::nom_scale_compact_usize, |num_fields| {
+                nom::multi::fold_many_m_n(
+                    num_fields,
+                    num_fields,
+                    nom::sequence::tuple((util::nom_bytes_decode, util::nom_option_decode(value))),
+                    Vec::new,
+                    |mut fields, (name, value)| {
+                        // Fields without a value are ignored.
+                        if let Some(value) = value {
+                            fields.push((String::from_utf8_lossy(name).into_owned(), value));
+                        }
+                        fields
+                    },
+                )
+            }),
+        )),
+        |(parent_id, metadata, fields)| TracingEntry {
+            parent_id,
+            name: metadata.name,
+            target: metadata.target,
+            level: metadata.level,
+            fields,
+        },
+    )(bytes)
+}
+
+/// Decodes a SCALE-encoded `WasmLevel`.
+fn level<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], TracingLevel, E> {
+    nom::branch::alt((
+        nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| TracingLevel::Error),
+        nom::combinator::map(nom::bytes::complete::tag(&[1]), |_| TracingLevel::Warn),
+        nom::combinator::map(nom::bytes::complete::tag(&[2]), |_| TracingLevel::Info),
+        nom::combinator::map(nom::bytes::complete::tag(&[3]), |_| TracingLevel::Debug),
+        nom::combinator::map(nom::bytes::complete::tag(&[4]), |_| TracingLevel::Trace),
+    ))(bytes)
+}
+
+/// Decodes a SCALE-encoded `WasmValue` and turns it into a string.
+fn value<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], String, E> {
+    nom::branch::alt((
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[0]),
+            nom::combinator::map(nom::number::complete::le_u8, |n| n.to_string()),
+        ),
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[1]),
+            nom::combinator::map(nom::number::complete::le_i8, |n| n.to_string()),
+        ),
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[2]),
+            nom::combinator::map(nom::number::complete::le_u32, |n| n.to_string()),
+        ),
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[3]),
+            nom::combinator::map(nom::number::complete::le_i32, |n| n.to_string()),
+        ),
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[4]),
+            nom::combinator::map(nom::number::complete::le_i64, |n| n.to_string()),
+        ),
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[5]),
+            nom::combinator::map(nom::number::complete::le_u64, |n| n.to_string()),
+        ),
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[6]),
+            nom::combinator::map(util::nom_bool_decode, |b| b.to_string()),
+        ),
+        // Strings and formatted values.
+        nom::sequence::preceded(
+            nom::branch::alt((
+                nom::bytes::complete::tag(&[7]),
+                nom::bytes::complete::tag(&[8]),
+            )),
+            nom::combinator::map(util::nom_bytes_decode, |s| {
+                String::from_utf8_lossy(s).into_owned()
+            }),
+        ),
+        // SCALE-encoded values.
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[9]),
+            nom::combinator::map(util::nom_bytes_decode, |v| format!("0x{}", hex::encode(v))),
+        ),
+    ))(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TracingEntry, TracingLevel};
+
+    #[test]
+    fn decode_entry() {
+        let encoded = [
+            // Parent ID.
+            &[1, 5, 0, 0, 0, 0, 0, 0, 0][..],
+            // Name, target, level.
+            &[12, b'f', b'o', b'o', 8, b'r', b't', 3],
+            // File, line, module path, is span.
+            &[4, b'f', 12, 0, 0, 0, 0, 0],
+            // Field names.
+            &[8, 4, b'a', 4, b'b'],
+            // Field values.
+            &[12, 4, b'a', 1, 5, 42, 0, 0, 0, 0, 0, 0, 0, 4, b'b', 0],
+            &[4, b'c', 1, 9, 8, 0xab, 0xcd],
+        ]
+        .concat();
+
+        let (_, entry) = nom::combinator::all_consuming::<_, _, (&[u8], nom::error::ErrorKind), _>(
+            super::decode_entry,
+        )(&encoded)
+        .unwrap();
+
+        assert_eq!(
+            entry,
+            TracingEntry {
+                parent_id: Some(5),
+                name: "foo".to_owned(),
+                target: "rt".to_owned(),
+                level: TracingLevel::Debug,
+                fields: vec![
+                    ("a".to_owned(), "42".to_owned()),
+                    ("c".to_owned(), "0xabcd".to_owned())
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn decode_metadata_bad_level() {
+        let encoded = [&[0, 0, 5][..], &[0, 0, 0, 0, 0, 0, 0, 0]].concat();
+        assert!(
+            nom::combinator::all_consuming::<_, _, (&[u8], nom::error::ErrorKind), _>(
+                super::decode_metadata
+            )(&encoded)
+            .is_err()
+        );
+    }
+}
@@ -424,6 +424,7 @@ impl Inner {
                         heap_pages: executor::DEFAULT_HEAP_PAGES,
                         exec_hint: vm::ExecHint::Oneshot,
                         allow_unresolved_imports: false, // TODO: what is a correct value here?
+                        fuel_limit: None,
                     }) {
                         Ok(w) => w,
                         Err(_) => {
@@ -450,6 +451,11 @@ impl Inner {
                     self.vm = resume.resume(self.max_log_level);
                 }
 
+                // Tracing isn't supported by this module.
+                host::HostVm::TracingEnabled(req) => {
+                    self.vm = req.resume(false);
+                }
+
                 host::HostVm::LogEmit(req) => {
                     // We add a hardcoded limit to the logs generated by the runtime in order to
                     // make sure that there is no memory leak. In practice, the runtime should
@@ -29,6 +29,8 @@
 //!   provides them at the end. Any storage access takes into account the intermediary list of
 //!   changes.
 //! - Keeps track of the logs generated by the call and concatenates them into a [`String`].
+//! - Handles the calls to the off-chain host functions (local storage, timestamp, HTTP requests,
+//!   etc.) according to the [`OffchainBehavior`] passed in the configuration.
 //! - Automatically handles some externalities, such as calculating the Merkle root or storage
 //!   transactions.
 //!
@@ -43,8 +45,8 @@ use crate::{
     util,
 };
 
-use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
-use core::fmt;
+use alloc::{borrow::ToOwned as _, collections::BTreeMap, string::String, vec::Vec};
+use core::{fmt, iter};
 use hashbrown::HashSet;
 
 pub use trie::TrieEntryVersion;
@@ -80,8 +82,59 @@ pub struct Config<'a, TParams> {
     /// >           "off", `1` for "error", `2` for "warn", `3` for "info", `4` for "debug",
     /// >           and `5` for "trace".
     pub max_log_level: u32,
+
+    /// How to handle the calls to the off-chain host functions, such as accessing the off-chain
+    /// local storage or obtaining the current time.
+    ///
+    /// > **Note**: This doesn't concern the off-chain indexing host functions, whose changes are
+    /// >           always tracked in [`Success::offchain_storage_changes`].
+    pub offchain_behavior: OffchainBehavior,
+
+    /// If `true`, the spans and events that the runtime emits through the tracing host functions
+    /// are recorded in [`Success::tracing`]. If `false`, the runtime is told that tracing is
+    /// disabled.
+    ///
+    /// > **Note**: Only runtimes compiled with tracing support emit spans and events.
+    pub tracing: bool,
 }
 
+/// See [`Config::offchain_behavior`].
+#[derive(Debug, Clone)]
+pub enum OffchainBehavior {
+    /// Any call to an off-chain host function or to a key generation host function makes the
+    /// execution fail with [`ErrorDetail::ForbiddenOffchainHostCall`].
+    ///
+    /// This is appropriate when executing blocks, as blocks aren't supposed to access the
+    /// off-chain context.
+    Deny,
+
+    /// The off-chain local storage is emulated in memory. It is empty at the start of the
+    /// execution and discarded at the end. HTTP requests always fail to start, the local node is
+    /// never a validator, and transactions can't be submitted. Calls to a key generation host
+    /// function make the execution fail with [`ErrorDetail::ForbiddenOffchainHostCall`].
+    ///
+    /// This is appropriate for runtime calls that might incidentally access the off-chain
+    /// context but whose output doesn't depend on it.
+    EmulateInMemory {
+        /// Value to provide to the runtime when it requests the current time, in milliseconds
+        /// since the UNIX epoch.
+        timestamp_ms: u64,
+        /// Value to provide to the runtime when it requests a random seed.
+        random_seed: [u8; 32],
+    },
+
+    /// Calls to the off-chain host functions and to the key generation host functions are
+    /// reported to the user through [`RuntimeHostVm::Offchain`].
+    ///
+    /// This is appropriate when running off-chain workers, or when calling runtime functions
+    /// that generate keys such as `SessionKeys_generate_session_keys`.
+    Forward,
+}
+
+/// Maximum number of spans and events that are recorded when [`Config::tracing`] is `true`
+/// before the execution fails with [`ErrorDetail::TracingTooLong`].
+const MAX_TRACING_RECORDS: usize = 256 * 1024;
+
 /// Start running the WebAssembly virtual machine.
 pub fn run(
     config: Config<impl Iterator<Item = impl AsRef<[u8]>> + Clone>,
@@ -108,6 +161,15 @@ pub fn run(
         root_calculation: None,
         logs: String::new(),
         max_log_level: config.max_log_level,
+        offchain_behavior: config.offchain_behavior,
+        offchain_local_storage: BTreeMap::new(),
+        tracing: if config.tracing {
+            Some(Vec::new())
+        } else {
+            None
+        },
+        tracing_open_spans: Vec::new(),
+        tracing_next_span_id: 1,
     }
     .run())
 }
@@ -129,6 +191,25 @@ pub struct Success {
     pub main_trie_root_calculation_cache: calculate_root::CalculationCache,
     /// Concatenation of all the log messages printed by the runtime.
     pub logs: String,
+    /// Spans and events emitted by the runtime, in the order in which they have been emitted.
+    /// Always empty if [`Config::tracing`] is `false`.
+    pub tracing: Vec<TracingRecord>,
+}
+
+/// See [`Success::tracing`].
+#[derive(Debug, Clone)]
+pub enum TracingRecord {
+    /// The runtime has entered a span.
+    Span {
+        /// Identifier of the span, unique within the execution. Never equal to 0.
+        id: u64,
+        /// Details of the span. [`host::TracingEntry::parent_id`] is `None` if the span has no
+        /// parent.
+        span: host::TracingEntry,
+    },
+    /// The runtime has emitted an event. [`host::TracingEntry::parent_id`] is `None` if the
+    /// event has no parent.
+    Event(host::TracingEntry),
 }
 
 /// Function execution has succeeded. Contains the return value of the call.
@@ -175,6 +256,12 @@ pub enum ErrorDetail {
     },
     /// Size of the logs generated by the runtime exceeds the limit.
     LogsTooLong,
+    /// Number of spans and events emitted by the runtime exceeds the limit.
+    TracingTooLong,
+    /// Runtime has called an off-chain host function while [`Config::offchain_behavior`] is
+    /// [`OffchainBehavior::Deny`], or a key generation host function while
+    /// [`Config::offchain_behavior`] isn't [`OffchainBehavior::Forward`].
+    ForbiddenOffchainHostCall,
 }
 
 /// Current state of the execution.
@@ -190,6 +277,9 @@ pub enum RuntimeHostVm {
     NextKey(NextKey),
     /// Verifying whether a signature is correct is required in order to continue.
     SignatureVerification(SignatureVerification),
+    /// Runtime has called an off-chain host function. Can only happen if
+    /// [`Config::offchain_behavior`] is [`OffchainBehavior::Forward`].
+    Offchain(OffchainContext),
 }
 
 impl RuntimeHostVm {
@@ -202,6 +292,7 @@ impl RuntimeHostVm {
             RuntimeHostVm::PrefixKeys(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::NextKey(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::SignatureVerification(inner) => inner.inner.vm.into_prototype(),
+            RuntimeHostVm::Offchain(inner) => inner.into_prototype(),
         }
     }
 }
@@ -595,6 +686,508 @@ impl SignatureVerification {
     }
 }
 
+/// Runtime has called an off-chain host function.
+#[must_use]
+pub enum OffchainContext {
+    /// Loading a value from the off-chain local storage is required in order to continue.
+    StorageGet(OffchainStorageGet),
+    /// Setting or clearing a value of the off-chain local storage is required in order to
+    /// continue.
+    StorageSet(OffchainStorageSet),
+    /// Providing the current time is required in order to continue.
+    Timestamp(OffchainTimestamp),
+    /// Providing a random seed is required in order to continue.
+    RandomSeed(OffchainRandomSeed),
+    /// Starting an HTTP request is required in order to continue.
+    HttpRequestStart(OffchainHttpRequestStart),
+    /// Adding a header to an HTTP request is required in order to continue.
+    HttpRequestAddHeader(OffchainHttpRequestAddHeader),
+    /// Writing a chunk of the body of an HTTP request is required in order to continue.
+    HttpRequestWriteBody(OffchainHttpRequestWriteBody),
+    /// Waiting for the responses of HTTP requests is required in order to continue.
+    HttpResponseWait(OffchainHttpResponseWait),
+    /// Providing the headers of the response of an HTTP request is required in order to
+    /// continue.
+    HttpResponseHeaders(OffchainHttpResponseHeaders),
+    /// Providing a chunk of the body of the response of an HTTP request is required in order to
+    /// continue.
+    HttpResponseReadBody(OffchainHttpResponseReadBody),
+    /// Indicating whether the local node is a validator is required in order to continue.
+    IsValidator(OffchainIsValidator),
+    /// Submitting a transaction is required in order to continue.
+    SubmitTransaction(OffchainSubmitTransaction),
+    /// Generating a new key pair in the keystore is required in order to continue.
+    KeyGeneration(OffchainKeyGeneration),
+}
+
+impl OffchainContext {
+    /// Cancels execution of the virtual machine and returns back the prototype.
+    pub fn into_prototype(self) -> host::HostVmPrototype {
+        match self {
+            OffchainContext::StorageGet(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::StorageSet(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::Timestamp(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::RandomSeed(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpRequestStart(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpRequestAddHeader(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpRequestWriteBody(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpResponseWait(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpResponseHeaders(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpResponseReadBody(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::IsValidator(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::SubmitTransaction(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::KeyGeneration(inner) => inner.inner.vm.into_prototype(),
+        }
+    }
+}
+
+/// Loading a value from the off-chain local storage is required in order to continue.
+#[must_use]
+pub struct OffchainStorageGet {
+    inner: Inner,
+}
+
+impl OffchainStorageGet {
+    /// Returns the key whose value must be loaded.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainStorageGet(req) => req.key(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Injects the corresponding off-chain local storage value.
+    pub fn inject_value(mut self, value: Option<&[u8]>) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainStorageGet(req) => self.inner.vm = req.resume(value),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Setting or clearing a value of the off-chain local storage is required in order to continue.
+#[must_use]
+pub struct OffchainStorageSet {
+    inner: Inner,
+}
+
+impl OffchainStorageSet {
+    /// Returns the key whose value must be set.
+    pub fn key(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainStorageSet(req) => req.key(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value to set. `None` if the entry must be removed.
+    pub fn value(&'_ self) -> Option<impl AsRef<[u8]> + '_> {
+        match &self.inner.vm {
+            host::HostVm::OffchainStorageSet(req) => req.value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// If `Some`, the value must only be set if the current value of the entry is equal to the
+    /// inner value, where `Some(None)` means that the entry must currently be absent.
+    pub fn old_value(&'_ self) -> Option<Option<impl AsRef<[u8]> + '_>> {
+        match &self.inner.vm {
+            host::HostVm::OffchainStorageSet(req) => req.old_value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution. `replaced` must be `true` if the value has been written.
+    pub fn resume(mut self, replaced: bool) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainStorageSet(req) => self.inner.vm = req.resume(replaced),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Providing the current time is required in order to continue.
+#[must_use]
+pub struct OffchainTimestamp {
+    inner: Inner,
+}
+
+impl OffchainTimestamp {
+    /// Resumes execution after having provided the number of milliseconds since the UNIX epoch.
+    pub fn inject_timestamp(mut self, value: u64) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainTimestamp(req) => self.inner.vm = req.resume(value),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Providing a random seed is required in order to continue.
+#[must_use]
+pub struct OffchainRandomSeed {
+    inner: Inner,
+}
+
+impl OffchainRandomSeed {
+    /// Resumes execution after having provided a random seed.
+    pub fn inject_random_seed(mut self, seed: [u8; 32]) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainRandomSeed(req) => self.inner.vm = req.resume(seed),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Starting an HTTP request is required in order to continue.
+#[must_use]
+pub struct OffchainHttpRequestStart {
+    inner: Inner,
+}
+
+impl OffchainHttpRequestStart {
+    /// Returns the HTTP method of the request (e.g. `GET`). Not guaranteed to be valid UTF-8.
+    pub fn method(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestStart(req) => req.method(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the URI of the request. Not guaranteed to be valid UTF-8.
+    pub fn uri(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestStart(req) => req.uri(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having started the request, which is now identified by the
+    /// given ID. `None` if the request couldn't be started.
+    pub fn resume(mut self, request_id: Option<u16>) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpRequestStart(req) => {
+                self.inner.vm = match request_id {
+                    Some(request_id) => req.resume(request_id),
+                    None => req.resume_failed(),
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Adding a header to an HTTP request is required in order to continue.
+#[must_use]
+pub struct OffchainHttpRequestAddHeader {
+    inner: Inner,
+}
+
+impl OffchainHttpRequestAddHeader {
+    /// Returns the identifier of the request. Not guaranteed to be valid.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the name of the header. Not guaranteed to be valid UTF-8.
+    pub fn name(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.name(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value of the header. Not guaranteed to be valid UTF-8.
+    pub fn value(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.value(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution. `success` must be `true` if the header has been added.
+    pub fn resume(mut self, success: bool) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => self.inner.vm = req.resume(success),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Writing a chunk of the body of an HTTP request is required in order to continue.
+#[must_use]
+pub struct OffchainHttpRequestWriteBody {
+    inner: Inner,
+}
+
+impl OffchainHttpRequestWriteBody {
+    /// Returns the identifier of the request. Not guaranteed to be valid.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the chunk to write. An empty chunk indicates that the body is complete.
+    pub fn chunk(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.chunk(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the timestamp, in milliseconds since the UNIX epoch, after which the operation
+    /// must fail.
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having written the chunk or not.
+    pub fn resume(mut self, result: Result<(), host::HttpError>) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => self.inner.vm = req.resume(result),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Waiting for the responses of HTTP requests is required in order to continue.
+#[must_use]
+pub struct OffchainHttpResponseWait {
+    inner: Inner,
+}
+
+impl OffchainHttpResponseWait {
+    /// Returns the identifiers of the requests. Not guaranteed to be valid.
+    pub fn request_ids(&self) -> &[u16] {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseWait(req) => req.request_ids(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the timestamp, in milliseconds since the UNIX epoch, after which waiting must
+    /// stop.
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseWait(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having provided the status of each request, in the same order
+    /// as [`OffchainHttpResponseWait::request_ids`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number of statuses isn't equal to the number of request IDs.
+    ///
+    pub fn resume(
+        mut self,
+        statuses: impl IntoIterator<Item = host::HttpRequestStatus>,
+    ) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpResponseWait(req) => self.inner.vm = req.resume(statuses),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Providing the headers of the response of an HTTP request is required in order to continue.
+#[must_use]
+pub struct OffchainHttpResponseHeaders {
+    inner: Inner,
+}
+
+impl OffchainHttpResponseHeaders {
+    /// Returns the identifier of the request. Not guaranteed to be valid.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseHeaders(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having provided the `(name, value)` headers of the response.
+    pub fn resume(
+        mut self,
+        headers: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>,
+    ) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpResponseHeaders(req) => self.inner.vm = req.resume(headers),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Providing a chunk of the body of the response of an HTTP request is required in order to
+/// continue.
+#[must_use]
+pub struct OffchainHttpResponseReadBody {
+    inner: Inner,
+}
+
+impl OffchainHttpResponseReadBody {
+    /// Returns the identifier of the request. Not guaranteed to be valid.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.request_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the maximum size of the chunk to provide.
+    pub fn max_size(&self) -> usize {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.max_size(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the timestamp, in milliseconds since the UNIX epoch, after which the operation
+    /// must fail.
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.deadline(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having provided the next chunk of the body. An empty chunk
+    /// indicates that the end of the body has been reached.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chunk is longer than what [`OffchainHttpResponseReadBody::max_size`]
+    /// returns.
+    ///
+    pub fn resume(mut self, result: Result<&[u8], host::HttpError>) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => self.inner.vm = req.resume(result),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Indicating whether the local node is a validator is required in order to continue.
+#[must_use]
+pub struct OffchainIsValidator {
+    inner: Inner,
+}
+
+impl OffchainIsValidator {
+    /// Resumes execution after having indicated whether the local node is a validator.
+    pub fn inject_is_validator(mut self, is_validator: bool) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainIsValidator(req) => self.inner.vm = req.resume(is_validator),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Submitting a transaction is required in order to continue.
+#[must_use]
+pub struct OffchainSubmitTransaction {
+    inner: Inner,
+}
+
+impl OffchainSubmitTransaction {
+    /// Returns the SCALE-encoded transaction that must be submitted.
+    pub fn transaction(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainSubmitTransaction(req) => req.transaction(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution. `success` must be `true` if the transaction has been accepted.
+    pub fn resume(mut self, success: bool) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::OffchainSubmitTransaction(req) => self.inner.vm = req.resume(success),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
+/// Generating a new key pair in the keystore is required in order to continue.
+#[must_use]
+pub struct OffchainKeyGeneration {
+    inner: Inner,
+}
+
+impl OffchainKeyGeneration {
+    /// Returns the cryptographic algorithm of the key to generate.
+    pub fn algorithm(&self) -> host::KeyGenerationAlgorithm {
+        match &self.inner.vm {
+            host::HostVm::KeyGeneration(req) => req.algorithm(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the identifier of the type of key to generate, for example `b"gran"` for a
+    /// GrandPa key.
+    pub fn key_type_id(&self) -> [u8; 4] {
+        match &self.inner.vm {
+            host::HostVm::KeyGeneration(req) => req.key_type_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the seed to generate the key from, if any. See [`host::KeyGeneration::seed`].
+    pub fn seed(&self) -> Option<&[u8]> {
+        match &self.inner.vm {
+            host::HostVm::KeyGeneration(req) => req.seed(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution after having provided the public key of the generated key pair.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the public key doesn't have the length that corresponds to
+    /// [`OffchainKeyGeneration::algorithm`]. See [`host::KeyGeneration::resume`].
+    ///
+    pub fn resume(mut self, public_key: &[u8]) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::KeyGeneration(req) => self.inner.vm = req.resume(public_key),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
 /// Implementation detail of the execution. Shared by all the variants of [`RuntimeHostVm`]
 /// other than [`RuntimeHostVm::Finished`].
 struct Inner {
@@ -632,6 +1225,23 @@ struct Inner {
 
     /// Value provided by [`Config::max_log_level`].
     max_log_level: u32,
+
+    /// Value provided by [`Config::offchain_behavior`].
+    offchain_behavior: OffchainBehavior,
+
+    /// Emulated off-chain local storage. Only used if [`Inner::offchain_behavior`] is
+    /// [`OffchainBehavior::EmulateInMemory`].
+    offchain_local_storage: BTreeMap<Vec<u8>, Vec<u8>>,
+
+    /// Spans and events emitted by the runtime so far. `None` if [`Config::tracing`] is `false`.
+    tracing: Option<Vec<TracingRecord>>,
+
+    /// Identifiers of the spans that the runtime has entered and not exited yet, from the
+    /// outermost to the innermost.
+    tracing_open_spans: Vec<u64>,
+
+    /// Identifier to assign to the next span entered by the runtime.
+    tracing_next_span_id: u64,
 }
 
 impl Inner {
@@ -661,6 +1271,7 @@ impl Inner {
                             .main_trie_root_calculation_cache
                             .unwrap(),
                         logs: self.logs,
+                        tracing: self.tracing.unwrap_or_default(),
                     }));
                 }
 
@@ -836,6 +1447,234 @@ impl Inner {
                     self.vm = req.resume();
                 }
 
+                host::HostVm::OffchainStorageGet(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        let value = self
+                            .offchain_local_storage
+                            .get(req.key().as_ref())
+                            .map(|v| &v[..]);
+                        self.vm = req.resume(value);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::StorageGet(
+                            OffchainStorageGet { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainStorageSet(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        let replaced = if let Some(old_value) = req.old_value() {
+                            match (
+                                self.offchain_local_storage.get(req.key().as_ref()),
+                                old_value,
+                            ) {
+                                (None, None) => true,
+                                (Some(current), Some(old_value)) => {
+                                    current[..] == *old_value.as_ref()
+                                }
+                                _ => false,
+                            }
+                        } else {
+                            true
+                        };
+
+                        if replaced {
+                            if let Some(value) = req.value() {
+                                self.offchain_local_storage
+                                    .insert(req.key().as_ref().to_vec(), value.as_ref().to_vec());
+                            } else {
+                                self.offchain_local_storage.remove(req.key().as_ref());
+                            }
+                        }
+
+                        self.vm = req.resume(replaced);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::StorageSet(
+                            OffchainStorageSet { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainTimestamp(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { timestamp_ms, .. } => {
+                        self.vm = req.resume(timestamp_ms);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::Timestamp(
+                            OffchainTimestamp { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainRandomSeed(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { random_seed, .. } => {
+                        self.vm = req.resume(random_seed);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::RandomSeed(
+                            OffchainRandomSeed { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainHttpRequestStart(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        self.vm = req.resume_failed();
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::HttpRequestStart(
+                            OffchainHttpRequestStart { inner: self },
+                        ));
+                    }
+                },
+
+                // Since no HTTP request can ever be started when emulating the off-chain
+                // context, all the functions below are necessarily called with an invalid
+                // request ID.
+                host::HostVm::OffchainHttpRequestAddHeader(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        self.vm = req.resume(false);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::HttpRequestAddHeader(
+                            OffchainHttpRequestAddHeader { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainHttpRequestWriteBody(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        self.vm = req.resume(Err(host::HttpError::Invalid));
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::HttpRequestWriteBody(
+                            OffchainHttpRequestWriteBody { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainHttpResponseWait(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        let num_requests = req.request_ids().len();
+                        self.vm = req.resume(
+                            iter::repeat(host::HttpRequestStatus::Invalid).take(num_requests),
+                        );
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::HttpResponseWait(
+                            OffchainHttpResponseWait { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainHttpResponseHeaders(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        self.vm = req.resume(iter::empty::<(&[u8], &[u8])>());
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::HttpResponseHeaders(
+                            OffchainHttpResponseHeaders { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainHttpResponseReadBody(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        self.vm = req.resume(Err(host::HttpError::Invalid));
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::HttpResponseReadBody(
+                            OffchainHttpResponseReadBody { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainIsValidator(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        self.vm = req.resume(false);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::IsValidator(
+                            OffchainIsValidator { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::OffchainSubmitTransaction(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::EmulateInMemory { .. } => {
+                        // Transactions submitted while emulating the off-chain context are
+                        // discarded.
+                        self.vm = req.resume(false);
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::SubmitTransaction(
+                            OffchainSubmitTransaction { inner: self },
+                        ));
+                    }
+                },
+
+                host::HostVm::KeyGeneration(req) => match self.offchain_behavior {
+                    OffchainBehavior::Deny | OffchainBehavior::EmulateInMemory { .. } => {
+                        return Self::forbidden_offchain_call(req.into());
+                    }
+                    OffchainBehavior::Forward => {
+                        self.vm = req.into();
+                        return RuntimeHostVm::Offchain(OffchainContext::KeyGeneration(
+                            OffchainKeyGeneration { inner: self },
+                        ));
+                    }
+                },
+
                 host::HostVm::SignatureVerification(req) => {
                     self.vm = req.into();
                     return RuntimeHostVm::SignatureVerification(SignatureVerification {
@@ -857,6 +1696,7 @@ impl Inner {
                         heap_pages: executor::DEFAULT_HEAP_PAGES,
                         exec_hint: vm::ExecHint::Oneshot,
                         allow_unresolved_imports: false, // TODO: what is a correct value here?
+                        fuel_limit: None,
                     }) {
                         Ok(w) => w,
                         Err(_) => {
@@ -933,9 +1773,80 @@ impl Inner {
                     }
                     self.vm = req.resume();
                 }
+
+                host::HostVm::TracingEnabled(req) => {
+                    let enabled = self.tracing.is_some();
+                    self.vm = req.resume(enabled);
+                }
+
+                host::HostVm::TracingEnterSpan(req) => {
+                    let id = self.tracing_next_span_id;
+                    self.tracing_next_span_id += 1;
+
+                    if let Some(tracing) = &mut self.tracing {
+                        // Similarly to the logs, we add a hardcoded limit to the number of spans
+                        // and events in order to make sure that there is no memory leak.
+                        if tracing.len() >= MAX_TRACING_RECORDS {
+                            return RuntimeHostVm::Finished(Err(Error {
+                                detail: ErrorDetail::TracingTooLong,
+                                prototype: host::HostVm::TracingEnterSpan(req).into_prototype(),
+                            }));
+                        }
+
+                        let mut span = req.span().clone();
+                        span.parent_id = span
+                            .parent_id
+                            .or_else(|| self.tracing_open_spans.last().copied());
+                        tracing.push(TracingRecord::Span { id, span });
+                    }
+
+                    self.tracing_open_spans.push(id);
+                    self.vm = req.resume(id);
+                }
+
+                host::HostVm::TracingEvent(req) => {
+                    if let Some(tracing) = &mut self.tracing {
+                        if tracing.len() >= MAX_TRACING_RECORDS {
+                            return RuntimeHostVm::Finished(Err(Error {
+                                detail: ErrorDetail::TracingTooLong,
+                                prototype: host::HostVm::TracingEvent(req).into_prototype(),
+                            }));
+                        }
+
+                        let mut event = req.event().clone();
+                        event.parent_id = event
+                            .parent_id
+                            .or_else(|| self.tracing_open_spans.last().copied());
+                        tracing.push(TracingRecord::Event(event));
+                    }
+
+                    self.vm = req.resume();
+                }
+
+                host::HostVm::TracingExitSpan(req) => {
+                    // Spans are normally exited in the reverse order of the one they have been
+                    // entered, but this isn't enforced.
+                    if let Some(pos) = self
+                        .tracing_open_spans
+                        .iter()
+                        .rposition(|id| *id == req.span_id())
+                    {
+                        self.tracing_open_spans.remove(pos);
+                    }
+                    self.vm = req.resume();
+                }
             }
         }
     }
+
+    /// Builds the value to return when the runtime calls an off-chain host function that
+    /// [`Config::offchain_behavior`] doesn't allow.
+    fn forbidden_offchain_call(vm: host::HostVm) -> RuntimeHostVm {
+        RuntimeHostVm::Finished(Err(Error {
+            detail: ErrorDetail::ForbiddenOffchainHostCall,
+            prototype: vm.into_prototype(),
+        }))
+    }
 }
 
 /// Performs the action described by [`host::HostVm::ExternalStorageAppend`] on an
@@ -86,10 +86,21 @@ impl VirtualMachinePrototype {
     /// functions, this number will be returned back in order for the user to know how to handle
     /// the call.
     ///
+    /// If `fuel_limit` is `Some`, each execution started from this prototype is allowed to consume
+    /// at most this amount of fuel. Each instruction executed consumes a deterministic amount of
+    /// fuel, and the execution stops with a [`Trap::OutOfFuel`] once the limit is reached. This
+    /// makes it possible to interrupt a runtime that would otherwise execute forever. Fuel
+    /// metering slows down the execution, and is disabled if `fuel_limit` is `None`.
+    ///
+    /// > **Note**: The fuel limit is only enforced by the interpreter. It is ignored if
+    /// >           [`ExecHint::CompileAheadOfTime`] or [`ExecHint::ForceWasmtime`] led to the
+    /// >           module being compiled.
+    ///
     /// See [the module-level documentation](..) for an explanation of the parameters.
     pub fn new(
         module_bytes: impl AsRef<[u8]>,
         exec_hint: ExecHint,
+        fuel_limit: Option<u64>,
         symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
     ) -> Result<Self, NewErr> {
         Ok(VirtualMachinePrototype {
@@ -100,11 +111,11 @@ impl VirtualMachinePrototype {
                 ),
                 #[cfg(not(all(target_arch = "x86_64", feature = "std")))]
                 ExecHint::CompileAheadOfTime => VirtualMachinePrototypeInner::Interpreter(
-                    interpreter::InterpreterPrototype::new(module_bytes, symbols)?,
+                    interpreter::InterpreterPrototype::new(module_bytes, fuel_limit, symbols)?,
                 ),
                 ExecHint::Oneshot | ExecHint::Untrusted | ExecHint::ForceWasmi => {
                     VirtualMachinePrototypeInner::Interpreter(
-                        interpreter::InterpreterPrototype::new(module_bytes, symbols)?,
+                        interpreter::InterpreterPrototype::new(module_bytes, fuel_limit, symbols)?,
                     )
                 }
 
@@ -761,10 +772,16 @@ pub enum ExecOutcome {
     },
 }
 
-/// Opaque error that happened during execution, such as an `unreachable` instruction.
+/// Error that happened during execution, such as an `unreachable` instruction.
 #[derive(Debug, derive_more::Display, Clone)]
-#[display(fmt = "{_0}")]
-pub struct Trap(String);
+pub enum Trap {
+    /// The execution has consumed all the fuel passed to [`VirtualMachinePrototype::new`].
+    #[display(fmt = "Execution has exceeded its fuel limit")]
+    OutOfFuel,
+    /// Any other error.
+    #[display(fmt = "{_0}")]
+    Other(String),
+}
 
 /// Error that can happen when initializing a [`VirtualMachinePrototype`].
 #[derive(Debug, derive_more::Display, Clone)]
@@ -46,15 +46,23 @@ struct BaseComponents {
     /// For each import of the module, either `None` if not a function, or `Some` containing the
     /// `usize` of that function.
     resolved_imports: Vec<Option<usize>>,
+
+    /// See [`super::VirtualMachinePrototype::new`]. If `None`, fuel metering is disabled.
+    fuel_limit: Option<u64>,
 }
 
 impl InterpreterPrototype {
     /// See [`super::VirtualMachinePrototype::new`].
     pub fn new(
         module_bytes: impl AsRef<[u8]>,
+        fuel_limit: Option<u64>,
         mut symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
     ) -> Result<Self, NewErr> {
-        let engine = wasmi::Engine::default(); // TODO: investigate config
+        // Fuel metering slows down the execution, and is thus only enabled if a limit has been
+        // requested.
+        let mut config = wasmi::Config::default(); // TODO: investigate rest of config
+        config.consume_fuel(fuel_limit.is_some());
+        let engine = wasmi::Engine::new(&config);
         let module = wasmi::Module::new(&engine, module_bytes.as_ref())
             .map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
 
@@ -92,6 +100,7 @@ impl InterpreterPrototype {
         Self::from_base_components(BaseComponents {
             module: Arc::new(module),
             resolved_imports,
+            fuel_limit,
         })
     }
 
@@ -225,6 +234,7 @@ impl Clone for InterpreterPrototype {
         InterpreterPrototype::from_base_components(BaseComponents {
             module: self.base_components.module.clone(),
             resolved_imports: self.base_components.resolved_imports.clone(),
+            fuel_limit: self.base_components.fuel_limit,
         })
         .unwrap()
     }
@@ -371,9 +381,22 @@ impl Prepare {
             })
         };
 
+        // Adjust the fuel of the store to the requested limit. `consume_fuel(0)` returns the
+        // amount of fuel that remains. Adding fuel can only fail in case of an overflow, in which
+        // case there is no limit anyway.
+        let mut store = self.inner.store;
+        if let Some(target) = self.inner.base_components.fuel_limit {
+            let remaining = store.consume_fuel(0).unwrap_or(0);
+            if target > remaining {
+                let _ = store.add_fuel(target - remaining);
+            } else {
+                let _ = store.consume_fuel(remaining - target);
+            }
+        }
+
         Ok(Interpreter {
             base_components: self.inner.base_components,
-            store: self.inner.store,
+            store,
             memory: self.inner.memory,
             dummy_output_value,
             execution: Some(Execution::NotStarted(
@@ -508,8 +531,15 @@ impl Interpreter {
                 self.execution = Some(Execution::Started(next));
                 Ok(outcome)
             }
+            Err(wasmi::Error::Trap(trap))
+                if matches!(trap.trap_code(), Some(wasmi::core::TrapCode::OutOfFuel)) =>
+            {
+                Ok(ExecOutcome::Finished {
+                    return_value: Err(Trap::OutOfFuel),
+                })
+            }
             Err(err) => Ok(ExecOutcome::Finished {
-                return_value: Err(Trap(err.to_string())),
+                return_value: Err(Trap::Other(err.to_string())),
             }),
         }
     }
@@ -736,7 +736,7 @@ impl Jit {
             Poll::Ready((store, Err(err))) => {
                 self.inner = JitInner::Done(store);
                 Ok(ExecOutcome::Finished {
-                    return_value: Err(Trap(err.to_string())),
+                    return_value: Err(Trap::Other(err.to_string())),
                 })
             }
             Poll::Pending => {
@@ -34,6 +34,7 @@ fn basic_seems_to_work() {
         let prototype = super::VirtualMachinePrototype::new(
             &include_bytes!("./test-polkadot-runtime-v9160.wasm")[..],
             exec_hint,
+            None,
             |_, _, _| Ok(0),
         )
         .unwrap();
@@ -73,7 +74,9 @@ fn out_of_memory_access() {
             0x06, 0x01, 0x00, 0x41, 0x03, 0x0b, 0x00,
         ];
 
-        assert!(super::VirtualMachinePrototype::new(input, exec_hint, |_, _, _| Ok(0)).is_err());
+        assert!(
+            super::VirtualMachinePrototype::new(input, exec_hint, None, |_, _, _| Ok(0)).is_err()
+        );
     }
 }
 
@@ -85,7 +88,9 @@ fn has_start_function() {
             0x02, 0x09, 0x01, 0x01, 0x71, 0x03, 0x69, 0x6d, 0x70, 0x00, 0x00, 0x08, 0x01, 0x00,
         ];
 
-        assert!(super::VirtualMachinePrototype::new(input, exec_hint, |_, _, _| Ok(0)).is_err());
+        assert!(
+            super::VirtualMachinePrototype::new(input, exec_hint, None, |_, _, _| Ok(0)).is_err()
+        );
     }
 }
 
@@ -98,7 +103,9 @@ fn unsupported_type() {
             0x00, 0x00,
         ];
 
-        assert!(super::VirtualMachinePrototype::new(input, exec_hint, |_, _, _| Ok(0)).is_err());
+        assert!(
+            super::VirtualMachinePrototype::new(input, exec_hint, None, |_, _, _| Ok(0)).is_err()
+        );
     }
 }
 
@@ -121,7 +128,8 @@ fn basic_host_function_return_value_works() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -164,7 +172,7 @@ fn no_memory() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0)),
             Err(super::NewErr::NoMemory)
         ));
     }
@@ -187,7 +195,7 @@ fn memory_misnamed() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0)),
             Err(super::NewErr::MemoryNotNamedMemory)
         ));
     }
@@ -214,7 +222,7 @@ fn two_memories() {
         // multi-memory Wasm proposal isn't finalized yet. Even once finalized, we want to deny
         // this feature in smoldot.
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0)),
             Err(super::NewErr::InvalidWasm(_) | super::NewErr::TwoMemories)
         ));
     }
@@ -236,7 +244,8 @@ fn exported_memory_works() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+            .unwrap();
     }
 }
 
@@ -257,7 +266,7 @@ fn unresolved_function() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Err(())),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Err(())),
             Err(super::NewErr::UnresolvedFunctionImport { .. })
         ));
     }
@@ -278,7 +287,7 @@ fn unsupported_signature() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0)),
             Err(super::NewErr::UnresolvedFunctionImport { .. })
         ));
     }
@@ -299,7 +308,7 @@ fn unsupported_import_type() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0)),
             Err(super::NewErr::ImportTypeNotSupported)
         ));
     }
@@ -321,7 +330,7 @@ fn start_function_forbidden() {
     for exec_hint in super::ExecHint::available_engines() {
         // TODO: `Ok(_)` shouldn't be accepted, but wasmtime doesn't really make it possible to detect the start function at the moment
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0)),
             Err(super::NewErr::StartFunctionNotSupported) | Ok(_)
         ));
     }
@@ -341,7 +350,8 @@ fn max_memory_pages() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert_eq!(
             prototype.memory_max_pages().unwrap(),
             super::HeapPages::new(4096)
@@ -363,7 +373,8 @@ fn get_global() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let mut prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert_eq!(prototype.global_value("test").unwrap(), 12);
     }
 }
@@ -382,7 +393,8 @@ fn call_non_existing_function() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert!(matches!(
             prototype.prepare().start("doesntexist", &[]),
             Err((super::StartErr::FunctionNotFound, _))
@@ -404,7 +416,8 @@ fn call_signature_not_supported() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert!(matches!(
             prototype.prepare().start("hello", &[]),
             Err((super::StartErr::SignatureNotSupported, _))
@@ -426,7 +439,8 @@ fn bad_params_types() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert!(matches!(
             prototype.prepare().start("hello", &[]),
             Err((super::StartErr::InvalidParameters, _))
@@ -448,7 +462,8 @@ fn try_to_call_global() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert!(matches!(
             prototype.prepare().start("hello", &[]),
             // TODO: wasmi doesn't properly detect NotAFunction at the moment
@@ -477,7 +492,8 @@ fn wrong_type_provided_initially() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -505,7 +521,8 @@ fn wrong_type_returned_by_host_function_call() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -531,7 +548,8 @@ fn memory_min_specified_in_wasm() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         let interpreter = prototype.prepare().start("hello", &[]).unwrap();
         assert_eq!(interpreter.memory_size(), super::HeapPages::new(16));
     }
@@ -551,7 +569,8 @@ fn memory_grow_works() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         let mut interpreter = prototype.prepare().start("hello", &[]).unwrap();
         assert_eq!(interpreter.memory_size(), super::HeapPages::new(16));
         interpreter.grow_memory(super::HeapPages::new(3)).unwrap();
@@ -573,7 +592,8 @@ fn memory_grow_detects_limit() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         let mut interpreter = prototype.prepare().start("hello", &[]).unwrap();
         assert_eq!(interpreter.memory_size(), super::HeapPages::new(16));
         assert!(interpreter.grow_memory(super::HeapPages::new(10)).is_err());
@@ -597,7 +617,8 @@ fn memory_grow_detects_limit_within_host_function() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -644,7 +665,8 @@ fn globals_reinitialized_after_reset() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let mut prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
         assert_eq!(prototype.global_value("myglob").unwrap(), 5);
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
@@ -674,7 +696,8 @@ fn memory_zeroed_after_reset() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
 
         let mut vm = prototype.prepare();
         vm.write_memory(11, &[5, 6]).unwrap();
@@ -718,7 +741,8 @@ fn memory_zeroed_after_prepare() {
 
     for exec_hint in super::ExecHint::available_engines() {
         let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, None, |_, _, _| Ok(0))
+                .unwrap();
 
         let mut vm = prototype.prepare();
         assert_eq!(vm.read_memory(11, 2).unwrap().as_ref(), &[0, 0]);
@@ -729,4 +753,36 @@ fn memory_zeroed_after_prepare() {
     }
 }
 
+#[test]
+fn fuel_limit_interrupts_infinite_loop() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (import "env" "memory" (memory $mem 1024 4096))
+            (func (export "hello")
+                (loop $l
+                    br $l))
+        )
+        "#,
+    )
+    .unwrap();
+
+    // The fuel limit is only enforced by the interpreter.
+    let prototype = super::VirtualMachinePrototype::new(
+        &module_bytes,
+        super::ExecHint::ForceWasmi,
+        Some(1_000_000),
+        |_, _, _| Ok(0),
+    )
+    .unwrap();
+
+    let mut vm = prototype.prepare().start("hello", &[]).unwrap();
+    assert!(matches!(
+        vm.run(None),
+        Ok(super::ExecOutcome::Finished {
+            return_value: Err(super::Trap::OutOfFuel)
+        })
+    ));
+}
+
 // TODO: test for memory reads and writes, including within host functions
@@ -0,0 +1,256 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Construction of signed extrinsics.
+//!
+//! A signed extrinsic (also called a *signed transaction*) consists of a call, the address of
+//! the account that sends it, a signature, and the so-called *extra* data of each of the
+//! *signed extensions* of the chain. The list of signed extensions is found in the metadata of
+//! the runtime (see [`crate::metadata::Extrinsic::signed_extensions`]).
+//!
+//! The signature covers the call, the extra data of the signed extensions, and the so-called
+//! *additional signed* data of the signed extensions. The additional signed data, such as the
+//! hash of the genesis block or the version of the runtime, isn't included in the extrinsic
+//! itself but is known by the runtime.
+//!
+//! The following signed extensions are supported: `CheckNonZeroSender`, `CheckSpecVersion`,
+//! `CheckTxVersion`, `CheckGenesis`, `CheckMortality`, `CheckNonce`, `CheckWeight`,
+//! `ChargeTransactionPayment`, `ChargeAssetTxPayment`, `PrevalidateAttests` and
+//! `CheckMetadataHash`. Other signed extensions are supported only if, according to the
+//! metadata, neither their extra data nor their additional signed data contain anything.
+//!
+//! Only version 4 of the extrinsics format is supported.
+
+use crate::{metadata, util};
+
+use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
+
+mod tests;
+
+/// Configuration for building an extrinsic. See [`build`].
+#[derive(Debug, Clone)]
+pub struct Config<'a> {
+    /// Metadata of the runtime of the chain.
+    pub metadata: &'a metadata::Metadata,
+
+    /// SCALE-encoded call to put in the extrinsic.
+    pub call: &'a [u8],
+
+    /// SCALE-encoded address of the sender of the extrinsic. For most chains, this is the
+    /// SCALE encoding of a `MultiAddress`, in other words `0x00` followed with the 32 bytes
+    /// public key of the sender.
+    pub address: &'a [u8],
+
+    /// Nonce of the sender. Must be equal to the number of extrinsics that the sender has
+    /// previously included in the chain.
+    pub nonce: u64,
+
+    /// Amount to pay to the block author in addition to the fees of the extrinsic.
+    pub tip: u128,
+
+    /// SCALE-encoded identifier of the asset in which to pay the fees, for chains that use the
+    /// `ChargeAssetTxPayment` signed extension. `None` to pay with the native currency of the
+    /// chain. Ignored if the chain doesn't use this signed extension.
+    pub asset_id: Option<&'a [u8]>,
+
+    /// Period of time during which the extrinsic is valid.
+    pub mortality: Mortality,
+
+    /// Hash of the genesis block of the chain.
+    pub genesis_hash: [u8; 32],
+
+    /// Specification version of the runtime of the chain.
+    pub spec_version: u32,
+
+    /// Transaction version of the runtime of the chain.
+    pub transaction_version: u32,
+}
+
+/// See [`Config::mortality`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mortality {
+    /// The extrinsic is valid forever.
+    Immortal,
+    /// The extrinsic is only valid during a certain number of blocks.
+    Mortal {
+        /// Number of blocks during which the extrinsic is valid. Rounded up to a power of two,
+        /// and clamped between 4 and 4096.
+        period: u64,
+        /// Number of the block from which the extrinsic is valid, typically the current best
+        /// block.
+        block_number: u64,
+        /// Hash of the block whose number is [`Mortality::Mortal::block_number`].
+        block_hash: [u8; 32],
+    },
+}
+
+/// Error potentially returned by [`build`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Error {
+    /// The version of the extrinsics format of the chain isn't supported.
+    #[display(fmt = "Unsupported extrinsic version: {_0}")]
+    UnsupportedExtrinsicVersion(u8),
+    /// One of the signed extensions of the chain isn't supported.
+    #[display(fmt = "Unsupported signed extension: {_0}")]
+    UnsupportedSignedExtension(String),
+}
+
+/// Builds a signed extrinsic.
+///
+/// `sign` is called with the payload to sign, and must return the SCALE-encoded signature. For
+/// most chains, this is the SCALE encoding of a `MultiSignature`, for example `0x01` followed
+/// with the 64 bytes of an sr25519 signature.
+///
+/// The returned extrinsic is prefixed with its length, and can be passed as is to the
+/// `author_submitExtrinsic` JSON-RPC function.
+pub fn build(config: Config, sign: impl FnOnce(&[u8]) -> Vec<u8>) -> Result<Vec<u8>, Error> {
+    if config.metadata.extrinsic.version != 4 {
+        return Err(Error::UnsupportedExtrinsicVersion(
+            config.metadata.extrinsic.version,
+        ));
+    }
+
+    let mut extra = Vec::new();
+    let mut additional_signed = Vec::new();
+
+    for extension in &config.metadata.extrinsic.signed_extensions {
+        match extension.identifier.as_str() {
+            "CheckNonZeroSender" | "CheckWeight" | "PrevalidateAttests" => {}
+            "CheckSpecVersion" => {
+                additional_signed.extend_from_slice(&config.spec_version.to_le_bytes());
+            }
+            "CheckTxVersion" => {
+                additional_signed.extend_from_slice(&config.transaction_version.to_le_bytes());
+            }
+            "CheckGenesis" => {
+                additional_signed.extend_from_slice(&config.genesis_hash);
+            }
+            "CheckMortality" | "CheckEra" => {
+                extra.extend_from_slice(&encode_era(&config.mortality));
+                match &config.mortality {
+                    Mortality::Immortal => {
+                        additional_signed.extend_from_slice(&config.genesis_hash);
+                    }
+                    Mortality::Mortal { block_hash, .. } => {
+                        additional_signed.extend_from_slice(block_hash);
+                    }
+                }
+            }
+            "CheckNonce" => {
+                extra.extend_from_slice(util::encode_scale_compact_u64(config.nonce).as_ref());
+            }
+            "ChargeTransactionPayment" => {
+                extra.extend_from_slice(util::encode_scale_compact_u128(config.tip).as_ref());
+            }
+            "ChargeAssetTxPayment" => {
+                extra.extend_from_slice(util::encode_scale_compact_u128(config.tip).as_ref());
+                match config.asset_id {
+                    Some(asset_id) => {
+                        extra.push(1);
+                        extra.extend_from_slice(asset_id);
+                    }
+                    None => extra.push(0),
+                }
+            }
+            "CheckMetadataHash" => {
+                // The verification of the metadata hash is disabled.
+                extra.push(0);
+                additional_signed.push(0);
+            }
+            _ => {
+                if !is_zero_sized(config.metadata, extension.ty, 0)
+                    || !is_zero_sized(config.metadata, extension.additional_signed, 0)
+                {
+                    return Err(Error::UnsupportedSignedExtension(
+                        extension.identifier.to_owned(),
+                    ));
+                }
+            }
+        }
+    }
+
+    // Payloads longer than 256 bytes are hashed before being signed.
+    let signature = {
+        let mut payload =
+            Vec::with_capacity(config.call.len() + extra.len() + additional_signed.len());
+        payload.extend_from_slice(config.call);
+        payload.extend_from_slice(&extra);
+        payload.extend_from_slice(&additional_signed);
+        if payload.len() > 256 {
+            sign(blake2_rfc::blake2b::blake2b(32, &[], &payload).as_bytes())
+        } else {
+            sign(&payload)
+        }
+    };
+
+    let body_len = 1 + config.address.len() + signature.len() + extra.len() + config.call.len();
+    let length_prefix = util::encode_scale_compact_usize(body_len);
+
+    let mut extrinsic = Vec::with_capacity(length_prefix.as_ref().len() + body_len);
+    extrinsic.extend_from_slice(length_prefix.as_ref());
+    // Version 4, with the most significant bit indicating a signed extrinsic.
+    extrinsic.push(0b1000_0000 | 4);
+    extrinsic.extend_from_slice(config.address);
+    extrinsic.extend_from_slice(&signature);
+    extrinsic.extend_from_slice(&extra);
+    extrinsic.extend_from_slice(config.call);
+    Ok(extrinsic)
+}
+
+/// Returns the SCALE encoding of the given mortality, as expected by the `CheckMortality`
+/// signed extension.
+pub fn encode_era(mortality: &Mortality) -> Vec<u8> {
+    match *mortality {
+        Mortality::Immortal => alloc::vec![0],
+        Mortality::Mortal {
+            period,
+            block_number,
+            ..
+        } => {
+            let period = period.clamp(4, 4096).next_power_of_two();
+            // Because the period is at most 4096, the phase doesn't need to be quantized, and
+            // the era starts exactly at `block_number`.
+            let phase = block_number % period;
+            let encoded = u16::try_from(period.trailing_zeros() - 1)
+                .unwrap()
+                .clamp(1, 15)
+                | (u16::try_from(phase).unwrap() << 4);
+            encoded.to_le_bytes().to_vec()
+        }
+    }
+}
+
+/// Returns `true` if the SCALE encoding of the type with the given identifier is always empty.
+fn is_zero_sized(metadata: &metadata::Metadata, ty: u32, depth: u8) -> bool {
+    // Protects against types that recursively contain themselves.
+    if depth >= 16 {
+        return false;
+    }
+
+    match metadata.type_by_id(ty).map(|t| &t.definition) {
+        Some(metadata::TypeDefinition::Composite(fields)) => fields
+            .iter()
+            .all(|field| is_zero_sized(metadata, field.ty, depth + 1)),
+        Some(metadata::TypeDefinition::Tuple(types)) => types
+            .iter()
+            .all(|ty| is_zero_sized(metadata, *ty, depth + 1)),
+        Some(metadata::TypeDefinition::Array { len, ty }) => {
+            *len == 0 || is_zero_sized(metadata, *ty, depth + 1)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,187 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::*;
+
+/// Metadata whose type `0` is `()` and type `1` is `u32`, and with the given signed extensions.
+fn metadata_with_extensions(extensions: &[(&str, u32, u32)]) -> metadata::Metadata {
+    let ty = |id, definition| metadata::Type {
+        id,
+        path: Vec::new(),
+        params: Vec::new(),
+        definition,
+        docs: Vec::new(),
+    };
+
+    metadata::Metadata {
+        version: 15,
+        types: vec![
+            ty(0, metadata::TypeDefinition::Tuple(Vec::new())),
+            ty(
+                1,
+                metadata::TypeDefinition::Primitive(metadata::Primitive::U32),
+            ),
+        ],
+        pallets: Vec::new(),
+        extrinsic: metadata::Extrinsic {
+            version: 4,
+            ty: None,
+            address_call_signature_extra_types: None,
+            signed_extensions: extensions
+                .iter()
+                .map(
+                    |(identifier, ty, additional_signed)| metadata::SignedExtension {
+                        identifier: (*identifier).to_owned(),
+                        ty: *ty,
+                        additional_signed: *additional_signed,
+                    },
+                )
+                .collect(),
+        },
+        runtime_type: 0,
+        runtime_apis: Vec::new(),
+        outer_enums: None,
+        custom: Vec::new(),
+    }
+}
+
+fn config<'a>(metadata: &'a metadata::Metadata, call: &'a [u8]) -> Config<'a> {
+    Config {
+        metadata,
+        call,
+        address: &[0x00, 0xaa, 0xaa],
+        nonce: 5,
+        tip: 0,
+        asset_id: None,
+        mortality: Mortality::Mortal {
+            period: 64,
+            block_number: 42,
+            block_hash: [0xbb; 32],
+        },
+        genesis_hash: [0xcc; 32],
+        spec_version: 1000,
+        transaction_version: 2,
+    }
+}
+
+#[test]
+fn era_encoding() {
+    assert_eq!(encode_era(&Mortality::Immortal), vec![0x00]);
+    assert_eq!(
+        encode_era(&Mortality::Mortal {
+            period: 64,
+            block_number: 42,
+            block_hash: [0; 32]
+        }),
+        vec![0xa5, 0x02]
+    );
+    // Period rounded up to 64.
+    assert_eq!(
+        encode_era(&Mortality::Mortal {
+            period: 50,
+            block_number: 42,
+            block_hash: [0; 32]
+        }),
+        vec![0xa5, 0x02]
+    );
+}
+
+#[test]
+fn build_layout() {
+    let metadata = metadata_with_extensions(&[
+        ("CheckNonZeroSender", 0, 0),
+        ("CheckSpecVersion", 0, 1),
+        ("CheckGenesis", 0, 0),
+        ("CheckMortality", 0, 0),
+        ("CheckNonce", 0, 0),
+        ("ChargeAssetTxPayment", 0, 0),
+        ("SomeEmptyExtension", 0, 0),
+    ]);
+
+    let call = [0x01, 0x02, 0x03];
+    let mut config = config(&metadata, &call);
+    config.tip = 1;
+    config.asset_id = Some(&[0x07]);
+
+    let mut signed_payload = None;
+    let extrinsic = build(config, |payload| {
+        signed_payload = Some(payload.to_vec());
+        vec![0x01, 0xdd]
+    })
+    .unwrap();
+
+    let extra = [0xa5, 0x02, 5 << 2, 1 << 2, 0x01, 0x07];
+
+    let mut expected_payload = call.to_vec();
+    expected_payload.extend_from_slice(&extra);
+    expected_payload.extend_from_slice(&1000u32.to_le_bytes());
+    expected_payload.extend_from_slice(&[0xcc; 32]);
+    expected_payload.extend_from_slice(&[0xbb; 32]);
+    assert_eq!(signed_payload.unwrap(), expected_payload);
+
+    let mut expected_body = vec![0x84, 0x00, 0xaa, 0xaa, 0x01, 0xdd];
+    expected_body.extend_from_slice(&extra);
+    expected_body.extend_from_slice(&call);
+    let mut expected = util::encode_scale_compact_usize(expected_body.len())
+        .as_ref()
+        .to_vec();
+    expected.extend_from_slice(&expected_body);
+    assert_eq!(extrinsic, expected);
+}
+
+#[test]
+fn long_payload_hashed() {
+    let metadata = metadata_with_extensions(&[("CheckNonce", 0, 0)]);
+    let call = [0x42; 300];
+
+    let mut signed_payload = None;
+    build(config(&metadata, &call), |payload| {
+        signed_payload = Some(payload.to_vec());
+        vec![0x01, 0xdd]
+    })
+    .unwrap();
+
+    let mut full_payload = call.to_vec();
+    full_payload.push(5 << 2);
+    assert_eq!(
+        signed_payload.unwrap(),
+        blake2_rfc::blake2b::blake2b(32, &[], &full_payload).as_bytes()
+    );
+}
+
+#[test]
+fn unknown_extension_rejected() {
+    let metadata = metadata_with_extensions(&[("CheckNonce", 0, 0), ("SomethingNew", 1, 0)]);
+    let call = [0x00];
+    assert!(matches!(
+        build(config(&metadata, &call), |_| unreachable!()),
+        Err(Error::UnsupportedSignedExtension(name)) if name == "SomethingNew"
+    ));
+}
+
+#[test]
+fn unsupported_extrinsic_version() {
+    let mut metadata = metadata_with_extensions(&[]);
+    metadata.extrinsic.version = 5;
+    let call = [0x00];
+    assert!(matches!(
+        build(config(&metadata, &call), |_| unreachable!()),
+        Err(Error::UnsupportedExtrinsicVersion(5))
+    ));
+}
@@ -0,0 +1,605 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! BEEFY finality gadget.
+//!
+//! BEEFY runs on top of GrandPa. Once a block has been finalized by GrandPa, the BEEFY validators
+//! sign a so-called *commitment* that contains the number of this block and a payload, in
+//! practice the root of the Merkle Mountain Range (MMR) of the chain. A commitment accompanied
+//! with the signatures of more than two thirds of the validators is called a *signed commitment*
+//! and is a proof of the finality of the block.
+//!
+//! Contrary to GrandPa, BEEFY uses ECDSA signatures over the Keccak-256 hash of the commitment.
+//! This makes it possible to cheaply verify signed commitments on other blockchains, and BEEFY
+//! is consequently used by bridges.
+//!
+//! The list of BEEFY validators, called the *validator set*, changes over time. Each validator
+//! set has an identifier that is incremented by one at each change. Changes are announced in the
+//! digest of block headers using the consensus engine [`ENGINE_ID`]. See
+//! [`decode_consensus_log`].
+
+pub mod mmr;
+
+use alloc::vec::Vec;
+use core::iter;
+use tiny_keccak::Hasher as _;
+
+/// Name of the consensus engine of the header digest items related to BEEFY.
+pub const ENGINE_ID: [u8; 4] = *b"BEEF";
+
+/// Identifier of the item of the payload of a commitment containing the root of the Merkle
+/// Mountain Range.
+pub const MMR_ROOT_PAYLOAD_ID: [u8; 2] = *b"mh";
+
+/// Attempt to decode the given SCALE-encoded versioned finality proof.
+///
+/// A versioned finality proof is what is gossiped over the network and returned by the
+/// `beefy_subscribeJustifications` JSON-RPC function.
+pub fn decode_versioned_finality_proof(
+    scale_encoded: &[u8],
+    block_number_bytes: usize,
+) -> Result<SignedCommitmentRef, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(versioned_finality_proof(
+        block_number_bytes,
+    )))(scale_encoded)
+    {
+        Ok((_, proof)) => Ok(proof),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode the given SCALE-encoded signed commitment.
+pub fn decode_signed_commitment(
+    scale_encoded: &[u8],
+    block_number_bytes: usize,
+) -> Result<SignedCommitmentRef, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(signed_commitment(
+        block_number_bytes,
+    )))(scale_encoded)
+    {
+        Ok((_, commitment)) => Ok(commitment),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode the opaque content of a header digest item whose consensus engine is
+/// [`ENGINE_ID`].
+pub fn decode_consensus_log(scale_encoded: &[u8]) -> Result<ConsensusLogRef, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(consensus_log))(scale_encoded) {
+        Ok((_, log)) => Ok(log),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode the given SCALE-encoded validator set, as returned for example by the
+/// `BeefyApi_validator_set` runtime function.
+pub fn decode_validator_set(scale_encoded: &[u8]) -> Result<ValidatorSetRef, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(validator_set))(scale_encoded) {
+        Ok((_, set)) => Ok(set),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Decoded signed commitment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedCommitmentRef<'a> {
+    /// Commitment that has been signed.
+    pub commitment: CommitmentRef<'a>,
+
+    /// Signatures of the commitment. Contains one entry per validator of the validator set, in
+    /// the same order as the validator set. Each entry is `None` if the corresponding validator
+    /// hasn't signed the commitment.
+    pub signatures: Vec<Option<&'a [u8; 65]>>,
+}
+
+/// Decoded commitment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitmentRef<'a> {
+    /// List of items that are committed to, each with its identifier. In practice, contains an
+    /// item whose identifier is [`MMR_ROOT_PAYLOAD_ID`].
+    pub payload: Vec<([u8; 2], &'a [u8])>,
+
+    /// Height of the block the commitment is about.
+    pub block_number: u64,
+
+    /// Identifier of the validator set that is expected to sign the commitment.
+    pub validator_set_id: u64,
+
+    /// SCALE encoding of the commitment, in other words the message that is signed.
+    scale_encoded: &'a [u8],
+}
+
+impl<'a> CommitmentRef<'a> {
+    /// Returns the root of the Merkle Mountain Range found in the payload, if any.
+    pub fn mmr_root(&self) -> Option<&'a [u8; 32]> {
+        self.payload
+            .iter()
+            .find(|(id, _)| *id == MMR_ROOT_PAYLOAD_ID)
+            .and_then(|(_, value)| <&[u8; 32]>::try_from(*value).ok())
+    }
+
+    /// Returns the SCALE encoding of the commitment.
+    pub fn scale_encoding(&self) -> &'a [u8] {
+        self.scale_encoded
+    }
+}
+
+/// Decoded BEEFY header digest item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsensusLogRef<'a> {
+    /// The validator set changes starting from the block containing this item. The new
+    /// validator set is enacted immediately.
+    AuthoritiesChange(ValidatorSetRef<'a>),
+    /// A validator of the current validator set, identified by its index, has been disabled.
+    OnDisabled(u32),
+    /// Root of the Merkle Mountain Range at this block.
+    MmrRoot(&'a [u8; 32]),
+}
+
+/// Decoded validator set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorSetRef<'a> {
+    /// Compressed ECDSA public keys of the validators.
+    pub validators: Vec<&'a [u8; 33]>,
+    /// Identifier of the validator set.
+    pub id: u64,
+}
+
+/// Configuration for a signed commitment verification process.
+#[derive(Debug)]
+pub struct VerifyConfig<'a, I> {
+    /// Signed commitment to verify.
+    pub signed_commitment: &'a SignedCommitmentRef<'a>,
+
+    /// Identifier of the validator set in [`VerifyConfig::validators`].
+    pub validator_set_id: u64,
+
+    /// List of validators that are expected to sign the commitment. Must implement
+    /// `ExactSizeIterator<Item = &[u8; 33]>`, where each item is the compressed ECDSA public key
+    /// of a validator.
+    pub validators: I,
+}
+
+/// Verifies that a signed commitment is valid, in other words that it has been signed by more
+/// than two thirds of the validators of the given validator set.
+pub fn verify<'a>(
+    config: VerifyConfig<'_, impl ExactSizeIterator<Item = &'a [u8; 33]>>,
+) -> Result<(), Error> {
+    let commitment = &config.signed_commitment.commitment;
+
+    if commitment.validator_set_id != config.validator_set_id {
+        return Err(Error::BadValidatorSetId);
+    }
+
+    let num_validators = config.validators.len();
+    if config.signed_commitment.signatures.len() != num_validators {
+        return Err(Error::BadSignaturesCount);
+    }
+
+    // The number of signatures must be strictly superior to two thirds of the number of
+    // validators, which is equivalent to tolerating up to `(n - 1) / 3` faulty validators.
+    let threshold = num_validators - num_validators.saturating_sub(1) / 3;
+    let num_signatures = config
+        .signed_commitment
+        .signatures
+        .iter()
+        .filter(|s| s.is_some())
+        .count();
+    if num_signatures < threshold {
+        return Err(Error::NotEnoughSignatures);
+    }
+
+    let message = {
+        let mut keccak = tiny_keccak::Keccak::v256();
+        keccak.update(commitment.scale_encoded);
+        let mut out = [0; 32];
+        keccak.finalize(&mut out);
+        libsecp256k1::Message::parse(&out)
+    };
+
+    for (signature, validator) in config
+        .signed_commitment
+        .signatures
+        .iter()
+        .zip(config.validators)
+    {
+        let Some(signature) = signature else {
+            continue;
+        };
+
+        if recover_compressed(&message, signature).as_ref() != Some(validator) {
+            return Err(Error::BadSignature(*validator));
+        }
+    }
+
+    Ok(())
+}
+
+/// Recovers the compressed public key that has produced the given signature of `message`.
+fn recover_compressed(message: &libsecp256k1::Message, signature: &[u8; 65]) -> Option<[u8; 33]> {
+    let rs = libsecp256k1::Signature::parse_standard_slice(&signature[..64]).ok()?;
+    let v = libsecp256k1::RecoveryId::parse(if signature[64] > 26 {
+        signature[64] - 27
+    } else {
+        signature[64]
+    })
+    .ok()?;
+    let public_key = libsecp256k1::recover(message, &rs, &v).ok()?;
+    Some(public_key.serialize_compressed())
+}
+
+/// Potential error when decoding or verifying BEEFY-related objects.
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Failed to decode the object.
+    #[display(fmt = "Failed to decode: {_0:?}")]
+    Decode(nom::error::ErrorKind),
+    /// Identifier of the validator set of the commitment doesn't match the one passed in the
+    /// configuration.
+    BadValidatorSetId,
+    /// Number of signatures in the signed commitment doesn't match the number of validators.
+    BadSignaturesCount,
+    /// Signed commitment doesn't contain enough signatures.
+    NotEnoughSignatures,
+    /// One of the signatures doesn't match the validator that is supposed to have produced it.
+    #[display(fmt = "Invalid signature of validator 0x{}", "hex::encode(_0)")]
+    BadSignature([u8; 33]),
+}
+
+/// Returns the SCALE encoding of a versioned finality proof containing the given signed
+/// commitment.
+///
+/// # Panic
+///
+/// Panics if the number of signatures doesn't fit in a `u32`.
+///
+pub fn encode_versioned_finality_proof<'a>(
+    signed_commitment: &'a SignedCommitmentRef<'a>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    // Signatures are encoded in a compact way: a bit field indicating which validators have
+    // signed, followed with the list of signatures that are present. The bits are ordered from
+    // the most significant bit to the least significant bit of each byte.
+    let signatures_from = signed_commitment
+        .signatures
+        .chunks(8)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0u8, |byte, (n, sig)| {
+                byte | (u8::from(sig.is_some()) << (7 - n))
+            })
+        })
+        .collect::<Vec<_>>();
+    let num_signatures = signed_commitment
+        .signatures
+        .iter()
+        .filter(|s| s.is_some())
+        .count();
+
+    iter::once(either::Left(either::Left([1u8])))
+        .chain(iter::once(either::Right(either::Left(
+            signed_commitment.commitment.scale_encoded,
+        ))))
+        .chain(iter::once(either::Right(either::Right(
+            crate::util::encode_scale_compact_usize(signatures_from.len()),
+        ))))
+        .chain(iter::once(either::Left(either::Right(signatures_from))))
+        .chain(iter::once(either::Left(either::Right(
+            u32::try_from(signed_commitment.signatures.len())
+                .unwrap()
+                .to_le_bytes()
+                .to_vec(),
+        ))))
+        .chain(iter::once(either::Right(either::Right(
+            crate::util::encode_scale_compact_usize(num_signatures),
+        ))))
+        .chain(
+            signed_commitment
+                .signatures
+                .iter()
+                .filter_map(|s| *s)
+                .map(|s| either::Right(either::Left(&s[..]))),
+        )
+}
+
+fn versioned_finality_proof<'a>(
+    block_number_bytes: usize,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], SignedCommitmentRef<'a>> {
+    // Only the version 1 exists at the moment. It uses the index `1`.
+    nom::sequence::preceded(
+        nom::bytes::complete::tag(&[1]),
+        signed_commitment(block_number_bytes),
+    )
+}
+
+pub(crate) fn signed_commitment<'a>(
+    block_number_bytes: usize,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], SignedCommitmentRef<'a>> {
+    nom::combinator::map_opt(
+        nom::sequence::tuple((
+            commitment(block_number_bytes),
+            crate::util::nom_bytes_decode,
+            nom::number::complete::le_u32,
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::multi::many_m_n(
+                    num_elems,
+                    num_elems,
+                    nom::combinator::map(nom::bytes::complete::take(65u32), |s| {
+                        <&[u8; 65]>::try_from(s).unwrap()
+                    }),
+                )
+            }),
+        )),
+        |(commitment, signatures_from, validator_set_len, signatures_compact)| {
+            let validator_set_len = usize::try_from(validator_set_len).ok()?;
+            if signatures_from.len() != validator_set_len.checked_add(7)? / 8 {
+                return None;
+            }
+
+            let mut signatures_compact = signatures_compact.into_iter();
+            let signatures = (0..validator_set_len)
+                .map(|n| {
+                    if signatures_from[n / 8] & (1 << (7 - (n % 8))) != 0 {
+                        signatures_compact.next().map(Some)
+                    } else {
+                        Some(None)
+                    }
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            // All the signatures must have been attributed to a validator.
+            if signatures_compact.next().is_some() {
+                return None;
+            }
+
+            Some(SignedCommitmentRef {
+                commitment,
+                signatures,
+            })
+        },
+    )
+}
+
+pub(crate) fn commitment<'a>(
+    block_number_bytes: usize,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], CommitmentRef<'a>> {
+    nom::combinator::map(
+        nom::combinator::consumed(nom::sequence::tuple((
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::multi::many_m_n(
+                    num_elems,
+                    num_elems,
+                    nom::sequence::tuple((
+                        nom::combinator::map(nom::bytes::complete::take(2u32), |id| {
+                            <[u8; 2]>::try_from(id).unwrap()
+                        }),
+                        crate::util::nom_bytes_decode,
+                    )),
+                )
+            }),
+            crate::util::nom_varsize_number_decode_u64(block_number_bytes),
+            nom::number::complete::le_u64,
+        ))),
+        |(scale_encoded, (payload, block_number, validator_set_id))| CommitmentRef {
+            payload,
+            block_number,
+            validator_set_id,
+            scale_encoded,
+        },
+    )
+}
+
+fn consensus_log(bytes: &[u8]) -> nom::IResult<&[u8], ConsensusLogRef> {
+    nom::branch::alt((
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[1]), validator_set),
+            ConsensusLogRef::AuthoritiesChange,
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[2]),
+                nom::number::complete::le_u32,
+            ),
+            ConsensusLogRef::OnDisabled,
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[3]),
+                nom::bytes::complete::take(32u32),
+            ),
+            |root| ConsensusLogRef::MmrRoot(<&[u8; 32]>::try_from(root).unwrap()),
+        ),
+    ))(bytes)
+}
+
+fn validator_set(bytes: &[u8]) -> nom::IResult<&[u8], ValidatorSetRef> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::multi::many_m_n(
+                    num_elems,
+                    num_elems,
+                    nom::combinator::map(nom::bytes::complete::take(33u32), |k| {
+                        <&[u8; 33]>::try_from(k).unwrap()
+                    }),
+                )
+            }),
+            nom::number::complete::le_u64,
+        )),
+        |(validators, id)| ValidatorSetRef { validators, id },
+    )(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Builds a SCALE-encoded commitment whose payload contains an MMR root.
+    fn build_commitment(block_number: u32, validator_set_id: u64) -> Vec<u8> {
+        let mut out = vec![1 << 2];
+        out.extend_from_slice(&MMR_ROOT_PAYLOAD_ID);
+        out.push(32 << 2);
+        out.extend_from_slice(&[0xab; 32]);
+        out.extend_from_slice(&block_number.to_le_bytes());
+        out.extend_from_slice(&validator_set_id.to_le_bytes());
+        out
+    }
+
+    fn keys(num: u8) -> Vec<(libsecp256k1::SecretKey, [u8; 33])> {
+        (1..=num)
+            .map(|n| {
+                let secret = libsecp256k1::SecretKey::parse(&[n; 32]).unwrap();
+                let public = libsecp256k1::PublicKey::from_secret_key(&secret);
+                (secret, public.serialize_compressed())
+            })
+            .collect()
+    }
+
+    fn sign(commitment: &[u8], secret: &libsecp256k1::SecretKey) -> [u8; 65] {
+        let mut hash = [0; 32];
+        let mut keccak = tiny_keccak::Keccak::v256();
+        keccak.update(commitment);
+        keccak.finalize(&mut hash);
+        let (sig, recovery_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(&hash), secret);
+        let mut out = [0; 65];
+        out[..64].copy_from_slice(&sig.serialize());
+        out[64] = recovery_id.serialize();
+        out
+    }
+
+    #[test]
+    fn decode_and_verify() {
+        let validators = keys(4);
+        let commitment = build_commitment(1234, 5);
+
+        let signatures = vec![
+            Some(sign(&commitment, &validators[0].0)),
+            None,
+            Some(sign(&commitment, &validators[2].0)),
+            Some(sign(&commitment, &validators[3].0)),
+        ];
+
+        let mut encoded = vec![1];
+        encoded.extend_from_slice(&commitment);
+        encoded.extend_from_slice(&[1 << 2, 0b1011_0000]);
+        encoded.extend_from_slice(&4u32.to_le_bytes());
+        encoded.push(3 << 2);
+        for sig in signatures.iter().flatten() {
+            encoded.extend_from_slice(sig);
+        }
+
+        let decoded = decode_versioned_finality_proof(&encoded, 4).unwrap();
+        assert_eq!(decoded.commitment.block_number, 1234);
+        assert_eq!(decoded.commitment.validator_set_id, 5);
+        assert_eq!(decoded.commitment.mmr_root(), Some(&[0xab; 32]));
+        assert_eq!(decoded.commitment.scale_encoding(), &commitment[..]);
+        assert_eq!(
+            decoded.signatures,
+            signatures.iter().map(|s| s.as_ref()).collect::<Vec<_>>()
+        );
+
+        let reencoded = encode_versioned_finality_proof(&decoded).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(reencoded, encoded);
+
+        verify(VerifyConfig {
+            signed_commitment: &decoded,
+            validator_set_id: 5,
+            validators: validators.iter().map(|(_, public)| public),
+        })
+        .unwrap();
+
+        assert!(matches!(
+            verify(VerifyConfig {
+                signed_commitment: &decoded,
+                validator_set_id: 6,
+                validators: validators.iter().map(|(_, public)| public),
+            }),
+            Err(Error::BadValidatorSetId)
+        ));
+
+        let mut swapped = validators.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+        swapped.swap(0, 2);
+        assert!(matches!(
+            verify(VerifyConfig {
+                signed_commitment: &decoded,
+                validator_set_id: 5,
+                validators: swapped.iter(),
+            }),
+            Err(Error::BadSignature(_))
+        ));
+    }
+
+    #[test]
+    fn not_enough_signatures() {
+        let validators = keys(4);
+        let commitment = build_commitment(1, 0);
+
+        let mut encoded = commitment.clone();
+        encoded.extend_from_slice(&[1 << 2, 0b1100_0000]);
+        encoded.extend_from_slice(&4u32.to_le_bytes());
+        encoded.push(2 << 2);
+        encoded.extend_from_slice(&sign(&commitment, &validators[0].0));
+        encoded.extend_from_slice(&sign(&commitment, &validators[1].0));
+
+        let decoded = decode_signed_commitment(&encoded, 4).unwrap();
+        assert!(matches!(
+            verify(VerifyConfig {
+                signed_commitment: &decoded,
+                validator_set_id: 0,
+                validators: validators.iter().map(|(_, public)| public),
+            }),
+            Err(Error::NotEnoughSignatures)
+        ));
+    }
+
+    #[test]
+    fn signatures_count_mismatch() {
+        let commitment = build_commitment(1, 0);
+
+        // The bit field indicates three signatures, but only two are present.
+        let mut encoded = commitment;
+        encoded.extend_from_slice(&[1 << 2, 0b1110_0000]);
+        encoded.extend_from_slice(&3u32.to_le_bytes());
+        encoded.push(2 << 2);
+        encoded.extend_from_slice(&[0; 130]);
+
+        assert!(decode_signed_commitment(&encoded, 4).is_err());
+    }
+
+    #[test]
+    fn decode_consensus_log_authorities_change() {
+        let validators = keys(2);
+        let mut encoded = vec![1, 2 << 2];
+        encoded.extend_from_slice(&validators[0].1);
+        encoded.extend_from_slice(&validators[1].1);
+        encoded.extend_from_slice(&7u64.to_le_bytes());
+
+        assert_eq!(
+            decode_consensus_log(&encoded).unwrap(),
+            ConsensusLogRef::AuthoritiesChange(ValidatorSetRef {
+                validators: vec![&validators[0].1, &validators[1].1],
+                id: 7,
+            })
+        );
+    }
+}
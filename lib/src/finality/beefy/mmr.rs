@@ -0,0 +1,755 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Merkle Mountain Range (MMR) proofs.
+//!
+//! Chains that use BEEFY maintain, in their runtime, a Merkle Mountain Range containing one leaf
+//! per block. The root of this MMR is what BEEFY validators sign (see
+//! [`super::CommitmentRef::mmr_root`]). An MMR proof makes it possible to prove that a certain
+//! leaf is part of the MMR whose root is known.
+//!
+//! # Overview
+//!
+//! An MMR is a list of perfect binary Merkle trees, called *peaks*, of strictly decreasing
+//! heights. The nodes of the MMR are numbered in post-order: the children of a node are always
+//! numbered before their parent, and the nodes of a peak are always numbered before the nodes
+//! of the peaks on its right.
+//!
+//! The root of the MMR is obtained by *bagging* the peaks: starting from the right, the two
+//! right-most hashes are replaced with the hash of their concatenation (the right-most first),
+//! until only one hash remains.
+//!
+//! Leaves are opaque from the point of view of this module. The hash of a leaf is the hash of
+//! its content, and the hash of a node is the hash of the concatenation of its two children.
+//! Only chains that use Keccak-256 as their MMR hashing algorithm, which is the case of all the
+//! chains where BEEFY is used in practice, are supported.
+//!
+//! # Runtime API
+//!
+//! The light client doesn't store the MMR. Proofs are generated by calling the
+//! `MmrApi_generate_proof` runtime function, and the root of the MMR is obtained through the
+//! `MmrApi_mmr_root` runtime function. See [`decode_generate_proof_output`] and
+//! [`decode_mmr_root_output`].
+
+use alloc::vec::Vec;
+use tiny_keccak::Hasher as _;
+
+/// Builds the parameters to pass to the `MmrApi_generate_proof` runtime function.
+///
+/// Returns `None` if one of the block numbers doesn't fit in `block_number_bytes` bytes.
+pub fn generate_proof_parameters(
+    block_numbers: &[u64],
+    best_known_block_number: Option<u64>,
+    block_number_bytes: usize,
+) -> Option<Vec<u8>> {
+    let encode_number = |out: &mut Vec<u8>, number: u64| {
+        let bytes = number.to_le_bytes();
+        if bytes.iter().skip(block_number_bytes).any(|b| *b != 0) {
+            return None;
+        }
+        out.extend_from_slice(&bytes[..block_number_bytes.min(8)]);
+        out.resize(out.len() + block_number_bytes.saturating_sub(8), 0);
+        Some(())
+    };
+
+    let mut out = Vec::with_capacity(5 + (block_numbers.len() + 2) * block_number_bytes);
+    out.extend_from_slice(crate::util::encode_scale_compact_usize(block_numbers.len()).as_ref());
+    for number in block_numbers {
+        encode_number(&mut out, *number)?;
+    }
+    match best_known_block_number {
+        Some(number) => {
+            out.push(1);
+            encode_number(&mut out, number)?;
+        }
+        None => out.push(0),
+    }
+    Some(out)
+}
+
+/// Attempt to decode a SCALE-encoded MMR leaf proof.
+pub fn decode_leaf_proof(scale_encoded: &[u8]) -> Result<LeafProofRef, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(leaf_proof))(scale_encoded) {
+        Ok((_, proof)) => Ok(proof),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode a SCALE-encoded list of opaque MMR leaves.
+pub fn decode_leaves(scale_encoded: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(leaves))(scale_encoded) {
+        Ok((_, leaves)) => Ok(leaves),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode the output of the `MmrApi_generate_proof` runtime function.
+pub fn decode_generate_proof_output(
+    scale_encoded: &[u8],
+) -> Result<Result<GenerateProofOutput, RuntimeError>, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(nom::branch::alt((
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[0]),
+                nom::sequence::tuple((
+                    nom::combinator::consumed(leaves),
+                    nom::combinator::consumed(leaf_proof),
+                )),
+            ),
+            |((scale_encoded_leaves, leaves), (scale_encoded_proof, proof))| {
+                Ok(GenerateProofOutput {
+                    leaves,
+                    scale_encoded_leaves,
+                    proof,
+                    scale_encoded_proof,
+                })
+            },
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[1]), runtime_error),
+            Err,
+        ),
+    ))))(scale_encoded)
+    {
+        Ok((_, output)) => Ok(output),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode the output of the `MmrApi_mmr_root` runtime function.
+pub fn decode_mmr_root_output(
+    scale_encoded: &[u8],
+) -> Result<Result<&[u8; 32], RuntimeError>, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(nom::branch::alt((
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[0]),
+                nom::bytes::complete::take(32u32),
+            ),
+            |root| Ok(<&[u8; 32]>::try_from(root).unwrap()),
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[1]), runtime_error),
+            Err,
+        ),
+    ))))(scale_encoded)
+    {
+        Ok((_, output)) => Ok(output),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Attempt to decode the output of the `MmrApi_verify_proof` runtime function.
+pub fn decode_verify_proof_output(scale_encoded: &[u8]) -> Result<Result<(), RuntimeError>, Error> {
+    match nom::combinator::all_consuming(nom::combinator::complete(nom::branch::alt((
+        nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| Ok(())),
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[1]), runtime_error),
+            Err,
+        ),
+    ))))(scale_encoded)
+    {
+        Ok((_, output)) => Ok(output),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error::Decode(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Decoded MMR leaf proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafProofRef<'a> {
+    /// Indices of the leaves the proof is for.
+    pub leaf_indices: Vec<u64>,
+    /// Number of leaves in the MMR when the proof was generated.
+    pub leaf_count: u64,
+    /// Hashes of the nodes of the MMR that are necessary in order to calculate the root.
+    pub items: Vec<&'a [u8; 32]>,
+}
+
+/// Decoded successful output of the `MmrApi_generate_proof` runtime function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerateProofOutput<'a> {
+    /// Content of the leaves that have been requested, in the same order as
+    /// [`LeafProofRef::leaf_indices`].
+    pub leaves: Vec<&'a [u8]>,
+    /// SCALE encoding of [`GenerateProofOutput::leaves`].
+    pub scale_encoded_leaves: &'a [u8],
+    /// Proof of the leaves.
+    pub proof: LeafProofRef<'a>,
+    /// SCALE encoding of [`GenerateProofOutput::proof`].
+    pub scale_encoded_proof: &'a [u8],
+}
+
+/// Error returned by the MMR-related runtime functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum RuntimeError {
+    /// Error during the calculation of a leaf or block index.
+    InvalidNumericOp,
+    /// Error while pushing a new node.
+    Push,
+    /// Error while calculating the root of the MMR.
+    GetRoot,
+    /// Error while committing the changes to the MMR.
+    Commit,
+    /// Error during the generation of a proof.
+    GenerateProof,
+    /// Error during the verification of a proof.
+    Verify,
+    /// Leaf not found in the storage.
+    LeafNotFound,
+    /// MMR pallet not included in the runtime.
+    PalletNotIncluded,
+    /// Cannot find the requested leaf index.
+    InvalidLeafIndex,
+    /// The provided best known block number is invalid.
+    InvalidBestKnownBlock,
+    /// Error unknown to this implementation.
+    #[display(fmt = "Unknown error #{_0}")]
+    Unknown(u8),
+}
+
+/// Configuration for an MMR proof verification process.
+#[derive(Debug)]
+pub struct VerifyConfig<'a, I> {
+    /// Expected root of the MMR.
+    pub root: &'a [u8; 32],
+
+    /// Content of the leaves to verify, in the same order as [`LeafProofRef::leaf_indices`].
+    /// Must implement `ExactSizeIterator<Item = &[u8]>`.
+    pub leaves: I,
+
+    /// Proof of the leaves.
+    pub proof: &'a LeafProofRef<'a>,
+}
+
+/// Verifies that the given leaves are part of the MMR whose root is provided.
+pub fn verify<'a>(
+    config: VerifyConfig<'_, impl ExactSizeIterator<Item = &'a [u8]>>,
+) -> Result<(), Error> {
+    if config.leaves.len() != config.proof.leaf_indices.len() {
+        return Err(Error::LeavesCountMismatch);
+    }
+
+    if config.proof.leaf_indices.is_empty() {
+        return Err(Error::NoLeaves);
+    }
+
+    // Since the leaf indices are inferior to the number of leaves, calculating their positions
+    // can't overflow if calculating the size of the MMR doesn't.
+    // If the MMR is empty, all the leaf indices are necessarily out of range.
+    let mmr_size = leaf_index_to_mmr_size(
+        config
+            .proof
+            .leaf_count
+            .checked_sub(1)
+            .ok_or(Error::LeafIndexOutOfRange)?,
+    )
+    .ok_or(Error::LeafCountTooLarge)?;
+
+    // Positions and hashes of the leaves, ordered by position.
+    let mut leaves = Vec::with_capacity(config.leaves.len());
+    for (leaf, leaf_index) in config.leaves.zip(config.proof.leaf_indices.iter()) {
+        if *leaf_index >= config.proof.leaf_count {
+            return Err(Error::LeafIndexOutOfRange);
+        }
+
+        leaves.push((leaf_index_to_pos(*leaf_index).unwrap(), keccak_256(&[leaf])));
+    }
+    leaves.sort_unstable_by_key(|(pos, _)| *pos);
+
+    // The same leaf can be provided multiple times, but always with the same content.
+    if leaves
+        .windows(2)
+        .any(|pair| pair[0].0 == pair[1].0 && pair[0].1 != pair[1].1)
+    {
+        return Err(Error::ConflictingLeaves);
+    }
+    leaves.dedup_by_key(|(pos, _)| *pos);
+
+    let mut proof_items = config.proof.items.iter().map(|item| **item);
+
+    // Calculate the hash of each peak of the MMR.
+    let mut peaks_hashes = Vec::new();
+    let mut leaves = &leaves[..];
+    for peak_pos in peaks(mmr_size) {
+        let num_leaves_in_peak = leaves
+            .iter()
+            .take_while(|(pos, _)| *pos <= peak_pos)
+            .count();
+        let (leaves_in_peak, rest) = leaves.split_at(num_leaves_in_peak);
+        leaves = rest;
+
+        let peak_hash = match leaves_in_peak {
+            [(pos, hash)] if *pos == peak_pos => *hash,
+            [] => match proof_items.next() {
+                Some(hash) => hash,
+                // All the remaining peaks have been bagged together in the proof.
+                None => break,
+            },
+            _ => peak_root(leaves_in_peak, peak_pos, &mut proof_items)?,
+        };
+
+        peaks_hashes.push(peak_hash);
+    }
+
+    if !leaves.is_empty() {
+        return Err(Error::CorruptedProof);
+    }
+
+    // The peaks on the right of the right-most leaf might have been bagged together.
+    if let Some(rhs_peaks) = proof_items.next() {
+        peaks_hashes.push(rhs_peaks);
+    }
+    if proof_items.next().is_some() {
+        return Err(Error::CorruptedProof);
+    }
+
+    // Bag the peaks, from right to left.
+    while peaks_hashes.len() > 1 {
+        let right = peaks_hashes.pop().unwrap();
+        let left = peaks_hashes.pop().unwrap();
+        peaks_hashes.push(keccak_256(&[&right, &left]));
+    }
+
+    match peaks_hashes.pop() {
+        Some(root) if root == *config.root => Ok(()),
+        Some(_) => Err(Error::RootMismatch),
+        None => Err(Error::CorruptedProof),
+    }
+}
+
+/// Error that can happen while decoding or verifying an MMR proof.
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Failed to decode the proof.
+    #[display(fmt = "Failed to decode: {_0:?}")]
+    Decode(nom::error::ErrorKind),
+    /// Number of leaves doesn't match the number of leaf indices of the proof.
+    LeavesCountMismatch,
+    /// Proof doesn't concern any leaf.
+    NoLeaves,
+    /// Leaf index is superior or equal to the number of leaves of the MMR.
+    LeafIndexOutOfRange,
+    /// Number of leaves of the MMR is too large to be supported.
+    LeafCountTooLarge,
+    /// The same leaf index has been provided multiple times with different contents.
+    ConflictingLeaves,
+    /// Proof doesn't contain the expected number of items.
+    CorruptedProof,
+    /// Root calculated from the proof doesn't match the expected root.
+    RootMismatch,
+}
+
+/// Calculates the hash of the peak at position `peak_pos` given a non-empty list of leaves
+/// below this peak, ordered by position.
+fn peak_root(
+    leaves: &[(u64, [u8; 32])],
+    peak_pos: u64,
+    proof_items: &mut impl Iterator<Item = [u8; 32]>,
+) -> Result<[u8; 32], Error> {
+    debug_assert!(!leaves.is_empty());
+
+    // Queue of `(position, hash, height)`, ordered by height then by position.
+    let mut queue = leaves
+        .iter()
+        .map(|(pos, hash)| (*pos, *hash, 0))
+        .collect::<alloc::collections::VecDeque<_>>();
+
+    while let Some((pos, hash, height)) = queue.pop_front() {
+        if pos == peak_pos {
+            return if queue.is_empty() {
+                Ok(hash)
+            } else {
+                Err(Error::CorruptedProof)
+            };
+        }
+
+        // The node at `pos + 1` is higher than `pos` only if `pos` is a right child.
+        let is_right_child = pos_height(pos + 1) > height;
+        let (sibling_pos, parent_pos) = if is_right_child {
+            (pos - sibling_offset(height), pos + 1)
+        } else {
+            (pos + sibling_offset(height), pos + parent_offset(height))
+        };
+
+        let sibling_hash = if queue.front().map(|(pos, _, _)| *pos) == Some(sibling_pos) {
+            queue.pop_front().unwrap().1
+        } else {
+            proof_items.next().ok_or(Error::CorruptedProof)?
+        };
+
+        let parent_hash = if is_right_child {
+            keccak_256(&[&sibling_hash, &hash])
+        } else {
+            keccak_256(&[&hash, &sibling_hash])
+        };
+
+        if parent_pos > peak_pos {
+            return Err(Error::CorruptedProof);
+        }
+
+        queue.push_back((parent_pos, parent_hash, height + 1));
+    }
+
+    Err(Error::CorruptedProof)
+}
+
+/// Returns the position of the node corresponding to the leaf with the given index.
+///
+/// Returns `None` in case of overflow.
+fn leaf_index_to_pos(index: u64) -> Option<u64> {
+    // The MMR contains at least one node, the leaf itself, and its size is thus never 0.
+    Some(leaf_index_to_mmr_size(index)? - u64::from((index + 1).trailing_zeros()) - 1)
+}
+
+/// Returns the number of nodes of an MMR whose last leaf has the given index.
+///
+/// Returns `None` in case of overflow.
+fn leaf_index_to_mmr_size(index: u64) -> Option<u64> {
+    let leaves_count = index.checked_add(1)?;
+    Some(leaves_count.checked_mul(2)? - u64::from(leaves_count.count_ones()))
+}
+
+/// Returns the height of the node at the given position, leaves having a height of 0.
+fn pos_height(pos: u64) -> u32 {
+    let mut pos = pos + 1;
+    // `pos` is the position of a peak of a perfect tree if it is only made of ones.
+    while pos.count_zeros() != pos.leading_zeros() {
+        let most_significant_bit = 1 << (63 - pos.leading_zeros());
+        pos -= most_significant_bit - 1;
+    }
+    63 - pos.leading_zeros()
+}
+
+/// Returns the offset between a node of the given height and its parent, if it is a left child.
+fn parent_offset(height: u32) -> u64 {
+    2 << height
+}
+
+/// Returns the offset between a node of the given height and its sibling.
+fn sibling_offset(height: u32) -> u64 {
+    (2 << height) - 1
+}
+
+/// Returns the positions of the peaks of an MMR of the given size, from left to right.
+fn peaks(mmr_size: u64) -> Vec<u64> {
+    let mut out = Vec::new();
+    if mmr_size == 0 {
+        return out;
+    }
+
+    // Find the highest perfect tree that fits in the MMR. Its root is the left-most peak.
+    let mut height = 0;
+    while 1u64
+        .checked_shl(height + 2)
+        .map_or(false, |size| size - 2 < mmr_size)
+    {
+        height += 1;
+    }
+    let mut pos = (1u64 << (height + 1)) - 2;
+    out.push(pos);
+
+    // Find the next peaks by moving to the right sibling, then descending to the left child
+    // until the position is within the MMR.
+    while height > 0 {
+        pos += sibling_offset(height);
+        while pos > mmr_size - 1 {
+            if height == 0 {
+                return out;
+            }
+            pos -= parent_offset(height - 1);
+            height -= 1;
+        }
+        out.push(pos);
+    }
+
+    out
+}
+
+fn keccak_256(data: &[&[u8]]) -> [u8; 32] {
+    let mut keccak = tiny_keccak::Keccak::v256();
+    for data in data {
+        keccak.update(data);
+    }
+    let mut out = [0; 32];
+    keccak.finalize(&mut out);
+    out
+}
+
+fn leaf_proof(bytes: &[u8]) -> nom::IResult<&[u8], LeafProofRef> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::multi::many_m_n(num_elems, num_elems, nom::number::complete::le_u64)
+            }),
+            nom::number::complete::le_u64,
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::multi::many_m_n(
+                    num_elems,
+                    num_elems,
+                    nom::combinator::map(nom::bytes::complete::take(32u32), |h| {
+                        <&[u8; 32]>::try_from(h).unwrap()
+                    }),
+                )
+            }),
+        )),
+        |(leaf_indices, leaf_count, items)| LeafProofRef {
+            leaf_indices,
+            leaf_count,
+            items,
+        },
+    )(bytes)
+}
+
+fn leaves(bytes: &[u8]) -> nom::IResult<&[u8], Vec<&[u8]>> {
+    nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+        nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode)
+    })(bytes)
+}
+
+fn runtime_error(bytes: &[u8]) -> nom::IResult<&[u8], RuntimeError> {
+    nom::combinator::map(nom::number::complete::u8, |index| match index {
+        0 => RuntimeError::InvalidNumericOp,
+        1 => RuntimeError::Push,
+        2 => RuntimeError::GetRoot,
+        3 => RuntimeError::Commit,
+        4 => RuntimeError::GenerateProof,
+        5 => RuntimeError::Verify,
+        6 => RuntimeError::LeafNotFound,
+        7 => RuntimeError::PalletNotIncluded,
+        8 => RuntimeError::InvalidLeafIndex,
+        9 => RuntimeError::InvalidBestKnownBlock,
+        n => RuntimeError::Unknown(n),
+    })(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn peaks_positions() {
+        assert_eq!(peaks(1), [0]);
+        assert_eq!(peaks(3), [2]);
+        assert_eq!(peaks(4), [2, 3]);
+        assert_eq!(peaks(7), [6]);
+        assert_eq!(peaks(8), [6, 7]);
+        assert_eq!(peaks(10), [6, 9]);
+        assert_eq!(peaks(11), [6, 9, 10]);
+    }
+
+    #[test]
+    fn leaf_positions() {
+        assert_eq!(leaf_index_to_pos(0), Some(0));
+        assert_eq!(leaf_index_to_pos(1), Some(1));
+        assert_eq!(leaf_index_to_pos(2), Some(3));
+        assert_eq!(leaf_index_to_pos(3), Some(4));
+        assert_eq!(leaf_index_to_pos(4), Some(7));
+        assert_eq!(leaf_index_to_pos(5), Some(8));
+        assert_eq!(leaf_index_to_pos(6), Some(10));
+    }
+
+    #[test]
+    fn verify_three_leaves() {
+        // MMR with three leaves. Nodes 0, 1 and 3 are leaves, and node 2 is the parent of
+        // nodes 0 and 1.
+        let leaves: [&[u8]; 3] = [b"leaf0", b"leaf1", b"leaf2"];
+        let hash0 = keccak_256(&[leaves[0]]);
+        let hash1 = keccak_256(&[leaves[1]]);
+        let hash3 = keccak_256(&[leaves[2]]);
+        let hash2 = keccak_256(&[&hash0, &hash1]);
+        let root = keccak_256(&[&hash3, &hash2]);
+
+        // Proof of the first leaf.
+        let proof = LeafProofRef {
+            leaf_indices: vec![0],
+            leaf_count: 3,
+            items: vec![&hash1, &hash3],
+        };
+        verify(VerifyConfig {
+            root: &root,
+            leaves: [leaves[0]].into_iter(),
+            proof: &proof,
+        })
+        .unwrap();
+        assert!(matches!(
+            verify(VerifyConfig {
+                root: &root,
+                leaves: [leaves[1]].into_iter(),
+                proof: &proof,
+            }),
+            Err(Error::RootMismatch)
+        ));
+
+        // Proof of the last leaf.
+        let proof = LeafProofRef {
+            leaf_indices: vec![2],
+            leaf_count: 3,
+            items: vec![&hash2],
+        };
+        verify(VerifyConfig {
+            root: &root,
+            leaves: [leaves[2]].into_iter(),
+            proof: &proof,
+        })
+        .unwrap();
+
+        // Proof of the first two leaves.
+        let proof = LeafProofRef {
+            leaf_indices: vec![0, 1],
+            leaf_count: 3,
+            items: vec![&hash3],
+        };
+        verify(VerifyConfig {
+            root: &root,
+            leaves: [leaves[0], leaves[1]].into_iter(),
+            proof: &proof,
+        })
+        .unwrap();
+
+        // Too many proof items.
+        let proof = LeafProofRef {
+            leaf_indices: vec![2],
+            leaf_count: 3,
+            items: vec![&hash2, &hash2, &hash2],
+        };
+        assert!(matches!(
+            verify(VerifyConfig {
+                root: &root,
+                leaves: [leaves[2]].into_iter(),
+                proof: &proof,
+            }),
+            Err(Error::CorruptedProof)
+        ));
+    }
+
+    #[test]
+    fn verify_conflicting_leaves() {
+        let leaves: [&[u8]; 2] = [b"leaf0", b"leaf1"];
+        let hash0 = keccak_256(&[leaves[0]]);
+        let hash1 = keccak_256(&[leaves[1]]);
+        let root = keccak_256(&[&hash0, &hash1]);
+
+        // The same leaf provided twice with the same content is accepted.
+        let proof = LeafProofRef {
+            leaf_indices: vec![0, 0],
+            leaf_count: 2,
+            items: vec![&hash1],
+        };
+        verify(VerifyConfig {
+            root: &root,
+            leaves: [leaves[0], leaves[0]].into_iter(),
+            proof: &proof,
+        })
+        .unwrap();
+
+        // The same leaf provided twice with different contents is rejected.
+        assert!(matches!(
+            verify(VerifyConfig {
+                root: &root,
+                leaves: [leaves[0], leaves[1]].into_iter(),
+                proof: &proof,
+            }),
+            Err(Error::ConflictingLeaves)
+        ));
+    }
+
+    #[test]
+    fn verify_huge_leaf_count() {
+        let proof = LeafProofRef {
+            leaf_indices: vec![0],
+            leaf_count: u64::max_value(),
+            items: vec![],
+        };
+        assert!(matches!(
+            verify(VerifyConfig {
+                root: &[0; 32],
+                leaves: [&b"leaf0"[..]].into_iter(),
+                proof: &proof,
+            }),
+            Err(Error::LeafCountTooLarge)
+        ));
+
+        let proof = LeafProofRef {
+            leaf_indices: vec![0],
+            leaf_count: 0,
+            items: vec![],
+        };
+        assert!(matches!(
+            verify(VerifyConfig {
+                root: &[0; 32],
+                leaves: [&b"leaf0"[..]].into_iter(),
+                proof: &proof,
+            }),
+            Err(Error::LeafIndexOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let mut encoded = vec![0, 4, 8, 0xab, 0xcd];
+        encoded.extend_from_slice(&[4, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 4]);
+        encoded.extend_from_slice(&[0x11; 32]);
+
+        for len in 0..encoded.len() {
+            assert!(decode_generate_proof_output(&encoded[..len]).is_err());
+        }
+        for len in 0..4 {
+            assert!(decode_leaves(&encoded[1..(1 + len)]).is_err());
+        }
+        for len in 0..(encoded.len() - 5) {
+            assert!(decode_leaf_proof(&encoded[5..(5 + len)]).is_err());
+        }
+        assert!(decode_mmr_root_output(&[0; 20]).is_err());
+        assert!(decode_verify_proof_output(&[]).is_err());
+    }
+
+    #[test]
+    fn generate_proof_parameters_encoding() {
+        assert_eq!(
+            generate_proof_parameters(&[1, 0x102], Some(5), 4).unwrap(),
+            [8, 1, 0, 0, 0, 2, 1, 0, 0, 1, 5, 0, 0, 0]
+        );
+        assert_eq!(generate_proof_parameters(&[], None, 4).unwrap(), [0, 0]);
+        assert!(generate_proof_parameters(&[1 << 32], None, 4).is_none());
+    }
+
+    #[test]
+    fn decode_generate_proof_output_ok() {
+        let mut encoded = vec![0, 4, 8, 0xab, 0xcd];
+        encoded.extend_from_slice(&[4, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 4]);
+        encoded.extend_from_slice(&[0x11; 32]);
+
+        let output = decode_generate_proof_output(&encoded).unwrap().unwrap();
+        assert_eq!(output.leaves, [&[0xab, 0xcd][..]]);
+        assert_eq!(output.scale_encoded_leaves, &encoded[1..5]);
+        assert_eq!(output.proof.leaf_indices, [2]);
+        assert_eq!(output.proof.leaf_count, 3);
+        assert_eq!(output.proof.items, [&[0x11; 32]]);
+        assert_eq!(output.scale_encoded_proof, &encoded[5..]);
+
+        assert_eq!(
+            decode_generate_proof_output(&[1, 7]).unwrap(),
+            Err(RuntimeError::PalletNotIncluded)
+        );
+    }
+}
@@ -0,0 +1,454 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Detection of GrandPa equivocations.
+//!
+//! During each round of GrandPa, each authority is supposed to emit at most one pre-vote and
+//! one pre-commit. An authority that signs two different pre-votes or two different pre-commits
+//! during the same round of the same authorities set is said to *equivocate*. Equivocations can
+//! be reported to the chain, which punishes the authority that has equivocated.
+//!
+//! The [`Detector`] keeps track of the votes that have been seen, for example in GrandPa commit
+//! messages or justifications, and reports equivocations when they are detected.
+//!
+//! The votes passed to the [`Detector`] are assumed to have been verified beforehand, for example
+//! with [`verify_vote`]. Otherwise, an attacker could make the [`Detector`] report equivocations
+//! that the chain would consider invalid.
+//!
+//! Once an equivocation has been detected, it can be reported to the chain by submitting an
+//! unsigned transaction built with [`report_equivocation_unsigned_extrinsic`]. This transaction
+//! requires a proof that the authority was part of the authorities set, which can be obtained by
+//! calling the `GrandpaApi_generate_key_ownership_proof` runtime function. See
+//! [`generate_key_ownership_proof_parameters`] and
+//! [`decode_generate_key_ownership_proof_output`].
+
+use crate::util;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{cmp, iter, mem};
+
+/// Kind of GrandPa vote.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VoteKind {
+    /// First step of a GrandPa round.
+    Prevote,
+    /// Second step of a GrandPa round. Pre-commits are found in commit messages and
+    /// justifications.
+    Precommit,
+}
+
+/// Vote signed by a GrandPa authority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedVote {
+    /// Hash of the block the vote is for.
+    pub target_hash: [u8; 32],
+    /// Height of the block the vote is for.
+    pub target_number: u64,
+    /// Ed25519 signature of the vote made by the authority.
+    pub signature: [u8; 64],
+}
+
+/// Two different votes of the same kind signed by the same authority during the same round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equivocation {
+    /// Identifier of the authorities set the authority belongs to.
+    pub set_id: u64,
+    /// Round during which the votes have been emitted.
+    pub round_number: u64,
+    /// Kind of both votes.
+    pub kind: VoteKind,
+    /// Public key of the authority that has equivocated.
+    pub authority_public_key: [u8; 32],
+    /// Vote that has been seen first.
+    pub first: SignedVote,
+    /// Vote that has been seen second. Always targets a different block than
+    /// [`Equivocation::first`].
+    pub second: SignedVote,
+}
+
+impl Equivocation {
+    /// Returns the SCALE encoding of the equivocation proof, as expected by the
+    /// `report_equivocation_unsigned` call of the GrandPa pallet.
+    pub fn scale_encoded_proof(&self, block_number_bytes: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + 1 + 8 + 32 + 2 * (32 + block_number_bytes + 64));
+        out.extend_from_slice(&self.set_id.to_le_bytes());
+        out.push(match self.kind {
+            VoteKind::Prevote => 0,
+            VoteKind::Precommit => 1,
+        });
+        out.extend_from_slice(&self.round_number.to_le_bytes());
+        out.extend_from_slice(&self.authority_public_key);
+        for vote in [&self.first, &self.second] {
+            out.extend_from_slice(&vote.target_hash);
+            out.extend(encode_block_number(vote.target_number, block_number_bytes));
+            out.extend_from_slice(&vote.signature);
+        }
+        out
+    }
+}
+
+/// Keeps track of the GrandPa votes that have been seen and detects equivocations.
+#[derive(Debug, Clone)]
+pub struct Detector {
+    /// List of votes that have been seen. Keys are the authorities set id, the round number,
+    /// the kind of vote, and the public key of the authority. Values are `None` if an
+    /// equivocation has already been reported for this key.
+    votes: BTreeMap<(u64, u64, VoteKind, [u8; 32]), Option<SignedVote>>,
+}
+
+impl Detector {
+    /// Initializes a new empty [`Detector`].
+    pub fn new() -> Self {
+        Detector {
+            votes: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of votes currently tracked.
+    pub fn len(&self) -> usize {
+        self.votes.len()
+    }
+
+    /// Returns `true` if no vote is currently tracked.
+    pub fn is_empty(&self) -> bool {
+        self.votes.is_empty()
+    }
+
+    /// Inserts a vote in the list of votes that have been seen.
+    ///
+    /// Returns an [`Equivocation`] if the authority has already emitted a vote of the same kind
+    /// during the same round for a different block. Only one equivocation is ever reported for
+    /// each authority, round, and kind of vote.
+    ///
+    /// The vote must have been verified beforehand, for example with [`verify_vote`].
+    pub fn insert_vote(
+        &mut self,
+        set_id: u64,
+        round_number: u64,
+        kind: VoteKind,
+        authority_public_key: &[u8; 32],
+        vote: SignedVote,
+    ) -> Option<Equivocation> {
+        let entry = self
+            .votes
+            .entry((set_id, round_number, kind, *authority_public_key))
+            .or_insert_with(|| Some(vote.clone()));
+
+        let is_equivocation = match entry {
+            Some(first) => {
+                first.target_hash != vote.target_hash || first.target_number != vote.target_number
+            }
+            None => false,
+        };
+        if !is_equivocation {
+            return None;
+        }
+
+        Some(Equivocation {
+            set_id,
+            round_number,
+            kind,
+            authority_public_key: *authority_public_key,
+            first: entry.take().unwrap(),
+            second: vote,
+        })
+    }
+
+    /// Removes all the votes of authorities sets strictly older than `set_id`, and all the votes
+    /// of rounds strictly older than `round_number` of the authorities set `set_id`.
+    ///
+    /// Must be called regularly, for example when a neighbor packet indicates that a more recent
+    /// round has started, in order to prevent the list of votes from growing forever.
+    pub fn prune(&mut self, set_id: u64, round_number: u64) {
+        self.votes = self
+            .votes
+            .split_off(&(set_id, round_number, VoteKind::Prevote, [0; 32]));
+    }
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configuration for [`verify_vote`].
+#[derive(Debug)]
+pub struct VerifyVoteConfig<'a> {
+    /// Identifier of the authorities set the authority belongs to.
+    pub set_id: u64,
+    /// Round during which the vote has been emitted.
+    pub round_number: u64,
+    /// Kind of vote.
+    pub kind: VoteKind,
+    /// Public key of the authority that has signed the vote.
+    pub authority_public_key: &'a [u8; 32],
+    /// Hash of the block the vote is for.
+    pub target_hash: &'a [u8; 32],
+    /// Height of the block the vote is for.
+    pub target_number: u64,
+    /// Signature to verify.
+    pub signature: &'a [u8; 64],
+    /// Number of bytes used to encode the block number in the header.
+    pub block_number_bytes: usize,
+}
+
+/// Verifies the signature of a GrandPa vote.
+///
+/// > **Note**: This function doesn't verify whether the authority belongs to the authorities set.
+pub fn verify_vote(config: VerifyVoteConfig) -> Result<(), BadSignatureError> {
+    let mut msg = Vec::with_capacity(1 + 32 + config.block_number_bytes + 8 + 8);
+    msg.push(match config.kind {
+        VoteKind::Prevote => 0u8,
+        VoteKind::Precommit => 1u8,
+    });
+    msg.extend_from_slice(&config.target_hash[..]);
+    msg.extend(encode_block_number(
+        config.target_number,
+        config.block_number_bytes,
+    ));
+    msg.extend_from_slice(&u64::to_le_bytes(config.round_number)[..]);
+    msg.extend_from_slice(&u64::to_le_bytes(config.set_id)[..]);
+    debug_assert_eq!(msg.len(), msg.capacity());
+
+    let public_key = ed25519_zebra::VerificationKey::try_from(&config.authority_public_key[..])
+        .map_err(|_| BadSignatureError)?;
+    public_key
+        .verify(&ed25519_zebra::Signature::from(*config.signature), &msg)
+        .map_err(|_| BadSignatureError)
+}
+
+/// Error potentially returned by [`verify_vote`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+#[display(fmt = "Invalid vote signature")]
+pub struct BadSignatureError;
+
+/// Returns the parameters to pass to the `GrandpaApi_generate_key_ownership_proof` runtime
+/// function in order to obtain the proof that the given authority belongs to the given
+/// authorities set.
+pub fn generate_key_ownership_proof_parameters(
+    set_id: u64,
+    authority_public_key: &[u8; 32],
+) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone {
+    [
+        either::Left(set_id.to_le_bytes()),
+        either::Right(*authority_public_key),
+    ]
+    .into_iter()
+}
+
+/// Decodes the output of the `GrandpaApi_generate_key_ownership_proof` runtime function.
+///
+/// Returns `None` if the runtime couldn't generate the proof, for example because the authority
+/// doesn't belong to the authorities set or because the authorities set is too old. Otherwise,
+/// returns the SCALE-encoded key ownership proof.
+pub fn decode_generate_key_ownership_proof_output(
+    scale_encoded: &[u8],
+) -> Result<Option<&[u8]>, DecodeError> {
+    let result: nom::IResult<_, _> =
+        nom::combinator::all_consuming(nom::combinator::complete(nom::branch::alt((
+            nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| None),
+            nom::combinator::map(
+                nom::sequence::preceded(nom::bytes::complete::tag(&[1]), util::nom_bytes_decode),
+                Some,
+            ),
+        ))))(scale_encoded);
+
+    match result {
+        Ok((_, proof)) => Ok(proof),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(DecodeError(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Error potentially returned by [`decode_generate_key_ownership_proof_output`].
+#[derive(Debug, derive_more::Display, Clone)]
+#[display(fmt = "Failed to decode key ownership proof: {_0:?}")]
+pub struct DecodeError(nom::error::ErrorKind);
+
+/// Builds the unsigned transaction that reports an equivocation to the chain.
+///
+/// `pallet_index` and `call_index` must be the index of the GrandPa pallet in the runtime and
+/// the index of the `report_equivocation_unsigned` call within this pallet.
+/// `scale_encoded_key_owner_proof` must be the proof returned by
+/// [`decode_generate_key_ownership_proof_output`].
+///
+/// The returned transaction is SCALE-encoded and can be submitted to the chain as is.
+pub fn report_equivocation_unsigned_extrinsic(
+    equivocation: &Equivocation,
+    block_number_bytes: usize,
+    pallet_index: u8,
+    call_index: u8,
+    scale_encoded_key_owner_proof: &[u8],
+) -> Vec<u8> {
+    // Version 4 of the extrinsics format, without signature.
+    let mut body = Vec::with_capacity(3 + 512 + scale_encoded_key_owner_proof.len());
+    body.extend_from_slice(&[0x04, pallet_index, call_index]);
+    body.extend_from_slice(&equivocation.scale_encoded_proof(block_number_bytes));
+    body.extend_from_slice(scale_encoded_key_owner_proof);
+
+    let length_prefix = util::encode_scale_compact_usize(body.len());
+    let mut out = Vec::with_capacity(length_prefix.as_ref().len() + body.len());
+    out.extend_from_slice(length_prefix.as_ref());
+    out.extend_from_slice(&body);
+    out
+}
+
+/// Encodes a block number in little endian, over `block_number_bytes` bytes.
+fn encode_block_number(
+    block_number: u64,
+    block_number_bytes: usize,
+) -> impl Iterator<Item = u8> + Clone {
+    // We don't know the number of bytes of this block number at compile time. We thus copy as
+    // many bytes as appropriate and pad with 0s if necessary.
+    let bytes = block_number.to_le_bytes();
+    bytes
+        .into_iter()
+        .take(cmp::min(
+            mem::size_of_val(&block_number),
+            block_number_bytes,
+        ))
+        .chain(
+            iter::repeat(0)
+                .take(block_number_bytes.saturating_sub(mem::size_of_val(&block_number))),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(target_hash: u8, signature: u8) -> SignedVote {
+        SignedVote {
+            target_hash: [target_hash; 32],
+            target_number: 5,
+            signature: [signature; 64],
+        }
+    }
+
+    #[test]
+    fn equivocation_reported_once() {
+        let mut detector = Detector::new();
+        let authority = [7; 32];
+
+        assert!(detector
+            .insert_vote(1, 10, VoteKind::Precommit, &authority, vote(1, 1))
+            .is_none());
+        assert!(detector
+            .insert_vote(1, 10, VoteKind::Precommit, &authority, vote(1, 1))
+            .is_none());
+        // Different kind of vote.
+        assert!(detector
+            .insert_vote(1, 10, VoteKind::Prevote, &authority, vote(2, 2))
+            .is_none());
+
+        let equivocation = detector
+            .insert_vote(1, 10, VoteKind::Precommit, &authority, vote(2, 2))
+            .unwrap();
+        assert_eq!(equivocation.first, vote(1, 1));
+        assert_eq!(equivocation.second, vote(2, 2));
+        assert_eq!(equivocation.authority_public_key, authority);
+
+        assert!(detector
+            .insert_vote(1, 10, VoteKind::Precommit, &authority, vote(3, 3))
+            .is_none());
+    }
+
+    #[test]
+    fn prune_removes_old_rounds() {
+        let mut detector = Detector::new();
+        for (set_id, round) in [(0, 50), (1, 9), (1, 10), (1, 11), (2, 0)] {
+            detector.insert_vote(set_id, round, VoteKind::Precommit, &[1; 32], vote(1, 1));
+        }
+        assert_eq!(detector.len(), 5);
+
+        detector.prune(1, 10);
+        assert_eq!(detector.len(), 3);
+
+        // The vote of round 10 is still known.
+        assert!(detector
+            .insert_vote(1, 10, VoteKind::Precommit, &[1; 32], vote(2, 2))
+            .is_some());
+        // The vote of round 9 has been forgotten.
+        assert!(detector
+            .insert_vote(1, 9, VoteKind::Precommit, &[1; 32], vote(2, 2))
+            .is_none());
+    }
+
+    #[test]
+    fn verify_vote_signature() {
+        let signing_key = ed25519_zebra::SigningKey::from([3; 32]);
+        let public_key: [u8; 32] = ed25519_zebra::VerificationKey::from(&signing_key).into();
+
+        let mut msg = vec![1u8];
+        msg.extend_from_slice(&[9; 32]);
+        msg.extend_from_slice(&12u32.to_le_bytes());
+        msg.extend_from_slice(&4u64.to_le_bytes());
+        msg.extend_from_slice(&2u64.to_le_bytes());
+        let signature: [u8; 64] = signing_key.sign(&msg).into();
+
+        let config = |target_number| VerifyVoteConfig {
+            set_id: 2,
+            round_number: 4,
+            kind: VoteKind::Precommit,
+            authority_public_key: &public_key,
+            target_hash: &[9; 32],
+            target_number,
+            signature: &signature,
+            block_number_bytes: 4,
+        };
+
+        assert!(verify_vote(config(12)).is_ok());
+        assert!(verify_vote(config(13)).is_err());
+    }
+
+    #[test]
+    fn key_ownership_proof_decode() {
+        assert_eq!(
+            decode_generate_key_ownership_proof_output(&[0]).unwrap(),
+            None
+        );
+        assert_eq!(
+            decode_generate_key_ownership_proof_output(&[1, 8, 0xaa, 0xbb]).unwrap(),
+            Some(&[0xaa, 0xbb][..])
+        );
+        assert!(decode_generate_key_ownership_proof_output(&[1, 8, 0xaa]).is_err());
+        assert!(decode_generate_key_ownership_proof_output(&[2]).is_err());
+    }
+
+    #[test]
+    fn extrinsic_encoding() {
+        let equivocation = Equivocation {
+            set_id: 1,
+            round_number: 2,
+            kind: VoteKind::Prevote,
+            authority_public_key: [3; 32],
+            first: vote(4, 5),
+            second: vote(6, 7),
+        };
+
+        let proof = equivocation.scale_encoded_proof(4);
+        assert_eq!(proof.len(), 8 + 1 + 8 + 32 + 2 * (32 + 4 + 64));
+        assert_eq!(proof[8], 0);
+
+        let extrinsic = report_equivocation_unsigned_extrinsic(&equivocation, 4, 11, 1, &[0xff]);
+        // Compact length prefix on two bytes.
+        assert_eq!(extrinsic.len(), 2 + 3 + proof.len() + 1);
+        assert_eq!(&extrinsic[2..5], &[0x04, 11, 1]);
+        assert_eq!(*extrinsic.last().unwrap(), 0xff);
+    }
+}
@@ -123,6 +123,17 @@ impl Verifier {
         }
     }
 
+    /// Returns the list of fragments passed at initialization.
+    pub fn fragments(&self) -> &[WarpSyncFragment] {
+        &self.fragments
+    }
+
+    /// Returns the fragment that the next call to [`Verifier::next`] will verify, or `None` if
+    /// the proof is empty.
+    pub fn next_fragment(&self) -> Option<&WarpSyncFragment> {
+        self.fragments.get(self.index)
+    }
+
     pub fn next(mut self, randomness_seed: [u8; 32]) -> Result<Next, Error> {
         if self.wrong_chain_algorithm {
             return Err(Error::WrongChainAlgorithm);
@@ -213,7 +224,7 @@ pub enum Next {
 }
 
 /// Fragment to be verified.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct WarpSyncFragment {
     /// Header of a block in the chain.
     pub scale_encoded_header: Vec<u8>,
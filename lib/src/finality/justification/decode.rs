@@ -50,7 +50,11 @@ pub fn decode_partial_grandpa(
     }
 }
 
-const PRECOMMIT_ENCODED_LEN: usize = 32 + 4 + 64 + 32;
+/// Number of bytes of a SCALE-encoded precommit, given the number of bytes used to encode
+/// block numbers.
+const fn precommit_encoded_len(block_number_bytes: usize) -> usize {
+    32 + block_number_bytes + 64 + 32
+}
 
 /// Decoded justification.
 // TODO: document and explain
@@ -131,11 +135,11 @@ impl<'a> PrecommitsRef<'a> {
                 data,
                 block_number_bytes,
             } => {
-                debug_assert_eq!(data.len() % PRECOMMIT_ENCODED_LEN, 0);
+                debug_assert_eq!(data.len() % precommit_encoded_len(block_number_bytes), 0);
                 PrecommitsRefIter {
                     inner: PrecommitsRefIterInner::Undecoded {
                         block_number_bytes,
-                        remaining_len: data.len() / PRECOMMIT_ENCODED_LEN,
+                        remaining_len: data.len() / precommit_encoded_len(block_number_bytes),
                         pointer: data,
                     },
                 }
@@ -463,4 +467,29 @@ mod tests {
         )
         .unwrap();
     }
+
+    #[test]
+    fn precommits_with_large_block_numbers() {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&5u64.to_le_bytes());
+        encoded.extend_from_slice(&[1; 32]);
+        encoded.extend_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        encoded.push(2 << 2);
+        for n in 0..2u8 {
+            encoded.extend_from_slice(&[2 + n; 32]);
+            encoded.extend_from_slice(&(0x1_0000_0000u64 + u64::from(n)).to_le_bytes());
+            encoded.extend_from_slice(&[4; 64]);
+            encoded.extend_from_slice(&[5 + n; 32]);
+        }
+        encoded.push(0);
+
+        let decoded = super::decode_grandpa(&encoded, 8).unwrap();
+        assert_eq!(decoded.target_number, 0x1_0000_0000);
+
+        let precommits = decoded.precommits.iter().collect::<Vec<_>>();
+        assert_eq!(precommits.len(), 2);
+        assert_eq!(*precommits[1].target_hash, [3; 32]);
+        assert_eq!(precommits[1].target_number, 0x1_0000_0001);
+        assert_eq!(*precommits[1].authority_public_key, [6; 32]);
+    }
 }
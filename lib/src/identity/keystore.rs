@@ -21,8 +21,9 @@
 //! `&mut self`, making it possible to share it through an `Arc` for example) containing a list of
 //! cryptographic key pairs (i.e. both the public and secret keys).
 //!
-//! Each key pair contained within the keystore is identified as a `(KeyNamespace, [u8; 32])`
-//! tuple, where the `[u8; 32]` is the public key. See [`KeyNamespace`].
+//! Each key pair contained within the keystore is identified as a `(KeyNamespace, Vec<u8>)`
+//! tuple, where the `Vec<u8>` is the public key. Public keys are 32 bytes long, except for ECDSA
+//! public keys which are 33 bytes long. See [`KeyNamespace`] and [`KeyAlgorithm`].
 //!
 //! A keystore is optionally associated with a directory of the file system into which it will
 //! store secret keys permanently. Keys present in this directory are considered to be the content
@@ -33,6 +34,20 @@
 //! Similarly, it is not intended to be possible to create two [`Keystore`] instances associated
 //! to the same directory at the same time.
 //!
+//! If a passphrase is passed to [`Keystore::new`], the secret keys written to the directory are
+//! encrypted using a key derived from this passphrase. Keys that were written without a
+//! passphrase can still be loaded, but encrypted keys can only be loaded if the same passphrase
+//! is provided. The keys found in the directory are loaded and decrypted only once, when the
+//! keystore is created.
+//!
+//! # Session keys
+//!
+//! Validators of a chain must register on chain their so-called *session keys*, which consist in
+//! the concatenation of the public keys that they use for the various consensus-related
+//! activities. The list of these keys is defined by the runtime of the chain, which generates
+//! them by calling the key generation host functions. See [`Keystore::generate`] and
+//! [`Keystore::has_session_keys`].
+//!
 //! > **Note**: The Substrate framework also has a keystore, however this keystore implementation
 //! >           isn't compatible with the Substrate keystore implementation. In other words, this
 //! >           keystore cannot load keys found in a directory that was previously associated with
@@ -45,9 +60,10 @@
 
 use crate::{identity::seed_phrase, util::SipHasherBuild};
 
+use chacha20poly1305::aead::{Aead as _, NewAead as _};
 use futures::lock::Mutex;
 use rand::{Rng as _, SeedableRng as _};
-use std::{borrow::Cow, fs, io, path, str};
+use std::{fs, io, path, str};
 
 /// Namespace of the key.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -56,8 +72,11 @@ pub enum KeyNamespace {
     Aura,
     AuthorityDiscovery,
     Babe,
+    Beefy,
     Grandpa,
     ImOnline,
+    ParachainValidator,
+    ParachainAssignment,
     // TODO: there exists other variants in Substrate but it's unclear whether they're in use (see https://github.com/paritytech/substrate/blob/cafe12e7785bf92e5dc04780c10e7f8330a15a4c/primitives/core/src/crypto.rs)
 }
 
@@ -68,19 +87,31 @@ impl KeyNamespace {
             KeyNamespace::Aura,
             KeyNamespace::AuthorityDiscovery,
             KeyNamespace::Babe,
+            KeyNamespace::Beefy,
             KeyNamespace::Grandpa,
             KeyNamespace::ImOnline,
+            KeyNamespace::ParachainValidator,
+            KeyNamespace::ParachainAssignment,
         ]
         .into_iter()
     }
 
+    /// Returns the namespace corresponding to the given key type identifier, as passed to the
+    /// key generation host functions. Returns `None` if the identifier is unknown.
+    pub fn from_key_type_id(key_type_id: &[u8; 4]) -> Option<Self> {
+        Self::from_string(str::from_utf8(key_type_id).ok()?)
+    }
+
     fn from_string(str: &str) -> Option<Self> {
         match str {
             "aura" => Some(KeyNamespace::Aura),
             "audi" => Some(KeyNamespace::AuthorityDiscovery),
             "babe" => Some(KeyNamespace::Babe),
+            "beef" => Some(KeyNamespace::Beefy),
             "gran" => Some(KeyNamespace::Grandpa),
             "imon" => Some(KeyNamespace::ImOnline),
+            "para" => Some(KeyNamespace::ParachainValidator),
+            "asgn" => Some(KeyNamespace::ParachainAssignment),
             _ => None,
         }
     }
@@ -90,18 +121,32 @@ impl KeyNamespace {
             KeyNamespace::Aura => "aura",
             KeyNamespace::AuthorityDiscovery => "audi",
             KeyNamespace::Babe => "babe",
+            KeyNamespace::Beefy => "beef",
             KeyNamespace::Grandpa => "gran",
             KeyNamespace::ImOnline => "imon",
+            KeyNamespace::ParachainValidator => "para",
+            KeyNamespace::ParachainAssignment => "asgn",
         }
     }
 }
 
+/// Algorithm of a key pair.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum KeyAlgorithm {
+    Ed25519,
+    Sr25519,
+    /// ECDSA on the Secp256k1 curve. Public keys are in their 33 bytes compressed form.
+    Ecdsa,
+}
+
 /// Collection of key pairs.
 ///
 /// This module doesn't give you access to the content of private keys, only to signing
 /// capabilities.
 pub struct Keystore {
     keys_directory: Option<path::PathBuf>,
+    /// Passphrase used to encrypt the keys written to [`Keystore::keys_directory`].
+    passphrase: Option<String>,
     guarded: Mutex<Guarded>,
     /// Cached base signing context cloned when signing with `sr25519`.
     sr25519_signing_context: schnorrkel::context::SigningContext,
@@ -114,12 +159,18 @@ impl Keystore {
     /// generate private keys.
     ///
     /// An error is returned if the `keys_directory` couldn't be opened because, for example, of
-    /// some missing permission or because it isn't a directory.
+    /// some missing permission or because it isn't a directory, or if one of the key files it
+    /// contains can't be loaded. Files whose name isn't the name of a key file are ignored.
     /// If the `keys_directory` doesn't exist, it will be created using `fs::create_dir_all`.
+    ///
+    /// If `passphrase` is `Some`, the keys that are saved in `keys_directory` are encrypted with
+    /// this passphrase. An error is returned if the directory contains a key that has been
+    /// encrypted with a different passphrase.
     pub async fn new(
         keys_directory: Option<path::PathBuf>,
+        passphrase: Option<String>,
         randomness_seed: [u8; 32],
-    ) -> Result<Self, io::Error> {
+    ) -> Result<Self, InitError> {
         let mut gen_rng = rand_chacha::ChaCha20Rng::from_seed(randomness_seed);
 
         let mut keys = hashbrown::HashMap::with_capacity_and_hasher(32, {
@@ -127,19 +178,19 @@ impl Keystore {
         });
 
         // Load the keys from the disk.
-        // TODO: return some diagnostic about invalid files?
         if let Some(keys_directory) = &keys_directory {
-            if !keys_directory.try_exists()? {
-                fs::create_dir_all(keys_directory)?;
+            if !keys_directory.try_exists().map_err(InitError::Io)? {
+                fs::create_dir_all(keys_directory).map_err(InitError::Io)?;
             }
 
-            for entry in fs::read_dir(keys_directory)? {
-                let entry = entry?;
-                if entry.file_type()?.is_dir() {
+            for entry in fs::read_dir(keys_directory).map_err(InitError::Io)? {
+                let entry = entry.map_err(InitError::Io)?;
+                if entry.file_type().map_err(InitError::Io)?.is_dir() {
                     continue;
                 }
 
-                // Try to match the file name.
+                // Try to match the file name. Files that don't look like keys, such as files
+                // created by the operating system, are ignored.
                 let file_name = match entry.file_name().into_string() {
                     Ok(n) => n,
                     Err(_) => continue,
@@ -153,26 +204,23 @@ impl Keystore {
                                 KeyNamespace::from_string,
                             ),
                             nom::bytes::complete::tag("-"),
-                            nom::combinator::map_opt(
-                                nom::bytes::complete::take(7u32),
-                                |b| match b {
-                                    "ed25519" => Some(PrivateKey::FileEd25519),
-                                    "sr25519" => Some(PrivateKey::FileSr25519),
-                                    _ => None,
-                                },
-                            ),
+                            nom::branch::alt((
+                                nom::combinator::map(nom::bytes::complete::tag("ed25519"), |_| {
+                                    KeyAlgorithm::Ed25519
+                                }),
+                                nom::combinator::map(nom::bytes::complete::tag("sr25519"), |_| {
+                                    KeyAlgorithm::Sr25519
+                                }),
+                                nom::combinator::map(nom::bytes::complete::tag("ecdsa"), |_| {
+                                    KeyAlgorithm::Ecdsa
+                                }),
+                            )),
                             nom::bytes::complete::tag("-"),
                             nom::combinator::map_opt(
                                 nom::bytes::complete::take_while(|c| {
                                     (c >= '0' && c <= '9') || (c >= 'a' && c <= 'f')
                                 }),
-                                |k: &str| {
-                                    if k.len() == 64 {
-                                        Some(<[u8; 32]>::try_from(hex::decode(k).unwrap()).unwrap())
-                                    } else {
-                                        None
-                                    }
-                                },
+                                |k: &str| hex::decode(k).ok(),
                             ),
                         ))),
                     );
@@ -181,42 +229,60 @@ impl Keystore {
                     Ok((_, v)) => v,
                     Err(_) => continue,
                 };
+                if public_key.len() != algorithm.public_key_len() {
+                    continue;
+                }
 
                 // Make sure that the content of the file is valid and that it corresponds to
                 // the public key advertised in the file name.
-                match algorithm {
-                    PrivateKey::FileEd25519 => {
-                        match Self::load_ed25519_from_file(keys_directory.join(entry.path())).await
-                        {
-                            Ok(kp) => {
-                                if ed25519_zebra::VerificationKey::from(&kp).as_ref() != public_key
-                                {
-                                    continue;
-                                }
-                            }
-                            Err(_) => continue,
+                // The decrypted keys are kept in memory, as decrypting them is expensive.
+                let path = keys_directory.join(entry.path());
+                let private_key = match algorithm {
+                    KeyAlgorithm::Ed25519 => {
+                        let kp = Self::load_ed25519_from_file(&path, passphrase.as_deref())
+                            .await
+                            .map_err(|error| InitError::KeyLoad {
+                                path: path.clone(),
+                                error,
+                            })?;
+                        if ed25519_zebra::VerificationKey::from(&kp).as_ref() != &public_key[..] {
+                            return Err(InitError::PublicKeyMismatch(path));
                         }
+                        PrivateKey::Ed25519(kp)
                     }
-                    PrivateKey::FileSr25519 => {
-                        match Self::load_sr25519_from_file(keys_directory.join(entry.path())).await
-                        {
-                            Ok(kp) => {
-                                if kp.public.to_bytes() != public_key {
-                                    continue;
-                                }
-                            }
-                            Err(err) => panic!("{err:?}"),
+                    KeyAlgorithm::Sr25519 => {
+                        let kp = Self::load_sr25519_from_file(&path, passphrase.as_deref())
+                            .await
+                            .map_err(|error| InitError::KeyLoad {
+                                path: path.clone(),
+                                error,
+                            })?;
+                        if kp.public.to_bytes() != public_key[..] {
+                            return Err(InitError::PublicKeyMismatch(path));
                         }
+                        PrivateKey::Sr25519(kp)
                     }
-                    _ => unreachable!(),
-                }
+                    KeyAlgorithm::Ecdsa => {
+                        let key = Self::load_ecdsa_from_file(&path, passphrase.as_deref())
+                            .await
+                            .map_err(|error| InitError::KeyLoad {
+                                path: path.clone(),
+                                error,
+                            })?;
+                        if ecdsa_public_key(&key) != public_key[..] {
+                            return Err(InitError::PublicKeyMismatch(path));
+                        }
+                        PrivateKey::Ecdsa(key)
+                    }
+                };
 
-                keys.insert((namespace, public_key), algorithm);
+                keys.insert((namespace, public_key), private_key);
             }
         }
 
         Ok(Keystore {
             keys_directory,
+            passphrase,
             guarded: Mutex::new(Guarded { gen_rng, keys }),
             sr25519_signing_context: schnorrkel::signing_context(b"substrate"),
         })
@@ -248,8 +314,8 @@ impl Keystore {
 
         for namespace in namespaces {
             self.guarded.get_mut().keys.insert(
-                (namespace, public_key),
-                PrivateKey::MemorySr25519(keypair.clone()),
+                (namespace, public_key.to_vec()),
+                PrivateKey::Sr25519(keypair.clone()),
             );
         }
 
@@ -284,17 +350,20 @@ impl Keystore {
         };
 
         if let Some(save_path) = save_path {
-            Self::write_to_file_ed25519(&save_path, &private_key).await?;
-            guarded
-                .keys
-                .insert((namespace, public_key), PrivateKey::FileEd25519);
-        } else {
-            guarded.keys.insert(
-                (namespace, public_key),
-                PrivateKey::MemoryEd25519(private_key),
-            );
+            Self::write_to_file_ed25519(
+                &save_path,
+                &private_key,
+                self.passphrase.as_deref(),
+                &mut guarded.gen_rng,
+            )
+            .await?;
         }
 
+        guarded.keys.insert(
+            (namespace, public_key.to_vec()),
+            PrivateKey::Ed25519(private_key),
+        );
+
         Ok(public_key)
     }
 
@@ -302,7 +371,7 @@ impl Keystore {
     ///
     /// > **Note**: Keep in mind that this function is racy, as keys can be added and removed
     /// >           in parallel of this function being called.
-    pub async fn keys(&self) -> impl Iterator<Item = (KeyNamespace, [u8; 32])> {
+    pub async fn keys(&self) -> impl Iterator<Item = (KeyNamespace, Vec<u8>)> {
         let guarded = self.guarded.lock().await;
         guarded.keys.keys().cloned().collect::<Vec<_>>().into_iter()
     }
@@ -336,79 +405,232 @@ impl Keystore {
         };
 
         if let Some(save_path) = save_path {
-            Self::write_to_file_sr25519(&save_path, &mini_secret).await?;
-            guarded
-                .keys
-                .insert((namespace, public_key), PrivateKey::FileSr25519);
+            Self::write_to_file_sr25519(
+                &save_path,
+                &mini_secret,
+                self.passphrase.as_deref(),
+                &mut guarded.gen_rng,
+            )
+            .await?;
+        }
+
+        guarded.keys.insert(
+            (namespace, public_key.to_vec()),
+            PrivateKey::Sr25519(keypair),
+        );
+
+        Ok(public_key)
+    }
+
+    /// Generates a new ECDSA key and inserts it in the keystore.
+    ///
+    /// If `save` is `true`, the generated key is saved in the file system. This function returns
+    /// an error only if `save` is `true` and the key couldn't be written to the file system.
+    /// The value of `save` is silently ignored if no path was provided to [`Keystore::new`].
+    ///
+    /// Returns the corresponding public key, in its compressed form.
+    pub async fn generate_ecdsa(
+        &self,
+        namespace: KeyNamespace,
+        save: bool,
+    ) -> Result<[u8; 33], io::Error> {
+        let mut guarded = self.guarded.lock().await;
+
+        // Not all 32 bytes values are valid secret keys, but the odds of generating an invalid
+        // one are astronomically small.
+        let private_key = loop {
+            let bytes: [u8; 32] = guarded.gen_rng.sample(rand::distributions::Standard);
+            if let Ok(private_key) = libsecp256k1::SecretKey::parse(&bytes) {
+                break private_key;
+            }
+        };
+        let public_key = ecdsa_public_key(&private_key);
+
+        let save_path = if save {
+            self.path_of_key(namespace, "ecdsa", &public_key)
         } else {
-            guarded
-                .keys
-                .insert((namespace, public_key), PrivateKey::MemorySr25519(keypair));
+            None
+        };
+
+        if let Some(save_path) = save_path {
+            Self::write_to_file_ecdsa(
+                &save_path,
+                &private_key,
+                self.passphrase.as_deref(),
+                &mut guarded.gen_rng,
+            )
+            .await?;
         }
 
+        guarded.keys.insert(
+            (namespace, public_key.to_vec()),
+            PrivateKey::Ecdsa(private_key),
+        );
+
+        Ok(public_key)
+    }
+
+    /// Generates a new key and inserts it in the keystore. This is typically called when the
+    /// runtime calls one of the key generation host functions.
+    ///
+    /// If `seed` is `None`, the key is generated randomly, and `save` has the same meaning as in
+    /// [`Keystore::generate_ed25519`], [`Keystore::generate_sr25519`] and
+    /// [`Keystore::generate_ecdsa`]. If `seed` is `Some`, the key is derived from this secret
+    /// phrase (for example `//Alice`) and is never saved in the file system.
+    ///
+    /// Returns the corresponding public key, whose length depends on the algorithm. See
+    /// [`KeyAlgorithm`].
+    pub async fn generate(
+        &self,
+        namespace: KeyNamespace,
+        algorithm: KeyAlgorithm,
+        seed: Option<&str>,
+        save: bool,
+    ) -> Result<Vec<u8>, GenerateError> {
+        let Some(seed) = seed else {
+            return match algorithm {
+                KeyAlgorithm::Ed25519 => self
+                    .generate_ed25519(namespace, save)
+                    .await
+                    .map(|k| k.to_vec()),
+                KeyAlgorithm::Sr25519 => self
+                    .generate_sr25519(namespace, save)
+                    .await
+                    .map(|k| k.to_vec()),
+                KeyAlgorithm::Ecdsa => self
+                    .generate_ecdsa(namespace, save)
+                    .await
+                    .map(|k| k.to_vec()),
+            }
+            .map_err(GenerateError::Io);
+        };
+
+        let (public_key, private_key) = match algorithm {
+            KeyAlgorithm::Ed25519 => {
+                let private_key = ed25519_zebra::SigningKey::from(
+                    seed_phrase::decode_ed25519_private_key(seed)
+                        .map_err(GenerateError::InvalidSeed)?,
+                );
+                let public_key: [u8; 32] =
+                    ed25519_zebra::VerificationKey::from(&private_key).into();
+                (public_key.to_vec(), PrivateKey::Ed25519(private_key))
+            }
+            KeyAlgorithm::Sr25519 => {
+                let private_key = seed_phrase::decode_sr25519_private_key(seed)
+                    .map_err(GenerateError::InvalidSeed)?;
+                // `from_bytes` only panics if the key is of the wrong length, which we know
+                // can't happen here.
+                let keypair = schnorrkel::SecretKey::from_bytes(&private_key)
+                    .unwrap()
+                    .to_keypair();
+                (
+                    keypair.public.to_bytes().to_vec(),
+                    PrivateKey::Sr25519(keypair),
+                )
+            }
+            KeyAlgorithm::Ecdsa => {
+                let private_key = libsecp256k1::SecretKey::parse(
+                    &seed_phrase::decode_ecdsa_private_key(seed)
+                        .map_err(GenerateError::InvalidSeed)?,
+                )
+                .map_err(|_| GenerateError::InvalidEcdsaSecretKey)?;
+                (
+                    ecdsa_public_key(&private_key).to_vec(),
+                    PrivateKey::Ecdsa(private_key),
+                )
+            }
+        };
+
+        self.guarded
+            .lock()
+            .await
+            .keys
+            .insert((namespace, public_key.clone()), private_key);
         Ok(public_key)
     }
 
+    /// Returns `true` if the keystore contains the private keys corresponding to all the given
+    /// public keys.
+    ///
+    /// `session_keys` is typically obtained by decoding the session keys of a validator, which
+    /// is done by calling the `SessionKeys_decode_session_keys` runtime function.
+    pub async fn has_session_keys(
+        &self,
+        mut session_keys: impl Iterator<Item = (KeyNamespace, impl AsRef<[u8]>)>,
+    ) -> bool {
+        let guarded = self.guarded.lock().await;
+        session_keys.all(|(namespace, public_key)| {
+            guarded
+                .keys
+                .contains_key(&(namespace, public_key.as_ref().to_vec()))
+        })
+    }
+
     /// Signs the given payload using the private key associated to the public key passed as
     /// parameter.
     ///
-    /// An error is returned if the key-namespace combination is not in the keystore, or if the
-    /// key couldn't be loaded from disk. In the case when a key couldn't be loaded from disk, it
-    /// is automatically removed from the keystore.
+    /// An error is returned if the key-namespace combination is not in the keystore.
+    ///
+    /// ECDSA keys must be used with [`Keystore::sign_ecdsa`] instead.
     pub async fn sign(
         &self,
         key_namespace: KeyNamespace,
         public_key: &[u8; 32],
         payload: &[u8],
     ) -> Result<[u8; 64], SignError> {
-        let mut guarded = self.guarded.lock().await;
+        let guarded = self.guarded.lock().await;
         let key = guarded
             .keys
-            .get(&(key_namespace, *public_key))
+            .get(&(key_namespace, public_key.to_vec()))
             .ok_or(SignError::UnknownPublicKey)?;
 
         match key {
-            PrivateKey::MemoryEd25519(key) => Ok(key.sign(payload).into()),
-            PrivateKey::FileEd25519 => {
-                match Self::load_ed25519_from_file(
-                    self.path_of_key_ed25519(key_namespace, public_key).unwrap(),
-                )
-                .await
-                {
-                    Ok(key) => {
-                        drop(guarded);
-                        Ok(key.sign(payload).into())
-                    }
-                    Err(err) => {
-                        guarded.keys.remove(&(key_namespace, *public_key));
-                        Err(err.into())
-                    }
-                }
-            }
-            PrivateKey::MemorySr25519(key) => Ok(key
+            PrivateKey::Ed25519(key) => Ok(key.sign(payload).into()),
+            PrivateKey::Sr25519(key) => Ok(key
                 .sign(self.sr25519_signing_context.bytes(payload))
                 .to_bytes()),
-            PrivateKey::FileSr25519 => {
-                match Self::load_sr25519_from_file(
-                    self.path_of_key_sr25519(key_namespace, public_key).unwrap(),
-                )
-                .await
-                {
-                    Ok(key) => {
-                        drop(guarded);
-                        Ok(key
-                            .sign(self.sr25519_signing_context.bytes(payload))
-                            .to_bytes())
-                    }
-                    Err(err) => {
-                        guarded.keys.remove(&(key_namespace, *public_key));
-                        Err(err.into())
-                    }
-                }
-            }
+            // ECDSA public keys are 33 bytes long.
+            PrivateKey::Ecdsa(_) => unreachable!(),
         }
     }
 
+    /// Signs the blake2 hash of the given payload using the ECDSA private key associated to the
+    /// public key passed as parameter.
+    ///
+    /// The signature is returned in its 65 bytes recoverable form, where the last byte is the
+    /// recovery identifier.
+    ///
+    /// An error is returned if the key-namespace combination is not in the keystore.
+    pub async fn sign_ecdsa(
+        &self,
+        key_namespace: KeyNamespace,
+        public_key: &[u8; 33],
+        payload: &[u8],
+    ) -> Result<[u8; 65], SignError> {
+        let guarded = self.guarded.lock().await;
+        let key = guarded
+            .keys
+            .get(&(key_namespace, public_key.to_vec()))
+            .ok_or(SignError::UnknownPublicKey)?;
+
+        // Only ECDSA public keys are 33 bytes long.
+        let PrivateKey::Ecdsa(key) = key else {
+            unreachable!()
+        };
+
+        // NOTE: safe to unwrap here because we supply the nn to blake2b fn
+        let message = libsecp256k1::Message::parse(
+            &<[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], payload).as_bytes())
+                .unwrap(),
+        );
+        let (signature, recovery_id) = libsecp256k1::sign(&message, key);
+
+        let mut out = [0; 65];
+        out[..64].copy_from_slice(&signature.serialize());
+        out[64] = recovery_id.serialize();
+        Ok(out)
+    }
+
     // TODO: doc
     ///
     /// Note that the labels must be `'static` due to requirements from the underlying library.
@@ -421,38 +643,17 @@ impl Keystore {
         transcript_items: impl Iterator<Item = (&'static [u8], either::Either<&'a [u8], u64>)> + 'a,
     ) -> impl core::future::Future<Output = Result<VrfSignature, SignVrfError>> + 'a {
         async move {
-            let mut guarded = self.guarded.lock().await;
+            let guarded = self.guarded.lock().await;
             let key = guarded
                 .keys
-                .get(&(key_namespace, *public_key))
+                .get(&(key_namespace, public_key.to_vec()))
                 .ok_or(SignVrfError::Sign(SignError::UnknownPublicKey))?;
 
             match key {
-                PrivateKey::MemoryEd25519(_) | PrivateKey::FileEd25519 => {
+                PrivateKey::Ed25519(_) | PrivateKey::Ecdsa(_) => {
                     Err(SignVrfError::WrongKeyAlgorithm)
                 }
-                PrivateKey::MemorySr25519(_) | PrivateKey::FileSr25519 => {
-                    let key = match key {
-                        PrivateKey::MemorySr25519(key) => Cow::Borrowed(key),
-                        PrivateKey::FileSr25519 => {
-                            match Self::load_sr25519_from_file(
-                                self.path_of_key_sr25519(key_namespace, public_key).unwrap(),
-                            )
-                            .await
-                            {
-                                Ok(key) => {
-                                    drop(guarded);
-                                    Cow::Owned(key)
-                                }
-                                Err(err) => {
-                                    guarded.keys.remove(&(key_namespace, *public_key));
-                                    return Err(err.into());
-                                }
-                            }
-                        }
-                        _ => unreachable!(),
-                    };
-
+                PrivateKey::Sr25519(key) => {
                     let mut transcript = merlin::Transcript::new(label);
                     for (label, value) in transcript_items {
                         match value {
@@ -477,12 +678,10 @@ impl Keystore {
 
     async fn load_ed25519_from_file(
         path: impl AsRef<path::Path>,
+        passphrase: Option<&str>,
     ) -> Result<ed25519_zebra::SigningKey, KeyLoadError> {
-        // TODO: read asynchronously?
-        let bytes = fs::read(path).map_err(KeyLoadError::Io)?;
-        let phrase =
-            str::from_utf8(&bytes).map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
-        let private_key = seed_phrase::decode_ed25519_private_key(phrase)
+        let phrase = Self::read_from_file(path, passphrase).await?;
+        let private_key = seed_phrase::decode_ed25519_private_key(&phrase)
             .map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
         // TODO: zero memory of the private key on drop ^
         Ok(ed25519_zebra::SigningKey::from(private_key))
@@ -490,12 +689,10 @@ impl Keystore {
 
     async fn load_sr25519_from_file(
         path: impl AsRef<path::Path>,
+        passphrase: Option<&str>,
     ) -> Result<schnorrkel::Keypair, KeyLoadError> {
-        // TODO: read asynchronously?
-        let bytes = fs::read(path).map_err(KeyLoadError::Io)?;
-        let phrase =
-            str::from_utf8(&bytes).map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
-        let private_key = seed_phrase::decode_sr25519_private_key(phrase)
+        let phrase = Self::read_from_file(path, passphrase).await?;
+        let private_key = seed_phrase::decode_sr25519_private_key(&phrase)
             .map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
         // TODO: zero memory of the private key on drop ^
         // `from_bytes` only panics if the key is of the wrong length, which we know can't
@@ -505,32 +702,119 @@ impl Keystore {
             .into())
     }
 
+    async fn load_ecdsa_from_file(
+        path: impl AsRef<path::Path>,
+        passphrase: Option<&str>,
+    ) -> Result<libsecp256k1::SecretKey, KeyLoadError> {
+        let phrase = Self::read_from_file(path, passphrase).await?;
+        let private_key = seed_phrase::decode_ecdsa_private_key(&phrase)
+            .map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
+        // TODO: zero memory of the private key on drop ^
+        libsecp256k1::SecretKey::parse(&private_key)
+            .map_err(|err| KeyLoadError::BadFormat(err.to_string()))
+    }
+
+    /// Reads the content of a file containing a secret key, decrypting it if necessary, and
+    /// returns the phrase that it contains.
+    async fn read_from_file(
+        path: impl AsRef<path::Path>,
+        passphrase: Option<&str>,
+    ) -> Result<String, KeyLoadError> {
+        // TODO: read asynchronously?
+        let bytes = fs::read(path).map_err(KeyLoadError::Io)?;
+        let content =
+            str::from_utf8(&bytes).map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
+
+        let Some(encrypted) = content.strip_prefix(ENCRYPTED_FILE_PREFIX) else {
+            return Ok(content.to_owned());
+        };
+
+        let encrypted =
+            hex::decode(encrypted).map_err(|err| KeyLoadError::BadFormat(err.to_string()))?;
+        if encrypted.len() < 16 + 12 {
+            return Err(KeyLoadError::BadFormat(
+                "encrypted content too short".to_owned(),
+            ));
+        }
+        let (salt, rest) = encrypted.split_at(16);
+        let (nonce, ciphertext) = rest.split_at(12);
+
+        let passphrase = passphrase.ok_or(KeyLoadError::Decryption)?;
+        let phrase = encryption_cipher(passphrase, salt)
+            .decrypt(chacha20poly1305::Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| KeyLoadError::Decryption)?;
+        // TODO: zero memory of the phrase on drop
+        String::from_utf8(phrase).map_err(|err| KeyLoadError::BadFormat(err.to_string()))
+    }
+
     async fn write_to_file_ed25519(
         path: impl AsRef<path::Path>,
         key: &ed25519_zebra::SigningKey,
+        passphrase: Option<&str>,
+        rng: &mut rand_chacha::ChaCha20Rng,
     ) -> Result<(), io::Error> {
         let phrase = hex::encode(key.as_ref());
-        Self::write_to_file(path, &phrase).await
+        Self::write_to_file(path, &phrase, passphrase, rng).await
     }
 
     async fn write_to_file_sr25519(
         path: impl AsRef<path::Path>,
         key: &schnorrkel::MiniSecretKey,
+        passphrase: Option<&str>,
+        rng: &mut rand_chacha::ChaCha20Rng,
     ) -> Result<(), io::Error> {
         let phrase = hex::encode(key.to_bytes());
-        Self::write_to_file(path, &phrase).await
+        Self::write_to_file(path, &phrase, passphrase, rng).await
+    }
+
+    async fn write_to_file_ecdsa(
+        path: impl AsRef<path::Path>,
+        key: &libsecp256k1::SecretKey,
+        passphrase: Option<&str>,
+        rng: &mut rand_chacha::ChaCha20Rng,
+    ) -> Result<(), io::Error> {
+        let phrase = hex::encode(key.serialize());
+        Self::write_to_file(path, &phrase, passphrase, rng).await
     }
 
     async fn write_to_file(
         path: impl AsRef<path::Path>,
         key_phrase: &str,
+        passphrase: Option<&str>,
+        rng: &mut rand_chacha::ChaCha20Rng,
     ) -> Result<(), io::Error> {
+        let mut content = String::with_capacity(2 + key_phrase.len());
+        content.push_str("0x");
+        content.push_str(key_phrase);
+
+        // If a passphrase is provided, the content of the file is replaced with its encrypted
+        // version. A new salt and a new nonce are generated for each file.
+        if let Some(passphrase) = passphrase {
+            let salt: [u8; 16] = rng.sample(rand::distributions::Standard);
+            let nonce: [u8; 12] = rng.sample(rand::distributions::Standard);
+            // Encrypting can only fail if the plaintext is too large, which can't happen here.
+            let ciphertext = encryption_cipher(passphrase, &salt)
+                .encrypt(
+                    chacha20poly1305::Nonce::from_slice(&nonce),
+                    content.as_bytes(),
+                )
+                .unwrap();
+
+            let mut encrypted = Vec::with_capacity(salt.len() + nonce.len() + ciphertext.len());
+            encrypted.extend_from_slice(&salt);
+            encrypted.extend_from_slice(&nonce);
+            encrypted.extend_from_slice(&ciphertext);
+
+            content.clear();
+            content.push_str(ENCRYPTED_FILE_PREFIX);
+            content.push_str(&hex::encode(encrypted));
+        }
+
         let mut file = fs::File::create(path)?;
         // TODO: proper security flags on Windows?
         #[cfg(target_family = "unix")]
         file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o400))?;
-        io::Write::write_all(&mut file, b"0x")?;
-        io::Write::write_all(&mut file, key_phrase.as_bytes())?;
+        io::Write::write_all(&mut file, content.as_bytes())?;
         io::Write::flush(&mut file)?; // This call is generally useless, but doesn't hurt.
         file.sync_all()?;
         Ok(())
@@ -556,7 +840,7 @@ impl Keystore {
         &self,
         key_namespace: KeyNamespace,
         key_algorithm: &str,
-        public_key: &[u8; 32],
+        public_key: &[u8],
     ) -> Option<path::PathBuf> {
         let keys_directory = match &self.keys_directory {
             Some(k) => k,
@@ -566,7 +850,7 @@ impl Keystore {
         // We don't use the same pathing scheme as Substrate, for two reasons:
         // - The fact that Substrate hex-encodes the namespace is completely unnecessary and
         // confusing.
-        // - Substrate doesn't indicate whether the key is ed25519 or sr25519, because the
+        // - Substrate doesn't indicate whether the key is ed25519, sr25519 or ecdsa, because the
         // algorithm to use is provided when signing or verifying. This is weird and in my opinion
         // not a good practice.
 
@@ -585,9 +869,73 @@ impl Keystore {
     }
 }
 
+impl KeyAlgorithm {
+    /// Returns the length in bytes of the public keys of this algorithm.
+    fn public_key_len(&self) -> usize {
+        match self {
+            KeyAlgorithm::Ed25519 | KeyAlgorithm::Sr25519 => 32,
+            KeyAlgorithm::Ecdsa => 33,
+        }
+    }
+}
+
+/// Returns the compressed public key corresponding to the given ECDSA secret key.
+fn ecdsa_public_key(private_key: &libsecp256k1::SecretKey) -> [u8; 33] {
+    libsecp256k1::PublicKey::from_secret_key(private_key).serialize_compressed()
+}
+
+/// Prefix of the content of the files containing an encrypted secret key. The prefix is followed
+/// with the hexadecimal encoding of a 16 bytes salt, a 12 bytes nonce, and the ciphertext.
+const ENCRYPTED_FILE_PREFIX: &str = "encrypted:";
+
+/// Builds the cipher used to encrypt and decrypt the content of the files containing a secret
+/// key, from the passphrase and the salt of the file.
+fn encryption_cipher(passphrase: &str, salt: &[u8]) -> chacha20poly1305::ChaCha20Poly1305 {
+    // This function returns an error only in case of wrong buffer length, making it safe to
+    // unwrap.
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha512>>(passphrase.as_bytes(), salt, 16384, &mut key)
+        .unwrap();
+    // TODO: zero memory of the key on drop
+    chacha20poly1305::ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key))
+}
+
 struct Guarded {
     gen_rng: rand_chacha::ChaCha20Rng,
-    keys: hashbrown::HashMap<(KeyNamespace, [u8; 32]), PrivateKey, SipHasherBuild>,
+    keys: hashbrown::HashMap<(KeyNamespace, Vec<u8>), PrivateKey, SipHasherBuild>,
+}
+
+/// Error potentially returned by [`Keystore::new`].
+#[derive(Debug, derive_more::Display)]
+pub enum InitError {
+    /// Error reported by the operating system while accessing the keys directory.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// Failed to load a key file.
+    #[display(fmt = "Failed to load key file {}: {error}", "path.display()")]
+    KeyLoad {
+        /// Path to the file.
+        path: path::PathBuf,
+        /// Error that happened.
+        error: KeyLoadError,
+    },
+    /// The key found in a key file doesn't match the public key found in the name of the file.
+    #[display(fmt = "Key file {} doesn't match its public key", "_0.display()")]
+    PublicKeyMismatch(path::PathBuf),
+}
+
+/// Error potentially returned by [`Keystore::generate`].
+#[derive(Debug, derive_more::Display)]
+pub enum GenerateError {
+    /// Failed to write the key to the file system.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// The seed of the key couldn't be decoded.
+    #[display(fmt = "Invalid seed: {_0}")]
+    InvalidSeed(seed_phrase::ParsePrivateKeyError),
+    /// The seed of the key decodes to an invalid ECDSA secret key.
+    #[display(fmt = "Seed doesn't correspond to a valid ECDSA secret key")]
+    InvalidEcdsaSecretKey,
 }
 
 pub struct VrfSignature {
@@ -598,12 +946,6 @@ pub struct VrfSignature {
 pub enum SignError {
     /// The given `(namespace, public key)` combination is unknown to this keystore.
     UnknownPublicKey,
-
-    /// Error while accessing the file containing the secret key.
-    /// Typically indicates the content of the file has been modified by something else than
-    /// the keystore.
-    #[display(fmt = "Error loading the secret key; {_0}")]
-    KeyLoad(KeyLoadError),
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -616,6 +958,9 @@ pub enum KeyLoadError {
     /// provided.
     #[display(fmt = "{_0}")]
     BadFormat(String),
+    /// The file is encrypted, and the passphrase is either missing or wrong.
+    #[display(fmt = "Failed to decrypt the secret key")]
+    Decryption,
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -626,34 +971,21 @@ pub enum SignVrfError {
 }
 
 enum PrivateKey {
-    MemoryEd25519(ed25519_zebra::SigningKey),
-    MemorySr25519(schnorrkel::Keypair),
-    FileEd25519,
-    FileSr25519,
-}
-
-impl From<KeyLoadError> for SignError {
-    fn from(err: KeyLoadError) -> SignError {
-        SignError::KeyLoad(err)
-    }
-}
-
-impl From<KeyLoadError> for SignVrfError {
-    fn from(err: KeyLoadError) -> SignVrfError {
-        SignVrfError::Sign(SignError::KeyLoad(err))
-    }
+    Ed25519(ed25519_zebra::SigningKey),
+    Sr25519(schnorrkel::Keypair),
+    Ecdsa(libsecp256k1::SecretKey),
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{KeyNamespace, Keystore};
+    use super::{KeyAlgorithm, KeyNamespace, Keystore};
 
     #[test]
     fn disk_storage_works_ed25519() {
         futures::executor::block_on(async move {
             let path = tempfile::tempdir().unwrap();
 
-            let keystore1 = Keystore::new(Some(path.path().to_owned()), rand::random())
+            let keystore1 = Keystore::new(Some(path.path().to_owned()), None, rand::random())
                 .await
                 .unwrap();
             let public_key = keystore1
@@ -662,12 +994,12 @@ mod tests {
                 .unwrap();
             drop(keystore1);
 
-            let keystore2 = Keystore::new(Some(path.path().to_owned()), rand::random())
+            let keystore2 = Keystore::new(Some(path.path().to_owned()), None, rand::random())
                 .await
                 .unwrap();
             assert_eq!(
                 keystore2.keys().await.next(),
-                Some((KeyNamespace::Babe, public_key))
+                Some((KeyNamespace::Babe, public_key.to_vec()))
             );
 
             let signature = keystore2
@@ -687,7 +1019,7 @@ mod tests {
         futures::executor::block_on(async move {
             let path = tempfile::tempdir().unwrap();
 
-            let keystore1 = Keystore::new(Some(path.path().to_owned()), rand::random())
+            let keystore1 = Keystore::new(Some(path.path().to_owned()), None, rand::random())
                 .await
                 .unwrap();
             let public_key = keystore1
@@ -696,12 +1028,12 @@ mod tests {
                 .unwrap();
             drop(keystore1);
 
-            let keystore2 = Keystore::new(Some(path.path().to_owned()), rand::random())
+            let keystore2 = Keystore::new(Some(path.path().to_owned()), None, rand::random())
                 .await
                 .unwrap();
             assert_eq!(
                 keystore2.keys().await.next(),
-                Some((KeyNamespace::Aura, public_key))
+                Some((KeyNamespace::Aura, public_key.to_vec()))
             );
 
             let signature = keystore2
@@ -719,4 +1051,208 @@ mod tests {
                 .is_ok());
         });
     }
+
+    #[test]
+    fn disk_storage_works_ecdsa() {
+        futures::executor::block_on(async move {
+            let path = tempfile::tempdir().unwrap();
+
+            let keystore1 = Keystore::new(Some(path.path().to_owned()), None, rand::random())
+                .await
+                .unwrap();
+            let public_key = keystore1
+                .generate_ecdsa(KeyNamespace::Grandpa, true)
+                .await
+                .unwrap();
+            drop(keystore1);
+
+            let keystore2 = Keystore::new(Some(path.path().to_owned()), None, rand::random())
+                .await
+                .unwrap();
+            assert_eq!(
+                keystore2.keys().await.next(),
+                Some((KeyNamespace::Grandpa, public_key.to_vec()))
+            );
+
+            let signature = keystore2
+                .sign_ecdsa(KeyNamespace::Grandpa, &public_key, b"hello world")
+                .await
+                .unwrap();
+
+            let message = libsecp256k1::Message::parse(
+                &<[u8; 32]>::try_from(
+                    blake2_rfc::blake2b::blake2b(32, &[], b"hello world").as_bytes(),
+                )
+                .unwrap(),
+            );
+            let recovered = libsecp256k1::recover(
+                &message,
+                &libsecp256k1::Signature::parse_standard_slice(&signature[..64]).unwrap(),
+                &libsecp256k1::RecoveryId::parse(signature[64]).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(recovered.serialize_compressed(), public_key);
+        });
+    }
+
+    #[test]
+    fn disk_storage_encrypted() {
+        futures::executor::block_on(async move {
+            let path = tempfile::tempdir().unwrap();
+
+            let keystore1 = Keystore::new(
+                Some(path.path().to_owned()),
+                Some("correct horse".to_owned()),
+                rand::random(),
+            )
+            .await
+            .unwrap();
+            let public_key = keystore1
+                .generate_sr25519(KeyNamespace::Aura, true)
+                .await
+                .unwrap();
+            drop(keystore1);
+
+            // Loading keys encrypted with a different passphrase fails.
+            assert!(matches!(
+                Keystore::new(
+                    Some(path.path().to_owned()),
+                    Some("battery staple".to_owned()),
+                    rand::random(),
+                )
+                .await,
+                Err(super::InitError::KeyLoad {
+                    error: super::KeyLoadError::Decryption,
+                    ..
+                })
+            ));
+
+            let keystore3 = Keystore::new(
+                Some(path.path().to_owned()),
+                Some("correct horse".to_owned()),
+                rand::random(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                keystore3.keys().await.next(),
+                Some((KeyNamespace::Aura, public_key.to_vec()))
+            );
+            assert!(keystore3
+                .sign(KeyNamespace::Aura, &public_key, b"hello world")
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn unrelated_files_ignored() {
+        futures::executor::block_on(async move {
+            let path = tempfile::tempdir().unwrap();
+            std::fs::write(path.path().join(".DS_Store"), b"").unwrap();
+            std::fs::write(path.path().join("aura-sr25519-not-hex"), b"").unwrap();
+
+            let keystore = Keystore::new(Some(path.path().to_owned()), None, rand::random())
+                .await
+                .unwrap();
+            assert!(keystore.keys().await.next().is_none());
+        });
+    }
+
+    #[test]
+    fn session_keys() {
+        futures::executor::block_on(async move {
+            let keystore = Keystore::new(None, None, rand::random()).await.unwrap();
+
+            let aura = keystore
+                .generate(KeyNamespace::Aura, KeyAlgorithm::Sr25519, None, false)
+                .await
+                .unwrap();
+            let grandpa = keystore
+                .generate(
+                    KeyNamespace::Grandpa,
+                    KeyAlgorithm::Ed25519,
+                    Some("//Alice"),
+                    false,
+                )
+                .await
+                .unwrap();
+
+            let beefy = keystore
+                .generate(
+                    KeyNamespace::Beefy,
+                    KeyAlgorithm::Ecdsa,
+                    Some("//Alice"),
+                    false,
+                )
+                .await
+                .unwrap();
+
+            // Public key of `//Alice` for Ed25519.
+            assert_eq!(
+                hex::encode(&grandpa),
+                "88dc3417d5058ec4b4503e0c12ea1a0a89be200fe98922423d4334014fa6b0ee"
+            );
+
+            // Public key of `//Alice` for ECDSA.
+            assert_eq!(
+                hex::encode(&beefy),
+                "020a1091341fe5664bfa1782d5e04779689068c916b04cb365ec3153755684d9a1"
+            );
+
+            assert!(
+                keystore
+                    .has_session_keys(
+                        [
+                            (KeyNamespace::Aura, &aura),
+                            (KeyNamespace::Grandpa, &grandpa),
+                            (KeyNamespace::Beefy, &beefy)
+                        ]
+                        .into_iter()
+                    )
+                    .await
+            );
+
+            // Wrong namespaces.
+            assert!(
+                !keystore
+                    .has_session_keys(
+                        [
+                            (KeyNamespace::Babe, &aura),
+                            (KeyNamespace::Grandpa, &grandpa)
+                        ]
+                        .into_iter()
+                    )
+                    .await
+            );
+
+            assert!(keystore
+                .generate(
+                    KeyNamespace::Aura,
+                    KeyAlgorithm::Sr25519,
+                    Some("not a valid seed"),
+                    false
+                )
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn key_type_ids() {
+        assert_eq!(
+            KeyNamespace::from_key_type_id(b"gran"),
+            Some(KeyNamespace::Grandpa)
+        );
+        assert_eq!(
+            KeyNamespace::from_key_type_id(b"para"),
+            Some(KeyNamespace::ParachainValidator)
+        );
+        assert_eq!(
+            KeyNamespace::from_key_type_id(b"beef"),
+            Some(KeyNamespace::Beefy)
+        );
+        assert_eq!(KeyNamespace::from_key_type_id(b"abcd"), None);
+        assert_eq!(KeyNamespace::from_key_type_id(&[0xff; 4]), None);
+    }
 }
@@ -74,6 +74,32 @@ pub fn decode_ed25519_private_key(phrase: &str) -> Result<[u8; 32], ParsePrivate
     Ok(secret_key)
 }
 
+/// Decodes a human-readable private key (a.k.a. a seed phrase) using the Secp256k1 curve (i.e.
+/// ECDSA).
+///
+/// Note that the returned secret key isn't guaranteed to be a valid Secp256k1 secret key, which
+/// is the case only for an astronomically small number of seed phrases.
+pub fn decode_ecdsa_private_key(phrase: &str) -> Result<[u8; 32], ParsePrivateKeyError> {
+    let parsed = parse_private_key(phrase)?;
+
+    let mut secret_key = parsed.seed;
+    for junction in parsed.path {
+        secret_key = match junction {
+            DeriveJunction::Soft(_) => todo!(), // TODO: return error
+            DeriveJunction::Hard(cc) => {
+                let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+                hash.update(crate::util::encode_scale_compact_usize(13).as_ref()); // Length of `"Secp256k1HDKD"`
+                hash.update(b"Secp256k1HDKD");
+                hash.update(&secret_key);
+                hash.update(&cc);
+                <[u8; 32]>::try_from(hash.finalize().as_bytes()).unwrap()
+            }
+        };
+    }
+
+    Ok(secret_key)
+}
+
 /// Turns a human-readable private key (a.k.a. a seed phrase) into a seed and a derivation path.
 pub fn parse_private_key(phrase: &str) -> Result<ParsedPrivateKey, ParsePrivateKeyError> {
     let parse_result: Result<_, nom::Err<nom::error::Error<&str>>> =
@@ -341,6 +367,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn alice_matches_ecdsa() {
+        let secret_key =
+            libsecp256k1::SecretKey::parse(&super::decode_ecdsa_private_key("//Alice").unwrap())
+                .unwrap();
+        assert_eq!(
+            hex::encode(
+                libsecp256k1::PublicKey::from_secret_key(&secret_key).serialize_compressed()
+            ),
+            "020a1091341fe5664bfa1782d5e04779689068c916b04cb365ec3153755684d9a1"
+        );
+    }
+
     #[test]
     fn hex_seed_matches_sr25519() {
         assert_eq!(
@@ -65,4 +65,7 @@ pub mod methods;
 pub mod parse;
 pub mod payment_info;
 pub mod requests_subscriptions;
+pub mod session_keys;
+pub mod shared_follow;
+pub mod trace_block;
 pub mod websocket_server;
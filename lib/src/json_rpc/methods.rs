@@ -17,12 +17,13 @@
 
 //! List of requests and how to answer them.
 
-use super::parse;
+use super::{parse, payment_info};
 use crate::header;
 
 use alloc::{
     borrow::Cow,
     boxed::Box,
+    collections::BTreeMap,
     format,
     string::{String, ToString as _},
     vec,
@@ -63,6 +64,70 @@ pub fn build_json_call_object_parameters(id_json: Option<&str>, method: MethodCa
     method.to_json_call_object_parameters(id_json)
 }
 
+/// Returns `true` if a call to the JSON-RPC method with the given name, which can be an alias,
+/// can be answered using only information that is available locally, without accessing the
+/// network or executing a runtime.
+///
+/// Returns `false` for unknown methods.
+pub fn is_cheap_method(name: &str) -> bool {
+    CHEAP_METHODS.contains(&name)
+}
+
+/// List of methods for which [`is_cheap_method`] returns `true`.
+const CHEAP_METHODS: &[&str] = &[
+    "author_unwatchExtrinsic",
+    "beefy_unsubscribeJustifications",
+    "chain_unsubscribeAllHeads",
+    "chain_unsubscribeFinalizedHeads",
+    "chain_unsubscribeFinalisedHeads",
+    "chain_unsubscribeNewHeads",
+    "chain_unsubscribeNewHead",
+    "unsubscribe_newHead",
+    "rpc_methods",
+    "state_unsubscribeRuntimeVersion",
+    "chain_unsubscribeRuntimeVersion",
+    "state_unsubscribeStorage",
+    "system_addLogFilter",
+    "system_addReservedPeer",
+    "system_chain",
+    "system_chainType",
+    "system_health",
+    "system_localListenAddresses",
+    "system_localPeerId",
+    "system_name",
+    "system_networkState",
+    "system_nodeRoles",
+    "system_peers",
+    "system_properties",
+    "system_removeReservedPeer",
+    "system_resetLogFilter",
+    "system_syncState",
+    "system_version",
+    "chainHead_unstable_genesisHash",
+    "chainHead_unstable_header",
+    "chainHead_unstable_stopBody",
+    "chainHead_unstable_stopCall",
+    "chainHead_unstable_stopStorage",
+    "chainHead_unstable_unfollow",
+    "chainHead_unstable_unpin",
+    "chainSpec_unstable_chainName",
+    "chainSpec_v1_chainName",
+    "chainSpec_unstable_genesisHash",
+    "chainSpec_v1_genesisHash",
+    "chainSpec_unstable_properties",
+    "chainSpec_v1_properties",
+    "sudo_unstable_p2pDiscover",
+    "sudo_unstable_version",
+    "transaction_unstable_unwatch",
+    "chainHead_v1_header",
+    "chainHead_v1_stopOperation",
+    "chainHead_v1_unfollow",
+    "chainHead_v1_unpin",
+    "transaction_v1_stop",
+    "transactionWatch_v1_unwatch",
+    "network_unstable_unsubscribeEvents",
+];
+
 /// Error produced by [`parse_json_call`].
 #[derive(Debug, derive_more::Display)]
 pub enum ParseError<'a> {
@@ -360,9 +425,8 @@ macro_rules! has_params {
 define_methods! {
     MethodCall,
     Response<'a>,
-    account_nextIndex() -> (), // TODO:
     author_hasKey() -> (), // TODO:
-    author_hasSessionKeys() -> (), // TODO:
+    author_hasSessionKeys(session_keys: HexString) -> bool,
     author_insertKey() -> (), // TODO:
     author_pendingExtrinsics() -> Vec<HexString>,  // TODO: what does the returned value mean?
     author_removeExtrinsic() -> (), // TODO:
@@ -371,6 +435,8 @@ define_methods! {
     author_submitExtrinsic(transaction: HexString) -> HashHexString,
     author_unwatchExtrinsic(subscription: Cow<'a, str>) -> bool,
     babe_epochAuthorship() -> (), // TODO:
+    beefy_subscribeJustifications() -> Cow<'a, str>,
+    beefy_unsubscribeJustifications(subscription: Cow<'a, str>) -> bool,
     chain_getBlock(hash: Option<HashHexString>) -> Block,
     chain_getBlockHash(height: Option<u64>) -> HashHexString [chain_getHead],
     chain_getFinalizedHead() -> HashHexString [chain_getFinalisedHead],
@@ -381,13 +447,19 @@ define_methods! {
     chain_unsubscribeAllHeads(subscription: String) -> bool,
     chain_unsubscribeFinalizedHeads(subscription: String) -> bool [chain_unsubscribeFinalisedHeads],
     chain_unsubscribeNewHeads(subscription: String) -> bool [unsubscribe_newHead, chain_unsubscribeNewHead],
-    childstate_getKeys() -> (), // TODO:
-    childstate_getStorage() -> (), // TODO:
-    childstate_getStorageHash() -> (), // TODO:
+    childstate_getKeys(child_storage_key: HexString, prefix: HexString, hash: Option<HashHexString>) -> Vec<HexString>,
+    childstate_getKeysPaged(child_storage_key: HexString, prefix: Option<HexString>, count: u32, start_key: Option<HexString>, hash: Option<HashHexString>) -> Vec<HexString> [childstate_getKeysPagedAt],
+    childstate_getStorage(child_storage_key: HexString, key: HexString, hash: Option<HashHexString>) -> HexString,
+    childstate_getStorageHash(child_storage_key: HexString, key: HexString, hash: Option<HashHexString>) -> HashHexString,
     childstate_getStorageSize() -> (), // TODO:
     grandpa_roundState() -> (), // TODO:
+    mmr_generateProof(#[rename = "blockNumbers"] block_numbers: Vec<u64>, #[rename = "bestKnownBlockNumber"] best_known_block_number: Option<u64>, at: Option<HashHexString>) -> LeavesProof,
+    mmr_root(at: Option<HashHexString>) -> HashHexString,
+    mmr_verifyProof(proof: LeavesProof) -> bool,
+    mmr_verifyProofStateless(#[rename = "mmrRoot"] mmr_root: HashHexString, proof: LeavesProof) -> bool,
     offchain_localStorageGet() -> (), // TODO:
     offchain_localStorageSet() -> (), // TODO:
+    payment_queryFeeDetails(extrinsic: HexString, hash: Option<HashHexString>) -> payment_info::FeeDetails,
     payment_queryInfo(extrinsic: HexString, hash: Option<HashHexString>) -> RuntimeDispatchInfo,
     /// Returns a list of all JSON-RPC methods that are available.
     rpc_methods() -> RpcMethods,
@@ -396,18 +468,22 @@ define_methods! {
     state_getKeysPaged(prefix: Option<HexString>, count: u32, start_key: Option<HexString>, hash: Option<HashHexString>) -> Vec<HexString> [state_getKeysPagedAt],
     state_getMetadata(hash: Option<HashHexString>) -> HexString,
     state_getPairs() -> (), // TODO:
-    state_getReadProof() -> (), // TODO:
+    state_getReadProof(keys: Vec<HexString>, at: Option<HashHexString>) -> ReadProof,
     state_getRuntimeVersion(at: Option<HashHexString>) -> RuntimeVersion<'a> [chain_getRuntimeVersion],
     state_getStorage(key: HexString, hash: Option<HashHexString>) -> HexString [state_getStorageAt],
     state_getStorageHash() -> () [state_getStorageHashAt], // TODO:
     state_getStorageSize() -> () [state_getStorageSizeAt], // TODO:
-    state_queryStorage() -> (), // TODO:
-    state_queryStorageAt(keys: Vec<HexString>, at: Option<HashHexString>) -> Vec<StorageChangeSet>, // TODO:
+    state_queryStorage(keys: Vec<HexString>, #[rename = "fromBlock"] from_block: HashHexString, #[rename = "toBlock"] to_block: Option<HashHexString>) -> Vec<StorageChangeSet>,
+    state_queryStorageAt(keys: Vec<HexString>, at: Option<HashHexString>) -> Vec<StorageChangeSet>,
     state_subscribeRuntimeVersion() -> Cow<'a, str> [chain_subscribeRuntimeVersion],
+    state_traceBlock(block: HashHexString, targets: Option<String>, storage_keys: Option<String>, methods: Option<String>) -> TraceBlockResponse,
     state_subscribeStorage(list: Vec<HexString>) -> Cow<'a, str>,
     state_unsubscribeRuntimeVersion(subscription: Cow<'a, str>) -> bool [chain_unsubscribeRuntimeVersion],
     state_unsubscribeStorage(subscription: Cow<'a, str>) -> bool,
-    system_accountNextIndex(account: AccountId) -> u64,
+    system_accountNextIndex(account: AccountId) -> u64 [account_nextIndex],
+    /// Adds comma-separated log filtering directives, such as `sync=trace`, on top of the ones
+    /// currently active.
+    system_addLogFilter(directives: Cow<'a, str>) -> (),
     system_addReservedPeer() -> (), // TODO:
     system_chain() -> Cow<'a, str>,
     system_chainType() -> Cow<'a, str>,
@@ -418,11 +494,14 @@ define_methods! {
     system_localPeerId() -> Cow<'a, str>,
     /// Returns, as an opaque string, the name of the client serving these JSON-RPC requests.
     system_name() -> Cow<'a, str>,
-    system_networkState() -> (), // TODO:
+    system_networkState() -> NetworkState,
     system_nodeRoles() -> Cow<'a, [NodeRole]>,
     system_peers() -> Vec<SystemPeer>,
     system_properties() -> Box<serde_json::value::RawValue>,
     system_removeReservedPeer() -> (), // TODO:
+    /// Removes all the log filtering directives added with `system_addLogFilter`.
+    system_resetLogFilter() -> (),
+    system_syncState() -> SystemSyncState,
     /// Returns, as an opaque string, the version of the client serving these JSON-RPC requests.
     system_version() -> Cow<'a, str>,
 
@@ -471,9 +550,9 @@ define_methods! {
         hash: HashHexString
     ) -> (),
 
-    chainSpec_unstable_chainName() -> Cow<'a, str>,
-    chainSpec_unstable_genesisHash() -> HashHexString,
-    chainSpec_unstable_properties() -> Box<serde_json::value::RawValue>,
+    chainSpec_unstable_chainName() -> Cow<'a, str> [chainSpec_v1_chainName],
+    chainSpec_unstable_genesisHash() -> HashHexString [chainSpec_v1_genesisHash],
+    chainSpec_unstable_properties() -> Box<serde_json::value::RawValue> [chainSpec_v1_properties],
 
     sudo_unstable_p2pDiscover(multiaddr: Cow<'a, str>) -> (),
     sudo_unstable_version() -> Cow<'a, str>,
@@ -481,6 +560,53 @@ define_methods! {
     transaction_unstable_submitAndWatch(transaction: HexString) -> Cow<'a, str>,
     transaction_unstable_unwatch(subscription: Cow<'a, str>) -> (),
 
+    // The functions below are the stable version of the functions above, as defined in the same
+    // document.
+    chainHead_v1_body(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        hash: HashHexString
+    ) -> ChainHeadBodyCallReturn<'a>,
+    chainHead_v1_call(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        hash: HashHexString,
+        function: Cow<'a, str>,
+        #[rename = "callParameters"] call_parameters: HexString
+    ) -> ChainHeadBodyCallReturn<'a>,
+    chainHead_v1_continue(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        #[rename = "operationId"] operation_id: Cow<'a, str>
+    ) -> (),
+    chainHead_v1_follow(
+        #[rename = "withRuntime"] with_runtime: bool
+    ) -> Cow<'a, str>,
+    chainHead_v1_header(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        hash: HashHexString
+    ) -> Option<HexString>,
+    chainHead_v1_stopOperation(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        #[rename = "operationId"] operation_id: Cow<'a, str>
+    ) -> (),
+    chainHead_v1_storage(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        hash: HashHexString,
+        items: Vec<ChainHeadStorageRequestItem>,
+        #[rename = "childTrie"] child_trie: Option<HexString>
+    ) -> ChainHeadStorageReturn<'a>,
+    chainHead_v1_unfollow(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>
+    ) -> (),
+    chainHead_v1_unpin(
+        #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
+        #[rename = "hashOrHashes"] hash_or_hashes: HashHexStringSingleOrArray
+    ) -> (),
+
+    transaction_v1_broadcast(transaction: HexString) -> Cow<'a, str>,
+    transaction_v1_stop(#[rename = "operationId"] operation_id: Cow<'a, str>) -> (),
+
+    transactionWatch_v1_submitAndWatch(transaction: HexString) -> Cow<'a, str>,
+    transactionWatch_v1_unwatch(subscription: Cow<'a, str>) -> (),
+
     // These functions are a custom addition in smoldot. As of the writing of this comment, there
     // is no plan to standardize them. See <https://github.com/paritytech/smoldot/issues/2245> and
     // <https://github.com/paritytech/smoldot/issues/2456>.
@@ -498,6 +624,7 @@ define_methods! {
     chain_allHead(subscription: Cow<'a, str>, result: Header) -> (),
     state_runtimeVersion(subscription: Cow<'a, str>, result: Option<RuntimeVersion<'a>>) -> (), // TODO: the Option is a custom addition
     state_storage(subscription: Cow<'a, str>, result: StorageChangeSet) -> (),
+    beefy_justifications(subscription: Cow<'a, str>, result: HexString) -> (),
 
     // The functions below are experimental and are defined in the document https://github.com/paritytech/json-rpc-interface-spec/
     chainHead_unstable_bodyEvent(subscription: Cow<'a, str>, result: ChainHeadBodyEvent) -> (),
@@ -506,6 +633,9 @@ define_methods! {
     chainHead_unstable_storageEvent(subscription: Cow<'a, str>, result: ChainHeadStorageEvent<'a>) -> (),
     transaction_unstable_watchEvent(subscription: Cow<'a, str>, result: TransactionWatchEvent<'a>) -> (),
 
+    chainHead_v1_followEvent(subscription: Cow<'a, str>, result: FollowEventV1<'a>) -> (),
+    transactionWatch_v1_watchEvent(subscription: Cow<'a, str>, result: TransactionWatchEvent<'a>) -> (),
+
     // This function is a custom addition in smoldot. As of the writing of this comment, there is
     // no plan to standardize it. See https://github.com/paritytech/smoldot/issues/2245.
     network_unstable_event(subscription: Cow<'a, str>, result: NetworkEvent<'a>) -> (),
@@ -552,6 +682,14 @@ impl fmt::Debug for HexString {
 #[derive(Debug, Clone)]
 pub struct HashHexString(pub [u8; 32]);
 
+/// Either a single hash or a list of hashes. Used by `chainHead_v1_unpin`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum HashHexStringSingleOrArray {
+    Single(HashHexString),
+    Array(Vec<HashHexString>),
+}
+
 // TODO: not great for type in public API
 impl<'a> serde::Deserialize<'a> for HashHexString {
     fn deserialize<D>(deserializer: D) -> Result<HashHexString, D::Error>
@@ -698,6 +836,116 @@ pub enum FollowEvent<'a> {
     Stop {},
 }
 
+/// Event generated by `chainHead_v1_follow`.
+///
+/// Contrary to the `unstable` version of the API, the outcome of the operations started with
+/// `chainHead_v1_body`, `chainHead_v1_call` and `chainHead_v1_storage` is reported through the
+/// follow subscription.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "event")]
+pub enum FollowEventV1<'a> {
+    #[serde(rename = "initialized")]
+    Initialized {
+        #[serde(rename = "finalizedBlockHashes")]
+        finalized_block_hashes: Vec<HashHexString>,
+        #[serde(
+            rename = "finalizedBlockRuntime",
+            skip_serializing_if = "Option::is_none"
+        )]
+        finalized_block_runtime: Option<MaybeRuntimeSpec<'a>>,
+    },
+    #[serde(rename = "newBlock")]
+    NewBlock {
+        #[serde(rename = "blockHash")]
+        block_hash: HashHexString,
+        #[serde(rename = "parentBlockHash")]
+        parent_block_hash: HashHexString,
+        #[serde(rename = "newRuntime")]
+        new_runtime: Option<MaybeRuntimeSpec<'a>>,
+    },
+    #[serde(rename = "bestBlockChanged")]
+    BestBlockChanged {
+        #[serde(rename = "bestBlockHash")]
+        best_block_hash: HashHexString,
+    },
+    #[serde(rename = "finalized")]
+    Finalized {
+        #[serde(rename = "finalizedBlockHashes")]
+        finalized_blocks_hashes: Vec<HashHexString>,
+        #[serde(rename = "prunedBlockHashes")]
+        pruned_blocks_hashes: Vec<HashHexString>,
+    },
+    #[serde(rename = "operationBodyDone")]
+    OperationBodyDone {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+        value: Vec<HexString>,
+    },
+    #[serde(rename = "operationCallDone")]
+    OperationCallDone {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+        output: HexString,
+    },
+    #[serde(rename = "operationInaccessible")]
+    OperationInaccessible {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+    },
+    #[serde(rename = "operationStorageItems")]
+    OperationStorageItems {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+        items: Vec<ChainHeadStorageResponseItem>,
+    },
+    #[serde(rename = "operationStorageDone")]
+    OperationStorageDone {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+    },
+    #[serde(rename = "operationWaitingForContinue")]
+    OperationWaitingForContinue {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+    },
+    #[serde(rename = "operationError")]
+    OperationError {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+        error: Cow<'a, str>,
+    },
+    #[serde(rename = "stop")]
+    Stop {},
+}
+
+/// Value returned by `chainHead_v1_body` and `chainHead_v1_call`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "result")]
+pub enum ChainHeadBodyCallReturn<'a> {
+    #[serde(rename = "started")]
+    Started {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+    },
+    #[serde(rename = "limitReached")]
+    LimitReached {},
+}
+
+/// Value returned by `chainHead_v1_storage`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "result")]
+pub enum ChainHeadStorageReturn<'a> {
+    #[serde(rename = "started")]
+    Started {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+        #[serde(rename = "discardedItems")]
+        discarded_items: usize,
+    },
+    #[serde(rename = "limitReached")]
+    LimitReached {},
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "event")]
 pub enum ChainHeadBodyEvent {
@@ -735,6 +983,41 @@ pub enum ChainHeadStorageEvent<'a> {
     Disjoint {},
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChainHeadStorageRequestItem {
+    pub key: HexString,
+    #[serde(rename = "type")]
+    pub ty: ChainHeadStorageType,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ChainHeadStorageType {
+    #[serde(rename = "value")]
+    Value,
+    #[serde(rename = "hash")]
+    Hash,
+    #[serde(rename = "closestDescendantMerkleValue")]
+    ClosestDescendantMerkleValue,
+    #[serde(rename = "descendantsValues")]
+    DescendantsValues,
+    #[serde(rename = "descendantsHashes")]
+    DescendantsHashes,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChainHeadStorageResponseItem {
+    pub key: HexString,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<HexString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<HexString>,
+    #[serde(
+        rename = "closestDescendantMerkleValue",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub closest_descendant_merkle_value: Option<HexString>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "event")]
 pub enum TransactionWatchEvent<'a> {
@@ -939,6 +1222,14 @@ pub struct HeaderDigest {
     pub logs: Vec<HexString>,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LeavesProof {
+    #[serde(rename = "blockHash")]
+    pub block_hash: HashHexString,
+    pub leaves: HexString,
+    pub proof: HexString,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct NetworkConfig {
     #[serde(rename = "totalAttempts")]
@@ -992,6 +1283,78 @@ pub struct RuntimeSpec<'a> {
     pub apis: HashMap<HexString, u32, fnv::FnvBuildHasher>,
 }
 
+/// Return value of `state_getReadProof`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReadProof {
+    /// Hash of the block the proof was generated against.
+    pub at: HashHexString,
+    /// List of entries of the Merkle proof.
+    pub proof: Vec<HexString>,
+}
+
+/// Return value of `state_traceBlock`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum TraceBlockResponse {
+    #[serde(rename = "traceError")]
+    TraceError(TraceError),
+    #[serde(rename = "blockTrace")]
+    BlockTrace(BlockTrace),
+}
+
+/// See [`TraceBlockResponse`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TraceError {
+    pub error: String,
+}
+
+/// See [`TraceBlockResponse`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BlockTrace {
+    #[serde(rename = "blockHash")]
+    pub block_hash: HashHexString,
+    #[serde(rename = "parentHash")]
+    pub parent_hash: HashHexString,
+    /// Comma-separated list of targets that spans and events have been filtered with.
+    #[serde(rename = "tracingTargets")]
+    pub tracing_targets: String,
+    /// Comma-separated list of hexadecimal storage key prefixes that events have been filtered
+    /// with.
+    #[serde(rename = "storageKeys")]
+    pub storage_keys: String,
+    /// Comma-separated list of method names that events have been filtered with.
+    pub methods: String,
+    pub spans: Vec<TraceSpan>,
+    pub events: Vec<TraceEvent>,
+}
+
+/// See [`BlockTrace::spans`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TraceSpan {
+    pub id: u64,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<u64>,
+    pub name: String,
+    pub target: String,
+    /// `true` if the span has been emitted by the runtime.
+    pub wasm: bool,
+}
+
+/// See [`BlockTrace::events`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TraceEvent {
+    pub target: String,
+    pub data: TraceEventData,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<u64>,
+}
+
+/// See [`TraceEvent::data`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TraceEventData {
+    #[serde(rename = "stringValues")]
+    pub string_values: BTreeMap<String, String>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct RuntimeVersion<'a> {
     #[serde(rename = "specName")]
@@ -1039,6 +1402,66 @@ pub struct SystemHealth {
     pub should_have_peers: bool,
 }
 
+/// Return value of `system_syncState`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SystemSyncState {
+    /// Height of the block the node started syncing from.
+    #[serde(rename = "startingBlock")]
+    pub starting_block: u64,
+    /// Height of the current best block of the node.
+    #[serde(rename = "currentBlock")]
+    pub current_block: u64,
+    /// Height of the highest block known to the node, including the best blocks reported by
+    /// its peers.
+    #[serde(rename = "highestBlock")]
+    pub highest_block: u64,
+}
+
+/// Return value of `system_networkState`. Mirrors the format used by Substrate full nodes.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetworkState {
+    #[serde(rename = "peerId")]
+    pub peer_id: String,
+    #[serde(rename = "listenedAddresses")]
+    pub listened_addresses: Vec<String>,
+    #[serde(rename = "externalAddresses")]
+    pub external_addresses: Vec<String>,
+    #[serde(rename = "connectedPeers")]
+    pub connected_peers: HashMap<String, NetworkStateConnectedPeer, fnv::FnvBuildHasher>,
+    #[serde(rename = "notConnectedPeers")]
+    pub not_connected_peers: HashMap<String, NetworkStateNotConnectedPeer, fnv::FnvBuildHasher>,
+    /// Not present in the Substrate format.
+    #[serde(rename = "pendingDials")]
+    pub pending_dials: Vec<NetworkStatePendingDial>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetworkStateConnectedPeer {
+    pub endpoint: NetworkStatePeerEndpoint,
+    #[serde(rename = "knownAddresses")]
+    pub known_addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub enum NetworkStatePeerEndpoint {
+    /// We are the dialer. Contains the address that has been dialed.
+    #[serde(rename = "dialing")]
+    Dialing(String),
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetworkStateNotConnectedPeer {
+    #[serde(rename = "knownAddresses")]
+    pub known_addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetworkStatePendingDial {
+    #[serde(rename = "peerId")]
+    pub peer_id: String,
+    pub address: String,
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SystemPeer {
     #[serde(rename = "peerId")]
@@ -1120,28 +1543,30 @@ impl serde::Serialize for Block {
     where
         S: serde::Serializer,
     {
+        // Mirrors the `SignedBlock` type of Substrate, where the justifications are found
+        // alongside the block rather than within it.
         #[derive(serde::Serialize)]
         struct SerdeBlock<'a> {
             block: SerdeBlockInner<'a>,
+            justifications: Option<Vec<Vec<Vec<u8>>>>,
         }
 
         #[derive(serde::Serialize)]
         struct SerdeBlockInner<'a> {
-            extrinsics: &'a [HexString],
             header: &'a Header,
-            justifications: Option<Vec<Vec<Vec<u8>>>>,
+            extrinsics: &'a [HexString],
         }
 
         SerdeBlock {
             block: SerdeBlockInner {
-                extrinsics: &self.extrinsics,
                 header: &self.header,
-                justifications: self.justifications.as_ref().map(|list| {
-                    list.iter()
-                        .map(|(e, j)| vec![e.to_vec(), j.clone()])
-                        .collect()
-                }),
+                extrinsics: &self.extrinsics,
             },
+            justifications: self.justifications.as_ref().map(|list| {
+                list.iter()
+                    .map(|(e, j)| vec![e.to_vec(), j.clone()])
+                    .collect()
+            }),
         }
         .serialize(serializer)
     }
@@ -1174,6 +1599,40 @@ impl serde::Serialize for RuntimeDispatchInfo {
     }
 }
 
+impl serde::Serialize for payment_info::FeeDetails {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        // The amounts are sent back as hexadecimal strings, and the tip isn't sent back, in
+        // accordance with the behavior of Substrate.
+        #[derive(serde::Serialize)]
+        struct SerdeFeeDetails {
+            #[serde(rename = "inclusionFee")]
+            inclusion_fee: Option<SerdeInclusionFee>,
+        }
+
+        #[derive(serde::Serialize)]
+        struct SerdeInclusionFee {
+            #[serde(rename = "baseFee")]
+            base_fee: String,
+            #[serde(rename = "lenFee")]
+            len_fee: String,
+            #[serde(rename = "adjustedWeightFee")]
+            adjusted_weight_fee: String,
+        }
+
+        SerdeFeeDetails {
+            inclusion_fee: self.inclusion_fee.map(|fee| SerdeInclusionFee {
+                base_fee: format!("0x{:x}", fee.base_fee),
+                len_fee: format!("0x{:x}", fee.len_fee),
+                adjusted_weight_fee: format!("0x{:x}", fee.adjusted_weight_fee),
+            }),
+        }
+        .serialize(serializer)
+    }
+}
+
 impl serde::Serialize for SystemHealth {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -1237,4 +1696,164 @@ mod tests {
             })
         ));
     }
+
+    #[test]
+    fn chain_head_v1_storage_items() {
+        let (_, call) = super::parse_json_call(
+            r#"{"jsonrpc":"2.0","id":2,"method":"chainHead_v1_storage","params":["foo","0x0000000000000000000000000000000000000000000000000000000000000000",[{"key":"0x1234","type":"value"},{"key":"0xab","type":"hash"}],null]}"#,
+        )
+        .unwrap();
+
+        match call {
+            super::MethodCall::chainHead_v1_storage {
+                items, child_trie, ..
+            } => {
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[0].key.0, [0x12, 0x34]);
+                assert_eq!(items[0].ty, super::ChainHeadStorageType::Value);
+                assert_eq!(items[1].ty, super::ChainHeadStorageType::Hash);
+                assert!(child_trie.is_none());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn cheap_methods_exist() {
+        // Makes sure that no method has been renamed without updating the list.
+        for method in super::CHEAP_METHODS {
+            assert!(
+                !matches!(
+                    super::MethodCall::from_defs(method, None),
+                    Err(super::MethodError::UnknownMethod(_))
+                ),
+                "{method}"
+            );
+        }
+
+        assert!(super::is_cheap_method("chainSpec_v1_chainName"));
+        assert!(!super::is_cheap_method("state_getStorage"));
+        assert!(!super::is_cheap_method("chainHead_v1_call"));
+        assert!(!super::is_cheap_method("system_accountNextIndex"));
+        assert!(!super::is_cheap_method("foo"));
+    }
+
+    #[test]
+    fn chain_spec_v1_alias() {
+        let (_, call) =
+            super::parse_json_call(r#"{"jsonrpc":"2.0","id":2,"method":"chainSpec_v1_chainName"}"#)
+                .unwrap();
+
+        assert!(matches!(
+            call,
+            super::MethodCall::chainSpec_unstable_chainName {}
+        ));
+    }
+
+    #[test]
+    fn chain_head_v1_unpin_hash_or_hashes() {
+        let (_, call) = super::parse_json_call(
+            r#"{"jsonrpc":"2.0","id":2,"method":"chainHead_v1_unpin","params":["foo","0x0000000000000000000000000000000000000000000000000000000000000000"]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            call,
+            super::MethodCall::chainHead_v1_unpin {
+                hash_or_hashes: super::HashHexStringSingleOrArray::Single(_),
+                ..
+            }
+        ));
+
+        let (_, call) = super::parse_json_call(
+            r#"{"jsonrpc":"2.0","id":2,"method":"chainHead_v1_unpin","params":["foo",["0x0000000000000000000000000000000000000000000000000000000000000000","0x0101010101010101010101010101010101010101010101010101010101010101"]]}"#,
+        )
+        .unwrap();
+        match call {
+            super::MethodCall::chainHead_v1_unpin {
+                hash_or_hashes: super::HashHexStringSingleOrArray::Array(hashes),
+                ..
+            } => {
+                assert_eq!(hashes.len(), 2);
+                assert_eq!(hashes[1].0, [1; 32]);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn chain_head_v1_operation_serialization() {
+        assert_eq!(
+            serde_json::to_string(&super::ChainHeadStorageReturn::Started {
+                operation_id: "5".into(),
+                discarded_items: 0,
+            })
+            .unwrap(),
+            r#"{"result":"started","operationId":"5","discardedItems":0}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&super::ChainHeadBodyCallReturn::LimitReached {}).unwrap(),
+            r#"{"result":"limitReached"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&super::FollowEventV1::OperationCallDone {
+                operation_id: "5".into(),
+                output: super::HexString(vec![0xab]),
+            })
+            .unwrap(),
+            r#"{"event":"operationCallDone","operationId":"5","output":"0xab"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&super::FollowEventV1::Initialized {
+                finalized_block_hashes: vec![super::HashHexString([0; 32])],
+                finalized_block_runtime: None,
+            })
+            .unwrap(),
+            format!(
+                r#"{{"event":"initialized","finalizedBlockHashes":["0x{}"]}}"#,
+                "00".repeat(32)
+            )
+        );
+    }
+
+    #[test]
+    fn block_serialization() {
+        let block = super::Block {
+            extrinsics: vec![super::HexString(vec![0x12, 0x34])],
+            header: super::Header {
+                parent_hash: super::HashHexString([0; 32]),
+                extrinsics_root: super::HashHexString([1; 32]),
+                state_root: super::HashHexString([2; 32]),
+                number: 10,
+                digest: super::HeaderDigest { logs: Vec::new() },
+            },
+            justifications: Some(vec![(*b"FRNK", vec![5, 6])]),
+        };
+
+        assert_eq!(
+            serde_json::to_string(&block).unwrap(),
+            format!(
+                r#"{{"block":{{"header":{{"parentHash":"0x{}","extrinsicsRoot":"0x{}","stateRoot":"0x{}","number":"0xa","digest":{{"logs":[]}}}},"extrinsics":["0x1234"]}},"justifications":[[[70,82,78,75],[5,6]]]}}"#,
+                "00".repeat(32),
+                "01".repeat(32),
+                "02".repeat(32)
+            )
+        );
+    }
+
+    #[test]
+    fn fee_details_serialization() {
+        let details = super::payment_info::FeeDetails {
+            inclusion_fee: Some(super::payment_info::InclusionFee {
+                base_fee: 125000000,
+                len_fee: 0,
+                adjusted_weight_fee: 3,
+            }),
+            tip: 5,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&details).unwrap(),
+            r#"{"inclusionFee":{"baseFee":"0x7735940","lenFee":"0x0","adjustedWeightFee":"0x3"}}"#
+        );
+    }
 }
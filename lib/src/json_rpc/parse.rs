@@ -17,7 +17,7 @@
 
 //! Parse JSON-RPC method calls and notifications, and build responses messages.
 
-use alloc::{borrow::Cow, string::String};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 
 /// Parses a JSON-encoded RPC method call or notification.
 pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
@@ -45,6 +45,66 @@ pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
     })
 }
 
+/// Returns `true` if the given JSON-encoded message is a batch of calls, as defined in the
+/// JSON-RPC 2.0 specification.
+///
+/// This function only looks at the first character of the message and doesn't check whether the
+/// batch is valid. Use [`parse_batch`] in order to parse it.
+pub fn is_batch(message_json: &str) -> bool {
+    message_json.trim_start().starts_with('[')
+}
+
+/// Parses a JSON-encoded batch of RPC method calls and notifications.
+///
+/// Returns the list of JSON-encoded elements of the batch, in the same order as in the batch.
+/// Each element can then be parsed with [`parse_call`].
+///
+/// Returns an error if the batch isn't a valid JSON array or if it is empty. The JSON-RPC 2.0
+/// specification forbids empty batches.
+pub fn parse_batch(batch_json: &str) -> Result<Vec<&str>, ParseError> {
+    let elements: Vec<&serde_json::value::RawValue> =
+        serde_json::from_str(batch_json).map_err(ParseError)?;
+
+    if elements.is_empty() {
+        return Err(ParseError(serde::de::Error::custom("empty batch")));
+    }
+
+    Ok(elements.into_iter().map(|e| e.get()).collect())
+}
+
+/// Builds a JSON response to a batch of calls, given the list of JSON-encoded responses to the
+/// individual calls.
+///
+/// # Example
+///
+/// ```
+/// # use smoldot::json_rpc::parse;
+/// let response = parse::build_batch_response(
+///     [
+///         parse::build_success_response("1", "true"),
+///         parse::build_success_response("2", "false"),
+///     ]
+///     .iter()
+///     .map(|r| &r[..]),
+/// );
+///
+/// assert_eq!(
+///     response,
+///     r#"[{"jsonrpc":"2.0","id":1,"result":true},{"jsonrpc":"2.0","id":2,"result":false}]"#
+/// );
+/// ```
+pub fn build_batch_response<'a>(responses_json: impl Iterator<Item = &'a str>) -> String {
+    let mut out = String::from("[");
+    for (index, response) in responses_json.enumerate() {
+        if index != 0 {
+            out.push(',');
+        }
+        out.push_str(response);
+    }
+    out.push(']');
+    out
+}
+
 /// Builds a JSON call.
 ///
 /// `method` must be the name of the method to call. `params_json` must be the JSON-formatted
@@ -381,6 +441,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_batch_works() {
+        let message = r#" [{"jsonrpc":"2.0","id":1,"method":"foo","params":[]}, {"jsonrpc":"2.0","method":"bar"}]"#;
+        assert!(super::is_batch(message));
+
+        let elements = super::parse_batch(message).unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(super::parse_call(elements[0]).unwrap().method, "foo");
+        assert_eq!(super::parse_call(elements[1]).unwrap().method, "bar");
+    }
+
+    #[test]
+    fn parse_batch_empty() {
+        assert!(super::is_batch("[]"));
+        assert!(super::parse_batch("[]").is_err());
+    }
+
+    #[test]
+    fn parse_batch_not_array() {
+        let message = r#"{"jsonrpc":"2.0","id":1,"method":"foo","params":[]}"#;
+        assert!(!super::is_batch(message));
+        assert!(super::parse_batch(message).is_err());
+    }
+
     #[test]
     fn parse_bad_id() {
         assert!(
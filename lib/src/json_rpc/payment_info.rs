@@ -54,10 +54,91 @@ pub fn decode_payment_info(
     }
 }
 
+/// Name of the runtime function to call in order to obtain the details of the payment fees.
+///
+/// The input to pass to this function is the same as for [`PAYMENT_FEES_FUNCTION_NAME`], and
+/// can be obtained with [`payment_info_parameters`].
+pub const FEE_DETAILS_FUNCTION_NAME: &str = "TransactionPaymentApi_query_fee_details";
+
+/// Details of the fees of a transaction, as returned by the
+/// `TransactionPaymentApi_query_fee_details` runtime function.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FeeDetails {
+    /// Fees that must be paid in order for the transaction to be included in a block. `None`
+    /// for unsigned transactions.
+    pub inclusion_fee: Option<InclusionFee>,
+    /// Tip added by the sender of the transaction.
+    pub tip: u128,
+}
+
+/// See [`FeeDetails::inclusion_fee`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InclusionFee {
+    /// Minimum amount that must be paid for any transaction.
+    pub base_fee: u128,
+    /// Amount that depends on the length of the transaction.
+    pub len_fee: u128,
+    /// Amount that depends on the weight of the transaction, multiplied by the current fee
+    /// multiplier of the chain.
+    pub adjusted_weight_fee: u128,
+}
+
+impl InclusionFee {
+    /// Returns the sum of all the fees, or `None` in case of overflow.
+    pub fn total(&self) -> Option<u128> {
+        self.base_fee
+            .checked_add(self.len_fee)?
+            .checked_add(self.adjusted_weight_fee)
+    }
+}
+
+/// Attempt to decode the output of the `TransactionPaymentApi_query_fee_details` runtime call.
+pub fn decode_fee_details(scale_encoded: &[u8]) -> Result<FeeDetails, DecodeError> {
+    // The output is made of an `Option` containing three `Balance`s, followed with a
+    // `Balance`. Rather than parsing the metadata in order to determine the actual type of
+    // `Balance`, its size is deduced from the length of the output.
+    let (has_inclusion_fee, balances) = match scale_encoded.split_first() {
+        Some((0, rest)) => (false, rest),
+        Some((1, rest)) => (true, rest),
+        _ => return Err(DecodeError::ParseError),
+    };
+
+    let num_balances = if has_inclusion_fee { 4 } else { 1 };
+    if balances.is_empty() || balances.len() % num_balances != 0 {
+        return Err(DecodeError::ParseError);
+    }
+    let balance_size = balances.len() / num_balances;
+    if balance_size > 16 {
+        return Err(DecodeError::ParseError);
+    }
+
+    let mut balances = balances.chunks(balance_size).map(|bytes| {
+        let mut le_bytes = [0; 16];
+        le_bytes[..bytes.len()].copy_from_slice(bytes);
+        u128::from_le_bytes(le_bytes)
+    });
+
+    let inclusion_fee = if has_inclusion_fee {
+        Some(InclusionFee {
+            base_fee: balances.next().unwrap(),
+            len_fee: balances.next().unwrap(),
+            adjusted_weight_fee: balances.next().unwrap(),
+        })
+    } else {
+        None
+    };
+
+    Ok(FeeDetails {
+        inclusion_fee,
+        tip: balances.next().unwrap(),
+    })
+}
+
 /// Potential error when decoding payment information runtime output.
 #[derive(Debug, derive_more::Display)]
 pub enum DecodeError {
-    /// Failed to parse the return value of `TransactionPaymentApi_query_info`.
+    /// Failed to parse the return value of `TransactionPaymentApi_query_info` or
+    /// `TransactionPaymentApi_query_fee_details`.
     ParseError,
     /// The `TransactionPaymentApi` API uses a version that smoldot doesn't support.
     UnknownRuntimeVersion,
@@ -133,3 +214,45 @@ fn nom_decode_payment_info<'a, E: nom::error::ParseError<&'a [u8]>>(
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn decode_fee_details_with_inclusion_fee() {
+        let mut encoded = vec![1];
+        for n in [1u128, 2, 3, 4] {
+            encoded.extend_from_slice(&n.to_le_bytes());
+        }
+
+        let details = super::decode_fee_details(&encoded).unwrap();
+        assert_eq!(
+            details,
+            super::FeeDetails {
+                inclusion_fee: Some(super::InclusionFee {
+                    base_fee: 1,
+                    len_fee: 2,
+                    adjusted_weight_fee: 3,
+                }),
+                tip: 4,
+            }
+        );
+        assert_eq!(details.inclusion_fee.unwrap().total(), Some(6));
+    }
+
+    #[test]
+    fn decode_fee_details_without_inclusion_fee() {
+        let mut encoded = vec![0];
+        encoded.extend_from_slice(&12u64.to_le_bytes());
+
+        let details = super::decode_fee_details(&encoded).unwrap();
+        assert_eq!(details.inclusion_fee, None);
+        assert_eq!(details.tip, 12);
+    }
+
+    #[test]
+    fn decode_fee_details_invalid_length() {
+        assert!(super::decode_fee_details(&[1, 0, 0, 0, 0, 0]).is_err());
+        assert!(super::decode_fee_details(&[0]).is_err());
+        assert!(super::decode_fee_details(&[2, 0]).is_err());
+    }
+}
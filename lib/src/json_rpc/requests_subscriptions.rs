@@ -36,7 +36,8 @@
 //! There should be:
 //!
 //! - One lightweight task for each client currently connected to the server.
-//! - A fixed number of lightweight tasks (e.g. 16) dedicated to answering requests.
+//! - For each [`RequestLane`], a fixed number of lightweight tasks (e.g. 16 for
+//! [`RequestLane::Expensive`] and 4 for [`RequestLane::Cheap`]) dedicated to answering requests.
 //! - A fixed number of lightweight tasks (e.g. 8) dedicated to processing subscription tasks by
 //! calling [`RequestsSubscriptions::run_subscription_task`] in a loop.
 //!
@@ -46,7 +47,7 @@
 //!
 //! - Calls [`RequestsSubscriptions::add_client`], denying the client if the function returns an
 //!   error.
-//! - Repeatedly polls the socket for a new request then calls
+//! - Repeatedly polls the socket for a new request, determines its [`RequestLane`], then calls
 //!   [`RequestsSubscriptions::queue_client_request`].
 //! - At the same time (for example in a `select!` block) calls
 //!   [`RequestsSubscriptions::next_response`] then sends the response to the socket.
@@ -72,12 +73,14 @@
 //! ## Requests
 //!
 //! There should be a certain, fixed, number of lightweight tasks dedicated to pulling requests
-//! from the state machine and answering them.
+//! from the state machine and answering them. Requests are split in two [`RequestLane`]s, and
+//! each task is dedicated to one lane. Requests that are cheap to answer are thus never stuck
+//! behind requests that take a long time to answer.
 //!
 //! Each of these lightweight tasks should:
 //!
-//! - Call [`RequestsSubscriptions::next_request`]. This function call sleeps until there is a
-//! request available.
+//! - Call [`RequestsSubscriptions::next_request`] with its lane. This function call sleeps until
+//! there is a request available.
 //! - Parse the request that was returned and generate its response. This step should be relatively
 //! fast (e.g. not more than one second), but can liberally perform asynchronous requests, lock
 //! mutexes, etc.
@@ -88,6 +91,12 @@
 //! enough, back-pressure will be applied onto [`RequestsSubscriptions::queue_client_request`],
 //! which in turn applies back-pressure onto the JSON-RPC clients.
 //!
+//! Requests are pulled from the clients in a round-robin way: a client that sends a lot of
+//! requests can't delay the requests of other clients by more than one request per client.
+//! Furthermore, the number of requests of each client that are being answered at the same time
+//! is capped to [`Config::max_parallel_requests_per_client`], in order to prevent a single client
+//! from occupying all the request-answering tasks.
+//!
 //! ## Subscriptions
 //!
 //! If a client-sent request requires starting a subscription, one of the
@@ -151,6 +160,11 @@ pub struct Config {
     /// time before the first one has been responded to. Any additional request will need to wait.
     pub max_requests_per_client: NonZeroU32,
 
+    /// For each client, the maximum number of JSON-RPC requests that can be returned by
+    /// [`RequestsSubscriptions::next_request`] and not responded to yet. Any additional request
+    /// stays in queue while the requests of other clients are being processed.
+    pub max_parallel_requests_per_client: NonZeroU32,
+
     /// Maximum number of active subscriptions that each client can start. Any additional
     /// subscription will be immediately rejected.
     pub max_subscriptions_per_client: u32,
@@ -160,23 +174,55 @@ pub struct Config {
     pub max_clients: u32,
 }
 
+/// Category of a request.
+///
+/// Requests of each lane are pulled separately using [`RequestsSubscriptions::next_request`].
+/// See the module-level documentation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RequestLane {
+    /// Request that can be answered quickly, typically from locally-available information.
+    Cheap,
+    /// Request that might take a long time to be answered, for example because it requires
+    /// networking.
+    Expensive,
+}
+
+impl RequestLane {
+    /// Index of the lane within the arrays of [`RequestsSubscriptions`] and [`ClientInner`].
+    fn index(self) -> usize {
+        match self {
+            RequestLane::Cheap => 0,
+            RequestLane::Expensive => 1,
+        }
+    }
+}
+
+/// Number of variants of [`RequestLane`].
+const NUM_LANES: usize = 2;
+
 pub struct RequestsSubscriptions<TSubMsg> {
     /// List of all clients of the state machine. Locked only when adding and removing clients.
     clients: Mutex<Clients<TSubMsg>>,
 
-    /// List of requests sent by a client and not yet pulled by
-    /// [`RequestsSubscriptions::next_request`].
+    /// For each [`RequestLane`], queue of clients that have at least one request of this lane
+    /// that hasn't been pulled by [`RequestsSubscriptions::next_request`] yet.
+    ///
+    /// Clients are popped from the front of the queue in order to pull one of their requests,
+    /// then pushed back at the end if they have more requests. This guarantees that requests
+    /// are pulled from clients in a round-robin way.
     ///
-    /// Can contain obsolete clients, in which case the entry should simply be ignored.
+    /// Each client is present at most once in each queue, as indicated by
+    /// [`ClientInner::in_ready_clients`]. Can contain obsolete clients, in which case the entry
+    /// should simply be ignored.
     ///
     /// We use an unbounded list because the maximum number of clients can be changed dynamically
     /// using [`RequestsSubscriptions::set_max_clients`], in which case it would be impossible
     /// to update the size of this list.
-    // TODO: what about entries of obsolete clients clogging the queue? how do we deal with this?
-    unpulled_requests: crossbeam_queue::SegQueue<(String, Weak<ClientInner<TSubMsg>>)>,
+    ready_clients: [crossbeam_queue::SegQueue<Weak<ClientInner<TSubMsg>>>; NUM_LANES],
 
-    /// Event notified whenever an element is pushed to [`RequestsSubscriptions::unpulled_requests`].
-    new_unpulled_request: event_listener::Event,
+    /// For each [`RequestLane`], event notified whenever an element is pushed to the
+    /// corresponding queue of [`RequestsSubscriptions::ready_clients`].
+    new_ready_client: [event_listener::Event; NUM_LANES],
 
     /// Queue of subscription-related tasks. Run manually using
     /// [`RequestsSubscriptions::run_subscription_task`].
@@ -201,6 +247,9 @@ pub struct RequestsSubscriptions<TSubMsg> {
     /// earlier requests to have been answered.
     max_requests_per_client: usize,
 
+    /// Maximum number of requests of each client that can be pulled and not responded to yet.
+    max_parallel_requests_per_client: usize,
+
     /// Maximum number of subscriptions each client can have active before new subscriptions are
     /// rejected.
     max_subscriptions_per_client: usize,
@@ -218,19 +267,26 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             usize::try_from(config.max_subscriptions_per_client).unwrap_or(usize::max_value());
         let max_requests_per_client =
             usize::try_from(config.max_requests_per_client.get()).unwrap_or(usize::max_value());
+        let max_parallel_requests_per_client =
+            usize::try_from(config.max_parallel_requests_per_client.get())
+                .unwrap_or(usize::max_value());
 
         Self {
             clients: Mutex::new(Clients {
                 list: hashbrown::HashMap::with_capacity_and_hasher(8, Default::default()),
                 next_id: 0,
             }),
-            unpulled_requests: crossbeam_queue::SegQueue::new(),
+            ready_clients: [
+                crossbeam_queue::SegQueue::new(),
+                crossbeam_queue::SegQueue::new(),
+            ],
+            new_ready_client: [event_listener::Event::new(), event_listener::Event::new()],
             subscriptions_tasks: executor::TasksQueue::new(),
-            new_unpulled_request: event_listener::Event::new(),
             next_request_id: atomic::Atomic::new(0),
             next_subscription_id: atomic::Atomic::new(0),
             max_clients: AtomicUsize::new(max_clients),
             max_requests_per_client,
+            max_parallel_requests_per_client,
             max_subscriptions_per_client,
         }
     }
@@ -250,6 +306,27 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         self.max_clients.store(max_clients, Ordering::Relaxed);
     }
 
+    /// Returns the maximum number of requests each client can have in flight. This is the value
+    /// that was passed as [`Config::max_requests_per_client`].
+    pub fn max_requests_per_client(&self) -> usize {
+        self.max_requests_per_client
+    }
+
+    /// Returns the number of requests of the given client that have been queued and whose
+    /// response hasn't been returned by [`RequestsSubscriptions::next_response`] yet.
+    ///
+    /// If this value reaches [`RequestsSubscriptions::max_requests_per_client`], then
+    /// [`RequestsSubscriptions::try_queue_client_request`] returns an error.
+    ///
+    /// Returns 0 if the [`ClientId`] is stale or invalid.
+    pub fn num_requests_in_fly(&self, client: &ClientId) -> usize {
+        client
+            .1
+            .upgrade()
+            .and_then(|c| Arc::downcast::<ClientInner<TSubMsg>>(c).ok())
+            .map_or(0, |c| c.total_requests_in_fly.load(Ordering::Relaxed))
+    }
+
     /// Adds a new client to the state machine. A new [`ClientId`] is attributed.
     ///
     /// Can return an error if the maximum simultaneous number of clients has been reached.
@@ -283,6 +360,12 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             total_requests_in_fly_dec_or_dead: event_listener::Event::new(),
             dead: AtomicBool::new(false),
             total_requests_in_fly: AtomicUsize::new(0),
+            unpulled_requests: [
+                crossbeam_queue::SegQueue::new(),
+                crossbeam_queue::SegQueue::new(),
+            ],
+            in_ready_clients: [AtomicBool::new(false), AtomicBool::new(false)],
+            num_requests_processing: AtomicUsize::new(0),
             guarded: Mutex::new(ClientInnerGuarded {
                 pending_requests: hashbrown::HashSet::with_capacity_and_hasher(
                     self.max_requests_per_client,
@@ -468,7 +551,12 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
     /// [`RequestsSubscriptions::next_response`] has returned a response to a previous request.
     ///
     /// Has no effect if the [`ClientId`] is stale or invalid.
-    pub async fn queue_client_request(&self, client: &ClientId, request: String) {
+    pub async fn queue_client_request(
+        &self,
+        client: &ClientId,
+        request: String,
+        lane: RequestLane,
+    ) {
         let client = match client
             .1
             .upgrade()
@@ -528,10 +616,9 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
 
         // We can now insert the request.
         // Note that it is possible for `client.dead` to have become true in the meanwhile, but
-        // this is not a problem as `unpulled_requests` is allowed to contain obsolete requests.
-        self.unpulled_requests
-            .push((request, Arc::downgrade(&client)));
-        self.new_unpulled_request.notify_additional(1);
+        // this is not a problem as `ready_clients` is allowed to contain obsolete clients.
+        client.unpulled_requests[lane.index()].push(request);
+        self.schedule_client(&client, lane.index());
     }
 
     /// Similar to [`RequestsSubscriptions::queue_client_request`], but succeeds or fails
@@ -545,6 +632,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         &self,
         client: &ClientId,
         request: String,
+        lane: RequestLane,
     ) -> Result<(), TryQueueClientRequestError> {
         let client = match client
             .1
@@ -579,27 +667,80 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         }
 
         // We can now insert the request.
-        // Note that it is possible for `client.dead` to have become true in the meanwhile, but
-        // this is not a problem as `unpulled_requests` is allowed to contain obsolete requests.
-        self.unpulled_requests
-            .push((request, Arc::downgrade(&client)));
-        self.new_unpulled_request.notify_additional(1);
+        // See the note in `queue_client_request`.
+        client.unpulled_requests[lane.index()].push(request);
+        self.schedule_client(&client, lane.index());
         Ok(())
     }
 
-    /// Waits until a request has been queued using
+    /// Similar to [`RequestsSubscriptions::try_queue_client_request`], but queues multiple
+    /// requests at once. Either all the requests are queued, or none of them.
+    ///
+    /// This is typically used in order to process a batch of JSON-RPC requests. The requests of
+    /// each [`RequestLane`] are pulled in the same order as they are in the list.
+    ///
+    /// Returns `Ok` and silently discards the requests if the [`ClientId`] is stale or invalid.
+    pub fn try_queue_client_requests(
+        &self,
+        client: &ClientId,
+        requests: Vec<(String, RequestLane)>,
+    ) -> Result<(), TryQueueClientRequestsError> {
+        let client = match client
+            .1
+            .upgrade()
+            .and_then(|c| Arc::downcast::<ClientInner<TSubMsg>>(c).ok())
+        {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        if client.dead.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // Try increase `total_requests_in_fly` by the number of requests, capping at a maximum
+        // of `max_requests_per_client`.
+        let failed_to_increase = client
+            .total_requests_in_fly
+            .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |old_value| {
+                old_value
+                    .checked_add(requests.len())
+                    .filter(|new_value| *new_value <= self.max_requests_per_client)
+            })
+            .is_err();
+        if failed_to_increase {
+            return Err(TryQueueClientRequestsError { requests });
+        }
+
+        // We can now insert the requests.
+        // See the note in `queue_client_request`.
+        for (request, lane) in requests {
+            client.unpulled_requests[lane.index()].push(request);
+        }
+        for lane_index in 0..NUM_LANES {
+            self.schedule_client(&client, lane_index);
+        }
+        Ok(())
+    }
+
+    /// Waits until a request of the given [`RequestLane`] has been queued using
     /// [`RequestsSubscriptions::queue_client_request`] and returns it, alongside with an
     /// identifier to later pass back when answering the request.
     ///
+    /// Requests are pulled from the clients in a round-robin way, and clients that have reached
+    /// [`Config::max_parallel_requests_per_client`] are skipped.
+    ///
     /// Note that the request's body, as a `String` has no guarantee to be valid. The `String` is
     /// simply the value that was passed to [`RequestsSubscriptions::queue_client_request`] and
     /// isn't parsed or validated by the state machine in any way.
-    pub async fn next_request(&self) -> (String, RequestId) {
-        // Try to pull a request from the queue. If there is none, wait for
-        // `new_unpulled_request`.
+    pub async fn next_request(&self, lane: RequestLane) -> (String, RequestId) {
+        let lane_index = lane.index();
+
+        // Try to pull a client from the queue of ready clients. If there is none, wait for
+        // `new_ready_client`.
         let (request_message, client) = loop {
-            // Because `new_unpulled_request` is notified *after* new items are pushed to the queue,
-            // we *must* check the queue after calling `new_unpulled_request.listen()` and before
+            // Because `new_ready_client` is notified *after* new items are pushed to the queue,
+            // we *must* check the queue after calling `new_ready_client.listen()` and before
             // sleeping.
             // However, since `listen()` is rather heavy, we try to avoid calling it as much as
             // possible.
@@ -611,25 +752,59 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             // - Try pull from queue again (mandatory to prevent race conditions).
             // - Actually wait for the notification, and jump back to step 1.
             let mut sleep_until = None;
-            let (request_message, client) = loop {
-                if let Some(item) = self.unpulled_requests.pop() {
+            let client = loop {
+                if let Some(item) = self.ready_clients[lane_index].pop() {
                     break item;
                 }
 
                 if let Some(sleep_until) = sleep_until.take() {
                     sleep_until.await;
                 } else {
-                    sleep_until = Some(self.new_unpulled_request.listen());
+                    sleep_until = Some(self.new_ready_client[lane_index].listen());
                 }
             };
 
             // The queue might contain obsolete entries. Check that the client still exist, and
             // if not throw away the entry and pull another one.
-            if let Some(client) = client.upgrade() {
-                if !client.dead.load(Ordering::Relaxed) {
-                    break (request_message, client);
-                }
+            let client = match client.upgrade() {
+                Some(client) if !client.dead.load(Ordering::Relaxed) => client,
+                _ => continue,
+            };
+
+            // The client is no longer in the queue. This must be done before checking the limit
+            // and pulling the request, as other tasks might push the client back concurrently.
+            client.in_ready_clients[lane_index].store(false, Ordering::SeqCst);
+
+            // Try increment `num_requests_processing`, capping at a maximum of
+            // `max_parallel_requests_per_client`. If the limit is reached, the client is not
+            // pushed back to the queue. It will be pushed back when one of its requests is
+            // responded to.
+            let could_increase = client
+                .num_requests_processing
+                .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |old_value| {
+                    if old_value >= self.max_parallel_requests_per_client {
+                        return None;
+                    }
+
+                    // Considering that `old_value < max`, and `max` fits in a `usize` by
+                    // definition, then `old_value + 1` also always fits in a `usize`. QED.
+                    // There's no risk of overflow.
+                    Some(old_value + 1)
+                })
+                .is_ok();
+            if !could_increase {
+                continue;
             }
+
+            // Another task might have pulled the last request of the client in the meanwhile.
+            let Some(request_message) = client.unpulled_requests[lane_index].pop() else {
+                self.release_processing_slot(&client);
+                continue;
+            };
+
+            // Push the client back at the end of the queue if it has more requests.
+            self.schedule_client(&client, lane_index);
+            break (request_message, client);
         };
 
         // Allocate a new identifier for this request.
@@ -674,6 +849,37 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
                 .push_back(ResponseSendBack::Response(response));
             lock.responses_send_back_pushed_or_dead.notify_additional(1);
         }
+
+        self.release_processing_slot(&client);
+    }
+
+    /// Pushes the given client at the end of the queue of
+    /// [`RequestsSubscriptions::ready_clients`] corresponding to the given lane, unless it is
+    /// already in this queue or has no request of this lane to pull.
+    fn schedule_client(&self, client: &Arc<ClientInner<TSubMsg>>, lane_index: usize) {
+        if client.unpulled_requests[lane_index].is_empty() {
+            return;
+        }
+
+        if client.in_ready_clients[lane_index].swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.ready_clients[lane_index].push(Arc::downgrade(client));
+        self.new_ready_client[lane_index].notify_additional(1);
+    }
+
+    /// Decrements [`ClientInner::num_requests_processing`], then schedules the client again in
+    /// case it was previously skipped because of [`Config::max_parallel_requests_per_client`].
+    fn release_processing_slot(&self, client: &Arc<ClientInner<TSubMsg>>) {
+        let _new_val = client
+            .num_requests_processing
+            .fetch_sub(1, Ordering::SeqCst);
+        debug_assert_ne!(_new_val, usize::max_value()); // Check for underflows
+
+        for lane_index in 0..NUM_LANES {
+            self.schedule_client(client, lane_index);
+        }
     }
 
     /// Waits until a subscription task is ready to be polled, and polls it.
@@ -1203,6 +1409,14 @@ pub struct TryQueueClientRequestError {
     pub request: String,
 }
 
+/// Error returned by [`RequestsSubscriptions::try_queue_client_requests`].
+#[derive(Debug, derive_more::Display, Clone)]
+#[display(fmt = "Queue of unpulled requests full")]
+pub struct TryQueueClientRequestsError {
+    /// Original requests, passed as parameter to the function.
+    pub requests: Vec<(String, RequestLane)>,
+}
+
 /// Error returned by [`RequestsSubscriptions::add_client`] and
 /// [`RequestsSubscriptions::add_client_mut`].
 #[derive(Debug, derive_more::Display, Clone)]
@@ -1237,11 +1451,11 @@ struct ClientInner<TSubMsg> {
     ///
     /// In other words, this is the number of requests that have been injected in this state
     /// machine but not fully processed yet. They can be in one of
-    /// [`RequestsSubscriptions::unpulled_requests`], [`ClientInnerGuarded::pending_requests`],
+    /// [`ClientInner::unpulled_requests`], [`ClientInnerGuarded::pending_requests`],
     /// or [`ClientInnerGuarded::responses_send_back`].
     ///
     /// Due to the racy nature of everything, a request might have increased the counter here but
-    /// not be present yet in [`RequestsSubscriptions::unpulled_requests`].
+    /// not be present yet in [`ClientInner::unpulled_requests`].
     total_requests_in_fly: AtomicUsize,
 
     /// One listener is notified every time [`ClientInner::total_requests_in_fly`] is decremented.
@@ -1250,6 +1464,19 @@ struct ClientInner<TSubMsg> {
     ///
     /// All listeners are also notified when [`ClientInner::dead`] is set to `true`.
     total_requests_in_fly_dec_or_dead: event_listener::Event,
+
+    /// For each [`RequestLane`], requests sent by the client and not yet pulled by
+    /// [`RequestsSubscriptions::next_request`].
+    unpulled_requests: [crossbeam_queue::SegQueue<String>; NUM_LANES],
+
+    /// For each [`RequestLane`], `true` if the client is in the corresponding queue of
+    /// [`RequestsSubscriptions::ready_clients`].
+    in_ready_clients: [AtomicBool; NUM_LANES],
+
+    /// Number of requests that have been pulled by [`RequestsSubscriptions::next_request`] and
+    /// not responded to yet. Can't exceed
+    /// [`RequestsSubscriptions::max_parallel_requests_per_client`].
+    num_requests_processing: AtomicUsize,
 }
 
 struct ClientInnerGuarded<TSubMsg> {
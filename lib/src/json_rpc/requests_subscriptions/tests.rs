@@ -17,7 +17,7 @@
 
 #![cfg(test)]
 
-use super::{Config, RequestsSubscriptions};
+use super::{Config, RequestLane, RequestsSubscriptions};
 use core::num::NonZeroU32;
 
 #[test]
@@ -26,6 +26,7 @@ fn clients_limit_adjustement() {
         let req_sub = RequestsSubscriptions::<()>::new(Config {
             max_clients: 2,
             max_requests_per_client: NonZeroU32::new(5).unwrap(),
+            max_parallel_requests_per_client: NonZeroU32::new(16).unwrap(),
             max_subscriptions_per_client: 5,
         });
 
@@ -41,3 +42,173 @@ fn clients_limit_adjustement() {
         assert!(req_sub.add_client().await.is_err());
     });
 }
+
+#[test]
+fn num_requests_in_fly() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 1,
+            max_requests_per_client: NonZeroU32::new(2).unwrap(),
+            max_parallel_requests_per_client: NonZeroU32::new(16).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+        assert_eq!(req_sub.max_requests_per_client(), 2);
+
+        let client = req_sub.add_client().await.unwrap();
+        assert_eq!(req_sub.num_requests_in_fly(&client), 0);
+
+        req_sub
+            .try_queue_client_request(&client, "foo".to_owned(), RequestLane::Expensive)
+            .unwrap();
+        req_sub
+            .try_queue_client_request(&client, "bar".to_owned(), RequestLane::Expensive)
+            .unwrap();
+        assert_eq!(req_sub.num_requests_in_fly(&client), 2);
+        assert!(req_sub
+            .try_queue_client_request(&client, "baz".to_owned(), RequestLane::Expensive)
+            .is_err());
+
+        let (_, request_id) = req_sub.next_request(RequestLane::Expensive).await;
+        req_sub.respond(&request_id, "response".to_owned()).await;
+        assert_eq!(req_sub.num_requests_in_fly(&client), 2);
+        assert_eq!(req_sub.next_response(&client).await, "response");
+        assert_eq!(req_sub.num_requests_in_fly(&client), 1);
+    });
+}
+
+#[test]
+fn queue_multiple_requests() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 1,
+            max_requests_per_client: NonZeroU32::new(3).unwrap(),
+            max_parallel_requests_per_client: NonZeroU32::new(16).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+
+        let client = req_sub.add_client().await.unwrap();
+
+        req_sub
+            .try_queue_client_request(&client, "foo".to_owned(), RequestLane::Expensive)
+            .unwrap();
+        assert!(req_sub
+            .try_queue_client_requests(
+                &client,
+                vec![
+                    ("bar".to_owned(), RequestLane::Expensive),
+                    ("baz".to_owned(), RequestLane::Expensive),
+                    ("qux".to_owned(), RequestLane::Expensive)
+                ]
+            )
+            .is_err());
+        assert_eq!(req_sub.num_requests_in_fly(&client), 1);
+
+        req_sub
+            .try_queue_client_requests(
+                &client,
+                vec![
+                    ("bar".to_owned(), RequestLane::Expensive),
+                    ("baz".to_owned(), RequestLane::Expensive),
+                ],
+            )
+            .unwrap();
+        assert_eq!(req_sub.num_requests_in_fly(&client), 3);
+
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "foo");
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "bar");
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "baz");
+    });
+}
+
+#[test]
+fn round_robin_between_clients() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 2,
+            max_requests_per_client: NonZeroU32::new(8).unwrap(),
+            max_parallel_requests_per_client: NonZeroU32::new(8).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+
+        let client1 = req_sub.add_client().await.unwrap();
+        let client2 = req_sub.add_client().await.unwrap();
+
+        for request in ["a1", "a2", "a3"] {
+            req_sub
+                .try_queue_client_request(&client1, request.to_owned(), RequestLane::Expensive)
+                .unwrap();
+        }
+        req_sub
+            .try_queue_client_request(&client2, "b1".to_owned(), RequestLane::Expensive)
+            .unwrap();
+
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "a1");
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "b1");
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "a2");
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "a3");
+    });
+}
+
+#[test]
+fn lanes_separate() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 1,
+            max_requests_per_client: NonZeroU32::new(8).unwrap(),
+            max_parallel_requests_per_client: NonZeroU32::new(8).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+
+        let client = req_sub.add_client().await.unwrap();
+
+        req_sub
+            .try_queue_client_requests(
+                &client,
+                vec![
+                    ("expensive".to_owned(), RequestLane::Expensive),
+                    ("cheap".to_owned(), RequestLane::Cheap),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(req_sub.next_request(RequestLane::Cheap).await.0, "cheap");
+        assert_eq!(
+            req_sub.next_request(RequestLane::Expensive).await.0,
+            "expensive"
+        );
+    });
+}
+
+#[test]
+fn max_parallel_requests_per_client() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 2,
+            max_requests_per_client: NonZeroU32::new(8).unwrap(),
+            max_parallel_requests_per_client: NonZeroU32::new(1).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+
+        let client1 = req_sub.add_client().await.unwrap();
+        let client2 = req_sub.add_client().await.unwrap();
+
+        for request in ["a1", "a2"] {
+            req_sub
+                .try_queue_client_request(&client1, request.to_owned(), RequestLane::Expensive)
+                .unwrap();
+        }
+
+        let (request, request_id) = req_sub.next_request(RequestLane::Expensive).await;
+        assert_eq!(request, "a1");
+
+        // The second request of `client1` must not be pulled as long as the first one hasn't
+        // been responded to, while the requests of `client2` are unaffected.
+        req_sub
+            .try_queue_client_request(&client2, "b1".to_owned(), RequestLane::Expensive)
+            .unwrap();
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "b1");
+
+        req_sub.respond(&request_id, "response".to_owned()).await;
+        assert_eq!(req_sub.next_request(RequestLane::Expensive).await.0, "a2");
+    });
+}
@@ -0,0 +1,161 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runtime calls behind the `author_rotateKeys` and `author_hasSessionKeys` JSON-RPC functions.
+//!
+//! The list of keys that form the session keys of a validator is defined by the runtime. New
+//! session keys are generated by calling [`GENERATE_SESSION_KEYS_FUNCTION_NAME`], during which
+//! the runtime calls the key generation host functions. Session keys are decoded by calling
+//! [`DECODE_SESSION_KEYS_FUNCTION_NAME`].
+
+use alloc::vec::Vec;
+
+/// Name of the runtime function to call in order to generate new session keys.
+pub const GENERATE_SESSION_KEYS_FUNCTION_NAME: &str = "SessionKeys_generate_session_keys";
+
+/// Produces the input to pass to the `SessionKeys_generate_session_keys` runtime call.
+///
+/// If `seed` is `Some`, the keys are derived from this secret phrase instead of being generated
+/// randomly.
+pub fn generate_session_keys_parameters(
+    seed: Option<&'_ [u8]>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + Clone + '_ {
+    match seed {
+        Some(seed) => either::Left(
+            [
+                either::Left([1]),
+                either::Right(either::Left(crate::util::encode_scale_compact_usize(
+                    seed.len(),
+                ))),
+                either::Right(either::Right(seed)),
+            ]
+            .into_iter(),
+        ),
+        None => either::Right(core::iter::once(either::Left([0]))),
+    }
+}
+
+/// Attempt to decode the output of the `SessionKeys_generate_session_keys` runtime call.
+///
+/// Returns the concatenation of the public keys that have been generated.
+pub fn decode_generate_session_keys_output(scale_encoded: &[u8]) -> Result<&[u8], DecodeError> {
+    let result: Result<_, nom::Err<nom::error::Error<&[u8]>>> = nom::combinator::all_consuming(
+        nom::combinator::complete(crate::util::nom_bytes_decode),
+    )(scale_encoded);
+    match result {
+        Ok((_, session_keys)) => Ok(session_keys),
+        Err(_) => Err(DecodeError()),
+    }
+}
+
+/// Name of the runtime function to call in order to decode session keys.
+pub const DECODE_SESSION_KEYS_FUNCTION_NAME: &str = "SessionKeys_decode_session_keys";
+
+/// Produces the input to pass to the `SessionKeys_decode_session_keys` runtime call.
+pub fn decode_session_keys_parameters(
+    session_keys: &'_ [u8],
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + Clone + '_ {
+    [
+        either::Left(crate::util::encode_scale_compact_usize(session_keys.len())),
+        either::Right(session_keys),
+    ]
+    .into_iter()
+}
+
+/// Attempt to decode the output of the `SessionKeys_decode_session_keys` runtime call.
+///
+/// Returns `None` if the runtime has considered the session keys as invalid. Otherwise, returns
+/// the list of public keys found in the session keys, each associated with its key type
+/// identifier (for example `b"gran"`).
+pub fn decode_decode_session_keys_output(
+    scale_encoded: &[u8],
+) -> Result<Option<Vec<(&[u8], [u8; 4])>>, DecodeError> {
+    let result: Result<_, nom::Err<nom::error::Error<&[u8]>>> =
+        nom::combinator::all_consuming(nom::combinator::complete(crate::util::nom_option_decode(
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_keys| {
+                nom::multi::many_m_n(
+                    num_keys,
+                    num_keys,
+                    nom::sequence::tuple((
+                        crate::util::nom_bytes_decode,
+                        nom::combinator::map(nom::bytes::streaming::take(4u32), |id| {
+                            <[u8; 4]>::try_from(id).unwrap()
+                        }),
+                    )),
+                )
+            }),
+        )))(scale_encoded);
+    match result {
+        Ok((_, keys)) => Ok(keys),
+        Err(_) => Err(DecodeError()),
+    }
+}
+
+/// Potential error when decoding the output of one of the session keys runtime functions.
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode the output of the session keys runtime function")]
+pub struct DecodeError();
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn generate_parameters() {
+        let encode = |seed| {
+            super::generate_session_keys_parameters(seed).fold(Vec::new(), |mut out, chunk| {
+                out.extend_from_slice(chunk.as_ref());
+                out
+            })
+        };
+
+        assert_eq!(encode(None), [0]);
+        assert_eq!(encode(Some(b"//Alice")), b"\x01\x1c//Alice");
+    }
+
+    #[test]
+    fn decode_generate_output() {
+        assert_eq!(
+            super::decode_generate_session_keys_output(&[8, 1, 2]).unwrap(),
+            &[1, 2]
+        );
+        assert!(super::decode_generate_session_keys_output(&[8, 1]).is_err());
+        assert!(super::decode_generate_session_keys_output(&[4, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn decode_decode_output() {
+        let mut encoded = vec![1, 8];
+        encoded.extend_from_slice(&[128]);
+        encoded.extend_from_slice(&[0xaa; 32]);
+        encoded.extend_from_slice(b"aura");
+        encoded.extend_from_slice(&[128]);
+        encoded.extend_from_slice(&[0xbb; 32]);
+        encoded.extend_from_slice(b"gran");
+
+        let keys = super::decode_decode_session_keys_output(&encoded)
+            .unwrap()
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0], (&[0xaa; 32][..], *b"aura"));
+        assert_eq!(keys[1], (&[0xbb; 32][..], *b"gran"));
+
+        assert_eq!(
+            super::decode_decode_session_keys_output(&[0]).unwrap(),
+            None
+        );
+        assert!(super::decode_decode_session_keys_output(&encoded[..encoded.len() - 1]).is_err());
+    }
+}
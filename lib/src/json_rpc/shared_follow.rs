@@ -0,0 +1,501 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Blocks pinned by multiple `chainHead_follow` subscriptions that share a single subscription
+//! to the chain.
+//!
+//! Rather than each `chainHead_follow` subscription following the chain and pinning blocks on
+//! its own, a JSON-RPC server can follow the chain once and share the blocks between all the
+//! subscriptions. The [`SharedFollow`] state machine tracks the tree of blocks, and which
+//! subscription has pinned which block.
+//!
+//! Each `chainHead_follow` subscription is called a "follower". Every block has a reference
+//! counter, which counts the number of followers that have the block pinned, plus one if the
+//! block is still part of the tree of blocks. A block is considered as no longer pinned when its
+//! counter reaches zero, at which point the underlying subscription to the chain can unpin it.
+//!
+//! Each follower can keep at most a certain number of blocks pinned. Blocks can't be unpinned
+//! without the consent of the JSON-RPC client, and as such a follower that has reached this
+//! limit when a new block is added is removed. No follower is added if the tree of blocks
+//! already contains more blocks than this limit.
+//!
+//! # Usage
+//!
+//! Call [`SharedFollow::new`] with the current finalized block, then [`SharedFollow::add_block`]
+//! for each non-finalized block. Keep the state up to date by calling
+//! [`SharedFollow::add_block`], [`SharedFollow::set_best_block`], and
+//! [`SharedFollow::finalize`] whenever the chain is updated.
+//!
+//! Most methods return a list of blocks that are no longer pinned by anything. These blocks must
+//! be unpinned from the underlying subscription to the chain.
+
+use alloc::vec::Vec;
+use core::{iter, mem, num::NonZeroUsize};
+use hashbrown::{HashMap, HashSet};
+
+mod tests;
+
+/// Configuration for a [`SharedFollow`].
+#[derive(Debug)]
+pub struct Config<TBl> {
+    /// Hash of the current finalized block.
+    pub finalized_block_hash: [u8; 32],
+
+    /// User data associated with the current finalized block.
+    pub finalized_block_user_data: TBl,
+
+    /// Maximum number of blocks that each follower can keep pinned.
+    pub max_pinned_blocks_per_follower: NonZeroUsize,
+}
+
+/// See [the module-level documentation](..).
+pub struct SharedFollow<TBl, TFol> {
+    /// Hash of the current finalized block.
+    finalized_block_hash: [u8; 32],
+
+    /// User data associated with the current finalized block.
+    finalized_block_user_data: TBl,
+
+    /// List of all the non-finalized blocks, ordered so that parents are always found before
+    /// their children.
+    non_finalized_blocks_ancestry_order: Vec<Block<TBl>>,
+
+    /// Hash of the current best block. Can be equal to [`SharedFollow::finalized_block_hash`].
+    best_block_hash: [u8; 32],
+
+    /// For each pinned block, the number of references to it. See the module-level
+    /// documentation.
+    pins: HashMap<[u8; 32], usize, fnv::FnvBuildHasher>,
+
+    /// List of all the active followers.
+    followers: HashMap<u64, Follower<TFol>, fnv::FnvBuildHasher>,
+
+    /// Identifier to assign to the next follower.
+    next_follower_id: u64,
+
+    /// See [`Config::max_pinned_blocks_per_follower`].
+    max_pinned_blocks_per_follower: NonZeroUsize,
+}
+
+struct Block<TBl> {
+    hash: [u8; 32],
+    parent_hash: [u8; 32],
+    user_data: TBl,
+}
+
+struct Follower<TFol> {
+    /// List of blocks that this follower has pinned.
+    pinned_blocks: HashSet<[u8; 32], fnv::FnvBuildHasher>,
+
+    /// User data passed to [`SharedFollow::add_follower`].
+    user_data: TFol,
+}
+
+/// Identifier of a follower within a [`SharedFollow`].
+///
+/// Identifiers are never reused, even after the follower has been removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FollowerId(u64);
+
+impl<TBl, TFol> SharedFollow<TBl, TFol> {
+    /// Initializes a new [`SharedFollow`] that contains only the finalized block and no
+    /// follower.
+    pub fn new(config: Config<TBl>) -> Self {
+        let mut pins = HashMap::with_capacity_and_hasher(32, Default::default());
+        pins.insert(config.finalized_block_hash, 1);
+
+        SharedFollow {
+            finalized_block_hash: config.finalized_block_hash,
+            finalized_block_user_data: config.finalized_block_user_data,
+            non_finalized_blocks_ancestry_order: Vec::with_capacity(32),
+            best_block_hash: config.finalized_block_hash,
+            pins,
+            followers: HashMap::with_capacity_and_hasher(0, Default::default()),
+            next_follower_id: 0,
+            max_pinned_blocks_per_follower: config.max_pinned_blocks_per_follower,
+        }
+    }
+
+    /// Returns the hash of the current finalized block.
+    pub fn finalized_block_hash(&self) -> &[u8; 32] {
+        &self.finalized_block_hash
+    }
+
+    /// Returns the user data associated with the current finalized block.
+    pub fn finalized_block_user_data(&self) -> &TBl {
+        &self.finalized_block_user_data
+    }
+
+    /// Returns the hash of the current best block. Can be equal to
+    /// [`SharedFollow::finalized_block_hash`].
+    pub fn best_block_hash(&self) -> &[u8; 32] {
+        &self.best_block_hash
+    }
+
+    /// Returns the list of all the non-finalized blocks, ordered so that parents are always
+    /// found before their children.
+    pub fn non_finalized_blocks_ancestry_order(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&[u8; 32], &TBl)> {
+        self.non_finalized_blocks_ancestry_order
+            .iter()
+            .map(|block| (&block.hash, &block.user_data))
+    }
+
+    /// Returns the user data of the given block of the tree of blocks, or `None` if the block
+    /// isn't part of the tree of blocks.
+    pub fn block_user_data(&self, hash: &[u8; 32]) -> Option<&TBl> {
+        if *hash == self.finalized_block_hash {
+            return Some(&self.finalized_block_user_data);
+        }
+
+        self.non_finalized_blocks_ancestry_order
+            .iter()
+            .find(|block| block.hash == *hash)
+            .map(|block| &block.user_data)
+    }
+
+    /// Returns the number of distinct blocks that are pinned, either because they are part of
+    /// the tree of blocks or because a follower has pinned them.
+    pub fn num_pinned_blocks(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Returns the value that was passed as [`Config::max_pinned_blocks_per_follower`].
+    pub fn max_pinned_blocks_per_follower(&self) -> NonZeroUsize {
+        self.max_pinned_blocks_per_follower
+    }
+
+    /// Returns the list of followers, alongside with the number of blocks that each follower
+    /// has pinned.
+    pub fn followers(&self) -> impl ExactSizeIterator<Item = (FollowerId, &TFol, usize)> {
+        self.followers.iter().map(|(id, follower)| {
+            (
+                FollowerId(*id),
+                &follower.user_data,
+                follower.pinned_blocks.len(),
+            )
+        })
+    }
+
+    /// Returns the list of followers and their user data.
+    pub fn followers_mut(&mut self) -> impl ExactSizeIterator<Item = (FollowerId, &mut TFol)> {
+        self.followers
+            .iter_mut()
+            .map(|(id, follower)| (FollowerId(*id), &mut follower.user_data))
+    }
+
+    /// Adds a new follower. All the blocks of the tree of blocks are considered as pinned by
+    /// this new follower.
+    ///
+    /// Returns back the user data if the tree of blocks contains more blocks than
+    /// [`Config::max_pinned_blocks_per_follower`].
+    pub fn add_follower(&mut self, user_data: TFol) -> Result<FollowerId, TFol> {
+        let num_blocks = 1 + self.non_finalized_blocks_ancestry_order.len();
+        if num_blocks > self.max_pinned_blocks_per_follower.get() {
+            return Err(user_data);
+        }
+
+        let mut pinned_blocks = HashSet::with_capacity_and_hasher(num_blocks, Default::default());
+        for hash in iter::once(&self.finalized_block_hash).chain(
+            self.non_finalized_blocks_ancestry_order
+                .iter()
+                .map(|block| &block.hash),
+        ) {
+            pinned_blocks.insert(*hash);
+            *self.pins.get_mut(hash).unwrap() += 1;
+        }
+
+        let id = self.next_follower_id;
+        self.next_follower_id += 1;
+        self.followers.insert(
+            id,
+            Follower {
+                pinned_blocks,
+                user_data,
+            },
+        );
+        Ok(FollowerId(id))
+    }
+
+    /// Removes the given follower. Returns its user data and the list of blocks that are no
+    /// longer pinned by anything, or `None` if the follower has already been removed.
+    pub fn remove_follower(&mut self, follower: FollowerId) -> Option<(TFol, Vec<[u8; 32]>)> {
+        let follower = self.followers.remove(&follower.0)?;
+        let unpinned = follower
+            .pinned_blocks
+            .iter()
+            .filter(|hash| self.release_block(hash))
+            .copied()
+            .collect();
+        Some((follower.user_data, unpinned))
+    }
+
+    /// Returns `true` if the given follower has the given block pinned. Returns `false` if the
+    /// follower has been removed.
+    pub fn is_pinned(&self, follower: FollowerId, hash: &[u8; 32]) -> bool {
+        self.followers
+            .get(&follower.0)
+            .map_or(false, |f| f.pinned_blocks.contains(hash))
+    }
+
+    /// Removes the pin of the given follower on the given block.
+    ///
+    /// On success, returns `true` if the block is no longer pinned by anything.
+    pub fn unpin_block(
+        &mut self,
+        follower: FollowerId,
+        hash: &[u8; 32],
+    ) -> Result<bool, UnpinError> {
+        let follower = self
+            .followers
+            .get_mut(&follower.0)
+            .ok_or(UnpinError::UnknownFollower)?;
+        if !follower.pinned_blocks.remove(hash) {
+            return Err(UnpinError::NotPinned);
+        }
+        Ok(self.release_block(hash))
+    }
+
+    /// Adds a reference to the given block if the given follower has this block pinned. Returns
+    /// `false` if the follower doesn't have the block pinned or has been removed.
+    ///
+    /// The block is guaranteed to stay pinned, even if the follower unpins it or is removed,
+    /// until [`SharedFollow::release_block`] is called.
+    pub fn acquire_block(&mut self, follower: FollowerId, hash: &[u8; 32]) -> bool {
+        if !self.is_pinned(follower, hash) {
+            return false;
+        }
+
+        *self.pins.get_mut(hash).unwrap() += 1;
+        true
+    }
+
+    /// Removes a reference to the given block that has been added with
+    /// [`SharedFollow::acquire_block`]. Returns `true` if the block is no longer pinned by
+    /// anything.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the block isn't pinned.
+    ///
+    pub fn release_block(&mut self, hash: &[u8; 32]) -> bool {
+        let counter = self.pins.get_mut(hash).unwrap();
+        *counter -= 1;
+        if *counter == 0 {
+            self.pins.remove(hash);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Adds a new non-finalized block to the tree of blocks.
+    ///
+    /// The block is considered as pinned by all the followers. Followers that already have
+    /// [`Config::max_pinned_blocks_per_follower`] blocks pinned are instead removed, and are
+    /// returned alongside with the list of blocks that are no longer pinned by anything.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the parent of the block isn't in the tree of blocks.
+    /// Panics if the block is already in the tree of blocks.
+    ///
+    pub fn add_block(
+        &mut self,
+        hash: [u8; 32],
+        parent_hash: [u8; 32],
+        user_data: TBl,
+        is_new_best: bool,
+    ) -> RemovedFollowers<TFol> {
+        assert!(
+            parent_hash == self.finalized_block_hash
+                || self
+                    .non_finalized_blocks_ancestry_order
+                    .iter()
+                    .any(|block| block.hash == parent_hash)
+        );
+        assert!(!self.pins.contains_key(&hash));
+
+        self.non_finalized_blocks_ancestry_order.push(Block {
+            hash,
+            parent_hash,
+            user_data,
+        });
+        self.pins.insert(hash, 1);
+        if is_new_best {
+            self.best_block_hash = hash;
+        }
+
+        let to_remove = self
+            .followers
+            .iter()
+            .filter(|(_, follower)| {
+                follower.pinned_blocks.len() >= self.max_pinned_blocks_per_follower.get()
+            })
+            .map(|(id, _)| FollowerId(*id))
+            .collect::<Vec<_>>();
+
+        let mut removed = RemovedFollowers {
+            followers: Vec::with_capacity(to_remove.len()),
+            unpinned_blocks: Vec::new(),
+        };
+        for follower in to_remove {
+            let (user_data, unpinned) = self.remove_follower(follower).unwrap();
+            removed.followers.push((follower, user_data));
+            removed.unpinned_blocks.extend(unpinned);
+        }
+
+        for follower in self.followers.values_mut() {
+            follower.pinned_blocks.insert(hash);
+            *self.pins.get_mut(&hash).unwrap() += 1;
+        }
+
+        removed
+    }
+
+    /// Updates the best block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the block isn't in the tree of blocks.
+    ///
+    pub fn set_best_block(&mut self, hash: [u8; 32]) {
+        assert!(self.block_user_data(&hash).is_some());
+        self.best_block_hash = hash;
+    }
+
+    /// Finalizes the given non-finalized block, and updates the best block.
+    ///
+    /// The blocks that aren't descendants of the new finalized block are removed from the tree
+    /// of blocks.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `hash` isn't a non-finalized block of the tree of blocks.
+    /// Panics if `best_block_hash` isn't the new finalized block or one of its descendants.
+    ///
+    pub fn finalize(&mut self, hash: [u8; 32], best_block_hash: [u8; 32]) -> Finalized<TBl> {
+        let new_finalized = self.non_finalized_blocks_ancestry_order.remove(
+            self.non_finalized_blocks_ancestry_order
+                .iter()
+                .position(|block| block.hash == hash)
+                .unwrap(),
+        );
+
+        // Gather the list of ancestors of the new finalized block.
+        let mut finalized_ancestors = HashSet::<_, fnv::FnvBuildHasher>::default();
+        let mut iter = new_finalized.parent_hash;
+        while iter != self.finalized_block_hash {
+            finalized_ancestors.insert(iter);
+            iter = self
+                .non_finalized_blocks_ancestry_order
+                .iter()
+                .find(|block| block.hash == iter)
+                .unwrap()
+                .parent_hash;
+        }
+
+        let previous_finalized_block_user_data =
+            mem::replace(&mut self.finalized_block_user_data, new_finalized.user_data);
+        let mut outcome = Finalized {
+            previous_finalized_block: (
+                mem::replace(&mut self.finalized_block_hash, hash),
+                previous_finalized_block_user_data,
+            ),
+            newly_finalized_blocks: Vec::with_capacity(finalized_ancestors.len()),
+            pruned_blocks: Vec::new(),
+            unpinned_blocks: Vec::new(),
+        };
+
+        // Because the blocks are in ancestry order, a block is a descendant of the new finalized
+        // block if and only if its parent is either the new finalized block or one of the
+        // descendants found so far.
+        let mut descendants = HashSet::<_, fnv::FnvBuildHasher>::default();
+        descendants.insert(hash);
+        let mut kept = Vec::with_capacity(self.non_finalized_blocks_ancestry_order.len());
+        for block in self.non_finalized_blocks_ancestry_order.drain(..) {
+            if descendants.contains(&block.parent_hash) {
+                descendants.insert(block.hash);
+                kept.push(block);
+            } else if finalized_ancestors.contains(&block.hash) {
+                outcome
+                    .newly_finalized_blocks
+                    .push((block.hash, block.user_data));
+            } else {
+                outcome.pruned_blocks.push((block.hash, block.user_data));
+            }
+        }
+        self.non_finalized_blocks_ancestry_order = kept;
+
+        assert!(descendants.contains(&best_block_hash));
+        self.best_block_hash = best_block_hash;
+
+        // Release the reference held by the tree of blocks on the blocks that have left it.
+        for removed_hash in iter::once(&outcome.previous_finalized_block.0)
+            .chain(outcome.newly_finalized_blocks.iter().map(|(h, _)| h))
+            .chain(outcome.pruned_blocks.iter().map(|(h, _)| h))
+        {
+            let counter = self.pins.get_mut(removed_hash).unwrap();
+            *counter -= 1;
+            if *counter == 0 {
+                self.pins.remove(removed_hash);
+                outcome.unpinned_blocks.push(*removed_hash);
+            }
+        }
+
+        outcome
+    }
+}
+
+/// Followers removed by [`SharedFollow::add_block`].
+#[derive(Debug)]
+pub struct RemovedFollowers<TFol> {
+    /// List of followers that have been removed, and their user data.
+    pub followers: Vec<(FollowerId, TFol)>,
+
+    /// List of blocks that are no longer pinned by anything as a result of the removal.
+    pub unpinned_blocks: Vec<[u8; 32]>,
+}
+
+/// Outcome of [`SharedFollow::finalize`].
+#[derive(Debug)]
+pub struct Finalized<TBl> {
+    /// Hash and user data of the block that was finalized before the call to
+    /// [`SharedFollow::finalize`].
+    pub previous_finalized_block: ([u8; 32], TBl),
+
+    /// Hashes and user data of the blocks that have been finalized, not including the new
+    /// finalized block, ordered by increasing block number.
+    pub newly_finalized_blocks: Vec<([u8; 32], TBl)>,
+
+    /// Hashes and user data of the blocks that aren't descendants of the new finalized block
+    /// and have been removed from the tree of blocks.
+    pub pruned_blocks: Vec<([u8; 32], TBl)>,
+
+    /// List of blocks that are no longer pinned by anything.
+    pub unpinned_blocks: Vec<[u8; 32]>,
+}
+
+/// Error potentially returned by [`SharedFollow::unpin_block`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub enum UnpinError {
+    /// Follower has been removed.
+    UnknownFollower,
+    /// Block isn't pinned by this follower.
+    NotPinned,
+}
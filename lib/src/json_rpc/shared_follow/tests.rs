@@ -0,0 +1,198 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{Config, SharedFollow, UnpinError};
+use core::num::NonZeroUsize;
+
+fn new(max_pinned_blocks_per_follower: usize) -> SharedFollow<u32, &'static str> {
+    SharedFollow::new(Config {
+        finalized_block_hash: [0; 32],
+        finalized_block_user_data: 0,
+        max_pinned_blocks_per_follower: NonZeroUsize::new(max_pinned_blocks_per_follower).unwrap(),
+    })
+}
+
+#[test]
+fn follower_pins_whole_tree() {
+    let mut shared_follow = new(8);
+    assert!(shared_follow
+        .add_block([1; 32], [0; 32], 1, true)
+        .followers
+        .is_empty());
+    assert!(shared_follow
+        .add_block([2; 32], [1; 32], 2, false)
+        .followers
+        .is_empty());
+    assert_eq!(shared_follow.best_block_hash(), &[1; 32]);
+
+    let follower = shared_follow.add_follower("a").unwrap();
+    assert!(shared_follow.is_pinned(follower, &[0; 32]));
+    assert!(shared_follow.is_pinned(follower, &[1; 32]));
+    assert!(shared_follow.is_pinned(follower, &[2; 32]));
+    assert_eq!(shared_follow.num_pinned_blocks(), 3);
+    assert_eq!(
+        shared_follow
+            .followers()
+            .map(|(_, _, n)| n)
+            .collect::<Vec<_>>(),
+        vec![3]
+    );
+
+    // Blocks added afterwards are pinned as well.
+    assert!(shared_follow
+        .add_block([3; 32], [2; 32], 3, true)
+        .followers
+        .is_empty());
+    assert!(shared_follow.is_pinned(follower, &[3; 32]));
+    assert_eq!(shared_follow.best_block_hash(), &[3; 32]);
+}
+
+#[test]
+fn follower_refused_if_tree_too_large() {
+    let mut shared_follow = new(2);
+    shared_follow.add_block([1; 32], [0; 32], 1, true);
+    assert!(shared_follow.add_follower("a").is_ok());
+
+    shared_follow.add_block([2; 32], [1; 32], 2, true);
+    assert_eq!(shared_follow.add_follower("b"), Err("b"));
+}
+
+#[test]
+fn follower_removed_when_limit_reached() {
+    let mut shared_follow = new(2);
+    let follower = shared_follow.add_follower("a").unwrap();
+    shared_follow.add_block([1; 32], [0; 32], 1, true);
+    assert!(shared_follow.is_pinned(follower, &[1; 32]));
+
+    // The finalized block leaves the tree but stays pinned by the follower.
+    let finalized = shared_follow.finalize([1; 32], [1; 32]);
+    assert_eq!(finalized.previous_finalized_block, ([0; 32], 0));
+    assert!(finalized.unpinned_blocks.is_empty());
+
+    // The follower has two blocks pinned and can't pin a third one.
+    let removed = shared_follow.add_block([2; 32], [1; 32], 2, true);
+    assert_eq!(removed.followers, vec![(follower, "a")]);
+    assert_eq!(removed.unpinned_blocks, vec![[0; 32]]);
+    assert!(!shared_follow.is_pinned(follower, &[1; 32]));
+    assert_eq!(shared_follow.followers().len(), 0);
+    assert_eq!(shared_follow.num_pinned_blocks(), 2);
+}
+
+#[test]
+fn unpin_after_finalization() {
+    let mut shared_follow = new(8);
+    shared_follow.add_block([1; 32], [0; 32], 1, true);
+    let follower1 = shared_follow.add_follower("a").unwrap();
+    let follower2 = shared_follow.add_follower("b").unwrap();
+
+    // Unpinning a block that is still part of the tree doesn't release it.
+    assert_eq!(shared_follow.unpin_block(follower1, &[0; 32]), Ok(false));
+    assert_eq!(
+        shared_follow.unpin_block(follower1, &[0; 32]),
+        Err(UnpinError::NotPinned)
+    );
+
+    let finalized = shared_follow.finalize([1; 32], [1; 32]);
+    assert!(finalized.unpinned_blocks.is_empty());
+    assert_eq!(shared_follow.finalized_block_hash(), &[1; 32]);
+    assert_eq!(shared_follow.finalized_block_user_data(), &1);
+
+    // The last follower to unpin the former finalized block releases it.
+    assert_eq!(shared_follow.unpin_block(follower2, &[0; 32]), Ok(true));
+    assert_eq!(shared_follow.num_pinned_blocks(), 1);
+}
+
+#[test]
+fn finalize_prunes_forks() {
+    let mut shared_follow = new(8);
+    //       /- 1 - 2 - 3
+    // 0 - -
+    //       \- 4 - 5
+    shared_follow.add_block([1; 32], [0; 32], 1, true);
+    shared_follow.add_block([4; 32], [0; 32], 4, false);
+    shared_follow.add_block([2; 32], [1; 32], 2, true);
+    shared_follow.add_block([5; 32], [4; 32], 5, false);
+    shared_follow.add_block([3; 32], [2; 32], 3, true);
+    let follower = shared_follow.add_follower("a").unwrap();
+
+    let finalized = shared_follow.finalize([2; 32], [3; 32]);
+    assert_eq!(finalized.previous_finalized_block, ([0; 32], 0));
+    assert_eq!(finalized.newly_finalized_blocks, vec![([1; 32], 1)]);
+    assert_eq!(finalized.pruned_blocks, vec![([4; 32], 4), ([5; 32], 5)]);
+    assert!(finalized.unpinned_blocks.is_empty());
+    assert_eq!(
+        shared_follow
+            .non_finalized_blocks_ancestry_order()
+            .map(|(h, _)| *h)
+            .collect::<Vec<_>>(),
+        vec![[3; 32]]
+    );
+    assert!(shared_follow.block_user_data(&[4; 32]).is_none());
+
+    // Removing the follower releases all the blocks that have left the tree.
+    let (user_data, mut unpinned) = shared_follow.remove_follower(follower).unwrap();
+    unpinned.sort();
+    assert_eq!(user_data, "a");
+    assert_eq!(unpinned, vec![[0; 32], [1; 32], [4; 32], [5; 32]]);
+    assert!(shared_follow.remove_follower(follower).is_none());
+    assert_eq!(shared_follow.num_pinned_blocks(), 2);
+}
+
+#[test]
+fn finalize_without_followers_unpins() {
+    let mut shared_follow = new(8);
+    shared_follow.add_block([1; 32], [0; 32], 1, true);
+    shared_follow.add_block([2; 32], [0; 32], 2, false);
+
+    let mut finalized = shared_follow.finalize([1; 32], [1; 32]);
+    finalized.unpinned_blocks.sort();
+    assert_eq!(finalized.unpinned_blocks, vec![[0; 32], [2; 32]]);
+    assert_eq!(shared_follow.num_pinned_blocks(), 1);
+}
+
+#[test]
+fn acquire_keeps_block_pinned() {
+    let mut shared_follow = new(8);
+    shared_follow.add_block([1; 32], [0; 32], 1, true);
+    let follower = shared_follow.add_follower("a").unwrap();
+    shared_follow.finalize([1; 32], [1; 32]);
+
+    assert!(shared_follow.acquire_block(follower, &[0; 32]));
+    assert!(!shared_follow.acquire_block(follower, &[9; 32]));
+
+    // The follower unpins the block while a reference is still held.
+    assert_eq!(shared_follow.unpin_block(follower, &[0; 32]), Ok(false));
+    assert!(!shared_follow.acquire_block(follower, &[0; 32]));
+    assert!(shared_follow.release_block(&[0; 32]));
+}
+
+#[test]
+fn unpin_unknown_follower() {
+    let mut shared_follow = new(8);
+    let follower = shared_follow.add_follower("a").unwrap();
+    assert!(shared_follow.remove_follower(follower).is_some());
+    assert_eq!(
+        shared_follow.unpin_block(follower, &[0; 32]),
+        Err(UnpinError::UnknownFollower)
+    );
+    assert!(!shared_follow.is_pinned(follower, &[0; 32]));
+
+    // Identifiers aren't reused.
+    assert_ne!(shared_follow.add_follower("b").unwrap(), follower);
+}
@@ -0,0 +1,330 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Answering `state_traceBlock` requests.
+//!
+//! Tracing a block consists in re-executing it on top of the storage of its parent by calling
+//! the [`EXECUTE_BLOCK_FUNCTION_NAME`] runtime function with
+//! [`runtime_host::Config::tracing`] set to `true`, and recording, using a [`Recorder`], the
+//! storage host functions called by the runtime during this execution and the spans and events
+//! that the runtime emits.
+//!
+//! The storage events generated by the [`Recorder`] mimic the ones emitted by Substrate under
+//! the `state` target, and are children of the span that covers the entire execution. Only
+//! runtimes compiled with tracing support emit spans and events of their own.
+
+use super::methods;
+use crate::{
+    executor::{runtime_host, storage_diff},
+    header, util,
+};
+
+use alloc::{
+    borrow::ToOwned as _,
+    collections::BTreeMap,
+    string::{String, ToString as _},
+    vec::Vec,
+};
+use core::iter;
+
+/// Name of the runtime function to call in order to re-execute a block.
+pub const EXECUTE_BLOCK_FUNCTION_NAME: &str = "Core_execute_block";
+
+/// Targets used if the request doesn't specify any, in accordance with the behavior of
+/// Substrate.
+pub const DEFAULT_TARGETS: &str = "pallet,frame,state";
+
+/// Produces the input to pass to the [`EXECUTE_BLOCK_FUNCTION_NAME`] runtime call.
+///
+/// The seal of the header, if any, is removed, as the runtime expects an unsealed header.
+pub fn execute_block_parameters(
+    scale_encoded_header: &[u8],
+    block_number_bytes: usize,
+    body: impl ExactSizeIterator<Item = impl AsRef<[u8]>>,
+) -> Result<Vec<u8>, header::Error> {
+    let mut unsealed_header = header::decode(scale_encoded_header, block_number_bytes)?;
+    let _seal_log = unsealed_header.digest.pop_seal();
+
+    let mut out = unsealed_header.scale_encoding_vec(block_number_bytes);
+    out.extend_from_slice(util::encode_scale_compact_usize(body.len()).as_ref());
+    for extrinsic in body {
+        out.extend_from_slice(extrinsic.as_ref());
+    }
+    Ok(out)
+}
+
+/// Records the storage accesses performed during the execution of a block.
+pub struct Recorder {
+    /// Value passed as `targets` in the request, or [`DEFAULT_TARGETS`].
+    targets_raw: String,
+    /// List of target prefixes parsed from [`Recorder::targets_raw`].
+    targets: Vec<String>,
+    /// Value passed as `storage_keys` in the request.
+    storage_keys_raw: String,
+    /// List of hexadecimal lowercase key prefixes parsed from [`Recorder::storage_keys_raw`].
+    /// Empty if all keys are accepted.
+    storage_keys: Vec<String>,
+    /// Value passed as `methods` in the request.
+    methods_raw: String,
+    /// List of method names parsed from [`Recorder::methods_raw`]. Empty if all methods are
+    /// accepted.
+    methods: Vec<String>,
+    /// Spans emitted by the runtime recorded so far.
+    spans: Vec<methods::TraceSpan>,
+    /// Events recorded so far.
+    events: Vec<methods::TraceEvent>,
+}
+
+/// Identifier of the span that covers the entire execution of the block. All events are
+/// children of this span.
+const ROOT_SPAN_ID: u64 = 1;
+
+impl Recorder {
+    /// Initializes a new [`Recorder`] from the parameters of a `state_traceBlock` request.
+    ///
+    /// Each parameter is a comma-separated list. `targets` are prefixes of the targets of the
+    /// spans and events to keep, optionally followed with `=<level>` (which is ignored),
+    /// `storage_keys` are hexadecimal prefixes of the storage keys of the events to keep, and
+    /// `methods` are the names of the storage methods of the events to keep.
+    pub fn new(targets: Option<&str>, storage_keys: Option<&str>, methods: Option<&str>) -> Self {
+        let targets_raw = targets.unwrap_or(DEFAULT_TARGETS).to_owned();
+        let storage_keys_raw = storage_keys.unwrap_or("").to_owned();
+        let methods_raw = methods.unwrap_or("").to_owned();
+
+        Recorder {
+            targets: split_list(&targets_raw)
+                .map(|target| target.split('=').next().unwrap().to_owned())
+                .collect(),
+            storage_keys: split_list(&storage_keys_raw)
+                .map(|key| key.trim_start_matches("0x").to_ascii_lowercase())
+                .collect(),
+            methods: split_list(&methods_raw).map(|m| m.to_owned()).collect(),
+            targets_raw,
+            storage_keys_raw,
+            methods_raw,
+            spans: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Records a read of the storage of the parent block.
+    pub fn storage_get(&mut self, key: &[u8], value: Option<&[u8]>) {
+        self.push_state_event("Get", key, "result", value.map(hex::encode));
+    }
+
+    /// Records a request for the key that follows `key` in the storage of the parent block.
+    pub fn storage_next_key(&mut self, key: &[u8], next_key: Option<&[u8]>) {
+        self.push_state_event("NextStorageKey", key, "result", next_key.map(hex::encode));
+    }
+
+    /// Records a request for the list of keys starting with `prefix` in the storage of the
+    /// parent block.
+    pub fn storage_prefix_keys(&mut self, prefix: &[u8], num_keys: usize) {
+        self.push_state_event("PrefixKeys", prefix, "result", Some(num_keys.to_string()));
+    }
+
+    /// Records the changes that the block performs on the storage, in the order of their keys.
+    pub fn storage_changes<T>(&mut self, changes: &storage_diff::TrieDiff<T>) {
+        let mut changes = changes
+            .diff_iter_unordered()
+            .map(|(key, value, _)| (key, value))
+            .collect::<Vec<_>>();
+        changes.sort_unstable_by_key(|(key, _)| *key);
+
+        for (key, value) in changes {
+            self.push_state_event("Put", key, "value", value.map(hex::encode));
+        }
+    }
+
+    /// Records the spans and events that the runtime has emitted, as found in
+    /// [`runtime_host::Success::tracing`].
+    pub fn runtime_tracing(&mut self, records: &[runtime_host::TracingRecord]) {
+        // The identifiers of the spans emitted by the runtime are shifted in order to not
+        // conflict with the span covering the entire execution.
+        let span_id = |id: Option<u64>| id.map_or(ROOT_SPAN_ID, |id| id + ROOT_SPAN_ID);
+
+        for record in records {
+            match record {
+                runtime_host::TracingRecord::Span { id, span } => {
+                    if !self.is_target_enabled(&span.target) {
+                        continue;
+                    }
+
+                    self.spans.push(methods::TraceSpan {
+                        id: span_id(Some(*id)),
+                        parent_id: Some(span_id(span.parent_id)),
+                        name: span.name.clone(),
+                        target: span.target.clone(),
+                        wasm: true,
+                    });
+                }
+                runtime_host::TracingRecord::Event(event) => {
+                    if !self.is_target_enabled(&event.target) {
+                        continue;
+                    }
+
+                    self.events.push(methods::TraceEvent {
+                        target: event.target.clone(),
+                        data: methods::TraceEventData {
+                            string_values: event.fields.iter().cloned().collect(),
+                        },
+                        parent_id: Some(span_id(event.parent_id)),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Builds the trace of the block.
+    pub fn finish(self, block_hash: [u8; 32], parent_hash: [u8; 32]) -> methods::BlockTrace {
+        methods::BlockTrace {
+            block_hash: methods::HashHexString(block_hash),
+            parent_hash: methods::HashHexString(parent_hash),
+            tracing_targets: self.targets_raw,
+            storage_keys: self.storage_keys_raw,
+            methods: self.methods_raw,
+            // The span covering the entire execution is always reported, so that the events
+            // always have a parent.
+            spans: iter::once(methods::TraceSpan {
+                id: ROOT_SPAN_ID,
+                parent_id: None,
+                name: EXECUTE_BLOCK_FUNCTION_NAME.to_owned(),
+                target: "executor".to_owned(),
+                wasm: false,
+            })
+            .chain(self.spans)
+            .collect(),
+            events: self.events,
+        }
+    }
+
+    /// Returns `true` if the spans and events of the given target must be recorded.
+    fn is_target_enabled(&self, target: &str) -> bool {
+        self.targets.iter().any(|t| target.starts_with(&t[..]))
+    }
+
+    fn push_state_event(
+        &mut self,
+        method: &str,
+        key: &[u8],
+        result_field: &str,
+        result: Option<String>,
+    ) {
+        if !self.is_target_enabled("state") {
+            return;
+        }
+
+        if !self.methods.is_empty() && !self.methods.iter().any(|m| m == method) {
+            return;
+        }
+
+        let key = hex::encode(key);
+        if !self.storage_keys.is_empty() && !self.storage_keys.iter().any(|k| key.starts_with(k)) {
+            return;
+        }
+
+        let mut string_values = BTreeMap::new();
+        string_values.insert("method".to_owned(), method.to_owned());
+        string_values.insert("key".to_owned(), key);
+        string_values.insert(
+            result_field.to_owned(),
+            result.unwrap_or_else(|| "None".to_owned()),
+        );
+
+        self.events.push(methods::TraceEvent {
+            target: "state".to_owned(),
+            data: methods::TraceEventData { string_values },
+            parent_id: Some(ROOT_SPAN_ID),
+        });
+    }
+}
+
+/// Splits a comma-separated list, ignoring empty entries.
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(|e| e.trim()).filter(|e| !e.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn default_targets_include_state() {
+        let mut recorder = super::Recorder::new(None, None, None);
+        recorder.storage_get(&[0xab, 0xcd], Some(&[1, 2]));
+        let trace = recorder.finish([0; 32], [1; 32]);
+        assert_eq!(trace.tracing_targets, super::DEFAULT_TARGETS);
+        assert_eq!(trace.events.len(), 1);
+        assert_eq!(trace.events[0].data.string_values["method"], "Get");
+        assert_eq!(trace.events[0].data.string_values["key"], "abcd");
+        assert_eq!(trace.events[0].data.string_values["result"], "0102");
+    }
+
+    #[test]
+    fn filters_applied() {
+        let mut recorder = super::Recorder::new(Some("pallet"), None, None);
+        recorder.storage_get(&[0xab], None);
+        assert!(recorder.finish([0; 32], [0; 32]).events.is_empty());
+
+        let mut recorder = super::Recorder::new(Some("state=trace"), Some("0xAB"), Some("Put"));
+        recorder.storage_get(&[0xab], None);
+        recorder.storage_next_key(&[0xab], None);
+        let mut diff = crate::executor::storage_diff::TrieDiff::empty();
+        diff.diff_insert(&[0xab, 0x01][..], &[5][..], ());
+        diff.diff_insert_erase(&[0xcd][..], ());
+        recorder.storage_changes(&diff);
+
+        let trace = recorder.finish([0; 32], [0; 32]);
+        assert_eq!(trace.events.len(), 1);
+        assert_eq!(trace.events[0].data.string_values["key"], "ab01");
+        assert_eq!(trace.events[0].data.string_values["value"], "05");
+    }
+
+    #[test]
+    fn runtime_spans_and_events() {
+        let entry = |parent_id, target: &str| crate::executor::host::TracingEntry {
+            parent_id,
+            name: "foo".to_owned(),
+            target: target.to_owned(),
+            level: crate::executor::host::TracingLevel::Info,
+            fields: vec![("a".to_owned(), "b".to_owned())],
+        };
+
+        let mut recorder = super::Recorder::new(Some("pallet,frame"), None, None);
+        recorder.runtime_tracing(&[
+            super::runtime_host::TracingRecord::Span {
+                id: 1,
+                span: entry(None, "frame_executive"),
+            },
+            super::runtime_host::TracingRecord::Span {
+                id: 2,
+                span: entry(Some(1), "runtime"),
+            },
+            super::runtime_host::TracingRecord::Event(entry(Some(1), "pallet_balances")),
+            super::runtime_host::TracingRecord::Event(entry(Some(2), "runtime")),
+        ]);
+
+        let trace = recorder.finish([0; 32], [0; 32]);
+        assert_eq!(trace.spans.len(), 2);
+        assert_eq!(trace.spans[1].id, 2);
+        assert_eq!(trace.spans[1].parent_id, Some(super::ROOT_SPAN_ID));
+        assert_eq!(trace.spans[1].target, "frame_executive");
+        assert!(trace.spans[1].wasm);
+        assert_eq!(trace.events.len(), 1);
+        assert_eq!(trace.events[0].parent_id, Some(2));
+        assert_eq!(trace.events[0].target, "pallet_balances");
+        assert_eq!(trace.events[0].data.string_values["a"], "b");
+    }
+}
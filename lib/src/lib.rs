@@ -201,12 +201,14 @@ pub mod chain;
 pub mod chain_spec;
 pub mod database;
 pub mod executor;
+pub mod extrinsic_builder;
 pub mod finality;
 pub mod header;
 pub mod identity;
 pub mod informant;
 pub mod json_rpc;
 pub mod libp2p;
+pub mod metadata;
 pub mod network;
 pub mod sync;
 pub mod transactions;
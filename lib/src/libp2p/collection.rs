@@ -113,6 +113,11 @@ pub struct Config {
     /// >           many substreams.
     pub max_inbound_substreams: usize,
 
+    /// Maximum size, in bytes, that the Yamux receive window of each substream can grow to.
+    ///
+    /// See [`established::Config::yamux_max_receive_window`].
+    pub yamux_max_receive_window: u64,
+
     pub notification_protocols: Vec<NotificationProtocolConfig>,
 
     pub request_response_protocols: Vec<ConfigRequestResponse>,
@@ -252,6 +257,9 @@ pub struct Network<TConn, TNow> {
     /// See [`Config::max_inbound_substreams`].
     max_inbound_substreams: usize,
 
+    /// See [`Config::yamux_max_receive_window`].
+    yamux_max_receive_window: u64,
+
     /// See [`Config::handshake_timeout`].
     handshake_timeout: Duration,
 
@@ -356,6 +364,7 @@ where
             randomness_seeds: ChaCha20Rng::from_seed(config.randomness_seed),
             noise_key: Arc::new(config.noise_key),
             max_inbound_substreams: config.max_inbound_substreams,
+            yamux_max_receive_window: config.yamux_max_receive_window,
             notification_protocols,
             request_response_protocols: config.request_response_protocols.into_iter().collect(), // TODO: stupid overhead
             ping_protocol: config.ping_protocol.into(),
@@ -386,6 +395,7 @@ where
             when_connected + self.handshake_timeout,
             self.noise_key.clone(),
             self.max_inbound_substreams,
+            self.yamux_max_receive_window,
             self.notification_protocols.clone(),
             self.request_response_protocols.clone(),
             self.ping_protocol.clone(),
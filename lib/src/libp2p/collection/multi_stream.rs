@@ -201,6 +201,7 @@ where
                         .collect(),
                     request_protocols: request_response_protocols.to_vec(), // TODO: overhead
                     max_inbound_substreams,
+                    yamux_max_receive_window: 0, // Unused for multi-stream connections.
                     randomness_seed,
                     ping_protocol: ping_protocol.to_string(), // TODO: cloning :-/
                     ping_interval: Duration::from_secs(20),   // TODO: hardcoded
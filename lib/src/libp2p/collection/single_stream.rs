@@ -68,6 +68,9 @@ enum SingleStreamConnectionTaskInner<TNow> {
         /// See [`super::Config::max_inbound_substreams`].
         max_inbound_substreams: usize,
 
+        /// See [`super::Config::yamux_max_receive_window`].
+        yamux_max_receive_window: u64,
+
         /// See [`OverlayNetwork`].
         notification_protocols: Arc<[OverlayNetwork]>,
 
@@ -141,6 +144,7 @@ where
         handshake_timeout: TNow,
         noise_key: Arc<NoiseKey>,
         max_inbound_substreams: usize,
+        yamux_max_receive_window: u64,
         notification_protocols: Arc<[OverlayNetwork]>,
         request_response_protocols: Arc<[ConfigRequestResponse]>,
         ping_protocol: Arc<str>,
@@ -156,6 +160,7 @@ where
                 timeout: handshake_timeout,
                 noise_key,
                 max_inbound_substreams,
+                yamux_max_receive_window,
                 notification_protocols,
                 request_response_protocols,
                 ping_protocol,
@@ -690,6 +695,7 @@ where
                 timeout,
                 noise_key,
                 max_inbound_substreams,
+                yamux_max_receive_window,
                 notification_protocols,
                 request_response_protocols,
                 ping_protocol,
@@ -754,6 +760,7 @@ where
                                 timeout,
                                 noise_key,
                                 max_inbound_substreams,
+                                yamux_max_receive_window,
                                 notification_protocols,
                                 request_response_protocols,
                                 ping_protocol,
@@ -783,6 +790,7 @@ where
                                         .collect(),
                                     request_protocols: request_response_protocols.to_vec(), // TODO: overhead
                                     max_inbound_substreams,
+                                    yamux_max_receive_window,
                                     randomness_seed,
                                     ping_protocol: ping_protocol.to_string(), // TODO: cloning :-/
                                     ping_interval: Duration::from_secs(20),   // TODO: hardcoded
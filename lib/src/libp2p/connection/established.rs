@@ -196,6 +196,9 @@ pub enum Event<TRqUd, TNotifUd> {
 pub struct Config<TNow> {
     /// Maximum number of substreams that the remote can have simultaneously opened.
     pub max_inbound_substreams: usize,
+    /// Maximum size, in bytes, that the Yamux receive window of each substream can grow to.
+    /// See [`yamux::Config::max_receive_window`]. Ignored for multi-stream connections.
+    pub yamux_max_receive_window: u64,
     /// List of request-response protocols supported for incoming substreams.
     pub request_protocols: Vec<ConfigRequestResponse>,
     /// List of notifications protocols supported for incoming substreams.
@@ -1126,6 +1126,7 @@ impl ConnectionPrototype {
         let mut yamux = yamux::Yamux::new(yamux::Config {
             is_initiator: self.encryption.is_initiator(),
             capacity: 64, // TODO: ?
+            max_receive_window: config.yamux_max_receive_window,
             randomness_seed: randomness.sample(rand::distributions::Standard),
         });
 
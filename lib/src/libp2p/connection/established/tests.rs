@@ -248,6 +248,7 @@ fn handshake_works() {
             notifications_protocols: Vec::new(),
             request_protocols: Vec::new(),
             max_inbound_substreams: 64,
+            yamux_max_receive_window: 1024 * 1024,
             ping_interval: Duration::from_secs(20),
             ping_protocol: "ping".to_owned(),
             ping_timeout: Duration::from_secs(20),
@@ -276,6 +277,7 @@ fn successful_request() {
             name: "test-request-protocol".to_owned(),
         }],
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -316,6 +318,64 @@ fn successful_request() {
     }
 }
 
+#[test]
+fn request_larger_than_default_window() {
+    // The request is larger than the default Yamux window, meaning that the receiver must
+    // properly give back credits to the sender for the request to go through.
+    let config = Config {
+        first_out_ping: Duration::new(60, 0),
+        notifications_protocols: Vec::new(),
+        request_protocols: vec![ConfigRequestResponse {
+            inbound_allowed: true,
+            inbound_config: ConfigRequestResponseIn::Payload {
+                max_size: 2 * 1024 * 1024,
+            },
+            max_response_size: 1024,
+            name: "test-request-protocol".to_owned(),
+        }],
+        max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
+        ping_interval: Duration::from_secs(20),
+        ping_protocol: "ping".to_owned(),
+        ping_timeout: Duration::from_secs(20),
+        randomness_seed: [0; 32],
+    };
+
+    let mut connections = perform_handshake(65536, 65536, config.clone(), config);
+
+    let request = (0..1024 * 1024).map(|n| n as u8).collect::<Vec<_>>();
+    let substream_id = connections
+        .alice
+        .add_request(0, request.clone(), Duration::from_secs(5), ())
+        .unwrap();
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    match event {
+        either::Right(Event::RequestIn {
+            id,
+            protocol_index: 0,
+            request: received,
+        }) => {
+            assert!(received == request);
+            connections
+                .bob
+                .respond_in_request(id, Ok(b"response payload".to_vec()))
+                .unwrap();
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+
+    let (_, event) = connections.run_until_event();
+    match event {
+        either::Left(Event::Response { id, response, .. }) => {
+            assert_eq!(id, substream_id);
+            assert_eq!(response.unwrap(), b"response payload".to_vec());
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+}
+
 #[test]
 fn refused_request() {
     let config = Config {
@@ -328,6 +388,7 @@ fn refused_request() {
             name: "test-request-protocol".to_owned(),
         }],
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -380,6 +441,7 @@ fn request_protocol_not_supported() {
             name: "test-request-protocol".to_owned(),
         }],
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -421,6 +483,7 @@ fn request_timeout() {
             name: "test-request-protocol".to_owned(),
         }],
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -471,6 +534,7 @@ fn outbound_substream_works() {
         }],
         request_protocols: Vec::new(),
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -552,6 +616,7 @@ fn outbound_substream_open_timeout() {
         }],
         request_protocols: Vec::new(),
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -604,6 +669,7 @@ fn outbound_substream_refuse() {
         }],
         request_protocols: Vec::new(),
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
@@ -657,6 +723,7 @@ fn outbound_substream_close_demanded() {
         }],
         request_protocols: Vec::new(),
         max_inbound_substreams: 64,
+        yamux_max_receive_window: 1024 * 1024,
         ping_interval: Duration::from_secs(20),
         ping_protocol: "ping".to_owned(),
         ping_timeout: Duration::from_secs(20),
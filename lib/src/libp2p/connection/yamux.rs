@@ -64,6 +64,7 @@ use rand_chacha::{rand_core::SeedableRng as _, ChaCha20Rng};
 pub use header::GoAwayErrorCode;
 
 mod header;
+mod tests;
 
 /// Name of the protocol, typically used when negotiated it using *multistream-select*.
 pub const PROTOCOL_NAME: &str = "/yamux/1.0.0";
@@ -78,6 +79,16 @@ pub struct Config {
     /// combined.
     pub capacity: usize,
 
+    /// Maximum size, in bytes, that the receive window of each substream can grow to.
+    ///
+    /// Each substream starts with a receive window of 256kiB, as mandated by the Yamux
+    /// specification. Whenever the remote entirely exhausts the window of a substream, its
+    /// throughput is considered to be limited by the window rather than by the connection, and
+    /// the window is doubled, up to this maximum.
+    ///
+    /// Values inferior to 256kiB are treated as 256kiB, meaning that the window never grows.
+    pub max_receive_window: u64,
+
     /// Seed used for the randomness. Used to avoid HashDoS attack and determines the order in
     /// which the data on substreams is sent out.
     pub randomness_seed: [u8; 32],
@@ -125,6 +136,9 @@ pub struct Yamux<T> {
 
     /// Source of randomness used for various purposes.
     randomness: ChaCha20Rng,
+
+    /// See [`Config::max_receive_window`].
+    max_receive_window: u64,
 }
 
 struct Substream<T> {
@@ -149,6 +163,10 @@ enum SubstreamState {
         /// If non-zero, a window update frame must be sent to the remote to grant this number of
         /// bytes.
         remote_window_pending_increase: u64,
+        /// Size of the window granted to the remote. The credits used by the remote are given
+        /// back in order for the window to stay at this size. Grows over time, up to
+        /// [`Yamux::max_receive_window`], if the remote is throttled by the window.
+        receive_window: u64,
         /// Amount of data the local node is allowed to transmit to the remote.
         allowed_window: u64,
         local_write: SubstreamStateLocalWrite,
@@ -302,6 +320,7 @@ impl<T> Yamux<T> {
             pings_waiting_reply: VecDeque::new(),
             rsts_to_send: VecDeque::with_capacity(config.capacity),
             randomness,
+            max_receive_window: cmp::max(config.max_receive_window, DEFAULT_FRAME_SIZE),
         }
     }
 
@@ -397,6 +416,7 @@ impl<T> Yamux<T> {
                 remote_syn_acked: false,
                 remote_allowed_window: DEFAULT_FRAME_SIZE,
                 remote_window_pending_increase: 0,
+                receive_window: DEFAULT_FRAME_SIZE,
                 allowed_window: DEFAULT_FRAME_SIZE,
                 local_write: SubstreamStateLocalWrite::Open,
                 remote_write_closed: false,
@@ -1001,6 +1021,12 @@ impl<T> Yamux<T> {
                                 break;
                             }
 
+                            // The data of the frame that opens the substream counts towards
+                            // the initial window.
+                            if is_data && u64::from(length) > DEFAULT_FRAME_SIZE {
+                                return Err(Error::CreditsExceeded);
+                            }
+
                             self.incoming = Incoming::PendingIncomingSubstream {
                                 substream_id: SubstreamId(stream_id),
                                 extra_window: if !is_data { length } else { 0 },
@@ -1035,6 +1061,7 @@ impl<T> Yamux<T> {
                                         remote_write_closed,
                                         remote_allowed_window,
                                         remote_window_pending_increase,
+                                        receive_window,
                                         ..
                                     },
                                 ..
@@ -1044,15 +1071,15 @@ impl<T> Yamux<T> {
                                     return Err(Error::WriteAfterFin);
                                 }
 
-                                // Check whether the remote has the right to send that much data.
                                 // Note that the credits aren't checked in the case of an unknown
                                 // substream.
-                                *remote_allowed_window = remote_allowed_window
-                                    .checked_sub(u64::from(length))
-                                    .ok_or(Error::CreditsExceeded)?;
-
-                                // TODO: make this behavior tweakable by the user!
-                                *remote_window_pending_increase += 256 * 1024;
+                                consume_receive_window(
+                                    remote_allowed_window,
+                                    remote_window_pending_increase,
+                                    receive_window,
+                                    self.max_receive_window,
+                                    length,
+                                )?;
                             }
 
                             self.incoming = Incoming::DataFrame {
@@ -1155,14 +1182,29 @@ impl<T> Yamux<T> {
                 data_frame_size,
                 fin,
             } => {
+                let mut remote_allowed_window = DEFAULT_FRAME_SIZE;
+                let mut remote_window_pending_increase = 0;
+                let mut receive_window = DEFAULT_FRAME_SIZE;
+                // Can't fail, as the size of the data frame has been checked when the header was
+                // received.
+                consume_receive_window(
+                    &mut remote_allowed_window,
+                    &mut remote_window_pending_increase,
+                    &mut receive_window,
+                    self.max_receive_window,
+                    data_frame_size,
+                )
+                .unwrap();
+
                 let _was_before = self.substreams.insert(
                     substream_id.0,
                     Substream {
                         state: SubstreamState::Healthy {
                             first_message_queued: false,
                             remote_syn_acked: true,
-                            remote_allowed_window: DEFAULT_FRAME_SIZE,
-                            remote_window_pending_increase: 0,
+                            remote_allowed_window,
+                            remote_window_pending_increase,
+                            receive_window,
                             allowed_window: DEFAULT_FRAME_SIZE + u64::from(extra_window),
                             local_write: SubstreamStateLocalWrite::Open,
                             remote_write_closed: data_frame_size == 0 && fin,
@@ -1892,10 +1934,17 @@ impl<'a, T> ExtractOut<'a, T> {
                             SubstreamState::Healthy {
                                 write_buffers,
                                 local_write,
+                                allowed_window,
                                 ..
                             } => {
-                                !write_buffers.is_empty()
-                                    || matches!(local_write, SubstreamStateLocalWrite::FinDesired)
+                                // Substreams whose window is exhausted must wait for the remote
+                                // to grant more credits, otherwise empty data frames would be
+                                // sent out in a loop.
+                                if write_buffers.is_empty() {
+                                    matches!(local_write, SubstreamStateLocalWrite::FinDesired)
+                                } else {
+                                    *allowed_window != 0
+                                }
                             }
                             _ => false,
                         })
@@ -1939,6 +1988,35 @@ impl<'a, T> ExtractOut<'a, T> {
     }
 }
 
+/// Updates the receive window of a substream after the remote has sent a data frame of the
+/// given length.
+fn consume_receive_window(
+    remote_allowed_window: &mut u64,
+    remote_window_pending_increase: &mut u64,
+    receive_window: &mut u64,
+    max_receive_window: u64,
+    length: u32,
+) -> Result<(), Error> {
+    // Check whether the remote has the right to send that much data.
+    *remote_allowed_window = remote_allowed_window
+        .checked_sub(u64::from(length))
+        .ok_or(Error::CreditsExceeded)?;
+
+    // Give back the credits that the remote has just used.
+    *remote_window_pending_increase += u64::from(length);
+
+    // If the remote has used all of its credits, it is likely that the speed at which it sends
+    // data is limited by the size of the window rather than by the connection. In that
+    // situation, the window is enlarged.
+    if *remote_allowed_window == 0 && *receive_window < max_receive_window {
+        let new_window = cmp::min(*receive_window * 2, max_receive_window);
+        *remote_window_pending_increase += new_window - *receive_window;
+        *receive_window = new_window;
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 struct VecWithOffset(Vec<u8>, usize);
 impl AsRef<[u8]> for VecWithOffset {
@@ -0,0 +1,138 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{Config, IncomingDataDetail, Yamux};
+
+/// Extracts all the data that `yamux` wants to send out.
+fn extract(yamux: &mut Yamux<()>) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut extract = yamux.extract_out(usize::MAX);
+    while let Some(buffer) = extract.next() {
+        data.extend_from_slice(buffer.as_ref());
+    }
+    data
+}
+
+/// Injects the given data into `to`, accepting all incoming substreams on the way. Returns the
+/// data that `to` wants to send out as a result.
+fn inject(data: &[u8], mut to: Yamux<()>) -> (Yamux<()>, Vec<u8>) {
+    let mut to_out = Vec::new();
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let outcome = to.incoming_data(&data[offset..]).unwrap();
+        to = outcome.yamux;
+        offset += outcome.bytes_read;
+        match outcome.detail {
+            Some(IncomingDataDetail::IncomingSubstream) => {
+                to.accept_pending_substream(());
+            }
+            Some(_) => {}
+            None if outcome.bytes_read == 0 => {
+                // Processing is blocked until the data queued by `to` is extracted.
+                let extracted = extract(&mut to);
+                assert!(!extracted.is_empty());
+                to_out.extend(extracted);
+            }
+            None => {}
+        }
+    }
+
+    to_out.extend(extract(&mut to));
+    (to, to_out)
+}
+
+/// Returns the length field of all the window update frames with a non-zero length found in
+/// the given data, which must only contain headers.
+fn window_updates(data: &[u8]) -> Vec<u32> {
+    assert_eq!(data.len() % 12, 0);
+    data.chunks(12)
+        .filter(|header| header[1] == 1)
+        .map(|header| u32::from_be_bytes(<[u8; 4]>::try_from(&header[8..12]).unwrap()))
+        .filter(|length| *length != 0)
+        .collect()
+}
+
+#[test]
+fn receive_window_grows_up_to_maximum() {
+    let mut alice = Yamux::new(Config {
+        is_initiator: true,
+        capacity: 8,
+        max_receive_window: 1024 * 1024,
+        randomness_seed: [0; 32],
+    });
+    let mut bob = Yamux::new(Config {
+        is_initiator: false,
+        capacity: 8,
+        max_receive_window: 1024 * 1024,
+        randomness_seed: [0; 32],
+    });
+
+    alice.open_substream(()).write(vec![0; 8 * 1024 * 1024]);
+
+    // Every time Alice exhausts the window, Bob gives back the credits and doubles the window,
+    // until it reaches the maximum.
+    let mut updates = Vec::new();
+    let mut to_bob = extract(&mut alice);
+    for _ in 0..4 {
+        let (bob_update, to_alice) = inject(&to_bob, bob);
+        bob = bob_update;
+        updates.extend(window_updates(&to_alice));
+        let (alice_update, to_bob_update) = inject(&to_alice, alice);
+        alice = alice_update;
+        to_bob = to_bob_update;
+    }
+
+    assert_eq!(
+        updates,
+        vec![512 * 1024, 1024 * 1024, 1024 * 1024, 1024 * 1024]
+    );
+}
+
+#[test]
+fn receive_window_maximum_below_default() {
+    let mut alice = Yamux::new(Config {
+        is_initiator: true,
+        capacity: 8,
+        max_receive_window: 256 * 1024,
+        randomness_seed: [0; 32],
+    });
+    let mut bob = Yamux::new(Config {
+        is_initiator: false,
+        capacity: 8,
+        max_receive_window: 0,
+        randomness_seed: [0; 32],
+    });
+
+    alice.open_substream(()).write(vec![0; 2 * 1024 * 1024]);
+
+    // The window never grows, and the credits used by Alice are simply given back.
+    let mut updates = Vec::new();
+    let mut to_bob = extract(&mut alice);
+    for _ in 0..3 {
+        let (bob_update, to_alice) = inject(&to_bob, bob);
+        bob = bob_update;
+        updates.extend(window_updates(&to_alice));
+        let (alice_update, to_bob_update) = inject(&to_alice, alice);
+        alice = alice_update;
+        to_bob = to_bob_update;
+    }
+
+    assert_eq!(updates, vec![256 * 1024; 3]);
+}
@@ -86,6 +86,11 @@ pub struct Config {
     /// >           many substreams.
     pub max_inbound_substreams: usize,
 
+    /// Maximum size, in bytes, that the Yamux receive window of each substream can grow to.
+    ///
+    /// See [`collection::Config::yamux_max_receive_window`].
+    pub yamux_max_receive_window: u64,
+
     pub notification_protocols: Vec<NotificationProtocolConfig>,
 
     pub request_response_protocols: Vec<ConfigRequestResponse>,
@@ -222,6 +227,7 @@ where
                 capacity: config.connections_capacity,
                 noise_key: config.noise_key,
                 max_inbound_substreams: config.max_inbound_substreams,
+                yamux_max_receive_window: config.yamux_max_receive_window,
                 notification_protocols: config.notification_protocols,
                 request_response_protocols: config.request_response_protocols,
                 ping_protocol: config.ping_protocol,
@@ -0,0 +1,716 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runtime metadata decoding.
+//!
+//! The metadata of a runtime is a data structure, returned by the `Metadata_metadata` runtime
+//! function, that describes the pallets of the runtime, their storage items, their constants,
+//! and the types of their calls, events and errors. It also describes the format of the
+//! extrinsics of the chain.
+//!
+//! Most of the types that the metadata refers to are found in a *type registry* stored at the
+//! beginning of the metadata. Within the metadata, types are designated by their index within
+//! this registry. See [`Metadata::types`].
+//!
+//! Only versions 14 and 15 of the metadata format are supported by this module.
+//!
+//! > **Note**: The output of the `Metadata_metadata` runtime function is prefixed with its
+//! >           length. This length prefix must be removed, for example with
+//! >           [`crate::json_rpc::methods::remove_metadata_length_prefix`], before calling
+//! >           [`decode`].
+
+use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
+use core::cmp;
+
+mod tests;
+
+/// Decodes a SCALE-encoded runtime metadata, without its length prefix.
+pub fn decode(scale_encoded: &[u8]) -> Result<Metadata, DecodeError> {
+    let (version, after_header) = match scale_encoded {
+        [b'm', b'e', b't', b'a', version, rest @ ..] => (*version, rest),
+        _ => return Err(DecodeError::InvalidMagicNumber),
+    };
+
+    if version != 14 && version != 15 {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    match nom::combinator::all_consuming(nom::combinator::complete(move |bytes| {
+        metadata(version, bytes)
+    }))(after_header)
+    {
+        Ok((_, out)) => Ok(out),
+        Err(nom::Err::Error(_) | nom::Err::Failure(_)) => Err(DecodeError::InvalidFormat),
+        Err(nom::Err::Incomplete(_)) => unreachable!(),
+    }
+}
+
+/// Error potentially returned by [`decode`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum DecodeError {
+    /// The metadata doesn't start with the expected magic number.
+    #[display(fmt = "Invalid magic number")]
+    InvalidMagicNumber,
+    /// The version of the metadata format isn't supported.
+    #[display(fmt = "Unsupported metadata version: {_0}")]
+    UnsupportedVersion(u8),
+    /// Failed to decode the metadata.
+    #[display(fmt = "Invalid metadata format")]
+    InvalidFormat,
+}
+
+/// Decoded runtime metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// Version of the metadata format. Either 14 or 15.
+    pub version: u8,
+
+    /// Registry of all the types referred to by the rest of the metadata.
+    ///
+    /// Types are referred to using their [`Type::id`], which in practice is always equal to
+    /// their index within this list.
+    pub types: Vec<Type>,
+
+    /// List of pallets of the runtime.
+    pub pallets: Vec<Pallet>,
+
+    /// Information about the extrinsics of the chain.
+    pub extrinsic: Extrinsic,
+
+    /// Identifier of the type of the runtime itself.
+    pub runtime_type: u32,
+
+    /// List of runtime APIs of the runtime. Always empty if [`Metadata::version`] is inferior
+    /// to 15.
+    pub runtime_apis: Vec<RuntimeApi>,
+
+    /// Identifiers of the types of the enums that regroup the calls, events and errors of all
+    /// the pallets. Always `None` if [`Metadata::version`] is inferior to 15.
+    pub outer_enums: Option<OuterEnums>,
+
+    /// List of custom values of the metadata, as names, identifier of their type, and
+    /// SCALE-encoded value. Always empty if [`Metadata::version`] is inferior to 15.
+    pub custom: Vec<(String, u32, Vec<u8>)>,
+}
+
+impl Metadata {
+    /// Returns the type with the given identifier, if any.
+    pub fn type_by_id(&self, id: u32) -> Option<&Type> {
+        // Types are in practice always ordered by identifier.
+        match self.types.get(usize::try_from(id).ok()?) {
+            Some(ty) if ty.id == id => Some(ty),
+            _ => self.types.iter().find(|ty| ty.id == id),
+        }
+    }
+
+    /// Returns the pallet with the given name, if any.
+    pub fn pallet_by_name(&self, name: &str) -> Option<&Pallet> {
+        self.pallets.iter().find(|pallet| pallet.name == name)
+    }
+}
+
+/// Entry of the type registry. See [`Metadata::types`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Type {
+    /// Identifier of the type.
+    pub id: u32,
+    /// Path of the type, as a list of segments. For example
+    /// `["sp_core", "crypto", "AccountId32"]`. Empty for primitive types and types without any
+    /// name such as tuples.
+    pub path: Vec<String>,
+    /// Generic parameters of the type, as names and optional identifier of the type.
+    pub params: Vec<(String, Option<u32>)>,
+    /// Definition of the type.
+    pub definition: TypeDefinition,
+    /// Documentation of the type.
+    pub docs: Vec<String>,
+}
+
+/// See [`Type::definition`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDefinition {
+    /// Structure.
+    Composite(Vec<Field>),
+    /// Enumeration.
+    Variant(Vec<Variant>),
+    /// List of elements whose length is known only at runtime.
+    Sequence(u32),
+    /// List of elements whose length is known at compile-time.
+    Array {
+        /// Number of elements.
+        len: u32,
+        /// Identifier of the type of the elements.
+        ty: u32,
+    },
+    /// Tuple, as a list of type identifiers.
+    Tuple(Vec<u32>),
+    /// Primitive type.
+    Primitive(Primitive),
+    /// SCALE-compact-encoded number of the given type.
+    Compact(u32),
+    /// Sequence of bits.
+    BitSequence {
+        /// Identifier of the type of the bit store.
+        store: u32,
+        /// Identifier of the type of the bit order.
+        order: u32,
+    },
+}
+
+/// See [`TypeDefinition::Primitive`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    Bool,
+    Char,
+    Str,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    U256,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    I256,
+}
+
+/// Field of a structure or of an enum variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Name of the field. `None` for fields of tuple structs and tuple variants.
+    pub name: Option<String>,
+    /// Identifier of the type of the field.
+    pub ty: u32,
+    /// Name of the type of the field as written in the source code of the runtime.
+    pub type_name: Option<String>,
+    /// Documentation of the field.
+    pub docs: Vec<String>,
+}
+
+/// Variant of an enum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    /// Name of the variant.
+    pub name: String,
+    /// Fields of the variant.
+    pub fields: Vec<Field>,
+    /// Index of the variant, used when SCALE-encoding a value of the enum.
+    pub index: u8,
+    /// Documentation of the variant.
+    pub docs: Vec<String>,
+}
+
+/// See [`Metadata::pallets`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pallet {
+    /// Name of the pallet.
+    pub name: String,
+    /// Storage items of the pallet, if it has any storage.
+    pub storage: Option<PalletStorage>,
+    /// Identifier of the type of the calls of the pallet, if any.
+    pub calls: Option<u32>,
+    /// Identifier of the type of the events of the pallet, if any.
+    pub event: Option<u32>,
+    /// List of constants of the pallet.
+    pub constants: Vec<Constant>,
+    /// Identifier of the type of the errors of the pallet, if any.
+    pub error: Option<u32>,
+    /// Index of the pallet, used when SCALE-encoding a call, event or error.
+    pub index: u8,
+    /// Documentation of the pallet. Always empty if [`Metadata::version`] is inferior to 15.
+    pub docs: Vec<String>,
+}
+
+/// See [`Pallet::storage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalletStorage {
+    /// Prefix that the keys of all the storage items of the pallet start with, before hashing.
+    pub prefix: String,
+    /// List of storage items.
+    pub entries: Vec<StorageEntry>,
+}
+
+/// See [`PalletStorage::entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageEntry {
+    /// Name of the storage item.
+    pub name: String,
+    /// If `true`, the runtime considers that the storage item has a default value, found in
+    /// [`StorageEntry::default`], when it is absent from the storage. If `false`, the runtime
+    /// considers that it is `None`.
+    pub has_default: bool,
+    /// Type of the storage item.
+    pub ty: StorageEntryType,
+    /// SCALE-encoded default value of the storage item.
+    pub default: Vec<u8>,
+    /// Documentation of the storage item.
+    pub docs: Vec<String>,
+}
+
+/// See [`StorageEntry::ty`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageEntryType {
+    /// Single value whose type has the given identifier.
+    Plain(u32),
+    /// Map of keys to values.
+    Map {
+        /// Hashers used to build the storage key, one per element of the key.
+        hashers: Vec<StorageHasher>,
+        /// Identifier of the type of the key.
+        key: u32,
+        /// Identifier of the type of the values.
+        value: u32,
+    },
+}
+
+/// Algorithm used to turn the key of a storage map into a storage key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StorageHasher {
+    Blake2_128,
+    Blake2_256,
+    Blake2_128Concat,
+    Twox128,
+    Twox256,
+    Twox64Concat,
+    Identity,
+}
+
+/// See [`Pallet::constants`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constant {
+    /// Name of the constant.
+    pub name: String,
+    /// Identifier of the type of the constant.
+    pub ty: u32,
+    /// SCALE-encoded value of the constant.
+    pub value: Vec<u8>,
+    /// Documentation of the constant.
+    pub docs: Vec<String>,
+}
+
+/// See [`Metadata::extrinsic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extrinsic {
+    /// Version of the extrinsics format.
+    pub version: u8,
+    /// Identifier of the type of the extrinsics. Always `Some` if [`Metadata::version`] is 14,
+    /// and always `None` if it is 15.
+    pub ty: Option<u32>,
+    /// Identifiers of the types of the address, call, signature and extra fields of the
+    /// extrinsics. Always `None` if [`Metadata::version`] is inferior to 15.
+    pub address_call_signature_extra_types: Option<(u32, u32, u32, u32)>,
+    /// List of signed extensions of the extrinsics.
+    pub signed_extensions: Vec<SignedExtension>,
+}
+
+/// See [`Extrinsic::signed_extensions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedExtension {
+    /// Name of the signed extension.
+    pub identifier: String,
+    /// Identifier of the type of the data included in the extrinsic.
+    pub ty: u32,
+    /// Identifier of the type of the data that isn't included in the extrinsic but is included
+    /// in the payload that is signed.
+    pub additional_signed: u32,
+}
+
+/// See [`Metadata::runtime_apis`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeApi {
+    /// Name of the runtime API, for example `Core`.
+    pub name: String,
+    /// List of functions of the runtime API.
+    pub methods: Vec<RuntimeApiMethod>,
+    /// Documentation of the runtime API.
+    pub docs: Vec<String>,
+}
+
+/// See [`RuntimeApi::methods`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeApiMethod {
+    /// Name of the function, for example `version`. The name of the runtime entry point is
+    /// the name of the runtime API and the name of the function separated with `_`.
+    pub name: String,
+    /// List of parameters of the function, as names and identifiers of their type.
+    pub inputs: Vec<(String, u32)>,
+    /// Identifier of the type of the value returned by the function.
+    pub output: u32,
+    /// Documentation of the function.
+    pub docs: Vec<String>,
+}
+
+/// See [`Metadata::outer_enums`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OuterEnums {
+    /// Identifier of the enum that regroups the calls of all the pallets.
+    pub call_enum_ty: u32,
+    /// Identifier of the enum that regroups the events of all the pallets.
+    pub event_enum_ty: u32,
+    /// Identifier of the enum that regroups the errors of all the pallets.
+    pub error_enum_ty: u32,
+}
+
+fn metadata(version: u8, bytes: &[u8]) -> nom::IResult<&[u8], Metadata> {
+    let (bytes, types) = vec_decode(ty)(bytes)?;
+    let (bytes, pallets) = vec_decode(move |b| pallet(version, b))(bytes)?;
+    let (bytes, extrinsic) = extrinsic(version, bytes)?;
+    let (bytes, runtime_type) = type_id(bytes)?;
+
+    let (bytes, runtime_apis, outer_enums, custom) = if version >= 15 {
+        let (bytes, runtime_apis) = vec_decode(runtime_api)(bytes)?;
+        let (bytes, outer_enums) = nom::combinator::map(
+            nom::sequence::tuple((type_id, type_id, type_id)),
+            |(call_enum_ty, event_enum_ty, error_enum_ty)| OuterEnums {
+                call_enum_ty,
+                event_enum_ty,
+                error_enum_ty,
+            },
+        )(bytes)?;
+        let (bytes, custom) =
+            vec_decode(nom::sequence::tuple((string, type_id, bytes_vec)))(bytes)?;
+        (bytes, runtime_apis, Some(outer_enums), custom)
+    } else {
+        (bytes, Vec::new(), None, Vec::new())
+    };
+
+    Ok((
+        bytes,
+        Metadata {
+            version,
+            types,
+            pallets,
+            extrinsic,
+            runtime_type,
+            runtime_apis,
+            outer_enums,
+            custom,
+        },
+    ))
+}
+
+fn ty(bytes: &[u8]) -> nom::IResult<&[u8], Type> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            type_id,
+            vec_decode(string),
+            vec_decode(nom::sequence::tuple((
+                string,
+                crate::util::nom_option_decode(type_id),
+            ))),
+            type_definition,
+            vec_decode(string),
+        )),
+        |(id, path, params, definition, docs)| Type {
+            id,
+            path,
+            params,
+            definition,
+            docs,
+        },
+    )(bytes)
+}
+
+fn type_definition(bytes: &[u8]) -> nom::IResult<&[u8], TypeDefinition> {
+    let (bytes, variant) = nom::number::complete::u8(bytes)?;
+    match variant {
+        0 => nom::combinator::map(vec_decode(field), TypeDefinition::Composite)(bytes),
+        1 => nom::combinator::map(vec_decode(variant_def), TypeDefinition::Variant)(bytes),
+        2 => nom::combinator::map(type_id, TypeDefinition::Sequence)(bytes),
+        3 => nom::combinator::map(
+            nom::sequence::tuple((nom::number::complete::le_u32, type_id)),
+            |(len, ty)| TypeDefinition::Array { len, ty },
+        )(bytes),
+        4 => nom::combinator::map(vec_decode(type_id), TypeDefinition::Tuple)(bytes),
+        5 => nom::combinator::map_opt(nom::number::complete::u8, |primitive| {
+            Some(TypeDefinition::Primitive(match primitive {
+                0 => Primitive::Bool,
+                1 => Primitive::Char,
+                2 => Primitive::Str,
+                3 => Primitive::U8,
+                4 => Primitive::U16,
+                5 => Primitive::U32,
+                6 => Primitive::U64,
+                7 => Primitive::U128,
+                8 => Primitive::U256,
+                9 => Primitive::I8,
+                10 => Primitive::I16,
+                11 => Primitive::I32,
+                12 => Primitive::I64,
+                13 => Primitive::I128,
+                14 => Primitive::I256,
+                _ => return None,
+            }))
+        })(bytes),
+        6 => nom::combinator::map(type_id, TypeDefinition::Compact)(bytes),
+        7 => nom::combinator::map(
+            nom::sequence::tuple((type_id, type_id)),
+            |(store, order)| TypeDefinition::BitSequence { store, order },
+        )(bytes),
+        _ => Err(nom::Err::Error(nom::error::make_error(
+            bytes,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+fn field(bytes: &[u8]) -> nom::IResult<&[u8], Field> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            crate::util::nom_option_decode(string),
+            type_id,
+            crate::util::nom_option_decode(string),
+            vec_decode(string),
+        )),
+        |(name, ty, type_name, docs)| Field {
+            name,
+            ty,
+            type_name,
+            docs,
+        },
+    )(bytes)
+}
+
+fn variant_def(bytes: &[u8]) -> nom::IResult<&[u8], Variant> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            string,
+            vec_decode(field),
+            nom::number::complete::u8,
+            vec_decode(string),
+        )),
+        |(name, fields, index, docs)| Variant {
+            name,
+            fields,
+            index,
+            docs,
+        },
+    )(bytes)
+}
+
+fn pallet(version: u8, bytes: &[u8]) -> nom::IResult<&[u8], Pallet> {
+    let (bytes, (name, storage, calls, event, constants, error, index)) = nom::sequence::tuple((
+        string,
+        crate::util::nom_option_decode(pallet_storage),
+        crate::util::nom_option_decode(type_id),
+        crate::util::nom_option_decode(type_id),
+        vec_decode(constant),
+        crate::util::nom_option_decode(type_id),
+        nom::number::complete::u8,
+    ))(bytes)?;
+
+    let (bytes, docs) = if version >= 15 {
+        vec_decode(string)(bytes)?
+    } else {
+        (bytes, Vec::new())
+    };
+
+    Ok((
+        bytes,
+        Pallet {
+            name,
+            storage,
+            calls,
+            event,
+            constants,
+            error,
+            index,
+            docs,
+        },
+    ))
+}
+
+fn pallet_storage(bytes: &[u8]) -> nom::IResult<&[u8], PalletStorage> {
+    nom::combinator::map(
+        nom::sequence::tuple((string, vec_decode(storage_entry))),
+        |(prefix, entries)| PalletStorage { prefix, entries },
+    )(bytes)
+}
+
+fn storage_entry(bytes: &[u8]) -> nom::IResult<&[u8], StorageEntry> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            string,
+            nom::branch::alt((
+                nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| false),
+                nom::combinator::map(nom::bytes::complete::tag(&[1]), |_| true),
+            )),
+            storage_entry_type,
+            bytes_vec,
+            vec_decode(string),
+        )),
+        |(name, has_default, ty, default, docs)| StorageEntry {
+            name,
+            has_default,
+            ty,
+            default,
+            docs,
+        },
+    )(bytes)
+}
+
+fn storage_entry_type(bytes: &[u8]) -> nom::IResult<&[u8], StorageEntryType> {
+    nom::branch::alt((
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[0]), type_id),
+            StorageEntryType::Plain,
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[1]),
+                nom::sequence::tuple((vec_decode(storage_hasher), type_id, type_id)),
+            ),
+            |(hashers, key, value)| StorageEntryType::Map {
+                hashers,
+                key,
+                value,
+            },
+        ),
+    ))(bytes)
+}
+
+fn storage_hasher(bytes: &[u8]) -> nom::IResult<&[u8], StorageHasher> {
+    nom::combinator::map_opt(nom::number::complete::u8, |hasher| match hasher {
+        0 => Some(StorageHasher::Blake2_128),
+        1 => Some(StorageHasher::Blake2_256),
+        2 => Some(StorageHasher::Blake2_128Concat),
+        3 => Some(StorageHasher::Twox128),
+        4 => Some(StorageHasher::Twox256),
+        5 => Some(StorageHasher::Twox64Concat),
+        6 => Some(StorageHasher::Identity),
+        _ => None,
+    })(bytes)
+}
+
+fn constant(bytes: &[u8]) -> nom::IResult<&[u8], Constant> {
+    nom::combinator::map(
+        nom::sequence::tuple((string, type_id, bytes_vec, vec_decode(string))),
+        |(name, ty, value, docs)| Constant {
+            name,
+            ty,
+            value,
+            docs,
+        },
+    )(bytes)
+}
+
+fn extrinsic(version: u8, bytes: &[u8]) -> nom::IResult<&[u8], Extrinsic> {
+    if version >= 15 {
+        nom::combinator::map(
+            nom::sequence::tuple((
+                nom::number::complete::u8,
+                nom::sequence::tuple((type_id, type_id, type_id, type_id)),
+                vec_decode(signed_extension),
+            )),
+            |(version, types, signed_extensions)| Extrinsic {
+                version,
+                ty: None,
+                address_call_signature_extra_types: Some(types),
+                signed_extensions,
+            },
+        )(bytes)
+    } else {
+        nom::combinator::map(
+            nom::sequence::tuple((
+                type_id,
+                nom::number::complete::u8,
+                vec_decode(signed_extension),
+            )),
+            |(ty, version, signed_extensions)| Extrinsic {
+                version,
+                ty: Some(ty),
+                address_call_signature_extra_types: None,
+                signed_extensions,
+            },
+        )(bytes)
+    }
+}
+
+fn signed_extension(bytes: &[u8]) -> nom::IResult<&[u8], SignedExtension> {
+    nom::combinator::map(
+        nom::sequence::tuple((string, type_id, type_id)),
+        |(identifier, ty, additional_signed)| SignedExtension {
+            identifier,
+            ty,
+            additional_signed,
+        },
+    )(bytes)
+}
+
+fn runtime_api(bytes: &[u8]) -> nom::IResult<&[u8], RuntimeApi> {
+    nom::combinator::map(
+        nom::sequence::tuple((string, vec_decode(runtime_api_method), vec_decode(string))),
+        |(name, methods, docs)| RuntimeApi {
+            name,
+            methods,
+            docs,
+        },
+    )(bytes)
+}
+
+fn runtime_api_method(bytes: &[u8]) -> nom::IResult<&[u8], RuntimeApiMethod> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            string,
+            vec_decode(nom::sequence::tuple((string, type_id))),
+            type_id,
+            vec_decode(string),
+        )),
+        |(name, inputs, output, docs)| RuntimeApiMethod {
+            name,
+            inputs,
+            output,
+            docs,
+        },
+    )(bytes)
+}
+
+/// Decodes a SCALE-compact-encoded type identifier.
+fn type_id(bytes: &[u8]) -> nom::IResult<&[u8], u32> {
+    nom::combinator::map_opt(crate::util::nom_scale_compact_usize, |id| {
+        u32::try_from(id).ok()
+    })(bytes)
+}
+
+fn string(bytes: &[u8]) -> nom::IResult<&[u8], String> {
+    nom::combinator::map(crate::util::nom_string_decode, |s: &str| s.to_owned())(bytes)
+}
+
+fn bytes_vec(bytes: &[u8]) -> nom::IResult<&[u8], Vec<u8>> {
+    nom::combinator::map(crate::util::nom_bytes_decode, |b: &[u8]| b.to_vec())(bytes)
+}
+
+/// Returns a parser that decodes a SCALE-encoded vector whose elements are decoded with
+/// `inner_decode`.
+fn vec_decode<'a, O>(
+    mut inner_decode: impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], O>,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], Vec<O>> {
+    move |bytes| {
+        let (mut bytes, num_elems) = crate::util::nom_scale_compact_usize(bytes)?;
+        // The number of elements can't be trusted, and each element is at least one byte.
+        let mut out = Vec::with_capacity(cmp::min(num_elems, bytes.len()));
+        for _ in 0..num_elems {
+            let (rest, elem) = inner_decode(bytes)?;
+            bytes = rest;
+            out.push(elem);
+        }
+        Ok((bytes, out))
+    }
+}
@@ -0,0 +1,149 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::*;
+
+/// Metadata v14 containing a single `u32` type and a single `System` pallet with a single
+/// `Number` storage item.
+fn minimal_v14() -> Vec<u8> {
+    let mut out = b"meta".to_vec();
+    out.push(14);
+    // Types: `[{ id: 0, path: [], params: [], def: Primitive(U32), docs: [] }]`.
+    out.extend_from_slice(&[0x04, 0x00, 0x00, 0x00, 0x05, 0x05, 0x00]);
+    // Pallets.
+    out.push(0x04);
+    out.push(0x18);
+    out.extend_from_slice(b"System");
+    // Storage.
+    out.extend_from_slice(&[0x01, 0x18]);
+    out.extend_from_slice(b"System");
+    out.extend_from_slice(&[0x04, 0x18]);
+    out.extend_from_slice(b"Number");
+    out.extend_from_slice(&[0x01, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    // Calls, event, constants, error, index.
+    out.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00]);
+    // Extrinsic.
+    out.extend_from_slice(&[0x00, 0x04, 0x00]);
+    // Runtime type.
+    out.push(0x00);
+    out
+}
+
+#[test]
+fn decode_v14() {
+    let metadata = decode(&minimal_v14()).unwrap();
+    assert_eq!(metadata.version, 14);
+    assert_eq!(
+        metadata.type_by_id(0).unwrap().definition,
+        TypeDefinition::Primitive(Primitive::U32)
+    );
+
+    let system = metadata.pallet_by_name("System").unwrap();
+    assert_eq!(system.index, 0);
+    let storage = system.storage.as_ref().unwrap();
+    assert_eq!(storage.prefix, "System");
+    assert_eq!(storage.entries.len(), 1);
+    assert_eq!(storage.entries[0].name, "Number");
+    assert!(storage.entries[0].has_default);
+    assert_eq!(storage.entries[0].ty, StorageEntryType::Plain(0));
+    assert_eq!(storage.entries[0].default, vec![0, 0, 0, 0]);
+
+    assert_eq!(metadata.extrinsic.version, 4);
+    assert_eq!(metadata.extrinsic.ty, Some(0));
+    assert!(metadata.runtime_apis.is_empty());
+    assert!(metadata.outer_enums.is_none());
+}
+
+#[test]
+fn decode_v15() {
+    let mut metadata = b"meta".to_vec();
+    metadata.push(15);
+    // Types: `[{ id: 0, path: [], params: [], def: Primitive(Bool), docs: [] }]`.
+    metadata.extend_from_slice(&[0x04, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00]);
+    // Pallets: `[{ name: "A", storage: None, calls: Some(0), event: None, constants: [],
+    // error: None, index: 3, docs: [] }]`.
+    metadata.extend_from_slice(&[
+        0x04, 0x04, b'A', 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00,
+    ]);
+    // Extrinsic.
+    metadata.extend_from_slice(&[0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    // Runtime type.
+    metadata.push(0x00);
+    // Runtime APIs: `[{ name: "Core", methods: [{ name: "version", inputs: [], output: 0,
+    // docs: [] }], docs: [] }]`.
+    metadata.extend_from_slice(&[0x04, 0x10]);
+    metadata.extend_from_slice(b"Core");
+    metadata.extend_from_slice(&[0x04, 0x1c]);
+    metadata.extend_from_slice(b"version");
+    metadata.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    // Outer enums, custom.
+    metadata.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+
+    let metadata = decode(&metadata).unwrap();
+    assert_eq!(metadata.version, 15);
+    assert_eq!(metadata.pallets[0].name, "A");
+    assert_eq!(metadata.pallets[0].calls, Some(0));
+    assert_eq!(metadata.pallets[0].index, 3);
+    assert_eq!(metadata.extrinsic.ty, None);
+    assert_eq!(
+        metadata.extrinsic.address_call_signature_extra_types,
+        Some((0, 0, 0, 0))
+    );
+    assert_eq!(metadata.runtime_apis[0].name, "Core");
+    assert_eq!(metadata.runtime_apis[0].methods[0].name, "version");
+    assert!(metadata.outer_enums.is_some());
+}
+
+#[test]
+fn invalid_magic_number() {
+    let mut metadata = minimal_v14();
+    metadata[0] = b'x';
+    assert!(matches!(
+        decode(&metadata),
+        Err(DecodeError::InvalidMagicNumber)
+    ));
+}
+
+#[test]
+fn unsupported_version() {
+    let mut metadata = minimal_v14();
+    metadata[4] = 13;
+    assert!(matches!(
+        decode(&metadata),
+        Err(DecodeError::UnsupportedVersion(13))
+    ));
+}
+
+#[test]
+fn trailing_data() {
+    let mut metadata = minimal_v14();
+    metadata.push(0);
+    assert!(matches!(decode(&metadata), Err(DecodeError::InvalidFormat)));
+}
+
+#[test]
+fn truncated() {
+    let metadata = minimal_v14();
+    for len in 5..metadata.len() {
+        assert!(matches!(
+            decode(&metadata[..len]),
+            Err(DecodeError::InvalidFormat)
+        ));
+    }
+}
@@ -27,6 +27,7 @@
 // Implementation note: each protocol goes into a different sub-module whose content is
 // re-exported here.
 
+mod beefy;
 mod block_announces;
 mod block_request;
 mod grandpa;
@@ -36,6 +37,7 @@ mod kademlia;
 mod state_request;
 mod storage_call_proof;
 
+pub use self::beefy::*;
 pub use self::block_announces::*;
 pub use self::block_request::*;
 pub use self::grandpa::*;
@@ -0,0 +1,124 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::finality::beefy;
+
+use nom::Finish as _;
+
+pub use crate::finality::beefy::{CommitmentRef, SignedCommitmentRef};
+
+/// Decoded notification sent on the BEEFY gossiping substream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeefyNotificationRef<'a> {
+    /// Vote of a single validator for a commitment.
+    Vote(BeefyVoteMessageRef<'a>),
+    /// Commitment signed by enough validators. Proves the finality of a block.
+    FinalityProof(SignedCommitmentRef<'a>),
+}
+
+/// Vote of a single validator for a commitment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeefyVoteMessageRef<'a> {
+    /// Commitment that is voted for.
+    pub commitment: CommitmentRef<'a>,
+    /// Compressed ECDSA public key of the validator.
+    pub validator: &'a [u8; 33],
+    /// ECDSA signature of the commitment made by [`BeefyVoteMessageRef::validator`].
+    pub signature: &'a [u8; 65],
+}
+
+/// Attempt to decode the given SCALE-encoded BEEFY notification.
+pub fn decode_beefy_notification(
+    scale_encoded: &[u8],
+    block_number_bytes: usize,
+) -> Result<BeefyNotificationRef, DecodeBeefyNotificationError> {
+    match nom::combinator::all_consuming(nom::combinator::complete(beefy_notification(
+        block_number_bytes,
+    )))(scale_encoded)
+    .finish()
+    {
+        Ok((_, notif)) => Ok(notif),
+        Err(err) => Err(DecodeBeefyNotificationError(err.code)),
+    }
+}
+
+/// Error potentially returned by [`decode_beefy_notification`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode a BEEFY notification")]
+pub struct DecodeBeefyNotificationError(nom::error::ErrorKind);
+
+// Nom combinators below.
+
+fn beefy_notification<'a>(
+    block_number_bytes: usize,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&[u8], BeefyNotificationRef> {
+    nom::error::context(
+        "beefy_notification",
+        nom::branch::alt((
+            nom::combinator::map(
+                nom::sequence::preceded(
+                    nom::bytes::complete::tag(&[0]),
+                    nom::sequence::tuple((
+                        beefy::commitment(block_number_bytes),
+                        nom::bytes::complete::take(33u32),
+                        nom::bytes::complete::take(65u32),
+                    )),
+                ),
+                |(commitment, validator, signature)| {
+                    BeefyNotificationRef::Vote(BeefyVoteMessageRef {
+                        commitment,
+                        validator: <&[u8; 33]>::try_from(validator).unwrap(),
+                        signature: <&[u8; 65]>::try_from(signature).unwrap(),
+                    })
+                },
+            ),
+            // Finality proofs are versioned. Only the version 1, using the index `1`, exists at
+            // the moment.
+            nom::combinator::map(
+                nom::sequence::preceded(
+                    nom::bytes::complete::tag(&[1, 1]),
+                    beefy::signed_commitment(block_number_bytes),
+                ),
+                BeefyNotificationRef::FinalityProof,
+            ),
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn decode_vote() {
+        let mut encoded = vec![0, 1 << 2, b'm', b'h', 32 << 2];
+        encoded.extend_from_slice(&[0xab; 32]);
+        encoded.extend_from_slice(&1234u32.to_le_bytes());
+        encoded.extend_from_slice(&5u64.to_le_bytes());
+        encoded.extend_from_slice(&[2; 33]);
+        encoded.extend_from_slice(&[3; 65]);
+
+        match super::decode_beefy_notification(&encoded, 4).unwrap() {
+            super::BeefyNotificationRef::Vote(vote) => {
+                assert_eq!(vote.commitment.block_number, 1234);
+                assert_eq!(vote.commitment.validator_set_id, 5);
+                assert_eq!(vote.commitment.mmr_root(), Some(&[0xab; 32]));
+                assert_eq!(vote.validator, &[2; 33]);
+                assert_eq!(vote.signature, &[3; 65]);
+            }
+            _ => panic!(),
+        }
+    }
+}
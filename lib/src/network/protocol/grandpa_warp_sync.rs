@@ -38,6 +38,7 @@
 use crate::{finality, header};
 
 use alloc::vec::Vec;
+use core::iter;
 
 // TODO: all the constraints explained here should be checked when decoding the message
 
@@ -69,6 +70,44 @@ pub struct GrandpaWarpSyncResponseFragment<'a> {
     pub scale_encoded_justification: &'a [u8],
 }
 
+/// Error potentially returned by [`decode_grandpa_warp_sync_request`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Request isn't a block hash")]
+pub struct DecodeGrandpaWarpSyncRequestError;
+
+/// Decodes a GrandPa warp sync request.
+///
+/// On success, returns the hash of the block the requester wants to start warp syncing from.
+pub fn decode_grandpa_warp_sync_request(
+    encoded: &[u8],
+) -> Result<[u8; 32], DecodeGrandpaWarpSyncRequestError> {
+    <[u8; 32]>::try_from(encoded).map_err(|_| DecodeGrandpaWarpSyncRequestError)
+}
+
+/// Builds the bytes corresponding to a GrandPa warp sync response.
+///
+/// The headers and justifications of the fragments aren't checked for validity.
+pub fn build_grandpa_warp_sync_response<'a>(
+    response: GrandpaWarpSyncResponse<'a>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    let num_fragments = response.fragments.len();
+
+    iter::once(either::Left(crate::util::encode_scale_compact_usize(
+        num_fragments,
+    )))
+    .chain(response.fragments.into_iter().flat_map(|fragment| {
+        [
+            either::Right(fragment.scale_encoded_header),
+            either::Right(fragment.scale_encoded_justification),
+        ]
+    }))
+    .chain(iter::once(either::Right(if response.is_finished {
+        &[1][..]
+    } else {
+        &[0][..]
+    })))
+}
+
 /// Error potentially returned by [`decode_grandpa_warp_sync_response`].
 #[derive(Debug, derive_more::Display)]
 #[display(fmt = "Failed to decode response")]
@@ -129,3 +168,34 @@ fn decode_fragment<'a>(
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn decode_request() {
+        assert_eq!(
+            super::decode_grandpa_warp_sync_request(&[5; 32]).unwrap(),
+            [5; 32]
+        );
+        assert!(super::decode_grandpa_warp_sync_request(&[5; 31]).is_err());
+        assert!(super::decode_grandpa_warp_sync_request(&[5; 33]).is_err());
+    }
+
+    #[test]
+    fn empty_response_encode_decode() {
+        let encoded = super::build_grandpa_warp_sync_response(super::GrandpaWarpSyncResponse {
+            fragments: Vec::new(),
+            is_finished: true,
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        assert_eq!(encoded, [0, 1]);
+
+        let decoded = super::decode_grandpa_warp_sync_response(&encoded, 4).unwrap();
+        assert!(decoded.fragments.is_empty());
+        assert!(decoded.is_finished);
+    }
+}
@@ -39,6 +39,57 @@ pub fn build_find_node_request(peer_id: &[u8]) -> Vec<u8> {
     out
 }
 
+/// Decodes a request built using [`build_find_node_request`].
+///
+/// On success, returns the key whose closest peers are requested.
+pub fn decode_find_node_request(request_bytes: &[u8]) -> Result<&[u8], DecodeFindNodeRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[required] request_ty = 1 => protobuf::enum_tag_decode,
+            #[required] key = 2 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, out)) if out.request_ty == 4 => Ok(out.key),
+        Ok((_, _)) => Err(DecodeFindNodeRequestError::BadRequestTy),
+        Err(_) => Err(DecodeFindNodeRequestError::ProtobufDecode(
+            ProtobufDecodeError,
+        )),
+    }
+}
+
+/// Builds a wire message to send back as a response to a request decoded using
+/// [`decode_find_node_request`].
+///
+/// Must be passed the list of peers closest to the requested key, and their addresses.
+pub fn build_find_node_response<'a>(
+    closer_peers: impl Iterator<
+        Item = (
+            &'a peer_id::PeerId,
+            impl Iterator<Item = &'a multiaddr::Multiaddr>,
+        ),
+    >,
+) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(1024);
+    for slice in protobuf::enum_tag_encode(1, 4) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for (peer_id, addrs) in closer_peers {
+        let peer =
+            protobuf::bytes_tag_encode(1, peer_id.as_bytes())
+                .map(either::Left)
+                .chain(addrs.flat_map(|addr| {
+                    protobuf::bytes_tag_encode(2, addr.to_vec()).map(either::Right)
+                }));
+        for slice in protobuf::message_tag_encode(8, peer) {
+            out.extend_from_slice(slice.as_ref());
+        }
+    }
+    out
+}
+
 /// Decodes a response to a request built using [`build_find_node_request`].
 // TODO: return a borrow of the response bytes ; we're limited by protobuf library
 pub fn decode_find_node_response(
@@ -82,6 +133,16 @@ pub fn decode_find_node_response(
     Ok(result)
 }
 
+/// Error potentially returned by [`decode_find_node_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeFindNodeRequestError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the request: {_0}")]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Request isn't a find node request.
+    BadRequestTy,
+}
+
 /// Error potentially returned by [`decode_find_node_response`].
 #[derive(Debug, derive_more::Display)]
 pub enum DecodeFindNodeResponseError {
@@ -101,3 +162,32 @@ pub enum DecodeFindNodeResponseError {
 /// Error while decoding the Protobuf encoding.
 #[derive(Debug, derive_more::Display)]
 pub struct ProtobufDecodeError;
+
+#[cfg(test)]
+mod tests {
+    use crate::libp2p::{multiaddr, peer_id};
+
+    #[test]
+    fn find_node_request_encode_decode() {
+        let key = [1, 2, 3, 4, 5];
+        let request = super::build_find_node_request(&key);
+        assert_eq!(super::decode_find_node_request(&request).unwrap(), &key[..]);
+    }
+
+    #[test]
+    fn find_node_response_encode_decode() {
+        let peer_id = peer_id::PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+        let addrs = vec![
+            "/ip4/1.2.3.4/tcp/30333"
+                .parse::<multiaddr::Multiaddr>()
+                .unwrap(),
+            "/dns/example.com/tcp/443/wss"
+                .parse::<multiaddr::Multiaddr>()
+                .unwrap(),
+        ];
+
+        let response = super::build_find_node_response(core::iter::once((&peer_id, addrs.iter())));
+        let decoded = super::decode_find_node_response(&response).unwrap();
+        assert_eq!(decoded, vec![(peer_id, addrs)]);
+    }
+}
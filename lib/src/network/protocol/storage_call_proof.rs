@@ -26,6 +26,13 @@ pub struct StorageProofRequestConfig<TKeysIter> {
     pub block_hash: [u8; 32],
     /// List of storage keys to query.
     pub keys: TKeysIter,
+    /// If `Some`, the keys are queried in the child trie whose key is the value. The key of the
+    /// child trie doesn't include the `:child_storage:default:` prefix, which is added when
+    /// building the request.
+    ///
+    /// The proof returned by the peer then also contains the entries of the main trie that lead
+    /// to the root of the child trie.
+    pub child_trie: Option<Vec<u8>>,
 }
 
 // See https://github.com/paritytech/substrate/blob/c8653447fc8ef8d95a92fe164c96dffb37919e85/client/network/sync/src/schema/api.v1.proto
@@ -35,17 +42,46 @@ pub struct StorageProofRequestConfig<TKeysIter> {
 pub fn build_storage_proof_request<'a>(
     config: StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone + 'a> + 'a>,
 ) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
-    protobuf::message_tag_encode(
-        2,
-        protobuf::bytes_tag_encode(2, config.block_hash)
-            .map(either::Left)
-            .chain(
-                config
-                    .keys
-                    .flat_map(|key| protobuf::bytes_tag_encode(3, key))
-                    .map(either::Right),
-            ),
-    )
+    if let Some(child_trie) = config.child_trie {
+        either::Right(
+            protobuf::message_tag_encode(
+                4,
+                protobuf::bytes_tag_encode(2, config.block_hash)
+                    .map(either::Left)
+                    .map(either::Left)
+                    .chain(
+                        protobuf::bytes_tag_encode(
+                            3,
+                            [&b":child_storage:default:"[..], &child_trie[..]].concat(),
+                        )
+                        .map(either::Right)
+                        .map(either::Left),
+                    )
+                    .chain(
+                        config
+                            .keys
+                            .flat_map(|key| protobuf::bytes_tag_encode(6, key))
+                            .map(either::Right),
+                    ),
+            )
+            .map(either::Right),
+        )
+    } else {
+        either::Left(
+            protobuf::message_tag_encode(
+                2,
+                protobuf::bytes_tag_encode(2, config.block_hash)
+                    .map(either::Left)
+                    .chain(
+                        config
+                            .keys
+                            .flat_map(|key| protobuf::bytes_tag_encode(3, key))
+                            .map(either::Right),
+                    ),
+            )
+            .map(either::Left),
+        )
+    }
 }
 
 /// Description of a call proof request that can be sent to a peer.
@@ -141,3 +177,31 @@ pub enum StorageOrCallProof {
     StorageProof,
     CallProof,
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn child_trie_request_prefixed_once() {
+        let request = super::build_storage_proof_request(super::StorageProofRequestConfig {
+            block_hash: [0xaa; 32],
+            keys: core::iter::once(b"foo".to_vec()),
+            child_trie: Some(b"bar".to_vec()),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        // Field 3 of the `RemoteReadChildRequest` message: tag, length, then the prefixed key.
+        let mut expected = vec![0x1a, 26];
+        expected.extend_from_slice(b":child_storage:default:bar");
+        assert!(request.windows(expected.len()).any(|w| w == &expected[..]));
+        assert_eq!(
+            request
+                .windows(b":child_storage:default:".len())
+                .filter(|w| *w == b":child_storage:default:")
+                .count(),
+            1
+        );
+    }
+}
@@ -43,8 +43,8 @@ mod notifications;
 mod requests_responses;
 
 pub use notifications::{
-    EncodedBlockAnnounce, EncodedBlockAnnounceHandshake, EncodedGrandpaCommitMessage, GrandpaState,
-    NotificationsOutErr,
+    EncodedBeefyFinalityProof, EncodedBlockAnnounce, EncodedBlockAnnounceHandshake,
+    EncodedGrandpaCommitMessage, GrandpaState, NotificationsOutErr,
 };
 
 pub use requests_responses::{
@@ -97,6 +97,19 @@ pub struct Config<TNow> {
     /// >           maximum number of addresses per peer ensures that the total number of
     /// >           addresses is capped as well.
     pub max_addresses_per_peer: NonZeroUsize,
+
+    /// Maximum number of substreams that each remote can have simultaneously opened on a
+    /// connection.
+    ///
+    /// If `None`, a limit is derived from the number of chains and of protocols per chain.
+    pub max_inbound_substreams: Option<usize>,
+
+    /// Maximum size, in bytes, that the Yamux receive window of each substream can grow to.
+    ///
+    /// A larger window lets remotes send data faster on connections with a high latency, at the
+    /// cost of potentially buffering more data in memory. Values inferior to 256kiB are treated
+    /// as 256kiB.
+    pub yamux_max_receive_window: u64,
 }
 
 /// Configuration for a specific overlay network.
@@ -116,9 +129,27 @@ pub struct ChainConfig {
     /// If `Some`, the chain uses the GrandPa networking protocol.
     pub grandpa_protocol_config: Option<GrandpaState>,
 
+    /// `true` if the chain uses the BEEFY networking protocol, in which case
+    /// [`Event::BeefyFinalityProof`] events are generated.
+    pub has_beefy_protocol: bool,
+
     /// `true` if incoming block requests are allowed.
     pub allow_inbound_block_requests: bool,
 
+    /// `true` if incoming Kademlia requests are allowed. If `true`, the local node answers the
+    /// requests of other peers looking for the nodes closest to a certain key, and other peers
+    /// are thus likely to insert the local node in their k-buckets.
+    ///
+    /// This should be `false` for nodes that aren't reachable from the outside, such as light
+    /// clients.
+    pub allow_inbound_kademlia_requests: bool,
+
+    /// `true` if incoming GrandPa warp sync requests are allowed.
+    ///
+    /// Answering these requests can consume a lot of bandwidth, as responses can weigh several
+    /// megabytes.
+    pub allow_inbound_grandpa_warp_sync_requests: bool,
+
     pub in_slots: u32,
 
     pub out_slots: u32,
@@ -158,6 +189,15 @@ pub struct ChainNetwork<TNow> {
     /// handshake was invalid, or had a different genesis hash, or similar problem.
     open_chains: hashbrown::HashSet<(PeerId, usize), SipHasherBuild>,
 
+    /// For each `(peer_id, chain_index)` in [`ChainNetwork::open_chains`] whose BEEFY substream
+    /// has been closed by the remote, when to try opening it again (`None` if it has already been
+    /// reopened) and the number of times it has been closed.
+    ///
+    /// BEEFY substreams are reopened with an exponential backoff, as the remote might close them
+    /// as soon as they are open.
+    beefy_substreams_backoff:
+        hashbrown::HashMap<(PeerId, usize), (Option<TNow>, u32), SipHasherBuild>,
+
     /// For each peer, the number of pending attempts.
     num_pending_per_peer: hashbrown::HashMap<PeerId, NonZeroUsize, SipHasherBuild>,
 
@@ -237,6 +277,8 @@ struct KBucketsPeer {
 enum InRequestTy {
     Identify { observed_addr: multiaddr::Multiaddr },
     Blocks,
+    KademliaFindNode { chain_index: usize, target: PeerId },
+    GrandpaWarpSync,
 }
 
 enum OutRequestTy {
@@ -252,7 +294,7 @@ enum OutRequestTy {
 }
 
 // Update this when a new notifications protocol is added.
-const NOTIFICATIONS_PROTOCOLS_PER_CHAIN: usize = 3;
+const NOTIFICATIONS_PROTOCOLS_PER_CHAIN: usize = 4;
 
 impl<TNow> ChainNetwork<TNow>
 where
@@ -291,23 +333,27 @@ where
             })
             .collect::<Vec<_>>();
 
-        // Maximum number that each remote is allowed to open.
+        // Maximum number that each remote is allowed to open, unless overridden by the
+        // configuration.
         // Note that this maximum doesn't have to be precise. There only needs to be *a* limit
         // that is not exaggerately large, and this limit shouldn't be too low as to cause
         // legitimate substreams to be refused.
         // According to the protocol, a remote can only open one substream of each protocol at
         // a time. However, we multiply this value by 2 in order to be generous. We also add 1
         // to account for the ping protocol.
-        let max_inbound_substreams = chains.len()
-            * (1 + requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN
-                + NOTIFICATIONS_PROTOCOLS_PER_CHAIN)
-            * 2;
+        let max_inbound_substreams = config.max_inbound_substreams.unwrap_or(
+            chains.len()
+                * (1 + requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN
+                    + NOTIFICATIONS_PROTOCOLS_PER_CHAIN)
+                * 2,
+        );
 
         ChainNetwork {
             inner: peers::Peers::new(peers::Config {
                 connections_capacity: config.connections_capacity,
                 peers_capacity: config.peers_capacity,
                 max_inbound_substreams,
+                yamux_max_receive_window: config.yamux_max_receive_window,
                 request_response_protocols,
                 noise_key: config.noise_key,
                 randomness_seed: randomness.sample(rand::distributions::Standard),
@@ -327,6 +373,10 @@ where
                 config.peers_capacity,
                 SipHasherBuild::new(randomness.gen()),
             ),
+            beefy_substreams_backoff: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                SipHasherBuild::new(randomness.gen()),
+            ),
             pending_ids: slab::Slab::with_capacity(config.peers_capacity),
             next_kademlia_operation_id: KademliaOperationId(0),
             pending_kademlia_errors: VecDeque::with_capacity(4),
@@ -927,6 +977,21 @@ where
             }
         };
 
+        // Mark as desired again the BEEFY substreams whose backoff delay has elapsed.
+        for ((peer_id, chain_index), (reopen_at, _)) in &mut self.beefy_substreams_backoff {
+            if reopen_at
+                .as_ref()
+                .map_or(false, |reopen_at| *reopen_at <= now)
+            {
+                *reopen_at = None;
+                self.inner.set_peer_notifications_out_desired(
+                    peer_id,
+                    chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 3,
+                    peers::DesiredState::DesiredReset,
+                );
+            }
+        }
+
         // Before returning the event, we check whether there is any desired outbound substream
         // to open.
         // Note: we can't use a `while let` due to borrow checker errors.
@@ -965,7 +1030,9 @@ where
                 })
             } else if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 1 {
                 Vec::new()
-            } else if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 2 {
+            } else if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 2
+                || notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 3
+            {
                 chain_config.role.scale_encoding().to_vec()
             } else {
                 unreachable!()
@@ -1059,6 +1126,26 @@ where
         self.inner.peers_list()
     }
 
+    /// Returns the addresses of the established connections with the given peer.
+    pub fn peer_connections_addresses(
+        &'_ self,
+        peer_id: &PeerId,
+    ) -> impl Iterator<Item = &'_ multiaddr::Multiaddr> + '_ {
+        self.inner
+            .established_peer_connections(peer_id)
+            .map(move |connection_id| &self.inner[connection_id])
+    }
+
+    /// Returns the list of outgoing connection attempts that have been started with
+    /// [`ChainNetwork::next_start_connect`] and whose outcome hasn't been reported yet.
+    pub fn pending_connections(
+        &'_ self,
+    ) -> impl Iterator<Item = (&'_ PeerId, &'_ multiaddr::Multiaddr)> + '_ {
+        self.pending_ids
+            .iter()
+            .map(|(_, (peer_id, multiaddr, _))| (peer_id, multiaddr))
+    }
+
     // TODO: docs and appropriate naming
     pub fn slots_to_assign(&'_ self, chain_index: usize) -> impl Iterator<Item = &'_ PeerId> + '_ {
         let chain = &self.chains[chain_index];
@@ -1242,6 +1329,19 @@ pub enum Event {
         message: EncodedGrandpaCommitMessage,
     },
 
+    /// Received a BEEFY finality proof from the network. Only generated if
+    /// [`ChainConfig::has_beefy_protocol`] is `true`.
+    ///
+    /// > **Note**: The finality proof hasn't been verified. Its format is valid, but it might
+    /// >           not have been signed by the validators of the chain.
+    BeefyFinalityProof {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the finality proof relates to.
+        chain_index: usize,
+        proof: EncodedBeefyFinalityProof,
+    },
+
     /// Error in the protocol in a connection, such as failure to decode a message. This event
     /// doesn't have any consequence on the health of the connection, and is purely for diagnostic
     /// purposes.
@@ -1276,6 +1376,36 @@ pub enum Event {
         /// Identifier of the request. Necessary to send back the answer.
         request_id: InRequestId,
     },
+    /// A remote has sent a Kademlia request for the nodes closest to a certain key.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_kademlia_requests`] is
+    /// `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_kademlia_find_node`].
+    KademliaFindNodeRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+    /// A remote has sent a GrandPa warp sync request.
+    ///
+    /// Can only happen for chains where
+    /// [`ChainConfig::allow_inbound_grandpa_warp_sync_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_grandpa_warp_sync`].
+    GrandpaWarpSyncRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Hash of the block the remote wants to start warp syncing from.
+        begin_hash: [u8; 32],
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
 
     RequestInCancel {
         request_id: InRequestId,
@@ -1315,9 +1445,21 @@ pub enum ProtocolError {
     /// Error while decoding a received Grandpa notification.
     #[display(fmt = "Error while decoding a received Grandpa notification: {_0}")]
     BadGrandpaNotification(protocol::DecodeGrandpaNotificationError),
+    /// Error while decoding a received BEEFY notification.
+    #[display(fmt = "Error while decoding a received BEEFY notification: {_0}")]
+    BadBeefyNotification(protocol::DecodeBeefyNotificationError),
     /// Received an invalid identify request.
     BadIdentifyRequest,
     /// Error while decoding a received blocks request.
     #[display(fmt = "Error while decoding a received blocks request: {_0}")]
     BadBlocksRequest(protocol::DecodeBlockRequestError),
+    /// Error while decoding a received Kademlia find node request.
+    #[display(fmt = "Error while decoding a received Kademlia find node request: {_0}")]
+    BadKademliaFindNodeRequest(protocol::DecodeFindNodeRequestError),
+    /// Key found in a received Kademlia find node request isn't a valid peer id.
+    #[display(fmt = "Invalid key in a received Kademlia find node request: {_0}")]
+    BadKademliaFindNodeKey(peer_id::FromBytesError),
+    /// Error while decoding a received GrandPa warp sync request.
+    #[display(fmt = "Error while decoding a received GrandPa warp sync request: {_0}")]
+    BadGrandpaWarpSyncRequest(protocol::DecodeGrandpaWarpSyncRequestError),
 }
@@ -24,7 +24,7 @@ use crate::util;
 
 use alloc::vec::Vec;
 use core::{
-    fmt,
+    cmp, fmt,
     ops::{Add, Sub},
     time::Duration,
 };
@@ -52,7 +52,13 @@ pub struct GrandpaState {
 }
 
 // Update this when a new notifications protocol is added.
-pub(super) const NOTIFICATIONS_PROTOCOLS_PER_CHAIN: usize = 3;
+pub(super) const NOTIFICATIONS_PROTOCOLS_PER_CHAIN: usize = 4;
+
+/// Returns the delay before trying to reopen a BEEFY substream that the remote has closed, given
+/// the number of times it has been closed before.
+fn beefy_reopen_delay(num_previous_closures: u32) -> Duration {
+    Duration::from_secs(5) * (1 << cmp::min(num_previous_closures, 6))
+}
 
 pub(super) fn protocols<'a>(
     chains: impl Iterator<Item = &'a ChainConfig>,
@@ -105,6 +111,20 @@ pub(super) fn protocols<'a>(
                     max_notification_size: 1024 * 1024,
                 })
             })
+            .chain({
+                // Similarly to GrandPa, BEEFY is always declared, but substreams are only opened
+                // and accepted if `has_beefy_protocol` is `true`.
+                iter::once(peers::NotificationProtocolConfig {
+                    protocol_name: match &chain.fork_id {
+                        Some(fork_id) => {
+                            format!("/{}/{}/beefy/2", hex::encode(chain.genesis_hash), fork_id)
+                        }
+                        None => format!("/{}/beefy/2", hex::encode(chain.genesis_hash)),
+                    },
+                    max_handshake_size: 4,
+                    max_notification_size: 1024 * 1024,
+                })
+            })
         })
         .collect()
 }
@@ -158,6 +178,13 @@ where
                     chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2,
                     peers::DesiredState::DesiredReset,
                 );
+                if self.chains[chain_index].chain_config.has_beefy_protocol {
+                    self.inner.set_peer_notifications_out_desired(
+                        &peer_id,
+                        chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 3,
+                        peers::DesiredState::DesiredReset,
+                    );
+                }
 
                 let slot_ty = {
                     let local_genesis = self.chains[chain_index].chain_config.genesis_hash;
@@ -256,6 +283,12 @@ where
                 None
             }
 
+            // Successfully opened BEEFY substream.
+            Ok(_) if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 3 => {
+                // Nothing to do.
+                None
+            }
+
             // Failed to open block announces substream.
             Err(error) if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 0 => {
                 let chain_index = notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN;
@@ -294,11 +327,11 @@ where
         if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 0 {
             let chain_index = notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN;
 
-            // The desirability of the transactions and grandpa substreams is always equal
-            // to whether the block announces substream is open.
+            // The desirability of the transactions, grandpa and BEEFY substreams is always
+            // equal to whether the block announces substream is open.
             //
-            // These two calls modify `self.inner`, but they are still cancellation-safe
-            // as they can be repeated multiple times.
+            // These calls modify `self.inner`, but they are still cancellation-safe as they can
+            // be repeated multiple times.
             self.inner.set_peer_notifications_out_desired(
                 &peer_id,
                 chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 1,
@@ -309,10 +342,17 @@ where
                 chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2,
                 peers::DesiredState::NotDesired,
             );
+            self.inner.set_peer_notifications_out_desired(
+                &peer_id,
+                chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 3,
+                peers::DesiredState::NotDesired,
+            );
 
             // The chain is now considered as closed.
             // TODO: can was_open ever be false?
             let was_open = self.open_chains.remove(&(peer_id.clone(), chain_index)); // TODO: cloning :(
+            self.beefy_substreams_backoff
+                .remove(&(peer_id.clone(), chain_index));
 
             if was_open {
                 // Update the k-buckets, marking the peer as disconnected.
@@ -349,7 +389,21 @@ where
             // Therefore, if the peer is considered open, try to reopen the substream that
             // has just been closed.
             // TODO: cloning of peer_id :-/
-            if self.open_chains.contains(&(peer_id.clone(), chain_index)) {
+            if !self.open_chains.contains(&(peer_id.clone(), chain_index)) {
+                return None;
+            }
+
+            if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 3 {
+                // Contrary to the other protocols, BEEFY is optional, and the remote might close
+                // the substream as soon as it is open. Reopening it is delayed in order to not
+                // enter a busy loop. The substream is marked as desired again in `next_event`.
+                let (reopen_at, num_closures) = self
+                    .beefy_substreams_backoff
+                    .entry((peer_id, chain_index))
+                    .or_insert((None, 0));
+                *reopen_at = Some(now.clone() + beefy_reopen_delay(*num_closures));
+                *num_closures = num_closures.saturating_add(1);
+            } else {
                 self.inner.set_peer_notifications_out_desired(
                     &peer_id,
                     notifications_protocol_index,
@@ -479,6 +533,27 @@ where
                     .to_vec()
             };
 
+            self.inner.in_notification_accept(substream_id, handshake);
+        } else if (notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN) == 3 {
+            // Remote wants to open a BEEFY substream.
+            let chain_index = notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN;
+
+            // Reject the substream if BEEFY is disabled or if this peer isn't "chain connected".
+            if !self.chains[chain_index].chain_config.has_beefy_protocol
+                || !self
+                    .open_chains // TODO: clone :-/
+                    .contains(&(peer_id.clone(), chain_index))
+            {
+                self.inner.in_notification_refuse(substream_id);
+                return None;
+            }
+
+            let handshake = self.chains[chain_index]
+                .chain_config
+                .role
+                .scale_encoding()
+                .to_vec();
+
             self.inner.in_notification_accept(substream_id, handshake);
         } else {
             // Unrecognized notifications protocol.
@@ -620,6 +695,37 @@ where
                     None
                 }
             }
+        } else if notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 3 {
+            let chain_index = notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN;
+            let block_number_bytes = self.chains[chain_index].chain_config.block_number_bytes;
+
+            // Don't report events about nodes we don't have an outbound substream with.
+            // TODO: cloning of peer_id :(
+            if !self.open_chains.contains(&(peer_id.clone(), chain_index)) {
+                return None;
+            }
+
+            match protocol::decode_beefy_notification(&notification, block_number_bytes) {
+                Ok(protocol::BeefyNotificationRef::FinalityProof(_)) => {
+                    Some(Event::BeefyFinalityProof {
+                        chain_index,
+                        peer_id,
+                        proof: EncodedBeefyFinalityProof {
+                            message: notification,
+                            block_number_bytes,
+                        },
+                    })
+                }
+                Ok(protocol::BeefyNotificationRef::Vote(_)) => {
+                    // Individual votes are only useful to nodes that participate in BEEFY, and
+                    // are ignored.
+                    None
+                }
+                Err(err) => Some(Event::ProtocolError {
+                    error: ProtocolError::BadBeefyNotification(err),
+                    peer_id,
+                }),
+            }
         } else {
             // Unrecognized notifications protocol.
             unreachable!();
@@ -868,3 +974,39 @@ impl fmt::Debug for EncodedGrandpaCommitMessage {
         fmt::Debug::fmt(&self.decode(), f)
     }
 }
+
+/// Undecoded but valid BEEFY finality proof.
+#[derive(Clone)]
+pub struct EncodedBeefyFinalityProof {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedBeefyFinalityProof {
+    /// Returns the SCALE-encoded versioned finality proof.
+    pub fn into_encoded(mut self) -> Vec<u8> {
+        // Skip the first byte because `self.message` is a `BeefyNotificationRef`.
+        self.message.remove(0);
+        self.message
+    }
+
+    /// Returns the SCALE-encoded versioned finality proof.
+    pub fn as_encoded(&self) -> &[u8] {
+        // Skip the first byte because `self.message` is a `BeefyNotificationRef`.
+        &self.message[1..]
+    }
+
+    /// Returns the decoded version of the finality proof.
+    pub fn decode(&self) -> protocol::SignedCommitmentRef {
+        match protocol::decode_beefy_notification(&self.message, self.block_number_bytes) {
+            Ok(protocol::BeefyNotificationRef::FinalityProof(proof)) => proof,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedBeefyFinalityProof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
@@ -98,8 +98,7 @@ pub(super) fn protocols<'a>(
             },
             inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
             max_response_size: 1024 * 1024,
-            // TODO: `false` here means we don't insert ourselves in the DHT, which is the polite thing to do for as long as Kad isn't implemented
-            inbound_allowed: false,
+            inbound_allowed: chain.allow_inbound_kademlia_requests,
         }))
         .chain(iter::once(peers::ConfigRequestResponse {
             name: match &chain.fork_id {
@@ -110,8 +109,7 @@ pub(super) fn protocols<'a>(
             },
             inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 32 },
             max_response_size: 16 * 1024 * 1024,
-            // We don't support inbound warp sync requests (yet).
-            inbound_allowed: false,
+            inbound_allowed: chain.allow_inbound_grandpa_warp_sync_requests,
         }))
         .chain(iter::once(peers::ConfigRequestResponse {
             name: match &chain.fork_id {
@@ -310,40 +308,91 @@ where
                     error: ProtocolError::BadIdentifyRequest,
                 }
             }
-        } else if ((protocol_index - 1) % requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN)
-            != 0
-        {
-            // Protocols that receive requests are whitelisted, meaning that no other protocol
-            // indices can reach here.
-            unreachable!()
         } else {
             let chain_index =
                 (protocol_index - 1) / requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN;
 
-            match protocol::decode_block_request(
-                self.chains[chain_index].chain_config.block_number_bytes,
-                &request_payload,
-            ) {
-                Ok(config) => {
-                    let _prev_value = self
-                        .in_requests_types
-                        .insert(request_id, InRequestTy::Blocks);
-                    debug_assert!(_prev_value.is_none());
-
-                    Event::BlocksRequestIn {
-                        peer_id,
-                        chain_index,
-                        config,
-                        request_id,
+            match (protocol_index - 1) % requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN {
+                0 => match protocol::decode_block_request(
+                    self.chains[chain_index].chain_config.block_number_bytes,
+                    &request_payload,
+                ) {
+                    Ok(config) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::Blocks);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::BlocksRequestIn {
+                            peer_id,
+                            chain_index,
+                            config,
+                            request_id,
+                        }
                     }
-                }
-                Err(error) => {
-                    self.inner.respond_in_request(request_id, Err(()));
-                    Event::ProtocolError {
-                        peer_id,
-                        error: ProtocolError::BadBlocksRequest(error),
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadBlocksRequest(error),
+                        }
+                    }
+                },
+                2 => {
+                    let target = match protocol::decode_find_node_request(&request_payload) {
+                        Ok(key) => PeerId::from_bytes(key.to_vec())
+                            .map_err(|(err, _)| ProtocolError::BadKademliaFindNodeKey(err)),
+                        Err(error) => Err(ProtocolError::BadKademliaFindNodeRequest(error)),
+                    };
+
+                    match target {
+                        Ok(target) => {
+                            let _prev_value = self.in_requests_types.insert(
+                                request_id,
+                                InRequestTy::KademliaFindNode {
+                                    chain_index,
+                                    target,
+                                },
+                            );
+                            debug_assert!(_prev_value.is_none());
+
+                            Event::KademliaFindNodeRequestIn {
+                                peer_id,
+                                chain_index,
+                                request_id,
+                            }
+                        }
+                        Err(error) => {
+                            self.inner.respond_in_request(request_id, Err(()));
+                            Event::ProtocolError { peer_id, error }
+                        }
                     }
                 }
+                3 => match protocol::decode_grandpa_warp_sync_request(&request_payload) {
+                    Ok(begin_hash) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::GrandpaWarpSync);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::GrandpaWarpSyncRequestIn {
+                            peer_id,
+                            chain_index,
+                            begin_hash,
+                            request_id,
+                        }
+                    }
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadGrandpaWarpSyncRequest(error),
+                        }
+                    }
+                },
+                // Protocols that receive requests are whitelisted, meaning that no other protocol
+                // indices can reach here.
+                _ => unreachable!(),
             }
         }
     }
@@ -922,6 +971,76 @@ where
 
         self.inner.respond_in_request(request_id, response);
     }
+
+    /// Sends back the response to a Kademlia find node request.
+    ///
+    /// The response contains the peers of the local k-buckets that are the closest to the
+    /// requested key, alongside with their known addresses.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_kademlia_find_node(&mut self, request_id: InRequestId) {
+        let (chain_index, target) = match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::KademliaFindNode {
+                chain_index,
+                target,
+            }) => (chain_index, target),
+            _ => panic!(),
+        };
+
+        // Substrate also sends back at most 20 peers.
+        let response = protocol::build_find_node_response(
+            self.chains[chain_index]
+                .kbuckets
+                .closest_entries(&target)
+                .take(20)
+                .map(|(peer_id, _)| {
+                    (
+                        peer_id,
+                        self.kbuckets_peers.get(peer_id).unwrap().addresses.iter(),
+                    )
+                }),
+        );
+
+        self.inner.respond_in_request(request_id, Ok(response));
+    }
+
+    /// Queue the response to a GrandPa warp sync request to send back.
+    ///
+    /// Pass `None` in order to deny the request. Do this if the block the remote wants to start
+    /// warp syncing from isn't known locally.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_grandpa_warp_sync(
+        &mut self,
+        request_id: InRequestId,
+        response: Option<protocol::GrandpaWarpSyncResponse>,
+    ) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::GrandpaWarpSync) => {}
+            _ => panic!(),
+        };
+
+        let response =
+            if let Some(response) = response {
+                Ok(protocol::build_grandpa_warp_sync_response(response).fold(
+                    Vec::new(),
+                    |mut a, b| {
+                        a.extend_from_slice(b.as_ref());
+                        a
+                    },
+                ))
+            } else {
+                Err(())
+            };
+
+        self.inner.respond_in_request(request_id, response);
+    }
 }
 
 /// Response to an outgoing request.
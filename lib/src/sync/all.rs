@@ -41,13 +41,17 @@ use crate::{
 use alloc::{borrow::Cow, vec::Vec};
 use core::{
     cmp, iter, marker, mem,
-    num::{NonZeroU32, NonZeroU64},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
     ops,
     time::Duration,
 };
 
+pub use blocks_tree::BestBlockPolicy;
 pub use optimistic::TrieEntryVersion;
-pub use warp_sync::{FragmentError as WarpSyncFragmentError, WarpSyncFragment};
+pub use warp_sync::{
+    FragmentError as WarpSyncFragmentError, Stage as WarpSyncStage,
+    VerifiedProgress as WarpSyncVerifiedProgress, WarpSyncFragment,
+};
 
 /// Configuration for the [`AllSync`].
 // TODO: review these fields
@@ -69,6 +73,12 @@ pub struct Config {
     /// the network is bounded.
     pub allow_unknown_consensus_engines: bool,
 
+    /// Rule used to determine which block among the non-finalized blocks is the best block.
+    ///
+    /// Only applies to the "all forks" strategy. Ignored when the optimistic strategy is used,
+    /// as it only ever downloads a single chain.
+    pub best_block_policy: BestBlockPolicy,
+
     /// Pre-allocated capacity for the number of block sources.
     pub sources_capacity: usize,
 
@@ -88,6 +98,18 @@ pub struct Config {
     /// See [`all_forks::Config::max_requests_per_block`] for more information.
     pub max_requests_per_block: NonZeroU32,
 
+    /// Maximum number of simultaneous GrandPa warp sync requests starting from the same block,
+    /// each towards a different source.
+    ///
+    /// See [`warp_sync::Config::max_parallel_fragments_requests`] for more information.
+    pub max_parallel_warp_sync_requests: NonZeroU32,
+
+    /// If `Some`, the GrandPa warp syncing resumes from the given progress, as previously
+    /// returned by [`AllSync::warp_sync_verified_progress`].
+    ///
+    /// See [`warp_sync::Config::resume_progress`] for more information.
+    pub warp_sync_resume_progress: Option<WarpSyncVerifiedProgress>,
+
     /// Number of blocks to download ahead of the best verified block.
     ///
     /// Whenever the latest best block is updated, the state machine will start block
@@ -99,11 +121,40 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// Strategy to use in order to synchronize the chain after the GrandPa warp syncing is over.
+    ///
+    /// Ignored if [`Config::full`] is `Some`, or if the chain doesn't use GrandPa, in which case
+    /// the optimistic syncing is always used.
+    pub sync_mode: SyncMode,
+
+    /// If `Some`, and if the chain doesn't have any finality mechanism, the blocks that are at
+    /// least this number of blocks below the best block are considered as finalized.
+    ///
+    /// See [`optimistic::Config::probabilistic_finality_depth`] for more information.
+    pub probabilistic_finality_depth: Option<NonZeroU64>,
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
 }
 
+/// See [`Config::sync_mode`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Download and verify all the forks of the chain. Blocks are downloaded in descending
+    /// order, starting from the blocks announced by the sources.
+    AllForks {
+        /// See [`all_forks::Config::forks_retention_limit`].
+        forks_retention_limit: Option<NonZeroUsize>,
+    },
+
+    /// Only download and verify the best chain, in ascending order. Forks are ignored.
+    ///
+    /// The best block doesn't depend on the finality of the chain, which makes it possible to
+    /// follow the head of the chain even if the finality of the chain is stalled.
+    Optimistic,
+}
+
 /// See [`Config::full`].
 #[derive(Debug)]
 pub struct ConfigFull {
@@ -177,6 +228,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                         sources_capacity: config.sources_capacity,
                         blocks_capacity: config.blocks_capacity,
                         download_ahead_blocks: config.download_ahead_blocks,
+                        probabilistic_finality_depth: None,
                         full: Some(optimistic::ConfigFull {
                             finalized_runtime: config_full.finalized_runtime,
                         }),
@@ -188,6 +240,8 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                     block_number_bytes: config.block_number_bytes,
                     sources_capacity: config.sources_capacity,
                     requests_capacity: config.sources_capacity, // TODO: ?! add as config?
+                    max_parallel_fragments_requests: config.max_parallel_warp_sync_requests,
+                    resume_progress: config.warp_sync_resume_progress,
                 }) {
                     Ok(inner) => AllSyncInner::GrandpaWarpSync { inner },
                     Err((
@@ -204,6 +258,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 sources_capacity: config.sources_capacity,
                                 blocks_capacity: config.blocks_capacity,
                                 download_ahead_blocks: config.download_ahead_blocks,
+                                probabilistic_finality_depth: config.probabilistic_finality_depth,
                                 full: None,
                             }),
                         }
@@ -220,6 +275,9 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 max_requests_per_block: config.max_requests_per_block,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                best_block_policy: config.best_block_policy,
+                download_ahead_blocks: config.download_ahead_blocks,
+                sync_mode: config.sync_mode,
             },
         }
     }
@@ -245,6 +303,29 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// If the GrandPa warp syncing is in progress, returns the highest block whose finality has
+    /// been verified so far. Returns `None` if the warp syncing isn't in progress or hasn't made
+    /// any progress.
+    ///
+    /// The value can later be passed back through [`Config::warp_sync_resume_progress`].
+    pub fn warp_sync_verified_progress(&self) -> Option<WarpSyncVerifiedProgress> {
+        match &self.inner {
+            AllSyncInner::GrandpaWarpSync { inner: sync } => sync.verified_progress(),
+            AllSyncInner::AllForks(_) | AllSyncInner::Optimistic { .. } => None,
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
+    /// If the GrandPa warp syncing is in progress, returns the stage it is currently at. Returns
+    /// `None` if the warp syncing isn't in progress.
+    pub fn warp_sync_stage(&self) -> Option<WarpSyncStage> {
+        match &self.inner {
+            AllSyncInner::GrandpaWarpSync { inner: sync } => Some(sync.stage()),
+            AllSyncInner::AllForks(_) | AllSyncInner::Optimistic { .. } => None,
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
     /// Returns the current status of the syncing.
     pub fn status(&self) -> Status<TSrc> {
         match &self.inner {
@@ -396,6 +477,43 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Builds the signatures check of the given header, in order for its signatures to be
+    /// verified ahead of time.
+    ///
+    /// The check can be performed in parallel, for example in another thread, using
+    /// [`verify::precheck::SignaturesCheck::verify`], and its outcome reported with
+    /// [`AllSync::insert_prechecked_signatures`]. Doing so speeds up the verification of the
+    /// header, which still happens in order.
+    ///
+    /// Returns `None` if the signatures of this header have already been verified, if they can't
+    /// be determined ahead of time, or if no header is being verified at the moment, for example
+    /// during the warp syncing.
+    pub fn signatures_check(
+        &self,
+        header: header::HeaderRef,
+    ) -> Option<verify::precheck::SignaturesCheck> {
+        match &self.inner {
+            AllSyncInner::AllForks(sync) => sync.signatures_check(header),
+            AllSyncInner::Optimistic { inner } => inner.signatures_check(header),
+            AllSyncInner::GrandpaWarpSync { .. } => None,
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
+    /// Stores signatures that have been verified ahead of time. See
+    /// [`AllSync::signatures_check`].
+    ///
+    /// Has no effect if the state machine is no longer in a state where headers are verified,
+    /// for example if it has switched to warp syncing.
+    pub fn insert_prechecked_signatures(&mut self, verified: verify::precheck::VerifiedSignatures) {
+        match &mut self.inner {
+            AllSyncInner::AllForks(sync) => sync.insert_prechecked_signatures(verified),
+            AllSyncInner::Optimistic { inner } => inner.insert_prechecked_signatures(verified),
+            AllSyncInner::GrandpaWarpSync { .. } => {}
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
     /// Returns true if it is believed that we are near the head of the chain.
     ///
     /// The way this method is implemented is opaque and cannot be relied on. The return value
@@ -1183,8 +1301,8 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                     finalized_block_runtime,
                                     finalized_storage_code,
                                     finalized_storage_heap_pages,
-                                ) = self.shared.transition_grandpa_warp_sync_all_forks(success);
-                                self.inner = AllSyncInner::AllForks(new_inner);
+                                ) = self.shared.transition_grandpa_warp_sync(success);
+                                self.inner = new_inner;
                                 ProcessOne::WarpSyncFinished {
                                     sync: self,
                                     finalized_block_runtime,
@@ -1207,8 +1325,8 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                     finalized_block_runtime,
                                     finalized_storage_code,
                                     finalized_storage_heap_pages,
-                                ) = self.shared.transition_grandpa_warp_sync_all_forks(success);
-                                self.inner = AllSyncInner::AllForks(new_inner);
+                                ) = self.shared.transition_grandpa_warp_sync(success);
+                                self.inner = new_inner;
                                 ProcessOne::WarpSyncFinished {
                                     sync: self,
                                     finalized_block_runtime,
@@ -1261,10 +1379,13 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     }
 
     /// Injects a block announcement made by a source into the state machine.
+    ///
+    /// The header is only copied if the announced block ends up being stored in the state
+    /// machine.
     pub fn block_announce(
         &mut self,
         source_id: SourceId,
-        announced_scale_encoded_header: Vec<u8>,
+        announced_scale_encoded_header: &[u8],
         is_best: bool,
     ) -> BlockAnnounceOutcome {
         let source_id = self.shared.sources.get(source_id.0).unwrap();
@@ -1294,13 +1415,13 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 }
             }
             (AllSyncInner::Optimistic { inner }, &SourceMapping::Optimistic(source_id)) => {
-                match header::decode(&announced_scale_encoded_header, inner.block_number_bytes()) {
+                match header::decode(announced_scale_encoded_header, inner.block_number_bytes()) {
                     Ok(header) => {
                         if is_best {
                             inner.raise_source_best_block(source_id, header.number);
                             inner[source_id].best_block_hash =
                                 header::hash_from_scale_encoded_header(
-                                    &announced_scale_encoded_header,
+                                    announced_scale_encoded_header,
                                 );
                         }
                         BlockAnnounceOutcome::Discarded
@@ -1313,7 +1434,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 &SourceMapping::GrandpaWarpSync(source_id),
             ) => {
                 let block_number_bytes = sync.block_number_bytes();
-                match header::decode(&announced_scale_encoded_header, block_number_bytes) {
+                match header::decode(announced_scale_encoded_header, block_number_bytes) {
                     Err(err) => BlockAnnounceOutcome::InvalidHeader(err),
                     Ok(header) => {
                         // If GrandPa warp syncing is in progress, the best block of the source is stored
@@ -1322,7 +1443,9 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                         if is_best {
                             let mut user_data = &mut sync[source_id];
                             user_data.best_block_number = header.number;
-                            user_data.best_block_hash = header.hash(block_number_bytes);
+                            user_data.best_block_hash = header::hash_from_scale_encoded_header(
+                                announced_scale_encoded_header,
+                            );
                         }
 
                         BlockAnnounceOutcome::Discarded
@@ -2006,6 +2129,14 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns the changes that must be applied to the storage of the finalized block in order
+    /// to obtain the storage of this block.
+    pub fn storage_diff(&self) -> &'a storage_diff::TrieDiff<TrieEntryVersion> {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => inner.storage_diff(),
+        }
+    }
+
     pub fn prefix_keys_ordered<'k: 'a>(
         &'k self, // TODO: unclear lifetime
         prefix: &'k [u8],
@@ -2017,6 +2148,21 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             }
         }
     }
+
+    /// Returns the key that immediately follows `key` in the storage, or `None` if there is no
+    /// such key.
+    ///
+    /// `in_finalized_next_key` is called with a key and must return the key that immediately
+    /// follows it in the storage of the finalized block.
+    pub fn next_key<'k>(
+        &'k self,
+        key: &'k [u8],
+        in_finalized_next_key: impl FnMut(&[u8]) -> Option<&'k [u8]>,
+    ) -> Option<&'k [u8]> {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => inner.next_key(key, in_finalized_next_key),
+        }
+    }
 }
 
 /// Outcome of calling [`AllSync::process_one`].
@@ -2397,6 +2543,11 @@ impl<TRq, TSrc, TBl> WarpSyncFragmentVerify<TRq, TSrc, TBl> {
         (ud.outer_source_id, &ud.user_data)
     }
 
+    /// Returns the fragment to be verified, or `None` if the proof sent by the source is empty.
+    pub fn fragment(&self) -> Option<&WarpSyncFragment> {
+        self.inner.fragment()
+    }
+
     /// Perform the verification.
     ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
@@ -2765,9 +2916,53 @@ struct Shared<TRq> {
     block_number_bytes: usize,
     /// Value passed through [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Value passed through [`Config::best_block_policy`].
+    best_block_policy: BestBlockPolicy,
+    /// Value passed through [`Config::download_ahead_blocks`].
+    download_ahead_blocks: NonZeroU32,
+    /// Value passed through [`Config::sync_mode`].
+    sync_mode: SyncMode,
 }
 
 impl<TRq> Shared<TRq> {
+    /// Transitions the sync state machine from the grandpa warp strategy to the strategy
+    /// indicated by [`Config::sync_mode`].
+    fn transition_grandpa_warp_sync<TSrc, TBl>(
+        &mut self,
+        grandpa: warp_sync::Success<
+            GrandpaWarpSyncSourceExtra<TSrc>,
+            GrandpaWarpSyncRequestExtra<TRq>,
+        >,
+    ) -> (
+        AllSyncInner<TRq, TSrc, TBl>,
+        host::HostVmPrototype,
+        Option<Vec<u8>>,
+        Option<Vec<u8>>,
+    ) {
+        match self.sync_mode {
+            SyncMode::AllForks { .. } => {
+                let (all_forks, runtime, storage_code, storage_heap_pages) =
+                    self.transition_grandpa_warp_sync_all_forks(grandpa);
+                (
+                    AllSyncInner::AllForks(all_forks),
+                    runtime,
+                    storage_code,
+                    storage_heap_pages,
+                )
+            }
+            SyncMode::Optimistic => {
+                let (inner, runtime, storage_code, storage_heap_pages) =
+                    self.transition_grandpa_warp_sync_optimistic(grandpa);
+                (
+                    AllSyncInner::Optimistic { inner },
+                    runtime,
+                    storage_code,
+                    storage_heap_pages,
+                )
+            }
+        }
+    }
+
     /// Transitions the sync state machine from the grandpa warp strategy to the "all-forks"
     /// strategy.
     fn transition_grandpa_warp_sync_all_forks<TSrc, TBl>(
@@ -2790,6 +2985,13 @@ impl<TRq> Shared<TRq> {
             max_disjoint_headers: self.max_disjoint_headers,
             max_requests_per_block: self.max_requests_per_block,
             allow_unknown_consensus_engines: self.allow_unknown_consensus_engines,
+            best_block_policy: self.best_block_policy,
+            forks_retention_limit: match self.sync_mode {
+                SyncMode::AllForks {
+                    forks_retention_limit,
+                } => forks_retention_limit,
+                SyncMode::Optimistic => None,
+            },
             full: false,
         });
 
@@ -2798,6 +3000,122 @@ impl<TRq> Shared<TRq> {
             .iter()
             .all(|(_, s)| matches!(s, SourceMapping::GrandpaWarpSync(_))));
 
+        self.warp_sync_requests_into_inline(grandpa.in_progress_requests);
+
+        for source in grandpa.sources {
+            let source_user_data = AllForksSourceExtra {
+                user_data: source.user_data,
+                outer_source_id: source.outer_source_id,
+            };
+
+            let updated_source_id = match all_forks
+                .prepare_add_source(source.best_block_number, source.best_block_hash)
+            {
+                all_forks::AddSource::BestBlockAlreadyVerified(b)
+                | all_forks::AddSource::BestBlockPendingVerification(b) => {
+                    b.add_source(source_user_data)
+                }
+                all_forks::AddSource::OldBestBlock(b) => b.add_source(source_user_data),
+                all_forks::AddSource::UnknownBestBlock(b) => {
+                    b.add_source_and_insert_block(source_user_data, None)
+                }
+            };
+
+            self.sources[source.outer_source_id.0] = SourceMapping::AllForks(updated_source_id);
+        }
+
+        debug_assert!(self
+            .sources
+            .iter()
+            .all(|(_, s)| matches!(s, SourceMapping::AllForks(_))));
+        debug_assert!(self
+            .requests
+            .iter()
+            .all(|(_, s)| matches!(s, RequestMapping::AllForks(..) | RequestMapping::Inline(..))));
+
+        (
+            all_forks,
+            grandpa.finalized_runtime,
+            grandpa.finalized_storage_code,
+            grandpa.finalized_storage_heap_pages,
+        )
+    }
+
+    /// Transitions the sync state machine from the grandpa warp strategy to the "optimistic"
+    /// strategy.
+    fn transition_grandpa_warp_sync_optimistic<TSrc, TBl>(
+        &mut self,
+        grandpa: warp_sync::Success<
+            GrandpaWarpSyncSourceExtra<TSrc>,
+            GrandpaWarpSyncRequestExtra<TRq>,
+        >,
+    ) -> (
+        optimistic::OptimisticSync<OptimisticRequestExtra<TRq>, OptimisticSourceExtra<TSrc>, TBl>,
+        host::HostVmPrototype,
+        Option<Vec<u8>>,
+        Option<Vec<u8>>,
+    ) {
+        let mut optimistic = optimistic::OptimisticSync::new(optimistic::Config {
+            chain_information: grandpa.chain_information,
+            block_number_bytes: self.block_number_bytes,
+            sources_capacity: self.sources_capacity,
+            blocks_capacity: self.blocks_capacity,
+            download_ahead_blocks: self.download_ahead_blocks,
+            // The GrandPa warp syncing is only ever used for chains that use GrandPa.
+            probabilistic_finality_depth: None,
+            full: None,
+        });
+
+        debug_assert!(self
+            .sources
+            .iter()
+            .all(|(_, s)| matches!(s, SourceMapping::GrandpaWarpSync(_))));
+
+        self.warp_sync_requests_into_inline(grandpa.in_progress_requests);
+
+        for source in grandpa.sources {
+            let updated_source_id = optimistic.add_source(
+                OptimisticSourceExtra {
+                    user_data: source.user_data,
+                    outer_source_id: source.outer_source_id,
+                    best_block_hash: source.best_block_hash,
+                },
+                source.best_block_number,
+            );
+
+            self.sources[source.outer_source_id.0] = SourceMapping::Optimistic(updated_source_id);
+        }
+
+        debug_assert!(self
+            .sources
+            .iter()
+            .all(|(_, s)| matches!(s, SourceMapping::Optimistic(_))));
+        debug_assert!(self.requests.iter().all(|(_, s)| matches!(
+            s,
+            RequestMapping::Optimistic(..) | RequestMapping::Inline(..)
+        )));
+
+        (
+            optimistic,
+            grandpa.finalized_runtime,
+            grandpa.finalized_storage_code,
+            grandpa.finalized_storage_heap_pages,
+        )
+    }
+
+    /// Converts the requests that were in progress in the GrandPa warp syncing state machine
+    /// into requests that are handled by the [`AllSync`] itself.
+    ///
+    /// Must be called while [`Shared::sources`] still contains the warp sync sources.
+    fn warp_sync_requests_into_inline(
+        &mut self,
+        in_progress_requests: Vec<(
+            warp_sync::SourceId,
+            warp_sync::RequestId,
+            GrandpaWarpSyncRequestExtra<TRq>,
+            warp_sync::RequestDetail,
+        )>,
+    ) {
         for (
             source_id,
             _,
@@ -2806,7 +3124,7 @@ impl<TRq> Shared<TRq> {
                 user_data,
             },
             detail,
-        ) in grandpa.in_progress_requests
+        ) in in_progress_requests
         {
             // TODO: DRY
             let detail = match detail {
@@ -2842,44 +3160,6 @@ impl<TRq> Shared<TRq> {
             self.requests[outer_request_id.0] =
                 RequestMapping::Inline(SourceId(source_id), detail, user_data);
         }
-
-        for source in grandpa.sources {
-            let source_user_data = AllForksSourceExtra {
-                user_data: source.user_data,
-                outer_source_id: source.outer_source_id,
-            };
-
-            let updated_source_id = match all_forks
-                .prepare_add_source(source.best_block_number, source.best_block_hash)
-            {
-                all_forks::AddSource::BestBlockAlreadyVerified(b)
-                | all_forks::AddSource::BestBlockPendingVerification(b) => {
-                    b.add_source(source_user_data)
-                }
-                all_forks::AddSource::OldBestBlock(b) => b.add_source(source_user_data),
-                all_forks::AddSource::UnknownBestBlock(b) => {
-                    b.add_source_and_insert_block(source_user_data, None)
-                }
-            };
-
-            self.sources[source.outer_source_id.0] = SourceMapping::AllForks(updated_source_id);
-        }
-
-        debug_assert!(self
-            .sources
-            .iter()
-            .all(|(_, s)| matches!(s, SourceMapping::AllForks(_))));
-        debug_assert!(self
-            .requests
-            .iter()
-            .all(|(_, s)| matches!(s, RequestMapping::AllForks(..) | RequestMapping::Inline(..))));
-
-        (
-            all_forks,
-            grandpa.finalized_runtime,
-            grandpa.finalized_storage_code,
-            grandpa.finalized_storage_heap_pages,
-        )
     }
 }
 
@@ -85,11 +85,17 @@
 use crate::{
     chain::{blocks_tree, chain_information},
     finality::grandpa,
-    header, verify,
+    header,
+    verify::{self, precheck},
 };
 
 use alloc::{borrow::ToOwned as _, vec::Vec};
-use core::{mem, num::NonZeroU32, ops, time::Duration};
+use core::{
+    mem,
+    num::{NonZeroU32, NonZeroUsize},
+    ops,
+    time::Duration,
+};
 
 mod disjoint;
 mod pending_blocks;
@@ -117,6 +123,9 @@ pub struct Config {
     /// the network is bounded.
     pub allow_unknown_consensus_engines: bool,
 
+    /// Rule used to determine which block among the non-finalized blocks is the best block.
+    pub best_block_policy: blocks_tree::BestBlockPolicy,
+
     /// Pre-allocated capacity for the number of block sources.
     pub sources_capacity: usize,
 
@@ -159,6 +168,15 @@ pub struct Config {
     /// The higher the value, the more bandwidth is potentially wasted.
     pub max_requests_per_block: NonZeroU32,
 
+    /// If `Some`, maximum number of non-finalized blocks beyond which only the blocks that are
+    /// children of the current best block are verified. Blocks belonging to other forks are kept
+    /// pending until the number of non-finalized blocks decreases, in other words until a block
+    /// gets finalized.
+    ///
+    /// This makes it possible to continue following the best chain when the finality of the
+    /// chain is stalled, while bounding the number of forks that are kept in memory.
+    pub forks_retention_limit: Option<NonZeroUsize>,
+
     /// If true, the block bodies and storage are also synchronized.
     pub full: bool,
 }
@@ -176,6 +194,9 @@ pub struct AllForksSync<TBl, TRq, TSrc> {
 /// Extra fields. In a separate structure in order to be moved around.
 struct Inner<TBl, TRq, TSrc> {
     blocks: pending_blocks::PendingBlocks<PendingBlock<TBl>, TRq, Source<TSrc>>,
+
+    /// Value passed through [`Config::forks_retention_limit`].
+    forks_retention_limit: Option<NonZeroUsize>,
 }
 
 struct PendingBlock<TBl> {
@@ -421,6 +442,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             block_number_bytes: config.block_number_bytes,
             blocks_capacity: config.blocks_capacity,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            best_block_policy: config.best_block_policy,
         });
 
         Self {
@@ -433,6 +455,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                     sources_capacity: config.sources_capacity,
                     verify_bodies: config.full,
                 }),
+                forks_retention_limit: config.forks_retention_limit,
             },
         }
     }
@@ -498,6 +521,22 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.iter_ancestry_order()
     }
 
+    /// Builds the signatures check of the given header, in order for its signatures to be
+    /// verified ahead of time.
+    ///
+    /// See [`blocks_tree::NonFinalizedTree::signatures_check`].
+    pub fn signatures_check(&self, header: header::HeaderRef) -> Option<precheck::SignaturesCheck> {
+        self.chain.signatures_check(header)
+    }
+
+    /// Stores signatures that have been verified ahead of time, so that they are no longer
+    /// verified when verifying the header they belong to.
+    ///
+    /// See [`AllForksSync::signatures_check`].
+    pub fn insert_prechecked_signatures(&mut self, verified: precheck::VerifiedSignatures) {
+        self.chain.insert_prechecked_signatures(verified);
+    }
+
     /// Gives access to the user data stored for a block of the data structure.
     ///
     /// # Panic
@@ -816,18 +855,21 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
     /// > **Note**: This information is normally reported by the source itself. In the case of a
     /// >           a networking peer, call this when the source sent a block announce.
     ///
+    /// The announced header is borrowed for as long as the returned [`BlockAnnounceOutcome`] is
+    /// alive, and is only copied if it ends up being stored in the state machine.
+    ///
     /// # Panic
     ///
     /// Panics if `source_id` is invalid.
     ///
-    pub fn block_announce(
-        &mut self,
+    pub fn block_announce<'a>(
+        &'a mut self,
         source_id: SourceId,
-        announced_scale_encoded_header: Vec<u8>,
+        announced_scale_encoded_header: &'a [u8],
         is_best: bool,
-    ) -> BlockAnnounceOutcome<TBl, TRq, TSrc> {
+    ) -> BlockAnnounceOutcome<'a, TBl, TRq, TSrc> {
         let announced_header = match header::decode(
-            &announced_scale_encoded_header,
+            announced_scale_encoded_header,
             self.chain.block_number_bytes(),
         ) {
             Ok(h) => h,
@@ -836,7 +878,8 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
 
         let announced_header_number = announced_header.number;
         let announced_header_parent_hash = *announced_header.parent_hash;
-        let announced_header_hash = announced_header.hash(self.chain.block_number_bytes());
+        let announced_header_hash =
+            header::hash_from_scale_encoded_header(announced_scale_encoded_header);
 
         // It is assumed that all sources will eventually agree on the same finalized chain. If
         // the block number is lower or equal than the locally-finalized block number, it is
@@ -870,7 +913,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                 announced_header_hash,
                 announced_header_number,
                 announced_header_parent_hash,
-                announced_header_encoded: announced_header.into(),
+                announced_header_encoded: announced_header,
                 source_id,
                 is_in_chain: true,
                 is_best,
@@ -890,7 +933,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                 announced_header_hash,
                 announced_header_number,
                 announced_header_parent_hash,
-                announced_header_encoded: announced_header.into(),
+                announced_header_encoded: announced_header,
                 source_id,
                 is_best,
             })
@@ -900,7 +943,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                 announced_header_hash,
                 announced_header_number,
                 announced_header_parent_hash,
-                announced_header_encoded: announced_header.into(),
+                announced_header_encoded: announced_header,
                 is_in_chain: false,
                 source_id,
                 is_best,
@@ -964,7 +1007,19 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             });
         }
 
+        // If the number of non-finalized blocks has reached the limit, only the blocks that
+        // extend the best chain are verified.
+        let only_best_chain = self
+            .inner
+            .forks_retention_limit
+            .map_or(false, |limit| self.chain.len() >= limit.get());
+        let best_chain_head_hash = self.chain.best_chain_head_hash();
+
         let block = self.inner.blocks.unverified_leaves().find(|block| {
+            if only_best_chain {
+                return block.parent_block_hash == best_chain_head_hash;
+            }
+
             block.parent_block_hash == self.chain.finalized_block_hash()
                 || self
                     .chain
@@ -1482,7 +1537,7 @@ pub struct AnnouncedBlockKnown<'a, TBl, TRq, TSrc> {
     announced_header_hash: [u8; 32],
     announced_header_parent_hash: [u8; 32],
     announced_header_number: u64,
-    announced_header_encoded: header::Header,
+    announced_header_encoded: header::HeaderRef<'a>,
     is_in_chain: bool,
     is_best: bool,
     source_id: SourceId,
@@ -1553,7 +1608,7 @@ impl<'a, TBl, TRq, TSrc> AnnouncedBlockKnown<'a, TBl, TRq, TSrc> {
                 &self.announced_header_hash,
             );
             if block_user_data.header.is_none() {
-                block_user_data.header = Some(self.announced_header_encoded);
+                block_user_data.header = Some(self.announced_header_encoded.into());
             }
 
             // Mark block as bad if it is not part of the finalized chain.
@@ -1579,7 +1634,7 @@ pub struct AnnouncedBlockUnknown<'a, TBl, TRq, TSrc> {
     announced_header_hash: [u8; 32],
     announced_header_parent_hash: [u8; 32],
     announced_header_number: u64,
-    announced_header_encoded: header::Header,
+    announced_header_encoded: header::HeaderRef<'a>,
     is_best: bool,
     source_id: SourceId,
 }
@@ -1623,7 +1678,7 @@ impl<'a, TBl, TRq, TSrc> AnnouncedBlockUnknown<'a, TBl, TRq, TSrc> {
                 parent_hash: self.announced_header_parent_hash,
             },
             PendingBlock {
-                header: Some(self.announced_header_encoded),
+                header: Some(self.announced_header_encoded.into()),
                 user_data,
             },
         );
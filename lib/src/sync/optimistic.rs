@@ -50,6 +50,7 @@ use crate::{
     executor::{host, storage_diff},
     header,
     trie::calculate_root,
+    verify::precheck,
 };
 
 use alloc::{
@@ -100,6 +101,18 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// If `Some`, and if the chain doesn't have any finality mechanism (in other words, if its
+    /// finality is [`chain_information::ChainInformationFinality::Outsourced`]), the blocks
+    /// that are at least this number of blocks below the best block are considered as
+    /// finalized.
+    ///
+    /// This is a probabilistic finality: the deeper a block is, the less likely it is that it
+    /// gets reverted. If `None`, the blocks of such chains are never finalized.
+    ///
+    /// Must be `None` for parachains, as their finality is provided by their relay chain.
+    /// Ignored if [`Config::full`] is `Some`.
+    pub probabilistic_finality_depth: Option<NonZeroU64>,
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
@@ -173,6 +186,9 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     /// See [`Config::download_ahead_blocks`].
     download_ahead_blocks: NonZeroU32,
 
+    /// See [`Config::probabilistic_finality_depth`].
+    probabilistic_finality_depth: Option<NonZeroU64>,
+
     /// List of sources of blocks.
     sources: HashMap<SourceId, Source<TSrc>, fnv::FnvBuildHasher>,
 
@@ -228,6 +244,36 @@ impl<TRq, TSrc, TBl> OptimisticSyncInner<TRq, TSrc, TBl> {
         self.make_requests_obsolete(chain);
         self
     }
+
+    /// Returns the height of the block that must be finalized according to
+    /// [`Config::probabilistic_finality_depth`], or `None` if no block must be finalized.
+    fn probabilistic_finality_target(
+        &self,
+        chain: &blocks_tree::NonFinalizedTree<Block<TBl>>,
+    ) -> Option<u64> {
+        let depth = self.probabilistic_finality_depth?;
+
+        if self.finalized_runtime.is_some() {
+            return None;
+        }
+
+        if !matches!(
+            self.finalized_chain_information
+                .chain_information
+                .as_ref()
+                .finality,
+            chain_information::ChainInformationFinalityRef::Outsourced
+        ) {
+            return None;
+        }
+
+        let target = chain.best_block_header().number.checked_sub(depth.get())?;
+        if target <= chain.finalized_block_header().number {
+            return None;
+        }
+
+        Some(target)
+    }
 }
 
 struct Source<TSrc> {
@@ -290,6 +336,10 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             // a malicious node could send non-finalized blocks. Accepting blocks with an
             // unrecognized consensus engine doesn't add any additional risk.
             allow_unknown_consensus_engines: true,
+            // The optimistic syncing only ever downloads a single chain. The best block is used
+            // in order to determine which blocks to download next and thus can't follow the
+            // finalized block.
+            best_block_policy: blocks_tree::BestBlockPolicy::MostPrimarySlots,
         };
 
         let chain = blocks_tree::NonFinalizedTree::new(blocks_tree_config.clone());
@@ -313,6 +363,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 ),
                 pending_encoded_justifications: Vec::new().into_iter(),
                 download_ahead_blocks: config.download_ahead_blocks,
+                probabilistic_finality_depth: config.probabilistic_finality_depth,
                 next_request_id: RequestId(0),
                 obsolete_requests: HashMap::with_capacity_and_hasher(0, Default::default()),
                 obsolete_requests_by_source: BTreeSet::new(),
@@ -398,6 +449,22 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         self.chain.iter_ancestry_order()
     }
 
+    /// Builds the signatures check of the given header, in order for its signatures to be
+    /// verified ahead of time.
+    ///
+    /// See [`blocks_tree::NonFinalizedTree::signatures_check`].
+    pub fn signatures_check(&self, header: header::HeaderRef) -> Option<precheck::SignaturesCheck> {
+        self.chain.signatures_check(header)
+    }
+
+    /// Stores signatures that have been verified ahead of time, so that they are no longer
+    /// verified when verifying the header they belong to.
+    ///
+    /// See [`OptimisticSync::signatures_check`].
+    pub fn insert_prechecked_signatures(&mut self, verified: precheck::VerifiedSignatures) {
+        self.chain.insert_prechecked_signatures(verified);
+    }
+
     /// Disassembles the state machine into its raw components.
     pub fn disassemble(self) -> Disassemble<TRq, TSrc> {
         Disassemble {
@@ -735,6 +802,17 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             });
         }
 
+        if self
+            .inner
+            .probabilistic_finality_target(&self.chain)
+            .is_some()
+        {
+            return ProcessOne::VerifyJustification(JustificationVerify {
+                chain: self.chain,
+                inner: self.inner,
+            });
+        }
+
         // The block isn't immediately extracted. A `Verify` struct is built, whose existence
         // confirms that a block is ready. If the `Verify` is dropped without `start` being called,
         // the block stays in the list.
@@ -786,6 +864,8 @@ pub enum ProcessOne<TRq, TSrc, TBl> {
 
     VerifyBlock(BlockVerify<TRq, TSrc, TBl>),
 
+    /// A justification is ready for verification, or some blocks are deep enough below the best
+    /// block to be finalized according to [`Config::probabilistic_finality_depth`].
     VerifyJustification(JustificationVerify<TRq, TSrc, TBl>),
 }
 
@@ -822,6 +902,12 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns the changes that must be applied to the storage of the finalized block in order
+    /// to obtain the storage of this block.
+    pub fn storage_diff(&self) -> &'a storage_diff::TrieDiff<TrieEntryVersion> {
+        &self.inner.inner.best_to_finalized_storage_diff
+    }
+
     pub fn prefix_keys_ordered<'k: 'a>(
         &'k self, // TODO: unclear lifetime
         prefix: &'k [u8],
@@ -832,6 +918,30 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             .best_to_finalized_storage_diff
             .storage_prefix_keys_ordered(prefix, in_finalized_ordered)
     }
+
+    /// Returns the key that immediately follows `key` in the storage, or `None` if there is no
+    /// such key.
+    ///
+    /// `in_finalized_next_key` is called with a key and must return the key that immediately
+    /// follows it in the storage of the finalized block.
+    pub fn next_key<'k>(
+        &'k self,
+        key: &'k [u8],
+        mut in_finalized_next_key: impl FnMut(&[u8]) -> Option<&'k [u8]>,
+    ) -> Option<&'k [u8]> {
+        let mut search = key;
+        loop {
+            match self
+                .inner
+                .inner
+                .best_to_finalized_storage_diff
+                .storage_next_key(search, in_finalized_next_key(search))
+            {
+                storage_diff::StorageNextKey::Found(found) => return found,
+                storage_diff::StorageNextKey::NextOf(next) => search = next,
+            }
+        }
+    }
 }
 
 /// Start the processing of a block verification.
@@ -1321,8 +1431,13 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
         OptimisticSync<TRq, TSrc, TBl>,
         JustificationVerification<TBl>,
     ) {
-        let (consensus_engine_id, justification, source_id) =
-            self.inner.pending_encoded_justifications.next().unwrap();
+        let Some((consensus_engine_id, justification, source_id)) =
+            self.inner.pending_encoded_justifications.next()
+        else {
+            // A `JustificationVerify` is built without any justification only if a block must
+            // be finalized according to `Config::probabilistic_finality_depth`.
+            return self.finalize_probabilistic();
+        };
 
         let mut apply = match self.chain.verify_justification(
             consensus_engine_id,
@@ -1404,6 +1519,54 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
             JustificationVerification::Finalized { finalized_blocks },
         )
     }
+
+    /// Finalizes the block indicated by
+    /// [`OptimisticSyncInner::probabilistic_finality_target`].
+    fn finalize_probabilistic(
+        mut self,
+    ) -> (
+        OptimisticSync<TRq, TSrc, TBl>,
+        JustificationVerification<TBl>,
+    ) {
+        let target_number = self
+            .inner
+            .probabilistic_finality_target(&self.chain)
+            .unwrap();
+
+        // Only blocks that become the new best block are ever inserted in the chain, meaning
+        // that the non-finalized blocks form a single chain and that there exists exactly one
+        // block per height.
+        let target_hash = self
+            .chain
+            .iter_unordered()
+            .find(|header| header.number == target_number)
+            .unwrap()
+            .hash(self.chain.block_number_bytes());
+
+        // See the comment in `perform` about the ordering of the finalized blocks.
+        let finalized_blocks = self
+            .chain
+            .set_finalized_block(&target_hash)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+
+        // `probabilistic_finality_target` returns `None` in full mode, meaning that there is no
+        // storage diff or runtime to update.
+        debug_assert!(self.inner.finalized_runtime.is_none());
+        self.inner.finalized_chain_information.chain_information =
+            self.chain.as_chain_information().into();
+
+        (
+            OptimisticSync {
+                chain: self.chain,
+                inner: self.inner,
+            },
+            JustificationVerification::Finalized { finalized_blocks },
+        )
+    }
 }
 
 /// Outcome of the verification of a justification.
@@ -1420,6 +1583,10 @@ pub enum JustificationVerification<TBl> {
 
     /// Processing of the justification is over. The best block has now been finalized.
     ///
+    /// If no justification was available, some blocks have been finalized according to
+    /// [`Config::probabilistic_finality_depth`]. In that situation, the best block isn't
+    /// necessarily finalized.
+    ///
     /// There might be more blocks remaining. Call [`OptimisticSync::process_one`] again.
     Finalized {
         /// Blocks that have been finalized.
@@ -32,6 +32,16 @@
 //! See the [`persisted_validation_data_parameters`] to obtain the input to pass to the runtime
 //! function. The first parameter is a `para_id` found in the chain specification of the
 //! parachain of parathread.
+//!
+//! # Elastic scaling
+//!
+//! A parachain can be assigned multiple cores of the relay chain, in which case multiple blocks
+//! of this parachain can be included in the same relay chain block. The persisted validation
+//! data only contains the latest of these blocks. The other blocks can be found by calling the
+//! `ParachainHost_candidate_events` runtime function (see [`CANDIDATE_EVENTS_FUNCTION_NAME`] and
+//! [`decode_candidate_events_return_value`]), then ordered using [`included_ancestors`].
+
+use alloc::vec::Vec;
 
 /// Produces the input to pass to the `ParachainHost_persisted_validation_data` runtime call.
 pub fn persisted_validation_data_parameters(
@@ -77,9 +87,10 @@ pub fn decode_persisted_validation_data_return_value(
     scale_encoded: &[u8],
     block_number_bytes: usize,
 ) -> Result<Option<PersistedValidationDataRef>, Error> {
-    let res: Result<_, nom::Err<nom::error::Error<_>>> = nom::combinator::all_consuming(
-        crate::util::nom_option_decode(persisted_validation_data(block_number_bytes)),
-    )(scale_encoded);
+    let res: Result<_, nom::Err<nom::error::Error<_>>> =
+        nom::combinator::all_consuming(nom::combinator::complete(crate::util::nom_option_decode(
+            persisted_validation_data(block_number_bytes),
+        )))(scale_encoded);
     match res {
         Ok((_, data)) => Ok(data),
         Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error(err.code)),
@@ -87,6 +98,103 @@ pub fn decode_persisted_validation_data_return_value(
     }
 }
 
+/// Name of the runtime function to call in order to obtain the list of candidate events of a
+/// relay chain block.
+pub const CANDIDATE_EVENTS_FUNCTION_NAME: &str = "ParachainHost_candidate_events";
+
+/// Attempt to decode the return value of the `ParachainHost_candidate_events` runtime call.
+pub fn decode_candidate_events_return_value(
+    scale_encoded: &[u8],
+) -> Result<Vec<CandidateEventRef>, Error> {
+    let res: Result<_, nom::Err<nom::error::Error<_>>> =
+        nom::combinator::all_consuming(nom::combinator::complete(nom::combinator::flat_map(
+            crate::util::nom_scale_compact_usize,
+            |num_elems| nom::multi::many_m_n(num_elems, num_elems, candidate_event),
+        )))(scale_encoded);
+    match res {
+        Ok((_, events)) => Ok(events),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(Error(err.code)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Returns the head data of the blocks of the parachain that have been included in a relay
+/// chain block and that are ancestors of `parent_head`, ordered from the oldest to the newest.
+///
+/// `parent_head` must be the head data found in the persisted validation data of the relay chain
+/// block, and `events` the list of candidate events of this same relay chain block.
+///
+/// The head data of the parachain are assumed to be block headers, which is the case for all the
+/// parachains built on top of Cumulus. Candidates whose head data can't be decoded, that don't
+/// belong to the given parachain, or that aren't ancestors of `parent_head` are ignored. The
+/// returned list never contains duplicates.
+pub fn included_ancestors<'a>(
+    para_id: u32,
+    parent_head: &[u8],
+    events: &[CandidateEventRef<'a>],
+    block_number_bytes: usize,
+) -> Vec<&'a [u8]> {
+    let mut candidates = events
+        .iter()
+        .filter(|ev| ev.kind == CandidateEventKind::Included && ev.para_id == para_id)
+        .map(|ev| {
+            (
+                crate::header::hash_from_scale_encoded_header(ev.head_data),
+                ev.head_data,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    let mut current = parent_head;
+    loop {
+        let Ok(decoded) = crate::header::decode(current, block_number_bytes) else {
+            break;
+        };
+
+        // Removing the candidate from the list guarantees that the loop ends even if the
+        // candidates form a cycle, and that the output contains no duplicate.
+        let Some(position) = candidates
+            .iter()
+            .position(|(hash, _)| hash == decoded.parent_hash)
+        else {
+            break;
+        };
+
+        let (_, head_data) = candidates.swap_remove(position);
+        out.push(head_data);
+        current = head_data;
+    }
+
+    out.reverse();
+    out
+}
+
+/// Decoded candidate event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CandidateEventRef<'a> {
+    /// What happened to the candidate.
+    pub kind: CandidateEventKind,
+    /// Identifier of the parachain the candidate belongs to.
+    pub para_id: u32,
+    /// Head data of the parachain after the candidate. For parachains built on top of Cumulus,
+    /// this is the header of the block of the candidate.
+    pub head_data: &'a [u8],
+    /// Index of the core of the relay chain the candidate occupies.
+    pub core_index: u32,
+}
+
+/// See [`CandidateEventRef::kind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CandidateEventKind {
+    /// The candidate has been backed and is now pending availability.
+    Backed,
+    /// The candidate has been made available and included in the relay chain.
+    Included,
+    /// The candidate has timed out before having been made available.
+    TimedOut,
+}
+
 /// Error that can happen during the decoding.
 #[derive(Debug, derive_more::Display)]
 #[display(fmt = "Error decoding persisted validation data")]
@@ -131,6 +239,73 @@ fn persisted_validation_data<'a, E: nom::error::ParseError<&'a [u8]>>(
     )
 }
 
+/// `Nom` combinator that parses a [`CandidateEventRef`].
+fn candidate_event<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&[u8], CandidateEventRef, E> {
+    // The candidate receipt consists in a candidate descriptor of 292 bytes starting with the
+    // parachain identifier, followed with the 32 bytes hash of the candidate commitments.
+    let receipt = || {
+        nom::sequence::terminated(
+            nom::number::complete::le_u32,
+            nom::bytes::complete::take(288u32 + 32),
+        )
+    };
+
+    nom::branch::alt((
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[0]),
+                nom::sequence::tuple((
+                    receipt(),
+                    crate::util::nom_bytes_decode,
+                    nom::number::complete::le_u32,
+                    nom::number::complete::le_u32,
+                )),
+            ),
+            |(para_id, head_data, core_index, _group_index)| CandidateEventRef {
+                kind: CandidateEventKind::Backed,
+                para_id,
+                head_data,
+                core_index,
+            },
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[1]),
+                nom::sequence::tuple((
+                    receipt(),
+                    crate::util::nom_bytes_decode,
+                    nom::number::complete::le_u32,
+                    nom::number::complete::le_u32,
+                )),
+            ),
+            |(para_id, head_data, core_index, _group_index)| CandidateEventRef {
+                kind: CandidateEventKind::Included,
+                para_id,
+                head_data,
+                core_index,
+            },
+        ),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[2]),
+                nom::sequence::tuple((
+                    receipt(),
+                    crate::util::nom_bytes_decode,
+                    nom::number::complete::le_u32,
+                )),
+            ),
+            |(para_id, head_data, core_index)| CandidateEventRef {
+                kind: CandidateEventKind::TimedOut,
+                para_id,
+                head_data,
+                core_index,
+            },
+        ),
+    ))(bytes)
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -177,4 +352,66 @@ mod tests {
             expected
         );
     }
+
+    #[test]
+    fn included_ancestors_ordered() {
+        use crate::header;
+        use alloc::vec::Vec;
+
+        let make_header = |parent_hash: &[u8; 32], number: u64| {
+            header::HeaderRef {
+                parent_hash,
+                number,
+                state_root: &[0; 32],
+                extrinsics_root: &[0; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4)
+        };
+
+        let block1 = make_header(&[0; 32], 1);
+        let block2 = make_header(&header::hash_from_scale_encoded_header(&block1), 2);
+        let block3 = make_header(&header::hash_from_scale_encoded_header(&block2), 3);
+        let block4 = make_header(&header::hash_from_scale_encoded_header(&block3), 4);
+
+        // Events are intentionally not ordered, and contain duplicates and events that must
+        // be ignored.
+        let mut encoded = Vec::new();
+        let events = [
+            (1u8, 2000u32, &block4, 2u32),
+            (1, 2000, &block2, 0),
+            (0, 2000, &block1, 3),
+            (1, 2001, &block1, 4),
+            (1, 2000, &block3, 1),
+            (1, 2000, &block2, 5),
+        ];
+        encoded.extend_from_slice(crate::util::encode_scale_compact_usize(events.len()).as_ref());
+        for (kind, para_id, head, core_index) in events {
+            encoded.push(kind);
+            encoded.extend_from_slice(&para_id.to_le_bytes());
+            encoded.extend_from_slice(&[0; 288 + 32]);
+            encoded.extend_from_slice(crate::util::encode_scale_compact_usize(head.len()).as_ref());
+            encoded.extend_from_slice(head);
+            encoded.extend_from_slice(&core_index.to_le_bytes());
+            encoded.extend_from_slice(&0u32.to_le_bytes());
+        }
+
+        let decoded = super::decode_candidate_events_return_value(&encoded).unwrap();
+        assert_eq!(decoded.len(), 6);
+        assert_eq!(decoded[2].kind, super::CandidateEventKind::Backed);
+        assert_eq!(decoded[3].para_id, 2001);
+        assert_eq!(decoded[4].core_index, 1);
+
+        assert_eq!(
+            super::included_ancestors(2000, &block4, &decoded, 4),
+            [&block2[..], &block3[..]]
+        );
+        assert!(super::included_ancestors(2000, &block2, &decoded, 4).is_empty());
+    }
+
+    #[test]
+    fn truncated_input() {
+        assert!(super::decode_persisted_validation_data_return_value(&[1, 233], 4).is_err());
+        assert!(super::decode_candidate_events_return_value(&[4, 1, 0, 0]).is_err());
+    }
 }
@@ -58,10 +58,14 @@
 //!
 //! - Downloading a warp sync proof from a source. This proof contains a list of *fragments*. Each
 //! fragment represents a change in the list of Grandpa authorities, and a list of signatures of
-//! the previous authorities that certify that this change is correct.
+//! the previous authorities that certify that this change is correct. The proof is requested
+//! from several sources at the same time (see [`Config::max_parallel_fragments_requests`]), so
+//! that a source that is slow to answer doesn't stall the warp syncing.
 //! - Verifying the fragments. Each fragment that is successfully verified progresses towards
 //! towards the head of the chain. Even if one fragment is invalid, all the previously-verified
-//! fragments can still be kept, and the warp syncing can resume from there.
+//! fragments can still be kept, and the warp syncing can resume from there. The proofs sent by
+//! the other sources are kept aside while a proof is being verified, and are verified in turn
+//! if the verification fails, without having to download anything again.
 //! - Downloading from a source the runtime code of the final block of the proof.
 //! - Performing some runtime calls in order to obtain the current consensus-related parameters
 //! of the chain. This might require obtaining some storage items, in which case they must also
@@ -70,6 +74,11 @@
 //! At the end of the syncing, a [`ValidChainInformation`] corresponding to the head of the chain
 //! is yielded.
 //!
+//! The progress of the verification of the fragments can be obtained at any time through
+//! [`InProgressWarpSync::verified_progress`], and passed back later through
+//! [`Config::resume_progress`] in order to not have to download and verify these fragments again,
+//! for example after a restart.
+//!
 //! # Usage
 //!
 //! Use the [`start_warp_sync()`] function to start a Grandpa warp syncing state machine.
@@ -111,7 +120,7 @@ use alloc::{
     vec,
     vec::Vec,
 };
-use core::{iter, mem, ops};
+use core::{iter, mem, num::NonZeroU32, ops};
 
 pub use warp_sync::{Error as FragmentError, WarpSyncFragment};
 
@@ -155,6 +164,40 @@ pub struct Config {
 
     /// The initial capacity of the list of requests.
     pub requests_capacity: usize,
+
+    /// Maximum number of warp sync requests starting from the same block to perform
+    /// simultaneously, each towards a different source.
+    ///
+    /// A value of 1 means that a source that doesn't answer blocks the warp syncing until its
+    /// request times out. Values higher than 1 cost more bandwidth.
+    pub max_parallel_fragments_requests: NonZeroU32,
+
+    /// If `Some`, the verification of the fragments resumes from the given progress, as
+    /// previously returned by [`InProgressWarpSync::verified_progress`], instead of starting
+    /// from [`Config::start_chain_information`].
+    ///
+    /// Ignored if the block of the progress isn't strictly higher than the finalized block of
+    /// [`Config::start_chain_information`].
+    ///
+    /// > **Note**: The progress isn't verified in any way. It must come from a trusted source.
+    pub resume_progress: Option<VerifiedProgress>,
+}
+
+/// Progress of the verification of the warp sync fragments.
+///
+/// See [`InProgressWarpSync::verified_progress`] and [`Config::resume_progress`].
+#[derive(Debug, Clone)]
+pub struct VerifiedProgress {
+    /// Header of the highest block whose finality has been verified.
+    pub header: Header,
+
+    /// Identifier of the GrandPa authorities set in charge of finalizing the children of
+    /// [`VerifiedProgress::header`].
+    pub grandpa_authorities_set_id: u64,
+
+    /// List of GrandPa authorities in charge of finalizing the children of
+    /// [`VerifiedProgress::header`].
+    pub grandpa_authorities: Vec<header::GrandpaAuthority>,
 }
 
 /// Initializes the warp sync state machine.
@@ -183,13 +226,39 @@ pub fn start_warp_sync<TSrc, TRq>(
         }
     }
 
+    let previous_verifier_values = config
+        .resume_progress
+        .filter(|progress| {
+            progress.header.number
+                > config
+                    .start_chain_information
+                    .as_ref()
+                    .finalized_block_header
+                    .number
+        })
+        .map(|progress| {
+            (
+                progress.header,
+                ChainInformationFinality::Grandpa {
+                    after_finalized_block_authorities_set_id: progress.grandpa_authorities_set_id,
+                    finalized_triggered_authorities: progress.grandpa_authorities,
+                    finalized_scheduled_change: None,
+                },
+            )
+        });
+
     Ok(InProgressWarpSync {
         start_chain_information: config.start_chain_information,
         block_number_bytes: config.block_number_bytes,
         sources: slab::Slab::with_capacity(config.sources_capacity),
         in_progress_requests: slab::Slab::with_capacity(config.requests_capacity),
+        max_parallel_fragments_requests: usize::try_from(
+            config.max_parallel_fragments_requests.get(),
+        )
+        .unwrap_or(usize::max_value()),
+        backup_responses: Vec::new(),
         phase: Phase::DownloadFragments {
-            previous_verifier_values: None,
+            previous_verifier_values,
         },
     })
 }
@@ -270,6 +339,25 @@ pub struct InProgressWarpSync<TSrc, TRq> {
     sources: slab::Slab<Source<TSrc>>,
     /// List of requests that have been added using [`InProgressWarpSync::add_request`].
     in_progress_requests: slab::Slab<(SourceId, TRq, RequestDetail)>,
+    /// Value of [`Config::max_parallel_fragments_requests`].
+    max_parallel_fragments_requests: usize,
+    /// Warp sync responses that have been received while the response of another source starting
+    /// from the same block was being verified. If the verification fails, one of these responses
+    /// is verified next rather than downloading the fragments again.
+    ///
+    /// Only ever contains responses that start from the block of the fragments currently being
+    /// verified, and that are different from each other and from the ones being verified.
+    backup_responses: Vec<BackupResponse>,
+}
+
+/// See [`InProgressWarpSync::backup_responses`].
+struct BackupResponse {
+    /// Source the fragments have been obtained from.
+    source_id: SourceId,
+    /// Same as [`Phase::PendingVerify::final_set_of_fragments`].
+    final_set_of_fragments: bool,
+    /// Fragments as sent by the source.
+    fragments: Vec<WarpSyncFragment>,
 }
 
 enum Phase {
@@ -375,6 +463,21 @@ pub enum Status<'a, TSrc> {
     },
 }
 
+/// See [`InProgressWarpSync::stage`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Warp sync fragments are being downloaded or verified.
+    Fragments,
+    /// All fragments have been verified, and the runtime of the finalized block is being
+    /// downloaded.
+    RuntimeDownload,
+    /// The runtime of the finalized block has been downloaded and is waiting to be compiled.
+    RuntimeCompilation,
+    /// The runtime has been compiled, and the information about the chain is being downloaded
+    /// and built.
+    ChainInformationDownload,
+}
+
 impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
     /// Returns the value that was initially passed in [`Config::block_number_bytes`].
     pub fn block_number_bytes(&self) -> usize {
@@ -391,6 +494,82 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
         (&self.start_chain_information).into()
     }
 
+    /// Returns the highest block whose finality has been verified, and the GrandPa authorities
+    /// in charge of finalizing its children.
+    ///
+    /// Returns `None` if no progress has been made compared to the starting point of the warp
+    /// syncing.
+    ///
+    /// The value can later be passed back through [`Config::resume_progress`].
+    pub fn verified_progress(&self) -> Option<VerifiedProgress> {
+        let (header, finality) = match &self.phase {
+            Phase::DownloadFragments {
+                previous_verifier_values,
+            }
+            | Phase::PendingVerify {
+                previous_verifier_values,
+                ..
+            } => {
+                let (header, finality) = previous_verifier_values.as_ref()?;
+                (header, finality)
+            }
+            Phase::RuntimeDownload {
+                header,
+                chain_information_finality,
+                ..
+            }
+            | Phase::ChainInformationDownload {
+                header,
+                chain_information_finality,
+                ..
+            } => (header, chain_information_finality),
+        };
+
+        // The header is the starting point if the warp sync proof was empty.
+        if header.number
+            <= self
+                .start_chain_information
+                .as_ref()
+                .finalized_block_header
+                .number
+        {
+            return None;
+        }
+
+        match finality {
+            ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                ..
+            } => Some(VerifiedProgress {
+                header: header.clone(),
+                grandpa_authorities_set_id: *after_finalized_block_authorities_set_id,
+                grandpa_authorities: finalized_triggered_authorities.clone(),
+            }),
+            // Warp syncing only supports GrandPa.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the stage the warp syncing is currently at.
+    ///
+    /// Contrary to [`InProgressWarpSync::status`], this distinguishes between downloading and
+    /// compiling the runtime.
+    pub fn stage(&self) -> Stage {
+        match self.phase {
+            Phase::DownloadFragments { .. } | Phase::PendingVerify { .. } => Stage::Fragments,
+            Phase::RuntimeDownload {
+                downloaded_runtime: None,
+                ..
+            } => Stage::RuntimeDownload,
+            Phase::RuntimeDownload {
+                downloaded_runtime: Some(_),
+                ..
+            } => Stage::RuntimeCompilation,
+            Phase::ChainInformationDownload { .. } => Stage::ChainInformationDownload,
+        }
+    }
+
     /// Returns the current status of the warp syncing.
     pub fn status(&self) -> Status<TSrc> {
         match self.phase {
@@ -531,10 +710,15 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
             if *downloaded_source == to_remove {
                 self.phase = Phase::DownloadFragments {
                     previous_verifier_values: previous_verifier_values.take(),
-                }
+                };
             }
         }
 
+        // Note that backup responses only exist while fragments are being verified, in which
+        // case the verification has been interrupted above without any progress.
+        self.backup_responses.retain(|r| r.source_id != to_remove);
+        self.verify_backup_response();
+
         let obsolete_requests_indices = self
             .in_progress_requests
             .iter()
@@ -574,23 +758,29 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
                     .hash(self.block_number_bytes),
             };
 
+            // Sources that are already being asked for the fragments starting at the same block.
             // TODO: O(n)
-            if !self
+            let sources_in_progress = self
                 .in_progress_requests
                 .iter()
-                .any(|(_, (_, _, rq))| match rq {
+                .filter_map(|(_, (src_id, _, rq))| match rq {
                     RequestDetail::WarpSyncRequest { block_hash }
                         if *block_hash == start_block_hash =>
                     {
-                        true
+                        Some(*src_id)
                     }
-                    _ => false,
+                    _ => None,
                 })
-            {
-                // Combine the request with every single available source.
+                .collect::<Vec<_>>();
+
+            if sources_in_progress.len() < self.max_parallel_fragments_requests {
+                // Combine the request with every single available source that isn't already
+                // being asked for these fragments.
                 either::Left(self.sources.iter().filter_map(move |(src_id, src)| {
                     // TODO: also filter by source finalized block? so that we don't request from sources below us
-                    if all_sources_already_tried || !src.already_tried {
+                    if (all_sources_already_tried || !src.already_tried)
+                        && !sources_in_progress.contains(&SourceId(src_id))
+                    {
                         Some((
                             SourceId(src_id),
                             &src.user_data,
@@ -954,6 +1144,47 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
 
                 user_data
             }
+            (
+                (rq_source_id, user_data, RequestDetail::WarpSyncRequest { block_hash }),
+                Phase::PendingVerify {
+                    previous_verifier_values,
+                    downloaded_source,
+                    verifier,
+                    ..
+                },
+            ) => {
+                let verified_block_hash = match previous_verifier_values.as_ref() {
+                    Some((header, _)) => header.hash(self.block_number_bytes),
+                    None => self
+                        .start_chain_information
+                        .as_ref()
+                        .finalized_block_header
+                        .hash(self.block_number_bytes),
+                };
+
+                // Uninteresting request. We downloaded fragments from the wrong starting point.
+                if verified_block_hash != block_hash || rq_source_id == *downloaded_source {
+                    return user_data;
+                }
+
+                // The response is compared with the ones that are already known. Honest sources
+                // are expected to send back the same fragments, in which case there is no point
+                // in verifying them multiple times.
+                let is_duplicate = verifier.as_ref().unwrap().fragments() == &fragments[..]
+                    || self
+                        .backup_responses
+                        .iter()
+                        .any(|r| r.fragments == fragments);
+                if !is_duplicate {
+                    self.backup_responses.push(BackupResponse {
+                        source_id: rq_source_id,
+                        final_set_of_fragments,
+                        fragments,
+                    });
+                }
+
+                user_data
+            }
             ((_, user_data, RequestDetail::WarpSyncRequest { .. }), _) => {
                 // Uninteresting download. We simply ignore the response.
                 user_data
@@ -962,6 +1193,46 @@ impl<TSrc, TRq> InProgressWarpSync<TSrc, TRq> {
         }
     }
 
+    /// If the fragments are being downloaded and a backup response is available, switches to
+    /// verifying it.
+    ///
+    /// Must only be called after the verification of some fragments has been interrupted without
+    /// any progress, as the backup responses start from the same block as these fragments.
+    fn verify_backup_response(&mut self) {
+        let Phase::DownloadFragments {
+            previous_verifier_values,
+        } = &mut self.phase
+        else {
+            return;
+        };
+
+        let Some(backup) = self.backup_responses.pop() else {
+            return;
+        };
+
+        let verifier = match &previous_verifier_values {
+            Some((_, chain_information_finality)) => warp_sync::Verifier::new(
+                chain_information_finality.into(),
+                self.block_number_bytes,
+                backup.fragments,
+                backup.final_set_of_fragments,
+            ),
+            None => warp_sync::Verifier::new(
+                self.start_chain_information.as_ref().finality,
+                self.block_number_bytes,
+                backup.fragments,
+                backup.final_set_of_fragments,
+            ),
+        };
+
+        self.phase = Phase::PendingVerify {
+            previous_verifier_values: previous_verifier_values.take(),
+            final_set_of_fragments: backup.final_set_of_fragments,
+            downloaded_source: backup.source_id,
+            verifier: Some(verifier),
+        };
+    }
+
     /// Start processing one CPU operation.
     ///
     /// This function takes ownership of `self` and yields it back after the operation is finished.
@@ -1096,6 +1367,16 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
         }
     }
 
+    /// Returns the fragment that is about to be verified, or `None` if the proof sent by the
+    /// source is empty.
+    pub fn fragment(&self) -> Option<&WarpSyncFragment> {
+        if let Phase::PendingVerify { verifier, .. } = &self.inner.phase {
+            verifier.as_ref().unwrap().next_fragment()
+        } else {
+            unreachable!()
+        }
+    }
+
     /// Verify one warp sync fragment.
     ///
     /// Must be passed a randomly-generated value that is used by the verification process. Note
@@ -1117,6 +1398,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                     *verifier = Some(next_verifier);
                 }
                 Ok(warp_sync::Next::EmptyProof) => {
+                    self.inner.backup_responses.clear();
                     self.inner.phase = Phase::RuntimeDownload {
                         header: self
                             .inner
@@ -1138,6 +1420,10 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                     scale_encoded_header,
                     chain_information_finality,
                 }) => {
+                    // The backup responses start from the same block as the fragments that have
+                    // just been verified, and are thus obsolete.
+                    self.inner.backup_responses.clear();
+
                     // As the verification of the fragment has succeeded, we are sure that the header
                     // is valid and can decode it.
                     let header: Header =
@@ -1162,6 +1448,8 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                     self.inner.phase = Phase::DownloadFragments {
                         previous_verifier_values: previous_verifier_values.take(),
                     };
+                    // Fall back to the fragments sent by another source, if any.
+                    self.inner.verify_backup_response();
                     return (self.inner, Some(error));
                 }
             }
@@ -1285,6 +1573,7 @@ impl<TSrc, TRq> BuildRuntime<TSrc, TRq> {
                 heap_pages: decoded_heap_pages,
                 exec_hint,
                 allow_unresolved_imports,
+                fuel_limit: None,
             }) {
                 Ok(runtime) => runtime,
                 Err(err) => {
@@ -336,6 +336,8 @@ pub fn validate_transaction(
                 storage_main_trie_changes: storage_diff::TrieDiff::empty(),
                 offchain_storage_changes: storage_diff::TrieDiff::empty(),
                 max_log_level: config.max_log_level,
+                offchain_behavior: runtime_host::OffchainBehavior::Deny,
+                tracing: false,
             });
 
             // Information used later, after `Core_initialize_block` is done.
@@ -373,6 +375,8 @@ pub fn validate_transaction(
                 storage_main_trie_changes: storage_diff::TrieDiff::empty(),
                 offchain_storage_changes: storage_diff::TrieDiff::empty(),
                 max_log_level: config.max_log_level,
+                offchain_behavior: runtime_host::OffchainBehavior::Deny,
+                tracing: false,
             });
 
             match vm {
@@ -465,6 +469,8 @@ impl Query {
                             success.main_trie_root_calculation_cache,
                         ),
                         max_log_level: 0,
+                        offchain_behavior: runtime_host::OffchainBehavior::Deny,
+                        tracing: false,
                     });
 
                     match vm {
@@ -492,6 +498,10 @@ impl Query {
                     inner = sig.verify_and_resume();
                     continue;
                 }
+                runtime_host::RuntimeHostVm::Offchain(_) => {
+                    // Off-chain host functions are configured to be denied.
+                    unreachable!()
+                }
             };
         }
     }
@@ -550,6 +560,10 @@ impl Query {
                     inner = sig.verify_and_resume();
                     continue;
                 }
+                runtime_host::RuntimeHostVm::Offchain(_) => {
+                    // Off-chain host functions are configured to be denied.
+                    unreachable!()
+                }
             };
         }
     }
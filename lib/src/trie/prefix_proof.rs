@@ -24,13 +24,18 @@
 //! the requested prefix, plus one. For example, if a tree has the nodes `[1, 5]`, `[1, 5, 8, 9]`,
 //! and `[1, 5, 8, 9, 2]`, then four queries are necessary to find all the keys whose prefix
 //! is `[1]`.
+//!
+//! The scan can optionally be restricted to the keys that are strictly superior to a certain
+//! start key, and to a maximum number of keys. This makes it possible to enumerate a large number
+//! of keys page by page, where the last key of a page is used as the start key of the next page.
+//! Trie nodes that can't possibly lead to keys that are part of the result aren't queried.
 
 // TODO: usage example
 
 use super::{nibble, proof_decode};
 
 use alloc::{borrow::ToOwned as _, vec, vec::Vec};
-use core::{fmt, iter, mem};
+use core::{fmt, iter, mem, num::NonZeroUsize};
 
 mod tests;
 
@@ -43,12 +48,31 @@ pub struct Config<'a> {
     ///
     /// > **Note**: The Merkle value and node value are always the same for the root node.
     pub trie_root_hash: [u8; 32],
+
+    /// If `true`, the proofs are allowed to contain entries that are disconnected from the root
+    /// node of the trie. Must be `true` when scanning a child trie, as proofs concerning child
+    /// tries also contain entries of the main trie.
+    ///
+    /// See [`proof_decode::decode_and_verify_proof_allow_unused`].
+    pub allow_unused_proof_entries: bool,
+
+    /// If `Some`, only the keys strictly superior to this key are returned.
+    pub start_key: Option<&'a [u8]>,
+
+    /// If `Some`, only the given number of keys are returned. These keys are the lowest keys, in
+    /// lexicographic order, that match the other criteria.
+    pub max_keys: Option<NonZeroUsize>,
 }
 
 /// Start a new scanning process.
 pub fn prefix_scan(config: Config<'_>) -> PrefixScan {
     PrefixScan {
         trie_root_hash: config.trie_root_hash,
+        allow_unused_proof_entries: config.allow_unused_proof_entries,
+        start_key: config
+            .start_key
+            .map(|k| nibble::bytes_to_nibbles(k.iter().copied()).collect()),
+        max_keys: config.max_keys,
         next_queries: vec![(
             nibble::bytes_to_nibbles(config.prefix.iter().copied()).collect(),
             QueryTy::Exact,
@@ -60,6 +84,11 @@ pub fn prefix_scan(config: Config<'_>) -> PrefixScan {
 /// Scan of a prefix in progress.
 pub struct PrefixScan {
     trie_root_hash: [u8; 32],
+    allow_unused_proof_entries: bool,
+    /// See [`Config::start_key`].
+    start_key: Option<Vec<nibble::Nibble>>,
+    /// See [`Config::max_keys`].
+    max_keys: Option<NonZeroUsize>,
     // TODO: we have lots of Vecs here; maybe find a way to optimize
     next_queries: Vec<(Vec<nibble::Nibble>, QueryTy)>,
     // TODO: we have lots of Vecs here; maybe find a way to optimize
@@ -90,10 +119,15 @@ impl PrefixScan {
     ///
     /// Returns an error if the proof is invalid. In that case, `self` isn't modified.
     pub fn resume(mut self, proof: &[u8]) -> Result<ResumeOutcome, (Self, Error)> {
-        let decoded_proof = match proof_decode::decode_and_verify_proof(proof_decode::Config {
+        let config = proof_decode::Config {
             proof,
             trie_root_hash: &self.trie_root_hash,
-        }) {
+        };
+        let decoded_proof = match if self.allow_unused_proof_entries {
+            proof_decode::decode_and_verify_proof_allow_unused(config)
+        } else {
+            proof_decode::decode_and_verify_proof(config)
+        } {
             Ok(d) => d,
             Err(err) => return Err((self, Error::InvalidProof(err))),
         };
@@ -185,7 +219,13 @@ impl PrefixScan {
 
                     // Insert in final results, making sure we check for duplicates.
                     debug_assert!(!self.final_result.iter().any(|n| *n == key));
-                    self.final_result.push(key);
+                    if self
+                        .start_key
+                        .as_ref()
+                        .map_or(true, |start_key| query_key > *start_key)
+                    {
+                        self.final_result.push(key);
+                    }
                 }
 
                 // For each child of the node, put into `next` the key that goes towards this
@@ -207,11 +247,36 @@ impl PrefixScan {
                 }
             }
 
+            // If the maximum number of keys has been reached, only keep the lowest keys and
+            // determine the highest key that can still be part of the result.
+            let max_key = match self.max_keys {
+                Some(max_keys) if self.final_result.len() >= max_keys.get() => {
+                    self.final_result.sort_unstable();
+                    self.final_result.truncate(max_keys.get());
+                    Some(
+                        nibble::bytes_to_nibbles(self.final_result.last().unwrap().iter().copied())
+                            .collect::<Vec<_>>(),
+                    )
+                }
+                _ => None,
+            };
+
+            // Discard the queries that can't lead to any key that is part of the result.
+            if self.start_key.is_some() || max_key.is_some() {
+                let start_key = self.start_key.as_deref();
+                next.retain(|(query_key, _)| {
+                    is_query_needed(query_key, start_key, max_key.as_deref())
+                });
+                self.next_queries.retain(|(query_key, _)| {
+                    is_query_needed(query_key, start_key, max_key.as_deref())
+                });
+            }
+
             // Finished when nothing more to request.
             if next.is_empty() && self.next_queries.is_empty() {
-                return Ok(ResumeOutcome::Success {
-                    keys: self.final_result,
-                });
+                let mut keys = self.final_result;
+                keys.sort_unstable();
+                return Ok(ResumeOutcome::Success { keys });
             }
 
             // If we have failed to make any progress during this iteration, return `InProgress`.
@@ -230,6 +295,31 @@ impl PrefixScan {
     }
 }
 
+/// Returns `false` if none of the keys that start with `query_key` can be both strictly superior
+/// to `start_key` and inferior or equal to `max_key`.
+fn is_query_needed(
+    query_key: &[nibble::Nibble],
+    start_key: Option<&[nibble::Nibble]>,
+    max_key: Option<&[nibble::Nibble]>,
+) -> bool {
+    // If `query_key` is inferior to `start_key` without being a prefix of it, then all the keys
+    // that start with `query_key` are inferior to `start_key` as well.
+    if let Some(start_key) = start_key {
+        if query_key < start_key && !start_key.starts_with(query_key) {
+            return false;
+        }
+    }
+
+    // All the keys that start with `query_key` are superior or equal to `query_key`.
+    if let Some(max_key) = max_key {
+        if query_key > max_key {
+            return false;
+        }
+    }
+
+    true
+}
+
 impl fmt::Debug for PrefixScan {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("PrefixScan").finish()
@@ -243,7 +333,7 @@ pub enum ResumeOutcome {
     InProgress(PrefixScan),
     /// Scan has succeeded.
     Success {
-        /// List of keys with the requested prefix.
+        /// List of keys with the requested prefix, in lexicographic order.
         keys: Vec<Vec<u8>>,
     },
 }
@@ -17,7 +17,12 @@
 
 #![cfg(test)]
 
-use super::{prefix_scan, Config, ResumeOutcome};
+use super::{
+    super::{nibble, proof_encode, trie_node, trie_structure},
+    prefix_scan, Config, ResumeOutcome,
+};
+use alloc::{vec, vec::Vec};
+use core::{array, num::NonZeroUsize};
 
 // TODO: more tests
 
@@ -14491,6 +14496,9 @@ fn regression_test_174() {
     let mut prefix_scan = prefix_scan(Config {
         prefix: REQUESTED,
         trie_root_hash: STATE_TRIE_ROOT,
+        allow_unused_proof_entries: false,
+        start_key: None,
+        max_keys: None,
     });
 
     for proof in PROOFS {
@@ -14510,3 +14518,91 @@ fn regression_test_174() {
         }
     }
 }
+
+#[test]
+fn start_key_and_max_keys() {
+    const PREFIX: &[u8] = &[0xab, 0xcd];
+
+    // Build a trie containing twelve keys that start with `PREFIX`, plus a few keys that don't.
+    let mut expected = (0..12u8)
+        .map(|n| {
+            let mut key = PREFIX.to_vec();
+            key.extend_from_slice(&[n * 17, n]);
+            key
+        })
+        .collect::<Vec<_>>();
+    expected.sort();
+
+    let mut trie = trie_structure::TrieStructure::new();
+    for key in expected
+        .iter()
+        .cloned()
+        .chain([vec![0xab], vec![0xab, 0xce, 0x1], vec![0x12, 0x34]])
+    {
+        match trie.node(nibble::bytes_to_nibbles(key.into_iter())) {
+            trie_structure::Entry::Vacant(e) => {
+                e.insert_storage_value().insert((), ());
+            }
+            trie_structure::Entry::Occupied(trie_structure::NodeAccess::Branch(e)) => {
+                e.insert_storage_value();
+            }
+            trie_structure::Entry::Occupied(trie_structure::NodeAccess::Storage(_)) => {}
+        }
+    }
+
+    // Build a proof containing the entire trie.
+    let mut proof_builder = proof_encode::ProofBuilder::new();
+    for node_index in trie.iter_unordered().collect::<Vec<_>>() {
+        let key = trie
+            .node_full_key_by_index(node_index)
+            .unwrap()
+            .collect::<Vec<_>>();
+        let node = trie.node_by_index(node_index).unwrap();
+        let partial_key = node.partial_key().collect::<Vec<_>>();
+        let node_value = trie_node::encode_to_vec(trie_node::Decoded {
+            children: array::from_fn(|nibble| {
+                let nibble = nibble::Nibble::try_from(u8::try_from(nibble).unwrap()).unwrap();
+                node.child_user_data(nibble).map(|_| &[][..])
+            }),
+            partial_key: partial_key.into_iter(),
+            storage_value: if node.has_storage_value() {
+                trie_node::StorageValue::Unhashed(&[1, 2, 3])
+            } else {
+                trie_node::StorageValue::None
+            },
+        })
+        .unwrap();
+        proof_builder.set_node_value(&key, &node_value, None);
+    }
+    proof_builder.make_coherent();
+    let trie_root_hash = proof_builder.trie_root_hash().unwrap();
+    let proof = proof_builder.build_to_vec();
+
+    let scan = |start_key: Option<&[u8]>, max_keys: Option<usize>| {
+        let mut prefix_scan = prefix_scan(Config {
+            prefix: PREFIX,
+            trie_root_hash,
+            allow_unused_proof_entries: false,
+            start_key,
+            max_keys: max_keys.map(|n| NonZeroUsize::new(n).unwrap()),
+        });
+
+        loop {
+            match prefix_scan.resume(&proof) {
+                Ok(ResumeOutcome::InProgress(scan)) => prefix_scan = scan,
+                Ok(ResumeOutcome::Success { keys }) => return keys,
+                Err((_, err)) => panic!("{err:?}"),
+            }
+        }
+    };
+
+    assert_eq!(scan(None, None), expected);
+    assert_eq!(scan(None, Some(5)), expected[..5]);
+    assert_eq!(scan(Some(&expected[3]), None), expected[4..]);
+    assert_eq!(scan(Some(&expected[3]), Some(5)), expected[4..9]);
+    assert_eq!(scan(Some(PREFIX), Some(2)), expected[..2]);
+    assert_eq!(
+        scan(Some(expected.last().unwrap()), Some(5)),
+        Vec::<Vec<u8>>::new()
+    );
+}
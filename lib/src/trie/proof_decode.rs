@@ -64,6 +64,31 @@ pub struct Config<'a, I> {
 /// Returns an error if the proof is invalid, or if the proof contains entries that are
 /// disconnected from the root node of the trie.
 pub fn decode_and_verify_proof<T>(config: Config<T>) -> Result<DecodedTrieProof<T>, Error>
+where
+    T: AsRef<[u8]>,
+{
+    decode_and_verify_proof_inner(config, false)
+}
+
+/// Similar to [`decode_and_verify_proof`], except that entries of the proof that are
+/// disconnected from the root node of the trie are ignored rather than leading to an error.
+///
+/// This is notably useful for proofs concerning child tries. These proofs also contain the
+/// entries of the main trie that lead to the root of the child trie, which are disconnected
+/// from the root node of the child trie.
+pub fn decode_and_verify_proof_allow_unused<T>(
+    config: Config<T>,
+) -> Result<DecodedTrieProof<T>, Error>
+where
+    T: AsRef<[u8]>,
+{
+    decode_and_verify_proof_inner(config, true)
+}
+
+fn decode_and_verify_proof_inner<T>(
+    config: Config<T>,
+    allow_unused_entries: bool,
+) -> Result<DecodedTrieProof<T>, Error>
 where
     T: AsRef<[u8]>,
 {
@@ -252,6 +277,16 @@ where
                         if let Some((value_position, value_entry_range)) =
                             merkle_values.get(&value_hash[..])
                         {
+                            // Only the version 1 of the trie hashes storage values, and only
+                            // if they are at least 33 bytes long. This check is valid even if
+                            // the trie is being migrated from the version 0 to the version 1,
+                            // as storage values of the version 0 are never hashed.
+                            if value_entry_range.end - value_entry_range.start
+                                < HASHED_STORAGE_VALUE_MIN_LEN
+                            {
+                                return Err(Error::UnexpectedHashedValue);
+                            }
+
                             let _ = unvisited_proof_entries.remove(value_position);
                             StorageValueInner::Known {
                                 is_inline: false,
@@ -294,7 +329,7 @@ where
 
     // The entire reason why we track the unvisited proof entries is to return this error if
     // necessary.
-    if !unvisited_proof_entries.is_empty() {
+    if !allow_unused_entries && !unvisited_proof_entries.is_empty() {
         return Err(Error::UnusedProofEntry);
     }
 
@@ -304,6 +339,10 @@ where
     })
 }
 
+/// Minimum length of storage values that are hashed in the version 1 of the trie. Shorter
+/// storage values are always inline in their node value.
+const HASHED_STORAGE_VALUE_MIN_LEN: usize = 33;
+
 /// Equivalent to [`StorageValue`] but contains offsets indexing [`DecodedTrieProof::proof`].
 #[derive(Debug, Copy, Clone)]
 enum StorageValueInner {
@@ -635,11 +674,112 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
         }
     }
 
+    /// Returns the Merkle value of the closest descendant of the given key in the trie,
+    /// including the key itself.
+    ///
+    /// Returns `Ok(None)` if it is known that there isn't any node in the trie whose key starts
+    /// with `key`. Returns an error if the proof doesn't contain enough information.
+    ///
+    /// Contrary to storage values, Merkle values don't depend on the version of the trie the
+    /// nodes have been inserted with. This function works no matter the version of the nodes,
+    /// including when the trie contains nodes of both versions.
+    pub fn closest_descendant_merkle_value(
+        &'_ self,
+        key: &[nibble::Nibble],
+    ) -> Result<Option<trie_node::MerkleValueOutput>, IncompleteProofError> {
+        // If the proof is empty, then we have no information about the trie whatsoever.
+        let root_key = match self.entries.keys().next() {
+            Some(k) => k,
+            None => return Err(IncompleteProofError()),
+        };
+
+        // If the closest descendant is in the trie, it is necessarily in the proof, as the nodes
+        // of the proof are all reachable from the root node, and any node whose key starts with
+        // `key` is reachable only through the closest descendant.
+        // Because keys are ordered lexicographically, the closest descendant is the first entry
+        // that is superior or equal to `key`.
+        if let Some((found_key, (_, node_value_range, _))) = self
+            .entries
+            .range::<[nibble::Nibble], _>((ops::Bound::Included(key), ops::Bound::Unbounded))
+            .next()
+        {
+            if found_key.starts_with(key) {
+                let node_value = &self.proof.as_ref()[node_value_range.clone()];
+                // The Merkle value of the root node is always a hash.
+                return Ok(Some(if node_value.len() >= 32 || found_key == root_key {
+                    trie_node::MerkleValueOutput::from_bytes(
+                        blake2_rfc::blake2b::blake2b(32, &[], node_value).as_bytes(),
+                    )
+                } else {
+                    trie_node::MerkleValueOutput::from_bytes(node_value)
+                }));
+            }
+        }
+
+        // Find the closest ancestor of `key` in the proof.
+        let (ancestor_key, (_, ancestor_node_value_range, ancestor_children_bitmap)) = match (0
+            ..key.len())
+            .rev()
+            .find_map(|len| self.entries.get_key_value(&key[..len]))
+        {
+            Some(a) => a,
+            None => {
+                // The key isn't below the root node, and the root node isn't below the key.
+                return Ok(None);
+            }
+        };
+
+        let child_nibble = key[ancestor_key.len()];
+        if ancestor_children_bitmap & (1 << u8::from(child_nibble)) == 0 {
+            // There isn't any child in the direction of `key`.
+            return Ok(None);
+        }
+
+        let mut child_key_prefix = ancestor_key.clone();
+        child_key_prefix.push(child_nibble);
+        if self
+            .entries
+            .range::<[nibble::Nibble], _>((
+                ops::Bound::Included(&child_key_prefix[..]),
+                ops::Bound::Unbounded,
+            ))
+            .next()
+            .map_or(false, |(k, _)| k.starts_with(&child_key_prefix))
+        {
+            // The child is in the proof. Since it doesn't start with `key` and isn't an
+            // ancestor of `key`, as otherwise it would have been found above, `key` doesn't
+            // have any descendant.
+            return Ok(None);
+        }
+
+        // The child is absent from the proof. If `key` is exactly the key of the ancestor
+        // followed with one nibble, then this child is the closest descendant of `key`. Its
+        // Merkle value is found in the node value of its parent. Otherwise, the partial key of
+        // the child is unknown.
+        if key.len() != ancestor_key.len() + 1 {
+            return Err(IncompleteProofError());
+        }
+
+        let ancestor_node_value = &self.proof.as_ref()[ancestor_node_value_range.clone()];
+        // The proof has been verified when it was decoded.
+        let decoded_ancestor = trie_node::decode(ancestor_node_value).unwrap();
+        let child_merkle_value =
+            decoded_ancestor.children[usize::from(u8::from(child_nibble))].unwrap();
+        Ok(Some(trie_node::MerkleValueOutput::from_bytes(
+            child_merkle_value,
+        )))
+    }
+
     /// Queries from the proof the storage value at the given key.
     ///
     /// Returns `None` if the storage value couldn't be determined from the proof. Returns
     /// `Some(None)` if the storage value is known to have no value.
     ///
+    /// The version of the storage value is determined by the way it is stored in its node value.
+    /// If the trie is being migrated from the version 0 to the version 1, the storage values of
+    /// both versions can be found in the proof. Storage values shorter than 33 bytes are
+    /// encoded identically in both versions and are reported as [`TrieEntryVersion::V0`].
+    ///
     /// > **Note**: This function is a convenient wrapper around
     /// >           [`DecodedTrieProof::trie_node_info`].
     // TODO: return a Result instead of Option?
@@ -661,6 +801,23 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
         }
     }
 
+    /// Returns the list of entries of the proof, in the order in which they are found in the
+    /// proof.
+    ///
+    /// Each entry is either the node value of a trie node or a standalone storage value. This is
+    /// typically used in order to send the proof back to someone else.
+    pub fn proof_entries(&'_ self) -> impl Iterator<Item = &'_ [u8]> + '_ {
+        // The proof has already been successfully decoded in `decode_and_verify_proof`, so
+        // decoding it again can't fail.
+        let (_, decoded_proof) = nom::combinator::all_consuming(nom::combinator::flat_map(
+            crate::util::nom_scale_compact_usize,
+            |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
+        ))(self.proof.as_ref())
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| ())
+        .unwrap();
+        decoded_proof.into_iter()
+    }
+
     // TODO: add a ̀`next_key` and a `prefix_keys` function
 }
 
@@ -672,7 +829,8 @@ pub enum StorageValue<'a> {
         /// The storage value.
         value: &'a [u8],
         /// `true` if the storage value was inline in the node. This indicates "version 0" of the
-        /// state version, while `false` indicates "version 1".
+        /// state version, while `false` indicates "version 1". Storage values shorter than 33
+        /// bytes are inline in both versions.
         inline: bool,
     },
     /// The hash of the storage value was found, but the un-hashed value wasn't in the proof. This
@@ -728,8 +886,16 @@ pub enum Error {
     /// A node has been passed separately and referred to by its hash, while its length is inferior
     /// to 32 bytes.
     UnexpectedHashedNode,
+    /// A storage value has been passed separately and referred to by its hash, while its length
+    /// is inferior to 33 bytes.
+    UnexpectedHashedValue,
 }
 
+/// Error potentially returned by [`DecodedTrieProof::closest_descendant_merkle_value`].
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "Proof doesn't contain enough information")]
+pub struct IncompleteProofError();
+
 /// Information about an entry in the proof.
 #[derive(Debug, Copy, Clone)]
 pub struct ProofEntry<'a> {
@@ -1029,6 +1195,129 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn unused_entry_allowed() {
+        // Same root node as in `very_small_root_node_decodes`, plus an entry that isn't
+        // connected to it.
+        let proof = vec![
+            8, 64, 66, 3, 52, 120, 31, 215, 222, 245, 16, 76, 51, 181, 0, 245, 192, 194, 12, 1, 2,
+            3,
+        ];
+        let trie_root_hash = &[
+            83, 2, 191, 235, 8, 252, 233, 114, 129, 199, 229, 115, 221, 238, 15, 205, 193, 110,
+            145, 107, 12, 3, 10, 145, 117, 211, 203, 151, 182, 147, 221, 178,
+        ];
+
+        assert!(matches!(
+            super::decode_and_verify_proof(super::Config {
+                proof: &proof,
+                trie_root_hash,
+            }),
+            Err(super::Error::UnusedProofEntry)
+        ));
+
+        super::decode_and_verify_proof_allow_unused(super::Config {
+            proof: &proof,
+            trie_root_hash,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn proof_entries_match_proof() {
+        // Same proof as in `unused_entry_allowed`.
+        let proof = vec![
+            8, 64, 66, 3, 52, 120, 31, 215, 222, 245, 16, 76, 51, 181, 0, 245, 192, 194, 12, 1, 2,
+            3,
+        ];
+        let trie_root_hash = &[
+            83, 2, 191, 235, 8, 252, 233, 114, 129, 199, 229, 115, 221, 238, 15, 205, 193, 110,
+            145, 107, 12, 3, 10, 145, 117, 211, 203, 151, 182, 147, 221, 178,
+        ];
+
+        let decoded = super::decode_and_verify_proof_allow_unused(super::Config {
+            proof: &proof,
+            trie_root_hash,
+        })
+        .unwrap();
+
+        assert_eq!(
+            decoded.proof_entries().collect::<Vec<_>>(),
+            vec![&proof[2..18], &proof[19..22]]
+        );
+    }
+
+    #[test]
+    fn child_trie_proof() {
+        // Builds a proof containing both the node of a child trie and the node of the main trie
+        // that contains the root of this child trie, similar to what peers send back when a
+        // child trie is queried.
+        use super::super::{nibble, trie_node};
+
+        fn single_node_trie(key: &[u8], value: &[u8]) -> Vec<u8> {
+            trie_node::encode_to_vec(trie_node::Decoded {
+                children: [None::<&[u8]>; 16],
+                partial_key: nibble::bytes_to_nibbles(key.iter().copied())
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                storage_value: trie_node::StorageValue::Unhashed(value),
+            })
+            .unwrap()
+        }
+
+        let child_trie_node = single_node_trie(b"foo", b"child trie value");
+        let child_trie_root = <[u8; 32]>::try_from(
+            blake2_rfc::blake2b::blake2b(32, &[], &child_trie_node).as_bytes(),
+        )
+        .unwrap();
+
+        let main_trie_key = b":child_storage:default:bar";
+        let main_trie_node = single_node_trie(main_trie_key, &child_trie_root);
+        let main_trie_root =
+            <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &main_trie_node).as_bytes())
+                .unwrap();
+
+        let mut proof = crate::util::encode_scale_compact_usize(2).as_ref().to_vec();
+        for node in [&main_trie_node, &child_trie_node] {
+            proof.extend_from_slice(crate::util::encode_scale_compact_usize(node.len()).as_ref());
+            proof.extend_from_slice(node);
+        }
+
+        // The child trie can be read by verifying the proof against the child trie root.
+        let child_decoded = super::decode_and_verify_proof_allow_unused(super::Config {
+            proof: &proof,
+            trie_root_hash: &child_trie_root,
+        })
+        .unwrap();
+        assert_eq!(
+            child_decoded.storage_value(b"foo").unwrap().unwrap().0,
+            b"child trie value"
+        );
+        assert!(matches!(
+            super::decode_and_verify_proof(super::Config {
+                proof: &proof,
+                trie_root_hash: &child_trie_root,
+            }),
+            Err(super::Error::UnusedProofEntry)
+        ));
+
+        // The root of the child trie is found in the main trie under the prefixed key.
+        let main_decoded = super::decode_and_verify_proof_allow_unused(super::Config {
+            proof: &proof,
+            trie_root_hash: &main_trie_root,
+        })
+        .unwrap();
+        assert_eq!(
+            main_decoded
+                .storage_value(main_trie_key)
+                .unwrap()
+                .unwrap()
+                .0,
+            &child_trie_root[..]
+        );
+        assert!(main_decoded.storage_value(b"bar").unwrap().is_none());
+    }
+
     #[test]
     fn identical_inline_nodes() {
         // One root node with two identical inlined children.
@@ -1097,4 +1386,177 @@ mod tests {
         })
         .unwrap();
     }
+
+    /// Builds a trie that is being migrated from the version 0 to the version 1. The storage
+    /// value at key `0x1234` is long and inline in its node (version 0), while the storage value
+    /// at key `0x2567` is hashed (version 1).
+    ///
+    /// Returns the root node, the node of `0x1234`, the node of `0x2567`, and the storage value
+    /// of `0x2567`.
+    fn mixed_version_trie_nodes() -> [Vec<u8>; 4] {
+        use super::{nibble::Nibble, trie_node};
+
+        let nibbles = |n: &[u8]| {
+            n.iter()
+                .map(|n| Nibble::try_from(*n).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let v0_leaf = trie_node::encode_to_vec(trie_node::Decoded {
+            partial_key: nibbles(&[2, 3, 4]).into_iter(),
+            children: [None::<&[u8]>; 16],
+            storage_value: trie_node::StorageValue::Unhashed(&[0xaa; 40]),
+        })
+        .unwrap();
+
+        let v1_value = vec![0xbb; 40];
+        let v1_leaf = trie_node::encode_to_vec(trie_node::Decoded {
+            partial_key: nibbles(&[5, 6, 7]).into_iter(),
+            children: [None::<&[u8]>; 16],
+            storage_value: trie_node::StorageValue::Hashed(&blake2(&v1_value)),
+        })
+        .unwrap();
+
+        let root = {
+            let v0_leaf_hash = blake2(&v0_leaf);
+            let v1_leaf_hash = blake2(&v1_leaf);
+            let mut children = [None::<&[u8]>; 16];
+            children[1] = Some(&v0_leaf_hash[..]);
+            children[2] = Some(&v1_leaf_hash[..]);
+            trie_node::encode_to_vec(trie_node::Decoded {
+                partial_key: nibbles(&[]).into_iter(),
+                children,
+                storage_value: trie_node::StorageValue::None,
+            })
+            .unwrap()
+        };
+
+        [root, v0_leaf, v1_leaf, v1_value]
+    }
+
+    fn blake2(data: &[u8]) -> [u8; 32] {
+        <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], data).as_bytes()).unwrap()
+    }
+
+    fn encode_proof<'a>(entries: impl ExactSizeIterator<Item = &'a Vec<u8>>) -> Vec<u8> {
+        let mut proof = crate::util::encode_scale_compact_usize(entries.len())
+            .as_ref()
+            .to_vec();
+        for entry in entries {
+            proof.extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+            proof.extend_from_slice(entry);
+        }
+        proof
+    }
+
+    #[test]
+    fn mixed_version_trie() {
+        use super::{nibble::Nibble, TrieEntryVersion};
+
+        let nodes = mixed_version_trie_nodes();
+        let trie_root = blake2(&nodes[0]);
+
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &trie_root,
+            proof: encode_proof(nodes.iter()),
+        })
+        .unwrap();
+
+        let (value, version) = decoded.storage_value(&[0x12, 0x34]).unwrap().unwrap();
+        assert_eq!(value, &[0xaa; 40][..]);
+        assert!(matches!(version, TrieEntryVersion::V0));
+
+        let (value, version) = decoded.storage_value(&[0x25, 0x67]).unwrap().unwrap();
+        assert_eq!(value, &[0xbb; 40][..]);
+        assert!(matches!(version, TrieEntryVersion::V1));
+
+        assert!(decoded.storage_value(&[0x30]).unwrap().is_none());
+
+        let nibble = |n: u8| Nibble::try_from(n).unwrap();
+        assert_eq!(
+            decoded
+                .closest_descendant_merkle_value(&[])
+                .unwrap()
+                .unwrap()
+                .as_ref(),
+            &trie_root[..]
+        );
+        assert_eq!(
+            decoded
+                .closest_descendant_merkle_value(&[nibble(1)])
+                .unwrap()
+                .unwrap()
+                .as_ref(),
+            &blake2(&nodes[1])[..]
+        );
+        assert_eq!(
+            decoded
+                .closest_descendant_merkle_value(&[nibble(2), nibble(5)])
+                .unwrap()
+                .unwrap()
+                .as_ref(),
+            &blake2(&nodes[2])[..]
+        );
+        assert!(decoded
+            .closest_descendant_merkle_value(&[nibble(3)])
+            .unwrap()
+            .is_none());
+        assert!(decoded
+            .closest_descendant_merkle_value(&[nibble(2), nibble(6)])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn mixed_version_trie_partial_proof() {
+        use super::nibble::Nibble;
+
+        let nodes = mixed_version_trie_nodes();
+        let trie_root = blake2(&nodes[0]);
+
+        // The node of `0x2567` and its storage value are absent from the proof.
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &trie_root,
+            proof: encode_proof(nodes[..2].iter()),
+        })
+        .unwrap();
+
+        assert!(decoded.storage_value(&[0x12, 0x34]).unwrap().is_some());
+        assert!(decoded.storage_value(&[0x25, 0x67]).is_none());
+
+        let nibble = |n: u8| Nibble::try_from(n).unwrap();
+        // The Merkle value of the child is found in the node value of the root.
+        assert_eq!(
+            decoded
+                .closest_descendant_merkle_value(&[nibble(2)])
+                .unwrap()
+                .unwrap()
+                .as_ref(),
+            &blake2(&nodes[2])[..]
+        );
+        assert!(decoded
+            .closest_descendant_merkle_value(&[nibble(2), nibble(5)])
+            .is_err());
+    }
+
+    #[test]
+    fn short_hashed_storage_value_rejected() {
+        use super::{nibble::Nibble, trie_node};
+
+        let value = vec![0xcc; 10];
+        let root = trie_node::encode_to_vec(trie_node::Decoded {
+            partial_key: [Nibble::try_from(1).unwrap(), Nibble::try_from(2).unwrap()].into_iter(),
+            children: [None::<&[u8]>; 16],
+            storage_value: trie_node::StorageValue::Hashed(&blake2(&value)),
+        })
+        .unwrap();
+
+        assert!(matches!(
+            super::decode_and_verify_proof(super::Config {
+                trie_root_hash: &blake2(&root),
+                proof: encode_proof([root, value].iter()),
+            }),
+            Err(super::Error::UnexpectedHashedValue)
+        ));
+    }
 }
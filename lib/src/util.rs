@@ -276,4 +276,5 @@ macro_rules! encode_scale_compact {
 }
 
 encode_scale_compact!(encode_scale_compact_u64, u64);
+encode_scale_compact!(encode_scale_compact_u128, u128);
 encode_scale_compact!(encode_scale_compact_usize, usize);
@@ -71,3 +71,4 @@ pub mod babe;
 pub mod header_body;
 pub mod header_only;
 pub mod inherents;
+pub mod precheck;
@@ -45,7 +45,7 @@
 //! the block header (with the exclusion of the seal itself) made using the public key in question.
 //!
 
-use crate::header;
+use crate::{header, verify::precheck};
 
 use core::{num::NonZeroU64, time::Duration};
 
@@ -77,6 +77,12 @@ pub struct VerifyConfig<'a, TAuthList> {
     /// Duration of a slot in milliseconds.
     /// Can be found by calling the `AuraApi_slot_duration` runtime function.
     pub slot_duration: NonZeroU64,
+
+    /// If `Some`, signatures that have already been verified ahead of time. If the signature of
+    /// the header is found in this collection, it is not verified again.
+    ///
+    /// See the [`precheck`] module.
+    pub prechecked_signatures: Option<&'a precheck::PrecheckedSignatures>,
 }
 
 /// Information yielded back after successfully verifying a block.
@@ -168,9 +174,7 @@ pub fn verify_header<'a>(
     let (seal_signature, pre_seal_hash) = {
         let mut unsealed_header = config.header;
         let seal_signature = match unsealed_header.digest.pop_seal() {
-            Some(header::Seal::Aura(seal)) => {
-                schnorrkel::Signature::from_bytes(seal).map_err(|_| VerifyError::BadSignature)?
-            }
+            Some(header::Seal::Aura(seal)) => *seal,
             _ => return Err(VerifyError::MissingSeal),
         };
         (
@@ -189,21 +193,26 @@ pub fn verify_header<'a>(
         usize::try_from(slot_number % u64::try_from(config.current_authorities.len()).unwrap())
             .unwrap();
 
-    // This `unwrap()` can only panic if `public_key` is the wrong length, which we know can't
-    // happen as it's of type `[u8; 32]`.
-    let authority_public_key = schnorrkel::PublicKey::from_bytes(
-        config
-            .current_authorities
-            .nth(signing_authority)
-            .unwrap()
-            .public_key,
-    )
-    .unwrap();
-
-    // Now verifying the signature in the seal.
-    authority_public_key
-        .verify_simple(b"substrate", &pre_seal_hash, &seal_signature)
-        .map_err(|_| VerifyError::BadSignature)?;
+    let authority_public_key = *config
+        .current_authorities
+        .nth(signing_authority)
+        .unwrap()
+        .public_key;
+
+    // Now verifying the signature in the seal, unless this has already been done ahead of time.
+    let signatures_check =
+        precheck::SignaturesCheck::aura(authority_public_key, pre_seal_hash, seal_signature);
+    if !config
+        .prechecked_signatures
+        .map_or(false, |prechecked| prechecked.contains(&signatures_check))
+    {
+        signatures_check.verify().map_err(|err| match err {
+            precheck::Error::BadPublicKey => VerifyError::BadPublicKey,
+            precheck::Error::BadSignature | precheck::Error::BadVrfProof => {
+                VerifyError::BadSignature
+            }
+        })?;
+    }
 
     // Success! 🚀
     Ok(VerifySuccess { authorities_change })
@@ -129,7 +129,7 @@
 //!
 //! See also the [`crate::chain::chain_information`] module for more help.
 
-use crate::{chain::chain_information, header};
+use crate::{chain::chain_information, header, verify::precheck};
 
 use core::{num::NonZeroU64, time::Duration};
 use num_traits::{cast::ToPrimitive as _, identities::One as _};
@@ -169,6 +169,12 @@ pub struct VerifyConfig<'a> {
     /// The [`chain_information::BabeEpochInformationRef::start_slot_number`] must be `None` if
     /// and only if the [`chain_information::BabeEpochInformationRef::epoch_index`] is `0`.
     pub parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+
+    /// If `Some`, signatures that have already been verified ahead of time. If the signatures
+    /// of the header are found in this collection, they are not verified again.
+    ///
+    /// See the [`precheck`] module.
+    pub prechecked_signatures: Option<&'a precheck::PrecheckedSignatures>,
 }
 
 /// Information yielded back after successfully verifying a block.
@@ -340,9 +346,7 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
 
     // Signature contained in the seal is copied and stored for later.
     let seal_signature = match config.header.digest.babe_seal() {
-        Some(seal) => {
-            schnorrkel::Signature::from_bytes(seal).map_err(|_| VerifyError::BadSignature)?
-        }
+        Some(seal) => *seal,
         None => return Err(VerifyError::MissingSeal),
     };
 
@@ -404,39 +408,38 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
         .nth(usize::try_from(authority_index).map_err(|_| VerifyError::InvalidAuthorityIndex)?)
         .ok_or(VerifyError::InvalidAuthorityIndex)?;
 
-    // This `unwrap()` can only panic if `public_key` is the wrong length, which we know can't
-    // happen as it's of type `[u8; 32]`.
-    let signing_public_key =
-        schnorrkel::PublicKey::from_bytes(signing_authority.public_key).unwrap();
-
-    // Now verifying the signature in the seal.
-    signing_public_key
-        .verify_simple(b"substrate", &pre_seal_hash, &seal_signature)
-        .map_err(|_| VerifyError::BadSignature)?;
-
-    // Now verify the VRF output and proof, if any.
+    // Now verifying the signature in the seal and the VRF output and proof, if any, unless this
+    // has already been done ahead of time.
     // The lack of VRF output/proof in the header is checked when we check whether the slot
     // type is allowed by the current configuration.
-    if let Some((vrf_output, vrf_proof)) = vrf_output_and_proof {
-        // In order to verify the VRF output, we first need to create a transcript containing all
-        // the data to verify the VRF against.
-        let transcript = {
-            let mut transcript = merlin::Transcript::new(&b"BABE"[..]);
-            transcript.append_u64(b"slot number", slot_number);
-            transcript.append_u64(b"current epoch", block_epoch_info.epoch_index);
-            transcript.append_message(b"chain randomness", &block_epoch_info.randomness[..]);
-            transcript
-        };
-
-        // These `unwrap()`s can only panic if `vrf_output` or `vrf_proof` are of the wrong
-        // length, which we know can't happen as they're of types `[u8; 32]` and `[u8; 64]`.
-        let vrf_output = schnorrkel::vrf::VRFPreOut::from_bytes(&vrf_output[..]).unwrap();
-        let vrf_proof = schnorrkel::vrf::VRFProof::from_bytes(&vrf_proof[..]).unwrap();
-
-        let (vrf_in_out, _) = signing_public_key
-            .vrf_verify(transcript, &vrf_output, &vrf_proof)
-            .map_err(|_| VerifyError::BadVrfProof)?;
+    let signatures_check = precheck::SignaturesCheck::babe(
+        *signing_authority.public_key,
+        pre_seal_hash,
+        seal_signature,
+        vrf_output_and_proof.map(|(vrf_output, vrf_proof)| {
+            (
+                slot_number,
+                block_epoch_info.epoch_index,
+                *block_epoch_info.randomness,
+                vrf_output,
+                vrf_proof,
+            )
+        }),
+    );
+    let verified_signatures = match config
+        .prechecked_signatures
+        .and_then(|prechecked| prechecked.get(&signatures_check))
+    {
+        Some(verified) => verified,
+        None => signatures_check.verify().map_err(|err| match err {
+            precheck::Error::BadPublicKey | precheck::Error::BadSignature => {
+                VerifyError::BadSignature
+            }
+            precheck::Error::BadVrfProof => VerifyError::BadVrfProof,
+        })?,
+    };
 
+    if let Some(vrf_output_bytes) = verified_signatures.vrf_output_bytes() {
         // If this is a primary slot claim, we need to make sure that the VRF output is below
         // a certain threshold, otherwise all the authorities could claim all the slots.
         if primary_slot_claim {
@@ -445,9 +448,7 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
                 block_epoch_info.authorities.clone().map(|a| a.weight),
                 signing_authority.weight,
             );
-            if u128::from_le_bytes(vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
-                >= threshold
-            {
+            if u128::from_le_bytes(vrf_output_bytes) >= threshold {
                 return Err(VerifyError::OverPrimaryClaimThreshold);
             }
         }
@@ -21,7 +21,7 @@ use crate::{
     header,
     trie::calculate_root,
     util,
-    verify::{aura, babe, inherents},
+    verify::{aura, babe, inherents, precheck},
 };
 
 use alloc::{string::String, vec::Vec};
@@ -64,6 +64,12 @@ pub struct Config<'a, TBody> {
     /// Number of bytes used to encode the block number in the header.
     pub block_number_bytes: usize,
 
+    /// If `Some`, signatures that have already been verified ahead of time, and that don't
+    /// need to be verified again.
+    ///
+    /// See the [`precheck`] module.
+    pub prechecked_signatures: Option<&'a precheck::PrecheckedSignatures>,
+
     /// Body of the block to verify.
     pub block_body: TBody,
 
@@ -257,6 +263,7 @@ pub fn verify(
                 now_from_unix_epoch: config.now_from_unix_epoch,
                 current_authorities: current_authorities.clone(),
                 slot_duration: *slot_duration,
+                prechecked_signatures: config.prechecked_signatures,
             });
 
             match result {
@@ -291,6 +298,7 @@ pub fn verify(
                 parent_block_epoch: parent_block_epoch.clone(),
                 slots_per_epoch: *slots_per_epoch,
                 now_from_unix_epoch: config.now_from_unix_epoch,
+                prechecked_signatures: config.prechecked_signatures,
             });
 
             match result {
@@ -373,6 +381,8 @@ pub fn verify(
             storage_main_trie_changes: Default::default(),
             offchain_storage_changes: Default::default(),
             max_log_level: config.max_log_level,
+            offchain_behavior: runtime_host::OffchainBehavior::Deny,
+            tracing: false,
         });
 
         match vm {
@@ -453,6 +463,8 @@ impl VerifyInner {
                             storage_main_trie_changes: success.storage_main_trie_changes,
                             offchain_storage_changes: success.offchain_storage_changes,
                             max_log_level: 0,
+                            offchain_behavior: runtime_host::OffchainBehavior::Deny,
+                            tracing: false,
                         });
 
                         match vm {
@@ -563,6 +575,10 @@ impl VerifyInner {
                 runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
                     self.inner = sig.verify_and_resume();
                 }
+                runtime_host::RuntimeHostVm::Offchain(_) => {
+                    // Off-chain host functions are configured to be denied.
+                    unreachable!()
+                }
             }
         }
     }
@@ -687,6 +703,7 @@ impl RuntimeCompilation {
             heap_pages: self.heap_pages,
             exec_hint: vm::ExecHint::CompileAheadOfTime,
             allow_unresolved_imports: false,
+            fuel_limit: None,
         }) {
             Ok(vm) => vm,
             Err(err) => {
@@ -18,7 +18,7 @@
 use crate::{
     chain::chain_information,
     header,
-    verify::{aura, babe},
+    verify::{aura, babe, precheck},
 };
 
 use core::{num::NonZeroU64, time::Duration};
@@ -39,6 +39,12 @@ pub struct Config<'a> {
     /// Number of bytes used to encode the block number in the header.
     pub block_number_bytes: usize,
 
+    /// If `Some`, signatures that have already been verified ahead of time, and that don't
+    /// need to be verified again.
+    ///
+    /// See the [`precheck`] module.
+    pub prechecked_signatures: Option<&'a precheck::PrecheckedSignatures>,
+
     /// Configuration items related to the consensus engine.
     pub consensus: ConfigConsensus<'a>,
 
@@ -222,6 +228,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 now_from_unix_epoch,
                 current_authorities,
                 slot_duration,
+                prechecked_signatures: config.prechecked_signatures,
             });
 
             match result {
@@ -249,6 +256,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 parent_block_next_epoch,
                 slots_per_epoch,
                 now_from_unix_epoch,
+                prechecked_signatures: config.prechecked_signatures,
             });
 
             match result {
@@ -0,0 +1,463 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Verification of the signatures of block headers ahead of time.
+//!
+//! Verifying the signature of the seal of a header, and the VRF output of this header in the
+//! case of Babe, is by far the most CPU-intensive part of verifying a header. Contrary to the
+//! rest of the verification, these checks don't need to know anything about the chain other
+//! than the public key of the author and, in the case of Babe, the epoch the block belongs to.
+//! They can consequently be performed in advance and in parallel, for example in other threads,
+//! for headers that are queued for verification.
+//!
+//! # Usage
+//!
+//! Build a [`SignaturesCheck`] from the header whose signatures should be checked ahead of time
+//! with [`SignaturesCheck::from_header`]. This function must be passed the consensus
+//! information of a block that is believed to be close to the header, typically the current
+//! best block, in order to guess the author of the block.
+//!
+//! Call [`SignaturesCheck::verify`], possibly on a different thread, then insert the
+//! [`VerifiedSignatures`] it returns in a [`PrecheckedSignatures`].
+//!
+//! When the header is later verified, the [`PrecheckedSignatures`] can be passed to
+//! [`super::babe::VerifyConfig::prechecked_signatures`] or
+//! [`super::aura::VerifyConfig::prechecked_signatures`], in which case the signatures that have
+//! already been verified are not verified again. All the other verifications are still
+//! performed normally and in order.
+//!
+//! A [`PrecheckedSignatures`] is indexed by the public key of the author, the signed hash, and
+//! the signatures themselves. If the author or epoch guessed by [`SignaturesCheck::from_header`]
+//! turns out to be wrong, the pre-check is simply not found and the signatures are verified
+//! normally.
+
+use crate::{chain::chain_information, header};
+
+use alloc::collections::VecDeque;
+use core::fmt;
+use hashbrown::HashMap;
+
+/// Signatures of a block header that can be verified ahead of time.
+///
+/// See [the module-level documentation](..).
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SignaturesCheck {
+    /// Public key of the author of the block.
+    public_key: [u8; 32],
+    /// Hash of the header without its seal. This is what the seal signs.
+    pre_seal_hash: [u8; 32],
+    /// Signature found in the seal of the header.
+    seal_signature: [u8; 64],
+    /// In the case of Babe, VRF output and proof of the block, if any.
+    vrf: Option<VrfCheck>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct VrfCheck {
+    slot_number: u64,
+    epoch_index: u64,
+    epoch_randomness: [u8; 32],
+    output: [u8; 32],
+    proof: [u8; 64],
+}
+
+impl SignaturesCheck {
+    /// Extracts the signatures to verify from the given header.
+    ///
+    /// `consensus` must be the consensus information of a block that is close to the header,
+    /// typically the current best block. It is used to determine the author of the block and,
+    /// in the case of Babe, the epoch the block belongs to.
+    ///
+    /// Returns `None` if the header doesn't contain any seal, or if its author can't be
+    /// determined. No error is returned, as any problem with the header will be detected when
+    /// actually verifying it.
+    pub fn from_header(
+        header: header::HeaderRef,
+        block_number_bytes: usize,
+        consensus: chain_information::ChainInformationConsensusRef,
+    ) -> Option<Self> {
+        // The seal applies to the header from where the seal isn't present.
+        let mut unsealed_header = header.clone();
+        let seal_signature = match unsealed_header.digest.pop_seal()? {
+            header::Seal::Aura(seal) | header::Seal::Babe(seal) => *seal,
+        };
+        let pre_seal_hash = unsealed_header.hash(block_number_bytes);
+
+        match consensus {
+            chain_information::ChainInformationConsensusRef::Unknown => None,
+            chain_information::ChainInformationConsensusRef::Aura {
+                mut finalized_authorities_list,
+                ..
+            } => {
+                let slot_number = header.digest.aura_pre_runtime()?.slot_number;
+                let num_authorities = u64::try_from(finalized_authorities_list.len()).ok()?;
+                let authority_index = slot_number.checked_rem(num_authorities)?;
+                let public_key = *finalized_authorities_list
+                    .nth(usize::try_from(authority_index).ok()?)?
+                    .public_key;
+
+                Some(SignaturesCheck::aura(
+                    public_key,
+                    pre_seal_hash,
+                    seal_signature,
+                ))
+            }
+            chain_information::ChainInformationConsensusRef::Babe {
+                slots_per_epoch,
+                finalized_block_epoch_information,
+                finalized_next_epoch_transition,
+            } => {
+                let pre_digest = header.digest.babe_pre_runtime()?;
+                let slot_number = pre_digest.slot_number();
+
+                // Guess the epoch of the block based on its slot number.
+                let epoch = match (
+                    finalized_block_epoch_information,
+                    finalized_next_epoch_transition.start_slot_number,
+                ) {
+                    (_, Some(next_start)) if slot_number >= next_start => {
+                        if slot_number - next_start >= slots_per_epoch.get() {
+                            return None;
+                        }
+                        finalized_next_epoch_transition
+                    }
+                    (Some(current), _)
+                        if current
+                            .start_slot_number
+                            .map_or(false, |start| start <= slot_number) =>
+                    {
+                        current
+                    }
+                    (None, None) => finalized_next_epoch_transition,
+                    _ => return None,
+                };
+
+                let (authority_index, vrf_output_and_proof) = match pre_digest {
+                    header::BabePreDigestRef::Primary(digest) => (
+                        digest.authority_index,
+                        Some((*digest.vrf_output, *digest.vrf_proof)),
+                    ),
+                    header::BabePreDigestRef::SecondaryPlain(digest) => {
+                        (digest.authority_index, None)
+                    }
+                    header::BabePreDigestRef::SecondaryVRF(digest) => (
+                        digest.authority_index,
+                        Some((*digest.vrf_output, *digest.vrf_proof)),
+                    ),
+                };
+
+                let public_key = *epoch
+                    .authorities
+                    .clone()
+                    .nth(usize::try_from(authority_index).ok()?)?
+                    .public_key;
+
+                Some(SignaturesCheck::babe(
+                    public_key,
+                    pre_seal_hash,
+                    seal_signature,
+                    vrf_output_and_proof.map(|(output, proof)| {
+                        (
+                            slot_number,
+                            epoch.epoch_index,
+                            *epoch.randomness,
+                            output,
+                            proof,
+                        )
+                    }),
+                ))
+            }
+        }
+    }
+
+    /// Builds the [`SignaturesCheck`] of an Aura header.
+    pub(super) fn aura(
+        public_key: [u8; 32],
+        pre_seal_hash: [u8; 32],
+        seal_signature: [u8; 64],
+    ) -> Self {
+        SignaturesCheck {
+            public_key,
+            pre_seal_hash,
+            seal_signature,
+            vrf: None,
+        }
+    }
+
+    /// Builds the [`SignaturesCheck`] of a Babe header.
+    ///
+    /// `vrf` contains, if the header contains a VRF output, the slot number of the block, the
+    /// index and randomness of its epoch, and the VRF output and proof.
+    pub(super) fn babe(
+        public_key: [u8; 32],
+        pre_seal_hash: [u8; 32],
+        seal_signature: [u8; 64],
+        vrf: Option<(u64, u64, [u8; 32], [u8; 32], [u8; 64])>,
+    ) -> Self {
+        SignaturesCheck {
+            public_key,
+            pre_seal_hash,
+            seal_signature,
+            vrf: vrf.map(
+                |(slot_number, epoch_index, epoch_randomness, output, proof)| VrfCheck {
+                    slot_number,
+                    epoch_index,
+                    epoch_randomness,
+                    output,
+                    proof,
+                },
+            ),
+        }
+    }
+
+    /// Verifies the signatures.
+    ///
+    /// This is the CPU-intensive operation that this module is about.
+    pub fn verify(&self) -> Result<VerifiedSignatures, Error> {
+        let public_key =
+            schnorrkel::PublicKey::from_bytes(&self.public_key).map_err(|_| Error::BadPublicKey)?;
+        let seal_signature = schnorrkel::Signature::from_bytes(&self.seal_signature)
+            .map_err(|_| Error::BadSignature)?;
+
+        public_key
+            .verify_simple(b"substrate", &self.pre_seal_hash, &seal_signature)
+            .map_err(|_| Error::BadSignature)?;
+
+        let vrf_output_bytes = if let Some(vrf) = &self.vrf {
+            // In order to verify the VRF output, we first need to create a transcript containing
+            // all the data to verify the VRF against.
+            let transcript = {
+                let mut transcript = merlin::Transcript::new(&b"BABE"[..]);
+                transcript.append_u64(b"slot number", vrf.slot_number);
+                transcript.append_u64(b"current epoch", vrf.epoch_index);
+                transcript.append_message(b"chain randomness", &vrf.epoch_randomness[..]);
+                transcript
+            };
+
+            // These `unwrap()`s can only panic if `output` or `proof` are of the wrong length,
+            // which we know can't happen as they're of types `[u8; 32]` and `[u8; 64]`.
+            let output = schnorrkel::vrf::VRFPreOut::from_bytes(&vrf.output[..]).unwrap();
+            let proof = schnorrkel::vrf::VRFProof::from_bytes(&vrf.proof[..]).unwrap();
+
+            let (vrf_in_out, _) = public_key
+                .vrf_verify(transcript, &output, &proof)
+                .map_err(|_| Error::BadVrfProof)?;
+
+            Some(vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
+        } else {
+            None
+        };
+
+        Ok(VerifiedSignatures {
+            key: self.cache_key(),
+            vrf_output_bytes,
+        })
+    }
+
+    /// Returns the key under which the outcome of the verification is stored in a
+    /// [`PrecheckedSignatures`].
+    fn cache_key(&self) -> [u8; 32] {
+        let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+        hash.update(&self.public_key);
+        hash.update(&self.pre_seal_hash);
+        hash.update(&self.seal_signature);
+        if let Some(vrf) = &self.vrf {
+            hash.update(&vrf.slot_number.to_le_bytes());
+            hash.update(&vrf.epoch_index.to_le_bytes());
+            hash.update(&vrf.epoch_randomness);
+            hash.update(&vrf.output);
+            hash.update(&vrf.proof);
+        }
+
+        let mut out = [0; 32];
+        out.copy_from_slice(hash.finalize().as_bytes());
+        out
+    }
+}
+
+impl fmt::Debug for SignaturesCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignaturesCheck")
+            .field("public_key", &hex::encode(self.public_key))
+            .field("pre_seal_hash", &hex::encode(self.pre_seal_hash))
+            .field("has_vrf", &self.vrf.is_some())
+            .finish()
+    }
+}
+
+/// Successful outcome of [`SignaturesCheck::verify`].
+///
+/// Can only be obtained by verifying signatures, and can be inserted in a
+/// [`PrecheckedSignatures`].
+#[derive(Debug, Clone)]
+pub struct VerifiedSignatures {
+    /// See [`SignaturesCheck::cache_key`].
+    key: [u8; 32],
+    /// Output of the VRF, if the check contained a VRF output and proof.
+    vrf_output_bytes: Option<[u8; 16]>,
+}
+
+impl VerifiedSignatures {
+    /// Returns the bytes derived from the output of the VRF, as used to compare against the
+    /// primary slot threshold. `None` if the header didn't contain any VRF output.
+    pub(super) fn vrf_output_bytes(&self) -> Option<[u8; 16]> {
+        self.vrf_output_bytes
+    }
+}
+
+/// Error potentially returned by [`SignaturesCheck::verify`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum Error {
+    /// Public key of the author is invalid.
+    BadPublicKey,
+    /// Block header signature is invalid.
+    BadSignature,
+    /// VRF proof in the block header is invalid.
+    BadVrfProof,
+}
+
+/// Collection of signatures that have been successfully verified ahead of time.
+///
+/// Holds at most a fixed number of entries. Inserting an entry when the collection is full
+/// removes the entry that has been inserted the earliest.
+pub struct PrecheckedSignatures {
+    /// Entries in the collection, indexed by [`SignaturesCheck::cache_key`]. The values are
+    /// [`VerifiedSignatures::vrf_output_bytes`].
+    ///
+    /// Since an entry can only be inserted after its signatures have successfully been verified,
+    /// the keys can't be chosen by a malicious peer.
+    entries: HashMap<[u8; 32], Option<[u8; 16]>, fnv::FnvBuildHasher>,
+
+    /// Keys of [`PrecheckedSignatures::entries`], in the order in which they have been inserted.
+    insertion_order: VecDeque<[u8; 32]>,
+
+    /// Maximum number of entries in [`PrecheckedSignatures::entries`].
+    capacity: usize,
+}
+
+impl PrecheckedSignatures {
+    /// Creates a new empty collection able to hold the given number of entries.
+    ///
+    /// If `capacity` is 0, the collection always stays empty.
+    pub fn with_capacity(capacity: usize) -> Self {
+        PrecheckedSignatures {
+            entries: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            insertion_order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns the number of entries in the collection.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the collection is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns `true` if the signatures of the given check have already been verified.
+    pub fn contains(&self, check: &SignaturesCheck) -> bool {
+        self.entries.contains_key(&check.cache_key())
+    }
+
+    /// Inserts the outcome of a successful verification in the collection.
+    pub fn insert(&mut self, verified: VerifiedSignatures) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self
+            .entries
+            .insert(verified.key, verified.vrf_output_bytes)
+            .is_some()
+        {
+            return;
+        }
+
+        if self.insertion_order.len() == self.capacity {
+            let removed = self.insertion_order.pop_front().unwrap();
+            self.entries.remove(&removed);
+        }
+
+        self.insertion_order.push_back(verified.key);
+    }
+
+    /// Returns the outcome of the verification of the given check, if it has been verified
+    /// successfully ahead of time.
+    pub(super) fn get(&self, check: &SignaturesCheck) -> Option<VerifiedSignatures> {
+        let key = check.cache_key();
+        let vrf_output_bytes = *self.entries.get(&key)?;
+        Some(VerifiedSignatures {
+            key,
+            vrf_output_bytes,
+        })
+    }
+
+    /// Removes all the entries of the collection.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.insertion_order.clear();
+    }
+}
+
+impl fmt::Debug for PrecheckedSignatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PrecheckedSignatures")
+            .field("len", &self.entries.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PrecheckedSignatures, SignaturesCheck, VerifiedSignatures};
+
+    fn check(n: u8) -> SignaturesCheck {
+        SignaturesCheck::aura([n; 32], [0; 32], [0; 64])
+    }
+
+    fn verified(check: &SignaturesCheck) -> VerifiedSignatures {
+        VerifiedSignatures {
+            key: check.cache_key(),
+            vrf_output_bytes: None,
+        }
+    }
+
+    #[test]
+    fn oldest_entry_evicted() {
+        let mut prechecked = PrecheckedSignatures::with_capacity(2);
+        prechecked.insert(verified(&check(1)));
+        prechecked.insert(verified(&check(2)));
+        prechecked.insert(verified(&check(1)));
+        assert_eq!(prechecked.len(), 2);
+
+        prechecked.insert(verified(&check(3)));
+        assert_eq!(prechecked.len(), 2);
+        assert!(!prechecked.contains(&check(1)));
+        assert!(prechecked.contains(&check(2)));
+        assert!(prechecked.contains(&check(3)));
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut prechecked = PrecheckedSignatures::with_capacity(0);
+        prechecked.insert(verified(&check(1)));
+        assert!(prechecked.is_empty());
+    }
+}
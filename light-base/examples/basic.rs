@@ -15,7 +15,10 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use core::iter;
+use core::{
+    iter,
+    num::{NonZeroU32, NonZeroUsize},
+};
 
 fn main() {
     // The `smoldot_light` library uses the `log` crate to emit logs.
@@ -38,6 +41,22 @@ fn main() {
         }),
         system_name: env!("CARGO_PKG_NAME").into(),
         system_version: env!("CARGO_PKG_VERSION").into(),
+        // The bandwidth used by the client can optionally be limited.
+        max_upload_bps: None,
+        max_download_bps: None,
+        // If `None`, a random identity on the peer-to-peer network is generated every time.
+        libp2p_key: None,
+        // Compiling the runtimes to native code makes runtime calls faster. Falls back to an
+        // interpreter on platforms where this isn't supported.
+        wasm_execution: smoldot_light::WasmExecution::Compiled,
+        database_storage: None,
+        dns: smoldot_light::DnsConfig::Platform,
+        // `async_std` runs tasks on a thread pool, which makes it possible to verify the
+        // signatures of block headers in parallel.
+        header_verification_workers: 2,
+        requests_hedging_percentile: Some(95),
+        yamux_max_receive_window: 4 * 1024 * 1024,
+        max_inbound_substreams: None,
     });
 
     // Ask the client to connect to a chain.
@@ -65,6 +84,61 @@ fn main() {
             // In this example, we don't use this feature, and as such we simply pass an empty string,
             // which is intentionally an invalid database content.
             database_content: "",
+            genesis_storage: None,
+            checkpoint: "",
+
+            // Maximum number of JSON-RPC requests whose response hasn't been pulled yet, and
+            // maximum number of active JSON-RPC subscriptions.
+            json_rpc_max_pending_responses: NonZeroU32::new(128).unwrap(),
+            json_rpc_max_subscriptions: 1024,
+            json_rpc_max_batch_size: 64,
+            json_rpc_max_parallel_requests: NonZeroU32::new(24).unwrap(),
+            json_rpc_max_parallel_cheap_requests: NonZeroU32::new(4).unwrap(),
+            max_pinned_blocks: NonZeroUsize::new(32).unwrap(),
+            max_block_hash_lookup_depth: 16384,
+            archive_fallback_endpoints: Vec::new(),
+
+            // If `Some`, the client periodically checks whether it is still connected to the
+            // peer-to-peer network of the chain, and tries to reconnect to the bootnodes if it
+            // isn't.
+            auto_recover: None,
+
+            // Reserved nodes are connected to in priority. If `reserved_only` is `true`, the
+            // client connects exclusively to them. The number of slots is the maximum number of
+            // full nodes the client tries to stay connected to.
+            reserved_nodes: Vec::new(),
+            reserved_only: false,
+            network_out_slots: 4,
+            serve_warp_sync: false,
+            enable_beefy: false,
+
+            // The chain is synchronized by downloading and verifying all its forks. Chains whose
+            // finality is unreliable can instead be synchronized optimistically.
+            sync_mode: smoldot_light::SyncMode::AllForks {
+                forks_retention_limit: None,
+            },
+
+            // Chains that don't use GrandPa have no finality mechanism. Setting this field
+            // makes it possible to consider the blocks that are deep enough as finalized.
+            probabilistic_finality_depth: None,
+            best_block_policy: smoldot_light::BestBlockPolicy::MostPrimarySlots,
+
+            // Recent runtime calls and their call proofs are kept in a cache, so that identical
+            // calls against the same block don't need to be performed again.
+            runtime_call_cache_size: 16,
+
+            // Runtime calls that execute too many instructions are interrupted. `None` means that
+            // runtime calls are never interrupted.
+            runtime_call_fuel_limit: None,
+
+            // Compiled runtimes that are no longer used by the chain are kept in memory, so that
+            // calls against older blocks don't need to compile them again.
+            max_cached_runtimes: NonZeroUsize::new(4).unwrap(),
+
+            // Submitted transactions are validated by calling the runtime before being
+            // broadcast, which requires downloading a call proof.
+            validate_transactions_locally: true,
+            event_hooks: Default::default(),
 
             // The client gives the possibility to insert an opaque "user data" alongside each chain.
             // This avoids having to create a separate `HashMap<ChainId, ...>` in parallel of the
@@ -0,0 +1,236 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Shows how to implement the `Platform` trait with the help of the
+//! `smoldot_light::platform::embedded` module, as would be done when running the client on a
+//! microcontroller.
+//!
+//! In this example, the "firmware" runs on the host. The clock is based on
+//! `std::time::Instant`, and no network stack is available, meaning that connection attempts
+//! always fail. On an actual device, `connect` would ask the network stack to open a socket,
+//! and the network stack would transfer data between this socket and a `StreamDriver`.
+
+use core::{
+    iter,
+    num::{NonZeroU32, NonZeroUsize},
+    time::Duration,
+};
+use futures::prelude::*;
+use smoldot_light::platform::{
+    embedded, ConnectError, Platform, PlatformConnection, PlatformSubstreamDirection, ReadBuffer,
+};
+use std::sync::{mpsc, Arc, OnceLock};
+
+/// Instant when the firmware has started. Plays the role of a hardware clock.
+static BOOT: OnceLock<std::time::Instant> = OnceLock::new();
+
+/// Timers of the platform. Must be a `static` because the functions of `Platform` don't have
+/// access to any state.
+static TIMERS: OnceLock<embedded::TimerQueue<Duration>> = OnceLock::new();
+
+fn clock() -> Duration {
+    BOOT.get_or_init(std::time::Instant::now).elapsed()
+}
+
+fn timers() -> &'static embedded::TimerQueue<Duration> {
+    TIMERS.get_or_init(|| embedded::TimerQueue::new(clock()))
+}
+
+struct FirmwarePlatform;
+
+impl Platform for FirmwarePlatform {
+    type Delay = embedded::Delay<Duration>;
+    type Yield = future::Ready<()>;
+    // The time elapsed since the device has booted is used as the instant type.
+    type Instant = Duration;
+    type Connection = std::convert::Infallible;
+    type Stream = embedded::Stream;
+    type ConnectFuture =
+        future::Ready<Result<PlatformConnection<Self::Stream, Self::Connection>, ConnectError>>;
+    type StreamUpdateFuture<'a> = embedded::StreamUpdate<'a>;
+    type NextSubstreamFuture<'a> =
+        future::Pending<Option<(Self::Stream, PlatformSubstreamDirection)>>;
+
+    fn now_from_unix_epoch() -> Duration {
+        // A device would typically obtain this value from a real-time clock or through NTP.
+        std::time::UNIX_EPOCH.elapsed().unwrap()
+    }
+
+    fn now() -> Self::Instant {
+        clock()
+    }
+
+    fn sleep(duration: Duration) -> Self::Delay {
+        timers().sleep_until(clock() + duration)
+    }
+
+    fn sleep_until(when: Self::Instant) -> Self::Delay {
+        timers().sleep_until(when)
+    }
+
+    fn yield_after_cpu_intensive() -> Self::Yield {
+        future::ready(())
+    }
+
+    fn connect(_multiaddr: &str) -> Self::ConnectFuture {
+        // This is where the network stack would be asked to open a TCP connection. The
+        // implementation would call `embedded::stream(16384, 16384)`, return the `Stream` as a
+        // `PlatformConnection::SingleStreamMultistreamSelectNoiseYamux`, and give the
+        // `StreamDriver` to the network stack.
+        future::ready(Err(ConnectError {
+            message: "No network stack".to_owned(),
+            is_bad_addr: true,
+        }))
+    }
+
+    fn open_out_substream(connection: &mut Self::Connection) {
+        match *connection {}
+    }
+
+    fn next_substream(connection: &'_ mut Self::Connection) -> Self::NextSubstreamFuture<'_> {
+        match *connection {}
+    }
+
+    fn update_stream(stream: &'_ mut Self::Stream) -> Self::StreamUpdateFuture<'_> {
+        stream.update()
+    }
+
+    fn read_buffer(stream: &mut Self::Stream) -> ReadBuffer {
+        stream.read_buffer()
+    }
+
+    fn advance_read_cursor(stream: &mut Self::Stream, bytes: usize) {
+        stream.advance_read_cursor(bytes)
+    }
+
+    fn writable_bytes(stream: &mut Self::Stream) -> usize {
+        stream.writable_bytes()
+    }
+
+    fn send(stream: &mut Self::Stream, data: &[u8]) {
+        stream.send(data)
+    }
+
+    fn close_send(stream: &mut Self::Stream) {
+        stream.close_send()
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    // The executor notifies the main loop whenever a task needs to be polled. On a device, this
+    // would typically set an event flag of the real-time operating system.
+    let (wake_up_tx, wake_up_rx) = mpsc::sync_channel(1);
+    let executor = Arc::new(embedded::Executor::new(Box::new(move || {
+        let _ = wake_up_tx.try_send(());
+    })));
+
+    let mut client = smoldot_light::Client::<FirmwarePlatform>::new(smoldot_light::ClientConfig {
+        tasks_spawner: {
+            let executor = executor.clone();
+            Box::new(move |name, task| executor.spawn(name, task))
+        },
+        system_name: env!("CARGO_PKG_NAME").into(),
+        system_version: env!("CARGO_PKG_VERSION").into(),
+        max_upload_bps: None,
+        max_download_bps: None,
+        libp2p_key: None,
+        // Microcontrollers can't compile the runtimes to native code.
+        wasm_execution: smoldot_light::WasmExecution::Interpreter,
+        database_storage: None,
+        dns: smoldot_light::DnsConfig::Platform,
+        // All the tasks run on the same thread.
+        header_verification_workers: 0,
+        requests_hedging_percentile: Some(95),
+        yamux_max_receive_window: 4 * 1024 * 1024,
+        max_inbound_substreams: None,
+    });
+
+    let smoldot_light::AddChainSuccess {
+        chain_id,
+        json_rpc_responses,
+    } = client
+        .add_chain(smoldot_light::AddChainConfig {
+            specification: include_str!("../../demo-chain-specs/polkadot.json"),
+            disable_json_rpc: false,
+            potential_relay_chains: iter::empty(),
+            database_content: "",
+            genesis_storage: None,
+            checkpoint: "",
+            json_rpc_max_pending_responses: NonZeroU32::new(16).unwrap(),
+            json_rpc_max_subscriptions: 16,
+            json_rpc_max_batch_size: 16,
+            json_rpc_max_parallel_requests: NonZeroU32::new(4).unwrap(),
+            json_rpc_max_parallel_cheap_requests: NonZeroU32::new(1).unwrap(),
+            max_pinned_blocks: NonZeroUsize::new(32).unwrap(),
+            max_block_hash_lookup_depth: 16384,
+            archive_fallback_endpoints: Vec::new(),
+            auto_recover: None,
+            reserved_nodes: Vec::new(),
+            reserved_only: false,
+            network_out_slots: 2,
+            serve_warp_sync: false,
+            enable_beefy: false,
+            sync_mode: smoldot_light::SyncMode::AllForks {
+                forks_retention_limit: None,
+            },
+            probabilistic_finality_depth: None,
+            best_block_policy: smoldot_light::BestBlockPolicy::MostPrimarySlots,
+            runtime_call_cache_size: 2,
+            runtime_call_fuel_limit: None,
+            max_cached_runtimes: NonZeroUsize::new(1).unwrap(),
+            validate_transactions_locally: false,
+            event_hooks: Default::default(),
+            user_data: (),
+        })
+        .unwrap();
+
+    let mut json_rpc_responses = json_rpc_responses.unwrap();
+    client
+        .json_rpc_request(
+            r#"{"id":1,"jsonrpc":"2.0","method":"system_chain","params":[]}"#,
+            chain_id,
+        )
+        .unwrap();
+
+    // The responses are printed by a task that runs on the executor as well.
+    executor.spawn(
+        "json-rpc-responses".to_owned(),
+        Box::pin(async move {
+            while let Some(response) = json_rpc_responses.next().await {
+                println!("JSON-RPC response: {response}");
+            }
+        }),
+    );
+
+    // Main loop of the firmware.
+    loop {
+        executor.run_until_stalled();
+
+        // Sleep until either the next timer expires or a task is woken up. On a device, this
+        // would be done by configuring a hardware timer and waiting for an interrupt.
+        let timeout = timers()
+            .next_deadline()
+            .map_or(Duration::from_secs(1), |deadline| {
+                deadline.saturating_sub(clock())
+            });
+        let _ = wake_up_rx.recv_timeout(timeout);
+
+        timers().advance(clock());
+    }
+}
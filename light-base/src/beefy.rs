@@ -0,0 +1,244 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracking of the BEEFY finality.
+//!
+//! BEEFY finality proofs are gossiped by the validators of the chain. This module listens to the
+//! finality proofs received by the networking, verifies them against the BEEFY validator set of
+//! the chain, and reports the ones that are valid.
+//!
+//! The BEEFY validator set is obtained by calling the `BeefyApi_validator_set` runtime function
+//! against the latest finalized block. It is queried again whenever a finality proof refers to a
+//! validator set that is more recent than the one known locally.
+
+use crate::{network_service, platform::Platform};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{future::Future, time::Duration};
+use futures::{channel::mpsc, prelude::*};
+use smoldot::finality::beefy;
+
+/// Minimum duration between two queries of the validator set.
+///
+/// Finality proofs that refer to an unknown validator set might have been crafted by a
+/// malicious peer. This minimum duration prevents such a peer from causing a large number of
+/// runtime calls.
+const VALIDATOR_SET_QUERY_MIN_INTERVAL: Duration = Duration::from_secs(20);
+
+/// BEEFY finality proof that has been successfully verified.
+#[derive(Debug, Clone)]
+pub struct FinalityProof {
+    /// Height of the block whose finality is proven.
+    pub block_number: u64,
+
+    /// Identifier of the BEEFY validator set that has signed the proof.
+    pub validator_set_id: u64,
+
+    /// Root of the Merkle Mountain Range of the chain found in the signed commitment, if any.
+    pub mmr_root: Option<[u8; 32]>,
+
+    /// SCALE-encoded versioned finality proof, as returned by the
+    /// `beefy_subscribeJustifications` JSON-RPC function. Contains the signed commitment.
+    pub scale_encoded_versioned_finality_proof: Vec<u8>,
+}
+
+/// Returns a stream of the BEEFY finality proofs received from the network and that are valid.
+///
+/// Proofs are yielded in increasing order of block number. Proofs for blocks lower than or equal
+/// to the one of the latest yielded proof are ignored.
+///
+/// `validator_set` is called whenever the BEEFY validator set must be obtained. It must return
+/// the SCALE-encoded output of the `BeefyApi_validator_set` runtime function called against the
+/// latest finalized block, or an error message.
+pub fn finality_proofs_stream<TPlat: Platform, TFut>(
+    log_target: String,
+    network_service: (Arc<network_service::NetworkService<TPlat>>, usize),
+    validator_set: impl FnMut() -> TFut,
+) -> impl Stream<Item = FinalityProof>
+where
+    TFut: Future<Output = Result<Vec<u8>, String>>,
+{
+    struct State<TPlat: Platform, TVs> {
+        log_target: String,
+        network_service: (Arc<network_service::NetworkService<TPlat>>, usize),
+        validator_set: TVs,
+        /// `None` if a new subscription must be started.
+        events: Option<mpsc::Receiver<network_service::Event>>,
+        /// List of known validator sets, with their identifier. Contains at most two entries:
+        /// the latest known validator set and the one before, in order to be able to verify
+        /// the proofs that are received around a change of validator set.
+        known_validator_sets: Vec<(u64, Vec<[u8; 33]>)>,
+        /// When the validator set has last been queried.
+        last_validator_set_query: Option<TPlat::Instant>,
+        /// Height of the block of the latest proof that has been yielded.
+        last_reported_block: Option<u64>,
+    }
+
+    let state = State {
+        log_target,
+        network_service,
+        validator_set,
+        events: None,
+        known_validator_sets: Vec::with_capacity(2),
+        last_validator_set_query: None,
+        last_reported_block: None,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            let Some(events) = state.events.as_mut() else {
+                state.events = Some(state.network_service.0.subscribe(64).await);
+                continue;
+            };
+
+            let proof = match events.next().await {
+                Some(network_service::Event::BeefyFinalityProof {
+                    chain_index, proof, ..
+                }) if chain_index == state.network_service.1 => proof,
+                Some(_) => continue,
+                None => {
+                    // The subscription has been closed by the network service because the
+                    // channel was full. Subscribe again.
+                    state.events = None;
+                    continue;
+                }
+            };
+
+            let decoded = proof.decode();
+            let commitment = &decoded.commitment;
+
+            if state
+                .last_reported_block
+                .map_or(false, |n| commitment.block_number <= n)
+            {
+                continue;
+            }
+
+            // Query the validator set if the proof refers to a more recent validator set than
+            // the ones that are known.
+            if state
+                .known_validator_sets
+                .iter()
+                .all(|(id, _)| *id < commitment.validator_set_id)
+                && state
+                    .last_validator_set_query
+                    .as_ref()
+                    .map_or(true, |when| {
+                        TPlat::now() >= when.clone() + VALIDATOR_SET_QUERY_MIN_INTERVAL
+                    })
+            {
+                state.last_validator_set_query = Some(TPlat::now());
+
+                let validator_set = match (state.validator_set)().await {
+                    Ok(v) => v,
+                    Err(error) => {
+                        log::debug!(
+                            target: &state.log_target,
+                            "Failed to query BEEFY validator set: {}", error
+                        );
+                        continue;
+                    }
+                };
+
+                // The runtime function returns an `Option<ValidatorSet>`.
+                match validator_set.split_first() {
+                    Some((1, encoded_set)) => match beefy::decode_validator_set(encoded_set) {
+                        Ok(set) => {
+                            log::debug!(
+                                target: &state.log_target,
+                                "BEEFY validator set updated. Id: {}. Number of validators: {}",
+                                set.id, set.validators.len()
+                            );
+                            if !state
+                                .known_validator_sets
+                                .iter()
+                                .any(|(id, _)| *id == set.id)
+                            {
+                                if state.known_validator_sets.len() >= 2 {
+                                    state.known_validator_sets.remove(0);
+                                }
+                                state
+                                    .known_validator_sets
+                                    .push((set.id, set.validators.into_iter().copied().collect()));
+                                state.known_validator_sets.sort_by_key(|(id, _)| *id);
+                            }
+                        }
+                        Err(error) => {
+                            log::warn!(
+                                target: &state.log_target,
+                                "Failed to decode BEEFY validator set: {}", error
+                            );
+                        }
+                    },
+                    Some((0, [])) => {
+                        log::debug!(
+                            target: &state.log_target,
+                            "BEEFY isn't enabled on the chain"
+                        );
+                    }
+                    _ => {
+                        log::warn!(
+                            target: &state.log_target,
+                            "Failed to decode BEEFY validator set"
+                        );
+                    }
+                }
+            }
+
+            let Some((_, validators)) = state
+                .known_validator_sets
+                .iter()
+                .find(|(id, _)| *id == commitment.validator_set_id)
+            else {
+                log::debug!(
+                    target: &state.log_target,
+                    "Discarding BEEFY finality proof of block #{} from unknown validator set {}",
+                    commitment.block_number, commitment.validator_set_id
+                );
+                continue;
+            };
+
+            if let Err(error) = beefy::verify(beefy::VerifyConfig {
+                signed_commitment: &decoded,
+                validator_set_id: commitment.validator_set_id,
+                validators: validators.iter(),
+            }) {
+                log::debug!(
+                    target: &state.log_target,
+                    "Discarding invalid BEEFY finality proof of block #{}: {}",
+                    commitment.block_number, error
+                );
+                continue;
+            }
+
+            log::debug!(
+                target: &state.log_target,
+                "BEEFY finality proof verified. Block: #{}. Validator set: {}",
+                commitment.block_number, commitment.validator_set_id
+            );
+
+            let item = FinalityProof {
+                block_number: commitment.block_number,
+                validator_set_id: commitment.validator_set_id,
+                mmr_root: commitment.mmr_root().copied(),
+                scale_encoded_versioned_finality_proof: proof.as_encoded().to_vec(),
+            };
+            state.last_reported_block = Some(item.block_number);
+            break Some((item, state));
+        }
+    })
+}
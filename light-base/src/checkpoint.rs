@@ -0,0 +1,616 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checkpoints encoding and decoding.
+//!
+//! A checkpoint is a small JSON document describing the finalized block of a chain and
+//! everything that is necessary in order to verify the finality of its descendants: the header
+//! of the finalized block, the list of GrandPa authorities, the BABE or Aura epochs, etc.
+//!
+//! Contrary to the so-called database (see the [`crate::database`] module), the format of a
+//! checkpoint is versioned and stable, and decoding errors are reported rather than silently
+//! ignored. Checkpoints are meant to be embedded within applications.
+//!
+//! The format is defined entirely in this module, and doesn't depend on the format of the
+//! database. Checkpoints look like this:
+//!
+//! ```json
+//! {
+//!     "version": 1,
+//!     "genesisHash": "<hex>",
+//!     "finalizedBlockHeader": "<hex SCALE-encoded header>",
+//!     "consensus": {
+//!         "type": "babe",
+//!         "slotsPerEpoch": 2400,
+//!         "currentEpoch": { ... },
+//!         "nextEpoch": { ... }
+//!     },
+//!     "finality": {
+//!         "type": "grandpa",
+//!         "setId": 3,
+//!         "authorities": [ ... ],
+//!         "scheduledChange": null
+//!     }
+//! }
+//! ```
+
+use alloc::{string::String, vec::Vec};
+use core::num::NonZeroU64;
+use smoldot::{
+    chain::chain_information::{
+        self, BabeEpochInformation, ChainInformation, ChainInformationConsensus,
+        ChainInformationFinality,
+    },
+    header,
+};
+
+/// Version of the checkpoint format generated by [`encode_checkpoint`].
+const CURRENT_VERSION: u32 = 1;
+
+/// Encodes a checkpoint containing the given chain information.
+pub fn encode_checkpoint<'a>(
+    genesis_block_hash: &[u8; 32],
+    chain_information: impl Into<chain_information::ValidChainInformationRef<'a>>,
+    block_number_bytes: usize,
+) -> String {
+    let chain_information = ChainInformation::from(chain_information::ValidChainInformation::from(
+        chain_information.into(),
+    ));
+
+    serde_json::to_string(&SerdeCheckpoint {
+        version: CURRENT_VERSION,
+        genesis_hash: hex::encode(genesis_block_hash),
+        finalized_block_header: hex::encode(
+            chain_information
+                .finalized_block_header
+                .scale_encoding_vec(block_number_bytes),
+        ),
+        consensus: match chain_information.consensus {
+            ChainInformationConsensus::Unknown => SerdeConsensus::Unknown,
+            ChainInformationConsensus::Aura {
+                finalized_authorities_list,
+                slot_duration,
+            } => SerdeConsensus::Aura {
+                slot_duration: slot_duration.get(),
+                authorities: finalized_authorities_list
+                    .iter()
+                    .map(|authority| hex::encode(authority.public_key))
+                    .collect(),
+            },
+            ChainInformationConsensus::Babe {
+                slots_per_epoch,
+                finalized_block_epoch_information,
+                finalized_next_epoch_transition,
+            } => SerdeConsensus::Babe {
+                slots_per_epoch: slots_per_epoch.get(),
+                current_epoch: finalized_block_epoch_information.as_ref().map(Into::into),
+                next_epoch: (&finalized_next_epoch_transition).into(),
+            },
+        },
+        finality: match chain_information.finality {
+            ChainInformationFinality::Outsourced => SerdeFinality::Outsourced,
+            ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                finalized_scheduled_change,
+            } => SerdeFinality::Grandpa {
+                set_id: after_finalized_block_authorities_set_id,
+                authorities: finalized_triggered_authorities
+                    .iter()
+                    .map(Into::into)
+                    .collect(),
+                scheduled_change: finalized_scheduled_change.map(|(trigger_block_number, list)| {
+                    SerdeGrandpaScheduledChange {
+                        trigger_block_number,
+                        authorities: list.iter().map(Into::into).collect(),
+                    }
+                }),
+            },
+        },
+    })
+    .unwrap()
+}
+
+/// Decodes a checkpoint previously generated with [`encode_checkpoint`].
+///
+/// Must be passed the number of bytes used to encode the number of a block for the given chain.
+///
+/// On success, returns the hash of the genesis block of the chain the checkpoint belongs to and
+/// the information about the finalized block.
+pub fn decode_checkpoint(
+    encoded: &str,
+    block_number_bytes: usize,
+) -> Result<([u8; 32], chain_information::ValidChainInformation), DecodeError> {
+    // The version is decoded separately first, so that checkpoints generated by a more recent
+    // version of the client are reported as such even if the rest of their format is unknown.
+    let version = serde_json::from_str::<SerdeVersion>(encoded)
+        .map_err(|_| DecodeError::InvalidFormat)?
+        .version;
+    if version != CURRENT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let decoded: SerdeCheckpoint =
+        serde_json::from_str(encoded).map_err(|_| DecodeError::InvalidFormat)?;
+
+    let genesis_block_hash = decode_hash(&decoded.genesis_hash)?;
+
+    let finalized_block_header = header::decode(
+        &hex::decode(&decoded.finalized_block_header).map_err(|_| DecodeError::InvalidFormat)?,
+        block_number_bytes,
+    )
+    .map_err(|_| DecodeError::InvalidFormat)?
+    .into();
+
+    let consensus = match decoded.consensus {
+        SerdeConsensus::Unknown => ChainInformationConsensus::Unknown,
+        SerdeConsensus::Aura {
+            slot_duration,
+            authorities,
+        } => ChainInformationConsensus::Aura {
+            slot_duration: NonZeroU64::new(slot_duration).ok_or(DecodeError::InvalidFormat)?,
+            finalized_authorities_list: authorities
+                .iter()
+                .map(|public_key| {
+                    Ok(header::AuraAuthority {
+                        public_key: decode_hash(public_key)?,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        },
+        SerdeConsensus::Babe {
+            slots_per_epoch,
+            current_epoch,
+            next_epoch,
+        } => ChainInformationConsensus::Babe {
+            slots_per_epoch: NonZeroU64::new(slots_per_epoch).ok_or(DecodeError::InvalidFormat)?,
+            finalized_block_epoch_information: current_epoch
+                .map(SerdeBabeEpoch::decode)
+                .transpose()?,
+            finalized_next_epoch_transition: next_epoch.decode()?,
+        },
+    };
+
+    let finality = match decoded.finality {
+        SerdeFinality::Outsourced => ChainInformationFinality::Outsourced,
+        SerdeFinality::Grandpa {
+            set_id,
+            authorities,
+            scheduled_change,
+        } => ChainInformationFinality::Grandpa {
+            after_finalized_block_authorities_set_id: set_id,
+            finalized_triggered_authorities: authorities
+                .iter()
+                .map(SerdeGrandpaAuthority::decode)
+                .collect::<Result<_, _>>()?,
+            finalized_scheduled_change: scheduled_change
+                .map(|change| {
+                    Ok::<_, DecodeError>((
+                        change.trigger_block_number,
+                        change
+                            .authorities
+                            .iter()
+                            .map(SerdeGrandpaAuthority::decode)
+                            .collect::<Result<_, _>>()?,
+                    ))
+                })
+                .transpose()?,
+        },
+    };
+
+    let chain_information = chain_information::ValidChainInformation::try_from(ChainInformation {
+        finalized_block_header,
+        consensus,
+        finality,
+    })
+    .map_err(DecodeError::InvalidChainInformation)?;
+
+    Ok((genesis_block_hash, chain_information))
+}
+
+/// Error potentially returned by [`decode_checkpoint`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeError {
+    /// Checkpoint isn't a valid JSON document of the expected format.
+    #[display(fmt = "Invalid checkpoint format")]
+    InvalidFormat,
+    /// Checkpoint has been generated by a more recent version of the client.
+    #[display(fmt = "Unsupported checkpoint version: {_0}")]
+    UnsupportedVersion(u32),
+    /// The information about the finalized block is inconsistent.
+    #[display(fmt = "{_0}")]
+    InvalidChainInformation(chain_information::ValidityError),
+}
+
+/// Decodes a hexadecimal-encoded 32 bytes value without `0x` prefix.
+fn decode_hash(hash: &str) -> Result<[u8; 32], DecodeError> {
+    let decoded = hex::decode(hash).map_err(|_| DecodeError::InvalidFormat)?;
+    <[u8; 32]>::try_from(decoded).map_err(|_| DecodeError::InvalidFormat)
+}
+
+#[derive(serde::Deserialize)]
+struct SerdeVersion {
+    version: u32,
+}
+
+// All the hashes, public keys and headers below are hexadecimal-encoded, and have no `0x`
+// prefix. Headers are SCALE-encoded.
+//
+// The format below must only ever be modified in a backwards-compatible way, or by increasing
+// `CURRENT_VERSION` and keeping the ability to decode the previous versions.
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeCheckpoint {
+    version: u32,
+    #[serde(rename = "genesisHash")]
+    genesis_hash: String,
+    #[serde(rename = "finalizedBlockHeader")]
+    finalized_block_header: String,
+    consensus: SerdeConsensus,
+    finality: SerdeFinality,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum SerdeConsensus {
+    #[serde(rename = "unknown")]
+    Unknown,
+    #[serde(rename = "aura")]
+    Aura {
+        #[serde(rename = "slotDuration")]
+        slot_duration: u64,
+        authorities: Vec<String>,
+    },
+    #[serde(rename = "babe")]
+    Babe {
+        #[serde(rename = "slotsPerEpoch")]
+        slots_per_epoch: u64,
+        #[serde(rename = "currentEpoch")]
+        current_epoch: Option<SerdeBabeEpoch>,
+        #[serde(rename = "nextEpoch")]
+        next_epoch: SerdeBabeEpoch,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeBabeEpoch {
+    #[serde(rename = "epochIndex")]
+    epoch_index: u64,
+    #[serde(rename = "startSlotNumber")]
+    start_slot_number: Option<u64>,
+    authorities: Vec<SerdeBabeAuthority>,
+    randomness: String,
+    c: (u64, u64),
+    #[serde(rename = "allowedSlots")]
+    allowed_slots: SerdeBabeAllowedSlots,
+}
+
+impl<'a> From<&'a BabeEpochInformation> for SerdeBabeEpoch {
+    fn from(epoch: &'a BabeEpochInformation) -> Self {
+        SerdeBabeEpoch {
+            epoch_index: epoch.epoch_index,
+            start_slot_number: epoch.start_slot_number,
+            authorities: epoch
+                .authorities
+                .iter()
+                .map(|authority| SerdeBabeAuthority {
+                    public_key: hex::encode(authority.public_key),
+                    weight: authority.weight,
+                })
+                .collect(),
+            randomness: hex::encode(epoch.randomness),
+            c: epoch.c,
+            allowed_slots: match epoch.allowed_slots {
+                header::BabeAllowedSlots::PrimarySlots => SerdeBabeAllowedSlots::Primary,
+                header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots => {
+                    SerdeBabeAllowedSlots::PrimaryAndSecondaryPlain
+                }
+                header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots => {
+                    SerdeBabeAllowedSlots::PrimaryAndSecondaryVrf
+                }
+            },
+        }
+    }
+}
+
+impl SerdeBabeEpoch {
+    fn decode(self) -> Result<BabeEpochInformation, DecodeError> {
+        Ok(BabeEpochInformation {
+            epoch_index: self.epoch_index,
+            start_slot_number: self.start_slot_number,
+            authorities: self
+                .authorities
+                .iter()
+                .map(|authority| {
+                    Ok(header::BabeAuthority {
+                        public_key: decode_hash(&authority.public_key)?,
+                        weight: authority.weight,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            randomness: decode_hash(&self.randomness)?,
+            c: self.c,
+            allowed_slots: match self.allowed_slots {
+                SerdeBabeAllowedSlots::Primary => header::BabeAllowedSlots::PrimarySlots,
+                SerdeBabeAllowedSlots::PrimaryAndSecondaryPlain => {
+                    header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots
+                }
+                SerdeBabeAllowedSlots::PrimaryAndSecondaryVrf => {
+                    header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots
+                }
+            },
+        })
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeBabeAuthority {
+    #[serde(rename = "publicKey")]
+    public_key: String,
+    weight: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum SerdeBabeAllowedSlots {
+    #[serde(rename = "primary")]
+    Primary,
+    #[serde(rename = "primaryAndSecondaryPlain")]
+    PrimaryAndSecondaryPlain,
+    #[serde(rename = "primaryAndSecondaryVrf")]
+    PrimaryAndSecondaryVrf,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum SerdeFinality {
+    #[serde(rename = "outsourced")]
+    Outsourced,
+    #[serde(rename = "grandpa")]
+    Grandpa {
+        #[serde(rename = "setId")]
+        set_id: u64,
+        authorities: Vec<SerdeGrandpaAuthority>,
+        #[serde(rename = "scheduledChange")]
+        scheduled_change: Option<SerdeGrandpaScheduledChange>,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeGrandpaScheduledChange {
+    #[serde(rename = "triggerBlockNumber")]
+    trigger_block_number: u64,
+    authorities: Vec<SerdeGrandpaAuthority>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeGrandpaAuthority {
+    #[serde(rename = "publicKey")]
+    public_key: String,
+    weight: u64,
+}
+
+impl<'a> From<&'a header::GrandpaAuthority> for SerdeGrandpaAuthority {
+    fn from(authority: &'a header::GrandpaAuthority) -> Self {
+        SerdeGrandpaAuthority {
+            public_key: hex::encode(authority.public_key),
+            weight: authority.weight.get(),
+        }
+    }
+}
+
+impl SerdeGrandpaAuthority {
+    fn decode(&self) -> Result<header::GrandpaAuthority, DecodeError> {
+        Ok(header::GrandpaAuthority {
+            public_key: decode_hash(&self.public_key)?,
+            weight: NonZeroU64::new(self.weight).ok_or(DecodeError::InvalidFormat)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_checkpoint, encode_checkpoint, DecodeError};
+    use alloc::vec;
+    use core::num::NonZeroU64;
+    use smoldot::{
+        chain::chain_information::{
+            BabeEpochInformation, ChainInformation, ChainInformationConsensus,
+            ChainInformationFinality, ValidChainInformation,
+        },
+        header,
+    };
+
+    fn finalized_block_header(number: u64) -> header::Header {
+        header::Header {
+            parent_hash: [1; 32],
+            number,
+            state_root: [2; 32],
+            extrinsics_root: [3; 32],
+            digest: header::DigestRef::empty().into(),
+        }
+    }
+
+    fn babe_grandpa() -> ValidChainInformation {
+        let epoch = |epoch_index, start_slot_number| BabeEpochInformation {
+            epoch_index,
+            start_slot_number: Some(start_slot_number),
+            authorities: vec![header::BabeAuthority {
+                public_key: [4; 32],
+                weight: 1,
+            }],
+            randomness: [5; 32],
+            c: (1, 4),
+            allowed_slots: header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots,
+        };
+
+        ValidChainInformation::try_from(ChainInformation {
+            finalized_block_header: finalized_block_header(10),
+            consensus: ChainInformationConsensus::Babe {
+                slots_per_epoch: NonZeroU64::new(2400).unwrap(),
+                finalized_block_epoch_information: Some(epoch(4, 100)),
+                finalized_next_epoch_transition: epoch(5, 2500),
+            },
+            finality: ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id: 3,
+                finalized_triggered_authorities: vec![
+                    header::GrandpaAuthority {
+                        public_key: [6; 32],
+                        weight: NonZeroU64::new(1).unwrap(),
+                    },
+                    header::GrandpaAuthority {
+                        public_key: [7; 32],
+                        weight: NonZeroU64::new(2).unwrap(),
+                    },
+                ],
+                finalized_scheduled_change: Some((
+                    20,
+                    vec![header::GrandpaAuthority {
+                        public_key: [8; 32],
+                        weight: NonZeroU64::new(1).unwrap(),
+                    }],
+                )),
+            },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn babe_grandpa_roundtrip() {
+        let encoded = encode_checkpoint(&[9; 32], &babe_grandpa(), 4);
+        let (genesis_hash, decoded) = decode_checkpoint(&encoded, 4).unwrap();
+        assert_eq!(genesis_hash, [9; 32]);
+        assert_eq!(encode_checkpoint(&genesis_hash, &decoded, 4), encoded);
+
+        let ChainInformation {
+            finalized_block_header,
+            consensus,
+            finality,
+        } = decoded.into();
+        assert_eq!(finalized_block_header.number, 10);
+        let ChainInformationConsensus::Babe {
+            finalized_block_epoch_information: Some(current_epoch),
+            finalized_next_epoch_transition,
+            ..
+        } = consensus
+        else {
+            panic!()
+        };
+        assert_eq!(current_epoch.epoch_index, 4);
+        assert_eq!(
+            finalized_next_epoch_transition.start_slot_number,
+            Some(2500)
+        );
+        let ChainInformationFinality::Grandpa {
+            after_finalized_block_authorities_set_id,
+            finalized_triggered_authorities,
+            finalized_scheduled_change: Some((trigger_block_number, _)),
+        } = finality
+        else {
+            panic!()
+        };
+        assert_eq!(after_finalized_block_authorities_set_id, 3);
+        assert_eq!(finalized_triggered_authorities.len(), 2);
+        assert_eq!(trigger_block_number, 20);
+    }
+
+    #[test]
+    fn aura_outsourced_roundtrip() {
+        let chain_information = ValidChainInformation::try_from(ChainInformation {
+            finalized_block_header: finalized_block_header(0),
+            consensus: ChainInformationConsensus::Aura {
+                finalized_authorities_list: vec![header::AuraAuthority {
+                    public_key: [4; 32],
+                }],
+                slot_duration: NonZeroU64::new(6000).unwrap(),
+            },
+            finality: ChainInformationFinality::Outsourced,
+        })
+        .unwrap();
+
+        let encoded = encode_checkpoint(&[9; 32], &chain_information, 8);
+        let (genesis_hash, decoded) = decode_checkpoint(&encoded, 8).unwrap();
+        assert_eq!(genesis_hash, [9; 32]);
+        assert_eq!(encode_checkpoint(&genesis_hash, &decoded, 8), encoded);
+    }
+
+    #[test]
+    fn format_is_stable() {
+        // The encoded format must not change without increasing the version.
+        let encoded = encode_checkpoint(&[9; 32], &babe_grandpa(), 4);
+        let value = serde_json::from_str::<serde_json::Value>(&encoded).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["genesisHash"], hex::encode([9; 32]));
+        assert_eq!(value["consensus"]["type"], "babe");
+        assert_eq!(value["consensus"]["slotsPerEpoch"], 2400);
+        assert_eq!(value["consensus"]["nextEpoch"]["epochIndex"], 5);
+        assert_eq!(
+            value["consensus"]["nextEpoch"]["allowedSlots"],
+            "primaryAndSecondaryVrf"
+        );
+        assert_eq!(value["finality"]["type"], "grandpa");
+        assert_eq!(value["finality"]["setId"], 3);
+        assert_eq!(
+            value["finality"]["scheduledChange"]["triggerBlockNumber"],
+            20
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(matches!(
+            decode_checkpoint(r#"{"version":2,"somethingElse":true}"#, 4),
+            Err(DecodeError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn invalid_format() {
+        let encoded = encode_checkpoint(&[9; 32], &babe_grandpa(), 4);
+        let mut value = serde_json::from_str::<serde_json::Value>(&encoded).unwrap();
+        value["genesisHash"] = serde_json::Value::from("0x1234");
+
+        for invalid in [
+            "",
+            "{}",
+            r#"{"version":1}"#,
+            &serde_json::to_string(&value).unwrap(),
+        ] {
+            assert!(matches!(
+                decode_checkpoint(invalid, 4),
+                Err(DecodeError::InvalidFormat)
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_chain_information() {
+        // The GrandPa scheduled change is before the finalized block.
+        let encoded = encode_checkpoint(&[9; 32], &babe_grandpa(), 4);
+        let mut value = serde_json::from_str::<serde_json::Value>(&encoded).unwrap();
+        value["finality"]["scheduledChange"]["triggerBlockNumber"] = serde_json::Value::from(5);
+
+        assert!(matches!(
+            decode_checkpoint(&serde_json::to_string(&value).unwrap(), 4),
+            Err(DecodeError::InvalidChainInformation(_))
+        ));
+    }
+}
@@ -23,7 +23,12 @@
 //! It can later de-serialize this database.
 //!
 //! This database doesn't contain just the state of the finalized block, but also other
-//! information. See [`DatabaseContent`].
+//! information, such as the progress of the GrandPa warp syncing if it is in progress, the
+//! headers of the most recently finalized blocks, and the hash of the runtime code of the
+//! finalized block. See [`DatabaseContent`].
+//!
+//! The second version of the format only adds optional fields to the first version. Databases
+//! encoded using the first version can still be decoded.
 //!
 //! This module provides the function to encode and decode this so-called database.
 
@@ -34,14 +39,20 @@ use alloc::{
     vec::Vec,
 };
 use core::cmp;
+use core::num::NonZeroU64;
 use smoldot::{
     chain,
     database::finalized_serialize,
+    header,
     libp2p::{multiaddr, PeerId},
+    sync::all,
 };
 
 use crate::{network_service, platform, sync_service};
 
+/// Maximum number of headers of recently-finalized blocks that a database contains.
+pub const MAX_RECENT_FINALIZED_HEADERS: usize = 32;
+
 /// A decoded database.
 pub struct DatabaseContent {
     /// Hash of the genesis block, as provided to [`encode_database`].
@@ -51,27 +62,39 @@ pub struct DatabaseContent {
     /// List of nodes that were known to be part of the peer-to-peer network when the database
     /// was encoded.
     pub known_nodes: Vec<(PeerId, Vec<multiaddr::Multiaddr>)>,
+    /// Progress of the GrandPa warp syncing when the database was encoded, if it was in
+    /// progress. Can be passed to [`sync_service::Config::warp_sync_resume_progress`].
+    pub warp_sync_progress: Option<all::WarpSyncVerifiedProgress>,
+    /// SCALE-encoded headers of the most recently finalized blocks when the database was
+    /// encoded, ordered by increasing block number. Each header is guaranteed to be the parent
+    /// of the next one. Empty if the database doesn't contain any header.
+    pub recent_finalized_headers: Vec<Vec<u8>>,
+    /// Hash of the runtime code of the finalized block when the database was encoded, if known.
+    pub finalized_runtime_code_hash: Option<[u8; 32]>,
 }
 
 /// Serializes the finalized state of the chain, using the given services.
 ///
+/// `recent_finalized_headers` must contain SCALE-encoded headers of recently-finalized blocks,
+/// ordered by increasing block number, and can be empty. `finalized_runtime_code_hash` is the
+/// hash of the runtime code of the finalized block, if known.
+///
 /// The returned string is guaranteed to not exceed `max_size` bytes. A truncated or invalid
 /// database is intentionally returned if `max_size` is too low to fit all the information.
 pub async fn encode_database<TPlat: platform::Platform>(
     network_service: &network_service::NetworkService<TPlat>,
     sync_service: &sync_service::SyncService<TPlat>,
     genesis_block_hash: &[u8; 32],
+    recent_finalized_headers: impl Iterator<Item = impl AsRef<[u8]>>,
+    finalized_runtime_code_hash: Option<[u8; 32]>,
     max_size: usize,
 ) -> String {
     // Craft the structure containing all the data that we would like to include.
     let mut database_draft = SerdeDatabase {
+        version: Some(2),
         genesis_hash: hex::encode(genesis_block_hash),
-        chain: match sync_service.serialize_chain_information().await {
-            Some(ci) => {
-                let encoded =
-                    finalized_serialize::encode_chain(&ci, sync_service.block_number_bytes());
-                serde_json::from_str(&encoded).unwrap()
-            }
+        chain: match serde_chain(sync_service).await {
+            Some(chain) => chain,
             None => {
                 // If the chain information can't be obtained, we just return a dummy value that
                 // will intentionally fail to decode if passed back.
@@ -83,16 +106,10 @@ pub async fn encode_database<TPlat: platform::Platform>(
                 };
             }
         },
-        nodes: network_service
-            .discovered_nodes(0) // TODO: hacky chain_index
-            .await
-            .map(|(peer_id, addrs)| {
-                (
-                    peer_id.to_base58(),
-                    addrs.map(|a| a.to_string()).collect::<Vec<_>>(),
-                )
-            })
-            .collect(),
+        nodes: serde_nodes(network_service).await,
+        warp_sync: serde_warp_sync(sync_service).await,
+        recent_finalized_headers: recent_finalized_headers.map(hex::encode).collect(),
+        runtime_code_hash: finalized_runtime_code_hash.map(hex::encode),
     };
 
     // Cap the database length to the maximum size.
@@ -103,6 +120,22 @@ pub async fn encode_database<TPlat: platform::Platform>(
             return serialized;
         }
 
+        // The recent headers are only an optimization and are removed first. The oldest headers
+        // are removed before the most recent ones.
+        if !database_draft.recent_finalized_headers.is_empty() {
+            let num_to_remove = cmp::max(1, database_draft.recent_finalized_headers.len() / 2);
+            database_draft
+                .recent_finalized_headers
+                .drain(..num_to_remove);
+            continue;
+        }
+
+        // The warp sync progress is only removed if removing the nodes isn't enough.
+        if database_draft.nodes.is_empty() && database_draft.warp_sync.is_some() {
+            database_draft.warp_sync = None;
+            continue;
+        }
+
         if database_draft.nodes.is_empty() {
             // Can't shrink the database anymore. Return the string `"<too-large>"` which will
             // fail to decode but will indicate what is wrong.
@@ -130,6 +163,259 @@ pub async fn encode_database<TPlat: platform::Platform>(
     }
 }
 
+/// Change to apply to a database in order to bring it up to date.
+///
+/// Contrary to a database, which contains the entire state of the chain, a delta only contains
+/// what has changed since the previous delta. Deltas can be stored one after the other, then
+/// merged into a database with [`compact_database`].
+#[derive(Debug, Clone)]
+pub struct DatabaseDelta {
+    encoded: String,
+}
+
+impl DatabaseDelta {
+    /// Returns the string representation of this delta, which can later be passed to
+    /// [`compact_database`].
+    pub fn as_str(&self) -> &str {
+        &self.encoded
+    }
+}
+
+impl AsRef<str> for DatabaseDelta {
+    fn as_ref(&self) -> &str {
+        &self.encoded
+    }
+}
+
+impl From<DatabaseDelta> for String {
+    fn from(delta: DatabaseDelta) -> String {
+        delta.encoded
+    }
+}
+
+/// State of a stream of [`DatabaseDelta`]s, used to determine what has changed since the
+/// previous delta of the stream.
+#[derive(Default)]
+pub struct DatabaseDeltaState {
+    /// Fields of the chain information reported through the previous deltas. `None` if no delta
+    /// has been generated yet.
+    reported_chain: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Nodes reported through the previous deltas.
+    reported_nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    /// Warp sync progress reported through the previous deltas.
+    reported_warp_sync: Option<serde_json::Value>,
+    /// Runtime code hash reported through the previous deltas.
+    reported_runtime_code_hash: Option<String>,
+}
+
+/// Generates a [`DatabaseDelta`] describing what has changed in the finalized state of the chain
+/// since the previous delta, using the given services.
+///
+/// `new_finalized_headers` must contain the SCALE-encoded headers of the blocks that have been
+/// finalized since the previous delta, ordered by increasing block number.
+/// `state` must be the same for all the deltas of a stream, and is updated by this function. The
+/// first delta generated with a given `state` contains the entire state of the chain.
+///
+/// Returns `None` if the chain information can't be obtained.
+pub async fn encode_database_delta<TPlat: platform::Platform>(
+    network_service: &network_service::NetworkService<TPlat>,
+    sync_service: &sync_service::SyncService<TPlat>,
+    genesis_block_hash: &[u8; 32],
+    new_finalized_headers: impl Iterator<Item = impl AsRef<[u8]>>,
+    finalized_runtime_code_hash: Option<[u8; 32]>,
+    state: &mut DatabaseDeltaState,
+) -> Option<DatabaseDelta> {
+    let chain = serde_chain(sync_service).await?;
+    let nodes = serde_nodes(network_service).await;
+    let warp_sync = serde_warp_sync(sync_service).await;
+
+    Some(delta_from_state(
+        state,
+        genesis_block_hash,
+        new_finalized_headers.map(hex::encode).collect(),
+        &chain,
+        nodes,
+        warp_sync,
+        finalized_runtime_code_hash.map(hex::encode),
+    ))
+}
+
+/// Builds a [`DatabaseDelta`] from the current state of the chain and updates `state`
+/// accordingly.
+fn delta_from_state(
+    state: &mut DatabaseDeltaState,
+    genesis_block_hash: &[u8; 32],
+    new_finalized_headers: Vec<String>,
+    chain: &serde_json::value::RawValue,
+    nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    warp_sync: Option<serde_json::Value>,
+    runtime_code_hash: Option<String>,
+) -> DatabaseDelta {
+    // The first delta of a stream replaces what the database contains.
+    let reset = state.reported_chain.is_none();
+
+    // The chain information is a JSON object, and only its fields that have changed are
+    // included. In practice, this is most of the time only the header of the finalized block.
+    let chain = match serde_json::from_str::<serde_json::Value>(chain.get()).unwrap() {
+        serde_json::Value::Object(fields) => fields,
+        _ => unreachable!(),
+    };
+    let reported_chain = state.reported_chain.get_or_insert_with(Default::default);
+    let chain_removed_fields = reported_chain
+        .keys()
+        .filter(|field| !chain.contains_key(*field))
+        .cloned()
+        .collect::<Vec<_>>();
+    let chain_changes = chain
+        .iter()
+        .filter(|(field, value)| reported_chain.get(*field) != Some(value))
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect::<serde_json::Map<_, _>>();
+    *reported_chain = chain;
+
+    let removed_nodes = state
+        .reported_nodes
+        .keys()
+        .filter(|peer_id| !nodes.contains_key(*peer_id))
+        .cloned()
+        .collect::<Vec<_>>();
+    let added_nodes = nodes
+        .iter()
+        .filter(|(peer_id, addrs)| state.reported_nodes.get(*peer_id) != Some(addrs))
+        .map(|(peer_id, addrs)| (peer_id.clone(), addrs.clone()))
+        .collect::<hashbrown::HashMap<_, _, fnv::FnvBuildHasher>>();
+    state.reported_nodes = nodes;
+
+    let warp_sync_changed = reset || state.reported_warp_sync != warp_sync;
+    let warp_sync_removed = warp_sync_changed && warp_sync.is_none() && !reset;
+    state.reported_warp_sync = warp_sync.clone();
+
+    // A runtime code hash that isn't known doesn't overwrite the one already reported.
+    let runtime_code_hash = runtime_code_hash
+        .filter(|hash| reset || state.reported_runtime_code_hash.as_ref() != Some(hash));
+    if runtime_code_hash.is_some() {
+        state.reported_runtime_code_hash = runtime_code_hash.clone();
+    }
+
+    let delta = SerdeDatabaseDelta {
+        genesis_hash: hex::encode(genesis_block_hash),
+        reset,
+        chain_changes,
+        chain_removed_fields,
+        new_finalized_headers,
+        added_nodes,
+        removed_nodes,
+        warp_sync: if warp_sync_changed { warp_sync } else { None },
+        warp_sync_removed,
+        runtime_code_hash,
+    };
+
+    DatabaseDelta {
+        encoded: serde_json::to_string(&delta).unwrap(),
+    }
+}
+
+/// Applies the given deltas, in order, to the given database, and returns the updated database.
+///
+/// `database` can be empty or invalid, in which case the returned database is built from the
+/// deltas alone. Deltas that fail to decode are ignored. A delta concerning a different chain
+/// than the database replaces the database entirely.
+///
+/// Because the first delta generated by a stream of deltas contains the entire state of the
+/// chain, applying all the deltas of a stream to any database leads to a valid database.
+pub fn compact_database(
+    database: &str,
+    deltas: impl IntoIterator<Item = impl AsRef<str>>,
+) -> String {
+    let mut database = serde_json::from_str::<SerdeDatabase>(database).ok();
+
+    // The chain information is modified field by field. In order to avoid parsing and
+    // serializing it for each delta, it is kept decoded until all the deltas have been applied.
+    let mut chain: serde_json::Map<String, serde_json::Value> = database
+        .as_ref()
+        .and_then(|database| serde_json::from_str(database.chain.get()).ok())
+        .unwrap_or_default();
+
+    for delta in deltas {
+        let Ok(delta) = serde_json::from_str::<SerdeDatabaseDelta>(delta.as_ref()) else {
+            continue;
+        };
+
+        if database
+            .as_ref()
+            .map_or(true, |database| database.genesis_hash != delta.genesis_hash)
+        {
+            database = Some(SerdeDatabase {
+                version: Some(2),
+                genesis_hash: delta.genesis_hash.clone(),
+                chain: serde_json::value::RawValue::from_string("{}".to_owned()).unwrap(),
+                nodes: Default::default(),
+                warp_sync: None,
+                recent_finalized_headers: Vec::new(),
+                runtime_code_hash: None,
+            });
+            chain = Default::default();
+        }
+        let database = database.as_mut().unwrap();
+
+        if delta.reset {
+            chain.clear();
+            database.nodes.clear();
+            database.warp_sync = None;
+            database.runtime_code_hash = None;
+        }
+
+        database.version = Some(2);
+        for field in &delta.chain_removed_fields {
+            chain.remove(field);
+        }
+        chain.extend(delta.chain_changes);
+        if delta.warp_sync.is_some() || delta.warp_sync_removed {
+            database.warp_sync = delta.warp_sync;
+        }
+        if delta.runtime_code_hash.is_some() {
+            database.runtime_code_hash = delta.runtime_code_hash;
+        }
+
+        for peer_id in &delta.removed_nodes {
+            database.nodes.remove(peer_id);
+        }
+        database.nodes.extend(delta.added_nodes);
+
+        // The headers of the database must form a chain. If the new headers don't directly
+        // follow the ones already in the database, the latter are discarded.
+        for header in delta.new_finalized_headers {
+            let Ok(decoded) = hex::decode(&header) else {
+                continue;
+            };
+
+            let follows_previous = database
+                .recent_finalized_headers
+                .last()
+                .and_then(|previous| hex::decode(previous).ok())
+                .map_or(true, |previous| {
+                    decoded.get(..32)
+                        == Some(&header::hash_from_scale_encoded_header(&previous)[..])
+                });
+            if !follows_previous {
+                database.recent_finalized_headers.clear();
+            }
+
+            database.recent_finalized_headers.push(header);
+        }
+
+        let num_headers = database.recent_finalized_headers.len();
+        database
+            .recent_finalized_headers
+            .drain(..num_headers.saturating_sub(MAX_RECENT_FINALIZED_HEADERS));
+    }
+
+    database.map_or(String::new(), |mut database| {
+        database.chain = serde_json::value::to_raw_value(&chain).unwrap();
+        serde_json::to_string(&database).unwrap()
+    })
+}
+
 /// Tries to decode the given database.
 ///
 /// An error is returned if the data is in an invalid format.
@@ -165,18 +451,515 @@ pub fn decode_database(encoded: &str, block_number_bytes: usize) -> Result<Datab
         })
         .collect::<Vec<_>>();
 
+    // Similarly, the warp sync progress is simply ignored if it fails to decode. It is only an
+    // optimization.
+    let warp_sync_progress = decoded
+        .warp_sync
+        .and_then(|v| serde_json::from_value::<SerdeWarpSyncProgress>(v).ok())
+        .and_then(|progress| {
+            let header = header::decode(&hex::decode(&progress.header).ok()?, block_number_bytes)
+                .ok()?
+                .into();
+            let grandpa_authorities = progress
+                .grandpa_authorities
+                .iter()
+                .map(|(public_key, weight)| {
+                    Some(header::GrandpaAuthority {
+                        public_key: <[u8; 32]>::try_from(hex::decode(public_key).ok()?).ok()?,
+                        weight: NonZeroU64::new(*weight)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(all::WarpSyncVerifiedProgress {
+                header,
+                grandpa_authorities_set_id: progress.grandpa_set_id,
+                grandpa_authorities,
+            })
+        });
+
+    // The recent headers are also only an optimization. They are ignored altogether if any of
+    // them is invalid or if they don't form a chain.
+    let recent_finalized_headers = decoded
+        .recent_finalized_headers
+        .iter()
+        .map(|header| {
+            let header = hex::decode(header).ok()?;
+            header::decode(&header, block_number_bytes).ok()?;
+            Some(header)
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|headers| {
+            headers.windows(2).all(|pair| {
+                header::decode(&pair[1], block_number_bytes)
+                    .unwrap()
+                    .parent_hash
+                    == &header::hash_from_scale_encoded_header(&pair[0])
+            })
+        })
+        .unwrap_or_default();
+
+    let finalized_runtime_code_hash = decoded
+        .runtime_code_hash
+        .and_then(|hash| <[u8; 32]>::try_from(hex::decode(hash).ok()?).ok());
+
     Ok(DatabaseContent {
         genesis_block_hash,
         chain_information,
         known_nodes,
+        warp_sync_progress,
+        recent_finalized_headers,
+        finalized_runtime_code_hash,
+    })
+}
+
+/// Removes from the given [`DatabaseContent::recent_finalized_headers`] the headers that are
+/// descendants of the block whose hash is `finalized_block_hash`, and returns the remaining
+/// headers alongside with [`DatabaseContent::finalized_runtime_code_hash`].
+///
+/// The runtime code hash concerns the last header of the database, and is thus set to `None` if
+/// any header has been removed. If `finalized_block_hash` isn't found in the headers, the headers
+/// don't connect to this finalized block and an empty list and `None` are returned.
+pub fn recent_finalized_headers_up_to(
+    mut recent_finalized_headers: Vec<Vec<u8>>,
+    finalized_runtime_code_hash: Option<[u8; 32]>,
+    finalized_block_hash: &[u8; 32],
+) -> (Vec<Vec<u8>>, Option<[u8; 32]>) {
+    let Some(position) = recent_finalized_headers
+        .iter()
+        .position(|header| header::hash_from_scale_encoded_header(header) == *finalized_block_hash)
+    else {
+        return (Vec::new(), None);
+    };
+
+    let finalized_runtime_code_hash =
+        finalized_runtime_code_hash.filter(|_| position == recent_finalized_headers.len() - 1);
+    recent_finalized_headers.truncate(position + 1);
+    (recent_finalized_headers, finalized_runtime_code_hash)
+}
+
+/// Returns the chain information of the given sync service, in the format of
+/// [`SerdeDatabase::chain`]. Returns `None` if the chain information can't be obtained.
+async fn serde_chain<TPlat: platform::Platform>(
+    sync_service: &sync_service::SyncService<TPlat>,
+) -> Option<Box<serde_json::value::RawValue>> {
+    let chain_information = sync_service.serialize_chain_information().await?;
+    let encoded =
+        finalized_serialize::encode_chain(&chain_information, sync_service.block_number_bytes());
+    Some(serde_json::from_str(&encoded).unwrap())
+}
+
+/// Returns the nodes known by the given network service, in the format of
+/// [`SerdeDatabase::nodes`].
+async fn serde_nodes<TPlat: platform::Platform>(
+    network_service: &network_service::NetworkService<TPlat>,
+) -> hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher> {
+    network_service
+        .discovered_nodes(0) // TODO: hacky chain_index
+        .await
+        .map(|(peer_id, addrs)| {
+            (
+                peer_id.to_base58(),
+                addrs.map(|a| a.to_string()).collect::<Vec<_>>(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the progress of the warp syncing of the given sync service, in the format of
+/// [`SerdeDatabase::warp_sync`].
+async fn serde_warp_sync<TPlat: platform::Platform>(
+    sync_service: &sync_service::SyncService<TPlat>,
+) -> Option<serde_json::Value> {
+    sync_service.warp_sync_progress().await.map(|progress| {
+        serde_json::to_value(SerdeWarpSyncProgress {
+            header: hex::encode(
+                progress
+                    .header
+                    .scale_encoding_vec(sync_service.block_number_bytes()),
+            ),
+            grandpa_set_id: progress.grandpa_authorities_set_id,
+            grandpa_authorities: progress
+                .grandpa_authorities
+                .iter()
+                .map(|a| (hex::encode(a.public_key), a.weight.get()))
+                .collect(),
+        })
+        .unwrap()
     })
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct SerdeDatabase {
+    /// Version of the format. Absent in the first version of the format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u32>,
     /// Hexadecimal-encoded hash of the genesis block header. Has no `0x` prefix.
     #[serde(rename = "genesisHash")]
     genesis_hash: String,
     chain: Box<serde_json::value::RawValue>,
     nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    /// Decoded as a [`SerdeWarpSyncProgress`]. Kept as a JSON value so that failing to decode it
+    /// doesn't make the entire database invalid.
+    #[serde(rename = "warpSync", default, skip_serializing_if = "Option::is_none")]
+    warp_sync: Option<serde_json::Value>,
+    /// Hexadecimal-encoded SCALE-encoded headers of recently-finalized blocks, ordered by
+    /// increasing block number. Have no `0x` prefix. Absent in the first version of the format.
+    #[serde(
+        rename = "recentFinalizedHeaders",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    recent_finalized_headers: Vec<String>,
+    /// Hexadecimal-encoded hash of the runtime code of the finalized block. Has no `0x` prefix.
+    /// Absent in the first version of the format.
+    #[serde(
+        rename = "runtimeCodeHash",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    runtime_code_hash: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeWarpSyncProgress {
+    /// Hexadecimal-encoded SCALE-encoded header of the highest block whose finality has been
+    /// verified. Has no `0x` prefix.
+    header: String,
+    #[serde(rename = "grandpaSetId")]
+    grandpa_set_id: u64,
+    /// Hexadecimal-encoded public keys, without `0x` prefix, and weights.
+    #[serde(rename = "grandpaAuthorities")]
+    grandpa_authorities: Vec<(String, u64)>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeDatabaseDelta {
+    /// Hexadecimal-encoded hash of the genesis block header. Has no `0x` prefix.
+    #[serde(rename = "genesisHash")]
+    genesis_hash: String,
+    /// If `true`, the chain information, nodes, warp sync progress and runtime code hash of the
+    /// database are cleared before the rest of the delta is applied. Set in the first delta of a
+    /// stream.
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    reset: bool,
+    /// Fields inserted in the object found in [`SerdeDatabase::chain`], overwriting the
+    /// existing ones.
+    #[serde(
+        rename = "chainChanges",
+        default,
+        skip_serializing_if = "serde_json::Map::is_empty"
+    )]
+    chain_changes: serde_json::Map<String, serde_json::Value>,
+    /// Fields removed from the object found in [`SerdeDatabase::chain`].
+    #[serde(
+        rename = "chainRemovedFields",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    chain_removed_fields: Vec<String>,
+    /// Appended to [`SerdeDatabase::recent_finalized_headers`].
+    #[serde(
+        rename = "newFinalizedHeaders",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    new_finalized_headers: Vec<String>,
+    /// Inserted in [`SerdeDatabase::nodes`], overwriting the existing entries.
+    #[serde(
+        rename = "addedNodes",
+        default,
+        skip_serializing_if = "hashbrown::HashMap::is_empty"
+    )]
+    added_nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    /// Removed from [`SerdeDatabase::nodes`].
+    #[serde(
+        rename = "removedNodes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    removed_nodes: Vec<String>,
+    /// Replaces [`SerdeDatabase::warp_sync`] if present.
+    #[serde(rename = "warpSync", default, skip_serializing_if = "Option::is_none")]
+    warp_sync: Option<serde_json::Value>,
+    /// If `true`, [`SerdeDatabase::warp_sync`] is removed.
+    #[serde(
+        rename = "warpSyncRemoved",
+        default,
+        skip_serializing_if = "core::ops::Not::not"
+    )]
+    warp_sync_removed: bool,
+    /// Replaces [`SerdeDatabase::runtime_code_hash`] if present.
+    #[serde(
+        rename = "runtimeCodeHash",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    runtime_code_hash: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compact_database, delta_from_state, recent_finalized_headers_up_to, DatabaseDeltaState,
+        SerdeDatabase,
+    };
+    use alloc::{borrow::ToOwned as _, string::String, vec, vec::Vec};
+    use smoldot::header;
+
+    fn raw(json: &str) -> Box<serde_json::value::RawValue> {
+        serde_json::value::RawValue::from_string(json.to_owned()).unwrap()
+    }
+
+    fn nodes(
+        list: &[(&str, &[&str])],
+    ) -> hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher> {
+        list.iter()
+            .map(|(peer_id, addrs)| {
+                (
+                    (*peer_id).to_owned(),
+                    addrs.iter().map(|a| (*a).to_owned()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn decode(database: &str) -> (serde_json::Value, SerdeDatabase) {
+        let database = serde_json::from_str::<SerdeDatabase>(database).unwrap();
+        (
+            serde_json::from_str(database.chain.get()).unwrap(),
+            database,
+        )
+    }
+
+    #[test]
+    fn first_delta_builds_database() {
+        let mut state = DatabaseDeltaState::default();
+        let delta = delta_from_state(
+            &mut state,
+            &[1; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"aa","babe":"bb"}"#),
+            nodes(&[("peer1", &["addr1"])]),
+            Some(serde_json::json!({ "grandpaSetId": 5 })),
+            Some(hex::encode([2; 32])),
+        );
+
+        let (chain, database) = decode(&compact_database("", [delta]));
+        assert_eq!(database.genesis_hash, hex::encode([1; 32]));
+        assert_eq!(
+            chain,
+            serde_json::json!({ "version": "1", "finalizedBlockHeader": "aa", "babe": "bb" })
+        );
+        assert_eq!(database.nodes, nodes(&[("peer1", &["addr1"])]));
+        assert_eq!(
+            database.warp_sync,
+            Some(serde_json::json!({ "grandpaSetId": 5 }))
+        );
+        assert_eq!(database.runtime_code_hash, Some(hex::encode([2; 32])));
+    }
+
+    #[test]
+    fn deltas_only_contain_changes() {
+        let mut state = DatabaseDeltaState::default();
+        let first = delta_from_state(
+            &mut state,
+            &[1; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"aa","babe":"bb"}"#),
+            nodes(&[("peer1", &["addr1"]), ("peer2", &["addr2"])]),
+            Some(serde_json::json!({ "grandpaSetId": 5 })),
+            Some(hex::encode([2; 32])),
+        );
+        let second = delta_from_state(
+            &mut state,
+            &[1; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"cc"}"#),
+            nodes(&[("peer1", &["addr1"]), ("peer3", &["addr3"])]),
+            None,
+            None,
+        );
+
+        // Nothing that is unchanged is repeated in the second delta.
+        let encoded = serde_json::from_str::<serde_json::Value>(second.as_str()).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({
+                "genesisHash": hex::encode([1; 32]),
+                "chainChanges": { "finalizedBlockHeader": "cc" },
+                "chainRemovedFields": ["babe"],
+                "addedNodes": { "peer3": ["addr3"] },
+                "removedNodes": ["peer2"],
+                "warpSyncRemoved": true,
+            })
+        );
+
+        let (chain, database) = decode(&compact_database("", [first, second]));
+        assert_eq!(
+            chain,
+            serde_json::json!({ "version": "1", "finalizedBlockHeader": "cc" })
+        );
+        assert_eq!(
+            database.nodes,
+            nodes(&[("peer1", &["addr1"]), ("peer3", &["addr3"])])
+        );
+        assert_eq!(database.warp_sync, None);
+        assert_eq!(database.runtime_code_hash, Some(hex::encode([2; 32])));
+    }
+
+    #[test]
+    fn compacting_step_by_step() {
+        let mut state = DatabaseDeltaState::default();
+        let mut database = String::new();
+        for n in 0..5 {
+            let delta = delta_from_state(
+                &mut state,
+                &[1; 32],
+                Vec::new(),
+                &raw(&format!(
+                    r#"{{"version":"1","finalizedBlockHeader":"{n:02x}"}}"#
+                )),
+                nodes(&[("peer1", &["addr1"])]),
+                None,
+                None,
+            );
+            database = compact_database(&database, [delta]);
+        }
+
+        let (chain, database) = decode(&database);
+        assert_eq!(
+            chain,
+            serde_json::json!({ "version": "1", "finalizedBlockHeader": "04" })
+        );
+        assert_eq!(database.nodes, nodes(&[("peer1", &["addr1"])]));
+    }
+
+    #[test]
+    fn first_delta_of_new_stream_resets_database() {
+        let mut state = DatabaseDeltaState::default();
+        let old = delta_from_state(
+            &mut state,
+            &[1; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"aa","babe":"bb"}"#),
+            nodes(&[("peer1", &["addr1"])]),
+            Some(serde_json::json!({ "grandpaSetId": 5 })),
+            Some(hex::encode([2; 32])),
+        );
+        let database = compact_database("", [old]);
+
+        // A new stream, for example after a restart, doesn't know what the database contains.
+        let mut state = DatabaseDeltaState::default();
+        let new = delta_from_state(
+            &mut state,
+            &[1; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"cc"}"#),
+            nodes(&[("peer2", &["addr2"])]),
+            None,
+            None,
+        );
+
+        let (chain, database) = decode(&compact_database(&database, [new]));
+        assert_eq!(
+            chain,
+            serde_json::json!({ "version": "1", "finalizedBlockHeader": "cc" })
+        );
+        assert_eq!(database.nodes, nodes(&[("peer2", &["addr2"])]));
+        assert_eq!(database.warp_sync, None);
+        assert_eq!(database.runtime_code_hash, None);
+    }
+
+    #[test]
+    fn different_genesis_replaces_database() {
+        let mut state = DatabaseDeltaState::default();
+        let first = delta_from_state(
+            &mut state,
+            &[1; 32],
+            vec![hex::encode([0; 40])],
+            &raw(r#"{"version":"1","finalizedBlockHeader":"aa","babe":"bb"}"#),
+            nodes(&[("peer1", &["addr1"])]),
+            None,
+            None,
+        );
+
+        let mut other_state = DatabaseDeltaState::default();
+        let mut other = delta_from_state(
+            &mut other_state,
+            &[2; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"cc"}"#),
+            nodes(&[]),
+            None,
+            None,
+        );
+        // Make sure that the database is replaced even if the delta isn't the first one of its
+        // stream.
+        let mut encoded = serde_json::from_str::<serde_json::Value>(other.as_str()).unwrap();
+        encoded.as_object_mut().unwrap().remove("reset");
+        other.encoded = serde_json::to_string(&encoded).unwrap();
+
+        let (chain, database) = decode(&compact_database("", [first, other]));
+        assert_eq!(database.genesis_hash, hex::encode([2; 32]));
+        assert_eq!(
+            chain,
+            serde_json::json!({ "version": "1", "finalizedBlockHeader": "cc" })
+        );
+        assert!(database.nodes.is_empty());
+        assert!(database.recent_finalized_headers.is_empty());
+    }
+
+    #[test]
+    fn invalid_deltas_ignored() {
+        let mut state = DatabaseDeltaState::default();
+        let delta = delta_from_state(
+            &mut state,
+            &[1; 32],
+            Vec::new(),
+            &raw(r#"{"version":"1","finalizedBlockHeader":"aa"}"#),
+            nodes(&[("peer1", &["addr1"])]),
+            None,
+            None,
+        );
+        let expected = compact_database("", [delta.as_str()]);
+
+        assert_eq!(
+            compact_database("not a database", ["", "{", delta.as_str(), "[1, 2]"]),
+            expected
+        );
+        assert_eq!(compact_database("", ["invalid"]), "");
+    }
+
+    #[test]
+    fn recent_headers_truncated_to_finalized_block() {
+        let headers = vec![vec![1], vec![2], vec![3]];
+        let finalized_hash = header::hash_from_scale_encoded_header([2]);
+
+        let (kept, runtime_code_hash) =
+            recent_finalized_headers_up_to(headers.clone(), Some([5; 32]), &finalized_hash);
+        assert_eq!(kept, [vec![1], vec![2]]);
+        assert_eq!(runtime_code_hash, None);
+    }
+
+    #[test]
+    fn recent_headers_ending_with_finalized_block_kept() {
+        let headers = vec![vec![1], vec![2], vec![3]];
+        let finalized_hash = header::hash_from_scale_encoded_header([3]);
+
+        let (kept, runtime_code_hash) =
+            recent_finalized_headers_up_to(headers.clone(), Some([5; 32]), &finalized_hash);
+        assert_eq!(kept, headers);
+        assert_eq!(runtime_code_hash, Some([5; 32]));
+    }
+
+    #[test]
+    fn recent_headers_not_connected_to_finalized_block_discarded() {
+        let headers = vec![vec![1], vec![2], vec![3]];
+        let finalized_hash = header::hash_from_scale_encoded_header([4]);
+
+        let (kept, runtime_code_hash) =
+            recent_finalized_headers_up_to(headers, Some([5; 32]), &finalized_hash);
+        assert!(kept.is_empty());
+        assert_eq!(runtime_code_hash, None);
+    }
 }
@@ -0,0 +1,51 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Persistent storage of the databases of the chains.
+//!
+//! When a [`DatabaseStorage`] is passed as [`crate::ClientConfig::database_storage`], the client
+//! loads the database of each chain from this storage when the chain is added, and periodically
+//! stores the up-to-date database of each chain into it. See [`crate::DatabaseStorageConfig`].
+//!
+//! This module provides two implementations of the [`DatabaseStorage`] trait, when the `std`
+//! feature is enabled: [`filesystem::FilesystemStorage`], which stores each database in a file,
+//! and [`memory::MemoryStorage`], which keeps the databases in memory and is mostly useful for
+//! testing purposes.
+
+use alloc::string::String;
+
+pub mod filesystem;
+pub mod memory;
+
+/// Storage where the databases of the chains are persisted.
+///
+/// The databases are identified by a key, which is the hexadecimal-encoded hash of the genesis
+/// block of their chain, without `0x` prefix. Multiple chains sharing the same genesis block,
+/// such as the same chain added multiple times, use the same database.
+///
+/// The methods of this trait are called from within asynchronous tasks and should return
+/// quickly.
+pub trait DatabaseStorage: Send + Sync {
+    /// Returns the database that has previously been stored with the given key, or `None` if
+    /// there isn't any or if it couldn't be loaded.
+    fn load(&self, chain_key: &str) -> Option<String>;
+
+    /// Stores the given database under the given key, overwriting the previous one if any.
+    ///
+    /// Errors are expected to be handled, for example logged, by the implementation.
+    fn store(&self, chain_key: &str, database: &str);
+}
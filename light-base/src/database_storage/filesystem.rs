@@ -0,0 +1,184 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(feature = "std")]
+#![cfg_attr(docsrs, doc(cfg(feature = "std")))]
+
+use super::DatabaseStorage;
+
+use alloc::{borrow::ToOwned as _, format, string::String, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// Implementation of the [`DatabaseStorage`] trait that stores each database in a file of the
+/// given directory.
+///
+/// The name of each file is derived from the key of the chain. Databases are first written to a
+/// temporary file, which is then renamed, so that a crash while writing doesn't corrupt the
+/// previously-stored database.
+///
+/// In order to not block the asynchronous tasks of the client, the files are written by a
+/// background thread. Databases that haven't been written yet when the process exits are lost.
+#[derive(Debug, Clone)]
+pub struct FilesystemStorage {
+    directory: PathBuf,
+    /// Databases that haven't been written yet, indexed by the path of their file. Shared with
+    /// the writing thread.
+    pending: Arc<Mutex<HashMap<PathBuf, String>>>,
+    /// Sends the path of the databases to write to the writing thread.
+    to_writer: mpsc::Sender<PathBuf>,
+}
+
+impl FilesystemStorage {
+    /// Builds a new [`FilesystemStorage`] storing the databases in the given directory. The
+    /// directory is created when the first database is stored if it doesn't exist.
+    ///
+    /// This spawns the thread that writes the databases. This thread stops when the
+    /// [`FilesystemStorage`] and all its clones are destroyed.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        let directory = directory.into();
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let (to_writer, from_storage) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("smoldot-database-storage".to_owned())
+            .spawn({
+                let directory = directory.clone();
+                let pending = pending.clone();
+                move || writer_thread(&directory, &pending, from_storage)
+            })
+            .unwrap();
+
+        FilesystemStorage {
+            directory,
+            pending,
+            to_writer,
+        }
+    }
+
+    /// Returns the path of the file containing the database of the given chain.
+    fn path(&self, chain_key: &str) -> PathBuf {
+        // Characters that might have a special meaning for the file system are replaced.
+        let file_name = chain_key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        self.directory.join(format!("{file_name}.json"))
+    }
+}
+
+impl DatabaseStorage for FilesystemStorage {
+    fn load(&self, chain_key: &str) -> Option<String> {
+        let path = self.path(chain_key);
+
+        // A database that is waiting to be written is more recent than the file.
+        if let Some(database) = self.pending.lock().unwrap().get(&path) {
+            return Some(database.clone());
+        }
+
+        fs::read_to_string(path).ok()
+    }
+
+    fn store(&self, chain_key: &str, database: &str) {
+        let path = self.path(chain_key);
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(path.clone(), database.to_owned());
+        // The writing thread only stops after all the senders have been destroyed.
+        self.to_writer.send(path).unwrap();
+    }
+}
+
+/// Function executed by the thread that writes the databases.
+fn writer_thread(
+    directory: &Path,
+    pending: &Mutex<HashMap<PathBuf, String>>,
+    from_storage: mpsc::Receiver<PathBuf>,
+) {
+    for path in from_storage {
+        // The database might have already been written if it has been stored multiple times in
+        // a row.
+        let Some(database) = pending.lock().unwrap().get(&path).cloned() else {
+            continue;
+        };
+
+        let tmp_path = path.with_extension("json.tmp");
+        let result = fs::create_dir_all(directory)
+            .and_then(|()| fs::write(&tmp_path, &database))
+            .and_then(|()| fs::rename(&tmp_path, &path));
+
+        if let Err(error) = result {
+            log::warn!(
+                target: "smoldot",
+                "Failed to store database in {}: {}", path.display(), error
+            );
+        }
+
+        // The entry is only removed if the database hasn't been stored again in the meantime.
+        let mut pending = pending.lock().unwrap();
+        if pending.get(&path) == Some(&database) {
+            pending.remove(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DatabaseStorage as _, FilesystemStorage};
+    use std::{fs, thread, time::Duration};
+
+    #[test]
+    fn store_then_load() {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-filesystem-storage-test-{}",
+            std::process::id()
+        ));
+        let storage = FilesystemStorage::new(&directory);
+        assert_eq!(storage.load("abcd"), None);
+
+        storage.store("abcd", "database1");
+        storage.store("abcd", "database2");
+        assert_eq!(storage.load("abcd").as_deref(), Some("database2"));
+
+        // Wait for the background thread to write the file.
+        while !storage.pending.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            fs::read_to_string(directory.join("abcd.json")).unwrap(),
+            "database2"
+        );
+        assert_eq!(
+            FilesystemStorage::new(&directory).load("abcd").as_deref(),
+            Some("database2")
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
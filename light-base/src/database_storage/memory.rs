@@ -0,0 +1,53 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(feature = "std")]
+#![cfg_attr(docsrs, doc(cfg(feature = "std")))]
+
+use super::DatabaseStorage;
+
+use alloc::{borrow::ToOwned as _, string::String};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Implementation of the [`DatabaseStorage`] trait that keeps the databases in memory.
+///
+/// The databases are lost when this storage is destroyed. It can however be shared between
+/// multiple successive [`crate::Client`]s.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    databases: Mutex<HashMap<String, String>>,
+}
+
+impl MemoryStorage {
+    /// Builds a new empty [`MemoryStorage`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DatabaseStorage for MemoryStorage {
+    fn load(&self, chain_key: &str) -> Option<String> {
+        self.databases.lock().unwrap().get(chain_key).cloned()
+    }
+
+    fn store(&self, chain_key: &str, database: &str) {
+        self.databases
+            .lock()
+            .unwrap()
+            .insert(chain_key.to_owned(), database.to_owned());
+    }
+}
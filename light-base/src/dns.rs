@@ -0,0 +1,62 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Resolution of domain names into IP addresses.
+//!
+//! By default, multiaddresses containing a domain name (for example `/dns/example.com/tcp/30333`)
+//! are passed as-is to [`crate::platform::Platform::connect`], and it is the responsibility of
+//! the platform to resolve the domain name. When a [`DnsResolver`] is configured through
+//! [`crate::ClientConfig::dns`], the client instead resolves the domain names itself and passes
+//! `/ip4` or `/ip6` multiaddresses to the platform. Multiaddresses that use TLS are an exception,
+//! as the platform needs the domain name in order to verify the certificate of the remote.
+//!
+//! This module provides two implementations of the [`DnsResolver`] trait:
+//! [`system::SystemResolver`], which uses the resolver of the operating system and requires the
+//! `std` feature, and [`doh::DnsOverHttps`], which sends DNS-over-HTTPS queries (RFC 8484)
+//! through a user-provided HTTPS client.
+
+use alloc::{string::String, vec::Vec};
+use futures::future::BoxFuture;
+
+pub mod doh;
+pub mod system;
+
+/// Resolver of domain names.
+pub trait DnsResolver: Send + Sync {
+    /// Resolves the given domain name into a list of IP addresses.
+    ///
+    /// The returned list is in order of preference. Returning an empty list is equivalent to
+    /// returning an error indicating that the domain name doesn't exist.
+    fn resolve(&self, domain_name: &str) -> BoxFuture<'static, Result<Vec<IpAddr>, DnsError>>;
+}
+
+/// IP address that a domain name resolves to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IpAddr {
+    /// IPv4 address.
+    V4([u8; 4]),
+    /// IPv6 address.
+    V6([u8; 16]),
+}
+
+/// Error potentially returned by [`DnsResolver::resolve`].
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "{message}")]
+pub struct DnsError {
+    /// Human-readable error message.
+    pub message: String,
+}
@@ -0,0 +1,417 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! DNS-over-HTTPS, as defined in RFC 8484.
+//!
+//! Smoldot doesn't embed any TLS or HTTP implementation. Instead, the HTTPS requests are
+//! performed through an [`HttpsClient`] provided by the API user.
+
+use super::{DnsError, DnsResolver, IpAddr};
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use futures::future::{self, BoxFuture, FutureExt as _};
+
+/// Client capable of performing HTTPS requests.
+pub trait HttpsClient: Send + Sync {
+    /// Sends an HTTPS `POST` request to the given URL, with the given body and with the
+    /// `Content-Type` and `Accept` headers set to `application/dns-message`, and returns the
+    /// body of the response.
+    ///
+    /// Must return an error if the status code of the response isn't `200`.
+    fn post(&self, url: &str, body: Vec<u8>) -> BoxFuture<'static, Result<Vec<u8>, String>>;
+}
+
+/// Implementation of the [`DnsResolver`] trait that sends queries to a DNS-over-HTTPS server.
+///
+/// For each domain name, both a `A` and a `AAAA` query are sent in parallel. IPv4 addresses are
+/// returned before IPv6 addresses.
+#[derive(Clone)]
+pub struct DnsOverHttps {
+    url: String,
+    client: Arc<dyn HttpsClient>,
+}
+
+impl DnsOverHttps {
+    /// Builds a new [`DnsOverHttps`] sending queries to the given URL, for example
+    /// `https://cloudflare-dns.com/dns-query`.
+    pub fn new(url: impl Into<String>, client: Arc<dyn HttpsClient>) -> Self {
+        DnsOverHttps {
+            url: url.into(),
+            client,
+        }
+    }
+}
+
+impl DnsResolver for DnsOverHttps {
+    fn resolve(&self, domain_name: &str) -> BoxFuture<'static, Result<Vec<IpAddr>, DnsError>> {
+        let queries = [RecordType::A, RecordType::Aaaa].map(|record_type| {
+            let query = encode_query(domain_name, record_type);
+            let client = self.client.clone();
+            let url = self.url.clone();
+            async move {
+                let response = client
+                    .post(&url, query?)
+                    .await
+                    .map_err(|message| DnsError {
+                        message: format!("DNS-over-HTTPS request failed: {message}"),
+                    })?;
+                decode_response(&response, record_type).map_err(|err| DnsError {
+                    message: format!("invalid DNS-over-HTTPS response: {err}"),
+                })
+            }
+        });
+
+        async move {
+            let [ipv4, ipv6] = queries;
+            let (ipv4, ipv6) = future::join(ipv4, ipv6).await;
+
+            // Resolving succeeds as long as one of the two queries succeeds, as some servers
+            // answer incorrectly to queries of a type they have no record of.
+            match (ipv4, ipv6) {
+                (Err(err), Err(_)) => Err(err),
+                (ipv4, ipv6) => Ok(ipv4
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(ipv6.unwrap_or_default())
+                    .collect()),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RecordType {
+    A,
+    Aaaa,
+}
+
+impl RecordType {
+    fn code(&self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Aaaa => 28,
+        }
+    }
+}
+
+/// Class of all the records we are interested in.
+const CLASS_IN: u16 = 1;
+
+/// Builds a DNS message in the wire format containing a query of the given type for the given
+/// domain name.
+fn encode_query(domain_name: &str, record_type: RecordType) -> Result<Vec<u8>, DnsError> {
+    let invalid_name = || DnsError {
+        message: format!("invalid domain name: {domain_name}"),
+    };
+
+    let mut out = Vec::with_capacity(12 + domain_name.len() + 6);
+
+    // Header. RFC 8484 recommends using an ID of 0 in order to improve HTTP caching.
+    out.extend_from_slice(&0u16.to_be_bytes()); // ID
+    out.extend_from_slice(&0x0100u16.to_be_bytes()); // Flags: recursion desired
+    out.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    out.extend_from_slice(&[0; 6]); // ANCOUNT, NSCOUNT, ARCOUNT
+
+    // Question.
+    for label in domain_name
+        .strip_suffix('.')
+        .unwrap_or(domain_name)
+        .split('.')
+    {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid_name());
+        }
+        out.push(u8::try_from(label.len()).unwrap());
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    if out.len() - 12 > 255 {
+        return Err(invalid_name());
+    }
+    out.extend_from_slice(&record_type.code().to_be_bytes());
+    out.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(out)
+}
+
+/// Error while decoding a DNS response.
+#[derive(Debug, derive_more::Display)]
+enum DecodeError {
+    /// Message is truncated or contains an out-of-bounds length.
+    #[display(fmt = "truncated message")]
+    Truncated,
+    /// Message isn't a response.
+    #[display(fmt = "not a response")]
+    NotResponse,
+    /// Server has returned an error code.
+    #[display(fmt = "server error code {_0}")]
+    ErrorCode(u8),
+}
+
+/// Decodes a DNS message in the wire format and returns the list of IP addresses of the given
+/// type that it contains.
+fn decode_response(message: &[u8], record_type: RecordType) -> Result<Vec<IpAddr>, DecodeError> {
+    let mut cursor = Cursor(message);
+
+    let _id = cursor.u16()?;
+    let flags = cursor.u16()?;
+    let qdcount = cursor.u16()?;
+    let ancount = cursor.u16()?;
+    let _nscount = cursor.u16()?;
+    let _arcount = cursor.u16()?;
+
+    if flags & 0x8000 == 0 {
+        return Err(DecodeError::NotResponse);
+    }
+    match u8::try_from(flags & 0xf).unwrap() {
+        0 => {}
+        // NXDOMAIN. The domain name simply doesn't exist.
+        3 => return Ok(Vec::new()),
+        code => return Err(DecodeError::ErrorCode(code)),
+    }
+
+    for _ in 0..qdcount {
+        cursor.skip_name()?;
+        cursor.take(4)?; // QTYPE and QCLASS
+    }
+
+    // The answers might contain `CNAME` records, which are ignored. Since the server performs
+    // the resolution recursively, the records the `CNAME`s point to are also in the answers.
+    let mut out = Vec::with_capacity(usize::from(ancount));
+    for _ in 0..ancount {
+        cursor.skip_name()?;
+        let rr_type = cursor.u16()?;
+        let rr_class = cursor.u16()?;
+        let _ttl = cursor.take(4)?;
+        let rdlength = cursor.u16()?;
+        let rdata = cursor.take(usize::from(rdlength))?;
+
+        if rr_type != record_type.code() || rr_class != CLASS_IN {
+            continue;
+        }
+
+        match record_type {
+            RecordType::A => out.push(IpAddr::V4(
+                <[u8; 4]>::try_from(rdata).map_err(|_| DecodeError::Truncated)?,
+            )),
+            RecordType::Aaaa => out.push(IpAddr::V6(
+                <[u8; 16]>::try_from(rdata).map_err(|_| DecodeError::Truncated)?,
+            )),
+        }
+    }
+
+    Ok(out)
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, num: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < num {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(num);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Skips over a domain name, which is either a list of labels terminated with an empty
+    /// label, or a list of labels terminated with a pointer to a different location.
+    fn skip_name(&mut self) -> Result<(), DecodeError> {
+        loop {
+            let len = self.take(1)?[0];
+            if len == 0 {
+                return Ok(());
+            }
+            if len & 0xc0 == 0xc0 {
+                self.take(1)?;
+                return Ok(());
+            }
+            self.take(usize::from(len))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_response, encode_query, DecodeError, IpAddr, RecordType};
+    use alloc::{vec, vec::Vec};
+
+    /// Builds a response to a query for `example.com`, containing the given answers. Each answer
+    /// refers to the domain name of the question through a compression pointer.
+    fn response(flags: u16, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(&flags.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&u16::try_from(answers.len()).unwrap().to_be_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        for (rr_type, rdata) in answers {
+            out.extend_from_slice(&[0xc0, 12]);
+            out.extend_from_slice(&rr_type.to_be_bytes());
+            out.extend_from_slice(&1u16.to_be_bytes());
+            out.extend_from_slice(&300u32.to_be_bytes());
+            out.extend_from_slice(&u16::try_from(rdata.len()).unwrap().to_be_bytes());
+            out.extend_from_slice(rdata);
+        }
+        out
+    }
+
+    #[test]
+    fn encode_basic() {
+        let query = encode_query("example.com", RecordType::Aaaa).unwrap();
+        assert_eq!(
+            query,
+            b"\x00\x00\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x07example\x03com\x00\x00\x1c\x00\x01"
+        );
+    }
+
+    #[test]
+    fn encode_trailing_dot() {
+        assert_eq!(
+            encode_query("example.com.", RecordType::A).unwrap(),
+            encode_query("example.com", RecordType::A).unwrap()
+        );
+    }
+
+    #[test]
+    fn encode_invalid_names() {
+        assert!(encode_query("", RecordType::A).is_err());
+        assert!(encode_query("example..com", RecordType::A).is_err());
+        assert!(encode_query(&"a".repeat(64), RecordType::A).is_err());
+        assert!(encode_query(&"a".repeat(63), RecordType::A).is_ok());
+
+        // 4 labels of 63 bytes amount to 257 bytes once encoded, above the limit of 255.
+        let label = "a".repeat(63);
+        let too_long = [&label[..]; 4].join(".");
+        assert!(encode_query(&too_long, RecordType::A).is_err());
+        assert!(encode_query(&too_long[..250], RecordType::A).is_ok());
+    }
+
+    #[test]
+    fn decode_a_records() {
+        let message = response(0x8180, &[(1, &[1, 2, 3, 4]), (1, &[5, 6, 7, 8])]);
+        assert_eq!(
+            decode_response(&message, RecordType::A).unwrap(),
+            vec![IpAddr::V4([1, 2, 3, 4]), IpAddr::V4([5, 6, 7, 8])]
+        );
+    }
+
+    #[test]
+    fn decode_aaaa_records_after_cname() {
+        // The `CNAME` contains a name that is partially compressed.
+        let message = response(
+            0x8180,
+            &[
+                (5, b"\x03www\xc0\x0c"),
+                (
+                    28,
+                    &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+                ),
+            ],
+        );
+        assert_eq!(
+            decode_response(&message, RecordType::Aaaa).unwrap(),
+            vec![IpAddr::V6([
+                0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1
+            ])]
+        );
+    }
+
+    #[test]
+    fn decode_ignores_other_types() {
+        let message = response(0x8180, &[(1, &[1, 2, 3, 4])]);
+        assert!(decode_response(&message, RecordType::Aaaa)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn decode_uncompressed_answer_name() {
+        let mut message = response(0x8180, &[]);
+        message[7] = 1;
+        message.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01\x00\x00\x01\x2c");
+        message.extend_from_slice(&[0, 4, 9, 9, 9, 9]);
+        assert_eq!(
+            decode_response(&message, RecordType::A).unwrap(),
+            vec![IpAddr::V4([9, 9, 9, 9])]
+        );
+    }
+
+    #[test]
+    fn decode_nxdomain() {
+        let message = response(0x8183, &[]);
+        assert!(decode_response(&message, RecordType::A).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decode_error_code() {
+        let message = response(0x8182, &[]);
+        assert!(matches!(
+            decode_response(&message, RecordType::A),
+            Err(DecodeError::ErrorCode(2))
+        ));
+    }
+
+    #[test]
+    fn decode_not_response() {
+        let message = response(0x0100, &[(1, &[1, 2, 3, 4])]);
+        assert!(matches!(
+            decode_response(&message, RecordType::A),
+            Err(DecodeError::NotResponse)
+        ));
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let message = response(0x8180, &[(1, &[1, 2, 3, 4])]);
+        for len in 0..message.len() {
+            assert!(matches!(
+                decode_response(&message[..len], RecordType::A),
+                Err(DecodeError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn decode_wrong_rdata_length() {
+        let message = response(0x8180, &[(1, &[1, 2, 3])]);
+        assert!(matches!(
+            decode_response(&message, RecordType::A),
+            Err(DecodeError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn decode_label_out_of_bounds() {
+        let mut message = response(0x8180, &[]);
+        // Replace the length of the `com` label with a length past the end of the message.
+        message[20] = 0x3f;
+        assert!(matches!(
+            decode_response(&message, RecordType::A),
+            Err(DecodeError::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(feature = "std")]
+#![cfg_attr(docsrs, doc(cfg(feature = "std")))]
+
+use super::{DnsError, DnsResolver, IpAddr};
+
+use alloc::{borrow::ToOwned as _, string::ToString as _, vec::Vec};
+use async_std::net::ToSocketAddrs as _;
+use futures::future::{BoxFuture, FutureExt as _};
+
+/// Implementation of the [`DnsResolver`] trait that uses the resolver of the operating system.
+#[derive(Debug, Default, Clone)]
+pub struct SystemResolver;
+
+impl DnsResolver for SystemResolver {
+    fn resolve(&self, domain_name: &str) -> BoxFuture<'static, Result<Vec<IpAddr>, DnsError>> {
+        let domain_name = domain_name.to_owned();
+        async move {
+            // The port is irrelevant, but is required by the API.
+            let addresses = (domain_name.as_str(), 0)
+                .to_socket_addrs()
+                .await
+                .map_err(|err| DnsError {
+                    message: err.to_string(),
+                })?;
+
+            Ok(addresses
+                .map(|addr| match addr.ip() {
+                    std::net::IpAddr::V4(ip) => IpAddr::V4(ip.octets()),
+                    std::net::IpAddr::V6(ip) => IpAddr::V6(ip.octets()),
+                })
+                .collect())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,265 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Detection of the GrandPa equivocations.
+//!
+//! This module listens to the GrandPa commit messages received by the networking, verifies the
+//! signatures of the pre-commits they contain against the GrandPa authorities known by the sync
+//! service, and reports the authorities that have signed two different pre-commits during the
+//! same round.
+//!
+//! The GrandPa neighbor packets received by the networking are used in order to know which
+//! rounds are over, so that the votes of these rounds can be forgotten. Neighbor packets that
+//! refer to an authorities set other than the one of the latest valid commit message are
+//! ignored.
+//!
+//! > **Note**: Full nodes don't gossip individual GrandPa votes to light clients. Consequently,
+//! >           only the pre-commits found in commit messages are inspected, and equivocations
+//! >           of pre-votes are never detected.
+
+use crate::{network_service, platform::Platform, sync_service};
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use core::time::Duration;
+use futures::{channel::mpsc, prelude::*};
+use smoldot::{chain::chain_information, finality::grandpa::equivocation};
+
+/// Number of rounds before the latest known round whose votes are kept in memory.
+///
+/// Commit messages can be received slightly late, and the rounds indicated by the neighbor
+/// packets of the peers can't be trusted. Keeping the votes of a few rounds prevents missing
+/// equivocations.
+const KEPT_ROUNDS: u64 = 8;
+
+/// Minimum duration between two retrievals of the GrandPa authorities from the sync service.
+///
+/// The authorities of each authorities set are cached. They are only retrieved again when a
+/// commit message of a more recent authorities set is received, which can happen at most once
+/// per this duration in order to not query the sync service for every commit message.
+const AUTHORITIES_REFRESH_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Returns a stream of the GrandPa equivocations detected in the commit messages received from
+/// the network.
+///
+/// Each equivocation is yielded only once.
+pub fn equivocations_stream<TPlat: Platform>(
+    log_target: String,
+    network_service: (Arc<network_service::NetworkService<TPlat>>, usize),
+    sync_service: Arc<sync_service::SyncService<TPlat>>,
+    block_number_bytes: usize,
+) -> impl Stream<Item = equivocation::Equivocation> {
+    struct State<TPlat: Platform> {
+        log_target: String,
+        network_service: (Arc<network_service::NetworkService<TPlat>>, usize),
+        sync_service: Arc<sync_service::SyncService<TPlat>>,
+        block_number_bytes: usize,
+        /// `None` if a new subscription must be started.
+        events: Option<mpsc::Receiver<network_service::Event>>,
+        detector: equivocation::Detector,
+        /// Equivocations that have been detected but not yielded yet.
+        pending: VecDeque<equivocation::Equivocation>,
+        /// Authorities set of the latest commit message whose signatures are valid.
+        latest_commit_set_id: Option<u64>,
+        /// Identifiers and public keys of the authorities sets of the finalized block and of its
+        /// scheduled change, if any, as of the latest retrieval from the sync service.
+        authorities_sets: Vec<(u64, Vec<[u8; 32]>)>,
+        /// When [`State::authorities_sets`] has last been retrieved. `None` if never.
+        authorities_sets_retrieval: Option<TPlat::Instant>,
+    }
+
+    let state = State {
+        log_target,
+        network_service,
+        sync_service,
+        block_number_bytes,
+        events: None,
+        detector: equivocation::Detector::new(),
+        pending: VecDeque::new(),
+        latest_commit_set_id: None,
+        authorities_sets: Vec::new(),
+        authorities_sets_retrieval: None,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(equivocation) = state.pending.pop_front() {
+                break Some((equivocation, state));
+            }
+
+            let Some(events) = state.events.as_mut() else {
+                state.events = Some(state.network_service.0.subscribe(64).await);
+                continue;
+            };
+
+            let message = match events.next().await {
+                Some(network_service::Event::GrandpaNeighborPacket {
+                    chain_index,
+                    state: grandpa_state,
+                    ..
+                }) if chain_index == state.network_service.1 => {
+                    if state.latest_commit_set_id != Some(grandpa_state.set_id) {
+                        continue;
+                    }
+                    state.detector.prune(
+                        grandpa_state.set_id,
+                        grandpa_state.round_number.saturating_sub(KEPT_ROUNDS),
+                    );
+                    continue;
+                }
+                Some(network_service::Event::GrandpaCommitMessage {
+                    chain_index,
+                    message,
+                    ..
+                }) if chain_index == state.network_service.1 => message,
+                Some(_) => continue,
+                None => {
+                    // The subscription has been closed by the network service because the
+                    // channel was full. Subscribe again.
+                    state.events = None;
+                    continue;
+                }
+            };
+
+            let commit = message.decode();
+
+            // Retrieve the authorities sets from the sync service if the commit belongs to a
+            // set more recent than the ones known. Commits of authorities sets that are too old
+            // or too recent are ignored.
+            if state
+                .authorities_sets
+                .iter()
+                .all(|(set_id, _)| *set_id < commit.set_id)
+                && state
+                    .authorities_sets_retrieval
+                    .as_ref()
+                    .map_or(true, |last| {
+                        TPlat::now() >= last.clone() + AUTHORITIES_REFRESH_INTERVAL
+                    })
+            {
+                state.authorities_sets_retrieval = Some(TPlat::now());
+                if let Some(chain_information) =
+                    state.sync_service.serialize_chain_information().await
+                {
+                    state.authorities_sets = grandpa_authorities_sets(&chain_information);
+                }
+            }
+            let Some((_, authorities)) = state
+                .authorities_sets
+                .iter()
+                .find(|(set_id, _)| *set_id == commit.set_id)
+            else {
+                continue;
+            };
+
+            let mut all_signatures_valid = true;
+            for (precommit, (signature, authority_public_key)) in commit
+                .message
+                .precommits
+                .iter()
+                .zip(commit.message.auth_data.iter())
+            {
+                if !authorities.iter().any(|a| *a == **authority_public_key) {
+                    continue;
+                }
+
+                if equivocation::verify_vote(equivocation::VerifyVoteConfig {
+                    set_id: commit.set_id,
+                    round_number: commit.round_number,
+                    kind: equivocation::VoteKind::Precommit,
+                    authority_public_key,
+                    target_hash: precommit.target_hash,
+                    target_number: precommit.target_number,
+                    signature,
+                    block_number_bytes: state.block_number_bytes,
+                })
+                .is_err()
+                {
+                    log::debug!(
+                        target: &state.log_target,
+                        "Discarding GrandPa commit of round {} with invalid signature",
+                        commit.round_number
+                    );
+                    all_signatures_valid = false;
+                    break;
+                }
+
+                if let Some(equivocation) = state.detector.insert_vote(
+                    commit.set_id,
+                    commit.round_number,
+                    equivocation::VoteKind::Precommit,
+                    authority_public_key,
+                    equivocation::SignedVote {
+                        target_hash: *precommit.target_hash,
+                        target_number: precommit.target_number,
+                        signature: **signature,
+                    },
+                ) {
+                    log::warn!(
+                        target: &state.log_target,
+                        "GrandPa authority {} has equivocated during round {} of set {}",
+                        hex::encode(authority_public_key),
+                        commit.round_number,
+                        commit.set_id
+                    );
+                    state.pending.push_back(equivocation);
+                }
+            }
+
+            if !all_signatures_valid {
+                continue;
+            }
+            state.latest_commit_set_id = Some(commit.set_id);
+
+            // Forget about the votes of the rounds that are over.
+            state.detector.prune(
+                commit.set_id,
+                commit.round_number.saturating_sub(KEPT_ROUNDS),
+            );
+        }
+    })
+}
+
+/// Returns the identifiers and public keys of the GrandPa authorities sets of the finalized block
+/// and of its scheduled change, if any.
+fn grandpa_authorities_sets(
+    chain_information: &chain_information::ValidChainInformation,
+) -> Vec<(u64, Vec<[u8; 32]>)> {
+    let chain_information::ChainInformationFinalityRef::Grandpa {
+        after_finalized_block_authorities_set_id,
+        finalized_triggered_authorities,
+        finalized_scheduled_change,
+    } = chain_information.as_ref().finality
+    else {
+        return Vec::new();
+    };
+
+    let mut sets = Vec::with_capacity(2);
+    sets.push((
+        after_finalized_block_authorities_set_id,
+        finalized_triggered_authorities
+            .iter()
+            .map(|a| a.public_key)
+            .collect(),
+    ));
+    if let Some((_, authorities)) = finalized_scheduled_change {
+        sets.push((
+            after_finalized_block_authorities_set_id + 1,
+            authorities.iter().map(|a| a.public_key).collect(),
+        ));
+    }
+    sets
+}
@@ -33,19 +33,40 @@
 //! queue grows past [`Config::max_pending_requests`] items, [`Frontend::queue_rpc_request`]
 //! will instead return an error.
 //!
+//! Batches of requests, as defined in the JSON-RPC 2.0 specification, are supported. Each
+//! element of a batch counts towards [`Config::max_pending_requests`] until the response to the
+//! whole batch has been returned. The responses to the elements of a batch are grouped into a
+//! single response, which [`Frontend::next_json_rpc_response`] returns once all the elements
+//! have been answered. Malformed elements are answered with an error. The notifications of the
+//! subscriptions started by a batch are only returned after the response to the batch.
+//!
 
 // TODO: doc
 // TODO: re-review this once finished
 
 mod background;
 
+pub mod shared_follow;
+
 use crate::{
     network_service, platform::Platform, runtime_service, sync_service, transactions_service,
 };
 
-use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
-use core::num::NonZeroU32;
-use futures::prelude::*;
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::{String, ToString as _},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    num::{NonZeroU32, NonZeroUsize},
+    pin::pin,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
 use smoldot::{
     chain_spec,
     json_rpc::{self, requests_subscriptions},
@@ -74,12 +95,25 @@ pub struct Config {
     /// the client.
     pub max_subscriptions: u32,
 
-    /// Maximum number of JSON-RPC requests that can be processed simultaneously.
+    /// Maximum number of elements in a batch of JSON-RPC requests. Batches that contain more
+    /// elements are immediately rejected. If 0, batches are always rejected.
+    pub max_batch_size: u32,
+
+    /// Maximum number of JSON-RPC requests that can be processed simultaneously, not including
+    /// the requests that can be answered without accessing the network.
     ///
     /// This parameter is necessary in order to prevent users from using up too much memory within
     /// the client.
     pub max_parallel_requests: NonZeroU32,
 
+    /// Maximum number of JSON-RPC requests that can be answered without accessing the network
+    /// (such as `system_health` or `chainHead_unstable_unpin`) that can be processed
+    /// simultaneously.
+    ///
+    /// These requests are processed separately from the other requests, so that they are
+    /// answered quickly even when the processing of the other requests is slow.
+    pub max_parallel_cheap_requests: NonZeroU32,
+
     /// Maximum number of subscriptions that can be processed simultaneously.
     ///
     /// In combination with [`Config::max_parallel_requests`], this can increase or decrease
@@ -98,6 +132,14 @@ pub fn service(config: Config) -> (Frontend, ServicePrototype) {
         requests_subscriptions::RequestsSubscriptions::new(requests_subscriptions::Config {
             max_clients: 1,
             max_requests_per_client: config.max_pending_requests,
+            // Each service has a single client, which is thus allowed to use all the
+            // request-processing tasks. The requests of different chains don't compete with each
+            // other, as each chain has its own service. The number of requests processed at the
+            // same time is instead bounded by `max_parallel_requests` and
+            // `max_parallel_cheap_requests`, and the cheap requests are processed separately.
+            max_parallel_requests_per_client: config
+                .max_parallel_requests
+                .saturating_add(config.max_parallel_cheap_requests.get()),
             max_subscriptions_per_client: config.max_subscriptions,
         });
 
@@ -111,8 +153,10 @@ pub fn service(config: Config) -> (Frontend, ServicePrototype) {
     // that are necessary.
     // This calculation must be in sync with the part of the code that spawns the tasks. Assertions
     // are there in order to make sure that this is the case.
-    let num_handles =
-        config.max_parallel_requests.get() + config.max_parallel_subscription_updates.get() + 1;
+    let num_handles = config.max_parallel_requests.get()
+        + config.max_parallel_cheap_requests.get()
+        + config.max_parallel_subscription_updates.get()
+        + 1;
 
     let mut background_aborts = Vec::with_capacity(usize::try_from(num_handles).unwrap());
     let mut background_abort_registrations = Vec::with_capacity(background_aborts.capacity());
@@ -122,10 +166,25 @@ pub fn service(config: Config) -> (Frontend, ServicePrototype) {
         background_abort_registrations.push(reg);
     }
 
+    let (batches_updates_tx, batches_updates_rx) = mpsc::unbounded();
+    let num_held_responses = Arc::new(AtomicUsize::new(0));
+
     let frontend = Frontend {
         log_target: log_target.clone(),
         requests_subscriptions: requests_subscriptions.clone(),
         client_id,
+        max_batch_size: usize::try_from(config.max_batch_size).unwrap_or(usize::max_value()),
+        next_batch_id: Arc::new(AtomicU64::new(0)),
+        batches_updates_tx,
+        batches: Arc::new(Mutex::new(Batches {
+            updates_rx: batches_updates_rx,
+            requests: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+            batches: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+            subscriptions: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+            ready: VecDeque::new(),
+            num_held_responses: num_held_responses.clone(),
+        })),
+        num_held_responses,
         background_aborts: Arc::from(background_aborts),
     };
 
@@ -134,6 +193,7 @@ pub fn service(config: Config) -> (Frontend, ServicePrototype) {
         log_target,
         requests_subscriptions,
         max_parallel_requests: config.max_parallel_requests,
+        max_parallel_cheap_requests: config.max_parallel_cheap_requests,
         max_parallel_subscription_updates: config.max_parallel_subscription_updates,
     };
 
@@ -160,6 +220,22 @@ pub struct Frontend {
     /// Target to use when emitting logs.
     log_target: String,
 
+    /// Value obtained through [`Config::max_batch_size`].
+    max_batch_size: usize,
+
+    /// Identifier to assign to the next batch of requests.
+    next_batch_id: Arc<AtomicU64>,
+
+    /// Sending side of [`Batches::updates_rx`].
+    batches_updates_tx: mpsc::UnboundedSender<BatchUpdate>,
+
+    /// State of the batches of requests whose responses haven't all been generated yet.
+    batches: Arc<Mutex<Batches>>,
+
+    /// Number of responses to the requests of batches that are held in
+    /// [`Frontend::batches`]. Counts towards [`Config::max_pending_requests`].
+    num_held_responses: Arc<AtomicUsize>,
+
     /// Handles to abort the background tasks that hold and process the
     /// [`Frontend::requests_subscriptions`].
     background_aborts: Arc<[future::AbortHandle]>,
@@ -172,15 +248,40 @@ impl Frontend {
     /// if the requests take a long time to process or if [`Frontend::next_json_rpc_response`]
     /// isn't called often enough. Use [`HandleRpcError::into_json_rpc_error`] to build the
     /// JSON-RPC response to immediately send back to the user.
+    ///
+    /// The request can be a batch of requests. In that case, the batch is either entirely queued
+    /// or entirely refused.
     pub fn queue_rpc_request(&self, json_rpc_request: String) -> Result<(), HandleRpcError> {
+        if json_rpc::parse::is_batch(&json_rpc_request) {
+            return self.queue_rpc_batch(json_rpc_request);
+        }
+
         // If the request isn't even a valid JSON-RPC request, we can't even send back a response.
         // We have no choice but to immediately refuse the request.
-        if let Err(error) = json_rpc::parse::parse_call(&json_rpc_request) {
+        let lane = match json_rpc::parse::parse_call(&json_rpc_request) {
+            Ok(call) if call.id_json.map_or(false, is_reserved_request_id) => {
+                // The identifier of the request could be confused with the identifiers assigned
+                // to the requests of batches. The request is processed like a batch in order for
+                // its identifier to be replaced.
+                return self.queue_requests(&json_rpc_request, &[&json_rpc_request], false);
+            }
+            Ok(call) => request_lane(call.method),
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Refused malformed JSON-RPC request: {}", error
+                );
+                return Err(HandleRpcError::MalformedJsonRpc(error));
+            }
+        };
+
+        if !self.has_room_for_requests(1) {
             log::warn!(
                 target: &self.log_target,
-                "Refused malformed JSON-RPC request: {}", error
+                "Request denied due to JSON-RPC service being overloaded. This will likely \
+                cause the JSON-RPC client to malfunction."
             );
-            return Err(HandleRpcError::MalformedJsonRpc(error));
+            return Err(HandleRpcError::Overloaded { json_rpc_request });
         }
 
         // Logging the request before it is queued.
@@ -193,12 +294,173 @@ impl Frontend {
             )
         );
 
+        match self.requests_subscriptions.try_queue_client_request(
+            &self.client_id,
+            json_rpc_request,
+            lane,
+        ) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Request denied due to JSON-RPC service being overloaded. This will likely \
+                    cause the JSON-RPC client to malfunction."
+                );
+
+                Err(HandleRpcError::Overloaded {
+                    json_rpc_request: err.request,
+                })
+            }
+        }
+    }
+
+    /// Queues the given batch of JSON-RPC requests. See [`Frontend::queue_rpc_request`].
+    fn queue_rpc_batch(&self, json_rpc_request: String) -> Result<(), HandleRpcError> {
+        let elements = match json_rpc::parse::parse_batch(&json_rpc_request) {
+            Ok(elements) => elements,
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Refused malformed JSON-RPC batch: {}", error
+                );
+                return Err(HandleRpcError::MalformedJsonRpc(error));
+            }
+        };
+
+        if elements.len() > self.max_batch_size {
+            log::warn!(
+                target: &self.log_target,
+                "Refused JSON-RPC batch of {} requests (maximum is {})",
+                elements.len(),
+                self.max_batch_size
+            );
+            return Err(HandleRpcError::BatchTooLarge {
+                max_batch_size: self.max_batch_size,
+            });
+        }
+
+        self.queue_requests(&json_rpc_request, &elements, true)
+    }
+
+    /// Queues the given requests, whose responses are grouped together. If `is_batch` is
+    /// `false`, `elements` must contain exactly one request, whose response is returned as is.
+    ///
+    /// `json_rpc_request` is the request as provided by the user, and is only used in order to
+    /// build the error.
+    fn queue_requests(
+        &self,
+        json_rpc_request: &str,
+        elements: &[&str],
+        is_batch: bool,
+    ) -> Result<(), HandleRpcError> {
+        debug_assert!(is_batch || elements.len() == 1);
+        let batch_id = self.next_batch_id.fetch_add(1, Ordering::Relaxed);
+
+        // The identifier of each request of the batch is replaced with an identifier that makes
+        // it possible to later find the batch the response belongs to. Notifications are queued
+        // as they are, as they don't generate any response. Malformed elements are immediately
+        // answered with an error.
+        let mut requests = Vec::with_capacity(elements.len());
+        let mut batch_elements = Vec::with_capacity(elements.len());
+        let mut num_immediate_responses = 0;
+        for element in elements {
+            let call = match json_rpc::parse::parse_call(element) {
+                Ok(call) => call,
+                Err(error) => {
+                    log::debug!(
+                        target: &self.log_target,
+                        "Malformed request in JSON-RPC batch: {}", error
+                    );
+                    batch_elements.push(BatchElement::Response(
+                        json_rpc::parse::build_error_response(
+                            "null",
+                            json_rpc::parse::ErrorResponse::InvalidRequest,
+                            None,
+                        ),
+                    ));
+                    num_immediate_responses += 1;
+                    continue;
+                }
+            };
+
+            let lane = request_lane(call.method);
+            match call.id_json {
+                Some(original_id_json) => {
+                    let batch_request_id = format!(
+                        "{RESERVED_REQUEST_ID_PREFIX}{batch_id}-{}",
+                        batch_elements.len()
+                    );
+                    let batch_request_id_json = serde_json::to_string(&batch_request_id).unwrap();
+                    requests.push((
+                        json_rpc::parse::build_call(json_rpc::parse::Call {
+                            id_json: Some(&batch_request_id_json),
+                            method: call.method,
+                            params_json: call.params_json,
+                        }),
+                        lane,
+                    ));
+                    batch_elements.push(BatchElement::Request {
+                        id: batch_request_id,
+                        original_id_json: original_id_json.to_owned(),
+                    });
+                }
+                None => requests.push(((*element).to_owned(), lane)),
+            }
+        }
+
+        // The responses that are held until the entire batch has been answered count towards
+        // the limit.
+        if !self.has_room_for_requests(requests.len() + num_immediate_responses) {
+            log::warn!(
+                target: &self.log_target,
+                "Request denied due to JSON-RPC service being overloaded. This will likely \
+                cause the JSON-RPC client to malfunction."
+            );
+            return Err(HandleRpcError::Overloaded {
+                json_rpc_request: json_rpc_request.to_owned(),
+            });
+        }
+
+        log::debug!(
+            target: &self.log_target,
+            "PendingRequestsQueue <= batch of {} requests: {}",
+            requests.len(),
+            crate::util::truncated_str(
+                json_rpc_request.chars().filter(|c| !c.is_control()),
+                100,
+            )
+        );
+
+        // The batch must be registered before its requests are queued, as the background might
+        // otherwise generate responses before the batch is known.
+        let has_responses = !batch_elements.is_empty();
+        if has_responses {
+            self.num_held_responses
+                .fetch_add(num_immediate_responses, Ordering::SeqCst);
+            // The receiving side is alive as long as `self` is alive.
+            self.batches_updates_tx
+                .unbounded_send(BatchUpdate::New {
+                    batch_id,
+                    is_batch,
+                    elements: batch_elements,
+                })
+                .unwrap();
+        }
+
         match self
             .requests_subscriptions
-            .try_queue_client_request(&self.client_id, json_rpc_request)
+            .try_queue_client_requests(&self.client_id, requests)
         {
             Ok(()) => Ok(()),
-            Err(err) => {
+            Err(_) => {
+                if has_responses {
+                    self.batches_updates_tx
+                        .unbounded_send(BatchUpdate::Cancel { batch_id })
+                        .unwrap();
+                    self.num_held_responses
+                        .fetch_sub(num_immediate_responses, Ordering::SeqCst);
+                }
+
                 log::warn!(
                     target: &self.log_target,
                     "Request denied due to JSON-RPC service being overloaded. This will likely \
@@ -206,21 +468,63 @@ impl Frontend {
                 );
 
                 Err(HandleRpcError::Overloaded {
-                    json_rpc_request: err.request,
+                    json_rpc_request: json_rpc_request.to_owned(),
                 })
             }
         }
     }
 
+    /// Returns `true` if the given number of requests can be queued without exceeding
+    /// [`Config::max_pending_requests`].
+    fn has_room_for_requests(&self, num_requests: usize) -> bool {
+        self.num_pending_requests()
+            .checked_add(num_requests)
+            .map_or(false, |n| n <= self.max_pending_requests())
+    }
+
+    /// Returns the value of [`Config::max_pending_requests`].
+    pub fn max_pending_requests(&self) -> usize {
+        self.requests_subscriptions.max_requests_per_client()
+    }
+
+    /// Returns the number of requests that have been queued and whose response hasn't been
+    /// returned by [`Frontend::next_json_rpc_response`] yet.
+    ///
+    /// [`Frontend::queue_rpc_request`] returns an error if this value reaches
+    /// [`Frontend::max_pending_requests`].
+    pub fn num_pending_requests(&self) -> usize {
+        self.requests_subscriptions
+            .num_requests_in_fly(&self.client_id)
+            + self.num_held_responses.load(Ordering::SeqCst)
+    }
+
     /// Waits until a JSON-RPC response has been generated, then returns it.
     ///
     /// If this function is called multiple times in parallel, the order in which the calls are
     /// responded to is unspecified.
     pub async fn next_json_rpc_response(&self) -> String {
-        let message = self
-            .requests_subscriptions
-            .next_response(&self.client_id)
-            .await;
+        let mut batches = self.batches.lock().await;
+
+        let message = loop {
+            if let Some((message, num_held_responses)) = batches.ready.pop_front() {
+                self.num_held_responses
+                    .fetch_sub(num_held_responses, Ordering::SeqCst);
+                break message;
+            }
+
+            // Responses to requests that belong to a batch are held back until the responses to
+            // all the requests of the batch are available. Batches whose elements have all been
+            // answered when they are queued are reported through `updates_rx`.
+            let next_response = pin!(self.requests_subscriptions.next_response(&self.client_id));
+            match future::select(next_response, batches.updates_rx.next()).await {
+                future::Either::Left((message, _)) => batches.inject_message(message),
+                future::Either::Right((Some(update), _)) => batches.apply_update(update),
+                future::Either::Right((None, _)) => {
+                    // The sending side is alive as long as `self` is alive.
+                    unreachable!()
+                }
+            }
+        };
 
         log::debug!(
             target: &self.log_target,
@@ -235,6 +539,233 @@ impl Frontend {
     }
 }
 
+/// Returns the lane in which a JSON-RPC request calling the given method must be queued.
+///
+/// See [`json_rpc::methods::is_cheap_method`].
+fn request_lane(method: &str) -> requests_subscriptions::RequestLane {
+    if json_rpc::methods::is_cheap_method(method) {
+        requests_subscriptions::RequestLane::Cheap
+    } else {
+        requests_subscriptions::RequestLane::Expensive
+    }
+}
+
+/// Prefix of the identifiers assigned to the requests of batches.
+const RESERVED_REQUEST_ID_PREFIX: &str = "smoldot-batch-";
+
+/// Returns `true` if the given JSON-formatted request identifier could be confused with the
+/// identifiers assigned to the requests of batches.
+fn is_reserved_request_id(id_json: &str) -> bool {
+    serde_json::from_str::<alloc::borrow::Cow<str>>(id_json)
+        .map_or(false, |id| id.starts_with(RESERVED_REQUEST_ID_PREFIX))
+}
+
+/// State of the batches of requests whose responses haven't all been generated yet.
+struct Batches {
+    /// Receiving side of [`Frontend::batches_updates_tx`]. Must be processed before looking
+    /// at [`Batches::requests`] and [`Batches::batches`].
+    updates_rx: mpsc::UnboundedReceiver<BatchUpdate>,
+
+    /// For each identifier of a request that belongs to a batch, the identifier of the batch,
+    /// the index of the request within the batch, and the original JSON-formatted identifier of
+    /// the request.
+    requests: hashbrown::HashMap<String, (u64, usize, String), fnv::FnvBuildHasher>,
+
+    /// List of batches, indexed by their identifier.
+    batches: hashbrown::HashMap<u64, Batch, fnv::FnvBuildHasher>,
+
+    /// Strings found in the `result` field of the held responses, which might be the identifier
+    /// of a subscription, and the batch of the response. The notifications of these
+    /// subscriptions are held back until the response to the batch has been returned.
+    subscriptions: hashbrown::HashMap<String, u64, fnv::FnvBuildHasher>,
+
+    /// Messages to return from [`Frontend::next_json_rpc_response`], in order, and the number of
+    /// responses counted in [`Frontend::num_held_responses`] that each of them contains.
+    ready: VecDeque<(String, usize)>,
+
+    /// See [`Frontend::num_held_responses`].
+    num_held_responses: Arc<AtomicUsize>,
+}
+
+/// See [`Batches::batches`].
+struct Batch {
+    /// If `false`, the batch contains a single request that wasn't sent as part of a batch, and
+    /// whose response must not be wrapped in a batch response.
+    is_batch: bool,
+
+    /// Responses to the requests of the batch, in the same order as in the batch. `None` if
+    /// the response hasn't been generated yet.
+    responses: Vec<Option<String>>,
+
+    /// Number of `None` entries in [`Batch::responses`].
+    num_missing: usize,
+
+    /// Notifications held back until the response to the batch has been returned.
+    notifications: Vec<String>,
+}
+
+/// Message sent on [`Frontend::batches_updates_tx`].
+enum BatchUpdate {
+    /// A new batch of requests is going to be queued.
+    New {
+        batch_id: u64,
+        /// See [`Batch::is_batch`].
+        is_batch: bool,
+        /// Elements of the batch. Notifications aren't included.
+        elements: Vec<BatchElement>,
+    },
+    /// Queuing a batch previously reported with [`BatchUpdate::New`] has failed.
+    Cancel { batch_id: u64 },
+}
+
+/// See [`BatchUpdate::New::elements`].
+enum BatchElement {
+    /// Request whose response is generated by the JSON-RPC service.
+    Request {
+        /// Identifier assigned to the request.
+        id: String,
+        /// JSON-formatted identifier that the JSON-RPC client has originally chosen.
+        original_id_json: String,
+    },
+    /// Element whose response is already known, for example because it is malformed.
+    Response(String),
+}
+
+impl Batches {
+    /// Processes an update sent on [`Frontend::batches_updates_tx`].
+    fn apply_update(&mut self, update: BatchUpdate) {
+        match update {
+            BatchUpdate::New {
+                batch_id,
+                is_batch,
+                elements,
+            } => {
+                let mut responses = Vec::with_capacity(elements.len());
+                for (index, element) in elements.into_iter().enumerate() {
+                    match element {
+                        BatchElement::Request {
+                            id,
+                            original_id_json,
+                        } => {
+                            self.requests
+                                .insert(id, (batch_id, index, original_id_json));
+                            responses.push(None);
+                        }
+                        BatchElement::Response(response) => responses.push(Some(response)),
+                    }
+                }
+
+                let num_missing = responses.iter().filter(|r| r.is_none()).count();
+                self.batches.insert(
+                    batch_id,
+                    Batch {
+                        is_batch,
+                        responses,
+                        num_missing,
+                        notifications: Vec::new(),
+                    },
+                );
+
+                if num_missing == 0 {
+                    self.finish_batch(batch_id);
+                }
+            }
+            BatchUpdate::Cancel { batch_id } => {
+                self.batches.remove(&batch_id);
+                self.requests.retain(|_, (id, _, _)| *id != batch_id);
+            }
+        }
+    }
+
+    /// Processes a message generated by the JSON-RPC service.
+    ///
+    /// The message is pushed to [`Batches::ready`], unless it is a response to a request that
+    /// belongs to a batch or a notification of a subscription started by a batch, in which case
+    /// it is held back until all the requests of the batch have a response.
+    fn inject_message(&mut self, message: String) {
+        while let Ok(Some(update)) = self.updates_rx.try_next() {
+            self.apply_update(update);
+        }
+
+        // Fast path. Avoid decoding the message if there isn't any batch.
+        if self.batches.is_empty() {
+            self.ready.push_back((message, 0));
+            return;
+        }
+
+        let Ok(mut response) = serde_json::from_str::<serde_json::Value>(&message) else {
+            self.ready.push_back((message, 0));
+            return;
+        };
+
+        // Notifications of subscriptions whose identifier is held back.
+        if response.get("id").is_none() {
+            if let Some(batch_id) = response
+                .get("params")
+                .and_then(|params| params.get("subscription"))
+                .and_then(|id| id.as_str())
+                .and_then(|id| self.subscriptions.get(id))
+            {
+                self.batches
+                    .get_mut(batch_id)
+                    .unwrap()
+                    .notifications
+                    .push(message);
+            } else {
+                self.ready.push_back((message, 0));
+            }
+            return;
+        }
+
+        let Some((batch_id, index, original_id_json)) = response
+            .get("id")
+            .and_then(|id| id.as_str())
+            .and_then(|id| self.requests.remove(id))
+        else {
+            self.ready.push_back((message, 0));
+            return;
+        };
+
+        // Put back the identifier that the JSON-RPC client has originally chosen.
+        response["id"] = serde_json::from_str(&original_id_json).unwrap();
+
+        if let Some(result) = response.get("result").and_then(|r| r.as_str()) {
+            self.subscriptions.insert(result.to_owned(), batch_id);
+        }
+
+        self.num_held_responses.fetch_add(1, Ordering::SeqCst);
+        let batch = self.batches.get_mut(&batch_id).unwrap();
+        debug_assert!(batch.responses[index].is_none());
+        batch.responses[index] = Some(response.to_string());
+        batch.num_missing -= 1;
+        if batch.num_missing == 0 {
+            self.finish_batch(batch_id);
+        }
+    }
+
+    /// Removes the given batch, whose requests all have a response, and pushes its response
+    /// and held notifications to [`Batches::ready`].
+    fn finish_batch(&mut self, batch_id: u64) {
+        let batch = self.batches.remove(&batch_id).unwrap();
+        debug_assert_eq!(batch.num_missing, 0);
+        self.subscriptions.retain(|_, id| *id != batch_id);
+
+        let num_responses = batch.responses.len();
+        let response = if batch.is_batch {
+            json_rpc::parse::build_batch_response(
+                batch.responses.iter().map(|r| r.as_deref().unwrap()),
+            )
+        } else {
+            debug_assert_eq!(num_responses, 1);
+            batch.responses.into_iter().next().unwrap().unwrap()
+        };
+
+        self.ready.push_back((response, num_responses));
+        self.ready
+            .extend(batch.notifications.into_iter().map(|n| (n, 0)));
+    }
+}
+
 impl Drop for Frontend {
     fn drop(&mut self) {
         // Call `abort()` if this was the last instance of the `Arc<AbortHandle>` (and thus the
@@ -261,6 +792,9 @@ pub struct ServicePrototype {
     /// Value obtained through [`Config::max_parallel_requests`].
     max_parallel_requests: NonZeroU32,
 
+    /// Value obtained through [`Config::max_parallel_cheap_requests`].
+    max_parallel_cheap_requests: NonZeroU32,
+
     /// Value obtained through [`Config::max_parallel_subscription_updates`].
     max_parallel_subscription_updates: NonZeroU32,
 
@@ -287,6 +821,10 @@ pub struct StartConfig<'a, TPlat: Platform> {
     /// Service that provides a ready-to-be-called runtime for the current best block.
     pub runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
 
+    /// State shared between all the `chainHead_follow` subscriptions of the chain, including
+    /// the ones of other JSON-RPC services.
+    pub shared_follow: Arc<shared_follow::SharedFollow<TPlat>>,
+
     /// Specification of the chain.
     pub chain_spec: &'a chain_spec::ChainSpec,
 
@@ -318,6 +856,29 @@ pub struct StartConfig<'a, TPlat: Platform> {
     /// >           expensive. We prefer to require this value from the upper layer instead, as
     /// >           it is most likely needed anyway.
     pub genesis_block_state_root: [u8; 32],
+
+    /// List of JSON-RPC servers that requests the service can't answer are forwarded to.
+    /// Each entry is either a `ws://` or `wss://` URL, or a multiaddress.
+    ///
+    /// See the documentation of `AddChainConfig::archive_fallback_endpoints`.
+    pub archive_fallback_endpoints: Vec<String>,
+
+    /// Maximum number of blocks below the current finalized block that `chain_getBlockHash` can
+    /// look up by downloading the headers of the ancestors of the finalized block.
+    ///
+    /// See the documentation of `AddChainConfig::max_block_hash_lookup_depth`.
+    pub max_block_hash_lookup_depth: u32,
+
+    /// SCALE-encoded headers of recently-finalized blocks, ordered by increasing block number,
+    /// for example restored from a database. Each header must be the parent of the next one.
+    /// Requests concerning these blocks are answered without querying the network.
+    pub recent_finalized_headers: Vec<Vec<u8>>,
+
+    /// BLAKE2 hash of the runtime code of the last block of
+    /// [`StartConfig::recent_finalized_headers`], if known. If the runtime service reports a
+    /// different runtime code for this block, the database that these headers come from is
+    /// considered as corrupted and [`StartConfig::recent_finalized_headers`] is discarded.
+    pub recent_finalized_runtime_code_hash: Option<[u8; 32]>,
 }
 
 impl ServicePrototype {
@@ -328,12 +889,28 @@ impl ServicePrototype {
             self.requests_subscriptions.clone(),
             config,
             self.max_parallel_requests,
+            self.max_parallel_cheap_requests,
             self.max_parallel_subscription_updates,
             self.background_abort_registrations,
         )
     }
 }
 
+/// See [`shared_follow::SharedFollow::pinned_blocks`].
+#[derive(Debug, Clone)]
+pub struct ChainHeadPinnedBlocks {
+    /// Number of distinct blocks that are pinned on behalf of all the `chainHead_follow`
+    /// subscriptions combined.
+    pub num_pinned_blocks: usize,
+
+    /// Value of [`shared_follow::Config::max_pinned_blocks`].
+    pub max_pinned_blocks_per_subscription: NonZeroUsize,
+
+    /// For each active `chainHead_follow` subscription that has requested runtime updates, its
+    /// identifier and the number of blocks that it currently has pinned.
+    pub subscriptions: Vec<(String, usize)>,
+}
+
 /// Error potentially returned when queuing a JSON-RPC request.
 #[derive(Debug, derive_more::Display)]
 pub enum HandleRpcError {
@@ -348,19 +925,58 @@ pub enum HandleRpcError {
     /// The request isn't a valid JSON-RPC request.
     #[display(fmt = "The request isn't a valid JSON-RPC request: {_0}")]
     MalformedJsonRpc(json_rpc::parse::ParseError),
+    /// The request is a batch that contains more than [`Config::max_batch_size`] requests.
+    #[display(fmt = "The batch contains more than {max_batch_size} requests.")]
+    BatchTooLarge {
+        /// Value of [`Config::max_batch_size`].
+        max_batch_size: usize,
+    },
 }
 
 impl HandleRpcError {
     /// Builds the JSON-RPC error string corresponding to this error.
     ///
     /// Returns `None` if the JSON-RPC requests isn't valid JSON-RPC or if the call was a
-    /// notification.
+    /// notification or a batch containing only notifications.
     pub fn into_json_rpc_error(self) -> Option<String> {
         let json_rpc_request = match self {
             HandleRpcError::Overloaded { json_rpc_request } => json_rpc_request,
             HandleRpcError::MalformedJsonRpc(_) => return None,
+            HandleRpcError::BatchTooLarge { max_batch_size } => {
+                // As the batch isn't processed, the error isn't associated to any specific
+                // request of the batch.
+                return Some(json_rpc::parse::build_error_response(
+                    "null",
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Batch contains more than {max_batch_size} requests"),
+                    ),
+                    None,
+                ));
+            }
         };
 
+        if json_rpc::parse::is_batch(&json_rpc_request) {
+            let responses = json_rpc::parse::parse_batch(&json_rpc_request)
+                .ok()?
+                .into_iter()
+                .filter_map(|element| json_rpc::parse::parse_call(element).ok()?.id_json)
+                .map(|id| {
+                    json_rpc::parse::build_error_response(
+                        id,
+                        json_rpc::parse::ErrorResponse::ServerError(-32000, "Too busy"),
+                        None,
+                    )
+                })
+                .collect::<Vec<_>>();
+            if responses.is_empty() {
+                return None;
+            }
+            return Some(json_rpc::parse::build_batch_response(
+                responses.iter().map(|r| &r[..]),
+            ));
+        }
+
         match json_rpc::parse::parse_call(&json_rpc_request) {
             Ok(json_rpc::parse::Call {
                 id_json: Some(id), ..
@@ -373,3 +989,149 @@ impl HandleRpcError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchElement, BatchUpdate, Batches};
+    use alloc::{collections::VecDeque, sync::Arc};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use futures::channel::mpsc;
+
+    fn batches() -> (Batches, mpsc::UnboundedSender<BatchUpdate>) {
+        let (tx, rx) = mpsc::unbounded();
+        let batches = Batches {
+            updates_rx: rx,
+            requests: Default::default(),
+            batches: Default::default(),
+            subscriptions: Default::default(),
+            ready: VecDeque::new(),
+            num_held_responses: Arc::new(AtomicUsize::new(0)),
+        };
+        (batches, tx)
+    }
+
+    #[test]
+    fn malformed_element_answered() {
+        let (mut batches, tx) = batches();
+        tx.unbounded_send(BatchUpdate::New {
+            batch_id: 0,
+            is_batch: true,
+            elements: vec![
+                BatchElement::Response(r#"{"error":1}"#.to_owned()),
+                BatchElement::Request {
+                    id: "smoldot-batch-0-1".to_owned(),
+                    original_id_json: "5".to_owned(),
+                },
+            ],
+        })
+        .unwrap();
+
+        batches.inject_message(
+            r#"{"jsonrpc":"2.0","id":"smoldot-batch-0-1","result":true}"#.to_owned(),
+        );
+        assert_eq!(
+            batches.ready.pop_front().unwrap(),
+            (
+                r#"[{"error":1},{"id":5,"jsonrpc":"2.0","result":true}]"#.to_owned(),
+                2
+            )
+        );
+        assert!(batches.batches.is_empty());
+        assert_eq!(batches.num_held_responses.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn already_answered_batch_ready() {
+        let (mut batches, _tx) = batches();
+        batches.apply_update(BatchUpdate::New {
+            batch_id: 0,
+            is_batch: true,
+            elements: vec![BatchElement::Response(r#"{"error":1}"#.to_owned())],
+        });
+        assert_eq!(
+            batches.ready.pop_front().unwrap(),
+            (r#"[{"error":1}]"#.to_owned(), 1)
+        );
+    }
+
+    #[test]
+    fn notifications_after_batch_response() {
+        let (mut batches, _tx) = batches();
+        batches.apply_update(BatchUpdate::New {
+            batch_id: 0,
+            is_batch: true,
+            elements: vec![
+                BatchElement::Request {
+                    id: "smoldot-batch-0-0".to_owned(),
+                    original_id_json: "1".to_owned(),
+                },
+                BatchElement::Request {
+                    id: "smoldot-batch-0-1".to_owned(),
+                    original_id_json: "2".to_owned(),
+                },
+            ],
+        });
+
+        let notification =
+            r#"{"jsonrpc":"2.0","method":"foo","params":{"subscription":"sub","result":1}}"#;
+        let other_notification =
+            r#"{"jsonrpc":"2.0","method":"foo","params":{"subscription":"other","result":1}}"#;
+
+        batches.inject_message(
+            r#"{"jsonrpc":"2.0","id":"smoldot-batch-0-0","result":"sub"}"#.to_owned(),
+        );
+        batches.inject_message(notification.to_owned());
+        batches.inject_message(other_notification.to_owned());
+        assert_eq!(
+            batches.ready.pop_front().unwrap(),
+            (other_notification.to_owned(), 0)
+        );
+        assert!(batches.ready.is_empty());
+
+        batches.inject_message(
+            r#"{"jsonrpc":"2.0","id":"smoldot-batch-0-1","result":null}"#.to_owned(),
+        );
+        assert_eq!(
+            batches.ready.pop_front().unwrap(),
+            (
+                r#"[{"id":1,"jsonrpc":"2.0","result":"sub"},{"id":2,"jsonrpc":"2.0","result":null}]"#
+                    .to_owned(),
+                2
+            )
+        );
+        assert_eq!(
+            batches.ready.pop_front().unwrap(),
+            (notification.to_owned(), 0)
+        );
+        assert!(batches.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn single_request_not_wrapped() {
+        let (mut batches, _tx) = batches();
+        batches.apply_update(BatchUpdate::New {
+            batch_id: 3,
+            is_batch: false,
+            elements: vec![BatchElement::Request {
+                id: "smoldot-batch-3-0".to_owned(),
+                original_id_json: r#""smoldot-batch-0-0""#.to_owned(),
+            }],
+        });
+        batches
+            .inject_message(r#"{"jsonrpc":"2.0","id":"smoldot-batch-3-0","result":5}"#.to_owned());
+        assert_eq!(
+            batches.ready.pop_front().unwrap(),
+            (
+                r#"{"id":"smoldot-batch-0-0","jsonrpc":"2.0","result":5}"#.to_owned(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn reserved_request_id() {
+        assert!(super::is_reserved_request_id(r#""smoldot-batch-1-2""#));
+        assert!(!super::is_reserved_request_id(r#""smoldot-rpc-1""#));
+        assert!(!super::is_reserved_request_id("12"));
+    }
+}
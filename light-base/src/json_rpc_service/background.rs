@@ -16,16 +16,19 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
-    network_service, platform::Platform, runtime_service, sync_service, transactions_service,
+    database::MAX_RECENT_FINALIZED_HEADERS, network_service, platform::Platform, runtime_service,
+    sync_service, transactions_service,
 };
 
-use super::StartConfig;
+use super::{shared_follow, StartConfig};
 
 use alloc::{
     borrow::ToOwned as _,
+    collections::VecDeque,
     format,
     string::{String, ToString as _},
     sync::Arc,
+    vec,
     vec::Vec,
 };
 use core::{
@@ -39,13 +42,16 @@ use futures::{lock::Mutex, prelude::*};
 use hashbrown::HashMap;
 use smoldot::{
     chain::fork_tree,
-    executor::{host, runtime_host},
+    executor::{host, runtime_host, vm},
     header,
+    informant::HashDisplay,
     json_rpc::{self, methods, requests_subscriptions},
     libp2p::{multiaddr, PeerId},
     network::protocol,
 };
 
+mod archive_fallback;
+mod beefy;
 mod chain_head;
 mod getters;
 mod state_chain;
@@ -91,6 +97,9 @@ struct Background<TPlat: Platform> {
     /// requests to perform.
     cache: Mutex<Cache>,
 
+    /// See [`StartConfig::shared_follow`].
+    shared_follow: Arc<shared_follow::SharedFollow<TPlat>>,
+
     /// Hash of the genesis block.
     /// Keeping the genesis block is important, as the genesis block hash is included in
     /// transaction signatures, and must therefore be queried by upper-level UIs.
@@ -99,6 +108,17 @@ struct Background<TPlat: Platform> {
     /// If `true`, we have already printed a warning about usage of the legacy JSON-RPC API. This
     /// flag prevents printing this message multiple times.
     printed_legacy_json_rpc_warning: atomic::AtomicBool,
+
+    /// Multiaddresses of the JSON-RPC servers to forward requests to. Obtained from
+    /// [`StartConfig::archive_fallback_endpoints`].
+    archive_fallback_endpoints: Vec<String>,
+
+    /// Index within [`Background::archive_fallback_endpoints`] of the next server to forward
+    /// a request to, modulo the number of servers.
+    next_archive_fallback_endpoint: atomic::AtomicUsize,
+
+    /// See [`StartConfig::max_block_hash_lookup_depth`].
+    max_block_hash_lookup_depth: u64,
 }
 
 struct FollowSubscription {
@@ -108,7 +128,112 @@ struct FollowSubscription {
     /// For each pinned block hash, the SCALE-encoded header of the block.
     pinned_blocks_headers: HashMap<[u8; 32], Vec<u8>, fnv::FnvBuildHasher>,
 
-    runtime_subscribe_all: Option<runtime_service::SubscriptionId>,
+    /// Identifier of the subscription within [`Background::shared_follow`]. `None` if the
+    /// subscription hasn't requested runtime updates.
+    runtime_subscribe_all: Option<shared_follow::FollowerId>,
+
+    /// Version of the `chainHead` functions that has been used to start the subscription.
+    /// Notifications concerning this subscription and the operations started through it are
+    /// sent using the corresponding JSON-RPC method names.
+    api_version: ApiVersion,
+
+    /// Operations started with `chainHead_v1_body`, `chainHead_v1_call` or
+    /// `chainHead_v1_storage` that are still in progress, indexed by operation ID. Always empty
+    /// if [`FollowSubscription::api_version`] is [`ApiVersion::Unstable`], as the operations are
+    /// then separate subscriptions.
+    operations: HashMap<String, ChainHeadOperation, fnv::FnvBuildHasher>,
+
+    /// Identifier to assign to the next operation started through this subscription.
+    next_operation_id: u64,
+}
+
+/// See [`FollowSubscription::operations`].
+struct ChainHeadOperation {
+    /// Aborts the operation. Used when `chainHead_v1_stopOperation` is called.
+    abort_handle: future::AbortHandle,
+
+    /// `Some` for storage operations, which generate an `operationWaitingForContinue` event
+    /// after each page of items and wait for `chainHead_v1_continue` to be called.
+    continue_sender: Option<chain_head::OperationContinueSender>,
+}
+
+/// Version of the new JSON-RPC API that a JSON-RPC client is using.
+///
+/// The `unstable` and `v1` functions share the same implementation, but notifications must be
+/// sent using the method names corresponding to the version that the client uses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ApiVersion {
+    Unstable,
+    V1,
+}
+
+impl ApiVersion {
+    /// Builds a `chainHead_*_followEvent` notification.
+    ///
+    /// Events that are specific to `chainHead_v1_follow`, such as the outcome of operations, are
+    /// built using [`chain_head::follow_event_v1`] instead.
+    fn follow_event(self, subscription: &str, result: methods::FollowEvent) -> String {
+        match self {
+            ApiVersion::Unstable => methods::ServerToClient::chainHead_unstable_followEvent {
+                subscription: subscription.into(),
+                result,
+            }
+            .to_json_call_object_parameters(None),
+            ApiVersion::V1 => chain_head::follow_event_v1(
+                subscription,
+                match result {
+                    methods::FollowEvent::Initialized {
+                        finalized_block_hash,
+                        finalized_block_runtime,
+                    } => methods::FollowEventV1::Initialized {
+                        finalized_block_hashes: vec![finalized_block_hash],
+                        finalized_block_runtime,
+                    },
+                    methods::FollowEvent::NewBlock {
+                        block_hash,
+                        parent_block_hash,
+                        new_runtime,
+                    } => methods::FollowEventV1::NewBlock {
+                        block_hash,
+                        parent_block_hash,
+                        new_runtime,
+                    },
+                    methods::FollowEvent::BestBlockChanged { best_block_hash } => {
+                        methods::FollowEventV1::BestBlockChanged { best_block_hash }
+                    }
+                    methods::FollowEvent::Finalized {
+                        finalized_blocks_hashes,
+                        pruned_blocks_hashes,
+                    } => methods::FollowEventV1::Finalized {
+                        finalized_blocks_hashes,
+                        pruned_blocks_hashes,
+                    },
+                    methods::FollowEvent::Stop {} => methods::FollowEventV1::Stop {},
+                },
+            ),
+        }
+    }
+
+    /// Builds a `transaction_unstable_watchEvent` or `transactionWatch_v1_watchEvent`
+    /// notification.
+    fn transaction_watch_event(
+        self,
+        subscription: &str,
+        result: methods::TransactionWatchEvent,
+    ) -> String {
+        let subscription = subscription.into();
+        match self {
+            ApiVersion::Unstable => methods::ServerToClient::transaction_unstable_watchEvent {
+                subscription,
+                result,
+            },
+            ApiVersion::V1 => methods::ServerToClient::transactionWatch_v1_watchEvent {
+                subscription,
+                result,
+            },
+        }
+        .to_json_call_object_parameters(None)
+    }
 }
 
 pub(super) enum SubscriptionMessage {
@@ -124,6 +249,9 @@ pub(super) enum SubscriptionMessage {
     StopIfStorage {
         stop_request_id: (String, requests_subscriptions::RequestId),
     },
+    StopIfBeefyJustifications {
+        stop_request_id: (String, requests_subscriptions::RequestId),
+    },
     StopIfTransactionLegacy {
         stop_request_id: (String, requests_subscriptions::RequestId),
     },
@@ -145,8 +273,22 @@ pub(super) enum SubscriptionMessage {
     StopIfChainHeadFollow {
         stop_request_id: (String, requests_subscriptions::RequestId),
     },
+    /// Sent by `chainHead_v1_stopOperation` to the follow subscription.
+    ChainHeadStopOperation {
+        operation_id: String,
+        stop_request_id: (String, requests_subscriptions::RequestId),
+    },
+    /// Sent by `chainHead_v1_continue` to the follow subscription.
+    ChainHeadContinue {
+        operation_id: String,
+        continue_request_id: (String, requests_subscriptions::RequestId),
+    },
+    StopIfTransactionBroadcast {
+        stop_request_id: (String, requests_subscriptions::RequestId),
+    },
+    /// The blocks are either all unpinned, or none of them is if one of them isn't pinned.
     ChainHeadFollowUnpin {
-        hash: methods::HashHexString,
+        hashes: Vec<methods::HashHexString>,
         unpin_request_id: (String, requests_subscriptions::RequestId),
     },
     ChainHeadHeader {
@@ -167,6 +309,12 @@ pub(super) enum SubscriptionMessage {
         key: methods::HexString,
         child_key: Option<methods::HexString>,
     },
+    ChainHeadStorageItems {
+        hash: methods::HashHexString,
+        get_request_id: (String, requests_subscriptions::RequestId),
+        items: Vec<methods::ChainHeadStorageRequestItem>,
+        child_trie: Option<methods::HexString>,
+    },
     ChainHeadBody {
         hash: methods::HashHexString,
         get_request_id: (String, requests_subscriptions::RequestId),
@@ -190,6 +338,24 @@ struct Cache {
     /// [`Cache::recent_pinned_blocks`] then this field is guaranteed to be `Some`.
     subscription_id: Option<runtime_service::SubscriptionId>,
 
+    /// Number of the current best block, as reported by the runtime service subscription of
+    /// [`Cache::subscription_id`]. `None` if unknown.
+    best_block_number: Option<u64>,
+
+    /// Hashes and SCALE-encoded headers of the most recently finalized blocks, ordered by
+    /// increasing block number. Each header is the parent of the next one. Contains at most
+    /// [`MAX_RECENT_FINALIZED_HEADERS`] entries.
+    ///
+    /// Initially filled with [`StartConfig::recent_finalized_headers`], then updated whenever
+    /// the runtime service reports a finalized block. Contrary to
+    /// [`Cache::recent_pinned_blocks`], these blocks aren't pinned and can only be used to
+    /// retrieve headers.
+    recent_finalized_headers: VecDeque<([u8; 32], Vec<u8>)>,
+
+    /// Value of [`StartConfig::recent_finalized_runtime_code_hash`]. Compared with the runtime
+    /// code hash reported by the runtime service, then set to `None`.
+    recent_finalized_runtime_code_hash: Option<[u8; 32]>,
+
     /// State trie root hashes and numbers of blocks that were not in
     /// [`Cache::recent_pinned_blocks`].
     ///
@@ -216,12 +382,87 @@ struct Cache {
         fnv::FnvBuildHasher,
     >,
 
-    /// When `state_getKeysPaged` is called and the response is truncated, the response is
-    /// inserted in this cache. The API user is likely to call `state_getKeysPaged` again with
-    /// the same parameters, in which case we hit the cache and avoid the networking requests.
-    /// The keys are `(block_hash, prefix)` and values are list of keys.
-    state_get_keys_paged:
-        lru::LruCache<([u8; 32], Option<methods::HexString>), Vec<Vec<u8>>, fnv::FnvBuildHasher>,
+    /// When `state_getKeysPaged` is called, the keys that have been downloaded are inserted in
+    /// this cache. The API user is likely to call `state_getKeysPaged` again with the same
+    /// parameters, or with the last key of the response as the start key, in which case we hit
+    /// the cache and avoid some or all of the networking requests.
+    /// The keys are `(block_hash, prefix)`.
+    state_get_keys_paged: lru::LruCache<
+        ([u8; 32], Option<methods::HexString>),
+        GetKeysPagedCacheEntry,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// Hashes of finalized blocks, indexed by block number, that have been verified by
+    /// downloading the headers of the ancestors of a finalized block. Finalized blocks can't be
+    /// reverted, and the entries of this cache thus never become stale.
+    finalized_block_hashes: lru::LruCache<u64, [u8; 32], fnv::FnvBuildHasher>,
+}
+
+impl Cache {
+    /// Updates [`Cache::best_block_number`] following a change of the best block.
+    ///
+    /// Does nothing if the header of the new best block isn't in [`Cache::recent_pinned_blocks`].
+    fn set_best_block(&mut self, hash: &[u8; 32], block_number_bytes: usize) {
+        if let Some(header) = self.recent_pinned_blocks.peek(hash) {
+            if let Ok(decoded) = header::decode(header, block_number_bytes) {
+                self.best_block_number = Some(decoded.number);
+            }
+        }
+    }
+
+    /// Pushes a header at the back of [`Cache::recent_finalized_headers`].
+    ///
+    /// If the header isn't a child of the last header in the list, the list is cleared first.
+    fn push_recent_finalized_header(
+        &mut self,
+        hash: [u8; 32],
+        scale_encoded_header: &[u8],
+        block_number_bytes: usize,
+    ) {
+        if self
+            .recent_finalized_headers
+            .back()
+            .map_or(false, |(h, _)| *h == hash)
+        {
+            return;
+        }
+
+        let Ok(decoded) = header::decode(scale_encoded_header, block_number_bytes) else {
+            return;
+        };
+
+        if self
+            .recent_finalized_headers
+            .back()
+            .map_or(false, |(h, _)| h != decoded.parent_hash)
+        {
+            self.recent_finalized_headers.clear();
+        }
+
+        if self.recent_finalized_headers.len() >= MAX_RECENT_FINALIZED_HEADERS {
+            self.recent_finalized_headers.pop_front();
+        }
+
+        self.recent_finalized_headers
+            .push_back((hash, scale_encoded_header.to_vec()));
+    }
+}
+
+/// Entry of [`Cache::state_get_keys_paged`].
+struct GetKeysPagedCacheEntry {
+    /// All the keys in [`GetKeysPagedCacheEntry::keys`] are strictly superior to this key.
+    /// `None` if the keys start at the beginning of the prefix.
+    start_key: Option<Vec<u8>>,
+
+    /// All the keys with the prefix that are strictly superior to
+    /// [`GetKeysPagedCacheEntry::start_key`] and inferior or equal to the last element of this
+    /// list, in lexicographic order.
+    keys: Vec<Vec<u8>>,
+
+    /// If `true`, [`GetKeysPagedCacheEntry::keys`] contains all the keys with the prefix that are
+    /// strictly superior to [`GetKeysPagedCacheEntry::start_key`].
+    complete: bool,
 }
 
 pub(super) fn start<TPlat: Platform>(
@@ -229,9 +470,28 @@ pub(super) fn start<TPlat: Platform>(
     requests_subscriptions: Arc<requests_subscriptions::RequestsSubscriptions<SubscriptionMessage>>,
     mut config: StartConfig<'_, TPlat>,
     max_parallel_requests: NonZeroU32,
+    max_parallel_cheap_requests: NonZeroU32,
     max_parallel_subscription_updates: NonZeroU32,
     background_abort_registrations: Vec<future::AbortRegistration>,
 ) {
+    // Invalid endpoints are ignored rather than preventing the service from starting.
+    let archive_fallback_endpoints = config
+        .archive_fallback_endpoints
+        .iter()
+        .filter_map(
+            |endpoint| match archive_fallback::endpoint_to_multiaddr(endpoint) {
+                Some(multiaddr) => Some(multiaddr),
+                None => {
+                    log::warn!(
+                        target: &log_target,
+                        "Ignoring invalid archive fallback endpoint: {}", endpoint
+                    );
+                    None
+                }
+            },
+        )
+        .collect();
+
     let me = Arc::new(Background {
         log_target,
         requests_subscriptions,
@@ -252,6 +512,17 @@ pub(super) fn start<TPlat: Platform>(
                 Default::default(),
             ),
             subscription_id: None,
+            best_block_number: None,
+            recent_finalized_headers: {
+                let num_headers = config.recent_finalized_headers.len();
+                config
+                    .recent_finalized_headers
+                    .drain(..)
+                    .skip(num_headers.saturating_sub(MAX_RECENT_FINALIZED_HEADERS))
+                    .map(|header| (header::hash_from_scale_encoded_header(&header), header))
+                    .collect()
+            },
+            recent_finalized_runtime_code_hash: config.recent_finalized_runtime_code_hash,
             block_state_root_hashes_numbers: lru::LruCache::with_hasher(
                 NonZeroUsize::new(32).unwrap(),
                 Default::default(),
@@ -260,25 +531,42 @@ pub(super) fn start<TPlat: Platform>(
                 NonZeroUsize::new(2).unwrap(),
                 Default::default(),
             ),
+            finalized_block_hashes: lru::LruCache::with_hasher(
+                NonZeroUsize::new(4096).unwrap(),
+                Default::default(),
+            ),
         }),
+        shared_follow: config.shared_follow.clone(),
         genesis_block_hash: config.genesis_block_hash,
         printed_legacy_json_rpc_warning: atomic::AtomicBool::new(false),
+        archive_fallback_endpoints,
+        next_archive_fallback_endpoint: atomic::AtomicUsize::new(0),
+        max_block_hash_lookup_depth: u64::from(config.max_block_hash_lookup_depth),
     });
 
     let mut background_abort_registrations = background_abort_registrations.into_iter();
 
-    // A certain number of tasks (`max_parallel_requests`) are dedicated to pulling requests
-    // from the inner state machine and processing them.
+    // A certain number of tasks (`max_parallel_requests` and `max_parallel_cheap_requests`) are
+    // dedicated to pulling requests from the inner state machine and processing them.
     // Each task can only process one request at a time, which is why we spawn one task per
     // desired level of parallelism.
-    for n in 0..max_parallel_requests.get() {
+    // Cheap requests are processed by separate tasks, in order for them to not be stuck behind
+    // requests that take a long time to be answered.
+    for (n, lane) in iter::repeat(requests_subscriptions::RequestLane::Expensive)
+        .take(usize::try_from(max_parallel_requests.get()).unwrap())
+        .chain(
+            iter::repeat(requests_subscriptions::RequestLane::Cheap)
+                .take(usize::try_from(max_parallel_cheap_requests.get()).unwrap()),
+        )
+        .enumerate()
+    {
         let me = me.clone();
         (config.tasks_executor)(
             format!("{}-requests-{}", me.log_target, n),
             future::Abortable::new(
                 async move {
                     loop {
-                        me.handle_request().await;
+                        me.handle_request(lane).await;
 
                         // We yield once between each request in order to politely let other tasks
                         // do some work and not monopolize the CPU.
@@ -349,10 +637,41 @@ pub(super) fn start<TPlat: Platform>(
                     let finalized_block_hash = header::hash_from_scale_encoded_header(
                         &subscribe_all.finalized_block_scale_encoded_header,
                     );
+
+                    // If the runtime code of the last restored header is known, check it
+                    // against the one reported by the runtime service. A mismatch means that
+                    // the database that the headers come from can't be trusted.
+                    if let Some(expected) = cache.recent_finalized_runtime_code_hash.take() {
+                        if cache
+                            .recent_finalized_headers
+                            .back()
+                            .map_or(false, |(h, _)| *h == finalized_block_hash)
+                            && subscribe_all.finalized_block_runtime_code_hash != Some(expected)
+                        {
+                            log::warn!(
+                                target: &me.log_target,
+                                "Runtime code of block {} doesn't match the database. Discarding \
+                                the restored block headers.",
+                                HashDisplay(&finalized_block_hash)
+                            );
+                            cache.recent_finalized_headers.clear();
+                        }
+                    }
+
+                    cache.push_recent_finalized_header(
+                        finalized_block_hash,
+                        &subscribe_all.finalized_block_scale_encoded_header,
+                        me.sync_service.block_number_bytes(),
+                    );
                     cache.recent_pinned_blocks.put(
                         finalized_block_hash,
                         subscribe_all.finalized_block_scale_encoded_header,
                     );
+                    cache.best_block_number = None;
+                    cache.set_best_block(
+                        &finalized_block_hash,
+                        me.sync_service.block_number_bytes(),
+                    );
 
                     for block in subscribe_all.non_finalized_blocks_ancestry_order {
                         if cache.recent_pinned_blocks.len()
@@ -367,6 +686,9 @@ pub(super) fn start<TPlat: Platform>(
                         cache
                             .recent_pinned_blocks
                             .put(hash, block.scale_encoded_header);
+                        if block.is_new_best {
+                            cache.set_best_block(&hash, me.sync_service.block_number_bytes());
+                        }
                     }
 
                     drop(cache);
@@ -390,9 +712,60 @@ pub(super) fn start<TPlat: Platform>(
                                 cache
                                     .recent_pinned_blocks
                                     .put(hash, block.scale_encoded_header);
+                                if block.is_new_best {
+                                    cache.set_best_block(
+                                        &hash,
+                                        me.sync_service.block_number_bytes(),
+                                    );
+                                }
+                            }
+                            Some(runtime_service::Notification::Finalized {
+                                hash,
+                                best_block_hash,
+                                ..
+                            }) => {
+                                let mut cache = me.cache.lock().await;
+                                cache.set_best_block(
+                                    &best_block_hash,
+                                    me.sync_service.block_number_bytes(),
+                                );
+
+                                // Gather the newly-finalized blocks, from the highest to the
+                                // lowest. Blocks that have been evicted from the LRU cache of
+                                // recent blocks are missing and interrupt the chain.
+                                let mut newly_finalized = Vec::new();
+                                let mut iter_hash = hash;
+                                while cache
+                                    .recent_finalized_headers
+                                    .back()
+                                    .map_or(true, |(h, _)| *h != iter_hash)
+                                {
+                                    let Some(header) = cache.recent_pinned_blocks.peek(&iter_hash)
+                                    else {
+                                        break;
+                                    };
+                                    let Ok(decoded) = header::decode(
+                                        header,
+                                        me.sync_service.block_number_bytes(),
+                                    ) else {
+                                        break;
+                                    };
+                                    newly_finalized.push((iter_hash, header.clone()));
+                                    iter_hash = *decoded.parent_hash;
+                                }
+
+                                for (hash, header) in newly_finalized.into_iter().rev() {
+                                    cache.push_recent_finalized_header(
+                                        hash,
+                                        &header,
+                                        me.sync_service.block_number_bytes(),
+                                    );
+                                }
+                            }
+                            Some(runtime_service::Notification::BestBlockChanged { hash }) => {
+                                let mut cache = me.cache.lock().await;
+                                cache.set_best_block(&hash, me.sync_service.block_number_bytes());
                             }
-                            Some(runtime_service::Notification::Finalized { .. })
-                            | Some(runtime_service::Notification::BestBlockChanged { .. }) => {}
                             None => break,
                         }
                     }
@@ -408,10 +781,10 @@ pub(super) fn start<TPlat: Platform>(
 }
 
 impl<TPlat: Platform> Background<TPlat> {
-    /// Pulls one request from the inner state machine, and processes it.
-    async fn handle_request(self: &Arc<Self>) {
+    /// Pulls one request of the given lane from the inner state machine, and processes it.
+    async fn handle_request(self: &Arc<Self>, lane: requests_subscriptions::RequestLane) {
         let (json_rpc_request, state_machine_request_id) =
-            self.requests_subscriptions.next_request().await;
+            self.requests_subscriptions.next_request(lane).await;
         log::debug!(target: &self.log_target, "PendingRequestsQueue => {}",
             crate::util::truncated_str(
                 json_rpc_request.chars().filter(|c| !c.is_control()),
@@ -441,8 +814,7 @@ impl<TPlat: Platform> Background<TPlat> {
 
         // Print a warning for legacy JSON-RPC functions.
         match call {
-            methods::MethodCall::account_nextIndex { .. }
-            | methods::MethodCall::author_hasKey { .. }
+            methods::MethodCall::author_hasKey { .. }
             | methods::MethodCall::author_hasSessionKeys { .. }
             | methods::MethodCall::author_insertKey { .. }
             | methods::MethodCall::author_pendingExtrinsics { .. }
@@ -452,6 +824,8 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::author_submitExtrinsic { .. }
             | methods::MethodCall::author_unwatchExtrinsic { .. }
             | methods::MethodCall::babe_epochAuthorship { .. }
+            | methods::MethodCall::beefy_subscribeJustifications { .. }
+            | methods::MethodCall::beefy_unsubscribeJustifications { .. }
             | methods::MethodCall::chain_getBlock { .. }
             | methods::MethodCall::chain_getBlockHash { .. }
             | methods::MethodCall::chain_getFinalizedHead { .. }
@@ -463,12 +837,18 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::chain_unsubscribeFinalizedHeads { .. }
             | methods::MethodCall::chain_unsubscribeNewHeads { .. }
             | methods::MethodCall::childstate_getKeys { .. }
+            | methods::MethodCall::childstate_getKeysPaged { .. }
             | methods::MethodCall::childstate_getStorage { .. }
             | methods::MethodCall::childstate_getStorageHash { .. }
             | methods::MethodCall::childstate_getStorageSize { .. }
             | methods::MethodCall::grandpa_roundState { .. }
+            | methods::MethodCall::mmr_generateProof { .. }
+            | methods::MethodCall::mmr_root { .. }
+            | methods::MethodCall::mmr_verifyProof { .. }
+            | methods::MethodCall::mmr_verifyProofStateless { .. }
             | methods::MethodCall::offchain_localStorageGet { .. }
             | methods::MethodCall::offchain_localStorageSet { .. }
+            | methods::MethodCall::payment_queryFeeDetails { .. }
             | methods::MethodCall::payment_queryInfo { .. }
             | methods::MethodCall::state_call { .. }
             | methods::MethodCall::state_getKeys { .. }
@@ -484,9 +864,11 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::state_queryStorageAt { .. }
             | methods::MethodCall::state_subscribeRuntimeVersion { .. }
             | methods::MethodCall::state_subscribeStorage { .. }
+            | methods::MethodCall::state_traceBlock { .. }
             | methods::MethodCall::state_unsubscribeRuntimeVersion { .. }
             | methods::MethodCall::state_unsubscribeStorage { .. }
             | methods::MethodCall::system_accountNextIndex { .. }
+            | methods::MethodCall::system_addLogFilter { .. }
             | methods::MethodCall::system_addReservedPeer { .. }
             | methods::MethodCall::system_chain { .. }
             | methods::MethodCall::system_chainType { .. }
@@ -500,6 +882,8 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::system_peers { .. }
             | methods::MethodCall::system_properties { .. }
             | methods::MethodCall::system_removeReservedPeer { .. }
+            | methods::MethodCall::system_resetLogFilter { .. }
+            | methods::MethodCall::system_syncState { .. }
             | methods::MethodCall::system_version { .. } => {
                 if !self
                     .printed_legacy_json_rpc_warning
@@ -537,11 +921,55 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::sudo_unstable_version { .. }
             | methods::MethodCall::transaction_unstable_submitAndWatch { .. }
             | methods::MethodCall::transaction_unstable_unwatch { .. }
+            | methods::MethodCall::chainHead_v1_body { .. }
+            | methods::MethodCall::chainHead_v1_call { .. }
+            | methods::MethodCall::chainHead_v1_continue { .. }
+            | methods::MethodCall::chainHead_v1_follow { .. }
+            | methods::MethodCall::chainHead_v1_header { .. }
+            | methods::MethodCall::chainHead_v1_stopOperation { .. }
+            | methods::MethodCall::chainHead_v1_storage { .. }
+            | methods::MethodCall::chainHead_v1_unfollow { .. }
+            | methods::MethodCall::chainHead_v1_unpin { .. }
+            | methods::MethodCall::transaction_v1_broadcast { .. }
+            | methods::MethodCall::transaction_v1_stop { .. }
+            | methods::MethodCall::transactionWatch_v1_submitAndWatch { .. }
+            | methods::MethodCall::transactionWatch_v1_unwatch { .. }
             | methods::MethodCall::network_unstable_subscribeEvents { .. }
             | methods::MethodCall::network_unstable_unsubscribeEvents { .. }
             | methods::MethodCall::chainHead_unstable_finalizedDatabase { .. } => {}
         }
 
+        crate::util::instrument!(
+            self.dispatch_request(
+                request_id,
+                call,
+                state_machine_request_id,
+                &json_rpc_request,
+            ),
+            "json-rpc-request",
+            chain = %self.log_target,
+            id = request_id,
+            method = call.name()
+        )
+        .await
+    }
+
+    /// Processes a JSON-RPC request that has been parsed by [`Background::handle_request`].
+    async fn dispatch_request(
+        self: &Arc<Self>,
+        request_id: &str,
+        call: methods::MethodCall<'_>,
+        state_machine_request_id: requests_subscriptions::RequestId,
+        json_rpc_request: &str,
+    ) {
+        // Requests that the light client can't answer by itself are forwarded to an archive
+        // node, if any has been configured.
+        if self.should_forward_to_archive(&call).await {
+            self.forward_to_archive((request_id, &state_machine_request_id), json_rpc_request)
+                .await;
+            return;
+        }
+
         // Each call is handled in a separate method.
         match call {
             methods::MethodCall::author_pendingExtrinsics {} => {
@@ -556,7 +984,7 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.submit_and_watch_transaction(
                     (request_id, &state_machine_request_id),
                     transaction,
-                    true,
+                    None,
                 )
                 .await
             }
@@ -567,6 +995,17 @@ impl<TPlat: Platform> Background<TPlat> {
                 )
                 .await;
             }
+            methods::MethodCall::beefy_subscribeJustifications {} => {
+                self.beefy_subscribe_justifications((request_id, &state_machine_request_id))
+                    .await;
+            }
+            methods::MethodCall::beefy_unsubscribeJustifications { subscription } => {
+                self.beefy_unsubscribe_justifications(
+                    (request_id, &state_machine_request_id),
+                    &subscription,
+                )
+                .await;
+            }
             methods::MethodCall::chain_getBlock { hash } => {
                 self.chain_get_block((request_id, &state_machine_request_id), hash)
                     .await;
@@ -616,6 +1055,90 @@ impl<TPlat: Platform> Background<TPlat> {
                 )
                 .await;
             }
+            methods::MethodCall::childstate_getKeys {
+                child_storage_key,
+                prefix,
+                hash,
+            } => {
+                self.childstate_get_keys(
+                    (request_id, &state_machine_request_id),
+                    child_storage_key,
+                    prefix,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::childstate_getKeysPaged {
+                child_storage_key,
+                prefix,
+                count,
+                start_key,
+                hash,
+            } => {
+                self.childstate_get_keys_paged(
+                    (request_id, &state_machine_request_id),
+                    child_storage_key,
+                    prefix,
+                    count,
+                    start_key,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::childstate_getStorage {
+                child_storage_key,
+                key,
+                hash,
+            } => {
+                self.childstate_get_storage(
+                    (request_id, &state_machine_request_id),
+                    child_storage_key,
+                    key,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::childstate_getStorageHash {
+                child_storage_key,
+                key,
+                hash,
+            } => {
+                self.childstate_get_storage_hash(
+                    (request_id, &state_machine_request_id),
+                    child_storage_key,
+                    key,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::mmr_generateProof { .. } => {
+                self.mmr_generate_proof((request_id, &state_machine_request_id))
+                    .await;
+            }
+            methods::MethodCall::mmr_root { at } => {
+                self.mmr_root((request_id, &state_machine_request_id), at)
+                    .await;
+            }
+            methods::MethodCall::mmr_verifyProof { proof } => {
+                self.mmr_verify_proof((request_id, &state_machine_request_id), proof)
+                    .await;
+            }
+            methods::MethodCall::mmr_verifyProofStateless { mmr_root, proof } => {
+                self.mmr_verify_proof_stateless(
+                    (request_id, &state_machine_request_id),
+                    mmr_root,
+                    proof,
+                )
+                .await;
+            }
+            methods::MethodCall::payment_queryFeeDetails { extrinsic, hash } => {
+                self.payment_query_fee_details(
+                    (request_id, &state_machine_request_id),
+                    &extrinsic.0,
+                    hash.as_ref().map(|h| &h.0),
+                )
+                .await;
+            }
             methods::MethodCall::payment_queryInfo { extrinsic, hash } => {
                 self.payment_query_info(
                     (request_id, &state_machine_request_id),
@@ -660,6 +1183,19 @@ impl<TPlat: Platform> Background<TPlat> {
                 )
                 .await;
             }
+            methods::MethodCall::state_queryStorage {
+                keys,
+                from_block,
+                to_block,
+            } => {
+                self.state_query_storage(
+                    (request_id, &state_machine_request_id),
+                    keys,
+                    from_block,
+                    to_block,
+                )
+                .await;
+            }
             methods::MethodCall::state_queryStorageAt { keys, at } => {
                 self.state_query_storage_at((request_id, &state_machine_request_id), keys, at)
                     .await;
@@ -668,6 +1204,10 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.state_get_metadata((request_id, &state_machine_request_id), hash)
                     .await;
             }
+            methods::MethodCall::state_getReadProof { keys, at } => {
+                self.state_get_read_proof((request_id, &state_machine_request_id), keys, at)
+                    .await;
+            }
             methods::MethodCall::state_getStorage { key, hash } => {
                 self.state_get_storage((request_id, &state_machine_request_id), key, hash)
                     .await;
@@ -729,6 +1269,10 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.system_name((request_id, &state_machine_request_id))
                     .await;
             }
+            methods::MethodCall::system_networkState {} => {
+                self.system_network_state((request_id, &state_machine_request_id))
+                    .await;
+            }
             methods::MethodCall::system_nodeRoles {} => {
                 self.system_node_roles((request_id, &state_machine_request_id))
                     .await;
@@ -741,6 +1285,10 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.system_properties((request_id, &state_machine_request_id))
                     .await;
             }
+            methods::MethodCall::system_syncState {} => {
+                self.system_sync_state((request_id, &state_machine_request_id))
+                    .await;
+            }
             methods::MethodCall::system_version {} => {
                 self.system_version((request_id, &state_machine_request_id))
                     .await;
@@ -815,8 +1363,12 @@ impl<TPlat: Platform> Background<TPlat> {
                 .await;
             }
             methods::MethodCall::chainHead_unstable_follow { runtime_updates } => {
-                self.chain_head_follow((request_id, &state_machine_request_id), runtime_updates)
-                    .await;
+                self.chain_head_follow(
+                    (request_id, &state_machine_request_id),
+                    runtime_updates,
+                    ApiVersion::Unstable,
+                )
+                .await;
             }
             methods::MethodCall::chainHead_unstable_genesisHash {} => {
                 self.chain_head_unstable_genesis_hash((request_id, &state_machine_request_id))
@@ -887,7 +1439,7 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.submit_and_watch_transaction(
                     (request_id, &state_machine_request_id),
                     transaction,
-                    false,
+                    Some(ApiVersion::Unstable),
                 )
                 .await
             }
@@ -899,29 +1451,154 @@ impl<TPlat: Platform> Background<TPlat> {
                 .await;
             }
 
-            _method @ (methods::MethodCall::account_nextIndex { .. }
-            | methods::MethodCall::author_hasKey { .. }
+            methods::MethodCall::chainHead_v1_body {
+                follow_subscription,
+                hash,
+            } => {
+                self.chain_head_v1_body(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_call {
+                follow_subscription,
+                hash,
+                function,
+                call_parameters,
+            } => {
+                self.chain_head_v1_call(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    hash,
+                    function.into_owned(),
+                    call_parameters,
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_continue {
+                follow_subscription,
+                operation_id,
+            } => {
+                self.chain_head_v1_continue(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    operation_id.into_owned(),
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_follow { with_runtime } => {
+                self.chain_head_follow(
+                    (request_id, &state_machine_request_id),
+                    with_runtime,
+                    ApiVersion::V1,
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_header {
+                follow_subscription,
+                hash,
+            } => {
+                self.chain_head_unstable_header(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    hash,
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_stopOperation {
+                follow_subscription,
+                operation_id,
+            } => {
+                self.chain_head_v1_stop_operation(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    operation_id.into_owned(),
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_storage {
+                follow_subscription,
+                hash,
+                items,
+                child_trie,
+            } => {
+                self.chain_head_v1_storage(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    hash,
+                    items,
+                    child_trie,
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_unfollow {
+                follow_subscription,
+            } => {
+                self.chain_head_unstable_unfollow(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                )
+                .await;
+            }
+            methods::MethodCall::chainHead_v1_unpin {
+                follow_subscription,
+                hash_or_hashes,
+            } => {
+                self.chain_head_v1_unpin(
+                    (request_id, &state_machine_request_id),
+                    &follow_subscription,
+                    match hash_or_hashes {
+                        methods::HashHexStringSingleOrArray::Single(hash) => vec![hash],
+                        methods::HashHexStringSingleOrArray::Array(hashes) => hashes,
+                    },
+                )
+                .await;
+            }
+            methods::MethodCall::transaction_v1_broadcast { transaction } => {
+                self.transaction_v1_broadcast((request_id, &state_machine_request_id), transaction)
+                    .await;
+            }
+            methods::MethodCall::transaction_v1_stop { operation_id } => {
+                self.transaction_v1_stop((request_id, &state_machine_request_id), &operation_id)
+                    .await;
+            }
+            methods::MethodCall::transactionWatch_v1_submitAndWatch { transaction } => {
+                self.submit_and_watch_transaction(
+                    (request_id, &state_machine_request_id),
+                    transaction,
+                    Some(ApiVersion::V1),
+                )
+                .await
+            }
+            methods::MethodCall::transactionWatch_v1_unwatch { subscription } => {
+                self.transaction_unstable_unwatch(
+                    (request_id, &state_machine_request_id),
+                    &subscription,
+                )
+                .await;
+            }
+
+            _method @ (methods::MethodCall::author_hasKey { .. }
             | methods::MethodCall::author_hasSessionKeys { .. }
             | methods::MethodCall::author_insertKey { .. }
             | methods::MethodCall::author_removeExtrinsic { .. }
             | methods::MethodCall::author_rotateKeys { .. }
             | methods::MethodCall::babe_epochAuthorship { .. }
-            | methods::MethodCall::childstate_getKeys { .. }
-            | methods::MethodCall::childstate_getStorage { .. }
-            | methods::MethodCall::childstate_getStorageHash { .. }
             | methods::MethodCall::childstate_getStorageSize { .. }
             | methods::MethodCall::grandpa_roundState { .. }
             | methods::MethodCall::offchain_localStorageGet { .. }
             | methods::MethodCall::offchain_localStorageSet { .. }
             | methods::MethodCall::state_getPairs { .. }
-            | methods::MethodCall::state_getReadProof { .. }
             | methods::MethodCall::state_getStorageHash { .. }
             | methods::MethodCall::state_getStorageSize { .. }
-            | methods::MethodCall::state_queryStorage { .. }
+            | methods::MethodCall::state_traceBlock { .. }
+            | methods::MethodCall::system_addLogFilter { .. }
             | methods::MethodCall::system_addReservedPeer { .. }
             | methods::MethodCall::system_dryRun { .. }
-            | methods::MethodCall::system_networkState { .. }
             | methods::MethodCall::system_removeReservedPeer { .. }
+            | methods::MethodCall::system_resetLogFilter { .. }
             | methods::MethodCall::network_unstable_subscribeEvents { .. }
             | methods::MethodCall::network_unstable_unsubscribeEvents { .. }) => {
                 // TODO: implement the ones that make sense to implement ^
@@ -1117,6 +1794,172 @@ impl<TPlat: Platform> Background<TPlat> {
         Ok(result)
     }
 
+    /// Similar to [`Background::storage_query`], but returns the entries of the storage proof
+    /// rather than the storage values.
+    async fn storage_read_proof(
+        &self,
+        keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        hash: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        let (state_trie_root_hash, block_number) = self
+            .state_trie_root_hash(hash)
+            .await
+            .map_err(StorageQueryError::FindStorageRootHashError)?;
+
+        let result = self
+            .sync_service
+            .clone()
+            .storage_read_proof(
+                block_number,
+                hash,
+                &state_trie_root_hash,
+                keys,
+                total_attempts,
+                timeout_per_request,
+            )
+            .await
+            .map_err(StorageQueryError::StorageRetrieval)?;
+
+        Ok(result)
+    }
+
+    /// Obtains the root hash of the given child trie, and the height of the given block.
+    ///
+    /// `child_trie` is the key of the child trie, without the `:child_storage:default:` prefix.
+    /// The root of a child trie is found in the main trie under the key
+    /// `:child_storage:default:` followed with the key of the child trie. Returns `Ok(None)` if
+    /// the child trie doesn't exist.
+    async fn child_trie_root(
+        &self,
+        child_trie: &[u8],
+        hash: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Option<([u8; 32], u64)>, StorageQueryError> {
+        let (state_trie_root_hash, block_number) = self
+            .state_trie_root_hash(hash)
+            .await
+            .map_err(StorageQueryError::FindStorageRootHashError)?;
+
+        let main_trie_key = b":child_storage:default:"
+            .iter()
+            .chain(child_trie.iter())
+            .copied()
+            .collect::<Vec<_>>();
+
+        let child_trie_root = self
+            .sync_service
+            .clone()
+            .storage_query(
+                block_number,
+                hash,
+                &state_trie_root_hash,
+                iter::once(&main_trie_key),
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await
+            .map_err(StorageQueryError::StorageRetrieval)?
+            .pop()
+            .unwrap();
+
+        match child_trie_root {
+            Some(root) => match <[u8; 32]>::try_from(&root[..]) {
+                Ok(root) => Ok(Some((root, block_number))),
+                Err(_) => Err(StorageQueryError::InvalidChildTrieRoot),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Similar to [`Background::storage_query`], but queries keys of the given child trie.
+    ///
+    /// `child_trie` is the key of the child trie, without the `:child_storage:default:` prefix.
+    /// All the values are `None` if the child trie doesn't exist.
+    async fn child_storage_query(
+        &self,
+        child_trie: &[u8],
+        keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        hash: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        let Some((child_trie_root, block_number)) = self
+            .child_trie_root(
+                child_trie,
+                hash,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await?
+        else {
+            return Ok(keys.map(|_| None).collect());
+        };
+
+        self.sync_service
+            .clone()
+            .child_storage_query(
+                block_number,
+                hash,
+                child_trie,
+                &child_trie_root,
+                keys,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await
+            .map_err(StorageQueryError::StorageRetrieval)
+    }
+
+    /// Queries the list of keys of the given child trie that start with the given prefix.
+    ///
+    /// `child_trie` is the key of the child trie, without the `:child_storage:default:` prefix.
+    /// The list is empty if the child trie doesn't exist.
+    async fn child_storage_prefix_keys_query(
+        &self,
+        child_trie: &[u8],
+        prefix: &[u8],
+        hash: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        let Some((child_trie_root, block_number)) = self
+            .child_trie_root(
+                child_trie,
+                hash,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await?
+        else {
+            return Ok(Vec::new());
+        };
+
+        self.sync_service
+            .clone()
+            .child_storage_prefix_keys_query(
+                block_number,
+                hash,
+                child_trie,
+                prefix,
+                &child_trie_root,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await
+            .map_err(StorageQueryError::StorageRetrieval)
+    }
+
     /// Obtain a lock to the runtime of the given block against the runtime service.
     // TODO: return better error?
     async fn runtime_lock(
@@ -1214,8 +2057,8 @@ impl<TPlat: Platform> Background<TPlat> {
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
     ) -> Result<RuntimeCallResult, RuntimeCallError> {
-        let (return_value, api_version) = self
-            .runtime_call_inner(
+        let (return_value, api_version) = crate::util::instrument!(
+            self.runtime_call_inner(
                 block_hash,
                 Some((runtime_api, required_api_version_range)),
                 function_to_call,
@@ -1223,8 +2066,13 @@ impl<TPlat: Platform> Background<TPlat> {
                 total_attempts,
                 timeout_per_request,
                 max_parallel,
-            )
-            .await?;
+            ),
+            "runtime-call",
+            chain = %self.log_target,
+            block = %hex::encode(block_hash),
+            function = function_to_call
+        )
+        .await?;
         Ok(RuntimeCallResult {
             return_value,
             api_version: api_version.unwrap(),
@@ -1243,8 +2091,8 @@ impl<TPlat: Platform> Background<TPlat> {
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
     ) -> Result<Vec<u8>, RuntimeCallError> {
-        let (return_value, _api_version) = self
-            .runtime_call_inner(
+        let (return_value, _api_version) = crate::util::instrument!(
+            self.runtime_call_inner(
                 block_hash,
                 None::<(&str, ops::RangeFull)>,
                 function_to_call,
@@ -1252,8 +2100,13 @@ impl<TPlat: Platform> Background<TPlat> {
                 total_attempts,
                 timeout_per_request,
                 max_parallel,
-            )
-            .await?;
+            ),
+            "runtime-call",
+            chain = %self.log_target,
+            block = %hex::encode(block_hash),
+            function = function_to_call
+        )
+        .await?;
         debug_assert!(_api_version.is_none());
         Ok(return_value)
     }
@@ -1273,21 +2126,13 @@ impl<TPlat: Platform> Background<TPlat> {
         // then performing the actual call. The first step is the longest and most difficult.
         let precall = self.runtime_lock(block_hash).await?;
 
-        let (runtime_call_lock, virtual_machine) = precall
-            .start(
-                function_to_call,
-                call_parameters.clone(),
-                total_attempts,
-                timeout_per_request,
-                max_parallel,
-            )
-            .await
-            .unwrap(); // TODO: don't unwrap
-
         // Check that the runtime version is correct.
         let runtime_api_version = if let Some((api_name, version_range)) = runtime_api_check {
-            let version = virtual_machine
-                .runtime_version()
+            let version = precall
+                .specification()
+                .map_err(|err| {
+                    RuntimeCallError::Call(runtime_service::RuntimeCallError::InvalidRuntime(err))
+                })?
                 .decode()
                 .apis
                 .find_version(api_name);
@@ -1300,6 +2145,26 @@ impl<TPlat: Platform> Background<TPlat> {
             None
         };
 
+        // Identical calls that have recently been performed are answered from the cache of the
+        // runtime service.
+        if let Some(output) = precall
+            .cached_output(function_to_call, call_parameters.clone())
+            .await
+        {
+            return Ok((output, runtime_api_version));
+        }
+
+        let (runtime_call_lock, virtual_machine) = precall
+            .start(
+                function_to_call,
+                call_parameters.clone(),
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await
+            .unwrap(); // TODO: don't unwrap
+
         // Now that we have obtained the virtual machine, we can perform the call.
         // This is a CPU-only operation that executes the virtual machine.
         // The virtual machine might access the storage.
@@ -1313,6 +2178,12 @@ impl<TPlat: Platform> Background<TPlat> {
             storage_main_trie_changes: Default::default(),
             offchain_storage_changes: Default::default(),
             max_log_level: 0,
+            offchain_behavior: runtime_host::OffchainBehavior::EmulateInMemory {
+                timestamp_ms: u64::try_from(TPlat::now_from_unix_epoch().as_millis())
+                    .unwrap_or(u64::max_value()),
+                random_seed: rand::random(),
+            },
+            tracing: false,
         }) {
             Ok(vm) => vm,
             Err((err, prototype)) => {
@@ -1325,11 +2196,21 @@ impl<TPlat: Platform> Background<TPlat> {
             match runtime_call {
                 runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
                     let output = success.virtual_machine.value().as_ref().to_vec();
+                    runtime_call_lock.cache_output(&output);
                     runtime_call_lock.unlock(success.virtual_machine.into_prototype());
                     break Ok((output, runtime_api_version));
                 }
                 runtime_host::RuntimeHostVm::Finished(Err(error)) => {
                     runtime_call_lock.unlock(error.prototype);
+                    if matches!(
+                        error.detail,
+                        runtime_host::ErrorDetail::WasmVm {
+                            error: host::Error::Trap(vm::Trap::OutOfFuel),
+                            ..
+                        }
+                    ) {
+                        break Err(RuntimeCallError::ExecutionTimeout);
+                    }
                     break Err(RuntimeCallError::RuntimeError(error.detail));
                 }
                 runtime_host::RuntimeHostVm::StorageGet(get) => {
@@ -1355,6 +2236,10 @@ impl<TPlat: Platform> Background<TPlat> {
                 runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
                     runtime_call = sig.verify_and_resume();
                 }
+                runtime_host::RuntimeHostVm::Offchain(_) => {
+                    // Off-chain host functions are configured to be emulated.
+                    unreachable!()
+                }
                 runtime_host::RuntimeHostVm::PrefixKeys(pk) => {
                     // TODO:
                     runtime_call_lock
@@ -1374,6 +2259,12 @@ enum StorageQueryError {
     /// Error while retrieving the storage item from other nodes.
     #[display(fmt = "{_0}")]
     StorageRetrieval(sync_service::StorageQueryError),
+    /// Root of the requested child trie found in the main trie isn't 32 bytes.
+    #[display(fmt = "Invalid child trie root hash")]
+    InvalidChildTrieRoot,
+    /// Child storage key passed as parameter doesn't start with `:child_storage:default:`.
+    #[display(fmt = "Child storage key doesn't start with `:child_storage:default:`")]
+    InvalidChildStorageKey,
 }
 
 // TODO: doc and properly derive Display
@@ -1387,6 +2278,9 @@ enum RuntimeCallError {
     RuntimeError(runtime_host::ErrorDetail),
     NextKeyForbidden,
     PrefixKeysForbidden,
+    /// Runtime call has consumed all the fuel it was allowed to consume.
+    #[display(fmt = "Runtime call has exceeded its execution limit")]
+    ExecutionTimeout,
     /// Required runtime API isn't supported by the runtime.
     ApiNotFound,
     /// Version requirement of runtime API isn't supported.
@@ -0,0 +1,385 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Forwarding of JSON-RPC requests to archive nodes.
+//!
+//! The light client can only obtain the storage of blocks whose state is still kept by the full
+//! nodes of the peer-to-peer network. Full nodes typically discard the state of blocks that are
+//! more than a few hundred blocks old. Additionally, some JSON-RPC functions can't be implemented
+//! by a light client.
+//!
+//! If the API user has configured archive fallback endpoints, requests that fall into these
+//! categories are sent as they are to one of these endpoints over a WebSocket connection, and the
+//! response is relayed back. The response isn't verified in any way, and is annotated with an
+//! `"untrusted": true` field.
+//!
+//! One connection is opened for each forwarded request. The request is sent in binary WebSocket
+//! frames, which the JSON-RPC server must accept.
+
+use super::{Background, Platform};
+
+use crate::platform::{PlatformConnection, ReadBuffer};
+
+use alloc::{
+    format,
+    string::{String, ToString as _},
+    vec::Vec,
+};
+use core::{cmp, sync::atomic, time::Duration};
+use futures::prelude::*;
+use smoldot::{
+    header,
+    json_rpc::{self, methods, requests_subscriptions},
+    libp2p::multiaddr::Multiaddr,
+};
+
+/// Number of blocks below the current best block after which the storage of a block is
+/// considered as no longer provable.
+///
+/// Full nodes keep the state of the latest 256 blocks by default.
+const MAX_PROVABLE_DEPTH: u64 = 256;
+
+/// Maximum duration of a forwarded request, including the time to connect.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Maximum number of bytes received from an archive node while waiting for a response.
+const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+impl<TPlat: Platform> Background<TPlat> {
+    /// Returns `true` if the given call should be forwarded to an archive node rather than being
+    /// processed locally.
+    ///
+    /// The decision is taken using only local information, without any network request. Calls
+    /// that target a block unknown to the light client are forwarded, as such a block is most
+    /// likely too old to be in any of the local caches.
+    pub(super) async fn should_forward_to_archive(&self, call: &methods::MethodCall<'_>) -> bool {
+        if self.archive_fallback_endpoints.is_empty() {
+            return false;
+        }
+
+        let block_hash = match call {
+            // Read-only functions that aren't implemented by the light client.
+            methods::MethodCall::babe_epochAuthorship { .. }
+            | methods::MethodCall::childstate_getStorageSize { .. }
+            | methods::MethodCall::grandpa_roundState { .. }
+            | methods::MethodCall::mmr_generateProof { .. }
+            | methods::MethodCall::state_getPairs { .. }
+            | methods::MethodCall::state_getStorageHash { .. }
+            | methods::MethodCall::state_getStorageSize { .. }
+            | methods::MethodCall::system_dryRun { .. } => return true,
+
+            // Functions that target a specific block.
+            methods::MethodCall::chain_getBlock { hash: Some(hash) }
+            | methods::MethodCall::chain_getHeader { hash: Some(hash) }
+            | methods::MethodCall::childstate_getKeys {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::childstate_getKeysPaged {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::childstate_getStorage {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::childstate_getStorageHash {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::mmr_root { at: Some(hash) }
+            | methods::MethodCall::payment_queryFeeDetails {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::payment_queryInfo {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::state_call {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::state_getKeys {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::state_getKeysPaged {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::state_getMetadata { hash: Some(hash) }
+            | methods::MethodCall::state_getReadProof { at: Some(hash), .. }
+            | methods::MethodCall::state_getRuntimeVersion { at: Some(hash) }
+            | methods::MethodCall::state_getStorage {
+                hash: Some(hash), ..
+            }
+            | methods::MethodCall::state_queryStorage {
+                from_block: hash, ..
+            }
+            | methods::MethodCall::state_queryStorageAt { at: Some(hash), .. }
+            | methods::MethodCall::state_traceBlock { block: hash, .. } => hash.0,
+
+            _ => return false,
+        };
+
+        let cache = self.cache.lock().await;
+        let block_number_bytes = self.sync_service.block_number_bytes();
+
+        // As long as the best block is unknown, the light client is still starting and any
+        // request is processed locally.
+        let Some(best_block_number) = cache.best_block_number else {
+            return false;
+        };
+
+        let block_number = if let Some(header) = cache.recent_pinned_blocks.peek(&block_hash) {
+            header::decode(header, block_number_bytes)
+                .ok()
+                .map(|header| header.number)
+        } else if let Some((_, header)) = cache
+            .recent_finalized_headers
+            .iter()
+            .find(|(hash, _)| *hash == block_hash)
+        {
+            header::decode(header, block_number_bytes)
+                .ok()
+                .map(|header| header.number)
+        } else if let Some(future::MaybeDone::Done(Ok((_, number)))) =
+            cache.block_state_root_hashes_numbers.peek(&block_hash)
+        {
+            Some(*number)
+        } else {
+            cache
+                .finalized_block_hashes
+                .iter()
+                .find(|(_, hash)| **hash == block_hash)
+                .map(|(number, _)| *number)
+        };
+
+        match block_number {
+            Some(block_number) => {
+                block_number.saturating_add(MAX_PROVABLE_DEPTH) < best_block_number
+            }
+            None => true,
+        }
+    }
+
+    /// Forwards the given JSON-RPC request to one of the archive nodes and sends back the
+    /// response.
+    pub(super) async fn forward_to_archive(
+        &self,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        json_rpc_request: &str,
+    ) {
+        debug_assert!(!self.archive_fallback_endpoints.is_empty());
+        let endpoint = &self.archive_fallback_endpoints[self
+            .next_archive_fallback_endpoint
+            .fetch_add(1, atomic::Ordering::Relaxed)
+            % self.archive_fallback_endpoints.len()];
+
+        log::debug!(
+            target: &self.log_target,
+            "Forwarding JSON-RPC request with id {} to {}", request_id.0, endpoint
+        );
+
+        let request = archive_request::<TPlat>(endpoint, request_id.0, json_rpc_request);
+        let timeout = TPlat::sleep(REQUEST_TIMEOUT);
+        futures::pin_mut!(request);
+        let outcome = match future::select(request, timeout).await {
+            future::Either::Left((outcome, _)) => outcome,
+            future::Either::Right(((), _)) => Err(ArchiveRequestError::Timeout),
+        };
+
+        let response = match outcome {
+            Ok(response) => response,
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Failed to forward JSON-RPC request to {}: {}", endpoint, error
+                );
+                json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Failed to forward request to archive node: {error}"),
+                    ),
+                    None,
+                )
+            }
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+}
+
+/// Turns an archive fallback endpoint, as passed by the API user, into a multiaddress that can
+/// be passed to [`Platform::connect`].
+///
+/// Endpoints can be either a `ws://` or `wss://` URL, or a multiaddress. The host of a URL can be
+/// a domain name, an IPv4 address, or an IPv6 address between brackets. Returns `None` if the
+/// endpoint is invalid or if the URL contains a path, as multiaddresses can't represent paths.
+pub(super) fn endpoint_to_multiaddr(endpoint: &str) -> Option<String> {
+    let (host_and_port, is_secure) = if let Some(rest) = endpoint.strip_prefix("wss://") {
+        (rest, true)
+    } else if let Some(rest) = endpoint.strip_prefix("ws://") {
+        (rest, false)
+    } else if endpoint.starts_with('/') {
+        return Some(endpoint.into());
+    } else {
+        return None;
+    };
+
+    let host_and_port = match host_and_port.split_once('/') {
+        Some((host_and_port, "")) => host_and_port,
+        None => host_and_port,
+        Some(_) => return None,
+    };
+
+    // IPv6 addresses are surrounded with brackets, and contain colons themselves.
+    let (host, port) = if let Some(rest) = host_and_port.strip_prefix('[') {
+        let (host, after_host) = rest.split_once(']')?;
+        match after_host {
+            "" => (host, None),
+            port => (host, Some(port.strip_prefix(':')?)),
+        }
+    } else {
+        match host_and_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_and_port, None),
+        }
+    };
+
+    let port = match port {
+        Some(port) => port.parse::<u16>().ok()?,
+        None if is_secure => 443,
+        None => 80,
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    let host_protocol = if host_and_port.starts_with('[') {
+        "ip6"
+    } else if host.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        "ip4"
+    } else {
+        "dns"
+    };
+
+    // Parsing the multiaddress ensures that IP addresses are valid.
+    let multiaddr = format!(
+        "/{host_protocol}/{host}/tcp/{port}/{}",
+        if is_secure { "wss" } else { "ws" }
+    );
+    multiaddr.parse::<Multiaddr>().ok()?;
+    Some(multiaddr)
+}
+
+/// Connects to the given multiaddress, sends the given JSON-RPC request, and waits for the
+/// response to the request with the given identifier.
+///
+/// The returned response is annotated with an `"untrusted": true` field.
+async fn archive_request<TPlat: Platform>(
+    multiaddr: &str,
+    request_id_json: &str,
+    json_rpc_request: &str,
+) -> Result<String, ArchiveRequestError> {
+    let mut stream = match TPlat::connect(multiaddr).await {
+        Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(stream)) => stream,
+        Ok(PlatformConnection::MultiStreamWebRtc { .. }) => {
+            return Err(ArchiveRequestError::UnsupportedAddress)
+        }
+        Err(err) => return Err(ArchiveRequestError::Connect(err.message)),
+    };
+
+    let request_id = serde_json::from_str::<serde_json::Value>(request_id_json)
+        .map_err(|_| ArchiveRequestError::InvalidResponse)?;
+
+    let mut to_send = json_rpc_request.as_bytes();
+    let mut received = Vec::new();
+    // Number of bytes at the start of `received` that have already been decoded.
+    let mut decoded_bytes = 0;
+
+    loop {
+        while !to_send.is_empty() {
+            let num_bytes = cmp::min(TPlat::writable_bytes(&mut stream), to_send.len());
+            if num_bytes == 0 {
+                break;
+            }
+            TPlat::send(&mut stream, &to_send[..num_bytes]);
+            to_send = &to_send[num_bytes..];
+        }
+
+        match TPlat::read_buffer(&mut stream) {
+            ReadBuffer::Open(buffer) => {
+                let num_bytes = buffer.len();
+                received.extend_from_slice(buffer);
+                TPlat::advance_read_cursor(&mut stream, num_bytes);
+            }
+            ReadBuffer::Closed | ReadBuffer::Reset => {
+                return Err(ArchiveRequestError::ConnectionClosed)
+            }
+        }
+
+        if received.len() > MAX_RESPONSE_SIZE {
+            return Err(ArchiveRequestError::ResponseTooLarge);
+        }
+
+        // The server is free to send other messages, such as notifications, before the response
+        // to the request. Messages are decoded one by one.
+        let mut messages = serde_json::Deserializer::from_slice(&received[decoded_bytes..])
+            .into_iter::<serde_json::Value>();
+        loop {
+            match messages.next() {
+                Some(Ok(mut message)) => {
+                    if message.get("id") != Some(&request_id) {
+                        continue;
+                    }
+
+                    if let Some(object) = message.as_object_mut() {
+                        object.insert("untrusted".into(), serde_json::Value::Bool(true));
+                    }
+
+                    return Ok(message.to_string());
+                }
+                Some(Err(err)) if err.is_eof() => break,
+                Some(Err(_)) => return Err(ArchiveRequestError::InvalidResponse),
+                None => break,
+            }
+        }
+        decoded_bytes += messages.byte_offset();
+
+        TPlat::update_stream(&mut stream).await;
+    }
+}
+
+/// Error potentially returned by [`archive_request`].
+#[derive(Debug, derive_more::Display)]
+enum ArchiveRequestError {
+    /// Failed to connect to the archive node.
+    #[display(fmt = "Failed to connect: {_0}")]
+    Connect(String),
+    /// The address of the archive node leads to a type of connection that isn't supported.
+    #[display(fmt = "Unsupported address")]
+    UnsupportedAddress,
+    /// The connection has been closed before the response has been received.
+    #[display(fmt = "Connection closed by the remote")]
+    ConnectionClosed,
+    /// The archive node has sent data that isn't valid JSON.
+    #[display(fmt = "Invalid response")]
+    InvalidResponse,
+    /// The archive node has sent more than [`MAX_RESPONSE_SIZE`] bytes.
+    #[display(fmt = "Response too large")]
+    ResponseTooLarge,
+    /// The archive node didn't respond within [`REQUEST_TIMEOUT`].
+    #[display(fmt = "Timeout")]
+    Timeout,
+}
@@ -0,0 +1,199 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! All JSON-RPC method handlers that relate to BEEFY.
+
+use super::{Background, Platform, SubscriptionMessage};
+
+use crate::beefy;
+
+use alloc::{borrow::ToOwned as _, string::ToString as _, sync::Arc, vec::Vec};
+use core::{
+    iter,
+    num::{NonZeroU32, NonZeroUsize},
+    time::Duration,
+};
+use futures::prelude::*;
+use smoldot::{
+    header,
+    json_rpc::{self, methods, requests_subscriptions},
+};
+
+impl<TPlat: Platform> Background<TPlat> {
+    /// Handles a call to [`methods::MethodCall::beefy_subscribeJustifications`].
+    pub(super) async fn beefy_subscribe_justifications(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        let (subscription_id, mut messages_rx, subscription_start) = match self
+            .requests_subscriptions
+            .start_subscription(request_id.1, 1)
+            .await
+        {
+            Ok(v) => v,
+            Err(requests_subscriptions::StartSubscriptionError::LimitReached) => {
+                self.requests_subscriptions
+                    .respond(
+                        request_id.1,
+                        json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Too many active subscriptions",
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        // The validator set is obtained by calling the runtime against the current finalized
+        // block.
+        let finality_proofs =
+            beefy::finality_proofs_stream(self.log_target.clone(), self.network_service.clone(), {
+                let me = self.clone();
+                move || {
+                    let me = me.clone();
+                    async move {
+                        let finalized_hash = header::hash_from_scale_encoded_header(
+                            &me.runtime_service
+                                .subscribe_all(
+                                    "beefy_subscribeJustifications",
+                                    16,
+                                    NonZeroUsize::new(24).unwrap(),
+                                )
+                                .await
+                                .finalized_block_scale_encoded_header,
+                        );
+
+                        me.runtime_call_no_api_check(
+                            &finalized_hash,
+                            "BeefyApi_validator_set",
+                            iter::empty::<Vec<u8>>(),
+                            3,
+                            Duration::from_secs(20),
+                            NonZeroU32::new(1).unwrap(),
+                        )
+                        .await
+                        .map_err(|err| err.to_string())
+                    }
+                }
+            });
+
+        subscription_start.start({
+            let me = self.clone();
+            let request_id = (request_id.0.to_owned(), request_id.1.clone());
+
+            async move {
+                me.requests_subscriptions
+                    .respond(
+                        &request_id.1,
+                        methods::Response::beefy_subscribeJustifications((&subscription_id).into())
+                            .to_json_response(&request_id.0),
+                    )
+                    .await;
+
+                futures::pin_mut!(finality_proofs);
+
+                loop {
+                    let next_message = messages_rx.next();
+                    futures::pin_mut!(next_message);
+                    match future::select(finality_proofs.next(), next_message).await {
+                        future::Either::Left((None, _)) => {
+                            // Stream created above is always unlimited.
+                            unreachable!()
+                        }
+                        future::Either::Left((Some(proof), _)) => {
+                            me.requests_subscriptions
+                                .set_queued_notification(
+                                    &request_id.1,
+                                    &subscription_id,
+                                    0,
+                                    methods::ServerToClient::beefy_justifications {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::HexString(
+                                            proof.scale_encoded_versioned_finality_proof,
+                                        ),
+                                    }
+                                    .to_json_call_object_parameters(None),
+                                )
+                                .await;
+                        }
+                        future::Either::Right((
+                            (
+                                SubscriptionMessage::StopIfBeefyJustifications { stop_request_id },
+                                confirmation_sender,
+                            ),
+                            _,
+                        )) => {
+                            me.requests_subscriptions
+                                .respond(
+                                    &stop_request_id.1,
+                                    methods::Response::beefy_unsubscribeJustifications(true)
+                                        .to_json_response(&stop_request_id.0),
+                                )
+                                .await;
+
+                            confirmation_sender.send();
+                            break;
+                        }
+                        future::Either::Right((_, _)) => {
+                            // Any other message.
+                            // Silently discard the confirmation sender.
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Handles a call to [`methods::MethodCall::beefy_unsubscribeJustifications`].
+    pub(super) async fn beefy_unsubscribe_justifications(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        subscription: &str,
+    ) {
+        // Stopping the subscription is done by sending a message to it.
+        // The task dedicated to this subscription will receive the message, send a response to
+        // the JSON-RPC client, then shut down.
+        let stop_message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                subscription,
+                SubscriptionMessage::StopIfBeefyJustifications {
+                    stop_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                },
+            )
+            .await;
+
+        // Send back a response manually if the task doesn't exist, or has discarded the message,
+        // which could happen for example because there was already a stop message earlier in its
+        // queue or because it was the wrong type of subscription.
+        if stop_message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::beefy_unsubscribeJustifications(false)
+                        .to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+}
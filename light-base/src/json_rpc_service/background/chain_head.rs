@@ -17,7 +17,7 @@
 
 //! All JSON-RPC method handlers that related to the `chainHead` API.
 
-use super::{Background, FollowSubscription, SubscriptionMessage};
+use super::{ApiVersion, Background, FollowSubscription, SubscriptionMessage};
 
 use crate::{platform::Platform, runtime_service, sync_service};
 
@@ -26,14 +26,16 @@ use alloc::{
     format,
     string::{String, ToString as _},
     sync::Arc,
+    vec,
     vec::Vec,
 };
 use core::{
     cmp, iter,
     num::{NonZeroU32, NonZeroUsize},
+    sync::atomic,
     time::Duration,
 };
-use futures::prelude::*;
+use futures::{channel::mpsc, prelude::*, stream};
 use hashbrown::HashMap;
 use smoldot::{
     chain::fork_tree,
@@ -166,9 +168,7 @@ impl<TPlat: Platform> Background<TPlat> {
                         match outcome {
                             either::Left(outcome) => break Some(outcome),
                             either::Right((
-                                SubscriptionMessage::StopIfChainHeadCall {
-                                    stop_request_id,
-                                },
+                                SubscriptionMessage::StopIfChainHeadCall { stop_request_id },
                                 confirmation_sender,
                             )) => {
                                 me.requests_subscriptions
@@ -194,179 +194,85 @@ impl<TPlat: Platform> Background<TPlat> {
 
                 let final_notif = match pre_runtime_call {
                     Some(Ok((runtime_call_lock, virtual_machine))) => {
-                        match runtime_host::run(runtime_host::Config {
+                        match run_chain_head_call::<TPlat>(
+                            runtime_call_lock,
                             virtual_machine,
-                            function_to_call: &function_to_call,
-                            parameter: iter::once(&call_parameters.0),
-                            main_trie_root_calculation_cache: None,
-                            offchain_storage_changes: Default::default(),
-                            storage_main_trie_changes: Default::default(),
-                            max_log_level: 0,
-                        }) {
-                            Err((error, prototype)) => {
-                                runtime_call_lock.unlock(prototype);
-                                methods::ServerToClient::chainHead_unstable_callEvent {
-                                    subscription: (&subscription_id).into(),
-                                    result: methods::ChainHeadCallEvent::Error {
-                                        error: error.to_string().into(),
-                                    },
-                                }
-                                .to_json_call_object_parameters(None)
-                            }
-                            Ok(mut runtime_call) => {
-                                loop {
-                                    match runtime_call {
-                                        runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
-                                            let output =
-                                                success.virtual_machine.value().as_ref().to_owned();
-                                            runtime_call_lock
-                                                .unlock(success.virtual_machine.into_prototype());
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Done {
-                                                        output: methods::HexString(output),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::Finished(Err(error)) => {
-                                            runtime_call_lock.unlock(error.prototype);
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Error {
-                                                        error: error.detail.to_string().into(),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::StorageGet(get) => {
-                                            // TODO: what if the remote lied to us?
-                                            let storage_value =
-                                                runtime_call_lock.storage_entry(get.key().as_ref());
-                                            let storage_value = match storage_value {
-                                                Ok(v) => v,
-                                                Err(error) => {
-                                                    runtime_call_lock.unlock(
-                                                        runtime_host::RuntimeHostVm::StorageGet(
-                                                            get,
-                                                        )
-                                                        .into_prototype(),
-                                                    );
-                                                    break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                            subscription: (&subscription_id).into(),
-                                                            result: methods::ChainHeadCallEvent::Inaccessible {
-                                                                error: error.to_string().into(),
-                                                            },
-                                                        }
-                                                        .to_json_call_object_parameters(None);
-                                                }
-                                            };
-                                            runtime_call = get.inject_value(
-                                                storage_value
-                                                    .map(|(val, vers)| (iter::once(val), vers)),
-                                            );
-                                        }
-                                        runtime_host::RuntimeHostVm::NextKey(nk) => {
-                                            // TODO: implement somehow
-                                            runtime_call_lock.unlock(
-                                                runtime_host::RuntimeHostVm::NextKey(nk)
-                                                    .into_prototype(),
-                                            );
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Inaccessible {
-                                                        error: "getting next key not implemented".into(),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::PrefixKeys(nk) => {
-                                            // TODO: implement somehow
-                                            runtime_call_lock.unlock(
-                                                runtime_host::RuntimeHostVm::PrefixKeys(nk)
-                                                    .into_prototype(),
-                                            );
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Inaccessible {
-                                                        error: "getting prefix keys not implemented".into(),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
-                                            runtime_call = sig.verify_and_resume();
-                                        }
-                                    }
-                                }
-                            }
+                            &function_to_call,
+                            &call_parameters.0,
+                        ) {
+                            Ok(output) => call_event(
+                                &subscription_id,
+                                methods::ChainHeadCallEvent::Done {
+                                    output: methods::HexString(output),
+                                },
+                            ),
+                            Err(ChainHeadCallError::Error(error)) => call_event(
+                                &subscription_id,
+                                methods::ChainHeadCallEvent::Error {
+                                    error: error.into(),
+                                },
+                            ),
+                            Err(ChainHeadCallError::Inaccessible(error)) => call_event(
+                                &subscription_id,
+                                methods::ChainHeadCallEvent::Inaccessible {
+                                    error: error.into(),
+                                },
+                            ),
                         }
                     }
                     Some(Err(runtime_service::RuntimeCallError::InvalidRuntime(error))) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
+                        call_event(
+                            &subscription_id,
+                            methods::ChainHeadCallEvent::Error {
                                 error: error.to_string().into(),
                             },
-                        }
-                        .to_json_call_object_parameters(None)
+                        )
                     }
                     Some(Err(runtime_service::RuntimeCallError::StorageRetrieval(error))) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
-                                error: error.to_string().into(),
-                            },
-                        }
-                        .to_json_call_object_parameters(None)
-                    }
-                    Some(Err(runtime_service::RuntimeCallError::MissingProofEntry)) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
-                                error: "incomplete call proof".into(),
-                            },
-                        }
-                        .to_json_call_object_parameters(None)
-                    }
-                    Some(Err(runtime_service::RuntimeCallError::CallProof(error))) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
+                        call_event(
+                            &subscription_id,
+                            methods::ChainHeadCallEvent::Error {
                                 error: error.to_string().into(),
                             },
-                        }
-                        .to_json_call_object_parameters(None)
+                        )
                     }
+                    Some(Err(runtime_service::RuntimeCallError::MissingProofEntry)) => call_event(
+                        &subscription_id,
+                        methods::ChainHeadCallEvent::Error {
+                            error: "incomplete call proof".into(),
+                        },
+                    ),
+                    Some(Err(runtime_service::RuntimeCallError::CallProof(error))) => call_event(
+                        &subscription_id,
+                        methods::ChainHeadCallEvent::Error {
+                            error: error.to_string().into(),
+                        },
+                    ),
                     Some(Err(runtime_service::RuntimeCallError::StorageQuery(error))) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
+                        call_event(
+                            &subscription_id,
+                            methods::ChainHeadCallEvent::Error {
                                 error: format!("failed to fetch call proof: {error}").into(),
                             },
-                        }
-                        .to_json_call_object_parameters(None)
-                    }
-                    None => methods::ServerToClient::chainHead_unstable_callEvent {
-                        subscription: (&subscription_id).into(),
-                        result: methods::ChainHeadCallEvent::Disjoint {},
+                        )
                     }
-                    .to_json_call_object_parameters(None),
+                    None => call_event(&subscription_id, methods::ChainHeadCallEvent::Disjoint {}),
                 };
 
                 me.requests_subscriptions
-                    .push_notification(&request_id.1,
-                        &subscription_id, final_notif)
+                    .push_notification(&request_id.1, &subscription_id, final_notif)
                     .await;
             }
         });
     }
 
-    /// Handles a call to [`methods::MethodCall::chainHead_unstable_follow`].
+    /// Handles a call to [`methods::MethodCall::chainHead_unstable_follow`] or to
+    /// [`methods::MethodCall::chainHead_v1_follow`].
     pub(super) async fn chain_head_follow(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
         runtime_updates: bool,
+        api_version: ApiVersion,
     ) {
         let (subscription_id, mut messages_rx, subscription_start) = match self
             .requests_subscriptions
@@ -393,10 +299,35 @@ impl<TPlat: Platform> Background<TPlat> {
         };
 
         let (mut subscribe_all, runtime_subscribe_all) = if runtime_updates {
-            let subscribe_all = self
-                .runtime_service
-                .subscribe_all("chainHead_follow", 32, NonZeroUsize::new(32).unwrap())
-                .await;
+            let Some(subscribe_all) = self.shared_follow.subscribe(&subscription_id).await else {
+                // More blocks are known than the subscription is allowed to keep pinned. The
+                // subscription stops immediately.
+                subscription_start.start({
+                    let me = self.clone();
+                    let request_id = (request_id.0.to_owned(), request_id.1.clone());
+
+                    async move {
+                        me.requests_subscriptions
+                            .respond(
+                                &request_id.1,
+                                methods::Response::chainHead_unstable_follow(
+                                    (&subscription_id).into(),
+                                )
+                                .to_json_response(&request_id.0),
+                            )
+                            .await;
+                        me.requests_subscriptions
+                            .push_notification(
+                                &request_id.1,
+                                &subscription_id,
+                                api_version
+                                    .follow_event(&subscription_id, methods::FollowEvent::Stop {}),
+                            )
+                            .await;
+                    }
+                });
+                return;
+            };
             let id = subscribe_all.new_blocks.id();
             (either::Left(subscribe_all), Some(id))
         } else {
@@ -428,16 +359,15 @@ impl<TPlat: Platform> Background<TPlat> {
                     );
 
                     initial_notifications.push({
-                        methods::ServerToClient::chainHead_unstable_followEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::FollowEvent::Initialized {
+                        api_version.follow_event(
+                            &subscription_id,
+                            methods::FollowEvent::Initialized {
                                 finalized_block_hash: methods::HashHexString(finalized_block_hash),
                                 finalized_block_runtime: Some(convert_runtime_spec(
                                     &subscribe_all.finalized_block_runtime,
                                 )),
                             },
-                        }
-                        .to_json_call_object_parameters(None)
+                        )
                     });
 
                     for block in &subscribe_all.non_finalized_blocks_ancestry_order {
@@ -460,32 +390,26 @@ impl<TPlat: Platform> Background<TPlat> {
                         };
                         non_finalized_blocks.insert(parent_node_index, hash);
 
-                        initial_notifications.push(
-                            methods::ServerToClient::chainHead_unstable_followEvent {
-                                subscription: (&subscription_id).into(),
-                                result: methods::FollowEvent::NewBlock {
-                                    block_hash: methods::HashHexString(hash),
-                                    new_runtime: if let Some(new_runtime) = &block.new_runtime {
-                                        Some(convert_runtime_spec(new_runtime))
-                                    } else {
-                                        None
-                                    },
-                                    parent_block_hash: methods::HashHexString(block.parent_hash),
+                        initial_notifications.push(api_version.follow_event(
+                            &subscription_id,
+                            methods::FollowEvent::NewBlock {
+                                block_hash: methods::HashHexString(hash),
+                                new_runtime: if let Some(new_runtime) = &block.new_runtime {
+                                    Some(convert_runtime_spec(new_runtime))
+                                } else {
+                                    None
                                 },
-                            }
-                            .to_json_call_object_parameters(None),
-                        );
+                                parent_block_hash: methods::HashHexString(block.parent_hash),
+                            },
+                        ));
 
                         if block.is_new_best {
-                            initial_notifications.push(
-                                methods::ServerToClient::chainHead_unstable_followEvent {
-                                    subscription: (&subscription_id).into(),
-                                    result: methods::FollowEvent::BestBlockChanged {
-                                        best_block_hash: methods::HashHexString(hash),
-                                    },
-                                }
-                                .to_json_call_object_parameters(None),
-                            );
+                            initial_notifications.push(api_version.follow_event(
+                                &subscription_id,
+                                methods::FollowEvent::BestBlockChanged {
+                                    best_block_hash: methods::HashHexString(hash),
+                                },
+                            ));
                         }
                     }
                 }
@@ -499,16 +423,13 @@ impl<TPlat: Platform> Background<TPlat> {
                         subscribe_all.finalized_block_scale_encoded_header.clone(),
                     );
 
-                    initial_notifications.push(
-                        methods::ServerToClient::chainHead_unstable_followEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::FollowEvent::Initialized {
-                                finalized_block_hash: methods::HashHexString(finalized_block_hash),
-                                finalized_block_runtime: None,
-                            },
-                        }
-                        .to_json_call_object_parameters(None),
-                    );
+                    initial_notifications.push(api_version.follow_event(
+                        &subscription_id,
+                        methods::FollowEvent::Initialized {
+                            finalized_block_hash: methods::HashHexString(finalized_block_hash),
+                            finalized_block_runtime: None,
+                        },
+                    ));
 
                     for block in &subscribe_all.non_finalized_blocks_ancestry_order {
                         let hash =
@@ -530,28 +451,22 @@ impl<TPlat: Platform> Background<TPlat> {
                         };
                         non_finalized_blocks.insert(parent_node_index, hash);
 
-                        initial_notifications.push(
-                            methods::ServerToClient::chainHead_unstable_followEvent {
-                                subscription: (&subscription_id).into(),
-                                result: methods::FollowEvent::NewBlock {
-                                    block_hash: methods::HashHexString(hash),
-                                    new_runtime: None,
-                                    parent_block_hash: methods::HashHexString(block.parent_hash),
-                                },
-                            }
-                            .to_json_call_object_parameters(None),
-                        );
+                        initial_notifications.push(api_version.follow_event(
+                            &subscription_id,
+                            methods::FollowEvent::NewBlock {
+                                block_hash: methods::HashHexString(hash),
+                                new_runtime: None,
+                                parent_block_hash: methods::HashHexString(block.parent_hash),
+                            },
+                        ));
 
                         if block.is_new_best {
-                            initial_notifications.push(
-                                methods::ServerToClient::chainHead_unstable_followEvent {
-                                    subscription: (&subscription_id).into(),
-                                    result: methods::FollowEvent::BestBlockChanged {
-                                        best_block_hash: methods::HashHexString(hash),
-                                    },
-                                }
-                                .to_json_call_object_parameters(None),
-                            );
+                            initial_notifications.push(api_version.follow_event(
+                                &subscription_id,
+                                methods::FollowEvent::BestBlockChanged {
+                                    best_block_hash: methods::HashHexString(hash),
+                                },
+                            ));
                         }
                     }
                 }
@@ -561,6 +476,9 @@ impl<TPlat: Platform> Background<TPlat> {
                 non_finalized_blocks,
                 pinned_blocks_headers,
                 runtime_subscribe_all,
+                api_version,
+                operations: HashMap::with_capacity_and_hasher(0, Default::default()),
+                next_operation_id: 0,
             };
 
             (subscription_id, initial_notifications, subscription_state)
@@ -586,26 +504,58 @@ impl<TPlat: Platform> Background<TPlat> {
                         .await;
                 }
 
+                // Operations started through a `chainHead_v1_follow` subscription are executed
+                // as part of the task of the subscription. Each operation yields its ID when it
+                // finishes.
+                let mut operations = stream::FuturesUnordered::<
+                    future::Abortable<future::BoxFuture<'static, String>>,
+                >::new();
+
                 loop {
-                    let next_block = match &mut subscribe_all {
-                        either::Left(subscribe_all) => {
-                            future::Either::Left(subscribe_all.new_blocks.next().map(either::Left))
+                    let wake_up = {
+                        let next_block = match &mut subscribe_all {
+                            either::Left(subscribe_all) => future::Either::Left(
+                                subscribe_all.new_blocks.next().map(either::Left),
+                            ),
+                            either::Right(subscribe_all) => future::Either::Right(
+                                subscribe_all.new_blocks.next().map(either::Right),
+                            ),
+                        };
+                        let next_message = messages_rx.next();
+                        let next_operation = async {
+                            if operations.is_empty() {
+                                future::pending().await
+                            } else {
+                                operations.next().await.unwrap()
+                            }
+                        };
+                        futures::pin_mut!(next_block, next_message, next_operation);
+
+                        match future::select(
+                            future::select(next_block, next_message),
+                            next_operation,
+                        )
+                        .await
+                        {
+                            future::Either::Left((future::Either::Left((block, _)), _)) => {
+                                FollowWakeUp::Block(block)
+                            }
+                            future::Either::Left((future::Either::Right((message, _)), _)) => {
+                                FollowWakeUp::Message(message)
+                            }
+                            future::Either::Right((operation, _)) => {
+                                FollowWakeUp::OperationFinished(operation)
+                            }
                         }
-                        either::Right(subscribe_all) => future::Either::Right(
-                            subscribe_all.new_blocks.next().map(either::Right),
-                        ),
                     };
-                    let next_message = messages_rx.next();
-                    futures::pin_mut!(next_message);
-                    futures::pin_mut!(next_block);
 
                     // TODO: doesn't enforce any maximum number of pinned blocks
-                    match future::select(next_block, next_message).await {
-                        future::Either::Left((either::Left(None) | either::Right(None), _)) => {
+                    match wake_up {
+                        FollowWakeUp::Block(either::Left(None) | either::Right(None)) => {
                             // TODO: clear queue of notifications?
                             break;
                         }
-                        future::Either::Left((
+                        FollowWakeUp::Block(
                             either::Left(Some(runtime_service::Notification::Finalized {
                                 best_block_hash,
                                 hash,
@@ -615,8 +565,7 @@ impl<TPlat: Platform> Background<TPlat> {
                                 best_block_hash,
                                 hash,
                             })),
-                            _,
-                        )) => {
+                        ) => {
                             let mut finalized_blocks_hashes = Vec::new();
                             let mut pruned_blocks_hashes = Vec::new();
 
@@ -643,15 +592,14 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .try_push_notification(
                                     &request_id.1,
                                     &subscription_id,
-                                    methods::ServerToClient::chainHead_unstable_followEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::FollowEvent::BestBlockChanged {
+                                    api_version.follow_event(
+                                        &subscription_id,
+                                        methods::FollowEvent::BestBlockChanged {
                                             best_block_hash: methods::HashHexString(
                                                 best_block_hash,
                                             ),
                                         },
-                                    }
-                                    .to_json_call_object_parameters(None),
+                                    ),
                                 )
                                 .await
                                 .is_err()
@@ -664,14 +612,13 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .try_push_notification(
                                     &request_id.1,
                                     &subscription_id,
-                                    methods::ServerToClient::chainHead_unstable_followEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::FollowEvent::Finalized {
+                                    api_version.follow_event(
+                                        &subscription_id,
+                                        methods::FollowEvent::Finalized {
                                             finalized_blocks_hashes,
                                             pruned_blocks_hashes,
                                         },
-                                    }
-                                    .to_json_call_object_parameters(None),
+                                    ),
                                 )
                                 .await
                                 .is_err()
@@ -679,34 +626,31 @@ impl<TPlat: Platform> Background<TPlat> {
                                 break;
                             }
                         }
-                        future::Either::Left((
+                        FollowWakeUp::Block(
                             either::Left(Some(runtime_service::Notification::BestBlockChanged {
                                 hash,
                             }))
                             | either::Right(Some(sync_service::Notification::BestBlockChanged {
                                 hash,
                             })),
-                            _,
-                        )) => {
+                        ) => {
                             let _ = me
                                 .requests_subscriptions
                                 .try_push_notification(
                                     &request_id.1,
                                     &subscription_id,
-                                    methods::ServerToClient::chainHead_unstable_followEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::FollowEvent::BestBlockChanged {
+                                    api_version.follow_event(
+                                        &subscription_id,
+                                        methods::FollowEvent::BestBlockChanged {
                                             best_block_hash: methods::HashHexString(hash),
                                         },
-                                    }
-                                    .to_json_call_object_parameters(None),
+                                    ),
                                 )
                                 .await;
                         }
-                        future::Either::Left((
-                            either::Left(Some(runtime_service::Notification::Block(block))),
-                            _,
-                        )) => {
+                        FollowWakeUp::Block(either::Left(Some(
+                            runtime_service::Notification::Block(block),
+                        ))) => {
                             let hash =
                                 header::hash_from_scale_encoded_header(&block.scale_encoded_header);
 
@@ -729,9 +673,9 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .try_push_notification(
                                     &request_id.1,
                                     &subscription_id,
-                                    methods::ServerToClient::chainHead_unstable_followEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::FollowEvent::NewBlock {
+                                    api_version.follow_event(
+                                        &subscription_id,
+                                        methods::FollowEvent::NewBlock {
                                             block_hash: methods::HashHexString(hash),
                                             parent_block_hash: methods::HashHexString(
                                                 block.parent_hash,
@@ -744,8 +688,7 @@ impl<TPlat: Platform> Background<TPlat> {
                                                 None
                                             },
                                         },
-                                    }
-                                    .to_json_call_object_parameters(None),
+                                    ),
                                 )
                                 .await
                                 .is_err()
@@ -759,13 +702,12 @@ impl<TPlat: Platform> Background<TPlat> {
                                     .try_push_notification(
                                         &request_id.1,
                                         &subscription_id,
-                                        methods::ServerToClient::chainHead_unstable_followEvent {
-                                            subscription: (&subscription_id).into(),
-                                            result: methods::FollowEvent::BestBlockChanged {
+                                        api_version.follow_event(
+                                            &subscription_id,
+                                            methods::FollowEvent::BestBlockChanged {
                                                 best_block_hash: methods::HashHexString(hash),
                                             },
-                                        }
-                                        .to_json_call_object_parameters(None),
+                                        ),
                                     )
                                     .await
                                     .is_err()
@@ -774,10 +716,9 @@ impl<TPlat: Platform> Background<TPlat> {
                                 }
                             }
                         }
-                        future::Either::Left((
-                            either::Right(Some(sync_service::Notification::Block(block))),
-                            _,
-                        )) => {
+                        FollowWakeUp::Block(either::Right(Some(
+                            sync_service::Notification::Block(block),
+                        ))) => {
                             let hash =
                                 header::hash_from_scale_encoded_header(&block.scale_encoded_header);
 
@@ -800,17 +741,16 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .try_push_notification(
                                     &request_id.1,
                                     &subscription_id,
-                                    methods::ServerToClient::chainHead_unstable_followEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::FollowEvent::NewBlock {
+                                    api_version.follow_event(
+                                        &subscription_id,
+                                        methods::FollowEvent::NewBlock {
                                             block_hash: methods::HashHexString(hash),
                                             parent_block_hash: methods::HashHexString(
                                                 block.parent_hash,
                                             ),
                                             new_runtime: None, // TODO:
                                         },
-                                    }
-                                    .to_json_call_object_parameters(None),
+                                    ),
                                 )
                                 .await
                                 .is_err()
@@ -824,13 +764,12 @@ impl<TPlat: Platform> Background<TPlat> {
                                     .try_push_notification(
                                         &request_id.1,
                                         &subscription_id,
-                                        methods::ServerToClient::chainHead_unstable_followEvent {
-                                            subscription: (&subscription_id).into(),
-                                            result: methods::FollowEvent::BestBlockChanged {
+                                        api_version.follow_event(
+                                            &subscription_id,
+                                            methods::FollowEvent::BestBlockChanged {
                                                 best_block_hash: methods::HashHexString(hash),
                                             },
-                                        }
-                                        .to_json_call_object_parameters(None),
+                                        ),
                                     )
                                     .await
                                     .is_err()
@@ -839,12 +778,9 @@ impl<TPlat: Platform> Background<TPlat> {
                                 }
                             }
                         }
-                        future::Either::Right((
-                            (
-                                SubscriptionMessage::StopIfChainHeadFollow { stop_request_id },
-                                confirmation_sender,
-                            ),
-                            _,
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::StopIfChainHeadFollow { stop_request_id },
+                            confirmation_sender,
                         )) => {
                             me.requests_subscriptions
                                 .respond(
@@ -857,16 +793,13 @@ impl<TPlat: Platform> Background<TPlat> {
                             confirmation_sender.send();
                             break;
                         }
-                        future::Either::Right((
-                            (
-                                SubscriptionMessage::ChainHeadBody {
-                                    hash,
-                                    get_request_id,
-                                    network_config,
-                                },
-                                confirmation_sender,
-                            ),
-                            _,
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadBody {
+                                hash,
+                                get_request_id,
+                                network_config,
+                            },
+                            confirmation_sender,
                         )) => {
                             // Determine whether the requested block hash is valid, and if yes its number.
                             let block_number = {
@@ -878,33 +811,74 @@ impl<TPlat: Platform> Background<TPlat> {
                                         me.sync_service.block_number_bytes(),
                                     )
                                     .unwrap(); // TODO: unwrap?
-                                    Some(decoded.number)
+                                    decoded.number
                                 } else {
+                                    me.requests_subscriptions
+                                        .respond(
+                                            &get_request_id.1,
+                                            json_rpc::parse::build_error_response(
+                                                &get_request_id.0,
+                                                json_rpc::parse::ErrorResponse::InvalidParams,
+                                                None,
+                                            ),
+                                        )
+                                        .await;
+                                    confirmation_sender.send();
                                     continue;
                                 }
                             };
 
-                            me.start_chain_head_body(
-                                (&get_request_id.0, &get_request_id.1),
-                                hash,
-                                network_config,
-                                block_number,
-                            )
-                            .await;
+                            match subscription_state.api_version {
+                                ApiVersion::Unstable => {
+                                    me.start_chain_head_body(
+                                        (&get_request_id.0, &get_request_id.1),
+                                        hash,
+                                        network_config,
+                                        Some(block_number),
+                                    )
+                                    .await;
+                                }
+                                ApiVersion::V1 => {
+                                    let operation_id = subscription_state.start_operation(
+                                        &mut operations,
+                                        None,
+                                        |operation_id| {
+                                            me.clone()
+                                                .chain_head_v1_body_operation(
+                                                    request_id.1.clone(),
+                                                    subscription_id.clone(),
+                                                    operation_id,
+                                                    hash.0,
+                                                    block_number,
+                                                    network_config,
+                                                )
+                                                .boxed()
+                                        },
+                                    );
+
+                                    me.requests_subscriptions
+                                        .respond(
+                                            &get_request_id.1,
+                                            methods::Response::chainHead_v1_body(body_call_return(
+                                                operation_id,
+                                            ))
+                                            .to_json_response(&get_request_id.0),
+                                        )
+                                        .await;
+                                }
+                            }
+
                             confirmation_sender.send();
                         }
-                        future::Either::Right((
-                            (
-                                SubscriptionMessage::ChainHeadStorage {
-                                    hash,
-                                    get_request_id,
-                                    network_config,
-                                    key,
-                                    child_key,
-                                },
-                                confirmation_sender,
-                            ),
-                            _,
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadStorage {
+                                hash,
+                                get_request_id,
+                                network_config,
+                                key,
+                                child_key,
+                            },
+                            confirmation_sender,
                         )) => {
                             // Obtain the header of the requested block.
                             // Contains `None` if the subscription is disjoint.
@@ -929,26 +903,94 @@ impl<TPlat: Platform> Background<TPlat> {
                             .await;
                             confirmation_sender.send();
                         }
-                        future::Either::Right((
-                            (
-                                SubscriptionMessage::ChainHeadCall {
-                                    hash,
-                                    get_request_id,
-                                    network_config,
-                                    function_to_call,
-                                    call_parameters,
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadStorageItems {
+                                hash,
+                                get_request_id,
+                                items,
+                                child_trie,
+                            },
+                            confirmation_sender,
+                        )) => {
+                            let Some(block_scale_encoded_header) = subscription_state
+                                .pinned_blocks_headers
+                                .get(&hash.0)
+                                .cloned()
+                            else {
+                                me.requests_subscriptions
+                                    .respond(
+                                        &get_request_id.1,
+                                        json_rpc::parse::build_error_response(
+                                            &get_request_id.0,
+                                            json_rpc::parse::ErrorResponse::InvalidParams,
+                                            None,
+                                        ),
+                                    )
+                                    .await;
+                                confirmation_sender.send();
+                                continue;
+                            };
+
+                            let (continue_sender, continue_receiver) = operation_continue_channel();
+                            let operation_id = subscription_state.start_operation(
+                                &mut operations,
+                                Some(continue_sender),
+                                |operation_id| {
+                                    me.clone()
+                                        .chain_head_v1_storage_operation(
+                                            request_id.1.clone(),
+                                            subscription_id.clone(),
+                                            operation_id,
+                                            hash.0,
+                                            block_scale_encoded_header,
+                                            items,
+                                            child_trie.map(|child_trie| child_trie.0),
+                                            continue_receiver,
+                                        )
+                                        .boxed()
                                 },
-                                confirmation_sender,
-                            ),
-                            _,
+                            );
+
+                            me.requests_subscriptions
+                                .respond(
+                                    &get_request_id.1,
+                                    methods::Response::chainHead_v1_storage(match operation_id {
+                                        Some(operation_id) => {
+                                            methods::ChainHeadStorageReturn::Started {
+                                                operation_id: operation_id.into(),
+                                                discarded_items: 0,
+                                            }
+                                        }
+                                        None => methods::ChainHeadStorageReturn::LimitReached {},
+                                    })
+                                    .to_json_response(&get_request_id.0),
+                                )
+                                .await;
+                            confirmation_sender.send();
+                        }
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadCall {
+                                hash,
+                                get_request_id,
+                                network_config,
+                                function_to_call,
+                                call_parameters,
+                            },
+                            confirmation_sender,
                         )) => {
-                            // Determine whether the requested block hash is valid and start the call.
-                            let pre_runtime_call = {
-                                let runtime_service_subscribe_all =
-                                    match subscription_state.runtime_subscribe_all {
-                                        Some(sa) => sa,
-                                        None => {
-                                            me.requests_subscriptions
+                            // Calls can only be performed if the subscription has requested
+                            // runtime updates, and on pinned blocks.
+                            let runtime_service_subscribe_all =
+                                match subscription_state.runtime_subscribe_all {
+                                    Some(sa)
+                                        if subscription_state
+                                            .pinned_blocks_headers
+                                            .contains_key(&hash.0) =>
+                                    {
+                                        sa
+                                    }
+                                    _ => {
+                                        me.requests_subscriptions
                                             .respond(
                                                 &get_request_id.1,
                                                 json_rpc::parse::build_error_response(
@@ -958,56 +1000,67 @@ impl<TPlat: Platform> Background<TPlat> {
                                                 ),
                                             )
                                             .await;
-                                            return;
-                                        }
-                                    };
+                                        confirmation_sender.send();
+                                        continue;
+                                    }
+                                };
+
+                            let pre_runtime_call = me
+                                .shared_follow
+                                .pinned_block_runtime_lock(runtime_service_subscribe_all, &hash.0)
+                                .await;
+
+                            match subscription_state.api_version {
+                                ApiVersion::Unstable => {
+                                    me.start_chain_head_call(
+                                        (&get_request_id.0, &get_request_id.1),
+                                        &function_to_call,
+                                        call_parameters,
+                                        pre_runtime_call,
+                                        network_config,
+                                    )
+                                    .await;
+                                }
+                                ApiVersion::V1 => {
+                                    let operation_id = subscription_state.start_operation(
+                                        &mut operations,
+                                        None,
+                                        |operation_id| {
+                                            me.clone()
+                                                .chain_head_v1_call_operation(
+                                                    request_id.1.clone(),
+                                                    subscription_id.clone(),
+                                                    operation_id,
+                                                    function_to_call,
+                                                    call_parameters,
+                                                    pre_runtime_call,
+                                                    network_config,
+                                                )
+                                                .boxed()
+                                        },
+                                    );
 
-                                if !subscription_state
-                                    .pinned_blocks_headers
-                                    .contains_key(&hash.0)
-                                {
                                     me.requests_subscriptions
                                         .respond(
                                             &get_request_id.1,
-                                            json_rpc::parse::build_error_response(
-                                                &get_request_id.0,
-                                                json_rpc::parse::ErrorResponse::InvalidParams,
-                                                None,
-                                            ),
+                                            methods::Response::chainHead_v1_call(body_call_return(
+                                                operation_id,
+                                            ))
+                                            .to_json_response(&get_request_id.0),
                                         )
                                         .await;
-                                    return;
                                 }
+                            }
 
-                                me.runtime_service
-                                    .pinned_block_runtime_lock(
-                                        runtime_service_subscribe_all,
-                                        &hash.0,
-                                    )
-                                    .await
-                                    .ok()
-                            };
-
-                            me.start_chain_head_call(
-                                (&get_request_id.0, &get_request_id.1),
-                                &function_to_call,
-                                call_parameters,
-                                pre_runtime_call,
-                                network_config,
-                            )
-                            .await;
                             confirmation_sender.send();
                         }
-                        future::Either::Right((
-                            (
-                                SubscriptionMessage::ChainHeadHeader {
-                                    hash,
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadHeader {
+                                hash,
 
-                                    get_request_id,
-                                },
-                                confirmation_sender,
-                            ),
-                            _,
+                                get_request_id,
+                            },
+                            confirmation_sender,
                         )) => {
                             let response = {
                                 subscription_state
@@ -1027,50 +1080,122 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .await;
                             confirmation_sender.send();
                         }
-                        future::Either::Right((
-                            (
-                                SubscriptionMessage::ChainHeadFollowUnpin {
-                                    hash,
-                                    unpin_request_id,
-                                },
-                                confirmation_sender,
-                            ),
-                            _,
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadFollowUnpin {
+                                hashes,
+                                unpin_request_id,
+                            },
+                            confirmation_sender,
                         )) => {
+                            // Either all the blocks are unpinned, or none of them is. The list
+                            // is invalid if one of the blocks isn't pinned or if it contains
+                            // duplicates.
                             let valid = {
-                                if subscription_state
-                                    .pinned_blocks_headers
-                                    .remove(&hash.0)
-                                    .is_some()
-                                {
+                                let mut deduplicated =
+                                    hashes.iter().map(|hash| hash.0).collect::<Vec<_>>();
+                                deduplicated.sort_unstable();
+                                deduplicated.dedup();
+                                deduplicated.len() == hashes.len()
+                                    && hashes.iter().all(|hash| {
+                                        subscription_state
+                                            .pinned_blocks_headers
+                                            .contains_key(&hash.0)
+                                    })
+                            };
+
+                            let response = if valid {
+                                for hash in &hashes {
+                                    subscription_state.pinned_blocks_headers.remove(&hash.0);
                                     if let Some(runtime_subscribe_all) =
                                         subscription_state.runtime_subscribe_all
                                     {
-                                        me.runtime_service
+                                        me.shared_follow
                                             .unpin_block(runtime_subscribe_all, &hash.0)
                                             .await;
                                     }
-                                    true
-                                } else {
-                                    false
                                 }
+
+                                methods::Response::chainHead_unstable_unpin(())
+                                    .to_json_response(&unpin_request_id.0)
+                            } else {
+                                json_rpc::parse::build_error_response(
+                                    &unpin_request_id.0,
+                                    json_rpc::parse::ErrorResponse::InvalidParams,
+                                    None,
+                                )
                             };
 
-                            if valid {
-                                me.requests_subscriptions
-                                    .respond(
-                                        &unpin_request_id.1,
-                                        methods::Response::chainHead_unstable_unpin(())
-                                            .to_json_response(&unpin_request_id.0),
-                                    )
-                                    .await;
-                                confirmation_sender.send();
+                            me.requests_subscriptions
+                                .respond(&unpin_request_id.1, response)
+                                .await;
+                            confirmation_sender.send();
+                        }
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadContinue {
+                                operation_id,
+                                continue_request_id,
+                            },
+                            confirmation_sender,
+                        )) => {
+                            let resumed = subscription_state
+                                .operations
+                                .get(&operation_id)
+                                .and_then(|operation| operation.continue_sender.as_ref())
+                                .map_or(false, |continue_sender| continue_sender.resume());
+
+                            let response = if resumed {
+                                methods::Response::chainHead_v1_continue(())
+                                    .to_json_response(&continue_request_id.0)
+                            } else {
+                                // The operation doesn't exist or isn't waiting for
+                                // `chainHead_v1_continue` to be called.
+                                json_rpc::parse::build_error_response(
+                                    &continue_request_id.0,
+                                    json_rpc::parse::ErrorResponse::InvalidParams,
+                                    None,
+                                )
+                            };
+
+                            me.requests_subscriptions
+                                .respond(&continue_request_id.1, response)
+                                .await;
+                            confirmation_sender.send();
+                        }
+                        FollowWakeUp::Message((
+                            SubscriptionMessage::ChainHeadStopOperation {
+                                operation_id,
+                                stop_request_id,
+                            },
+                            confirmation_sender,
+                        )) => {
+                            // Stopping an operation that doesn't exist or that has already
+                            // finished silently succeeds.
+                            if let Some(operation) =
+                                subscription_state.operations.remove(&operation_id)
+                            {
+                                operation.abort_handle.abort();
                             }
+
+                            me.requests_subscriptions
+                                .respond(
+                                    &stop_request_id.1,
+                                    methods::Response::chainHead_v1_stopOperation(())
+                                        .to_json_response(&stop_request_id.0),
+                                )
+                                .await;
+                            confirmation_sender.send();
                         }
-                        future::Either::Right((_, _)) => {
+                        FollowWakeUp::Message(_) => {
                             // Any other message.
                             // Silently discard the confirmation sender.
                         }
+                        FollowWakeUp::OperationFinished(Ok(operation_id)) => {
+                            subscription_state.operations.remove(&operation_id);
+                        }
+                        FollowWakeUp::OperationFinished(Err(future::Aborted)) => {
+                            // The operation has been stopped with `chainHead_v1_stopOperation`
+                            // and is already removed from the list.
+                        }
                     }
                 }
 
@@ -1078,11 +1203,7 @@ impl<TPlat: Platform> Background<TPlat> {
                     .push_notification(
                         &request_id.1,
                         &subscription_id,
-                        methods::ServerToClient::chainHead_unstable_followEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::FollowEvent::Stop {},
-                        }
-                        .to_json_call_object_parameters(None),
+                        api_version.follow_event(&subscription_id, methods::FollowEvent::Stop {}),
                     )
                     .await;
             }
@@ -1244,25 +1365,19 @@ impl<TPlat: Platform> Background<TPlat> {
                                     debug_assert_eq!(values.len(), 1);
                                     let value = values.into_iter().next().unwrap();
                                     let output = value.map(|v| methods::HexString(v).to_string());
-                                    break methods::ServerToClient::chainHead_unstable_storageEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::ChainHeadStorageEvent::Done {
-                                            value: output,
-                                        },
-                                    }
-                                    .to_json_call_object_parameters(None)
+                                    break storage_event(
+                                        &subscription_id,
+                                        methods::ChainHeadStorageEvent::Done { value: output },
+                                    );
                                 }
                                 either::Left(Err(_)) => {
-                                    break methods::ServerToClient::chainHead_unstable_storageEvent {
-                                        subscription: (&subscription_id).into(),
-                                        result: methods::ChainHeadStorageEvent::Inaccessible {},
-                                    }
-                                    .to_json_call_object_parameters(None)
+                                    break storage_event(
+                                        &subscription_id,
+                                        methods::ChainHeadStorageEvent::Inaccessible {},
+                                    )
                                 }
                                 either::Right((
-                                    SubscriptionMessage::StopIfChainHeadBody {
-                                        stop_request_id,
-                                    },
+                                    SubscriptionMessage::StopIfChainHeadBody { stop_request_id },
                                     confirmation_sender,
                                 )) => {
                                     me.requests_subscriptions
@@ -1283,18 +1398,16 @@ impl<TPlat: Platform> Background<TPlat> {
                             }
                         }
                     }
-                    Some(Err(err)) => methods::ServerToClient::chainHead_unstable_storageEvent {
-                        subscription: (&subscription_id).into(),
-                        result: methods::ChainHeadStorageEvent::Error {
+                    Some(Err(err)) => storage_event(
+                        &subscription_id,
+                        methods::ChainHeadStorageEvent::Error {
                             error: err.to_string().into(),
                         },
-                    }
-                    .to_json_call_object_parameters(None),
-                    None => methods::ServerToClient::chainHead_unstable_storageEvent {
-                        subscription: (&subscription_id).into(),
-                        result: methods::ChainHeadStorageEvent::Disjoint {},
-                    }
-                    .to_json_call_object_parameters(None),
+                    ),
+                    None => storage_event(
+                        &subscription_id,
+                        methods::ChainHeadStorageEvent::Disjoint {},
+                    ),
                 };
 
                 me.requests_subscriptions
@@ -1304,6 +1417,648 @@ impl<TPlat: Platform> Background<TPlat> {
         });
     }
 
+    /// Handles a call to [`methods::MethodCall::chainHead_v1_storage`].
+    pub(super) async fn chain_head_v1_storage(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        follow_subscription: &str,
+        hash: methods::HashHexString,
+        items: Vec<methods::ChainHeadStorageRequestItem>,
+        child_trie: Option<methods::HexString>,
+    ) {
+        // This is implemented by sending a message to the notifications task.
+        // The task dedicated to this subscription will receive the message and send a response to
+        // the JSON-RPC client.
+        let message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                follow_subscription,
+                SubscriptionMessage::ChainHeadStorageItems {
+                    get_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                    hash,
+                    items,
+                    child_trie,
+                },
+            )
+            .await;
+
+        // The task always sends back a response if it has received the message. If the
+        // subscription doesn't exist, the specification requires `limitReached` to be returned.
+        if message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::chainHead_v1_storage(
+                        methods::ChainHeadStorageReturn::LimitReached {},
+                    )
+                    .to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+
+    /// Performs a `chainHead_v1_storage` operation, reporting its progress through the
+    /// `chainHead_v1_followEvent` notifications of the given subscription.
+    async fn chain_head_v1_storage_operation(
+        self: Arc<Self>,
+        request_id: requests_subscriptions::RequestId,
+        subscription_id: String,
+        operation_id: String,
+        hash: [u8; 32],
+        block_scale_encoded_header: Vec<u8>,
+        items: Vec<methods::ChainHeadStorageRequestItem>,
+        child_trie: Option<Vec<u8>>,
+        mut continue_receiver: OperationContinueReceiver,
+    ) {
+        // Maximum number of items queried and reported at once. After each page, the JSON-RPC
+        // client must call `chainHead_v1_continue` in order for the next page to be queried.
+        const PAGE_SIZE: usize = 16;
+
+        let decoded_header = match header::decode(
+            &block_scale_encoded_header,
+            self.sync_service.block_number_bytes(),
+        ) {
+            Ok(h) => h,
+            Err(err) => {
+                self.requests_subscriptions
+                    .push_notification(
+                        &request_id,
+                        &subscription_id,
+                        follow_event_v1(
+                            &subscription_id,
+                            methods::FollowEventV1::OperationError {
+                                operation_id: (&operation_id).into(),
+                                error: err.to_string().into(),
+                            },
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        // Root of the trie to query. If a child trie is queried, its root is stored in the
+        // main trie.
+        let trie_root = match &child_trie {
+            None => *decoded_header.state_root,
+            Some(child_trie) => {
+                let outcome = self
+                    .sync_service
+                    .clone()
+                    .storage_query(
+                        decoded_header.number,
+                        &hash,
+                        decoded_header.state_root,
+                        iter::once([&b":child_storage:default:"[..], &child_trie[..]].concat()),
+                        3,
+                        Duration::from_secs(8),
+                        NonZeroU32::new(1).unwrap(),
+                    )
+                    .await;
+
+                let event = match outcome.map(|mut values| values.pop().unwrap()) {
+                    Ok(Some(root)) => match <[u8; 32]>::try_from(&root[..]) {
+                        Ok(root) => Ok(root),
+                        Err(_) => Err(methods::FollowEventV1::OperationError {
+                            operation_id: (&operation_id).into(),
+                            error: "Invalid child trie root".into(),
+                        }),
+                    },
+                    // The child trie doesn't exist, meaning that none of the items has a value.
+                    Ok(None) => Err(methods::FollowEventV1::OperationStorageDone {
+                        operation_id: (&operation_id).into(),
+                    }),
+                    Err(_) => Err(methods::FollowEventV1::OperationInaccessible {
+                        operation_id: (&operation_id).into(),
+                    }),
+                };
+
+                match event {
+                    Ok(root) => root,
+                    Err(event) => {
+                        self.requests_subscriptions
+                            .push_notification(
+                                &request_id,
+                                &subscription_id,
+                                follow_event_v1(&subscription_id, event),
+                            )
+                            .await;
+                        return;
+                    }
+                }
+            }
+        };
+
+        // Items of type `value` or `hash` are queried in groups of `PAGE_SIZE` items.
+        // Items of type `descendantsValues` or `descendantsHashes` are enumerated `PAGE_SIZE` keys
+        // at a time, and each page of keys is reported before the next one is downloaded, so that
+        // the full list of descendants is never held in memory.
+        let mut items = items.into_iter().peekable();
+        // Descendants item currently being enumerated, alongside with the last key that has been
+        // reported for it.
+        let mut descendants_in_progress =
+            None::<(methods::ChainHeadStorageRequestItem, Option<Vec<u8>>)>;
+        // True if items have been reported since the last `chainHead_v1_continue`.
+        let mut wait_for_continue = false;
+
+        loop {
+            if descendants_in_progress.is_none() {
+                match items.peek() {
+                    None => break,
+                    Some(item)
+                        if matches!(
+                            item.ty,
+                            methods::ChainHeadStorageType::DescendantsValues
+                                | methods::ChainHeadStorageType::DescendantsHashes
+                        ) =>
+                    {
+                        descendants_in_progress = Some((items.next().unwrap(), None));
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            // Wait for `chainHead_v1_continue` before querying anything else after a page of
+            // items has been reported.
+            if wait_for_continue {
+                wait_for_continue = false;
+                continue_receiver.start_waiting();
+                self.requests_subscriptions
+                    .push_notification(
+                        &request_id,
+                        &subscription_id,
+                        follow_event_v1(
+                            &subscription_id,
+                            methods::FollowEventV1::OperationWaitingForContinue {
+                                operation_id: (&operation_id).into(),
+                            },
+                        ),
+                    )
+                    .await;
+                continue_receiver.wait().await;
+            }
+
+            // List of keys whose value must be queried, alongside with the type of the item
+            // they originate from.
+            let page = if let Some((item, start_key)) = &mut descendants_in_progress {
+                let keys = match &child_trie {
+                    None => {
+                        self.sync_service
+                            .clone()
+                            .storage_prefix_keys_paged_query(
+                                decoded_header.number,
+                                &hash,
+                                &item.key.0,
+                                start_key.as_deref(),
+                                NonZeroUsize::new(PAGE_SIZE).unwrap(),
+                                &trie_root,
+                                3,
+                                Duration::from_secs(8),
+                                NonZeroU32::new(1).unwrap(),
+                            )
+                            .await
+                    }
+                    Some(child_trie) => {
+                        self.sync_service
+                            .clone()
+                            .child_storage_prefix_keys_paged_query(
+                                decoded_header.number,
+                                &hash,
+                                child_trie,
+                                &item.key.0,
+                                start_key.as_deref(),
+                                NonZeroUsize::new(PAGE_SIZE).unwrap(),
+                                &trie_root,
+                                3,
+                                Duration::from_secs(8),
+                                NonZeroU32::new(1).unwrap(),
+                            )
+                            .await
+                    }
+                };
+
+                let Ok(keys) = keys else {
+                    self.requests_subscriptions
+                        .push_notification(
+                            &request_id,
+                            &subscription_id,
+                            follow_event_v1(
+                                &subscription_id,
+                                methods::FollowEventV1::OperationInaccessible {
+                                    operation_id: (&operation_id).into(),
+                                },
+                            ),
+                        )
+                        .await;
+                    return;
+                };
+
+                let ty = item.ty;
+                if keys.len() < PAGE_SIZE {
+                    descendants_in_progress = None;
+                } else {
+                    *start_key = keys.last().cloned();
+                }
+                keys.into_iter().map(|key| (key, ty)).collect::<Vec<_>>()
+            } else {
+                let mut page = Vec::with_capacity(PAGE_SIZE);
+                while page.len() < PAGE_SIZE {
+                    match items.peek() {
+                        Some(item)
+                            if !matches!(
+                                item.ty,
+                                methods::ChainHeadStorageType::DescendantsValues
+                                    | methods::ChainHeadStorageType::DescendantsHashes
+                            ) =>
+                        {
+                            let item = items.next().unwrap();
+                            page.push((item.key.0, item.ty));
+                        }
+                        _ => break,
+                    }
+                }
+                page
+            };
+
+            if page.is_empty() {
+                continue;
+            }
+
+            // Items of type `closestDescendantMerkleValue` are queried separately from the
+            // items whose storage value is needed.
+            let (merkle_value_page, page): (Vec<_>, Vec<_>) =
+                page.into_iter().partition(|(_, ty)| {
+                    matches!(
+                        ty,
+                        methods::ChainHeadStorageType::ClosestDescendantMerkleValue
+                    )
+                });
+
+            let values = if page.is_empty() {
+                Ok(Vec::new())
+            } else {
+                match &child_trie {
+                    None => {
+                        self.sync_service
+                            .clone()
+                            .storage_query(
+                                decoded_header.number,
+                                &hash,
+                                &trie_root,
+                                page.iter().map(|(key, _)| key),
+                                3,
+                                Duration::from_secs(8),
+                                NonZeroU32::new(1).unwrap(),
+                            )
+                            .await
+                    }
+                    Some(child_trie) => {
+                        self.sync_service
+                            .clone()
+                            .child_storage_query(
+                                decoded_header.number,
+                                &hash,
+                                child_trie,
+                                &trie_root,
+                                page.iter().map(|(key, _)| key),
+                                3,
+                                Duration::from_secs(8),
+                                NonZeroU32::new(1).unwrap(),
+                            )
+                            .await
+                    }
+                }
+            };
+
+            let merkle_values = if merkle_value_page.is_empty() {
+                Ok(Vec::new())
+            } else {
+                self.sync_service
+                    .clone()
+                    .closest_descendant_merkle_value_query(
+                        decoded_header.number,
+                        &hash,
+                        child_trie.as_deref(),
+                        &trie_root,
+                        merkle_value_page.iter().map(|(key, _)| key),
+                        3,
+                        Duration::from_secs(8),
+                    )
+                    .await
+            };
+
+            let (Ok(values), Ok(merkle_values)) = (values, merkle_values) else {
+                self.requests_subscriptions
+                    .push_notification(
+                        &request_id,
+                        &subscription_id,
+                        follow_event_v1(
+                            &subscription_id,
+                            methods::FollowEventV1::OperationInaccessible {
+                                operation_id: (&operation_id).into(),
+                            },
+                        ),
+                    )
+                    .await;
+                return;
+            };
+
+            // Keys that don't have any storage value, or any descendant, are omitted from the
+            // results.
+            debug_assert_eq!(values.len(), page.len());
+            debug_assert_eq!(merkle_values.len(), merkle_value_page.len());
+            let items = page
+                .into_iter()
+                .zip(values)
+                .filter_map(|((key, ty), value)| {
+                    let value = value?;
+                    Some(match ty {
+                        methods::ChainHeadStorageType::Hash
+                        | methods::ChainHeadStorageType::DescendantsHashes => {
+                            let mut hash_context = blake2_rfc::blake2b::Blake2b::new(32);
+                            hash_context.update(&value);
+                            methods::ChainHeadStorageResponseItem {
+                                key: methods::HexString(key),
+                                value: None,
+                                hash: Some(methods::HexString(
+                                    hash_context.finalize().as_bytes().to_vec(),
+                                )),
+                                closest_descendant_merkle_value: None,
+                            }
+                        }
+                        _ => methods::ChainHeadStorageResponseItem {
+                            key: methods::HexString(key),
+                            value: Some(methods::HexString(value)),
+                            hash: None,
+                            closest_descendant_merkle_value: None,
+                        },
+                    })
+                })
+                .chain(merkle_value_page.into_iter().zip(merkle_values).filter_map(
+                    |((key, _), merkle_value)| {
+                        Some(methods::ChainHeadStorageResponseItem {
+                            key: methods::HexString(key),
+                            value: None,
+                            hash: None,
+                            closest_descendant_merkle_value: Some(methods::HexString(
+                                merkle_value?,
+                            )),
+                        })
+                    },
+                ))
+                .collect::<Vec<_>>();
+
+            if !items.is_empty() {
+                self.requests_subscriptions
+                    .push_notification(
+                        &request_id,
+                        &subscription_id,
+                        follow_event_v1(
+                            &subscription_id,
+                            methods::FollowEventV1::OperationStorageItems {
+                                operation_id: (&operation_id).into(),
+                                items,
+                            },
+                        ),
+                    )
+                    .await;
+                wait_for_continue = true;
+            }
+        }
+
+        self.requests_subscriptions
+            .push_notification(
+                &request_id,
+                &subscription_id,
+                follow_event_v1(
+                    &subscription_id,
+                    methods::FollowEventV1::OperationStorageDone {
+                        operation_id: (&operation_id).into(),
+                    },
+                ),
+            )
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::chainHead_v1_body`].
+    pub(super) async fn chain_head_v1_body(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        follow_subscription: &str,
+        hash: methods::HashHexString,
+    ) {
+        // This is implemented by sending a message to the notifications task.
+        // The task dedicated to this subscription will receive the message and send a response to
+        // the JSON-RPC client.
+        let message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                follow_subscription,
+                SubscriptionMessage::ChainHeadBody {
+                    get_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                    hash,
+                    network_config: methods::NetworkConfig {
+                        max_parallel: 1,
+                        timeout_ms: 4000,
+                        total_attempts: 3,
+                    },
+                },
+            )
+            .await;
+
+        // The task always sends back a response if it has received the message. If the
+        // subscription doesn't exist, the specification requires `limitReached` to be returned.
+        if message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::chainHead_v1_body(
+                        methods::ChainHeadBodyCallReturn::LimitReached {},
+                    )
+                    .to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+
+    /// Performs a `chainHead_v1_body` operation, reporting its outcome through the
+    /// `chainHead_v1_followEvent` notifications of the given subscription.
+    async fn chain_head_v1_body_operation(
+        self: Arc<Self>,
+        request_id: requests_subscriptions::RequestId,
+        subscription_id: String,
+        operation_id: String,
+        hash: [u8; 32],
+        block_number: u64,
+        network_config: methods::NetworkConfig,
+    ) {
+        // TODO: right now we query the header because the underlying function returns an error if we don't
+        let outcome = self
+            .sync_service
+            .clone()
+            .block_query(
+                block_number,
+                hash,
+                protocol::BlocksRequestFields {
+                    header: true,
+                    body: true,
+                    justifications: false,
+                },
+                cmp::min(10, network_config.total_attempts),
+                Duration::from_millis(u64::from(cmp::min(20000, network_config.timeout_ms))),
+                NonZeroU32::new(network_config.max_parallel.clamp(1, 5)).unwrap(),
+            )
+            .await;
+
+        let event = match outcome {
+            Ok(block_data) => methods::FollowEventV1::OperationBodyDone {
+                operation_id: (&operation_id).into(),
+                value: block_data
+                    .body
+                    .unwrap()
+                    .into_iter()
+                    .map(methods::HexString)
+                    .collect(),
+            },
+            Err(()) => methods::FollowEventV1::OperationInaccessible {
+                operation_id: (&operation_id).into(),
+            },
+        };
+
+        self.requests_subscriptions
+            .push_notification(
+                &request_id,
+                &subscription_id,
+                follow_event_v1(&subscription_id, event),
+            )
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::chainHead_v1_call`].
+    pub(super) async fn chain_head_v1_call(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        follow_subscription: &str,
+        hash: methods::HashHexString,
+        function_to_call: String,
+        call_parameters: methods::HexString,
+    ) {
+        // This is implemented by sending a message to the notifications task.
+        // The task dedicated to this subscription will receive the message and send a response to
+        // the JSON-RPC client.
+        let message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                follow_subscription,
+                SubscriptionMessage::ChainHeadCall {
+                    get_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                    hash,
+                    call_parameters,
+                    function_to_call,
+                    network_config: methods::NetworkConfig {
+                        max_parallel: 1,
+                        timeout_ms: 8000,
+                        total_attempts: 3,
+                    },
+                },
+            )
+            .await;
+
+        // The task always sends back a response if it has received the message. If the
+        // subscription doesn't exist, the specification requires `limitReached` to be returned.
+        if message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::chainHead_v1_call(
+                        methods::ChainHeadBodyCallReturn::LimitReached {},
+                    )
+                    .to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+
+    /// Performs a `chainHead_v1_call` operation, reporting its outcome through the
+    /// `chainHead_v1_followEvent` notifications of the given subscription.
+    async fn chain_head_v1_call_operation(
+        self: Arc<Self>,
+        request_id: requests_subscriptions::RequestId,
+        subscription_id: String,
+        operation_id: String,
+        function_to_call: String,
+        call_parameters: methods::HexString,
+        pre_runtime_call: Option<runtime_service::RuntimeLock<TPlat>>,
+        network_config: methods::NetworkConfig,
+    ) {
+        let outcome = match &pre_runtime_call {
+            Some(pre_runtime_call) => Some(
+                pre_runtime_call
+                    .start(
+                        &function_to_call,
+                        iter::once(&call_parameters.0),
+                        cmp::min(10, network_config.total_attempts),
+                        Duration::from_millis(u64::from(cmp::min(
+                            20000,
+                            network_config.timeout_ms,
+                        ))),
+                        NonZeroU32::new(network_config.max_parallel.clamp(1, 5)).unwrap(),
+                    )
+                    .await,
+            ),
+            None => None,
+        };
+
+        let event = match outcome {
+            Some(Ok((runtime_call_lock, virtual_machine))) => {
+                match run_chain_head_call::<TPlat>(
+                    runtime_call_lock,
+                    virtual_machine,
+                    &function_to_call,
+                    &call_parameters.0,
+                ) {
+                    Ok(output) => methods::FollowEventV1::OperationCallDone {
+                        operation_id: (&operation_id).into(),
+                        output: methods::HexString(output),
+                    },
+                    Err(ChainHeadCallError::Error(error)) => {
+                        methods::FollowEventV1::OperationError {
+                            operation_id: (&operation_id).into(),
+                            error: error.into(),
+                        }
+                    }
+                    Err(ChainHeadCallError::Inaccessible(_)) => {
+                        methods::FollowEventV1::OperationInaccessible {
+                            operation_id: (&operation_id).into(),
+                        }
+                    }
+                }
+            }
+            Some(Err(runtime_service::RuntimeCallError::InvalidRuntime(error))) => {
+                methods::FollowEventV1::OperationError {
+                    operation_id: (&operation_id).into(),
+                    error: error.to_string().into(),
+                }
+            }
+            // All the other errors are caused by the call proof not being obtainable or
+            // being invalid, which means that the call might succeed if it is retried.
+            Some(Err(_)) | None => methods::FollowEventV1::OperationInaccessible {
+                operation_id: (&operation_id).into(),
+            },
+        };
+
+        self.requests_subscriptions
+            .push_notification(
+                &request_id,
+                &subscription_id,
+                follow_event_v1(&subscription_id, event),
+            )
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::chainHead_unstable_body`].
     pub(super) async fn chain_head_unstable_body(
         self: &Arc<Self>,
@@ -1425,9 +2180,9 @@ impl<TPlat: Platform> Background<TPlat> {
 
                         match outcome {
                             either::Left(Ok(block_data)) => {
-                                break methods::ServerToClient::chainHead_unstable_bodyEvent {
-                                    subscription: (&subscription_id).into(),
-                                    result: methods::ChainHeadBodyEvent::Done {
+                                break body_event(
+                                    &subscription_id,
+                                    methods::ChainHeadBodyEvent::Done {
                                         value: block_data
                                             .body
                                             .unwrap()
@@ -1435,15 +2190,13 @@ impl<TPlat: Platform> Background<TPlat> {
                                             .map(methods::HexString)
                                             .collect(),
                                     },
-                                }
-                                .to_json_call_object_parameters(None)
+                                )
                             }
                             either::Left(Err(())) => {
-                                break methods::ServerToClient::chainHead_unstable_bodyEvent {
-                                    subscription: (&subscription_id).into(),
-                                    result: methods::ChainHeadBodyEvent::Inaccessible {},
-                                }
-                                .to_json_call_object_parameters(None)
+                                break body_event(
+                                    &subscription_id,
+                                    methods::ChainHeadBodyEvent::Inaccessible {},
+                                )
                             }
                             either::Right((
                                 SubscriptionMessage::StopIfChainHeadStorage { stop_request_id },
@@ -1467,11 +2220,7 @@ impl<TPlat: Platform> Background<TPlat> {
                         }
                     }
                 } else {
-                    methods::ServerToClient::chainHead_unstable_bodyEvent {
-                        subscription: (&subscription_id).into(),
-                        result: methods::ChainHeadBodyEvent::Disjoint {},
-                    }
-                    .to_json_call_object_parameters(None)
+                    body_event(&subscription_id, methods::ChainHeadBodyEvent::Disjoint {})
                 };
 
                 me.requests_subscriptions
@@ -1621,6 +2370,75 @@ impl<TPlat: Platform> Background<TPlat> {
         }
     }
 
+    /// Handles a call to [`methods::MethodCall::chainHead_v1_continue`].
+    pub(super) async fn chain_head_v1_continue(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        follow_subscription: &str,
+        operation_id: String,
+    ) {
+        // This is implemented by sending a message to the notifications task, which resumes the
+        // operation and sends back the response.
+        let message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                follow_subscription,
+                SubscriptionMessage::ChainHeadContinue {
+                    operation_id,
+                    continue_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                },
+            )
+            .await;
+
+        // Send back a response manually if the task doesn't exist.
+        if message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    json_rpc::parse::build_error_response(
+                        request_id.0,
+                        json_rpc::parse::ErrorResponse::InvalidParams,
+                        None,
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::chainHead_v1_stopOperation`].
+    pub(super) async fn chain_head_v1_stop_operation(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        follow_subscription: &str,
+        operation_id: String,
+    ) {
+        // Stopping the operation is done by sending a message to the notifications task, which
+        // aborts the operation and sends back the response.
+        let stop_message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                follow_subscription,
+                SubscriptionMessage::ChainHeadStopOperation {
+                    operation_id,
+                    stop_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                },
+            )
+            .await;
+
+        // Send back a response manually if the task doesn't exist.
+        if stop_message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::chainHead_v1_stopOperation(())
+                        .to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+
     /// Handles a call to [`methods::MethodCall::chainHead_unstable_unfollow`].
     pub(super) async fn chain_head_unstable_unfollow(
         self: &Arc<Self>,
@@ -1672,13 +2490,12 @@ impl<TPlat: Platform> Background<TPlat> {
                 follow_subscription,
                 SubscriptionMessage::ChainHeadFollowUnpin {
                     unpin_request_id: (request_id.0.to_owned(), request_id.1.clone()),
-                    hash,
+                    hashes: vec![hash],
                 },
             )
             .await;
 
-        // Send back a response manually if the task doesn't exist, or has discarded the message,
-        // which happens if the block to unpin isn't valid.
+        // Send back a response manually if the task doesn't exist.
         if message_received.is_err() {
             self.requests_subscriptions
                 .respond(
@@ -1693,16 +2510,61 @@ impl<TPlat: Platform> Background<TPlat> {
         }
     }
 
+    /// Handles a call to [`methods::MethodCall::chainHead_v1_unpin`].
+    pub(super) async fn chain_head_v1_unpin(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        follow_subscription: &str,
+        hashes: Vec<methods::HashHexString>,
+    ) {
+        // This is implemented by sending a message to the notifications task.
+        // The task dedicated to this subscription will receive the message and send a response to
+        // the JSON-RPC client.
+        let message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                follow_subscription,
+                SubscriptionMessage::ChainHeadFollowUnpin {
+                    unpin_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                    hashes,
+                },
+            )
+            .await;
+
+        // According to the specification, unpinning blocks of a subscription that doesn't exist
+        // is a no-op.
+        if message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::chainHead_v1_unpin(()).to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+
     /// Handles a call to [`methods::MethodCall::chainHead_unstable_finalizedDatabase`].
     pub(super) async fn chain_head_unstable_finalized_database(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
         max_size_bytes: Option<u64>,
     ) {
+        let recent_finalized_headers = self
+            .cache
+            .lock()
+            .await
+            .recent_finalized_headers
+            .iter()
+            .map(|(_, header)| header.clone())
+            .collect::<Vec<_>>();
+
         let response = crate::database::encode_database(
             &self.network_service.0,
             &self.sync_service,
             &self.genesis_block_hash,
+            recent_finalized_headers.iter(),
+            self.runtime_service.finalized_runtime_code_hash().await,
             usize::try_from(max_size_bytes.unwrap_or(u64::max_value()))
                 .unwrap_or(usize::max_value()),
         )
@@ -1744,3 +2606,271 @@ fn convert_runtime_spec(
         },
     }
 }
+
+/// Maximum number of `chainHead_v1` operations that can be in progress at the same time within
+/// a single follow subscription.
+const MAX_OPERATIONS: usize = 16;
+
+/// Event that wakes up the task of a follow subscription.
+enum FollowWakeUp<TBlock> {
+    /// Notification from the runtime or sync service.
+    Block(TBlock),
+    /// Message sent to the subscription by a JSON-RPC request handler.
+    Message(
+        (
+            SubscriptionMessage,
+            requests_subscriptions::ConfirmationSend,
+        ),
+    ),
+    /// A `chainHead_v1` operation has finished. Contains its identifier, or an error if it has
+    /// been aborted.
+    OperationFinished(Result<String, future::Aborted>),
+}
+
+impl FollowSubscription {
+    /// Starts a new `chainHead_v1` operation whose future is built by `operation` from the
+    /// identifier of the operation, and adds it to `operations`.
+    ///
+    /// Returns the identifier of the operation, or `None` if [`MAX_OPERATIONS`] operations are
+    /// already in progress, in which case `operation` isn't called.
+    fn start_operation(
+        &mut self,
+        operations: &mut stream::FuturesUnordered<
+            future::Abortable<future::BoxFuture<'static, String>>,
+        >,
+        continue_sender: Option<OperationContinueSender>,
+        operation: impl FnOnce(String) -> future::BoxFuture<'static, ()>,
+    ) -> Option<String> {
+        if self.operations.len() >= MAX_OPERATIONS {
+            return None;
+        }
+
+        let operation_id = self.next_operation_id.to_string();
+        self.next_operation_id += 1;
+
+        let (abort_handle, abort_registration) = future::AbortHandle::new_pair();
+        self.operations.insert(
+            operation_id.clone(),
+            super::ChainHeadOperation {
+                abort_handle,
+                continue_sender,
+            },
+        );
+
+        let future = operation(operation_id.clone());
+        operations.push(future::Abortable::new(
+            {
+                let operation_id = operation_id.clone();
+                async move {
+                    future.await;
+                    operation_id
+                }
+                .boxed()
+            },
+            abort_registration,
+        ));
+
+        Some(operation_id)
+    }
+}
+
+/// Sending side of the channel used to resume a `chainHead_v1_storage` operation that waits
+/// for `chainHead_v1_continue` to be called.
+pub(super) struct OperationContinueSender {
+    waiting: Arc<atomic::AtomicBool>,
+    tx: mpsc::UnboundedSender<()>,
+}
+
+impl OperationContinueSender {
+    /// Resumes the operation. Returns `false` if the operation isn't waiting to be resumed.
+    fn resume(&self) -> bool {
+        if !self.waiting.swap(false, atomic::Ordering::SeqCst) {
+            return false;
+        }
+
+        let _ = self.tx.unbounded_send(());
+        true
+    }
+}
+
+/// Receiving side of the channel created with [`operation_continue_channel`].
+struct OperationContinueReceiver {
+    waiting: Arc<atomic::AtomicBool>,
+    rx: mpsc::UnboundedReceiver<()>,
+}
+
+impl OperationContinueReceiver {
+    /// Marks the operation as waiting for `chainHead_v1_continue`. Must be called before the
+    /// `operationWaitingForContinue` event is generated.
+    fn start_waiting(&self) {
+        self.waiting.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Waits until [`OperationContinueSender::resume`] is called.
+    async fn wait(&mut self) {
+        // If the sender has been destroyed, the operation has been removed from the subscription
+        // and the future of the operation is about to be destroyed as well.
+        if self.rx.next().await.is_none() {
+            future::pending::<()>().await;
+        }
+    }
+}
+
+/// Creates a channel used to resume a `chainHead_v1_storage` operation.
+fn operation_continue_channel() -> (OperationContinueSender, OperationContinueReceiver) {
+    let waiting = Arc::new(atomic::AtomicBool::new(false));
+    let (tx, rx) = mpsc::unbounded();
+    (
+        OperationContinueSender {
+            waiting: waiting.clone(),
+            tx,
+        },
+        OperationContinueReceiver { waiting, rx },
+    )
+}
+
+/// Builds the response to `chainHead_v1_body` or `chainHead_v1_call` from the identifier of the
+/// operation that has been started, if any.
+fn body_call_return(operation_id: Option<String>) -> methods::ChainHeadBodyCallReturn<'static> {
+    match operation_id {
+        Some(operation_id) => methods::ChainHeadBodyCallReturn::Started {
+            operation_id: operation_id.into(),
+        },
+        None => methods::ChainHeadBodyCallReturn::LimitReached {},
+    }
+}
+
+/// Builds a `chainHead_v1_followEvent` notification.
+pub(super) fn follow_event_v1(subscription: &str, result: methods::FollowEventV1) -> String {
+    methods::ServerToClient::chainHead_v1_followEvent {
+        subscription: subscription.into(),
+        result,
+    }
+    .to_json_call_object_parameters(None)
+}
+
+/// Builds a `chainHead_unstable_bodyEvent` notification.
+fn body_event(subscription: &str, result: methods::ChainHeadBodyEvent) -> String {
+    methods::ServerToClient::chainHead_unstable_bodyEvent {
+        subscription: subscription.into(),
+        result,
+    }
+    .to_json_call_object_parameters(None)
+}
+
+/// Builds a `chainHead_unstable_callEvent` notification.
+fn call_event(subscription: &str, result: methods::ChainHeadCallEvent) -> String {
+    methods::ServerToClient::chainHead_unstable_callEvent {
+        subscription: subscription.into(),
+        result,
+    }
+    .to_json_call_object_parameters(None)
+}
+
+/// Builds a `chainHead_unstable_storageEvent` notification.
+fn storage_event(subscription: &str, result: methods::ChainHeadStorageEvent) -> String {
+    methods::ServerToClient::chainHead_unstable_storageEvent {
+        subscription: subscription.into(),
+        result,
+    }
+    .to_json_call_object_parameters(None)
+}
+
+/// Error returned by [`run_chain_head_call`].
+enum ChainHeadCallError {
+    /// The runtime call has failed.
+    Error(String),
+    /// The runtime call has accessed storage that couldn't be obtained.
+    Inaccessible(String),
+}
+
+/// Executes a runtime call whose call proof has been obtained, and returns its output.
+fn run_chain_head_call<TPlat: Platform>(
+    runtime_call_lock: runtime_service::RuntimeCallLock<'_>,
+    virtual_machine: executor::host::HostVmPrototype,
+    function_to_call: &str,
+    call_parameters: &[u8],
+) -> Result<Vec<u8>, ChainHeadCallError> {
+    let mut runtime_call = match runtime_host::run(runtime_host::Config {
+        virtual_machine,
+        function_to_call,
+        parameter: iter::once(call_parameters),
+        main_trie_root_calculation_cache: None,
+        offchain_storage_changes: Default::default(),
+        storage_main_trie_changes: Default::default(),
+        max_log_level: 0,
+        offchain_behavior: runtime_host::OffchainBehavior::EmulateInMemory {
+            timestamp_ms: u64::try_from(TPlat::now_from_unix_epoch().as_millis())
+                .unwrap_or(u64::max_value()),
+            random_seed: rand::random(),
+        },
+        tracing: false,
+    }) {
+        Ok(runtime_call) => runtime_call,
+        Err((error, prototype)) => {
+            runtime_call_lock.unlock(prototype);
+            return Err(ChainHeadCallError::Error(error.to_string()));
+        }
+    };
+
+    loop {
+        match runtime_call {
+            runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                let output = success.virtual_machine.value().as_ref().to_owned();
+                runtime_call_lock.unlock(success.virtual_machine.into_prototype());
+                return Ok(output);
+            }
+            runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                runtime_call_lock.unlock(error.prototype);
+                if matches!(
+                    error.detail,
+                    runtime_host::ErrorDetail::WasmVm {
+                        error: executor::host::Error::Trap(executor::vm::Trap::OutOfFuel),
+                        ..
+                    }
+                ) {
+                    return Err(ChainHeadCallError::Error(
+                        "Runtime call has exceeded its execution limit".to_owned(),
+                    ));
+                }
+                return Err(ChainHeadCallError::Error(error.detail.to_string()));
+            }
+            runtime_host::RuntimeHostVm::StorageGet(get) => {
+                // TODO: what if the remote lied to us?
+                let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
+                let storage_value = match storage_value {
+                    Ok(v) => v,
+                    Err(error) => {
+                        runtime_call_lock
+                            .unlock(runtime_host::RuntimeHostVm::StorageGet(get).into_prototype());
+                        return Err(ChainHeadCallError::Inaccessible(error.to_string()));
+                    }
+                };
+                runtime_call =
+                    get.inject_value(storage_value.map(|(val, vers)| (iter::once(val), vers)));
+            }
+            runtime_host::RuntimeHostVm::NextKey(nk) => {
+                // TODO: implement somehow
+                runtime_call_lock.unlock(runtime_host::RuntimeHostVm::NextKey(nk).into_prototype());
+                return Err(ChainHeadCallError::Inaccessible(
+                    "getting next key not implemented".to_owned(),
+                ));
+            }
+            runtime_host::RuntimeHostVm::PrefixKeys(nk) => {
+                // TODO: implement somehow
+                runtime_call_lock
+                    .unlock(runtime_host::RuntimeHostVm::PrefixKeys(nk).into_prototype());
+                return Err(ChainHeadCallError::Inaccessible(
+                    "getting prefix keys not implemented".to_owned(),
+                ));
+            }
+            runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                runtime_call = sig.verify_and_resume();
+            }
+            runtime_host::RuntimeHostVm::Offchain(_) => {
+                // Off-chain host functions are configured to be emulated.
+                unreachable!()
+            }
+        }
+    }
+}
@@ -20,7 +20,8 @@
 use super::{Background, Platform};
 
 use alloc::{borrow::Cow, format, string::ToString as _, sync::Arc, vec::Vec};
-use core::num::NonZeroUsize;
+use core::{cmp, num::NonZeroUsize};
+use hashbrown::HashMap;
 use smoldot::{
     header,
     json_rpc::{methods, requests_subscriptions},
@@ -237,6 +238,77 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::system_networkState`].
+    pub(super) async fn system_network_state(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        let (network_service, chain_index) = &self.network_service;
+
+        let mut known_addresses = network_service
+            .discovered_nodes(*chain_index)
+            .await
+            .map(|(peer_id, addresses)| {
+                (
+                    peer_id.to_string(),
+                    addresses.map(|a| a.to_string()).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<HashMap<_, _, fnv::FnvBuildHasher>>();
+
+        // Since light clients never listen for incoming connections, all the connections are
+        // necessarily outgoing.
+        let connected_peers = network_service
+            .peers_connections_addresses()
+            .await
+            .filter_map(|(peer_id, addresses)| {
+                let peer_id = peer_id.to_string();
+                let endpoint = addresses.first()?.to_string();
+                Some((
+                    peer_id.clone(),
+                    methods::NetworkStateConnectedPeer {
+                        endpoint: methods::NetworkStatePeerEndpoint::Dialing(endpoint),
+                        known_addresses: known_addresses.remove(&peer_id).unwrap_or_default(),
+                    },
+                ))
+            })
+            .collect();
+
+        let not_connected_peers = known_addresses
+            .into_iter()
+            .map(|(peer_id, known_addresses)| {
+                (
+                    peer_id,
+                    methods::NetworkStateNotConnectedPeer { known_addresses },
+                )
+            })
+            .collect();
+
+        let pending_dials = network_service
+            .pending_dials()
+            .await
+            .map(|(peer_id, address)| methods::NetworkStatePendingDial {
+                peer_id: peer_id.to_string(),
+                address: address.to_string(),
+            })
+            .collect();
+
+        self.requests_subscriptions
+            .respond(
+                request_id.1,
+                methods::Response::system_networkState(methods::NetworkState {
+                    peer_id: self.peer_id_base58.clone(),
+                    listened_addresses: Vec::new(),
+                    external_addresses: Vec::new(),
+                    connected_peers,
+                    not_connected_peers,
+                    pending_dials,
+                })
+                .to_json_response(request_id.0),
+            )
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::system_nodeRoles`].
     pub(super) async fn system_node_roles(
         self: &Arc<Self>,
@@ -296,6 +368,36 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::system_syncState`].
+    pub(super) async fn system_sync_state(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        // Answered by the sync service rather than the runtime service, as the runtime isn't
+        // known during the warp syncing.
+        let starting_block = self.sync_service.starting_block_number();
+        let (_, current_block) = self.sync_service.block_numbers().await;
+
+        // The local best block is included, as the peers might not have announced their latest
+        // blocks yet.
+        let highest_block = self
+            .sync_service
+            .syncing_peers()
+            .await
+            .map(|(_, _, best_number, _)| best_number)
+            .fold(current_block, cmp::max);
+
+        let response = methods::Response::system_syncState(methods::SystemSyncState {
+            starting_block,
+            current_block,
+            highest_block,
+        })
+        .to_json_response(request_id.0);
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::system_version`].
     pub(super) async fn system_version(
         self: &Arc<Self>,
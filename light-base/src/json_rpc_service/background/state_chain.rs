@@ -17,7 +17,7 @@
 
 //! All legacy JSON-RPC method handlers that relate to the chain or the storage.
 
-use super::{Background, Platform, SubscriptionMessage};
+use super::{Background, GetKeysPagedCacheEntry, Platform, StorageQueryError, SubscriptionMessage};
 
 use crate::runtime_service;
 
@@ -30,12 +30,13 @@ use alloc::{
     vec::Vec,
 };
 use core::{
-    iter,
+    cmp, iter,
     num::{NonZeroU32, NonZeroUsize},
     time::Duration,
 };
 use futures::{lock::MutexGuard, prelude::*};
 use smoldot::{
+    finality::beefy,
     header,
     informant::HashDisplay,
     json_rpc::{self, methods, requests_subscriptions},
@@ -44,6 +45,10 @@ use smoldot::{
 
 mod sub_utils;
 
+/// Maximum number of keys that can be passed to `state_queryStorage`, `state_queryStorageAt`,
+/// and `state_getReadProof`.
+const MAX_QUERY_STORAGE_KEYS: usize = 256;
+
 impl<TPlat: Platform> Background<TPlat> {
     /// Handles a call to [`methods::MethodCall::system_accountNextIndex`].
     pub(super) async fn account_next_index(
@@ -68,25 +73,46 @@ impl<TPlat: Platform> Background<TPlat> {
             )
             .await;
 
-        let response = match result {
-            Ok(result) => {
-                // TODO: we get a u32 when expecting a u64; figure out problem
-                // TODO: don't unwrap
-                let index =
-                    u32::from_le_bytes(<[u8; 4]>::try_from(&result.return_value[..]).unwrap());
-                methods::Response::system_accountNextIndex(u64::from(index))
-                    .to_json_response(request_id.0)
+        // The runtime returns the nonce of the account as found in the storage of the best
+        // block. Transactions of this account that are pending in the local pool must be taken
+        // into account, otherwise a wallet that submits multiple transactions in a row would
+        // re-use the same nonce.
+        let on_chain_nonce = result.map_err(|error| error.to_string()).and_then(|r| {
+            // The type of the nonce depends on the runtime. Substrate runtimes typically use a
+            // `u32`, but some use a `u64`.
+            match r.return_value.len() {
+                4 => Ok(u64::from(u32::from_le_bytes(
+                    <[u8; 4]>::try_from(&r.return_value[..]).unwrap(),
+                ))),
+                8 => Ok(u64::from_le_bytes(
+                    <[u8; 8]>::try_from(&r.return_value[..]).unwrap(),
+                )),
+                _ => Err("Failed to decode runtime output: invalid nonce length".to_owned()),
+            }
+        });
+
+        let response = match on_chain_nonce {
+            Ok(on_chain_nonce) => {
+                let pending_nonce = self
+                    .transactions_service
+                    .highest_pending_nonce(account.0.to_vec())
+                    .await;
+                let index = cmp::max(
+                    on_chain_nonce,
+                    pending_nonce.map_or(0, |n| n.saturating_add(1)),
+                );
+                methods::Response::system_accountNextIndex(index).to_json_response(request_id.0)
             }
             Err(error) => {
                 log::warn!(
                     target: &self.log_target,
-                    "Returning error from `state_getMetadata`. \
+                    "Returning error from `system_accountNextIndex`. \
                     API user might not function properly. Error: {}",
                     error
                 );
                 json_rpc::parse::build_error_response(
                     request_id.0,
-                    json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                    json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
                     None,
                 )
             }
@@ -218,12 +244,22 @@ impl<TPlat: Platform> Background<TPlat> {
                     methods::Response::chain_getBlockHash(methods::HashHexString(best_block))
                         .to_json_response(request_id.0)
                 }
-                Some(_) => {
-                    // While the block could be found in `known_blocks`, there is no guarantee
-                    // that blocks in `known_blocks` are canonical, and we have no choice but to
-                    // return null.
+                Some(height) => {
+                    // Non-finalized blocks aren't guaranteed to be canonical, and `null` is
+                    // returned for them.
                     // TODO: ask a full node instead? or maybe keep a list of canonical blocks?
-                    json_rpc::parse::build_success_response(request_id.0, "null")
+                    match self.finalized_block_hash_by_number(height).await {
+                        Ok(Some(hash)) => {
+                            methods::Response::chain_getBlockHash(methods::HashHexString(hash))
+                                .to_json_response(request_id.0)
+                        }
+                        Ok(None) => json_rpc::parse::build_success_response(request_id.0, "null"),
+                        Err(error) => json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                            None,
+                        ),
+                    }
                 }
             }
         };
@@ -233,6 +269,110 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Returns the hash of the finalized block with the given number, or `None` if the block
+    /// with this number isn't finalized.
+    ///
+    /// If the hash isn't known locally, the headers of the ancestors of the closest block whose
+    /// hash is known are downloaded from the peer-to-peer network, down to the requested block.
+    /// The verified hashes are inserted in [`super::Cache::finalized_block_hashes`].
+    async fn finalized_block_hash_by_number(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<[u8; 32]>, String> {
+        // Find the block whose hash is known and that is the closest to the requested block.
+        let (mut start_number, mut start_hash) = {
+            let mut cache_lock = self.cache.lock().await;
+            let cache_lock = &mut *cache_lock;
+
+            let (finalized_number, oldest_recent) = match (
+                cache_lock.recent_finalized_headers.back(),
+                cache_lock.recent_finalized_headers.front(),
+            ) {
+                (Some((_, finalized_header)), Some((oldest_hash, _))) => {
+                    let finalized_number =
+                        header::decode(finalized_header, self.sync_service.block_number_bytes())
+                            .map_err(|error| format!("Failed to decode header: {error}"))?
+                            .number;
+                    let num_recent = u64::try_from(cache_lock.recent_finalized_headers.len())
+                        .unwrap_or(u64::max_value());
+                    (
+                        finalized_number,
+                        (finalized_number - (num_recent - 1), *oldest_hash),
+                    )
+                }
+                // The cache is populated as soon as the JSON-RPC service starts. This can only
+                // happen if the finalized block has changed in a way that interrupted the list.
+                _ => return Err("Finalized block is unknown".to_owned()),
+            };
+
+            if block_number > finalized_number {
+                return Ok(None);
+            }
+
+            if finalized_number - block_number > self.max_block_hash_lookup_depth {
+                return Err(format!(
+                    "Block is more than {} blocks below the finalized block",
+                    self.max_block_hash_lookup_depth
+                ));
+            }
+
+            // The headers of `recent_finalized_headers` are each the parent of the next one.
+            if block_number >= oldest_recent.0 {
+                let offset = usize::try_from(block_number - oldest_recent.0).unwrap();
+                return Ok(Some(cache_lock.recent_finalized_headers[offset].0));
+            }
+
+            if let Some(hash) = cache_lock.finalized_block_hashes.get(&block_number) {
+                return Ok(Some(*hash));
+            }
+
+            cache_lock
+                .finalized_block_hashes
+                .iter()
+                .filter(|(n, _)| **n > block_number && **n < oldest_recent.0)
+                .min_by_key(|(n, _)| **n)
+                .map(|(n, h)| (*n, *h))
+                .unwrap_or(oldest_recent)
+        };
+
+        // Download the headers of the ancestors of the starting block, by groups of up to
+        // 128 blocks, until the requested block is reached.
+        loop {
+            debug_assert!(start_number > block_number);
+            let num_blocks = cmp::min(start_number - block_number + 1, 128);
+            let ancestry = self
+                .sync_service
+                .clone()
+                .block_ancestry_query(
+                    start_number,
+                    start_hash,
+                    NonZeroU32::new(u32::try_from(num_blocks).unwrap()).unwrap(),
+                    3,
+                    Duration::from_secs(8),
+                )
+                .await
+                .map_err(|()| "Failed to download the headers of the ancestors of the block")?;
+
+            // The block ancestry query guarantees that each entry is the parent of the previous
+            // one, and that the list isn't empty.
+            let mut cache_lock = self.cache.lock().await;
+            for (index, (hash, _)) in ancestry.iter().enumerate() {
+                let number = start_number - u64::try_from(index).unwrap();
+                cache_lock.finalized_block_hashes.put(number, *hash);
+                if number == block_number {
+                    return Ok(Some(*hash));
+                }
+            }
+            drop(cache_lock);
+
+            let (_, last_header) = ancestry.last().unwrap();
+            let last_header = header::decode(last_header, self.sync_service.block_number_bytes())
+                .map_err(|error| format!("Failed to decode header: {error}"))?;
+            start_number = last_header.number - 1;
+            start_hash = *last_header.parent_hash;
+        }
+    }
+
     /// Handles a call to [`methods::MethodCall::chain_getHeader`].
     pub(super) async fn chain_get_header(
         self: &Arc<Self>,
@@ -253,6 +393,12 @@ impl<TPlat: Platform> Background<TPlat> {
             let mut cache_lock = self.cache.lock().await;
             if let Some(header) = cache_lock.recent_pinned_blocks.get(&hash) {
                 Ok(header.clone())
+            } else if let Some((_, header)) = cache_lock
+                .recent_finalized_headers
+                .iter()
+                .find(|(h, _)| *h == hash)
+            {
+                Ok(header.clone())
             } else {
                 // Header isn't known locally. We need to ask the network.
                 // First, try to determine the block number by looking into the cache.
@@ -861,100 +1007,99 @@ impl<TPlat: Platform> Background<TPlat> {
         }
     }
 
-    /// Handles a call to [`methods::MethodCall::payment_queryInfo`].
-    pub(super) async fn payment_query_info(
+    /// Handles a call to [`methods::MethodCall::childstate_getKeys`].
+    pub(super) async fn childstate_get_keys(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
-        extrinsic: &[u8],
-        block_hash: Option<&[u8; 32]>,
+        child_storage_key: methods::HexString,
+        prefix: methods::HexString,
+        hash: Option<methods::HashHexString>,
     ) {
-        let block_hash = match block_hash {
-            Some(h) => *h,
+        // `hash` equal to `None` means "best block".
+        let hash = match hash {
+            Some(h) => h.0,
             None => header::hash_from_scale_encoded_header(
                 sub_utils::subscribe_best(&self.runtime_service).await.0,
             ),
         };
 
-        let result = self
-            .runtime_call(
-                &block_hash,
-                "TransactionPaymentApi",
-                1..=2,
-                json_rpc::payment_info::PAYMENT_FEES_FUNCTION_NAME,
-                json_rpc::payment_info::payment_info_parameters(extrinsic),
-                4,
-                Duration::from_secs(4),
-                NonZeroU32::new(2).unwrap(),
-            )
-            .await;
-
-        let response = match result {
-            Ok(result) => match json_rpc::payment_info::decode_payment_info(
-                &result.return_value,
-                result.api_version,
-            ) {
-                Ok(info) => {
-                    methods::Response::payment_queryInfo(info).to_json_response(request_id.0)
-                }
-                Err(error) => json_rpc::parse::build_error_response(
-                    request_id.0,
-                    json_rpc::parse::ErrorResponse::ServerError(
-                        -32000,
-                        &format!("Failed to decode runtime output: {error}"),
-                    ),
-                    None,
-                ),
-            },
-            Err(error) => {
-                log::warn!(
-                    target: &self.log_target,
-                    "Returning error from `state_getMetadata`. \
-                    API user might not function properly. Error: {}",
-                    error
-                );
-                json_rpc::parse::build_error_response(
-                    request_id.0,
-                    json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
-                    None,
+        let outcome = match strip_child_storage_prefix(&child_storage_key.0) {
+            Ok(child_trie) => {
+                self.child_storage_prefix_keys_query(
+                    child_trie,
+                    &prefix.0,
+                    &hash,
+                    3,
+                    Duration::from_secs(12),
+                    NonZeroU32::new(1).unwrap(),
                 )
+                .await
+            }
+            Err(error) => Err(error),
+        };
+
+        let response = match outcome {
+            Ok(keys) => {
+                let out = keys.into_iter().map(methods::HexString).collect::<Vec<_>>();
+                methods::Response::childstate_getKeys(out).to_json_response(request_id.0)
             }
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
         };
 
         self.requests_subscriptions
-            .respond(&request_id.1, response)
+            .respond(request_id.1, response)
             .await;
     }
 
-    /// Handles a call to [`methods::MethodCall::state_call`].
-    pub(super) async fn state_call(
+    /// Handles a call to [`methods::MethodCall::childstate_getKeysPaged`].
+    pub(super) async fn childstate_get_keys_paged(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
-        function_to_call: &str,
-        call_parameters: methods::HexString,
+        child_storage_key: methods::HexString,
+        prefix: Option<methods::HexString>,
+        count: u32,
+        start_key: Option<methods::HexString>,
         hash: Option<methods::HashHexString>,
     ) {
-        let block_hash = if let Some(hash) = hash {
-            hash.0
-        } else {
-            header::hash_from_scale_encoded_header(
+        // `hash` equal to `None` means "best block".
+        let hash = match hash {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
                 sub_utils::subscribe_best(&self.runtime_service).await.0,
-            )
+            ),
         };
 
-        let result = self
-            .runtime_call_no_api_check(
-                &block_hash,
-                function_to_call,
-                iter::once(call_parameters.0),
-                3,
-                Duration::from_secs(10),
-                NonZeroU32::new(3).unwrap(),
-            )
-            .await;
+        let outcome = match strip_child_storage_prefix(&child_storage_key.0) {
+            Ok(child_trie) => {
+                self.child_storage_prefix_keys_query(
+                    child_trie,
+                    prefix.as_ref().map_or(&[][..], |p| &p.0[..]),
+                    &hash,
+                    3,
+                    Duration::from_secs(12),
+                    NonZeroU32::new(1).unwrap(),
+                )
+                .await
+            }
+            Err(error) => Err(error),
+        };
 
-        let response = match result {
-            Ok(data) => methods::Response::state_call(methods::HexString(data.to_vec()))
-                .to_json_response(request_id.0),
+        let response = match outcome {
+            Ok(keys) => {
+                // Contrary to `state_getKeysPaged`, the start key is excluded from the result, in
+                // accordance with the behavior of Substrate.
+                let out = keys
+                    .into_iter()
+                    .filter(|k| start_key.as_ref().map_or(true, |start| *k > start.0))
+                    .map(methods::HexString)
+                    .take(usize::try_from(count).unwrap_or(usize::max_value()))
+                    .collect::<Vec<_>>();
+                methods::Response::childstate_getKeysPaged(out).to_json_response(request_id.0)
+            }
             Err(error) => json_rpc::parse::build_error_response(
                 request_id.0,
                 json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
@@ -967,14 +1112,14 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
-    /// Handles a call to [`methods::MethodCall::state_getKeys`].
-    pub(super) async fn state_get_keys(
+    /// Handles a call to [`methods::MethodCall::childstate_getStorage`].
+    pub(super) async fn childstate_get_storage(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
-        prefix: methods::HexString,
+        child_storage_key: methods::HexString,
+        key: methods::HexString,
         hash: Option<methods::HashHexString>,
     ) {
-        // `hash` equal to `None` means "best block".
         let hash = match hash {
             Some(h) => h.0,
             None => header::hash_from_scale_encoded_header(
@@ -982,47 +1127,24 @@ impl<TPlat: Platform> Background<TPlat> {
             ),
         };
 
-        // Obtain the state trie root and height of the requested block.
-        // This is necessary to perform network storage queries.
-        let (state_root, block_number) = match self.state_trie_root_hash(&hash).await {
-            Ok(v) => v,
-            Err(err) => {
-                self.requests_subscriptions
-                    .respond(
-                        request_id.1,
-                        json_rpc::parse::build_error_response(
-                            request_id.0,
-                            json_rpc::parse::ErrorResponse::ServerError(
-                                -32000,
-                                &format!("Failed to fetch block information: {err}"),
-                            ),
-                            None,
-                        ),
-                    )
-                    .await;
-                return;
+        let response = match strip_child_storage_prefix(&child_storage_key.0) {
+            Ok(child_trie) => {
+                self.child_storage_query(
+                    child_trie,
+                    iter::once(&key.0),
+                    &hash,
+                    3,
+                    Duration::from_secs(12),
+                    NonZeroU32::new(1).unwrap(),
+                )
+                .await
             }
+            Err(error) => Err(error),
         };
-
-        let outcome = self
-            .sync_service
-            .clone()
-            .storage_prefix_keys_query(
-                block_number,
-                &hash,
-                &prefix.0,
-                &state_root,
-                3,
-                Duration::from_secs(12),
-                NonZeroU32::new(1).unwrap(),
-            )
-            .await;
-
-        let response = match outcome {
-            Ok(keys) => {
-                let out = keys.into_iter().map(methods::HexString).collect::<Vec<_>>();
-                methods::Response::state_getKeys(out).to_json_response(request_id.0)
-            }
+        let response = match response.map(|mut r| r.pop().unwrap()) {
+            Ok(Some(value)) => methods::Response::childstate_getStorage(methods::HexString(value))
+                .to_json_response(request_id.0),
+            Ok(None) => json_rpc::parse::build_success_response(request_id.0, "null"),
             Err(error) => json_rpc::parse::build_error_response(
                 request_id.0,
                 json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
@@ -1031,20 +1153,18 @@ impl<TPlat: Platform> Background<TPlat> {
         };
 
         self.requests_subscriptions
-            .respond(&request_id.1, response)
+            .respond(request_id.1, response)
             .await;
     }
 
-    /// Handles a call to [`methods::MethodCall::state_getKeysPaged`].
-    pub(super) async fn state_get_keys_paged(
+    /// Handles a call to [`methods::MethodCall::childstate_getStorageHash`].
+    pub(super) async fn childstate_get_storage_hash(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
-        prefix: Option<methods::HexString>,
-        count: u32,
-        start_key: Option<methods::HexString>,
+        child_storage_key: methods::HexString,
+        key: methods::HexString,
         hash: Option<methods::HashHexString>,
     ) {
-        // `hash` equal to `None` means "best block".
         let hash = match hash {
             Some(h) => h.0,
             None => header::hash_from_scale_encoded_header(
@@ -1052,62 +1172,563 @@ impl<TPlat: Platform> Background<TPlat> {
             ),
         };
 
-        // Because the user is likely to call this function multiple times in a row with the exact
-        // same parameters, we store the untruncated responses in a cache. Check if we hit the
-        // cache.
-        if let Some(keys) = self
-            .cache
-            .lock()
-            .await
-            .state_get_keys_paged
-            .get(&(hash, prefix.clone()))
-        {
-            let out = keys
-                .iter()
-                .filter(|k| start_key.as_ref().map_or(true, |start| *k >= &start.0)) // TODO: not sure if start should be in the set or not?
-                .cloned()
-                .map(methods::HexString)
-                .take(usize::try_from(count).unwrap_or(usize::max_value()))
-                .collect::<Vec<_>>();
-
-            self.requests_subscriptions
-                .respond(
-                    request_id.1,
-                    methods::Response::state_getKeysPaged(out).to_json_response(request_id.0),
+        let response = match strip_child_storage_prefix(&child_storage_key.0) {
+            Ok(child_trie) => {
+                self.child_storage_query(
+                    child_trie,
+                    iter::once(&key.0),
+                    &hash,
+                    3,
+                    Duration::from_secs(12),
+                    NonZeroU32::new(1).unwrap(),
                 )
-                .await;
-            return;
-        }
-
-        // Obtain the state trie root and height of the requested block.
-        // This is necessary to perform network storage queries.
-        let (state_root, block_number) = match self.state_trie_root_hash(&hash).await {
-            Ok(v) => v,
-            Err(err) => {
-                self.requests_subscriptions
-                    .respond(
-                        request_id.1,
-                        json_rpc::parse::build_error_response(
-                            request_id.0,
-                            json_rpc::parse::ErrorResponse::ServerError(
-                                -32000,
-                                &format!("Failed to fetch block information: {err}"),
-                            ),
-                            None,
-                        ),
-                    )
-                    .await;
-                return;
+                .await
             }
+            Err(error) => Err(error),
         };
-
-        let outcome = self
+        let response = match response.map(|mut r| r.pop().unwrap()) {
+            Ok(Some(value)) => {
+                let mut hash_context = blake2_rfc::blake2b::Blake2b::new(32);
+                hash_context.update(&value);
+                let mut value_hash: [u8; 32] = Default::default();
+                value_hash.copy_from_slice(hash_context.finalize().as_bytes());
+                methods::Response::childstate_getStorageHash(methods::HashHexString(value_hash))
+                    .to_json_response(request_id.0)
+            }
+            Ok(None) => json_rpc::parse::build_success_response(request_id.0, "null"),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::mmr_generateProof`].
+    pub(super) async fn mmr_generate_proof(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        // The `MmrApi_generate_proof` runtime function reads the nodes of the MMR from the
+        // off-chain storage of the node, which is filled through off-chain indexing while
+        // importing blocks. A light client never has this storage, and thus can't generate
+        // proofs.
+        self.requests_subscriptions
+            .respond(
+                request_id.1,
+                json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        "mmr_generateProof isn't supported by the light client, as generating \
+                        MMR proofs requires the off-chain storage of a full node",
+                    ),
+                    None,
+                ),
+            )
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::mmr_root`].
+    pub(super) async fn mmr_root(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        at: Option<methods::HashHexString>,
+    ) {
+        let block_hash = match at {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        let response = match self.mmr_root_at(&block_hash).await {
+            Ok(root) => methods::Response::mmr_root(methods::HashHexString(root))
+                .to_json_response(request_id.0),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::mmr_verifyProof`].
+    pub(super) async fn mmr_verify_proof(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        proof: methods::LeavesProof,
+    ) {
+        // The verification is delegated to the runtime, as the proof might have been generated
+        // against the MMR of an older block, in which case the root to verify it against isn't
+        // known.
+        let result = self
+            .runtime_call(
+                &proof.block_hash.0,
+                "MmrApi",
+                2..=2,
+                "MmrApi_verify_proof",
+                [&proof.leaves.0, &proof.proof.0].into_iter(),
+                3,
+                Duration::from_secs(10),
+                NonZeroU32::new(2).unwrap(),
+            )
+            .await;
+
+        let response = match result {
+            Ok(result) => match beefy::mmr::decode_verify_proof_output(&result.return_value) {
+                Ok(Ok(())) => {
+                    methods::Response::mmr_verifyProof(true).to_json_response(request_id.0)
+                }
+                Ok(Err(beefy::mmr::RuntimeError::Verify)) => {
+                    methods::Response::mmr_verifyProof(false).to_json_response(request_id.0)
+                }
+                Ok(Err(error)) => json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Failed to verify MMR proof: {error}"),
+                    ),
+                    None,
+                ),
+                Err(error) => json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Failed to decode runtime output: {error}"),
+                    ),
+                    None,
+                ),
+            },
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::mmr_verifyProofStateless`].
+    pub(super) async fn mmr_verify_proof_stateless(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        mmr_root: methods::HashHexString,
+        proof: methods::LeavesProof,
+    ) {
+        let decoded = beefy::mmr::decode_leaves(&proof.leaves.0)
+            .and_then(|leaves| Ok((leaves, beefy::mmr::decode_leaf_proof(&proof.proof.0)?)));
+
+        let response = match decoded {
+            Ok((leaves, leaf_proof)) => {
+                let is_valid = beefy::mmr::verify(beefy::mmr::VerifyConfig {
+                    root: &mmr_root.0,
+                    leaves: leaves.into_iter(),
+                    proof: &leaf_proof,
+                })
+                .is_ok();
+                methods::Response::mmr_verifyProofStateless(is_valid).to_json_response(request_id.0)
+            }
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    &format!("Failed to decode MMR proof: {error}"),
+                ),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
+    /// Obtains the root of the Merkle Mountain Range at the given block.
+    async fn mmr_root_at(self: &Arc<Self>, block_hash: &[u8; 32]) -> Result<[u8; 32], String> {
+        let result = self
+            .runtime_call(
+                block_hash,
+                "MmrApi",
+                2..=2,
+                "MmrApi_mmr_root",
+                iter::empty::<Vec<u8>>(),
+                3,
+                Duration::from_secs(10),
+                NonZeroU32::new(2).unwrap(),
+            )
+            .await
+            .map_err(|error| error.to_string())?;
+
+        match beefy::mmr::decode_mmr_root_output(&result.return_value) {
+            Ok(Ok(root)) => Ok(*root),
+            Ok(Err(error)) => Err(format!("Failed to obtain MMR root: {error}")),
+            Err(error) => Err(format!("Failed to decode runtime output: {error}")),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::payment_queryFeeDetails`].
+    pub(super) async fn payment_query_fee_details(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        extrinsic: &[u8],
+        block_hash: Option<&[u8; 32]>,
+    ) {
+        let block_hash = match block_hash {
+            Some(h) => *h,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        // The output of `TransactionPaymentApi_query_fee_details` hasn't changed between the
+        // various versions of the API.
+        let result = self
+            .runtime_call(
+                &block_hash,
+                "TransactionPaymentApi",
+                1..=4,
+                json_rpc::payment_info::FEE_DETAILS_FUNCTION_NAME,
+                json_rpc::payment_info::payment_info_parameters(extrinsic),
+                4,
+                Duration::from_secs(4),
+                NonZeroU32::new(2).unwrap(),
+            )
+            .await;
+
+        let response = match result {
+            Ok(result) => match json_rpc::payment_info::decode_fee_details(&result.return_value) {
+                Ok(details) => methods::Response::payment_queryFeeDetails(details)
+                    .to_json_response(request_id.0),
+                Err(error) => json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Failed to decode runtime output: {error}"),
+                    ),
+                    None,
+                ),
+            },
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Returning error from `payment_queryFeeDetails`. \
+                    API user might not function properly. Error: {}",
+                    error
+                );
+                json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                    None,
+                )
+            }
+        };
+
+        self.requests_subscriptions
+            .respond(&request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::payment_queryInfo`].
+    pub(super) async fn payment_query_info(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        extrinsic: &[u8],
+        block_hash: Option<&[u8; 32]>,
+    ) {
+        let block_hash = match block_hash {
+            Some(h) => *h,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        let result = self
+            .runtime_call(
+                &block_hash,
+                "TransactionPaymentApi",
+                1..=2,
+                json_rpc::payment_info::PAYMENT_FEES_FUNCTION_NAME,
+                json_rpc::payment_info::payment_info_parameters(extrinsic),
+                4,
+                Duration::from_secs(4),
+                NonZeroU32::new(2).unwrap(),
+            )
+            .await;
+
+        let response = match result {
+            Ok(result) => match json_rpc::payment_info::decode_payment_info(
+                &result.return_value,
+                result.api_version,
+            ) {
+                Ok(info) => {
+                    methods::Response::payment_queryInfo(info).to_json_response(request_id.0)
+                }
+                Err(error) => json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Failed to decode runtime output: {error}"),
+                    ),
+                    None,
+                ),
+            },
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Returning error from `state_getMetadata`. \
+                    API user might not function properly. Error: {}",
+                    error
+                );
+                json_rpc::parse::build_error_response(
+                    request_id.0,
+                    json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                    None,
+                )
+            }
+        };
+
+        self.requests_subscriptions
+            .respond(&request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_call`].
+    pub(super) async fn state_call(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        function_to_call: &str,
+        call_parameters: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        let block_hash = if let Some(hash) = hash {
+            hash.0
+        } else {
+            header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            )
+        };
+
+        let result = self
+            .runtime_call_no_api_check(
+                &block_hash,
+                function_to_call,
+                iter::once(call_parameters.0),
+                3,
+                Duration::from_secs(10),
+                NonZeroU32::new(3).unwrap(),
+            )
+            .await;
+
+        let response = match result {
+            Ok(data) => methods::Response::state_call(methods::HexString(data.to_vec()))
+                .to_json_response(request_id.0),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getKeys`].
+    pub(super) async fn state_get_keys(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        prefix: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        // `hash` equal to `None` means "best block".
+        let hash = match hash {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        // Obtain the state trie root and height of the requested block.
+        // This is necessary to perform network storage queries.
+        let (state_root, block_number) = match self.state_trie_root_hash(&hash).await {
+            Ok(v) => v,
+            Err(err) => {
+                self.requests_subscriptions
+                    .respond(
+                        request_id.1,
+                        json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                &format!("Failed to fetch block information: {err}"),
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        let outcome = self
+            .sync_service
+            .clone()
+            .storage_prefix_keys_query(
+                block_number,
+                &hash,
+                &prefix.0,
+                &state_root,
+                3,
+                Duration::from_secs(12),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await;
+
+        let response = match outcome {
+            Ok(keys) => {
+                let out = keys.into_iter().map(methods::HexString).collect::<Vec<_>>();
+                methods::Response::state_getKeys(out).to_json_response(request_id.0)
+            }
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(&request_id.1, response)
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getKeysPaged`].
+    pub(super) async fn state_get_keys_paged(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        prefix: Option<methods::HexString>,
+        count: u32,
+        start_key: Option<methods::HexString>,
+        hash: Option<methods::HashHexString>,
+    ) {
+        // `hash` equal to `None` means "best block".
+        let hash = match hash {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        let Some(count) = NonZeroUsize::new(usize::try_from(count).unwrap_or(usize::max_value()))
+        else {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::state_getKeysPaged(Vec::new())
+                        .to_json_response(request_id.0),
+                )
+                .await;
+            return;
+        };
+
+        // The start key is excluded from the response.
+        let start_key = start_key.map(|k| k.0);
+
+        // The user is likely to call this function multiple times in a row with the same
+        // parameters, or with the last key of the previous response as the start key. Because of
+        // this, the keys that have been downloaded are stored in a cache.
+        // Keys found in the cache that are part of the response, and key after which the
+        // remaining keys must be downloaded from the network.
+        let (mut out, scan_start_key, extend_cache) = {
+            let mut cache = self.cache.lock().await;
+            match cache
+                .state_get_keys_paged
+                .get(&(hash, prefix.clone()))
+                .filter(|entry| match (&entry.start_key, &start_key) {
+                    (None, _) => true,
+                    (Some(_), None) => false,
+                    (Some(entry_start), Some(start)) => start >= entry_start,
+                }) {
+                Some(entry) => {
+                    let from_cache = entry
+                        .keys
+                        .iter()
+                        .filter(|k| start_key.as_ref().map_or(true, |start| *k > start))
+                        .take(count.get())
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    if from_cache.len() == count.get() || entry.complete {
+                        drop(cache);
+                        let out = from_cache.into_iter().map(methods::HexString).collect();
+                        self.requests_subscriptions
+                            .respond(
+                                request_id.1,
+                                methods::Response::state_getKeysPaged(out)
+                                    .to_json_response(request_id.0),
+                            )
+                            .await;
+                        return;
+                    }
+
+                    // The cache can be extended only if there is no gap between the keys in the
+                    // cache and the keys that are about to be downloaded.
+                    let cache_end = entry.keys.last().or(entry.start_key.as_ref());
+                    if !from_cache.is_empty() || start_key.as_ref() == cache_end {
+                        (from_cache, cache_end.cloned(), true)
+                    } else {
+                        (Vec::new(), start_key.clone(), false)
+                    }
+                }
+                None => (Vec::new(), start_key.clone(), false),
+            }
+        };
+
+        // Obtain the state trie root and height of the requested block.
+        // This is necessary to perform network storage queries.
+        let (state_root, block_number) = match self.state_trie_root_hash(&hash).await {
+            Ok(v) => v,
+            Err(err) => {
+                self.requests_subscriptions
+                    .respond(
+                        request_id.1,
+                        json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                &format!("Failed to fetch block information: {err}"),
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        // Only the keys that aren't in the cache are downloaded from the network.
+        let num_missing_keys = NonZeroUsize::new(count.get() - out.len()).unwrap();
+        let outcome = self
             .sync_service
             .clone()
-            .storage_prefix_keys_query(
+            .storage_prefix_keys_paged_query(
                 block_number,
                 &hash,
-                &prefix.as_ref().unwrap().0, // TODO: don't unwrap! what is this Option?
+                prefix.as_ref().map_or(&[][..], |p| &p.0[..]),
+                scan_start_key.as_deref(),
+                num_missing_keys,
                 &state_root,
                 3,
                 Duration::from_secs(12),
@@ -1117,26 +1738,40 @@ impl<TPlat: Platform> Background<TPlat> {
 
         let response = match outcome {
             Ok(keys) => {
-                // TODO: instead of requesting all keys with that prefix from the network, pass `start_key` to the network service
-                let out = keys
-                    .iter()
-                    .filter(|k| start_key.as_ref().map_or(true, |start| *k >= &start.0)) // TODO: not sure if start should be in the set or not?
-                    .cloned() // TODO: instead of cloning, make `Response::state_getKeysPaged` accept references
-                    .map(methods::HexString)
-                    .take(usize::try_from(count).unwrap_or(usize::max_value()))
-                    .collect::<Vec<_>>();
+                let complete = keys.len() < num_missing_keys.get();
 
-                // If the returned response is somehow truncated, it is very likely that the
-                // JSON-RPC client will call the function again with the exact same parameters.
-                // Thus, store the results in a cache.
-                if out.len() != keys.len() {
-                    self.cache
-                        .lock()
-                        .await
-                        .state_get_keys_paged
-                        .push((hash, prefix), keys);
+                {
+                    let mut cache = self.cache.lock().await;
+                    let cache_entry = if extend_cache {
+                        // The cache entry might have been modified or removed in parallel.
+                        cache
+                            .state_get_keys_paged
+                            .get_mut(&(hash, prefix.clone()))
+                            .filter(|entry| {
+                                entry.keys.last().or(entry.start_key.as_ref())
+                                    == scan_start_key.as_ref()
+                            })
+                    } else {
+                        None
+                    };
+
+                    if let Some(cache_entry) = cache_entry {
+                        cache_entry.keys.extend(keys.iter().cloned());
+                        cache_entry.complete = complete;
+                    } else {
+                        cache.state_get_keys_paged.push(
+                            (hash, prefix),
+                            GetKeysPagedCacheEntry {
+                                start_key: scan_start_key,
+                                keys: keys.clone(),
+                                complete,
+                            },
+                        );
+                    }
                 }
 
+                out.extend(keys);
+                let out = out.into_iter().map(methods::HexString).collect::<Vec<_>>();
                 methods::Response::state_getKeysPaged(out).to_json_response(request_id.0)
             }
             Err(error) => json_rpc::parse::build_error_response(
@@ -1266,6 +1901,60 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::state_getReadProof`].
+    pub(super) async fn state_get_read_proof(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        keys: Vec<methods::HexString>,
+        at: Option<methods::HashHexString>,
+    ) {
+        if keys.len() > MAX_QUERY_STORAGE_KEYS {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    json_rpc::parse::build_error_response(
+                        request_id.0,
+                        json_rpc::parse::ErrorResponse::ServerError(
+                            -32000,
+                            &format!("Too many keys (maximum is {MAX_QUERY_STORAGE_KEYS})"),
+                        ),
+                        None,
+                    ),
+                )
+                .await;
+            return;
+        }
+
+        // `at` equal to `None` means "best block".
+        let at = match at {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        // The proof sent by the peer is verified before being returned, so that the JSON-RPC
+        // client doesn't receive a proof that doesn't match the state of the block.
+        let fut = self.storage_read_proof(keys.iter(), &at, 3, Duration::from_secs(12));
+
+        let response = match fut.await {
+            Ok(proof) => methods::Response::state_getReadProof(methods::ReadProof {
+                at: methods::HashHexString(at),
+                proof: proof.into_iter().map(methods::HexString).collect(),
+            })
+            .to_json_response(request_id.0),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::state_getStorage`].
     pub(super) async fn state_get_storage(
         self: &Arc<Self>,
@@ -1304,28 +1993,222 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
-    /// Handles a call to [`methods::MethodCall::state_queryStorageAt`].
-    pub(super) async fn state_query_storage_at(
+    /// Handles a call to [`methods::MethodCall::state_queryStorage`].
+    pub(super) async fn state_query_storage(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
         keys: Vec<methods::HexString>,
-        at: Option<methods::HashHexString>,
+        from_block: methods::HashHexString,
+        to_block: Option<methods::HashHexString>,
     ) {
-        let best_block = header::hash_from_scale_encoded_header(
-            &sub_utils::subscribe_best(&self.runtime_service).await.0,
-        );
+        // Querying the storage of a block requires a network request. Because this JSON-RPC
+        // function is given a range of blocks, the number of blocks is capped in order to avoid
+        // a single call sending a large number of requests.
+        const MAX_BLOCKS: u64 = 64;
+
+        if keys.len() > MAX_QUERY_STORAGE_KEYS {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    json_rpc::parse::build_error_response(
+                        request_id.0,
+                        json_rpc::parse::ErrorResponse::ServerError(
+                            -32000,
+                            &format!("Too many keys (maximum is {MAX_QUERY_STORAGE_KEYS})"),
+                        ),
+                        None,
+                    ),
+                )
+                .await;
+            return;
+        }
+
+        // `to_block` equal to `None` means "best block".
+        let to_block = match to_block {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        // Build the list of blocks between `from_block` and `to_block`, in reverse order, by
+        // walking the parents of `to_block`.
+        // Each entry contains the hash, number, and state trie root of the block.
+        let blocks = {
+            let (_, from_block_number) = match self.state_trie_root_hash(&from_block.0).await {
+                Ok(v) => v,
+                Err(err) => {
+                    self.requests_subscriptions
+                        .respond(
+                            request_id.1,
+                            json_rpc::parse::build_error_response(
+                                request_id.0,
+                                json_rpc::parse::ErrorResponse::ServerError(
+                                    -32000,
+                                    &format!("Failed to fetch fromBlock: {err}"),
+                                ),
+                                None,
+                            ),
+                        )
+                        .await;
+                    return;
+                }
+            };
+
+            let mut blocks = Vec::new();
+            let mut next_hash = to_block;
+            let error = loop {
+                let scale_encoded_header = match self.block_header(&next_hash).await {
+                    Ok(h) => h,
+                    Err(()) => break Some("Failed to fetch block header"),
+                };
+                let decoded = match header::decode(
+                    &scale_encoded_header,
+                    self.sync_service.block_number_bytes(),
+                ) {
+                    Ok(h) => h,
+                    Err(_) => break Some("Failed to decode block header"),
+                };
+
+                if decoded.number < from_block_number
+                    || (decoded.number == from_block_number && next_hash != from_block.0)
+                {
+                    break Some("fromBlock isn't an ancestor of toBlock");
+                }
+
+                if decoded.number - from_block_number >= MAX_BLOCKS {
+                    break Some("Range of blocks is too large");
+                }
+
+                blocks.push((next_hash, decoded.number, *decoded.state_root));
 
-        let cache = self.cache.lock().await;
+                if next_hash == from_block.0 {
+                    break None;
+                }
+                next_hash = *decoded.parent_hash;
+            };
 
-        let at = at.as_ref().map(|h| h.0).unwrap_or(best_block);
+            if let Some(error) = error {
+                self.requests_subscriptions
+                    .respond(
+                        request_id.1,
+                        json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(-32000, error),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
 
-        let mut out = methods::StorageChangeSet {
-            block: methods::HashHexString(best_block),
-            changes: Vec::new(),
+            blocks.reverse();
+            blocks
         };
 
-        drop(cache);
+        // Query the storage of each block one by one. All the keys of a block are queried using
+        // a single storage proof request. In accordance with Substrate, the first item of the
+        // output contains all the keys, and the following items only contain the keys whose
+        // value has changed compared to the previous block.
+        let mut out = Vec::with_capacity(blocks.len());
+        let mut previous_values: Option<Vec<Option<Vec<u8>>>> = None;
+        for (block_hash, block_number, state_root) in blocks {
+            let values = match self
+                .sync_service
+                .clone()
+                .storage_query(
+                    block_number,
+                    &block_hash,
+                    &state_root,
+                    keys.iter(),
+                    3,
+                    Duration::from_secs(12),
+                    NonZeroU32::new(1).unwrap(),
+                )
+                .await
+            {
+                Ok(v) => v,
+                Err(error) => {
+                    self.requests_subscriptions
+                        .respond(
+                            request_id.1,
+                            json_rpc::parse::build_error_response(
+                                request_id.0,
+                                json_rpc::parse::ErrorResponse::ServerError(
+                                    -32000,
+                                    &error.to_string(),
+                                ),
+                                None,
+                            ),
+                        )
+                        .await;
+                    return;
+                }
+            };
+
+            let changes = keys
+                .iter()
+                .zip(values.iter())
+                .enumerate()
+                .filter(|(index, (_, value))| {
+                    previous_values
+                        .as_ref()
+                        .map_or(true, |previous| previous[*index] != **value)
+                })
+                .map(|(_, (key, value))| (key.clone(), value.clone().map(methods::HexString)))
+                .collect::<Vec<_>>();
+
+            if !changes.is_empty() {
+                out.push(methods::StorageChangeSet {
+                    block: methods::HashHexString(block_hash),
+                    changes,
+                });
+            }
+
+            previous_values = Some(values);
+        }
+
+        self.requests_subscriptions
+            .respond(
+                request_id.1,
+                methods::Response::state_queryStorage(out).to_json_response(request_id.0),
+            )
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_queryStorageAt`].
+    pub(super) async fn state_query_storage_at(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        keys: Vec<methods::HexString>,
+        at: Option<methods::HashHexString>,
+    ) {
+        if keys.len() > MAX_QUERY_STORAGE_KEYS {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    json_rpc::parse::build_error_response(
+                        request_id.0,
+                        json_rpc::parse::ErrorResponse::ServerError(
+                            -32000,
+                            &format!("Too many keys (maximum is {MAX_QUERY_STORAGE_KEYS})"),
+                        ),
+                        None,
+                    ),
+                )
+                .await;
+            return;
+        }
+
+        // `at` equal to `None` means "best block".
+        let at = match at {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
 
+        // All the keys are queried using a single storage proof request.
         let fut = self.storage_query(
             keys.iter(),
             &at,
@@ -1334,20 +2217,67 @@ impl<TPlat: Platform> Background<TPlat> {
             NonZeroU32::new(1).unwrap(),
         );
 
-        if let Ok(values) = fut.await {
-            for (value, key) in values.into_iter().zip(keys) {
-                out.changes.push((key, value.map(methods::HexString)));
+        let response = match fut.await {
+            Ok(values) => {
+                let out = methods::StorageChangeSet {
+                    block: methods::HashHexString(at),
+                    changes: keys
+                        .into_iter()
+                        .zip(values)
+                        .map(|(key, value)| (key, value.map(methods::HexString)))
+                        .collect(),
+                };
+
+                methods::Response::state_queryStorageAt(vec![out]).to_json_response(request_id.0)
             }
-        }
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
 
         self.requests_subscriptions
-            .respond(
-                request_id.1,
-                methods::Response::state_queryStorageAt(vec![out]).to_json_response(request_id.0),
-            )
+            .respond(request_id.1, response)
             .await;
     }
 
+    /// Returns the SCALE-encoded header of the given block, either from the cache of recent
+    /// blocks or by querying the peer-to-peer network.
+    async fn block_header(&self, hash: &[u8; 32]) -> Result<Vec<u8>, ()> {
+        {
+            let mut cache_lock = self.cache.lock().await;
+            if let Some(header) = cache_lock.recent_pinned_blocks.get(hash) {
+                return Ok(header.clone());
+            }
+            if let Some((_, header)) = cache_lock
+                .recent_finalized_headers
+                .iter()
+                .find(|(h, _)| h == hash)
+            {
+                return Ok(header.clone());
+            }
+        }
+
+        // The `block_query` method guarantees that the header is present and valid.
+        let block = self
+            .sync_service
+            .clone()
+            .block_query_unknown_number(
+                *hash,
+                protocol::BlocksRequestFields {
+                    header: true,
+                    body: false,
+                    justifications: false,
+                },
+                3,
+                Duration::from_secs(8),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await?;
+        Ok(block.header.unwrap())
+    }
+
     /// Handles a call to [`methods::MethodCall::state_subscribeRuntimeVersion`].
     pub(super) async fn state_subscribe_runtime_version(
         self: &Arc<Self>,
@@ -1760,3 +2690,11 @@ impl<TPlat: Platform> Background<TPlat> {
         }
     }
 }
+
+/// Removes the `:child_storage:default:` prefix from a child storage key passed as parameter to
+/// a `childstate_*` JSON-RPC function.
+fn strip_child_storage_prefix(child_storage_key: &[u8]) -> Result<&[u8], StorageQueryError> {
+    child_storage_key
+        .strip_prefix(b":child_storage:default:")
+        .ok_or(StorageQueryError::InvalidChildStorageKey)
+}
@@ -17,7 +17,7 @@
 
 //! All JSON-RPC method handlers that relate to transactions.
 
-use super::{Background, Platform, SubscriptionMessage};
+use super::{ApiVersion, Background, Platform, SubscriptionMessage};
 
 use crate::transactions_service;
 
@@ -112,15 +112,19 @@ impl<TPlat: Platform> Background<TPlat> {
         }
     }
 
-    /// Handles a call to [`methods::MethodCall::author_submitAndWatchExtrinsic`] (if `is_legacy`
-    /// is `true`) or to [`methods::MethodCall::transaction_unstable_submitAndWatch`] (if
-    /// `is_legacy` is `false`).
+    /// Handles a call to [`methods::MethodCall::author_submitAndWatchExtrinsic`] (if
+    /// `api_version` is `None`), to [`methods::MethodCall::transaction_unstable_submitAndWatch`]
+    /// or to [`methods::MethodCall::transactionWatch_v1_submitAndWatch`].
     pub(super) async fn submit_and_watch_transaction(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
         transaction: methods::HexString,
-        is_legacy: bool,
+        api_version: Option<ApiVersion>,
     ) {
+        let is_legacy = api_version.is_none();
+        // Only relevant if `is_legacy` is `false`.
+        let api_version = api_version.unwrap_or(ApiVersion::Unstable);
+
         let (subscription_id, mut messages_rx, subscription_start) = match self
             .requests_subscriptions
             .start_subscription(request_id.1, 16)
@@ -259,6 +263,16 @@ impl<TPlat: Platform> Background<TPlat> {
                     };
 
                     let update = match (status_update, is_legacy) {
+                        (transactions_service::TransactionStatus::Validated, true) => {
+                            // The legacy API has no equivalent to this event.
+                            continue;
+                        }
+                        (transactions_service::TransactionStatus::Validated, false) => api_version
+                            .transaction_watch_event(
+                                &subscription_id,
+                                methods::TransactionWatchEvent::Validated {},
+                            ),
+
                         (transactions_service::TransactionStatus::Broadcast(peers), false) => {
                             methods::ServerToClient::author_extrinsicUpdate {
                                 subscription: (&subscription_id).into(),
@@ -270,14 +284,13 @@ impl<TPlat: Platform> Background<TPlat> {
                         }
                         (transactions_service::TransactionStatus::Broadcast(peers), true) => {
                             num_broadcasted_peers += peers.len();
-                            methods::ServerToClient::transaction_unstable_watchEvent {
-                                subscription: (&subscription_id).into(),
-                                result: methods::TransactionWatchEvent::Broadcasted {
+                            api_version.transaction_watch_event(
+                                &subscription_id,
+                                methods::TransactionWatchEvent::Broadcasted {
                                     num_peers: u32::try_from(num_broadcasted_peers)
                                         .unwrap_or(u32::max_value()),
                                 },
-                            }
-                            .to_json_call_object_parameters(None)
+                            )
                         }
 
                         (
@@ -320,29 +333,25 @@ impl<TPlat: Platform> Background<TPlat> {
                             false,
                         ) => {
                             included_block = Some(block_hash);
-                            methods::ServerToClient::transaction_unstable_watchEvent {
-                                subscription: (&subscription_id).into(),
-                                result: methods::TransactionWatchEvent::BestChainBlockIncluded {
+                            api_version.transaction_watch_event(
+                                &subscription_id,
+                                methods::TransactionWatchEvent::BestChainBlockIncluded {
                                     block: Some(methods::TransactionWatchEventBlock {
                                         hash: methods::HashHexString(block_hash),
                                         index: methods::NumberAsString(index),
                                     }),
                                 },
-                            }
-                            .to_json_call_object_parameters(None)
+                            )
                         }
                         (
                             transactions_service::TransactionStatus::IncludedBlockUpdate {
                                 block_hash: None,
                             },
                             false,
-                        ) => methods::ServerToClient::transaction_unstable_watchEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::TransactionWatchEvent::BestChainBlockIncluded {
-                                block: None,
-                            },
-                        }
-                        .to_json_call_object_parameters(None),
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::BestChainBlockIncluded { block: None },
+                        ),
 
                         (
                             transactions_service::TransactionStatus::Dropped(
@@ -367,6 +376,12 @@ impl<TPlat: Platform> Background<TPlat> {
                                 transactions_service::DropReason::ValidateError(_),
                             ),
                             true,
+                        )
+                        | (
+                            transactions_service::TransactionStatus::Dropped(
+                                transactions_service::DropReason::Mortality,
+                            ),
+                            true,
                         ) => methods::ServerToClient::author_extrinsicUpdate {
                             subscription: (&subscription_id).into(),
                             result: methods::TransactionStatus::Dropped,
@@ -377,51 +392,59 @@ impl<TPlat: Platform> Background<TPlat> {
                                 transactions_service::DropReason::GapInChain,
                             ),
                             false,
-                        ) => methods::ServerToClient::transaction_unstable_watchEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::TransactionWatchEvent::Dropped {
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::Dropped {
                                 error: "gap in chain of blocks".into(),
                                 broadcasted: num_broadcasted_peers != 0,
                             },
-                        }
-                        .to_json_call_object_parameters(None),
+                        ),
                         (
                             transactions_service::TransactionStatus::Dropped(
                                 transactions_service::DropReason::MaxPendingTransactionsReached,
                             ),
                             false,
-                        ) => methods::ServerToClient::transaction_unstable_watchEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::TransactionWatchEvent::Dropped {
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::Dropped {
                                 error: "transactions pool full".into(),
                                 broadcasted: num_broadcasted_peers != 0,
                             },
-                        }
-                        .to_json_call_object_parameters(None),
+                        ),
+                        (
+                            transactions_service::TransactionStatus::Dropped(
+                                transactions_service::DropReason::Mortality,
+                            ),
+                            false,
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::Dropped {
+                                error: "transaction mortality expired".into(),
+                                broadcasted: num_broadcasted_peers != 0,
+                            },
+                        ),
                         (
                             transactions_service::TransactionStatus::Dropped(
                                 transactions_service::DropReason::Invalid(error),
                             ),
                             false,
-                        ) => methods::ServerToClient::transaction_unstable_watchEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::TransactionWatchEvent::Invalid {
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::Invalid {
                                 error: error.to_string().into(),
                             },
-                        }
-                        .to_json_call_object_parameters(None),
+                        ),
                         (
                             transactions_service::TransactionStatus::Dropped(
                                 transactions_service::DropReason::ValidateError(error),
                             ),
                             false,
-                        ) => methods::ServerToClient::transaction_unstable_watchEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::TransactionWatchEvent::Error {
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::Error {
                                 error: error.to_string().into(),
                             },
-                        }
-                        .to_json_call_object_parameters(None),
+                        ),
 
                         (
                             transactions_service::TransactionStatus::Dropped(
@@ -440,16 +463,15 @@ impl<TPlat: Platform> Background<TPlat> {
                                 transactions_service::DropReason::Finalized { block_hash, index },
                             ),
                             false,
-                        ) => methods::ServerToClient::transaction_unstable_watchEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::TransactionWatchEvent::Finalized {
+                        ) => api_version.transaction_watch_event(
+                            &subscription_id,
+                            methods::TransactionWatchEvent::Finalized {
                                 block: methods::TransactionWatchEventBlock {
                                     hash: methods::HashHexString(block_hash),
                                     index: methods::NumberAsString(index),
                                 },
                             },
-                        }
-                        .to_json_call_object_parameters(None),
+                        ),
                     };
 
                     // TODO: handle situation where buffer is full
@@ -462,7 +484,131 @@ impl<TPlat: Platform> Background<TPlat> {
         });
     }
 
-    /// Handles a call to [`methods::MethodCall::transaction_unstable_unwatch`].
+    /// Handles a call to [`methods::MethodCall::transaction_v1_broadcast`].
+    pub(super) async fn transaction_v1_broadcast(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        transaction: methods::HexString,
+    ) {
+        // The operation is tracked as a subscription that never generates any notification, so
+        // that `transaction_v1_stop` can find it.
+        let (operation_id, mut messages_rx, subscription_start) = match self
+            .requests_subscriptions
+            .start_subscription(request_id.1, 1)
+            .await
+        {
+            Ok(v) => v,
+            Err(requests_subscriptions::StartSubscriptionError::LimitReached) => {
+                self.requests_subscriptions
+                    .respond(
+                        request_id.1,
+                        json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Too many active subscriptions",
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        subscription_start.start({
+            let mut transaction_updates = self
+                .transactions_service
+                .submit_and_watch_transaction(transaction.0, 16)
+                .await;
+            let me = self.clone();
+            let request_id = (request_id.0.to_owned(), request_id.1.clone());
+
+            async move {
+                me.requests_subscriptions
+                    .respond(
+                        &request_id.1,
+                        methods::Response::transaction_v1_broadcast((&operation_id).into())
+                            .to_json_response(&request_id.0),
+                    )
+                    .await;
+
+                loop {
+                    let next_message = messages_rx.next();
+                    futures::pin_mut!(next_message);
+                    match future::select(transaction_updates.next(), next_message).await {
+                        future::Either::Left((Some(_), _)) => {}
+                        future::Either::Left((None, _)) => {
+                            // The transactions service has stopped tracking the transaction.
+                            break;
+                        }
+                        future::Either::Right((
+                            (
+                                SubscriptionMessage::StopIfTransactionBroadcast { stop_request_id },
+                                confirmation_sender,
+                            ),
+                            _,
+                        )) => {
+                            // Note that the transactions service doesn't support cancelling a
+                            // transaction. Stopping the operation only stops tracking it, and the
+                            // transaction might still end up being included in the chain.
+                            me.requests_subscriptions
+                                .respond(
+                                    &stop_request_id.1,
+                                    methods::Response::transaction_v1_stop(())
+                                        .to_json_response(&stop_request_id.0),
+                                )
+                                .await;
+
+                            confirmation_sender.send();
+                            break;
+                        }
+                        future::Either::Right(_) => {
+                            // Silently discard the message.
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Handles a call to [`methods::MethodCall::transaction_v1_stop`].
+    pub(super) async fn transaction_v1_stop(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        operation_id: &str,
+    ) {
+        // Stopping the operation is done by sending a message to it.
+        // The task dedicated to this operation will receive the message, send a response to
+        // the JSON-RPC client, then shut down.
+        let stop_message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                operation_id,
+                SubscriptionMessage::StopIfTransactionBroadcast {
+                    stop_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                },
+            )
+            .await;
+
+        // As required by the specification, an error is returned if the operation doesn't exist.
+        if stop_message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    json_rpc::parse::build_error_response(
+                        request_id.0,
+                        json_rpc::parse::ErrorResponse::InvalidParams,
+                        None,
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::transaction_unstable_unwatch`] or to
+    /// [`methods::MethodCall::transactionWatch_v1_unwatch`].
     pub(super) async fn transaction_unstable_unwatch(
         self: &Arc<Self>,
         request_id: (&str, &requests_subscriptions::RequestId),
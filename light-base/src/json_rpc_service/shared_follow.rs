@@ -0,0 +1,542 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Follow state shared between all the `chainHead_follow` subscriptions of a chain that have
+//! requested runtime updates.
+//!
+//! Rather than each `chainHead_follow` subscription subscribing to the runtime service and
+//! pinning blocks on its own, a single subscription to the runtime service is shared between all
+//! the subscriptions of all the JSON-RPC clients of the chain. The tree of blocks is tracked
+//! once, and each block is pinned at most once in the runtime service. See
+//! [`smoldot::json_rpc::shared_follow`] for details about how blocks are pinned.
+//!
+//! The subscription to the runtime service is only started when the shared follow state is
+//! first needed.
+//!
+//! Followers whose notifications channel is full are removed when the next notification is
+//! dispatched. Followers that have been destroyed are removed immediately. Removing a follower
+//! releases all the blocks that it has pinned, and causes the corresponding `chainHead_follow`
+//! subscription to generate a `stop` event.
+
+use super::ChainHeadPinnedBlocks;
+use crate::{platform::Platform, runtime_service};
+
+use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use core::{iter, num::NonZeroUsize};
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use smoldot::{executor, header, json_rpc::shared_follow};
+
+/// Number of notifications that can be queued for each follower. A follower whose queue is full
+/// when a new notification needs to be reported is removed.
+const FOLLOWER_QUEUE_SIZE: usize = 32;
+
+/// Configuration for a [`SharedFollow`].
+pub struct Config<TPlat: Platform> {
+    /// Name of the chain, for logging purposes.
+    ///
+    /// > **Note**: This name will be directly printed out. Any special character should already
+    /// >           have been filtered out from this name.
+    pub log_name: String,
+
+    /// Closure that spawns background tasks.
+    pub tasks_executor: Box<dyn FnMut(String, future::BoxFuture<'static, ()>) + Send>,
+
+    /// Service that the blocks are pinned in.
+    pub runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+
+    /// Maximum number of blocks that each `chainHead_follow` subscription can keep pinned.
+    pub max_pinned_blocks: NonZeroUsize,
+}
+
+/// See [the module-level documentation](..).
+pub struct SharedFollow<TPlat: Platform> {
+    /// See [`Config::runtime_service`].
+    runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+
+    /// State shared with the background task.
+    guarded: Arc<Mutex<Guarded>>,
+
+    /// Sending side of the channel towards the background task.
+    to_background: mpsc::UnboundedSender<ToBackground>,
+
+    /// See [`Config::max_pinned_blocks`].
+    max_pinned_blocks: NonZeroUsize,
+
+    /// Handle to abort the background task.
+    background_task_abort: future::AbortHandle,
+}
+
+struct Guarded {
+    /// State of the tree of blocks. `None` if the subscription to the runtime service hasn't
+    /// been started yet or is being restarted.
+    canonical: Option<Canonical>,
+
+    /// Event notified when [`Guarded::canonical`] becomes `Some`.
+    ready: event_listener::Event,
+}
+
+struct Canonical {
+    /// Identifier of the subscription to the runtime service under which the blocks are pinned.
+    subscription_id: runtime_service::SubscriptionId,
+
+    /// Runtime of the current finalized block.
+    finalized_block_runtime: Result<executor::CoreVersion, runtime_service::RuntimeError>,
+
+    /// Tree of blocks and followers. The value of
+    /// [`runtime_service::BlockNotification::is_new_best`] is meaningless, and
+    /// [`shared_follow::SharedFollow::best_block_hash`] should be used instead.
+    tree: shared_follow::SharedFollow<runtime_service::BlockNotification, Follower>,
+}
+
+struct Follower {
+    /// Identifier of the `chainHead_follow` JSON-RPC subscription. Used only for debugging
+    /// purposes.
+    json_rpc_subscription_id: String,
+
+    /// Sending side of [`Subscription::notifications`].
+    notifications_tx: mpsc::Sender<runtime_service::Notification>,
+}
+
+/// Message sent to the background task.
+enum ToBackground {
+    /// Start the subscription to the runtime service, if it isn't started yet.
+    Start,
+    /// The given follower has been destroyed.
+    RemoveFollower(FollowerId),
+}
+
+/// Identifier of a follower. Can be copied and sent around freely.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FollowerId {
+    id: shared_follow::FollowerId,
+    runtime_subscription_id: runtime_service::SubscriptionId,
+}
+
+/// Return value of [`SharedFollow::subscribe`]. Mirrors [`runtime_service::SubscribeAll`].
+pub struct SubscribeAll {
+    /// SCALE-encoded header of the finalized block at the time of the subscription.
+    pub finalized_block_scale_encoded_header: Vec<u8>,
+
+    /// Runtime of the finalized block at the time of the subscription.
+    pub finalized_block_runtime: Result<executor::CoreVersion, runtime_service::RuntimeError>,
+
+    /// List of all known non-finalized blocks at the time of subscription, ordered so that
+    /// parents are always found before their children.
+    pub non_finalized_blocks_ancestry_order: Vec<runtime_service::BlockNotification>,
+
+    /// Notifications about the follower.
+    pub new_blocks: Subscription,
+}
+
+/// Notifications about a follower. The follower is removed when this object is destroyed.
+pub struct Subscription {
+    id: FollowerId,
+    notifications: mpsc::Receiver<runtime_service::Notification>,
+    to_background: mpsc::UnboundedSender<ToBackground>,
+}
+
+impl Subscription {
+    /// Returns the next notification, or `None` if the follower has been removed.
+    pub async fn next(&mut self) -> Option<runtime_service::Notification> {
+        self.notifications.next().await
+    }
+
+    /// Returns the identifier of the follower.
+    pub fn id(&self) -> FollowerId {
+        self.id
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // The background task is only ever stopped when the `SharedFollow` is destroyed, in which
+        // case there's nothing to release anyway.
+        let _ = self
+            .to_background
+            .unbounded_send(ToBackground::RemoveFollower(self.id));
+    }
+}
+
+impl<TPlat: Platform> SharedFollow<TPlat> {
+    /// Initializes a new [`SharedFollow`] and spawns its background task.
+    pub fn new(mut config: Config<TPlat>) -> Self {
+        let log_target = format!("chain-head-follow-{}", config.log_name);
+
+        let guarded = Arc::new(Mutex::new(Guarded {
+            canonical: None,
+            ready: event_listener::Event::new(),
+        }));
+
+        let (to_background, from_foreground) = mpsc::unbounded();
+
+        let background_task_abort;
+        (config.tasks_executor)(log_target, {
+            let runtime_service = config.runtime_service.clone();
+            let guarded = guarded.clone();
+            let max_pinned_blocks = config.max_pinned_blocks;
+            let (abortable, abort) = future::abortable(async move {
+                run_background(runtime_service, guarded, max_pinned_blocks, from_foreground).await;
+            });
+            background_task_abort = abort;
+            abortable.map(|_| ()).boxed()
+        });
+
+        SharedFollow {
+            runtime_service: config.runtime_service,
+            guarded,
+            to_background,
+            max_pinned_blocks: config.max_pinned_blocks,
+            background_task_abort,
+        }
+    }
+
+    /// Returns the number of blocks that are currently pinned, for debugging purposes.
+    pub async fn pinned_blocks(&self) -> ChainHeadPinnedBlocks {
+        let guarded = self.guarded.lock().await;
+        let Some(canonical) = guarded.canonical.as_ref() else {
+            return ChainHeadPinnedBlocks {
+                num_pinned_blocks: 0,
+                max_pinned_blocks_per_subscription: self.max_pinned_blocks,
+                subscriptions: Vec::new(),
+            };
+        };
+
+        ChainHeadPinnedBlocks {
+            num_pinned_blocks: canonical.tree.num_pinned_blocks(),
+            max_pinned_blocks_per_subscription: self.max_pinned_blocks,
+            subscriptions: canonical
+                .tree
+                .followers()
+                .map(|(_, follower, num_pinned)| {
+                    (follower.json_rpc_subscription_id.clone(), num_pinned)
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds a new follower. Waits until the state is known if necessary.
+    ///
+    /// All the blocks that are reported are considered as pinned by the new follower.
+    ///
+    /// Returns `None` if the tree of blocks contains more blocks than a follower can keep
+    /// pinned, in which case the `chainHead_follow` subscription should immediately stop.
+    ///
+    /// The identifier of the `chainHead_follow` JSON-RPC subscription is used only for
+    /// debugging purposes.
+    pub async fn subscribe(&self, json_rpc_subscription_id: &str) -> Option<SubscribeAll> {
+        let mut guarded = self.wait_ready().await;
+        let canonical = guarded.canonical.as_mut().unwrap();
+
+        let (notifications_tx, notifications) = mpsc::channel(FOLLOWER_QUEUE_SIZE);
+        let id = canonical
+            .tree
+            .add_follower(Follower {
+                json_rpc_subscription_id: json_rpc_subscription_id.to_owned(),
+                notifications_tx,
+            })
+            .ok()?;
+
+        let best_block_hash = *canonical.tree.best_block_hash();
+        Some(SubscribeAll {
+            finalized_block_scale_encoded_header: canonical
+                .tree
+                .finalized_block_user_data()
+                .scale_encoded_header
+                .clone(),
+            finalized_block_runtime: canonical.finalized_block_runtime.clone(),
+            non_finalized_blocks_ancestry_order: canonical
+                .tree
+                .non_finalized_blocks_ancestry_order()
+                .map(|(hash, block)| runtime_service::BlockNotification {
+                    is_new_best: *hash == best_block_hash,
+                    ..block.clone()
+                })
+                .collect(),
+            new_blocks: Subscription {
+                id: FollowerId {
+                    id,
+                    runtime_subscription_id: canonical.subscription_id,
+                },
+                notifications,
+                to_background: self.to_background.clone(),
+            },
+        })
+    }
+
+    /// Removes the pin of the given follower on the given block. Unpins the block from the
+    /// runtime service if nothing else has it pinned.
+    ///
+    /// Does nothing if the follower has already been removed or doesn't have this block pinned.
+    pub async fn unpin_block(&self, follower: FollowerId, hash: &[u8; 32]) {
+        let mut guarded = self.guarded.lock().await;
+        let Some(canonical) = guarded
+            .canonical
+            .as_mut()
+            .filter(|c| c.subscription_id == follower.runtime_subscription_id)
+        else {
+            return;
+        };
+
+        if let Ok(true) = canonical.tree.unpin_block(follower.id, hash) {
+            drop(guarded);
+            self.runtime_service
+                .unpin_block(follower.runtime_subscription_id, hash)
+                .await;
+        }
+    }
+
+    /// Returns a runtime lock for the given block, or `None` if the follower doesn't have this
+    /// block pinned anymore or has been removed.
+    pub async fn pinned_block_runtime_lock(
+        &self,
+        follower: FollowerId,
+        hash: &[u8; 32],
+    ) -> Option<runtime_service::RuntimeLock<TPlat>> {
+        // An additional reference to the block is held while the runtime lock is obtained, in
+        // order to guarantee that the block isn't unpinned in the meanwhile. This way, the
+        // shared state isn't locked while waiting.
+        {
+            let mut guarded = self.guarded.lock().await;
+            let canonical = guarded
+                .canonical
+                .as_mut()
+                .filter(|c| c.subscription_id == follower.runtime_subscription_id)?;
+            if !canonical.tree.acquire_block(follower.id, hash) {
+                return None;
+            }
+        }
+
+        let runtime_lock = self
+            .runtime_service
+            .pinned_block_runtime_lock(follower.runtime_subscription_id, hash)
+            .await
+            .ok();
+
+        // If the subscription to the runtime service has been reset in the meanwhile, the block
+        // has been implicitly unpinned.
+        let mut guarded = self.guarded.lock().await;
+        if let Some(canonical) = guarded
+            .canonical
+            .as_mut()
+            .filter(|c| c.subscription_id == follower.runtime_subscription_id)
+        {
+            if canonical.tree.release_block(hash) {
+                drop(guarded);
+                self.runtime_service
+                    .unpin_block(follower.runtime_subscription_id, hash)
+                    .await;
+            }
+        }
+
+        runtime_lock
+    }
+
+    /// Starts the subscription to the runtime service if necessary, and waits until the state
+    /// is known.
+    async fn wait_ready(&self) -> futures::lock::MutexGuard<'_, Guarded> {
+        loop {
+            let guarded = self.guarded.lock().await;
+            if guarded.canonical.is_some() {
+                return guarded;
+            }
+
+            let wait_fut = guarded.ready.listen();
+            drop(guarded);
+            let _ = self.to_background.unbounded_send(ToBackground::Start);
+            wait_fut.await;
+        }
+    }
+}
+
+impl<TPlat: Platform> Drop for SharedFollow<TPlat> {
+    fn drop(&mut self) {
+        self.background_task_abort.abort();
+    }
+}
+
+/// Runs the task that keeps the shared follow state up to date. Never returns.
+async fn run_background<TPlat: Platform>(
+    runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+    guarded: Arc<Mutex<Guarded>>,
+    max_pinned_blocks: NonZeroUsize,
+    mut from_foreground: mpsc::UnboundedReceiver<ToBackground>,
+) {
+    // Nothing is done until the state is needed for the first time.
+    loop {
+        match from_foreground.next().await {
+            Some(ToBackground::Start) => break,
+            Some(ToBackground::RemoveFollower(_)) => {}
+            // The `SharedFollow` has been destroyed.
+            None => return,
+        }
+    }
+
+    loop {
+        // The maximum number of pinned blocks is ignored, as the limit is instead enforced
+        // individually for each follower.
+        let mut subscribe_all = runtime_service
+            .subscribe_all(
+                "chainHead_follow",
+                32,
+                NonZeroUsize::new(usize::max_value()).unwrap(),
+            )
+            .await;
+        let subscription_id = subscribe_all.new_blocks.id();
+
+        // Headers have been verified by the sync service and are always valid.
+        let finalized_block_header = header::decode(
+            &subscribe_all.finalized_block_scale_encoded_header,
+            runtime_service.block_number_bytes(),
+        )
+        .unwrap();
+        let finalized_block_parent_hash = *finalized_block_header.parent_hash;
+
+        let mut tree = shared_follow::SharedFollow::new(shared_follow::Config {
+            finalized_block_hash: header::hash_from_scale_encoded_header(
+                &subscribe_all.finalized_block_scale_encoded_header,
+            ),
+            finalized_block_user_data: runtime_service::BlockNotification {
+                is_new_best: false,
+                scale_encoded_header: subscribe_all.finalized_block_scale_encoded_header,
+                parent_hash: finalized_block_parent_hash,
+                new_runtime: None,
+                new_runtime_code_hash: None,
+            },
+            max_pinned_blocks_per_follower: max_pinned_blocks,
+        });
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+            let is_new_best = block.is_new_best;
+            tree.add_block(hash, block.parent_hash, block, is_new_best);
+        }
+
+        {
+            let mut guarded_lock = guarded.lock().await;
+            guarded_lock.canonical = Some(Canonical {
+                subscription_id,
+                finalized_block_runtime: subscribe_all.finalized_block_runtime,
+                tree,
+            });
+            guarded_lock.ready.notify(usize::max_value());
+        }
+
+        loop {
+            let event = {
+                let next_notification = subscribe_all.new_blocks.next();
+                let next_message = from_foreground.next();
+                futures::pin_mut!(next_notification, next_message);
+                match future::select(next_notification, next_message).await {
+                    future::Either::Left((notification, _)) => either::Left(notification),
+                    future::Either::Right((message, _)) => either::Right(message),
+                }
+            };
+
+            let mut guarded_lock = guarded.lock().await;
+            let canonical = guarded_lock.canonical.as_mut().unwrap();
+
+            // List of blocks that are no longer pinned by anything and that must be unpinned
+            // from the runtime service.
+            let mut to_unpin = Vec::new();
+
+            match event {
+                either::Left(None) => break,
+                either::Right(None) => return,
+                either::Right(Some(ToBackground::Start)) => {}
+                either::Right(Some(ToBackground::RemoveFollower(follower))) => {
+                    if follower.runtime_subscription_id == subscription_id {
+                        if let Some((_, unpinned)) = canonical.tree.remove_follower(follower.id) {
+                            to_unpin.extend(unpinned);
+                        }
+                    }
+                }
+                either::Left(Some(notification)) => {
+                    match &notification {
+                        runtime_service::Notification::Block(block) => {
+                            let hash =
+                                header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                            // Followers that can't pin any more block are removed by the state
+                            // machine, which drops their notifications channel.
+                            let removed = canonical.tree.add_block(
+                                hash,
+                                block.parent_hash,
+                                block.clone(),
+                                block.is_new_best,
+                            );
+                            to_unpin.extend(removed.unpinned_blocks);
+                        }
+                        runtime_service::Notification::Finalized {
+                            hash,
+                            best_block_hash,
+                            ..
+                        } => {
+                            let finalized = canonical.tree.finalize(*hash, *best_block_hash);
+
+                            // The runtime of the new finalized block is the one of the last
+                            // newly-finalized block that has modified it, if any.
+                            if let Some(new_runtime) = finalized
+                                .newly_finalized_blocks
+                                .iter()
+                                .map(|(_, block)| block)
+                                .chain(iter::once(canonical.tree.finalized_block_user_data()))
+                                .filter_map(|block| block.new_runtime.as_ref())
+                                .next_back()
+                            {
+                                canonical.finalized_block_runtime = new_runtime.clone();
+                            }
+
+                            to_unpin.extend(finalized.unpinned_blocks);
+                        }
+                        runtime_service::Notification::BestBlockChanged { hash } => {
+                            canonical.tree.set_best_block(*hash);
+                        }
+                    }
+
+                    // Dispatch the notification to each follower, and gather the list of
+                    // followers whose channel is full or closed.
+                    let to_remove = canonical
+                        .tree
+                        .followers_mut()
+                        .filter_map(|(id, follower)| {
+                            follower
+                                .notifications_tx
+                                .try_send(notification.clone())
+                                .is_err()
+                                .then_some(id)
+                        })
+                        .collect::<Vec<_>>();
+                    for id in to_remove {
+                        let (_, unpinned) = canonical.tree.remove_follower(id).unwrap();
+                        to_unpin.extend(unpinned);
+                    }
+                }
+            }
+
+            // The blocks are unpinned from the runtime service after the shared state has been
+            // unlocked. Since they are no longer pinned by anything, nothing can access them in
+            // the meanwhile.
+            drop(guarded_lock);
+            for hash in to_unpin {
+                runtime_service.unpin_block(subscription_id, &hash).await;
+            }
+        }
+
+        // The runtime service subscription is dead, which means that all its pinned blocks
+        // have been implicitly unpinned. Destroying the followers closes their notification
+        // channels, which causes them to generate a `stop` event.
+        guarded.lock().await.canonical = None;
+    }
+}
@@ -62,9 +62,15 @@
 //! Responses can be pulled by calling the [`AddChainSuccess::json_rpc_responses`] that is returned
 //! after a chain has been added.
 //!
+//! Alternatively, the [`rpc`] module provides a strongly-typed JSON-RPC client that takes care of
+//! building requests and matching them with their responses.
+//!
 // TODO: talk about the fact that a randomness environment is assumed?
 
-#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#![cfg_attr(
+    not(any(test, feature = "std", feature = "tokio", feature = "wasi")),
+    no_std
+)]
 #![forbid(unsafe_code)]
 #![deny(rustdoc::broken_intra_doc_links)]
 // TODO: the `unused_crate_dependencies` lint is disabled because of dev-dependencies, see <https://github.com/rust-lang/rust/issues/95513>
@@ -72,19 +78,45 @@
 
 extern crate alloc;
 
-use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
-use core::{num::NonZeroU32, pin::Pin};
-use futures::{channel::oneshot, prelude::*};
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::{String, ToString as _},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    cmp, fmt, iter, mem,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    pin::Pin,
+    time::Duration,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+};
 use hashbrown::{hash_map::Entry, HashMap};
 use itertools::Itertools as _;
 use smoldot::{
     chain::{self, chain_information},
-    chain_spec, header,
+    chain_spec, executor,
+    finality::{grandpa::equivocation as grandpa_equivocation, justification},
+    header,
     informant::HashDisplay,
+    json_rpc,
     libp2p::{connection, multiaddr, peer_id},
+    metadata,
+    network::protocol,
+    sync, trie,
 };
 
+mod beefy;
+mod checkpoint;
 mod database;
+mod equivocations;
 mod json_rpc_service;
 mod network_service;
 mod runtime_service;
@@ -92,10 +124,18 @@ mod sync_service;
 mod transactions_service;
 mod util;
 
+pub mod database_storage;
+pub mod dns;
 pub mod platform;
+pub mod rpc;
 
-pub use json_rpc_service::HandleRpcError;
+pub use beefy::FinalityProof as BeefyFinalityProof;
+pub use checkpoint::DecodeError as CheckpointDecodeError;
+pub use database::{compact_database, DatabaseDelta};
+pub use json_rpc_service::{ChainHeadPinnedBlocks, HandleRpcError};
 pub use peer_id::PeerId;
+pub use sync::all::{BestBlockPolicy, SyncMode};
+pub use sync_service::SyncProgress;
 
 /// Configuration for a client.
 ///
@@ -113,6 +153,133 @@ pub struct ClientConfig {
     /// Value returned when a JSON-RPC client requests the version of the client. Reasonable value
     /// is `env!("CARGO_PKG_VERSION")`.
     pub system_version: String,
+
+    /// If `Some`, maximum number of bytes per second that the client sends over the peer-to-peer
+    /// networks of all the chains combined. Up to one second worth of data can be sent in a burst.
+    ///
+    /// Note that limiting the bandwidth too much can prevent the client from functioning
+    /// properly, as requests might time out.
+    pub max_upload_bps: Option<NonZeroU32>,
+
+    /// If `Some`, maximum number of bytes per second that the client receives from the
+    /// peer-to-peer networks of all the chains combined. Up to one second worth of data can be
+    /// received in a burst.
+    ///
+    /// Note that limiting the bandwidth too much can prevent the client from functioning
+    /// properly, as requests might time out.
+    pub max_download_bps: Option<NonZeroU32>,
+
+    /// If `Some`, Ed25519 private key to use as the identity of the client on the peer-to-peer
+    /// networks of all the chains. If `None`, a random identity is generated for each chain
+    /// every time the client starts.
+    ///
+    /// Using a fixed identity makes it possible for the full nodes to recognize the client
+    /// across restarts, for example in order to put it in their list of reserved peers. Note,
+    /// however, that this also makes it possible to link together the activities of the client
+    /// on the various chains.
+    pub libp2p_key: Option<[u8; 32]>,
+
+    /// Method used in order to execute the runtimes of the chains.
+    ///
+    /// See [`WasmExecution`] for more information.
+    pub wasm_execution: WasmExecution,
+
+    /// If `Some`, storage where the client loads and periodically stores the database of each
+    /// chain. See [`DatabaseStorageConfig`].
+    pub database_storage: Option<DatabaseStorageConfig>,
+
+    /// Method used in order to resolve the domain names found in the multiaddresses of the
+    /// peers, such as `/dns/example.com/tcp/30333`.
+    ///
+    /// See [`DnsConfig`] for more information.
+    pub dns: DnsConfig,
+
+    /// Number of background tasks, per chain, that verify the signatures of block headers ahead
+    /// of time and in parallel while catching up with the head of a chain. The blocks are still
+    /// verified and added to the chain in order.
+    ///
+    /// This is only beneficial if the tasks spawned through [`ClientConfig::tasks_spawner`] can
+    /// run on multiple threads. If 0, the signatures are verified one by one by the syncing task.
+    pub header_verification_workers: usize,
+
+    /// If `Some`, the storage proof, call proof, and blocks requests that are still in progress
+    /// after the given percentile, between 1 and 100, of the duration of the recent requests of
+    /// the same kind are also sent to a second peer, and the first valid answer is used. Failed
+    /// requests count as having taken as long as their timeout. This reduces the time it takes
+    /// to answer JSON-RPC requests when a peer is slow to respond, at the cost of sending more
+    /// requests.
+    ///
+    /// If `None`, a request is only sent to another peer after the previous one has failed.
+    pub requests_hedging_percentile: Option<u8>,
+
+    /// Maximum size, in bytes, that the Yamux receive window of each substream of the
+    /// peer-to-peer connections can grow to.
+    ///
+    /// A larger window lets peers send data faster on connections with a high latency, at the
+    /// cost of potentially buffering more data in memory. Values inferior to 256kiB are treated
+    /// as 256kiB. Light clients are often constrained in memory, and a reasonable value is 4MiB.
+    pub yamux_max_receive_window: u64,
+
+    /// Maximum number of substreams that each peer can have simultaneously opened on a
+    /// connection.
+    ///
+    /// If `None`, a limit is derived from the number of protocols of the chain.
+    pub max_inbound_substreams: Option<usize>,
+}
+
+/// See [`ClientConfig::dns`].
+#[derive(Clone)]
+pub enum DnsConfig {
+    /// Multiaddresses containing a domain name are passed as-is to
+    /// [`platform::Platform::connect`], which is responsible for resolving them.
+    Platform,
+
+    /// Domain names are resolved by the client using the resolver of the operating system.
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    System,
+
+    /// Domain names are resolved by the client by sending DNS-over-HTTPS queries to the given
+    /// URL, for example `https://cloudflare-dns.com/dns-query`, using the given HTTPS client.
+    DnsOverHttps {
+        url: String,
+        client: Arc<dyn dns::doh::HttpsClient>,
+    },
+
+    /// Domain names are resolved by the client using the given resolver.
+    Custom(Arc<dyn dns::DnsResolver>),
+}
+
+/// See [`ClientConfig::database_storage`].
+#[derive(Clone)]
+pub struct DatabaseStorageConfig {
+    /// Storage where the databases are persisted.
+    ///
+    /// When a chain is added with an empty [`AddChainConfig::database_content`], its database
+    /// is loaded from this storage.
+    pub storage: Arc<dyn database_storage::DatabaseStorage>,
+
+    /// Minimum delay between two successive stores of the database of a chain.
+    ///
+    /// The database of a chain is only stored after its finalized block has changed, and is
+    /// stored one last time when the chain is removed. A reasonable value is 30 seconds.
+    pub store_interval: Duration,
+}
+
+/// See [`ClientConfig::wasm_execution`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WasmExecution {
+    /// The runtimes are executed by an interpreter. Preparing a runtime is fast, but executing
+    /// it is slow.
+    Interpreter,
+
+    /// The runtimes are compiled to native code, which makes runtime calls considerably faster
+    /// at the cost of a slower and more memory-hungry preparation of each runtime.
+    ///
+    /// Compiling is only supported on x86_64 platforms when the `std` feature is enabled. On
+    /// other platforms, the runtimes are executed by an interpreter, as with
+    /// [`WasmExecution::Interpreter`].
+    Compiled,
 }
 
 /// See [`Client::add_chain`].
@@ -133,6 +300,28 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
     /// reserves the right to break the format of this data at any point.
     pub database_content: &'a str,
 
+    /// Storage of the genesis block of the chain, for chain specifications that only contain
+    /// the hash of the root of the genesis storage trie rather than the genesis storage itself.
+    ///
+    /// Embedding the genesis storage in the chain specification can be impractical for chains
+    /// whose genesis storage is very large. An error is returned if the chain specification
+    /// already contains the genesis storage, or if the storage provided here doesn't match the
+    /// hash found in the chain specification.
+    ///
+    /// Pass `None` to use the chain specification as is.
+    pub genesis_storage: Option<GenesisStorage<'a>>,
+
+    /// Checkpoint that was generated by calling [`Client::export_checkpoint`] in the past.
+    ///
+    /// If the checkpoint describes a block more recent than both the checkpoint found in the
+    /// chain specification and the database in [`AddChainConfig::database_content`], the client
+    /// starts synchronizing from this block. Contrary to
+    /// [`AddChainConfig::database_content`], an error is returned if this data is invalid or
+    /// belongs to a different chain.
+    ///
+    /// Pass an empty string if no checkpoint is known.
+    pub checkpoint: &'a str,
+
     /// If [`AddChainConfig`] defines a parachain, contains the list of relay chains to choose
     /// from. Ignored if not a parachain.
     ///
@@ -144,13 +333,359 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
     /// For example: if user A adds a chain named "Kusama", then user B adds a different chain
     /// also named "Kusama", then user B adds a parachain whose relay chain is "Kusama", it would
     /// be wrong to connect to the "Kusama" created by user A.
+    ///
+    /// The relay chain of a parachain can itself be a parachain. If none of the chains of this
+    /// list matches the relay chain of the parachain, the parachains of these chains are
+    /// tried, then their own parachains, and so on.
     pub potential_relay_chains: TRelays,
 
     /// If `true`, then no JSON-RPC service is started for this chain. This saves up a lot of
     /// resources, but will cause all JSON-RPC requests targeting this chain to fail.
     pub disable_json_rpc: bool,
+
+    /// Maximum number of JSON-RPC requests that can be queued or processed at the same time and
+    /// whose response hasn't been pulled from [`JsonRpcResponses`] yet. Any additional request
+    /// passed to [`Client::json_rpc_request`] is refused.
+    ///
+    /// A reasonable value is 128. Ignored if [`AddChainConfig::disable_json_rpc`] is `true`.
+    pub json_rpc_max_pending_responses: NonZeroU32,
+
+    /// Maximum number of active JSON-RPC subscriptions. Any additional subscription is
+    /// immediately rejected.
+    ///
+    /// A reasonable value is 1024. Note that some JSON-RPC clients, such as the PolkadotJS UI,
+    /// are very heavy in terms of subscriptions. Ignored if [`AddChainConfig::disable_json_rpc`]
+    /// is `true`.
+    pub json_rpc_max_subscriptions: u32,
+
+    /// Maximum number of requests in a batch of JSON-RPC requests. Batches that contain more
+    /// requests are refused. If 0, batches of requests are always refused.
+    ///
+    /// Each request of a batch counts towards
+    /// [`AddChainConfig::json_rpc_max_pending_responses`]. A reasonable value is 64. Ignored if
+    /// [`AddChainConfig::disable_json_rpc`] is `true`.
+    pub json_rpc_max_batch_size: u32,
+
+    /// Maximum number of JSON-RPC requests of this chain that can be processed at the same time,
+    /// not including the requests that can be answered without accessing the network. The other
+    /// requests wait in a queue.
+    ///
+    /// Each chain has its own JSON-RPC service, and this limit only applies to the requests sent
+    /// to this chain. A reasonable value is 24. Ignored if [`AddChainConfig::disable_json_rpc`]
+    /// is `true`.
+    pub json_rpc_max_parallel_requests: NonZeroU32,
+
+    /// Maximum number of JSON-RPC requests of this chain that can be answered without accessing
+    /// the network, such as `system_health` or `chainHead_v1_unpin`, that can be processed at
+    /// the same time.
+    ///
+    /// These requests are processed separately from the ones counted in
+    /// [`AddChainConfig::json_rpc_max_parallel_requests`], so that they are answered quickly even
+    /// when the other requests are slow. A reasonable value is 4. Ignored if
+    /// [`AddChainConfig::disable_json_rpc`] is `true`.
+    pub json_rpc_max_parallel_cheap_requests: NonZeroU32,
+
+    /// Maximum number of blocks that each `chainHead_follow` JSON-RPC subscription can keep
+    /// pinned.
+    ///
+    /// When this limit is reached and a new block must be reported, the subscription generates
+    /// a `stop` event. A subscription started while more blocks than this limit are known
+    /// generates a `stop` event immediately.
+    ///
+    /// A reasonable value is 32. Use [`Client::chain_head_pinned_blocks`] in order to inspect
+    /// the number of blocks currently pinned. Ignored if [`AddChainConfig::disable_json_rpc`] is
+    /// `true`.
+    ///
+    /// > **Note**: Identical chains share their `chainHead_follow` state. This field is ignored
+    /// >           if an identical chain has already been added before.
+    pub max_pinned_blocks: NonZeroUsize,
+
+    /// Maximum number of blocks below the current finalized block that `chain_getBlockHash` can
+    /// look up.
+    ///
+    /// Light clients don't store the hashes of past blocks. When `chain_getBlockHash` is called
+    /// with the number of a finalized block that isn't known locally, the headers of the
+    /// ancestors of the finalized block are downloaded from the peer-to-peer network, up to the
+    /// requested block. The verified hashes are cached.
+    ///
+    /// A reasonable value is 16384. Ignored if [`AddChainConfig::disable_json_rpc`] is `true`.
+    pub max_block_hash_lookup_depth: u32,
+
+    /// List of JSON-RPC servers that JSON-RPC requests are forwarded to when they can't be
+    /// answered by the light client itself. Each entry is either a `ws://` or `wss://` URL
+    /// without a path, such as `wss://example.com` or `ws://[::1]:9944`, or a multiaddress such
+    /// as `/dns/example.com/tcp/443/wss`. Connections are opened through
+    /// [`platform::Platform::connect`], and `wss://` endpoints are thus only supported by
+    /// platforms that support `/wss` multiaddresses.
+    ///
+    /// Requests are forwarded if they target a block that is more than 256 blocks below the
+    /// current best block or that is unknown to the light client, or if they call a read-only
+    /// JSON-RPC function that the light client doesn't implement. Responses obtained this way
+    /// aren't verified, and are annotated with an `"untrusted": true` field.
+    ///
+    /// Subscriptions are never forwarded. Leave empty in order to never forward requests.
+    /// Ignored if [`AddChainConfig::disable_json_rpc`] is `true`.
+    pub archive_fallback_endpoints: Vec<String>,
+
+    /// If `Some`, the client periodically checks whether the chain is connected to at least one
+    /// peer and, if it isn't, adds back the bootnodes of the chain specification and the
+    /// reserved nodes to the list of nodes to connect to. If
+    /// [`AddChainConfig::reserved_only`] is `true`, only the reserved nodes are added back. See
+    /// [`AutoRecoverConfig`].
+    ///
+    /// The services of the chain are kept alive during this process. In other words, JSON-RPC
+    /// subscriptions remain active and don't need to be re-issued.
+    pub auto_recover: Option<AutoRecoverConfig>,
+
+    /// List of nodes that the client connects to in priority, in addition to the bootnodes of
+    /// the chain specification. Each entry is a multiaddress ending with `/p2p/` followed by the
+    /// identity of the node, for example `/dns/example.com/tcp/30334/ws/p2p/12D3KooW...`.
+    ///
+    /// Reserved nodes are typically nodes belonging to the infrastructure of the API user.
+    /// Everything that they send is verified in the same way as for any other node. More
+    /// reserved nodes can later be added with [`Client::add_reserved_peer`].
+    pub reserved_nodes: Vec<String>,
+
+    /// If `true`, the client only connects to the nodes in [`AddChainConfig::reserved_nodes`] or
+    /// added with [`Client::add_reserved_peer`]. The bootnodes of the chain specification are
+    /// ignored, and no peer discovery is performed.
+    ///
+    /// > **Note**: Identical chains share their networking. This field and
+    /// >           [`AddChainConfig::network_out_slots`] are ignored if an identical chain
+    /// >           has already been added before.
+    pub reserved_only: bool,
+
+    /// Maximum number of full nodes that the client tries to stay connected to and receive
+    /// block announces from. Reserved nodes count towards this limit.
+    ///
+    /// A reasonable value is 4.
+    pub network_out_slots: u32,
+
+    /// If `true`, the client answers the GrandPa warp sync requests of other peers using the
+    /// warp sync proof that it has itself downloaded and verified.
+    ///
+    /// Has no effect for parachains, or if the chain has started from a recent enough checkpoint
+    /// that no warp syncing was necessary.
+    ///
+    /// > **Note**: Warp sync responses can weigh several megabytes. Enabling this option can
+    /// >           consume a lot of bandwidth.
+    ///
+    /// > **Note**: Identical chains share their networking. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub serve_warp_sync: bool,
+
+    /// If `true`, the client opens BEEFY gossiping substreams with its peers and verifies the
+    /// BEEFY finality proofs that they send. See [`Client::subscribe_beefy_finality`].
+    ///
+    /// Has no effect for parachains, or for chains that don't use GrandPa. If `false`, no BEEFY
+    /// finality proof is ever reported.
+    ///
+    /// > **Note**: Identical chains share their networking. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub enable_beefy: bool,
+
+    /// Strategy to use in order to synchronize the chain once the GrandPa warp syncing is over.
+    ///
+    /// [`SyncMode::AllForks`] downloads and verifies all the forks of the chain, and is the
+    /// most appropriate for the vast majority of chains. [`SyncMode::Optimistic`] only follows
+    /// the best chain, and makes it possible to continue following the head of chains whose
+    /// finality is unreliable rather than being stuck at the latest finalized block. Setting the
+    /// `forks_retention_limit` of [`SyncMode::AllForks`] achieves a similar effect while still
+    /// tracking the forks of the chain as long as the limit isn't reached.
+    ///
+    /// Has no effect for parachains, or for chains that don't use GrandPa, which are always
+    /// synchronized optimistically.
+    ///
+    /// > **Note**: Identical chains share their synchronization. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub sync_mode: SyncMode,
+
+    /// If `Some`, and if the chain doesn't have any finality mechanism (in other words, doesn't
+    /// use GrandPa), the blocks that are at least this number of blocks below the best block
+    /// are considered as finalized. If `None`, the blocks of such chains are never finalized.
+    ///
+    /// This is a probabilistic finality: the deeper a block is, the less likely it is that it
+    /// gets reverted. Blocks finalized this way are reported like any other finalized block,
+    /// for example by the `chain_subscribeFinalizedHeads` JSON-RPC function.
+    ///
+    /// Has no effect for parachains, whose finality is provided by their relay chain.
+    ///
+    /// > **Note**: Identical chains share their synchronization. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub probabilistic_finality_depth: Option<NonZeroU64>,
+
+    /// Rule used to determine which block is reported as the best block of the chain before it
+    /// is finalized, for example by the `chain_subscribeNewHeads` JSON-RPC function.
+    ///
+    /// [`BestBlockPolicy::MostPrimarySlots`] is the rule used by Substrate and is appropriate
+    /// for the vast majority of chains. [`BestBlockPolicy::FollowFinalityOnly`] always reports
+    /// the latest finalized block as the best block, which is more conservative.
+    ///
+    /// The best block of a parachain is derived from the best block of its relay chain, and
+    /// only [`BestBlockPolicy::FollowFinalityOnly`] has an effect for parachains. This field
+    /// has no effect for chains that don't use GrandPa, which are always synchronized
+    /// optimistically.
+    ///
+    /// > **Note**: Identical chains share their synchronization. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub best_block_policy: BestBlockPolicy,
+
+    /// Maximum number of runtime calls whose call proof and output are kept in memory.
+    ///
+    /// Runtime calls performed against a block, for example by the `state_call` JSON-RPC
+    /// function, require downloading a call proof from a full node. When an identical call is
+    /// performed against the same block, the call proof and output found in this cache are
+    /// re-used instead. Increasing this value makes it possible to answer bursts of identical
+    /// requests instantly, at the cost of memory usage. If 0, no cache is used.
+    ///
+    /// > **Note**: Identical chains share their runtime service. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub runtime_call_cache_size: usize,
+
+    /// Maximum amount of fuel that each runtime call is allowed to consume.
+    ///
+    /// Every instruction executed by the runtime consumes a deterministic amount of fuel. Runtime
+    /// calls that reach this limit, for example because of a bug in the runtime that makes it
+    /// loop forever, are interrupted and return [`RuntimeCallError::ExecutionTimeout`].
+    ///
+    /// If `None`, runtime calls can run for an unlimited amount of time. Counting the fuel slows
+    /// down the execution of the runtime, and is only done if a limit is set.
+    ///
+    /// > **Note**: This limit is only enforced when [`ClientConfig::wasm_execution`] is
+    /// >           [`WasmExecution::Interpreter`].
+    ///
+    /// > **Note**: Identical chains share their runtime service. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub runtime_call_fuel_limit: Option<u64>,
+
+    /// Maximum number of compiled runtimes that are kept in memory after they stop being used
+    /// by the blocks of the chain.
+    ///
+    /// Runtime calls performed against old blocks, for example by the `chainHead_v1_call`
+    /// JSON-RPC function, might require a runtime that is different from the current one. This
+    /// runtime is then downloaded and compiled. Keeping it in memory avoids compiling it again
+    /// for the next call, at the cost of memory usage.
+    ///
+    /// > **Note**: Identical chains share their runtime service. This field is ignored if an
+    /// >           identical chain has already been added before.
+    pub max_cached_runtimes: NonZeroUsize,
+
+    /// If `true`, transactions submitted with [`Client::submit_transaction`] or the JSON-RPC
+    /// functions are validated against the best block, by calling the
+    /// `TaggedTransactionQueue_validate_transaction` runtime function, before being broadcast to
+    /// the peer-to-peer network. Transactions that are invalid are reported as
+    /// [`TransactionStatus::Invalid`] and aren't broadcast.
+    ///
+    /// If `false`, transactions are broadcast immediately without being validated. This saves
+    /// downloading a call proof for each transaction, but invalid transactions are only
+    /// detected by the full nodes and are never reported as invalid, and their mortality isn't
+    /// tracked.
+    ///
+    /// > **Note**: Identical chains share their transactions service. This field is ignored if
+    /// >           an identical chain has already been added before.
+    pub validate_transactions_locally: bool,
+
+    /// Callbacks called whenever the best block or the finalized block of the chain changes, or
+    /// whenever a runtime upgrade is finalized. See [`ChainEventHooks`].
+    ///
+    /// This is a more lightweight alternative to JSON-RPC subscriptions, and works even if
+    /// [`AddChainConfig::disable_json_rpc`] is `true`. Use `Default::default()` in order to not
+    /// register any hook.
+    pub event_hooks: ChainEventHooks,
+}
+
+/// See [`AddChainConfig::auto_recover`].
+#[derive(Debug, Clone)]
+pub struct AutoRecoverConfig {
+    /// Delay between two checks of the health of the chain when it is healthy. Also the delay
+    /// before the first re-connection attempt after the chain has been detected as having no
+    /// peer.
+    pub min_backoff: Duration,
+
+    /// Maximum delay between two re-connection attempts. The delay between two attempts is
+    /// doubled after each unsuccessful attempt until it reaches this value.
+    pub max_backoff: Duration,
+}
+
+/// See [`AddChainConfig::genesis_storage`].
+#[derive(Debug, Clone)]
+pub enum GenesisStorage<'a> {
+    /// List of keys and values of the genesis storage.
+    Items(Vec<(Vec<u8>, Vec<u8>)>),
+    /// JSON-encoded genesis storage, in the same format as the `genesis.raw` field of chain
+    /// specifications, for example `{"top":{"0x3a636f6465":"0x..."},"childrenDefault":{}}`.
+    Json(&'a str),
+}
+
+/// See [`AddChainConfig::event_hooks`].
+///
+/// The callbacks are called from within a background task and should return quickly. They stop
+/// being called when the chain is removed with [`Client::remove_chain`].
+#[derive(Default, Clone)]
+pub struct ChainEventHooks {
+    /// Called whenever the best block of the chain changes.
+    pub on_new_best_block: Option<Arc<dyn Fn(&BlockEvent) + Send + Sync>>,
+
+    /// Called whenever the finalized block of the chain changes. If multiple blocks are finalized
+    /// at once, only the highest one is reported.
+    pub on_finalized: Option<Arc<dyn Fn(&BlockEvent) + Send + Sync>>,
+
+    /// Called whenever a block whose runtime is different from the one of its parent is
+    /// finalized.
+    ///
+    /// Runtime upgrades are only reported once finalized, so that they are never reported for
+    /// blocks that are later discarded.
+    pub on_runtime_upgrade: Option<Arc<dyn Fn(&RuntimeVersionChange) + Send + Sync>>,
+}
+
+impl fmt::Debug for ChainEventHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainEventHooks")
+            .field("on_new_best_block", &self.on_new_best_block.is_some())
+            .field("on_finalized", &self.on_finalized.is_some())
+            .field("on_runtime_upgrade", &self.on_runtime_upgrade.is_some())
+            .finish()
+    }
+}
+
+/// Block reported to [`ChainEventHooks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockEvent {
+    /// Height of the block.
+    pub number: u64,
+
+    /// Hash of the block.
+    pub hash: [u8; 32],
+
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: Vec<u8>,
+}
+
+/// Change to the runtime of the finalized block of a chain. See
+/// [`Client::runtime_version_updates`] and [`ChainEventHooks::on_runtime_upgrade`].
+#[derive(Debug, Clone)]
+pub struct RuntimeVersionChange {
+    /// Version of the runtime before the change. `None` if the previous runtime is invalid.
+    pub previous_version: Option<executor::CoreVersion>,
+
+    /// Version of the runtime after the change. `None` if the new runtime is invalid.
+    pub new_version: Option<executor::CoreVersion>,
+
+    /// First block whose runtime is the new runtime.
+    ///
+    /// If the client has fallen behind and the exact block is unknown, contains the first
+    /// finalized block known to have the new runtime instead.
+    pub block: BlockEvent,
+
+    /// BLAKE2 hash of the `:code` storage item of the new runtime. `None` if the storage item
+    /// is empty.
+    pub code_hash: Option<[u8; 32]>,
 }
 
+/// Maximum number of levels of parachains between a chain found in
+/// [`AddChainConfig::potential_relay_chains`] and the relay chain of a parachain being added.
+const MAX_RELAY_CHAINS_NESTING_DEPTH: usize = 4;
+
 /// Chain registered in a [`Client`].
 //
 // Implementation detail: corresponds to indices within [`Client::public_api_chains`].
@@ -185,6 +720,39 @@ pub struct Client<TPlat: platform::Platform, TChain = ()> {
     /// Value to return when the `system_version` RPC is called. Should be set to the version of
     /// the final executable.
     system_version: String,
+
+    /// Limiter shared between all the network services. Built from
+    /// [`ClientConfig::max_upload_bps`].
+    network_upload_limiter: Option<Arc<network_service::BandwidthLimiter<TPlat>>>,
+
+    /// Limiter shared between all the network services. Built from
+    /// [`ClientConfig::max_download_bps`].
+    network_download_limiter: Option<Arc<network_service::BandwidthLimiter<TPlat>>>,
+
+    /// Value of [`ClientConfig::libp2p_key`].
+    libp2p_key: Option<[u8; 32]>,
+
+    /// Value of [`ClientConfig::wasm_execution`].
+    wasm_execution: WasmExecution,
+
+    /// Value of [`ClientConfig::database_storage`].
+    database_storage: Option<DatabaseStorageConfig>,
+
+    /// Resolver built from [`ClientConfig::dns`]. `None` if the domain names are resolved by the
+    /// platform.
+    dns_resolver: Option<Arc<dyn dns::DnsResolver>>,
+
+    /// Value of [`ClientConfig::header_verification_workers`].
+    header_verification_workers: usize,
+
+    /// Value of [`ClientConfig::requests_hedging_percentile`].
+    requests_hedging_percentile: Option<u8>,
+
+    /// Value of [`ClientConfig::yamux_max_receive_window`].
+    yamux_max_receive_window: u64,
+
+    /// Value of [`ClientConfig::max_inbound_substreams`].
+    max_inbound_substreams: Option<usize>,
 }
 
 struct PublicApiChain<TChain> {
@@ -198,6 +766,12 @@ struct PublicApiChain<TChain> {
     /// [`chain_spec::ChainSpec::id`]. Used in order to match parachains with relay chains.
     chain_spec_chain_id: String,
 
+    /// If the chain is a parachain, contains the chain that was picked as its relay chain
+    /// amongst [`AddChainConfig::potential_relay_chains`] (or their parachains) when the chain
+    /// was added. Reset to `None` when that relay chain is removed, as its [`ChainId`] can then
+    /// be reused for a different chain.
+    relay_chain_id: Option<ChainId>,
+
     /// Handle that sends requests to the JSON-RPC service that runs in the background.
     /// Destroying this handle also shuts down the service. `None` iff
     /// [`AddChainConfig::disable_json_rpc`] was `true` when adding the chain.
@@ -206,6 +780,17 @@ struct PublicApiChain<TChain> {
     /// Dummy channel. Nothing is ever sent on it, but the receiving side is stored in the
     /// [`JsonRpcResponses`] in order to detect when the chain has been removed.
     _public_api_chain_destroyed_tx: oneshot::Sender<()>,
+
+    /// Dummy channel similar to [`PublicApiChain::_public_api_chain_destroyed_tx`]. The
+    /// receiving side is held by the task that performs the automatic recovery of the chain.
+    /// `None` iff [`AddChainConfig::auto_recover`] was `None` when adding the chain.
+    _auto_recover_stop_tx: Option<oneshot::Sender<()>>,
+
+    /// Dummy channels similar to [`PublicApiChain::_public_api_chain_destroyed_tx`]. The
+    /// receiving sides are held by the futures and streams returned by the methods of [`Client`]
+    /// that access the services of the chain, such as [`Client::chain_health`], in order to
+    /// detect when the chain has been removed.
+    chain_removed_tx: Vec<oneshot::Sender<()>>,
 }
 
 /// Identifies a chain, so that multiple identical chains are de-duplicated.
@@ -249,6 +834,7 @@ struct ChainServices<TPlat: platform::Platform> {
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
     transactions_service: Arc<transactions_service::TransactionsService<TPlat>>,
+    shared_follow: Arc<json_rpc_service::shared_follow::SharedFollow<TPlat>>,
     // TODO: can be grabbed from the sync service instead
     block_number_bytes: usize,
 }
@@ -261,6 +847,7 @@ impl<TPlat: platform::Platform> Clone for ChainServices<TPlat> {
             sync_service: self.sync_service.clone(),
             runtime_service: self.runtime_service.clone(),
             transactions_service: self.transactions_service.clone(),
+            shared_follow: self.shared_follow.clone(),
             block_number_bytes: self.block_number_bytes,
         }
     }
@@ -312,6 +899,206 @@ impl JsonRpcResponses {
         self.inner = None;
         None
     }
+
+    /// Returns the maximum number of requests whose response hasn't been pulled yet. This is
+    /// the value of [`AddChainConfig::json_rpc_max_pending_responses`].
+    ///
+    /// Returns 0 if the chain has been removed.
+    pub fn capacity(&self) -> usize {
+        self.inner
+            .as_ref()
+            .map_or(0, |frontend| frontend.max_pending_requests())
+    }
+
+    /// Returns the number of requests that have been sent and whose response hasn't been pulled
+    /// with [`JsonRpcResponses::next`] yet.
+    ///
+    /// [`Client::json_rpc_request`] returns an error if this value is equal to
+    /// [`JsonRpcResponses::capacity`].
+    ///
+    /// Returns 0 if the chain has been removed.
+    pub fn len(&self) -> usize {
+        self.inner
+            .as_ref()
+            .map_or(0, |frontend| frontend.num_pending_requests())
+    }
+
+    /// Returns `true` if [`JsonRpcResponses::len`] is equal to 0.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Health of a chain. See [`Client::chain_health`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainHealth {
+    /// Number of peers of the peer-to-peer network of the chain that the client is connected to.
+    pub peers: usize,
+
+    /// `false` if the client believes that it is near the head of the chain, in other words that
+    /// the initial synchronization is finished. Identical to the `isSyncing` field of the
+    /// `system_health` JSON-RPC function.
+    pub is_syncing: bool,
+
+    /// Height of the current best block of the chain.
+    pub best_block_number: u64,
+
+    /// Hash of the current best block of the chain.
+    pub best_block_hash: [u8; 32],
+
+    /// Height of the current finalized block of the chain.
+    pub finalized_block_number: u64,
+
+    /// Hash of the current finalized block of the chain.
+    pub finalized_block_hash: [u8; 32],
+}
+
+/// Changes to the storage of a chain. See [`Client::subscribe_storage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageChangeSet {
+    /// Hash of the block whose storage has been queried.
+    pub block_hash: [u8; 32],
+
+    /// List of keys whose value has changed, with their new value. The value is `None` if the
+    /// key no longer has a value in the storage.
+    pub changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+/// Bandwidth used by the peer-to-peer network of a chain. See [`Client::network_bandwidth`].
+#[derive(Debug, Clone)]
+pub struct NetworkBandwidth {
+    /// Total number of bytes received since the chain has been added.
+    pub total_bytes_received: u64,
+
+    /// Total number of bytes sent since the chain has been added.
+    pub total_bytes_sent: u64,
+
+    /// Bandwidth used for each peer the client is currently connected to.
+    pub peers: Vec<PeerBandwidth>,
+}
+
+/// See [`NetworkBandwidth::peers`].
+#[derive(Debug, Clone)]
+pub struct PeerBandwidth {
+    /// Identity of the peer.
+    pub peer_id: PeerId,
+
+    /// Number of bytes received from this peer since the client has connected to it.
+    pub bytes_received: u64,
+
+    /// Number of bytes sent to this peer since the client has connected to it.
+    pub bytes_sent: u64,
+}
+
+/// Event that happened on the peer-to-peer network of a chain. See [`Client::network_events`].
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    /// A block announces substream has been opened with the given peer, which is now used for
+    /// synchronizing the chain.
+    Connected {
+        /// Identity of the peer.
+        peer_id: PeerId,
+        /// Role the peer reports playing on the network.
+        role: smoldot::network::protocol::Role,
+        /// Height of the best block according to the peer.
+        best_block_number: u64,
+        /// Hash of the best block according to the peer.
+        best_block_hash: [u8; 32],
+    },
+
+    /// The given peer, previously reported with [`NetworkEvent::Connected`], is no longer used
+    /// for synchronizing the chain.
+    Disconnected {
+        /// Identity of the peer.
+        peer_id: PeerId,
+    },
+
+    /// The given peer has announced a block. The header hasn't been verified.
+    BlockAnnounce {
+        /// Identity of the peer.
+        peer_id: PeerId,
+        /// SCALE-encoded header of the announced block.
+        scale_encoded_header: Vec<u8>,
+        /// `true` if the peer indicates that this block is its new best block.
+        is_best: bool,
+    },
+
+    /// The given peer has sent a GrandPa neighbor packet, indicating the state of its GrandPa
+    /// voter.
+    GrandpaNeighborPacket {
+        /// Identity of the peer.
+        peer_id: PeerId,
+        /// GrandPa round the peer is in.
+        round_number: u64,
+        /// Identifier of the set of authorities the peer is voting with.
+        set_id: u64,
+        /// Height of the highest block considered final by the peer.
+        commit_finalized_height: u64,
+    },
+}
+
+/// GrandPa equivocation detected on a chain. See [`Client::equivocation_reports`].
+#[derive(Debug, Clone)]
+pub struct EquivocationReport {
+    /// Authority that has equivocated, and the two votes it has signed.
+    pub equivocation: smoldot::finality::grandpa::equivocation::Equivocation,
+
+    /// SCALE-encoded equivocation proof, as expected by the `report_equivocation_unsigned` call
+    /// of the GrandPa pallet.
+    pub scale_encoded_equivocation_proof: Vec<u8>,
+
+    /// SCALE-encoded transaction that has been submitted in order to report the equivocation to
+    /// the chain. `None` if no transaction has been submitted, either because no
+    /// [`EquivocationReportSubmission`] has been passed to [`Client::equivocation_reports`] or
+    /// because the runtime couldn't generate a proof of the ownership of the key of the
+    /// authority.
+    pub submitted_transaction: Option<Vec<u8>>,
+}
+
+/// Information necessary in order to report equivocations to a chain. See
+/// [`Client::equivocation_reports`].
+#[derive(Debug, Clone, Copy)]
+pub struct EquivocationReportSubmission {
+    /// Index of the GrandPa pallet in the runtime of the chain.
+    pub grandpa_pallet_index: u8,
+
+    /// Index of the `report_equivocation_unsigned` call within the GrandPa pallet.
+    pub report_equivocation_unsigned_call_index: u8,
+}
+
+/// Status of a transaction. See [`Client::submit_transaction`].
+#[derive(Debug, Clone)]
+pub enum TransactionStatus {
+    /// Transaction has been successfully validated against the best block of the chain.
+    Validated,
+
+    /// Transaction has been broadcasted to peers. Contains the total number of peers the
+    /// transaction has been sent to so far.
+    Broadcast(usize),
+
+    /// Transaction has been included in the block of the best chain with the given hash.
+    InBlock([u8; 32]),
+
+    /// The block with the given hash, which was previously reported with
+    /// [`TransactionStatus::InBlock`], is no longer part of the best chain. The transaction will
+    /// be reported again if it gets included in another block.
+    Retracted([u8; 32]),
+
+    /// Transaction has been included in the finalized block with the given hash.
+    ///
+    /// This is always the last item of the stream.
+    Finalized([u8; 32]),
+
+    /// Transaction has been dropped, for example because the maximum number of pending
+    /// transactions has been reached or because it couldn't be validated.
+    ///
+    /// This is always the last item of the stream.
+    Dropped,
+
+    /// Transaction is invalid.
+    ///
+    /// This is always the last item of the stream.
+    Invalid(smoldot::transactions::validate::TransactionValidityError),
 }
 
 impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
@@ -324,6 +1111,28 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             chains_by_key: HashMap::with_capacity_and_hasher(expected_chains, Default::default()),
             system_name: config.system_name,
             system_version: config.system_version,
+            network_upload_limiter: config
+                .max_upload_bps
+                .map(|max| Arc::new(network_service::BandwidthLimiter::new(max))),
+            network_download_limiter: config
+                .max_download_bps
+                .map(|max| Arc::new(network_service::BandwidthLimiter::new(max))),
+            libp2p_key: config.libp2p_key,
+            wasm_execution: config.wasm_execution,
+            database_storage: config.database_storage,
+            dns_resolver: match config.dns {
+                DnsConfig::Platform => None,
+                #[cfg(feature = "std")]
+                DnsConfig::System => Some(Arc::new(dns::system::SystemResolver)),
+                DnsConfig::DnsOverHttps { url, client } => {
+                    Some(Arc::new(dns::doh::DnsOverHttps::new(url, client)))
+                }
+                DnsConfig::Custom(resolver) => Some(resolver),
+            },
+            header_verification_workers: config.header_verification_workers,
+            requests_hedging_percentile: config.requests_hedging_percentile,
+            yamux_max_receive_window: config.yamux_max_receive_window,
+            max_inbound_substreams: config.max_inbound_substreams,
         }
     }
 
@@ -335,29 +1144,114 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         config: AddChainConfig<'_, TChain, impl Iterator<Item = ChainId>>,
     ) -> Result<AddChainSuccess, AddChainError> {
         // Decode the chain specification.
-        let chain_spec = match chain_spec::ChainSpec::from_json_bytes(config.specification) {
+        let mut chain_spec = match chain_spec::ChainSpec::from_json_bytes(config.specification) {
             Ok(cs) => cs,
             Err(err) => {
                 return Err(AddChainError::ChainSpecParseError(err));
             }
         };
 
+        // Inject the genesis storage provided separately, if any.
+        match config.genesis_storage {
+            Some(GenesisStorage::Items(items)) => chain_spec.set_genesis_storage(items),
+            Some(GenesisStorage::Json(json)) => chain_spec.set_genesis_storage_json(json),
+            None => Ok(()),
+        }
+        .map_err(AddChainError::InvalidGenesisStorageConfig)?;
+
+        // Build the chain information of the genesis block. It is needed below in order to
+        // determine the chain information to start syncing from.
+        let genesis_chain_information = chain_spec.as_chain_information().map(|(ci, _)| ci); // TODO: don't just throw away the runtime
+
+        // If no database is provided, load it from the database storage, if any. The databases
+        // are identified in the storage by the hash of the genesis block of their chain.
+        let stored_database_content = match (&self.database_storage, &genesis_chain_information) {
+            (Some(database_storage), genesis_ci) if config.database_content.is_empty() => {
+                let genesis_block_hash = match genesis_ci {
+                    Ok(genesis_ci) => Some(
+                        genesis_ci
+                            .as_ref()
+                            .finalized_block_header
+                            .hash(chain_spec.block_number_bytes().into()),
+                    ),
+                    Err(chain_spec::FromGenesisStorageError::UnknownStorageItems) => chain_spec
+                        .genesis_storage()
+                        .into_trie_root_hash()
+                        .map(|state_root| {
+                            header::Header {
+                                parent_hash: [0; 32],
+                                number: 0,
+                                state_root: *state_root,
+                                extrinsics_root: smoldot::trie::empty_trie_merkle_value(),
+                                digest: header::DigestRef::empty().into(),
+                            }
+                            .hash(chain_spec.block_number_bytes().into())
+                        }),
+                    Err(_) => None,
+                };
+
+                genesis_block_hash
+                    .and_then(|hash| database_storage.storage.load(&hex::encode(hash)))
+                    .unwrap_or_default()
+            }
+            _ => String::new(),
+        };
+
+        let mut database_content = database::decode_database(
+            if config.database_content.is_empty() {
+                &stored_database_content
+            } else {
+                config.database_content
+            },
+            chain_spec.block_number_bytes().into(),
+        );
+
+        // The progress of the warp syncing is extracted from the database ahead of time, as the
+        // rest of the database might be thrown away below.
+        let database_warp_sync_progress = database_content
+            .as_mut()
+            .ok()
+            .and_then(|db| Some((db.genesis_block_hash, db.warp_sync_progress.take()?)));
+
+        // Same for the headers of the recently-finalized blocks and the runtime code hash of
+        // the last of them.
+        let database_recent_finalized_headers = database_content.as_mut().ok().map(|db| {
+            (
+                db.genesis_block_hash,
+                mem::take(&mut db.recent_finalized_headers),
+                db.finalized_runtime_code_hash,
+            )
+        });
+
         // Load the information about the chain from the chain spec. If a light sync state (also
         // known as a checkpoint) is present in the chain spec, it is possible to start syncing at
         // the finalized block it describes.
+        let chain_spec_checkpoint = chain_spec.light_sync_state().map(|s| {
+            s.as_chain_information()
+                .map_err(InvalidCheckpointError::Incomplete)
+                .and_then(|ci| {
+                    chain::chain_information::ValidChainInformation::try_from(ci)
+                        .map_err(InvalidCheckpointError::Invalid)
+                })
+        });
+
+        // If the checkpoint of the chain specification describes the genesis block, it must
+        // match the genesis block found in the chain specification. This is verified below.
+        let chain_spec_checkpoint_genesis_hash = match &chain_spec_checkpoint {
+            Some(Ok(cp)) if cp.as_ref().finalized_block_header.number == 0 => Some(
+                cp.as_ref()
+                    .finalized_block_header
+                    .hash(chain_spec.block_number_bytes().into()),
+            ),
+            _ => None,
+        };
+
         // TODO: clean up that block
         let (chain_information, genesis_block_header, checkpoint_nodes) = {
             match (
-                chain_spec.as_chain_information().map(|(ci, _)| ci), // TODO: don't just throw away the runtime
-                chain_spec.light_sync_state().map(|s| {
-                    chain::chain_information::ValidChainInformation::try_from(
-                        s.as_chain_information(),
-                    )
-                }),
-                database::decode_database(
-                    config.database_content,
-                    chain_spec.block_number_bytes().into(),
-                ),
+                genesis_chain_information,
+                chain_spec_checkpoint,
+                database_content,
             ) {
                 // Use the database if it contains a more recent block than the chain spec checkpoint.
                 (Ok(genesis_ci), checkpoint, Ok(database_content))
@@ -470,38 +1364,141 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             }
         };
 
+        if chain_spec_checkpoint_genesis_hash.map_or(false, |hash| {
+            hash != genesis_block_header.hash(chain_spec.block_number_bytes().into())
+        }) {
+            return Err(AddChainError::InvalidCheckpoint(
+                InvalidCheckpointError::GenesisMismatch,
+            ));
+        }
+
+        // Use the checkpoint passed by the API user if it is more recent than what has been
+        // found above.
+        let chain_information = if !config.checkpoint.is_empty() {
+            let (checkpoint_genesis_hash, checkpoint) = checkpoint::decode_checkpoint(
+                config.checkpoint,
+                chain_spec.block_number_bytes().into(),
+            )
+            .map_err(AddChainError::InvalidUserCheckpoint)?;
+
+            if checkpoint_genesis_hash
+                != genesis_block_header.hash(chain_spec.block_number_bytes().into())
+            {
+                return Err(AddChainError::UserCheckpointGenesisMismatch);
+            }
+
+            if checkpoint.as_ref().finalized_block_header.number
+                > chain_information.as_ref().finalized_block_header.number
+            {
+                checkpoint
+            } else {
+                chain_information
+            }
+        } else {
+            chain_information
+        };
+
+        // Resume the warp syncing from the database if it has progressed beyond the block found
+        // above.
+        let warp_sync_resume_progress =
+            database_warp_sync_progress.and_then(|(genesis_block_hash, progress)| {
+                if genesis_block_hash
+                    == genesis_block_header.hash(chain_spec.block_number_bytes().into())
+                    && progress.header.number
+                        > chain_information.as_ref().finalized_block_header.number
+                {
+                    Some(progress)
+                } else {
+                    None
+                }
+            });
+
         // If the chain specification specifies a parachain, find the corresponding relay chain
         // in the list of potential relay chains passed by the user.
+        // The relay chain can itself be a parachain. If none of the potential relay chains
+        // matches, the parachains of the potential relay chains are tried, then their own
+        // parachains, and so on, up to a maximum depth.
         // If no relay chain can be found, the chain creation fails. Exactly one matching relay
-        // chain must be found. If there are multiple ones, the creation fails as well.
+        // chain must be found at the first depth where there is a match. If there are multiple
+        // ones, the creation fails as well.
         let relay_chain_id = if let Some((relay_chain_id, _para_id)) = chain_spec.relay_chain() {
-            let chain = config
+            let mut candidates = config
                 .potential_relay_chains
-                .filter(|c| {
-                    self.public_api_chains
-                        .get(c.0)
-                        .map_or(false, |chain| chain.chain_spec_chain_id == relay_chain_id)
-                })
-                .exactly_one();
-
-            match chain {
-                Ok(c) => Some(c),
-                Err(mut iter) => {
-                    // `iter` here is identical to the iterator above before `exactly_one` is
-                    // called. This lets us know what failed.
-                    return Err(if iter.next().is_none() {
-                        AddChainError::NoRelayChainFound
-                    } else {
-                        debug_assert!(iter.next().is_some());
-                        AddChainError::MultipleRelayChains
-                    });
+                .filter(|c| self.public_api_chains.contains(c.0))
+                .collect::<Vec<_>>();
+            let mut visited_keys =
+                hashbrown::HashSet::<_, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+                    candidates.len(),
+                    Default::default(),
+                );
+
+            let mut depth = 0;
+            loop {
+                let chain = candidates
+                    .iter()
+                    .filter(|c| self.public_api_chains[c.0].chain_spec_chain_id == relay_chain_id)
+                    .exactly_one();
+
+                match chain {
+                    Ok(c) => break Some(*c),
+                    Err(mut iter) => {
+                        // `iter` here is identical to the iterator above before `exactly_one`
+                        // is called. This lets us know what failed.
+                        if iter.next().is_some() {
+                            debug_assert!(iter.next().is_some());
+                            return Err(AddChainError::MultipleRelayChains);
+                        }
+                    }
+                }
+
+                depth += 1;
+                if depth > MAX_RELAY_CHAINS_NESTING_DEPTH {
+                    return Err(AddChainError::NoRelayChainFound);
+                }
+
+                // Replace the list of candidates with the parachains of the candidates.
+                // Only the chains that have been added with one of the candidates as their relay
+                // chain are considered, rather than all the chains that share the same
+                // `ChainKey`. Chains added by other users of the client must not be visible to
+                // the caller unless the caller has passed them as potential relay chains.
+                // Chains that have already been visited are skipped, as otherwise a chain could
+                // be considered more than once.
+                for candidate in &candidates {
+                    visited_keys.insert(self.public_api_chains[candidate.0].key.clone());
+                }
+                candidates = self
+                    .public_api_chains
+                    .iter()
+                    .filter(|(_, chain)| {
+                        chain
+                            .relay_chain_id
+                            .map_or(false, |relay| candidates.contains(&relay))
+                    })
+                    .filter(|(_, chain)| !visited_keys.contains(&chain.key))
+                    .map(|(id, _)| ChainId(id))
+                    .collect();
+                if candidates.is_empty() {
+                    return Err(AddChainError::NoRelayChainFound);
                 }
             }
         } else {
             None
         };
 
-        // Build the list of bootstrap nodes ahead of time.
+        // A chain can't be its own relay chain, whether directly or indirectly.
+        if let Some(relay_chain_id) = relay_chain_id {
+            let genesis_block_hash =
+                genesis_block_header.hash(chain_spec.block_number_bytes().into());
+            let mut relay_chain_key = Some(&self.public_api_chains[relay_chain_id.0].key);
+            while let Some(key) = relay_chain_key {
+                if key.genesis_block_hash == genesis_block_hash {
+                    return Err(AddChainError::RelayChainCycle);
+                }
+                relay_chain_key = key.relay_chain.as_ref().map(|(k, _)| &**k);
+            }
+        }
+
+        // Build the list of bootstrap nodes ahead of time.
         // Because the specification of the format of a multiaddress is a bit flexible, it is
         // not possible to firmly affirm that a multiaddress is invalid. For this reason, we
         // simply ignore unparsable bootnode addresses rather than returning an error.
@@ -532,6 +1529,20 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             (valid_list, invalid_list)
         };
 
+        // Contrary to the bootnodes of the chain specification, reserved nodes are explicitly
+        // provided by the API user, and an error is returned if they can't be parsed.
+        let reserved_nodes = config
+            .reserved_nodes
+            .iter()
+            .map(|node| {
+                parse_node_address(node).ok_or_else(|| {
+                    AddChainError::InvalidReservedNode(
+                        node.chars().filter(|c| c.is_ascii()).collect::<String>(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
         // All the checks are performed above. Adding the chain can't fail anymore at this point.
 
         // Grab a couple of fields from the chain specification for later, as the chain
@@ -540,6 +1551,28 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         let genesis_block_hash = genesis_block_header.hash(chain_spec.block_number_bytes().into());
         let genesis_block_state_root = genesis_block_header.state_root;
 
+        // The recent headers found in the database are only used if they belong to this chain
+        // and connect to the finalized block that the chain starts from. This isn't the case
+        // if, for example, a more recent checkpoint has been used instead of the database.
+        let (database_recent_finalized_headers, database_finalized_runtime_code_hash) = {
+            let finalized_block_hash = chain_information
+                .as_ref()
+                .finalized_block_header
+                .hash(chain_spec.block_number_bytes().into());
+            database_recent_finalized_headers
+                .filter(|(db_genesis_block_hash, _, _)| {
+                    *db_genesis_block_hash == genesis_block_hash
+                })
+                .map(|(_, headers, runtime_code_hash)| {
+                    database::recent_finalized_headers_up_to(
+                        headers,
+                        runtime_code_hash,
+                        &finalized_block_hash,
+                    )
+                })
+                .unwrap_or_default()
+        };
+
         // The key generated here uniquely identifies this chain within smoldot. Mutiple chains
         // having the same key will use the same services.
         //
@@ -632,7 +1665,8 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             Entry::Vacant(entry) => {
                 // Key used by the networking. Represents the identity of the node on the
                 // peer-to-peer network.
-                let network_noise_key = connection::NoiseKey::new(&rand::random());
+                let network_noise_key =
+                    connection::NoiseKey::new(&self.libp2p_key.unwrap_or_else(rand::random));
 
                 // Spawn a background task that initializes the services of the new chain and
                 // yields a `ChainServices`.
@@ -640,6 +1674,28 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                     let spawn_new_task = self.spawn_new_task.clone();
                     let chain_spec = chain_spec.clone(); // TODO: quite expensive
                     let log_name = log_name.clone();
+                    let network_out_slots = config.network_out_slots;
+                    let network_reserved_only = config.reserved_only;
+                    let network_serve_warp_sync = config.serve_warp_sync;
+                    let network_enable_beefy = config.enable_beefy;
+                    let sync_mode = config.sync_mode;
+                    let probabilistic_finality_depth = config.probabilistic_finality_depth;
+                    let best_block_policy = config.best_block_policy;
+                    let runtime_call_cache_size = config.runtime_call_cache_size;
+                    let runtime_call_fuel_limit = config.runtime_call_fuel_limit;
+                    let max_cached_runtimes = config.max_cached_runtimes;
+                    let validate_transactions_locally = config.validate_transactions_locally;
+                    let wasm_execution = self.wasm_execution;
+                    let dns_resolver = self.dns_resolver.clone();
+                    let header_verification_workers = self.header_verification_workers;
+                    let requests_hedging_percentile = self.requests_hedging_percentile;
+                    let max_pinned_blocks = config.max_pinned_blocks;
+                    let network_limiters = (
+                        self.network_upload_limiter.clone(),
+                        self.network_download_limiter.clone(),
+                    );
+                    let network_connections_config =
+                        (self.yamux_max_receive_window, self.max_inbound_substreams);
 
                     let future = async move {
                         // Wait until the relay chain has finished initializing, if necessary.
@@ -676,6 +1732,25 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                             chain_spec,
                             relay_chain.as_ref().map(|(r, _)| r),
                             network_noise_key,
+                            network_out_slots,
+                            network_reserved_only,
+                            network_serve_warp_sync,
+                            network_enable_beefy,
+                            network_limiters,
+                            network_connections_config,
+                            warp_sync_resume_progress,
+                            sync_mode,
+                            probabilistic_finality_depth,
+                            best_block_policy,
+                            runtime_call_cache_size,
+                            runtime_call_fuel_limit,
+                            max_cached_runtimes,
+                            validate_transactions_locally,
+                            wasm_execution,
+                            dns_resolver,
+                            header_verification_workers,
+                            requests_hedging_percentile,
+                            max_pinned_blocks,
                         )
                         .await;
 
@@ -751,6 +1826,9 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             }
         };
 
+        // Cloned in order to not keep `self` borrowed, as some methods of `self` are called below.
+        let log_name = log_name.clone();
+
         if !invalid_bootstrap_nodes_sanitized.is_empty() {
             log::warn!(
                 target: "smoldot",
@@ -761,7 +1839,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         }
 
         // Print a warning if the list of bootnodes is empty, as this is a common mistake.
-        if bootstrap_nodes.is_empty() {
+        if bootstrap_nodes.is_empty() && reserved_nodes.is_empty() {
             // Note the usage of the word "likely", because another chain with the same key might
             // have been added earlier and contains bootnodes, or we might receive an incoming
             // substream on a connection normally used for a different chain.
@@ -777,12 +1855,25 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         let public_api_chains_entry = self.public_api_chains.vacant_entry();
         let new_chain_id = ChainId(public_api_chains_entry.key());
 
+        // If automatic recovery is enabled, the task spawned below continues running after the
+        // initial topology has been added, until the chain is removed.
+        let (auto_recover, auto_recover_stop_tx) = match config.auto_recover {
+            Some(auto_recover_config) => {
+                let (tx, rx) = oneshot::channel();
+                (Some((auto_recover_config, rx)), Some(tx))
+            }
+            None => (None, None),
+        };
+
         // Multiple chains can share the same network service, but each specify different
         // bootstrap nodes and database nodes. In order to resolve this, each chain adds their own
         // bootnodes and database nodes to the network service after it has been initialized. This
         // is done by adding a short-lived task that waits for the chain initialization to finish
         // then adds the nodes.
         (self.spawn_new_task)("network-service-add-initial-topology".to_owned(), {
+            let log_name = log_name.clone();
+            let reserved_only = config.reserved_only;
+
             // Clone `running_chain_init`.
             let mut running_chain_init = match services_init {
                 future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
@@ -794,14 +1885,41 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                 // Wait for the chain to finish initializing to proceed.
                 (&mut running_chain_init).await;
                 let running_chain = Pin::new(&mut running_chain_init).take_output().unwrap();
-                running_chain
-                    .network_service
-                    .discover(&TPlat::now(), 0, checkpoint_nodes, false)
-                    .await;
-                running_chain
-                    .network_service
-                    .discover(&TPlat::now(), 0, bootstrap_nodes, true)
-                    .await;
+                for (peer_id, addr) in reserved_nodes {
+                    running_chain
+                        .network_service
+                        .add_reserved_peer(0, peer_id, iter::once(addr))
+                        .await;
+                }
+
+                // In reserved-only mode, discovering other nodes would be pointless. The
+                // automatic recovery then only adds back the reserved nodes.
+                let bootstrap_nodes = if reserved_only {
+                    Vec::new()
+                } else {
+                    running_chain
+                        .network_service
+                        .discover(&TPlat::now(), 0, checkpoint_nodes, false)
+                        .await;
+                    running_chain
+                        .network_service
+                        .discover(&TPlat::now(), 0, bootstrap_nodes.clone(), true)
+                        .await;
+                    bootstrap_nodes
+                };
+
+                if let Some((auto_recover_config, auto_recover_stop_rx)) = auto_recover {
+                    let recover = auto_recover_chain(
+                        log_name,
+                        running_chain,
+                        bootstrap_nodes,
+                        auto_recover_config,
+                    );
+                    futures::pin_mut!(recover);
+                    // The receiver resolves when the sender is destroyed, in other words when
+                    // the chain is removed.
+                    let _ = future::select(recover, auto_recover_stop_rx).await;
+                }
             }
             .boxed()
         });
@@ -818,15 +1936,19 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
 
             let (frontend, service_starter) = json_rpc_service::service(json_rpc_service::Config {
                 log_name: log_name.clone(), // TODO: add a way to differentiate multiple different json-rpc services under the same chain
-                max_pending_requests: NonZeroU32::new(128).unwrap(),
-                max_subscriptions: 1024, // Note: the PolkadotJS UI is very heavy in terms of subscriptions.
-                max_parallel_requests: NonZeroU32::new(24).unwrap(),
+                max_pending_requests: config.json_rpc_max_pending_responses,
+                max_subscriptions: config.json_rpc_max_subscriptions,
+                max_batch_size: config.json_rpc_max_batch_size,
+                max_parallel_requests: config.json_rpc_max_parallel_requests,
+                max_parallel_cheap_requests: config.json_rpc_max_parallel_cheap_requests,
                 max_parallel_subscription_updates: NonZeroU32::new(8).unwrap(),
             });
 
             let spawn_new_task = self.spawn_new_task.clone();
             let system_name = self.system_name.clone();
             let system_version = self.system_version.clone();
+            let archive_fallback_endpoints = config.archive_fallback_endpoints.clone();
+            let max_block_hash_lookup_depth = config.max_block_hash_lookup_depth;
 
             let init_future = async move {
                 // Wait for the chain to finish initializing before starting the JSON-RPC service.
@@ -839,12 +1961,17 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                     network_service: (running_chain.network_service, 0), // TODO: 0?
                     transactions_service: running_chain.transactions_service,
                     runtime_service: running_chain.runtime_service,
+                    shared_follow: running_chain.shared_follow,
                     chain_spec: &chain_spec,
                     peer_id: &running_chain.network_identity,
                     system_name,
                     system_version,
                     genesis_block_hash,
                     genesis_block_state_root,
+                    archive_fallback_endpoints,
+                    max_block_hash_lookup_depth,
+                    recent_finalized_headers: database_recent_finalized_headers,
+                    recent_finalized_runtime_code_hash: database_finalized_runtime_code_hash,
                 })
             };
 
@@ -861,9 +1988,55 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             user_data: config.user_data,
             key: new_chain_key,
             chain_spec_chain_id,
+            relay_chain_id,
             json_rpc_frontend: json_rpc_frontend.clone(),
             _public_api_chain_destroyed_tx: public_api_chain_destroyed_tx,
+            _auto_recover_stop_tx: auto_recover_stop_tx,
+            chain_removed_tx: Vec::new(),
         });
+
+        // Call the event hooks of the chain, if any.
+        if config.event_hooks.on_new_best_block.is_some()
+            || config.event_hooks.on_finalized.is_some()
+            || config.event_hooks.on_runtime_upgrade.is_some()
+        {
+            let (services, chain_removed_rx) = self.chain_services(new_chain_id);
+            let event_hooks = config.event_hooks;
+            let task = async move {
+                let services = services.await;
+                run_chain_event_hooks(services, event_hooks).await;
+            };
+            (self.spawn_new_task)(
+                format!("{log_name}-event-hooks"),
+                // The receiver resolves when the sender is destroyed, in other words when the
+                // chain is removed.
+                future::select(task.boxed(), chain_removed_rx)
+                    .map(|_| ())
+                    .boxed(),
+            );
+        }
+
+        // Periodically store the database of the chain in the database storage, if any.
+        if let Some(database_storage) = self.database_storage.clone() {
+            let task_name = format!("{log_name}-database-storage");
+            let database_updates = self.database_updates(new_chain_id);
+            let initial_database = if config.database_content.is_empty() {
+                stored_database_content
+            } else {
+                config.database_content.to_owned()
+            };
+            (self.spawn_new_task)(
+                task_name,
+                persist_database::<TPlat>(
+                    database_storage,
+                    hex::encode(genesis_block_hash),
+                    initial_database,
+                    database_updates,
+                )
+                .boxed(),
+            );
+        }
+
         Ok(AddChainSuccess {
             chain_id: new_chain_id,
             json_rpc_responses: json_rpc_frontend.map(|f| JsonRpcResponses {
@@ -889,6 +2062,14 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
     pub fn remove_chain(&mut self, id: ChainId) -> TChain {
         let removed_chain = self.public_api_chains.remove(id.0);
 
+        // The parachains of the removed chain keep running, but must no longer be found as
+        // descendants of whatever chain later reuses the same `ChainId`.
+        for (_, chain) in self.public_api_chains.iter_mut() {
+            if chain.relay_chain_id == Some(id) {
+                chain.relay_chain_id = None;
+            }
+        }
+
         let running_chain = self.chains_by_key.get_mut(&removed_chain.key).unwrap();
         if running_chain.num_references.get() == 1 {
             log::info!(target: "smoldot", "Shutting down chain {}", running_chain.log_name);
@@ -942,150 +2123,2602 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         self.json_rpc_request_inner(json_rpc_request.into(), chain_id)
     }
 
-    fn json_rpc_request_inner(
+    /// Returns a stream of [`ChainHealth`] describing the health of the given chain.
+    ///
+    /// A new item is produced whenever the health of the chain changes. The first item is
+    /// produced as soon as the chain has finished initializing. Changes to the number of peers
+    /// are checked periodically rather than being reported instantly.
+    ///
+    /// The stream ends when the chain is removed with [`Client::remove_chain`].
+    ///
+    /// This is a more lightweight alternative to repeatedly sending `system_health` JSON-RPC
+    /// requests and subscribing to new blocks, and works even if
+    /// [`AddChainConfig::disable_json_rpc`] was `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn chain_health(
         &mut self,
-        json_rpc_request: String,
         chain_id: ChainId,
-    ) -> Result<(), HandleRpcError> {
-        let json_rpc_sender = match self
-            .public_api_chains
-            .get_mut(chain_id.0)
-            .unwrap()
-            .json_rpc_frontend
-        {
-            Some(ref mut json_rpc_sender) => json_rpc_sender,
-            _ => panic!(),
-        };
+    ) -> impl Stream<Item = ChainHealth> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .map(chain_health_stream)
+            .flatten_stream()
+            .take_until(chain_removed_rx)
+    }
 
-        json_rpc_sender.queue_rpc_request(json_rpc_request)
+    /// Returns a stream reporting the progress of the initial synchronization of the given chain.
+    ///
+    /// An item is produced as soon as the chain has finished initializing, then whenever the
+    /// progress changes.
+    /// See [`SyncProgress::percentage`] for an estimation of the completion.
+    ///
+    /// The stream ends after having produced [`SyncProgress::Finished`], or when the chain is
+    /// removed with [`Client::remove_chain`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn sync_progress(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Stream<Item = SyncProgress> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .map(sync_progress_stream)
+            .flatten_stream()
+            .take_until(chain_removed_rx)
     }
-}
 
-/// Error potentially returned by [`Client::add_chain`].
-#[derive(Debug, derive_more::Display)]
-pub enum AddChainError {
-    /// Failed to decode the specification of the chain.
-    #[display(fmt = "Failed to decode chain specification: {_0}")]
-    ChainSpecParseError(chain_spec::ParseError),
-    /// The chain specification must contain either the storage of the genesis block, or a
-    /// checkpoint. Neither was provided.
-    #[display(fmt = "Either a checkpoint or the genesis storage must be provided")]
-    ChainSpecNeitherGenesisStorageNorCheckpoint,
-    /// Checkpoint provided in the chain specification is invalid.
-    #[display(fmt = "Invalid checkpoint in chain specification: {_0}")]
-    InvalidCheckpoint(chain_information::ValidityError),
-    /// Failed to build the information about the chain from the genesis storage. This indicates
-    /// invalid data in the genesis storage.
-    #[display(fmt = "Failed to build genesis chain information: {_0}")]
-    InvalidGenesisStorage(chain_spec::FromGenesisStorageError),
-    /// The list of potential relay chains doesn't contain any relay chain with the name indicated
-    /// in the chain specification of the parachain.
-    #[display(fmt = "Couldn't find relevant relay chain")]
-    NoRelayChainFound,
-    /// The list of potential relay chains contains more than one relay chain with the name
-    /// indicated in the chain specification of the parachain.
-    #[display(fmt = "Multiple relevant relay chains found")]
-    MultipleRelayChains,
-}
+    /// Returns a stream of the changes to the runtime of the finalized block of the given chain.
+    ///
+    /// Runtime upgrades are only reported once the block that contains them has been finalized,
+    /// so that they are never reported for blocks that are later discarded. The runtime that the
+    /// finalized block has when the stream starts isn't reported.
+    ///
+    /// The stream ends when the chain is removed with [`Client::remove_chain`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn runtime_version_updates(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Stream<Item = RuntimeVersionChange> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .map(chain_events_stream)
+            .flatten_stream()
+            .filter_map(|event| {
+                future::ready(match event {
+                    ChainEvent::RuntimeVersionChange(change) => Some(change),
+                    _ => None,
+                })
+            })
+            .take_until(chain_removed_rx)
+    }
 
-/// Starts all the services of the client.
-///
-/// Returns some of the services that have been started. If these service get shut down, all the
-/// other services will later shut down as well.
-async fn start_services<TPlat: platform::Platform>(
-    log_name: String,
-    spawn_new_task: Arc<
-        dyn Fn(String, Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
-    >,
-    chain_information: chain::chain_information::ValidChainInformation,
-    genesis_block_scale_encoded_header: Vec<u8>,
-    chain_spec: chain_spec::ChainSpec,
-    relay_chain: Option<&ChainServices<TPlat>>,
-    network_noise_key: connection::NoiseKey,
-) -> ChainServices<TPlat> {
-    // Since `network_noise_key` is moved out below, use it to build the network identity ahead
-    // of the network service starting.
-    let network_identity =
-        peer_id::PublicKey::Ed25519(*network_noise_key.libp2p_public_ed25519_key()).into_peer_id();
+    /// Returns information about the blocks pinned by the `chainHead_follow` JSON-RPC
+    /// subscriptions of the given chain.
+    ///
+    /// This is meant to be used for debugging purposes, for example in order to find JSON-RPC
+    /// clients that never unpin blocks. See [`AddChainConfig::max_pinned_blocks`].
+    ///
+    /// Returns `None` if [`AddChainConfig::disable_json_rpc`] was `true` when adding the chain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn chain_head_pinned_blocks(
+        &mut self,
+        chain_id: ChainId,
+    ) -> Option<impl Future<Output = ChainHeadPinnedBlocks> + Send + 'static> {
+        self.public_api_chains
+            .get(chain_id.0)
+            .unwrap()
+            .json_rpc_frontend
+            .as_ref()?;
 
-    // The network service is responsible for connecting to the peer-to-peer network.
-    let (network_service, mut network_event_receivers) =
-        network_service::NetworkService::new(network_service::Config {
-            tasks_executor: Box::new({
-                let spawn_new_task = spawn_new_task.clone();
-                move |name, fut| spawn_new_task(name, fut)
-            }),
-            num_events_receivers: 1, // Configures the length of `network_event_receivers`
-            noise_key: network_noise_key,
-            chains: vec![network_service::ConfigChain {
-                log_name: log_name.clone(),
-                has_grandpa_protocol: matches!(
-                    chain_information.as_ref().finality,
-                    chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
-                ),
-                genesis_block_hash: header::hash_from_scale_encoded_header(
-                    &genesis_block_scale_encoded_header,
-                ),
-                finalized_block_height: chain_information.as_ref().finalized_block_header.number,
-                best_block: (
-                    chain_information.as_ref().finalized_block_header.number,
-                    chain_information
-                        .as_ref()
-                        .finalized_block_header
-                        .hash(chain_spec.block_number_bytes().into()),
-                ),
-                fork_id: chain_spec.fork_id().map(|n| n.to_owned()),
-                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-            }],
-        })
-        .await;
+        let (services, _) = self.chain_services(chain_id);
+        Some(async move { services.await.shared_follow.pinned_blocks().await })
+    }
 
-    let (sync_service, runtime_service) = if let Some(relay_chain) = relay_chain {
-        // Chain is a parachain.
+    /// Returns the number of bytes sent and received over the peer-to-peer network of the given
+    /// chain.
+    ///
+    /// Identical chains share their networking. As such, the counters also include the data
+    /// transferred on behalf of the other identical chains.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn network_bandwidth(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Future<Output = Result<NetworkBandwidth, NetworkBandwidthError>> + Send + 'static
+    {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
 
-        // The sync service is leveraging the network service, downloads block headers,
-        // and verifies them, to determine what are the best and finalized blocks of the
-        // chain.
-        let sync_service = Arc::new(
-            sync_service::SyncService::new(sync_service::Config {
-                log_name: log_name.clone(),
-                chain_information: chain_information.clone(),
-                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-                tasks_executor: Box::new({
-                    let spawn_new_task = spawn_new_task.clone();
-                    move |name, fut| spawn_new_task(name, fut)
-                }),
-                network_service: (network_service.clone(), 0),
-                network_events_receiver: network_event_receivers.pop().unwrap(),
-                parachain: Some(sync_service::ConfigParachain {
-                    parachain_id: chain_spec.relay_chain().unwrap().1,
-                    relay_chain_sync: relay_chain.runtime_service.clone(),
-                    relay_chain_block_number_bytes: relay_chain.block_number_bytes,
-                }),
-            })
-            .await,
-        );
+        let bandwidth = async move {
+            let services = services.await;
+            let (total_bytes_received, total_bytes_sent) =
+                services.network_service.total_bandwidth();
+            let peers = services
+                .network_service
+                .peers_bandwidth()
+                .await
+                .map(|(peer_id, bytes_received, bytes_sent)| PeerBandwidth {
+                    peer_id,
+                    bytes_received,
+                    bytes_sent,
+                })
+                .collect();
+            NetworkBandwidth {
+                total_bytes_received,
+                total_bytes_sent,
+                peers,
+            }
+        };
 
-        // The runtime service follows the runtime of the best block of the chain,
-        // and allows performing runtime calls.
-        let runtime_service = Arc::new(
-            runtime_service::RuntimeService::new(runtime_service::Config {
-                log_name: log_name.clone(),
-                tasks_executor: Box::new({
-                    let spawn_new_task = spawn_new_task.clone();
-                    move |name, fut| spawn_new_task(name, fut)
-                }),
-                sync_service: sync_service.clone(),
-                genesis_block_scale_encoded_header,
+        until_chain_removed(
+            bandwidth.map(Ok),
+            chain_removed_rx,
+            NetworkBandwidthError::ChainRemoved,
+        )
+    }
+
+    /// Returns a stream of [`NetworkEvent`]s happening on the peer-to-peer network of the given
+    /// chain.
+    ///
+    /// Only the events that happen after this function has been called are reported. The stream
+    /// ends if the consumer of the stream is too slow to process the events, in which case this
+    /// function must be called again. It also ends when the chain is removed with
+    /// [`Client::remove_chain`].
+    ///
+    /// Identical chains share their networking. As such, the events reported here also concern
+    /// the other identical chains.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn network_events(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Stream<Item = NetworkEvent> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .then(|services| async move { services.network_service.subscribe(64).await })
+            .flatten_stream()
+            .filter_map(|event| {
+                future::ready(match event {
+                    network_service::Event::Connected {
+                        peer_id,
+                        chain_index: 0,
+                        role,
+                        best_block_number,
+                        best_block_hash,
+                    } => Some(NetworkEvent::Connected {
+                        peer_id,
+                        role,
+                        best_block_number,
+                        best_block_hash,
+                    }),
+                    network_service::Event::Disconnected {
+                        peer_id,
+                        chain_index: 0,
+                    } => Some(NetworkEvent::Disconnected { peer_id }),
+                    network_service::Event::BlockAnnounce {
+                        peer_id,
+                        chain_index: 0,
+                        announce,
+                    } => {
+                        let decoded = announce.decode();
+                        Some(NetworkEvent::BlockAnnounce {
+                            peer_id,
+                            scale_encoded_header: decoded.scale_encoded_header.to_vec(),
+                            is_best: decoded.is_best,
+                        })
+                    }
+                    network_service::Event::GrandpaNeighborPacket {
+                        peer_id,
+                        chain_index: 0,
+                        state,
+                    } => Some(NetworkEvent::GrandpaNeighborPacket {
+                        peer_id,
+                        round_number: state.round_number,
+                        set_id: state.set_id,
+                        commit_finalized_height: state.commit_finalized_height,
+                    }),
+                    _ => None,
+                })
             })
-            .await,
-        );
+            .take_until(chain_removed_rx)
+    }
 
-        (sync_service, runtime_service)
-    } else {
-        // Chain is a relay chain.
+    /// Returns a stream of [`StorageChangeSet`]s describing the changes to the storage values of
+    /// the given keys.
+    ///
+    /// The storage values are queried from the peer-to-peer network every time the best block of
+    /// the chain changes. The first item contains the values of all the keys. Subsequent items
+    /// only contain the keys whose value differs from the one reported in the previous item, and
+    /// are only produced if at least one value has changed. Blocks whose storage couldn't be
+    /// retrieved are skipped.
+    ///
+    /// The stream ends when the chain is removed with [`Client::remove_chain`].
+    ///
+    /// This is equivalent to the `state_subscribeStorage` JSON-RPC function, and works even if
+    /// [`AddChainConfig::disable_json_rpc`] was `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn subscribe_storage(
+        &mut self,
+        chain_id: ChainId,
+        keys: impl IntoIterator<Item = impl Into<Vec<u8>>>,
+    ) -> impl Stream<Item = StorageChangeSet> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        let keys = keys.into_iter().map(Into::into).collect::<Vec<_>>();
+        services
+            .map(move |services| storage_changes_stream(services, keys))
+            .flatten_stream()
+            .take_until(chain_removed_rx)
+    }
 
-        // The sync service is leveraging the network service, downloads block headers,
+    /// Submits a transaction to the given chain and returns a stream of [`TransactionStatus`]
+    /// describing the progress of the transaction.
+    ///
+    /// `transaction` must be the SCALE-encoded transaction. It is validated against the best
+    /// block of the chain, unless [`AddChainConfig::validate_transactions_locally`] was `false`,
+    /// then broadcasted to the peers of the chain until it is included in a finalized block or
+    /// dropped.
+    ///
+    /// The stream ends after [`TransactionStatus::Finalized`], [`TransactionStatus::Dropped`] or
+    /// [`TransactionStatus::Invalid`] has been produced, or when the chain is removed with
+    /// [`Client::remove_chain`]. Dropping the stream doesn't cancel the sending of the
+    /// transaction.
+    ///
+    /// This is equivalent to the `author_submitAndWatchExtrinsic` JSON-RPC function, and works
+    /// even if [`AddChainConfig::disable_json_rpc`] was `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn submit_transaction(
+        &mut self,
+        chain_id: ChainId,
+        transaction: impl Into<Vec<u8>>,
+    ) -> impl Stream<Item = TransactionStatus> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        let transaction = transaction.into();
+        services
+            .then(move |services| async move {
+                services
+                    .transactions_service
+                    .submit_and_watch_transaction(transaction, 16)
+                    .await
+            })
+            .map(transaction_status_stream)
+            .flatten_stream()
+            .take_until(chain_removed_rx)
+    }
+
+    /// Returns the nonce that the next transaction of the given account should use.
+    ///
+    /// `account` must be the SCALE-encoded account id, in other words the 32 bytes public key
+    /// for most chains.
+    ///
+    /// The nonce of the account is read from the `System.Account` storage item of the current
+    /// best block. If transactions of this account that use this nonce or a higher one have
+    /// been submitted with [`Client::submit_transaction`] or the JSON-RPC functions and are
+    /// still pending, the nonce following the highest of them is returned instead. This makes
+    /// it possible to submit multiple transactions in quick succession without waiting for them
+    /// to be included.
+    ///
+    /// > **Note**: Transactions are only taken into account once they have been validated,
+    /// >           which happens shortly after they have been submitted.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn next_nonce(
+        &mut self,
+        chain_id: ChainId,
+        account: impl Into<Vec<u8>>,
+    ) -> impl Future<Output = Result<u64, NextNonceError>> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        let account = account.into();
+
+        let query = async move {
+            let services = services.await;
+            next_nonce(&services, account).await
+        };
+
+        until_chain_removed(query, chain_removed_rx, NextNonceError::ChainRemoved)
+    }
+
+    /// Verifies the given GrandPa justification against the state of the finality of the given
+    /// chain.
+    ///
+    /// `scale_encoded_header` must be the SCALE-encoded header of the block targeted by the
+    /// justification, and `scale_encoded_justification` the SCALE-encoded justification, for
+    /// example obtained through the `grandpa_subscribeJustifications` JSON-RPC function or from a
+    /// third party.
+    ///
+    /// The justification is verified against the list of GrandPa authorities of the latest
+    /// finalized block known by the client. As such, justifications of blocks that precede a
+    /// change in the list of GrandPa authorities that the client is already aware of can't be
+    /// verified and an error is returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn verify_grandpa_justification(
+        &mut self,
+        chain_id: ChainId,
+        scale_encoded_header: &[u8],
+        scale_encoded_justification: &[u8],
+    ) -> impl Future<Output = Result<(), VerifyJustificationError>> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        let scale_encoded_header = scale_encoded_header.to_vec();
+        let scale_encoded_justification = scale_encoded_justification.to_vec();
+
+        let verify = async move {
+            let services = services.await;
+
+            let header = header::decode(&scale_encoded_header, services.block_number_bytes)
+                .map_err(VerifyJustificationError::InvalidHeader)?;
+            let justification = justification::decode::decode_grandpa(
+                &scale_encoded_justification,
+                services.block_number_bytes,
+            )
+            .map_err(VerifyJustificationError::InvalidJustification)?;
+
+            if *justification.target_hash != header.hash(services.block_number_bytes)
+                || justification.target_number != header.number
+            {
+                return Err(VerifyJustificationError::TargetMismatch);
+            }
+
+            let chain_information = services
+                .sync_service
+                .serialize_chain_information()
+                .await
+                .ok_or(VerifyJustificationError::FinalityStateUnknown)?;
+            let chain_information = chain_information.as_ref();
+
+            let chain_information::ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                finalized_scheduled_change,
+            } = chain_information.finality
+            else {
+                return Err(VerifyJustificationError::NotGrandpa);
+            };
+
+            if header.number <= chain_information.finalized_block_header.number {
+                return Err(VerifyJustificationError::BlockTooOld);
+            }
+
+            // The block right after the one where a scheduled change is triggered, and its
+            // descendants, are finalized by the new list of authorities.
+            let (authorities_set_id, authorities_list) = match finalized_scheduled_change {
+                Some((trigger_block_height, new_authorities))
+                    if header.number > trigger_block_height =>
+                {
+                    (
+                        after_finalized_block_authorities_set_id + 1,
+                        new_authorities,
+                    )
+                }
+                _ => (
+                    after_finalized_block_authorities_set_id,
+                    finalized_triggered_authorities,
+                ),
+            };
+
+            justification::verify::verify(justification::verify::Config {
+                justification,
+                block_number_bytes: services.block_number_bytes,
+                authorities_set_id,
+                authorities_list: authorities_list.iter().map(|a| a.public_key),
+                randomness_seed: rand::random(),
+            })
+            .map_err(VerifyJustificationError::Verification)
+        };
+
+        until_chain_removed(
+            verify,
+            chain_removed_rx,
+            VerifyJustificationError::ChainRemoved,
+        )
+    }
+
+    /// Verifies a storage proof against the given state trie root, and returns the storage
+    /// values of the given keys.
+    ///
+    /// `proof` must be the SCALE-encoded list of trie nodes forming the proof, for example
+    /// obtained from a full node or from a third party. The proof is verified using the same
+    /// code as the proofs that the client downloads from the network.
+    ///
+    /// On success, the returned list contains one entry per key of `keys`, in the same order,
+    /// with `None` if the proof indicates that there is no storage value associated with this
+    /// key. An error is returned if the proof is invalid, or if it doesn't contain the
+    /// information necessary to determine the storage value of one of the keys.
+    ///
+    /// This function doesn't depend on the state of any chain. The state trie root can for
+    /// example be obtained from a block header verified by the client.
+    pub fn verify_storage_proof(
+        &self,
+        state_trie_root: &[u8; 32],
+        proof: &[u8],
+        keys: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, VerifyStorageProofError> {
+        let decoded_proof =
+            trie::proof_decode::decode_and_verify_proof(trie::proof_decode::Config {
+                trie_root_hash: state_trie_root,
+                proof,
+            })
+            .map_err(VerifyStorageProofError::InvalidProof)?;
+
+        keys.into_iter()
+            .enumerate()
+            .map(
+                |(key_index, key)| match decoded_proof.storage_value(key.as_ref()) {
+                    Some(value) => Ok(value.map(|(value, _)| value.to_vec())),
+                    None => Err(VerifyStorageProofError::MissingProofEntry(key_index)),
+                },
+            )
+            .collect()
+    }
+
+    /// Returns a stream of the BEEFY finality proofs of the given chain.
+    ///
+    /// The finality proofs gossiped by the BEEFY validators are verified against the BEEFY
+    /// validator set of the chain, obtained by calling the `BeefyApi_validator_set` runtime
+    /// function against the latest finalized block. Only valid proofs are reported, in increasing
+    /// order of block number. Each proof contains the signed commitment of the validators, which
+    /// includes the root of the Merkle Mountain Range of the chain and can be relayed to bridges.
+    ///
+    /// BEEFY is only supported on relay chains that use GrandPa. The stream never produces any
+    /// item for other chains, or if [`AddChainConfig::enable_beefy`] was `false`. The stream ends
+    /// when the chain is removed with [`Client::remove_chain`].
+    ///
+    /// This is equivalent to the `beefy_subscribeJustifications` JSON-RPC function, and works
+    /// even if [`AddChainConfig::disable_json_rpc`] was `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn subscribe_beefy_finality(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Stream<Item = BeefyFinalityProof> + Send + 'static {
+        let log_target = format!(
+            "beefy-{}",
+            self.chains_by_key
+                .get(&self.public_api_chains.get(chain_id.0).unwrap().key)
+                .unwrap()
+                .log_name
+        );
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .map(move |services| {
+                beefy::finality_proofs_stream(
+                    log_target,
+                    (services.network_service.clone(), 0),
+                    move || {
+                        let services = services.clone();
+                        async move {
+                            let finalized_block_hash = services
+                                .sync_service
+                                .serialize_chain_information()
+                                .await
+                                .ok_or_else(|| "Finalized block unknown".to_owned())?
+                                .as_ref()
+                                .finalized_block_header
+                                .hash(services.block_number_bytes);
+                            runtime_call(
+                                &services,
+                                &finalized_block_hash,
+                                "BeefyApi_validator_set",
+                                &[],
+                            )
+                            .await
+                            .map_err(|err| err.to_string())
+                        }
+                    },
+                )
+            })
+            .flatten_stream()
+            .take_until(chain_removed_rx)
+    }
+
+    /// Returns a stream of the GrandPa equivocations detected on the given chain.
+    ///
+    /// The GrandPa commit messages gossiped by the peers of the chain are inspected, and an item
+    /// is produced whenever a GrandPa authority is found to have signed two different
+    /// pre-commits during the same round. Each equivocation is only reported once. Only the
+    /// signatures of the authorities of the current and next authorities sets, as known by the
+    /// client, are taken into account.
+    ///
+    /// If `submission` is `Some`, each equivocation is also reported to the chain. A proof of
+    /// the ownership of the key of the authority is obtained by calling the
+    /// `GrandpaApi_generate_key_ownership_proof` runtime function against the latest finalized
+    /// block, then the `report_equivocation_unsigned` unsigned transaction is submitted in the
+    /// same way as [`Client::submit_transaction`]. Each item of the stream is produced after the
+    /// transaction has been submitted.
+    ///
+    /// The stream ends when the chain is removed with [`Client::remove_chain`]. It never produces
+    /// any item for chains that don't use GrandPa.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn equivocation_reports(
+        &mut self,
+        chain_id: ChainId,
+        submission: Option<EquivocationReportSubmission>,
+    ) -> impl Stream<Item = EquivocationReport> + Send + 'static {
+        let log_target = format!(
+            "equivocations-{}",
+            self.chains_by_key
+                .get(&self.public_api_chains.get(chain_id.0).unwrap().key)
+                .unwrap()
+                .log_name
+        );
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .map(move |services| {
+                equivocations::equivocations_stream(
+                    log_target.clone(),
+                    (services.network_service.clone(), 0),
+                    services.sync_service.clone(),
+                    services.block_number_bytes,
+                )
+                .then(move |equivocation| {
+                    let services = services.clone();
+                    let log_target = log_target.clone();
+                    async move {
+                        let scale_encoded_equivocation_proof =
+                            equivocation.scale_encoded_proof(services.block_number_bytes);
+                        let submitted_transaction = match submission {
+                            Some(submission) => {
+                                report_equivocation(&services, &equivocation, submission)
+                                    .await
+                                    .map_err(|error| {
+                                        log::warn!(
+                                            target: &log_target,
+                                            "Failed to report GrandPa equivocation: {}",
+                                            error
+                                        );
+                                    })
+                                    .ok()
+                            }
+                            None => None,
+                        };
+
+                        EquivocationReport {
+                            equivocation,
+                            scale_encoded_equivocation_proof,
+                            submitted_transaction,
+                        }
+                    }
+                })
+            })
+            .flatten_stream()
+            .take_until(chain_removed_rx)
+    }
+
+    /// Calls a runtime function of the given chain against the state of the given block.
+    ///
+    /// `function` is the name of the runtime entry point to call, for example
+    /// `TransactionPaymentApi_query_info`, and `parameters` its SCALE-encoded parameters. On
+    /// success, the SCALE-encoded value returned by the runtime is returned.
+    ///
+    /// The runtime code of the block and a call proof are downloaded from the peer-to-peer
+    /// network, and the call is then executed locally. Consequently, the block must still be
+    /// known by the full nodes of the network.
+    ///
+    /// This is equivalent to the `state_call` JSON-RPC function, and works even if
+    /// [`AddChainConfig::disable_json_rpc`] was `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn runtime_call(
+        &mut self,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        function: impl Into<String>,
+        parameters: impl Into<Vec<u8>>,
+    ) -> impl Future<Output = Result<Vec<u8>, RuntimeCallError>> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        let function = function.into();
+        let parameters = parameters.into();
+
+        let call = async move {
+            let services = services.await;
+            runtime_call(&services, &block_hash, &function, &parameters).await
+        };
+
+        until_chain_removed(call, chain_removed_rx, RuntimeCallError::ChainRemoved)
+    }
+
+    /// Returns the metadata of the runtime of the given block of the given chain.
+    ///
+    /// The metadata is obtained by calling the `Metadata_metadata` runtime function, as
+    /// described in [`Client::runtime_call`]. It is then cached alongside with the compiled
+    /// runtime, meaning that asking for the metadata of blocks that use the same runtime
+    /// doesn't call the runtime again.
+    ///
+    /// In addition to the raw metadata, the returned value contains the decoded metadata if the
+    /// format of the metadata is supported by [`smoldot::metadata::decode`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn runtime_metadata(
+        &mut self,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+    ) -> impl Future<Output = Result<RuntimeMetadata, RuntimeMetadataError>> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+
+        let call = async move {
+            let services = services.await;
+            runtime_metadata(&services, &block_hash).await
+        };
+
+        until_chain_removed(
+            call,
+            chain_removed_rx,
+            RuntimeMetadataError::Call(RuntimeCallError::ChainRemoved),
+        )
+    }
+
+    /// Estimates the fees that the given transaction would have to pay if it was included in
+    /// a child of the given block.
+    ///
+    /// `transaction` must be the SCALE-encoded transaction. The fees are obtained by calling the
+    /// `TransactionPaymentApi_query_info` and `TransactionPaymentApi_query_fee_details` runtime
+    /// functions, as described in [`Client::runtime_call`].
+    ///
+    /// This is equivalent to the `payment_queryInfo` and `payment_queryFeeDetails` JSON-RPC
+    /// functions, and works even if [`AddChainConfig::disable_json_rpc`] was `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn estimate_fee(
+        &mut self,
+        chain_id: ChainId,
+        transaction: impl Into<Vec<u8>>,
+        block_hash: [u8; 32],
+    ) -> impl Future<Output = Result<FeeEstimate, EstimateFeeError>> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        let transaction = transaction.into();
+
+        let call = async move {
+            let services = services.await;
+            estimate_fee(&services, &transaction, &block_hash).await
+        };
+
+        until_chain_removed(
+            call,
+            chain_removed_rx,
+            EstimateFeeError::Call(RuntimeCallError::ChainRemoved),
+        )
+    }
+
+    /// Generates a checkpoint describing the current finalized block of the given chain.
+    ///
+    /// The checkpoint contains the header of the finalized block and the information necessary
+    /// in order to verify the finality of its descendants, such as the list of GrandPa
+    /// authorities and the BABE epochs. It can later be passed as
+    /// [`AddChainConfig::checkpoint`] in order to start synchronizing from this block, which
+    /// lets the client skip most of the warp syncing.
+    ///
+    /// Contrary to the output of the `chainHead_unstable_finalizedDatabase` JSON-RPC function,
+    /// the format of the checkpoint is versioned and stable, and doesn't contain any list of
+    /// peers. This makes it suitable for being embedded within an application.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn export_checkpoint(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Future<Output = Result<String, ExportCheckpointError>> + Send + 'static {
+        let genesis_block_hash = self
+            .public_api_chains
+            .get(chain_id.0)
+            .unwrap()
+            .key
+            .genesis_block_hash;
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+
+        let export = async move {
+            let services = services.await;
+            let chain_information = services
+                .sync_service
+                .serialize_chain_information()
+                .await
+                .ok_or(ExportCheckpointError::FinalityStateUnknown)?;
+            Ok(checkpoint::encode_checkpoint(
+                &genesis_block_hash,
+                &chain_information,
+                services.block_number_bytes,
+            ))
+        };
+
+        until_chain_removed(
+            export,
+            chain_removed_rx,
+            ExportCheckpointError::ChainRemoved,
+        )
+    }
+
+    /// Returns a stream of [`DatabaseDelta`]s describing the changes to the database of the given
+    /// chain.
+    ///
+    /// A delta is produced every time the finalized block of the chain changes. The first delta
+    /// contains the entire state of the chain, while the following ones only contain what has
+    /// changed since the previous delta, such as the headers of the newly-finalized blocks, the
+    /// parts of the chain information that have been modified, and the nodes that have been
+    /// discovered or forgotten. Deltas can be persisted one after the
+    /// other, then merged into a database with [`compact_database`], which can be passed as
+    /// [`AddChainConfig::database_content`]. This avoids having to serialize and store the
+    /// entire database every time it changes.
+    ///
+    /// The stream ends when the chain is removed with [`Client::remove_chain`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn database_updates(
+        &mut self,
+        chain_id: ChainId,
+    ) -> impl Stream<Item = DatabaseDelta> + Send + 'static {
+        let genesis_block_hash = self
+            .public_api_chains
+            .get(chain_id.0)
+            .unwrap()
+            .key
+            .genesis_block_hash;
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+        services
+            .map(move |services| database_deltas_stream(services, genesis_block_hash))
+            .flatten_stream()
+            .take_until(chain_removed_rx)
+    }
+
+    /// Adds a node to the list of reserved nodes of the given chain. See
+    /// [`AddChainConfig::reserved_nodes`].
+    ///
+    /// The address must be a multiaddress ending with `/p2p/` followed by the identity of the
+    /// node.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn add_reserved_peer(
+        &mut self,
+        chain_id: ChainId,
+        address: &str,
+    ) -> impl Future<Output = Result<(), ReservedPeerError>> + Send + 'static {
+        let node = parse_node_address(address);
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+
+        let add = async move {
+            let (peer_id, addr) = node.ok_or(ReservedPeerError::InvalidAddress)?;
+            services
+                .await
+                .network_service
+                .add_reserved_peer(0, peer_id, iter::once(addr))
+                .await;
+            Ok(())
+        };
+
+        until_chain_removed(add, chain_removed_rx, ReservedPeerError::ChainRemoved)
+    }
+
+    /// Removes a node from the list of reserved nodes of the given chain.
+    ///
+    /// If [`AddChainConfig::reserved_only`] was `true`, the client disconnects from this node.
+    /// Otherwise, the node is from now on treated like any other node.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn remove_reserved_peer(
+        &mut self,
+        chain_id: ChainId,
+        peer_id: PeerId,
+    ) -> impl Future<Output = Result<(), ReservedPeerError>> + Send + 'static {
+        let (services, chain_removed_rx) = self.chain_services(chain_id);
+
+        let remove = async move {
+            if services
+                .await
+                .network_service
+                .remove_reserved_peer(0, &peer_id)
+                .await
+            {
+                Ok(())
+            } else {
+                Err(ReservedPeerError::NotReserved)
+            }
+        };
+
+        until_chain_removed(remove, chain_removed_rx, ReservedPeerError::ChainRemoved)
+    }
+
+    /// Returns a future that yields the services of the given chain once it has finished
+    /// initializing, plus a receiver that resolves when the chain is removed.
+    ///
+    /// The receiver resolves when its sender is destroyed, which happens when the chain is
+    /// removed with [`Client::remove_chain`]. See also [`until_chain_removed`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    fn chain_services(
+        &mut self,
+        chain_id: ChainId,
+    ) -> (
+        impl Future<Output = ChainServices<TPlat>> + Send + 'static,
+        oneshot::Receiver<()>,
+    ) {
+        let public_api_chain = self.public_api_chains.get_mut(chain_id.0).unwrap();
+
+        let (chain_removed_tx, chain_removed_rx) = oneshot::channel();
+        public_api_chain
+            .chain_removed_tx
+            .retain(|tx| !tx.is_canceled());
+        public_api_chain.chain_removed_tx.push(chain_removed_tx);
+
+        // Clone `running_chain_init`.
+        let mut running_chain_init = match &self
+            .chains_by_key
+            .get(&public_api_chain.key)
+            .unwrap()
+            .services
+        {
+            future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
+            future::MaybeDone::Future(d) => future::MaybeDone::Future(d.clone()),
+            future::MaybeDone::Gone => unreachable!(),
+        };
+
+        let services = async move {
+            // Wait for the chain to finish initializing to proceed.
+            (&mut running_chain_init).await;
+            Pin::new(&mut running_chain_init).take_output().unwrap()
+        };
+
+        (services, chain_removed_rx)
+    }
+
+    fn json_rpc_request_inner(
+        &mut self,
+        json_rpc_request: String,
+        chain_id: ChainId,
+    ) -> Result<(), HandleRpcError> {
+        let json_rpc_sender = match self
+            .public_api_chains
+            .get_mut(chain_id.0)
+            .unwrap()
+            .json_rpc_frontend
+        {
+            Some(ref mut json_rpc_sender) => json_rpc_sender,
+            _ => panic!(),
+        };
+
+        json_rpc_sender.queue_rpc_request(json_rpc_request)
+    }
+}
+
+/// Runs the given future to completion, or returns `Err(chain_removed_error)` if
+/// `chain_removed_rx` resolves first.
+///
+/// `chain_removed_rx` is meant to be the receiver returned by `Client::chain_services`, in which
+/// case the future is interrupted when the chain is removed.
+async fn until_chain_removed<T, E>(
+    future: impl Future<Output = Result<T, E>>,
+    chain_removed_rx: oneshot::Receiver<()>,
+    chain_removed_error: E,
+) -> Result<T, E> {
+    futures::pin_mut!(future);
+    match future::select(future, chain_removed_rx).await {
+        future::Either::Left((result, _)) => result,
+        future::Either::Right(_) => Err(chain_removed_error),
+    }
+}
+
+/// Error potentially returned by [`Client::add_chain`].
+#[derive(Debug, derive_more::Display)]
+pub enum AddChainError {
+    /// Failed to decode the specification of the chain.
+    #[display(fmt = "Failed to decode chain specification: {_0}")]
+    ChainSpecParseError(chain_spec::ParseError),
+    /// The chain specification must contain either the storage of the genesis block, or a
+    /// checkpoint. Neither was provided.
+    #[display(fmt = "Either a checkpoint or the genesis storage must be provided")]
+    ChainSpecNeitherGenesisStorageNorCheckpoint,
+    /// Checkpoint provided in the chain specification is invalid.
+    #[display(fmt = "Invalid checkpoint in chain specification: {_0}")]
+    InvalidCheckpoint(InvalidCheckpointError),
+    /// Failed to build the information about the chain from the genesis storage. This indicates
+    /// invalid data in the genesis storage.
+    #[display(fmt = "Failed to build genesis chain information: {_0}")]
+    InvalidGenesisStorage(chain_spec::FromGenesisStorageError),
+    /// Failed to use the genesis storage passed in [`AddChainConfig::genesis_storage`].
+    #[display(fmt = "Invalid genesis storage: {_0}")]
+    InvalidGenesisStorageConfig(chain_spec::SetGenesisStorageError),
+    /// The list of potential relay chains doesn't contain any relay chain with the name indicated
+    /// in the chain specification of the parachain.
+    #[display(fmt = "Couldn't find relevant relay chain")]
+    NoRelayChainFound,
+    /// The list of potential relay chains contains more than one relay chain with the name
+    /// indicated in the chain specification of the parachain.
+    #[display(fmt = "Multiple relevant relay chains found")]
+    MultipleRelayChains,
+    /// The relay chain of the parachain is, directly or indirectly, a parachain of the chain
+    /// being added.
+    #[display(fmt = "Chain is its own relay chain")]
+    RelayChainCycle,
+    /// Failed to decode the checkpoint passed in [`AddChainConfig::checkpoint`].
+    #[display(fmt = "Failed to decode checkpoint: {_0}")]
+    InvalidUserCheckpoint(checkpoint::DecodeError),
+    /// The checkpoint passed in [`AddChainConfig::checkpoint`] belongs to a different chain.
+    #[display(fmt = "Checkpoint doesn't belong to this chain")]
+    UserCheckpointGenesisMismatch,
+    /// One of the entries of [`AddChainConfig::reserved_nodes`] couldn't be parsed.
+    #[display(fmt = "Invalid reserved node address: {_0}")]
+    InvalidReservedNode(String),
+}
+
+/// See [`AddChainError::InvalidCheckpoint`].
+#[derive(Debug, derive_more::Display)]
+pub enum InvalidCheckpointError {
+    /// The checkpoint lacks information necessary to start syncing from it.
+    #[display(fmt = "{_0}")]
+    Incomplete(chain_spec::CheckpointToChainInformationError),
+    /// The information found in the checkpoint is inconsistent.
+    #[display(fmt = "{_0}")]
+    Invalid(chain_information::ValidityError),
+    /// The checkpoint describes a genesis block that is different from the one found in the
+    /// chain specification.
+    #[display(fmt = "Checkpoint conflicts with the genesis block of the chain specification")]
+    GenesisMismatch,
+}
+
+/// Error potentially returned by [`Client::export_checkpoint`].
+#[derive(Debug, derive_more::Display)]
+pub enum ExportCheckpointError {
+    /// The chain has been removed before the checkpoint could be generated.
+    #[display(fmt = "Chain has been removed")]
+    ChainRemoved,
+    /// The state of the finality of the chain isn't known yet.
+    #[display(fmt = "State of the finality of the chain isn't known yet")]
+    FinalityStateUnknown,
+}
+
+/// Error potentially returned by [`Client::network_bandwidth`].
+#[derive(Debug, derive_more::Display)]
+pub enum NetworkBandwidthError {
+    /// The chain has been removed before the information could be retrieved.
+    #[display(fmt = "Chain has been removed")]
+    ChainRemoved,
+}
+
+/// Error potentially returned by [`Client::add_reserved_peer`] and
+/// [`Client::remove_reserved_peer`].
+#[derive(Debug, derive_more::Display)]
+pub enum ReservedPeerError {
+    /// The chain has been removed before the operation could finish.
+    #[display(fmt = "Chain has been removed")]
+    ChainRemoved,
+    /// The address isn't a multiaddress ending with `/p2p/` followed by a valid peer id.
+    #[display(fmt = "Invalid node address")]
+    InvalidAddress,
+    /// The node wasn't in the list of reserved nodes.
+    #[display(fmt = "Node isn't a reserved node")]
+    NotReserved,
+}
+
+/// Error potentially returned by [`Client::next_nonce`].
+#[derive(Debug, derive_more::Display)]
+pub enum NextNonceError {
+    /// The chain has been removed before the nonce could be determined.
+    #[display(fmt = "Chain has been removed")]
+    ChainRemoved,
+    /// Failed to download the `System.Account` storage item from the network.
+    #[display(fmt = "Failed to download the account information: {_0}")]
+    StorageQueryFailed(String),
+    /// The `System.Account` storage item is too short to contain a nonce.
+    #[display(fmt = "Invalid account information")]
+    InvalidAccountInfo,
+}
+
+/// Error potentially returned by [`Client::verify_grandpa_justification`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyJustificationError {
+    /// The chain has been removed before the verification could finish.
+    #[display(fmt = "Chain has been removed")]
+    ChainRemoved,
+    /// Failed to decode the header.
+    #[display(fmt = "Failed to decode header: {_0}")]
+    InvalidHeader(header::Error),
+    /// Failed to decode the justification.
+    #[display(fmt = "{_0}")]
+    InvalidJustification(justification::decode::Error),
+    /// The justification doesn't target the given header.
+    #[display(fmt = "Justification doesn't target the given header")]
+    TargetMismatch,
+    /// The state of the finality of the chain isn't known yet.
+    #[display(fmt = "State of the finality of the chain isn't known yet")]
+    FinalityStateUnknown,
+    /// The chain doesn't use GrandPa for its finality.
+    #[display(fmt = "Chain doesn't use GrandPa")]
+    NotGrandpa,
+    /// The block is already finalized. The list of authorities that has finalized it might
+    /// no longer be known.
+    #[display(fmt = "Block is already finalized")]
+    BlockTooOld,
+    /// The justification is invalid.
+    #[display(fmt = "Invalid justification: {_0}")]
+    Verification(justification::verify::Error),
+}
+
+/// Error potentially returned by [`Client::verify_storage_proof`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyStorageProofError {
+    /// The proof is invalid or doesn't match the state trie root.
+    #[display(fmt = "Invalid proof: {_0}")]
+    InvalidProof(trie::proof_decode::Error),
+    /// The proof doesn't contain the storage value of the key whose index is indicated.
+    #[display(fmt = "Proof doesn't contain the storage value of key #{_0}")]
+    MissingProofEntry(usize),
+}
+
+/// Error potentially returned by [`Client::runtime_call`].
+#[derive(Debug, derive_more::Display)]
+pub enum RuntimeCallError {
+    /// The chain has been removed before the call could finish.
+    #[display(fmt = "Chain has been removed")]
+    ChainRemoved,
+    /// Failed to download the header of the block from the network.
+    #[display(fmt = "Failed to download the header of the block")]
+    HeaderQueryFailed,
+    /// Failed to download the runtime code of the block from the network.
+    #[display(fmt = "Failed to download the runtime code of the block: {_0}")]
+    RuntimeCodeQueryFailed(String),
+    /// Failed to compile the runtime of the block, or to download or verify the call proof.
+    #[display(fmt = "{_0}")]
+    Call(String),
+    /// Failed to start the execution of the runtime function.
+    #[display(fmt = "Failed to start runtime call: {_0}")]
+    StartError(executor::host::StartErr),
+    /// The runtime function has returned an error or has crashed.
+    #[display(fmt = "Runtime error: {_0}")]
+    RuntimeError(executor::runtime_host::ErrorDetail),
+    /// The runtime has tried to enumerate storage keys, which isn't supported.
+    #[display(fmt = "Runtime has tried to enumerate storage keys")]
+    ForbiddenHostFunction,
+    /// The runtime function has consumed all the fuel allowed by
+    /// [`AddChainConfig::runtime_call_fuel_limit`].
+    #[display(fmt = "Runtime call has exceeded its execution limit")]
+    ExecutionTimeout,
+}
+
+/// Metadata of a runtime. See [`Client::runtime_metadata`].
+#[derive(Debug, Clone)]
+pub struct RuntimeMetadata {
+    /// SCALE-encoded metadata, without its length prefix.
+    pub scale_encoded: Arc<[u8]>,
+    /// Decoded version of [`RuntimeMetadata::scale_encoded`]. `None` if the format of the
+    /// metadata isn't supported.
+    pub decoded: Option<metadata::Metadata>,
+}
+
+/// Error potentially returned by [`Client::runtime_metadata`].
+#[derive(Debug, derive_more::Display)]
+pub enum RuntimeMetadataError {
+    /// Failed to call the `Metadata_metadata` runtime function.
+    #[display(fmt = "{_0}")]
+    Call(RuntimeCallError),
+    /// The output of the `Metadata_metadata` runtime function doesn't start with a valid length
+    /// prefix.
+    #[display(fmt = "Invalid metadata length prefix: {_0}")]
+    InvalidLengthPrefix(json_rpc::methods::RemoveMetadataLengthPrefixError),
+}
+
+/// Fees of a transaction. See [`Client::estimate_fee`].
+#[derive(Debug, Clone)]
+pub struct FeeEstimate {
+    /// Output of the `TransactionPaymentApi_query_info` runtime function. Contains the weight
+    /// of the transaction and the total of the fees, excluding the tip.
+    pub dispatch_info: json_rpc::methods::RuntimeDispatchInfo,
+    /// Output of the `TransactionPaymentApi_query_fee_details` runtime function. Contains the
+    /// breakdown of the fees.
+    pub fee_details: json_rpc::payment_info::FeeDetails,
+}
+
+/// Error potentially returned by [`Client::estimate_fee`].
+#[derive(Debug, derive_more::Display)]
+pub enum EstimateFeeError {
+    /// Failed to call one of the runtime functions.
+    #[display(fmt = "{_0}")]
+    Call(RuntimeCallError),
+    /// The runtime doesn't support the `TransactionPaymentApi` API.
+    #[display(fmt = "Runtime doesn't support the TransactionPaymentApi API")]
+    ApiNotFound,
+    /// Failed to decode the output of one of the runtime functions.
+    #[display(fmt = "Failed to decode runtime output: {_0}")]
+    Decode(json_rpc::payment_info::DecodeError),
+}
+
+/// Parses an address of the form `<multiaddr>/p2p/<peer_id>`, as found for example in the list
+/// of bootnodes of chain specifications.
+fn parse_node_address(address: &str) -> Option<(peer_id::PeerId, multiaddr::Multiaddr)> {
+    let mut addr = address.parse::<multiaddr::Multiaddr>().ok()?;
+    let peer_id = match addr.iter().last() {
+        Some(multiaddr::ProtocolRef::P2p(peer_id)) => {
+            peer_id::PeerId::from_bytes(peer_id.to_vec()).ok()?
+        }
+        _ => return None,
+    };
+    addr.pop();
+    Some((peer_id, addr))
+}
+
+/// Periodically checks whether the given chain is connected to at least one peer, and adds
+/// back the given bootstrap nodes to the network service if it isn't.
+///
+/// Never returns. Must be cancelled by dropping the future when the chain is removed.
+async fn auto_recover_chain<TPlat: platform::Platform>(
+    log_name: String,
+    services: ChainServices<TPlat>,
+    bootstrap_nodes: Vec<(peer_id::PeerId, Vec<multiaddr::Multiaddr>)>,
+    config: AutoRecoverConfig,
+) {
+    let mut backoff = config.min_backoff;
+
+    loop {
+        TPlat::sleep(backoff).await;
+
+        let num_peers = services.sync_service.syncing_peers().await.len();
+        if num_peers != 0 {
+            backoff = config.min_backoff;
+            continue;
+        }
+
+        backoff = cmp::min(backoff.saturating_mul(2), config.max_backoff);
+
+        log::warn!(
+            target: "smoldot",
+            "Chain {} isn't connected to any peer. Adding back its bootnodes and reserved \
+            nodes. Next attempt in {:?}.",
+            log_name, backoff
+        );
+
+        services
+            .network_service
+            .discover(&TPlat::now(), 0, bootstrap_nodes.iter().cloned(), true)
+            .await;
+        services.network_service.discover_reserved_peers(0).await;
+    }
+}
+
+/// Builds the stream returned by [`Client::chain_health`].
+fn chain_health_stream<TPlat: platform::Platform>(
+    services: ChainServices<TPlat>,
+) -> impl Stream<Item = ChainHealth> {
+    struct State<TPlat: platform::Platform> {
+        services: ChainServices<TPlat>,
+        /// `None` if a new subscription must be started.
+        new_blocks: Option<mpsc::Receiver<sync_service::Notification>>,
+        finalized_block: (u64, [u8; 32]),
+        best_block_hash: [u8; 32],
+        /// Heights of all the non-finalized blocks, indexed by their hash.
+        non_finalized_blocks: HashMap<[u8; 32], u64, fnv::FnvBuildHasher>,
+        last_reported: Option<ChainHealth>,
+    }
+
+    let state = State {
+        services,
+        new_blocks: None,
+        finalized_block: (0, [0; 32]),
+        best_block_hash: [0; 32],
+        non_finalized_blocks: HashMap::with_capacity_and_hasher(16, Default::default()),
+        last_reported: None,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            let block_number_bytes = state.services.block_number_bytes;
+            let block_number = |scale_encoded_header: &[u8]| {
+                header::decode(scale_encoded_header, block_number_bytes)
+                    .ok()
+                    .map(|h| h.number)
+            };
+
+            if let Some(new_blocks) = state.new_blocks.as_mut() {
+                // Wait for either a block notification or for some time to pass, in order to
+                // detect changes in the number of peers.
+                let notification =
+                    match future::select(new_blocks.next(), TPlat::sleep(Duration::from_secs(5)))
+                        .await
+                    {
+                        future::Either::Left((notification, _)) => Some(notification),
+                        future::Either::Right(((), _)) => None,
+                    };
+
+                match notification {
+                    None => {}
+                    Some(None) => {
+                        // Subscription has been closed by the sync service, for example because
+                        // the channel was full. Subscribe again.
+                        state.new_blocks = None;
+                        continue;
+                    }
+                    Some(Some(sync_service::Notification::Block(block))) => {
+                        let hash =
+                            header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                        if let Some(number) = block_number(&block.scale_encoded_header) {
+                            state.non_finalized_blocks.insert(hash, number);
+                        }
+                        if block.is_new_best {
+                            state.best_block_hash = hash;
+                        }
+                    }
+                    Some(Some(sync_service::Notification::Finalized {
+                        hash,
+                        best_block_hash,
+                    })) => {
+                        if let Some(number) = state.non_finalized_blocks.get(&hash) {
+                            state.finalized_block = (*number, hash);
+                        }
+                        let finalized_number = state.finalized_block.0;
+                        state
+                            .non_finalized_blocks
+                            .retain(|_, number| *number > finalized_number);
+                        state.best_block_hash = best_block_hash;
+                    }
+                    Some(Some(sync_service::Notification::BestBlockChanged { hash })) => {
+                        state.best_block_hash = hash;
+                    }
+                }
+            } else {
+                let subscription = state.services.sync_service.subscribe_all(32, false).await;
+                let finalized_hash = header::hash_from_scale_encoded_header(
+                    &subscription.finalized_block_scale_encoded_header,
+                );
+                state.finalized_block = (
+                    block_number(&subscription.finalized_block_scale_encoded_header).unwrap_or(0),
+                    finalized_hash,
+                );
+                state.best_block_hash = finalized_hash;
+                state.non_finalized_blocks.clear();
+                for block in subscription.non_finalized_blocks_ancestry_order {
+                    let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                    if let Some(number) = block_number(&block.scale_encoded_header) {
+                        state.non_finalized_blocks.insert(hash, number);
+                    }
+                    if block.is_new_best {
+                        state.best_block_hash = hash;
+                    }
+                }
+                state.new_blocks = Some(subscription.new_blocks);
+            }
+
+            let best_block_number = if state.best_block_hash == state.finalized_block.1 {
+                state.finalized_block.0
+            } else {
+                state
+                    .non_finalized_blocks
+                    .get(&state.best_block_hash)
+                    .copied()
+                    .unwrap_or(state.finalized_block.0)
+            };
+
+            let health = ChainHealth {
+                peers: state.services.sync_service.syncing_peers().await.len(),
+                is_syncing: !state
+                    .services
+                    .runtime_service
+                    .is_near_head_of_chain_heuristic()
+                    .await,
+                best_block_number,
+                best_block_hash: state.best_block_hash,
+                finalized_block_number: state.finalized_block.0,
+                finalized_block_hash: state.finalized_block.1,
+            };
+
+            if state.last_reported.as_ref() != Some(&health) {
+                state.last_reported = Some(health.clone());
+                break Some((health, state));
+            }
+        }
+    })
+}
+
+/// Implementation of [`Client::sync_progress`].
+fn sync_progress_stream<TPlat: platform::Platform>(
+    services: ChainServices<TPlat>,
+) -> impl Stream<Item = SyncProgress> {
+    // `None` once `Finished` has been reported, in order to end the stream.
+    let state = Some((services, None, None::<SyncProgress>));
+
+    stream::unfold(state, |state| async move {
+        let (services, mut subscription, mut last_reported) = state?;
+        loop {
+            let receiver = match &mut subscription {
+                Some(receiver) => receiver,
+                None => {
+                    subscription.insert(services.sync_service.subscribe_sync_progress(16).await)
+                }
+            };
+
+            // The channel is closed if it was full, in which case we subscribe again. The new
+            // subscription starts by yielding the current progress.
+            let Some(progress) = receiver.next().await else {
+                subscription = None;
+                continue;
+            };
+
+            if last_reported.as_ref() == Some(&progress) {
+                continue;
+            }
+
+            if progress == SyncProgress::Finished {
+                break Some((progress, None));
+            }
+
+            last_reported = Some(progress.clone());
+            break Some((progress, Some((services, subscription, last_reported))));
+        }
+    })
+}
+
+/// Calls the [`ChainEventHooks`] of a chain whenever the corresponding events happen. Never
+/// returns.
+async fn run_chain_event_hooks<TPlat: platform::Platform>(
+    services: ChainServices<TPlat>,
+    hooks: ChainEventHooks,
+) {
+    let events = chain_events_stream(services);
+    futures::pin_mut!(events);
+    while let Some(event) = events.next().await {
+        match event {
+            ChainEvent::NewBestBlock(block) => {
+                if let Some(on_new_best_block) = &hooks.on_new_best_block {
+                    on_new_best_block(&block);
+                }
+            }
+            ChainEvent::Finalized(block) => {
+                if let Some(on_finalized) = &hooks.on_finalized {
+                    on_finalized(&block);
+                }
+            }
+            ChainEvent::RuntimeVersionChange(change) => {
+                if let Some(on_runtime_upgrade) = &hooks.on_runtime_upgrade {
+                    on_runtime_upgrade(&change);
+                }
+            }
+        }
+    }
+}
+
+/// Event generated by [`chain_events_stream`].
+enum ChainEvent {
+    NewBestBlock(BlockEvent),
+    Finalized(BlockEvent),
+    RuntimeVersionChange(RuntimeVersionChange),
+}
+
+/// Builds a stream of the changes to the best block, finalized block, and runtime of the
+/// finalized block of a chain. Used by [`ChainEventHooks`] and [`Client::runtime_version_updates`].
+///
+/// Runtime changes are only reported once the block that contains them is finalized. The stream
+/// never ends.
+fn chain_events_stream<TPlat: platform::Platform>(
+    services: ChainServices<TPlat>,
+) -> impl Stream<Item = ChainEvent> {
+    /// Non-finalized block known by the stream.
+    struct Block {
+        event: BlockEvent,
+        parent_hash: [u8; 32],
+        /// `Some` if the runtime of the block is different from the one of its parent. Contains
+        /// the version and the code hash of the new runtime.
+        new_runtime: Option<(Option<executor::CoreVersion>, Option<[u8; 32]>)>,
+    }
+
+    struct State<TPlat: platform::Platform> {
+        services: ChainServices<TPlat>,
+        /// `None` if a new subscription must be started.
+        new_blocks: Option<runtime_service::Subscription<TPlat>>,
+        /// `None` before the first subscription.
+        finalized: Option<BlockEvent>,
+        best_block_hash: [u8; 32],
+        non_finalized_blocks: HashMap<[u8; 32], Block, fnv::FnvBuildHasher>,
+        /// Version and code hash of the runtime of the finalized block. `None` before the first
+        /// subscription.
+        finalized_runtime: Option<(Option<executor::CoreVersion>, Option<[u8; 32]>)>,
+        /// Hashes of the blocks that have last been reported, in order to not report them again
+        /// after the subscription has been re-opened.
+        reported_best_block_hash: Option<[u8; 32]>,
+        reported_finalized_hash: Option<[u8; 32]>,
+        /// Events waiting to be yielded.
+        pending: VecDeque<ChainEvent>,
+    }
+
+    let state = State {
+        services,
+        new_blocks: None,
+        finalized: None,
+        best_block_hash: [0; 32],
+        non_finalized_blocks: HashMap::with_capacity_and_hasher(16, Default::default()),
+        finalized_runtime: None,
+        reported_best_block_hash: None,
+        reported_finalized_hash: None,
+        pending: VecDeque::new(),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                break Some((event, state));
+            }
+
+            let block_number_bytes = state.services.block_number_bytes;
+            let block_event = |scale_encoded_header: Vec<u8>| BlockEvent {
+                number: header::decode(&scale_encoded_header, block_number_bytes)
+                    .map_or(0, |h| h.number),
+                hash: header::hash_from_scale_encoded_header(&scale_encoded_header),
+                scale_encoded_header,
+            };
+
+            // Note that blocks are unpinned immediately, as the runtimes of the blocks are never
+            // accessed.
+            if let Some(new_blocks) = state.new_blocks.as_mut() {
+                match new_blocks.next().await {
+                    None => {
+                        // Subscription has been closed by the runtime service, for example
+                        // because the channel was full. Subscribe again.
+                        state.new_blocks = None;
+                        continue;
+                    }
+                    Some(runtime_service::Notification::Block(block)) => {
+                        let event = block_event(block.scale_encoded_header);
+                        new_blocks.unpin_block(&event.hash).await;
+                        if block.is_new_best {
+                            state.best_block_hash = event.hash;
+                        }
+                        state.non_finalized_blocks.insert(
+                            event.hash,
+                            Block {
+                                event,
+                                parent_hash: block.parent_hash,
+                                new_runtime: block
+                                    .new_runtime
+                                    .map(|rt| (rt.ok(), block.new_runtime_code_hash)),
+                            },
+                        );
+                    }
+                    Some(runtime_service::Notification::BestBlockChanged { hash }) => {
+                        state.best_block_hash = hash;
+                    }
+                    Some(runtime_service::Notification::Finalized {
+                        hash,
+                        best_block_hash,
+                        pruned_blocks,
+                    }) => {
+                        // Gather all the newly-finalized blocks, from the highest to the lowest.
+                        let mut newly_finalized = Vec::new();
+                        let mut iter = hash;
+                        while let Some(block) = state.non_finalized_blocks.remove(&iter) {
+                            iter = block.parent_hash;
+                            newly_finalized.push(block);
+                        }
+                        for pruned in pruned_blocks {
+                            state.non_finalized_blocks.remove(&pruned);
+                        }
+
+                        for block in newly_finalized.iter().rev() {
+                            if let Some((new_version, code_hash)) = &block.new_runtime {
+                                let previous = state
+                                    .finalized_runtime
+                                    .replace((new_version.clone(), *code_hash));
+                                state.pending.push_back(ChainEvent::RuntimeVersionChange(
+                                    RuntimeVersionChange {
+                                        previous_version: previous.and_then(|(v, _)| v),
+                                        new_version: new_version.clone(),
+                                        block: block.event.clone(),
+                                        code_hash: *code_hash,
+                                    },
+                                ));
+                            }
+                        }
+
+                        if let Some(block) = newly_finalized.into_iter().next() {
+                            state.finalized = Some(block.event);
+                        }
+                        state.best_block_hash = best_block_hash;
+                    }
+                }
+            } else {
+                let subscription = state
+                    .services
+                    .runtime_service
+                    .subscribe_all("chain-events", 32, NonZeroUsize::new(32).unwrap())
+                    .await;
+
+                let finalized = block_event(subscription.finalized_block_scale_encoded_header);
+                subscription.new_blocks.unpin_block(&finalized.hash).await;
+
+                // Runtime changes that have happened while no subscription was open are
+                // reported as if they happened at the current finalized block.
+                let finalized_runtime = (
+                    subscription.finalized_block_runtime.ok(),
+                    subscription.finalized_block_runtime_code_hash,
+                );
+                if let Some((previous_version, previous_code_hash)) = state.finalized_runtime.take()
+                {
+                    if previous_code_hash != finalized_runtime.1 {
+                        state.pending.push_back(ChainEvent::RuntimeVersionChange(
+                            RuntimeVersionChange {
+                                previous_version,
+                                new_version: finalized_runtime.0.clone(),
+                                block: finalized.clone(),
+                                code_hash: finalized_runtime.1,
+                            },
+                        ));
+                    }
+                }
+                state.finalized_runtime = Some(finalized_runtime);
+
+                state.best_block_hash = finalized.hash;
+                state.non_finalized_blocks.clear();
+                for block in subscription.non_finalized_blocks_ancestry_order {
+                    let event = block_event(block.scale_encoded_header);
+                    subscription.new_blocks.unpin_block(&event.hash).await;
+                    if block.is_new_best {
+                        state.best_block_hash = event.hash;
+                    }
+                    state.non_finalized_blocks.insert(
+                        event.hash,
+                        Block {
+                            event,
+                            parent_hash: block.parent_hash,
+                            new_runtime: block
+                                .new_runtime
+                                .map(|rt| (rt.ok(), block.new_runtime_code_hash)),
+                        },
+                    );
+                }
+
+                state.finalized = Some(finalized);
+                state.new_blocks = Some(subscription.new_blocks);
+            }
+
+            let finalized = state.finalized.as_ref().unwrap();
+            if state.reported_finalized_hash != Some(finalized.hash) {
+                state.reported_finalized_hash = Some(finalized.hash);
+                state
+                    .pending
+                    .push_back(ChainEvent::Finalized(finalized.clone()));
+            }
+
+            if state.reported_best_block_hash != Some(state.best_block_hash) {
+                let best = if state.best_block_hash == finalized.hash {
+                    Some(finalized)
+                } else {
+                    state
+                        .non_finalized_blocks
+                        .get(&state.best_block_hash)
+                        .map(|b| &b.event)
+                };
+                if let Some(best) = best {
+                    state.reported_best_block_hash = Some(state.best_block_hash);
+                    state
+                        .pending
+                        .push_back(ChainEvent::NewBestBlock(best.clone()));
+                }
+            }
+        }
+    })
+}
+
+/// Builds the stream returned by [`Client::subscribe_storage`].
+fn storage_changes_stream<TPlat: platform::Platform>(
+    services: ChainServices<TPlat>,
+    keys: Vec<Vec<u8>>,
+) -> impl Stream<Item = StorageChangeSet> {
+    struct State<TPlat: platform::Platform> {
+        services: ChainServices<TPlat>,
+        keys: Vec<Vec<u8>>,
+        /// `None` if a new subscription must be started.
+        new_blocks: Option<mpsc::Receiver<sync_service::Notification>>,
+        finalized_block_hash: [u8; 32],
+        best_block_hash: [u8; 32],
+        /// Height and state trie root hash of the finalized block and all the non-finalized
+        /// blocks, indexed by their hash.
+        blocks: HashMap<[u8; 32], (u64, [u8; 32]), fnv::FnvBuildHasher>,
+        /// Hash of the latest block whose storage has been successfully queried, and values of
+        /// the keys at this block. `None` if nothing has been reported yet.
+        last_reported: Option<([u8; 32], Vec<Option<Vec<u8>>>)>,
+    }
+
+    let state = State {
+        services,
+        keys,
+        new_blocks: None,
+        finalized_block_hash: [0; 32],
+        best_block_hash: [0; 32],
+        blocks: HashMap::with_capacity_and_hasher(16, Default::default()),
+        last_reported: None,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            let block_number_bytes = state.services.block_number_bytes;
+            let decode = |scale_encoded_header: &[u8]| {
+                header::decode(scale_encoded_header, block_number_bytes)
+                    .ok()
+                    .map(|h| (h.number, *h.state_root))
+            };
+
+            if let Some(new_blocks) = state.new_blocks.as_mut() {
+                match new_blocks.next().await {
+                    None => {
+                        // Subscription has been closed by the sync service, for example because
+                        // the channel was full. Subscribe again.
+                        state.new_blocks = None;
+                        continue;
+                    }
+                    Some(sync_service::Notification::Block(block)) => {
+                        let hash =
+                            header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                        if let Some(info) = decode(&block.scale_encoded_header) {
+                            state.blocks.insert(hash, info);
+                        }
+                        if block.is_new_best {
+                            state.best_block_hash = hash;
+                        }
+                    }
+                    Some(sync_service::Notification::Finalized {
+                        hash,
+                        best_block_hash,
+                    }) => {
+                        state.finalized_block_hash = hash;
+                        if let Some((finalized_number, _)) = state.blocks.get(&hash).copied() {
+                            state
+                                .blocks
+                                .retain(|h, (number, _)| *number > finalized_number || *h == hash);
+                        }
+                        state.best_block_hash = best_block_hash;
+                    }
+                    Some(sync_service::Notification::BestBlockChanged { hash }) => {
+                        state.best_block_hash = hash;
+                    }
+                }
+            } else {
+                let subscription = state.services.sync_service.subscribe_all(32, false).await;
+                state.finalized_block_hash = header::hash_from_scale_encoded_header(
+                    &subscription.finalized_block_scale_encoded_header,
+                );
+                state.best_block_hash = state.finalized_block_hash;
+                state.blocks.clear();
+                if let Some(info) = decode(&subscription.finalized_block_scale_encoded_header) {
+                    state.blocks.insert(state.finalized_block_hash, info);
+                }
+                for block in subscription.non_finalized_blocks_ancestry_order {
+                    let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                    if let Some(info) = decode(&block.scale_encoded_header) {
+                        state.blocks.insert(hash, info);
+                    }
+                    if block.is_new_best {
+                        state.best_block_hash = hash;
+                    }
+                }
+                state.new_blocks = Some(subscription.new_blocks);
+            }
+
+            if state
+                .last_reported
+                .as_ref()
+                .map_or(false, |(hash, _)| *hash == state.best_block_hash)
+            {
+                continue;
+            }
+
+            let Some((block_number, state_trie_root_hash)) =
+                state.blocks.get(&state.best_block_hash).copied()
+            else {
+                continue;
+            };
+
+            let values = match state
+                .services
+                .sync_service
+                .clone()
+                .storage_query(
+                    block_number,
+                    &state.best_block_hash,
+                    &state_trie_root_hash,
+                    state.keys.iter(),
+                    3,
+                    Duration::from_secs(20),
+                    NonZeroU32::new(1).unwrap(),
+                )
+                .await
+            {
+                Ok(values) => values,
+                Err(error) => {
+                    log::debug!(
+                        target: "smoldot",
+                        "Failed to query storage of block {}: {}",
+                        HashDisplay(&state.best_block_hash), error
+                    );
+                    continue;
+                }
+            };
+
+            let changes = state
+                .keys
+                .iter()
+                .zip(values.iter())
+                .enumerate()
+                .filter(|(index, _)| {
+                    state
+                        .last_reported
+                        .as_ref()
+                        .map_or(true, |(_, previous)| previous[*index] != values[*index])
+                })
+                .map(|(_, (key, value))| (key.clone(), value.clone()))
+                .collect::<Vec<_>>();
+            let is_first = state.last_reported.is_none();
+            let block_hash = state.best_block_hash;
+            state.last_reported = Some((block_hash, values));
+
+            if is_first || !changes.is_empty() {
+                break Some((
+                    StorageChangeSet {
+                        block_hash,
+                        changes,
+                    },
+                    state,
+                ));
+            }
+        }
+    })
+}
+
+/// Applies the deltas of the given stream to the given database, and stores the updated
+/// database in the storage. Stops when the stream ends, in other words when the chain is
+/// removed.
+async fn persist_database<TPlat: platform::Platform>(
+    config: DatabaseStorageConfig,
+    chain_key: String,
+    mut database: String,
+    database_updates: impl Stream<Item = DatabaseDelta>,
+) {
+    futures::pin_mut!(database_updates);
+
+    let mut next_store = TPlat::now();
+
+    // Deltas that haven't been applied to `database` yet. They are only applied right before
+    // the database is stored, in order to not decode and encode the entire database every time
+    // a block is finalized.
+    let mut pending_deltas = Vec::<DatabaseDelta>::new();
+
+    loop {
+        // Wait either for a new delta or, if the database has changed, for the moment when the
+        // database can be stored.
+        let next_delta = {
+            let wait_store = async {
+                if !pending_deltas.is_empty() {
+                    TPlat::sleep_until(next_store.clone()).await
+                } else {
+                    future::pending().await
+                }
+            };
+            futures::pin_mut!(wait_store);
+            match future::select(database_updates.next(), wait_store).await {
+                future::Either::Left((delta, _)) => Some(delta),
+                future::Either::Right(((), _)) => None,
+            }
+        };
+
+        match next_delta {
+            Some(Some(delta)) => {
+                pending_deltas.push(delta);
+                if TPlat::now() < next_store {
+                    continue;
+                }
+            }
+            Some(None) => {
+                // The chain has been removed.
+                if !pending_deltas.is_empty() {
+                    database = compact_database(&database, pending_deltas.drain(..));
+                    config.storage.store(&chain_key, &database);
+                }
+                break;
+            }
+            None => {}
+        }
+
+        database = compact_database(&database, pending_deltas.drain(..));
+        config.storage.store(&chain_key, &database);
+        next_store = TPlat::now() + config.store_interval;
+    }
+}
+
+/// Builds the stream returned by [`Client::database_updates`].
+fn database_deltas_stream<TPlat: platform::Platform>(
+    services: ChainServices<TPlat>,
+    genesis_block_hash: [u8; 32],
+) -> impl Stream<Item = DatabaseDelta> {
+    struct State<TPlat: platform::Platform> {
+        services: ChainServices<TPlat>,
+        genesis_block_hash: [u8; 32],
+        /// `None` if a new subscription must be started.
+        new_blocks: Option<mpsc::Receiver<sync_service::Notification>>,
+        finalized_block_hash: [u8; 32],
+        /// Height and SCALE-encoded header of all the non-finalized blocks, indexed by their
+        /// hash.
+        blocks: HashMap<[u8; 32], (u64, Vec<u8>), fnv::FnvBuildHasher>,
+        /// What has been reported through the previous deltas.
+        delta_state: database::DatabaseDeltaState,
+    }
+
+    let state = State {
+        services,
+        genesis_block_hash,
+        new_blocks: None,
+        finalized_block_hash: [0; 32],
+        blocks: HashMap::with_capacity_and_hasher(16, Default::default()),
+        delta_state: database::DatabaseDeltaState::default(),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            let block_number_bytes = state.services.block_number_bytes;
+
+            // Headers of the blocks finalized since the previous delta, in decreasing order.
+            let mut new_finalized_headers = Vec::new();
+
+            if let Some(new_blocks) = state.new_blocks.as_mut() {
+                match new_blocks.next().await {
+                    None => {
+                        // Subscription has been closed by the sync service, for example because
+                        // the channel was full. Subscribe again.
+                        state.new_blocks = None;
+                        continue;
+                    }
+                    Some(sync_service::Notification::Block(block)) => {
+                        let hash =
+                            header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                        if let Ok(decoded) =
+                            header::decode(&block.scale_encoded_header, block_number_bytes)
+                        {
+                            state
+                                .blocks
+                                .insert(hash, (decoded.number, block.scale_encoded_header));
+                        }
+                        continue;
+                    }
+                    Some(sync_service::Notification::BestBlockChanged { .. }) => continue,
+                    Some(sync_service::Notification::Finalized { hash, .. }) => {
+                        let mut iter_hash = hash;
+                        while iter_hash != state.finalized_block_hash {
+                            let Some((_, header)) = state.blocks.remove(&iter_hash) else {
+                                break;
+                            };
+                            iter_hash = *header::decode(&header, block_number_bytes)
+                                .unwrap()
+                                .parent_hash;
+                            new_finalized_headers.push(header);
+                        }
+
+                        if let Some(finalized_number) = new_finalized_headers
+                            .first()
+                            .map(|h| header::decode(h, block_number_bytes).unwrap().number)
+                        {
+                            state
+                                .blocks
+                                .retain(|_, (number, _)| *number > finalized_number);
+                        }
+                        state.finalized_block_hash = hash;
+                    }
+                }
+            } else {
+                let subscription = state.services.sync_service.subscribe_all(32, false).await;
+                state.finalized_block_hash = header::hash_from_scale_encoded_header(
+                    &subscription.finalized_block_scale_encoded_header,
+                );
+                state.blocks.clear();
+                for block in subscription.non_finalized_blocks_ancestry_order {
+                    let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                    if let Ok(decoded) =
+                        header::decode(&block.scale_encoded_header, block_number_bytes)
+                    {
+                        state
+                            .blocks
+                            .insert(hash, (decoded.number, block.scale_encoded_header));
+                    }
+                }
+                state.new_blocks = Some(subscription.new_blocks);
+
+                // A delta is generated immediately after subscribing, as blocks might have been
+                // finalized while not subscribed.
+                new_finalized_headers.push(subscription.finalized_block_scale_encoded_header);
+            }
+
+            let delta = database::encode_database_delta(
+                &state.services.network_service,
+                &state.services.sync_service,
+                &state.genesis_block_hash,
+                new_finalized_headers.iter().rev(),
+                state
+                    .services
+                    .runtime_service
+                    .finalized_runtime_code_hash()
+                    .await,
+                &mut state.delta_state,
+            )
+            .await;
+
+            if let Some(delta) = delta {
+                break Some((delta, state));
+            }
+        }
+    })
+}
+
+/// Builds the stream returned by [`Client::submit_transaction`].
+fn transaction_status_stream(
+    updates: mpsc::Receiver<transactions_service::TransactionStatus>,
+) -> impl Stream<Item = TransactionStatus> {
+    struct State {
+        /// `None` if the last item has been produced.
+        updates: Option<mpsc::Receiver<transactions_service::TransactionStatus>>,
+        num_broadcasted_peers: usize,
+        included_block: Option<[u8; 32]>,
+    }
+
+    let state = State {
+        updates: Some(updates),
+        num_broadcasted_peers: 0,
+        included_block: None,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            // If the channel is closed without a final update, it has become full and the
+            // transactions service has given up on it.
+            let update = state.updates.as_mut()?.next().await.unwrap_or(
+                transactions_service::TransactionStatus::Dropped(
+                    transactions_service::DropReason::MaxPendingTransactionsReached,
+                ),
+            );
+
+            let status = match update {
+                transactions_service::TransactionStatus::Validated => TransactionStatus::Validated,
+                transactions_service::TransactionStatus::Broadcast(peers) => {
+                    state.num_broadcasted_peers += peers.len();
+                    TransactionStatus::Broadcast(state.num_broadcasted_peers)
+                }
+                transactions_service::TransactionStatus::IncludedBlockUpdate {
+                    block_hash: Some((block_hash, _)),
+                } => {
+                    state.included_block = Some(block_hash);
+                    TransactionStatus::InBlock(block_hash)
+                }
+                transactions_service::TransactionStatus::IncludedBlockUpdate {
+                    block_hash: None,
+                } => match state.included_block.take() {
+                    Some(block_hash) => TransactionStatus::Retracted(block_hash),
+                    None => continue,
+                },
+                transactions_service::TransactionStatus::Dropped(reason) => {
+                    state.updates = None;
+                    match reason {
+                        transactions_service::DropReason::Finalized { block_hash, .. } => {
+                            TransactionStatus::Finalized(block_hash)
+                        }
+                        transactions_service::DropReason::Invalid(error) => {
+                            TransactionStatus::Invalid(error)
+                        }
+                        transactions_service::DropReason::GapInChain
+                        | transactions_service::DropReason::MaxPendingTransactionsReached
+                        | transactions_service::DropReason::ValidateError(_)
+                        | transactions_service::DropReason::Mortality => TransactionStatus::Dropped,
+                    }
+                }
+            };
+
+            break Some((status, state));
+        }
+    })
+}
+
+/// Obtains a proof of the ownership of the key of the authority that has equivocated, then
+/// submits a transaction reporting the equivocation. Returns the transaction that has been
+/// submitted.
+async fn report_equivocation<TPlat: platform::Platform>(
+    services: &ChainServices<TPlat>,
+    equivocation: &grandpa_equivocation::Equivocation,
+    submission: EquivocationReportSubmission,
+) -> Result<Vec<u8>, String> {
+    let finalized_block_hash = services
+        .sync_service
+        .serialize_chain_information()
+        .await
+        .ok_or_else(|| "Finalized block unknown".to_owned())?
+        .as_ref()
+        .finalized_block_header
+        .hash(services.block_number_bytes);
+
+    let parameters = grandpa_equivocation::generate_key_ownership_proof_parameters(
+        equivocation.set_id,
+        &equivocation.authority_public_key,
+    )
+    .fold(Vec::new(), |mut acc, item| {
+        acc.extend_from_slice(item.as_ref());
+        acc
+    });
+    let output = runtime_call(
+        services,
+        &finalized_block_hash,
+        "GrandpaApi_generate_key_ownership_proof",
+        &parameters,
+    )
+    .await
+    .map_err(|error| error.to_string())?;
+    let key_owner_proof = grandpa_equivocation::decode_generate_key_ownership_proof_output(&output)
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| "Runtime couldn't generate key ownership proof".to_owned())?;
+
+    let transaction = grandpa_equivocation::report_equivocation_unsigned_extrinsic(
+        equivocation,
+        services.block_number_bytes,
+        submission.grandpa_pallet_index,
+        submission.report_equivocation_unsigned_call_index,
+        key_owner_proof,
+    );
+    services
+        .transactions_service
+        .submit_transaction(transaction.clone())
+        .await;
+    Ok(transaction)
+}
+
+/// Implementation of [`Client::next_nonce`].
+async fn next_nonce<TPlat: platform::Platform>(
+    services: &ChainServices<TPlat>,
+    account: Vec<u8>,
+) -> Result<u64, NextNonceError> {
+    let (best_block_hash, best_block_number, best_block_state_root) = {
+        let subscribe_all = services.sync_service.subscribe_all(1, false).await;
+        let best_block_header = subscribe_all
+            .non_finalized_blocks_ancestry_order
+            .into_iter()
+            .find(|block| block.is_new_best)
+            .map_or(
+                subscribe_all.finalized_block_scale_encoded_header,
+                |block| block.scale_encoded_header,
+            );
+        // The sync service only ever reports valid headers.
+        let decoded = header::decode(&best_block_header, services.block_number_bytes).unwrap();
+        (
+            header::hash_from_scale_encoded_header(&best_block_header),
+            decoded.number,
+            *decoded.state_root,
+        )
+    };
+
+    // The key is `twox128("System") ++ twox128("Account") ++ blake2_128(account) ++ account`.
+    let key = {
+        const SYSTEM_ACCOUNT_PREFIX: [u8; 32] = [
+            0x26, 0xaa, 0x39, 0x4e, 0xea, 0x56, 0x30, 0xe0, 0x7c, 0x48, 0xae, 0x0c, 0x95, 0x58,
+            0xce, 0xf7, 0xb9, 0x9d, 0x88, 0x0e, 0xc6, 0x81, 0x79, 0x9c, 0x0c, 0xf3, 0x0e, 0x88,
+            0x86, 0x37, 0x1d, 0xa9,
+        ];
+        let mut key = Vec::with_capacity(SYSTEM_ACCOUNT_PREFIX.len() + 16 + account.len());
+        key.extend_from_slice(&SYSTEM_ACCOUNT_PREFIX);
+        key.extend_from_slice(blake2_rfc::blake2b::blake2b(16, &[], &account).as_bytes());
+        key.extend_from_slice(&account);
+        key
+    };
+
+    let on_chain_nonce = {
+        let value = services
+            .sync_service
+            .clone()
+            .storage_query(
+                best_block_number,
+                &best_block_hash,
+                &best_block_state_root,
+                iter::once(&key),
+                3,
+                Duration::from_secs(8),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await
+            .map_err(|err| NextNonceError::StorageQueryFailed(err.to_string()))?
+            .pop()
+            .unwrap();
+
+        // The `AccountInfo` struct starts with the nonce, as a `u32`. An account that doesn't
+        // exist has a nonce of 0.
+        match value {
+            Some(value) => u64::from(u32::from_le_bytes(
+                <[u8; 4]>::try_from(value.get(..4).ok_or(NextNonceError::InvalidAccountInfo)?)
+                    .unwrap(),
+            )),
+            None => 0,
+        }
+    };
+
+    let pending_nonce = services
+        .transactions_service
+        .highest_pending_nonce(account)
+        .await;
+
+    Ok(cmp::max(
+        on_chain_nonce,
+        pending_nonce.map_or(0, |n| n.saturating_add(1)),
+    ))
+}
+
+/// Implementation of [`Client::runtime_call`].
+async fn runtime_call<TPlat: platform::Platform>(
+    services: &ChainServices<TPlat>,
+    block_hash: &[u8; 32],
+    function: &str,
+    parameters: &[u8],
+) -> Result<Vec<u8>, RuntimeCallError> {
+    let precall = runtime_lock(services, block_hash).await?;
+    runtime_call_with_lock(&precall, function, parameters).await
+}
+
+/// Implementation of [`Client::runtime_metadata`].
+async fn runtime_metadata<TPlat: platform::Platform>(
+    services: &ChainServices<TPlat>,
+    block_hash: &[u8; 32],
+) -> Result<RuntimeMetadata, RuntimeMetadataError> {
+    let precall = runtime_lock(services, block_hash)
+        .await
+        .map_err(RuntimeMetadataError::Call)?;
+
+    // The metadata is cached alongside with the runtime, and is thus downloaded only once per
+    // runtime.
+    let scale_encoded = if let Some(cached) = precall.cached_metadata().await {
+        cached
+    } else {
+        let output = runtime_call_with_lock(&precall, "Metadata_metadata", &[])
+            .await
+            .map_err(RuntimeMetadataError::Call)?;
+        let metadata = Arc::<[u8]>::from(
+            json_rpc::methods::remove_metadata_length_prefix(&output)
+                .map_err(RuntimeMetadataError::InvalidLengthPrefix)?,
+        );
+        precall.cache_metadata(metadata.clone()).await;
+        metadata
+    };
+
+    let decoded = metadata::decode(&scale_encoded).ok();
+    Ok(RuntimeMetadata {
+        scale_encoded,
+        decoded,
+    })
+}
+
+/// Implementation of [`Client::estimate_fee`].
+async fn estimate_fee<TPlat: platform::Platform>(
+    services: &ChainServices<TPlat>,
+    transaction: &[u8],
+    block_hash: &[u8; 32],
+) -> Result<FeeEstimate, EstimateFeeError> {
+    let precall = runtime_lock(services, block_hash)
+        .await
+        .map_err(EstimateFeeError::Call)?;
+
+    let api_version = precall
+        .specification()
+        .map_err(|err| EstimateFeeError::Call(RuntimeCallError::Call(err.to_string())))?
+        .decode()
+        .apis
+        .find_version("TransactionPaymentApi")
+        .ok_or(EstimateFeeError::ApiNotFound)?;
+
+    // Both runtime functions accept the same parameters.
+    let parameters = json_rpc::payment_info::payment_info_parameters(transaction).fold(
+        Vec::with_capacity(transaction.len() + 4),
+        |mut params, chunk| {
+            params.extend_from_slice(chunk.as_ref());
+            params
+        },
+    );
+
+    let dispatch_info = {
+        let output = runtime_call_with_lock(
+            &precall,
+            json_rpc::payment_info::PAYMENT_FEES_FUNCTION_NAME,
+            &parameters,
+        )
+        .await
+        .map_err(EstimateFeeError::Call)?;
+        json_rpc::payment_info::decode_payment_info(&output, api_version)
+            .map_err(EstimateFeeError::Decode)?
+    };
+
+    let fee_details = {
+        let output = runtime_call_with_lock(
+            &precall,
+            json_rpc::payment_info::FEE_DETAILS_FUNCTION_NAME,
+            &parameters,
+        )
+        .await
+        .map_err(EstimateFeeError::Call)?;
+        json_rpc::payment_info::decode_fee_details(&output).map_err(EstimateFeeError::Decode)?
+    };
+
+    Ok(FeeEstimate {
+        dispatch_info,
+        fee_details,
+    })
+}
+
+/// Downloads the header and the runtime code of the given block, then compiles its runtime or
+/// re-uses an identical runtime that has already been compiled, in order to prepare runtime
+/// calls against this block.
+async fn runtime_lock<TPlat: platform::Platform>(
+    services: &ChainServices<TPlat>,
+    block_hash: &[u8; 32],
+) -> Result<runtime_service::RuntimeLock<TPlat>, RuntimeCallError> {
+    // The state trie root and the height of the block are needed in order to request the
+    // runtime code and the call proof.
+    let (state_trie_root_hash, block_number) = {
+        let block = services
+            .sync_service
+            .clone()
+            .block_query_unknown_number(
+                *block_hash,
+                protocol::BlocksRequestFields {
+                    header: true,
+                    body: false,
+                    justifications: false,
+                },
+                4,
+                Duration::from_secs(8),
+                NonZeroU32::new(2).unwrap(),
+            )
+            .await
+            .map_err(|()| RuntimeCallError::HeaderQueryFailed)?;
+        // The `block_query` function guarantees that the header is present and valid.
+        let header = block.header.unwrap();
+        let decoded = header::decode(&header, services.block_number_bytes).unwrap();
+        (*decoded.state_root, decoded.number)
+    };
+
+    let (storage_code, storage_heap_pages) = {
+        let mut code_query_result = services
+            .sync_service
+            .clone()
+            .storage_query(
+                block_number,
+                block_hash,
+                &state_trie_root_hash,
+                [&b":code"[..], &b":heappages"[..]].into_iter(),
+                3,
+                Duration::from_secs(20),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await
+            .map_err(|err| RuntimeCallError::RuntimeCodeQueryFailed(err.to_string()))?;
+        let heap_pages = code_query_result.pop().unwrap();
+        let code = code_query_result.pop().unwrap();
+        (code, heap_pages)
+    };
+
+    // The runtime service re-uses any identical runtime that it already has compiled.
+    let pinned_runtime_id = services
+        .runtime_service
+        .compile_and_pin_runtime(storage_code, storage_heap_pages)
+        .await;
+    let precall = services
+        .runtime_service
+        .pinned_runtime_lock(
+            pinned_runtime_id.clone(),
+            *block_hash,
+            block_number,
+            state_trie_root_hash,
+        )
+        .await;
+    services
+        .runtime_service
+        .unpin_runtime(pinned_runtime_id)
+        .await;
+
+    Ok(precall)
+}
+
+/// Performs a runtime call against the block of the given [`runtime_service::RuntimeLock`].
+async fn runtime_call_with_lock<TPlat: platform::Platform>(
+    precall: &runtime_service::RuntimeLock<TPlat>,
+    function: &str,
+    parameters: &[u8],
+) -> Result<Vec<u8>, RuntimeCallError> {
+    // Identical calls that have recently been performed are answered from the cache of the
+    // runtime service.
+    if let Some(output) = precall
+        .cached_output(function, iter::once(parameters))
+        .await
+    {
+        return Ok(output);
+    }
+
+    let (runtime_call_lock, virtual_machine) = precall
+        .start(
+            function,
+            iter::once(parameters),
+            3,
+            Duration::from_secs(20),
+            NonZeroU32::new(1).unwrap(),
+        )
+        .await
+        .map_err(|err| RuntimeCallError::Call(err.to_string()))?;
+
+    let mut call = match executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine,
+        function_to_call: function,
+        parameter: iter::once(parameters),
+        main_trie_root_calculation_cache: None,
+        storage_main_trie_changes: Default::default(),
+        offchain_storage_changes: Default::default(),
+        max_log_level: 0,
+        offchain_behavior: executor::runtime_host::OffchainBehavior::EmulateInMemory {
+            timestamp_ms: u64::try_from(TPlat::now_from_unix_epoch().as_millis())
+                .unwrap_or(u64::max_value()),
+            random_seed: rand::random(),
+        },
+        tracing: false,
+    }) {
+        Ok(vm) => vm,
+        Err((err, prototype)) => {
+            runtime_call_lock.unlock(prototype);
+            return Err(RuntimeCallError::StartError(err));
+        }
+    };
+
+    loop {
+        match call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                let output = success.virtual_machine.value().as_ref().to_vec();
+                runtime_call_lock.cache_output(&output);
+                runtime_call_lock.unlock(success.virtual_machine.into_prototype());
+                break Ok(output);
+            }
+            executor::runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                runtime_call_lock.unlock(error.prototype);
+                if matches!(
+                    error.detail,
+                    executor::runtime_host::ErrorDetail::WasmVm {
+                        error: executor::host::Error::Trap(executor::vm::Trap::OutOfFuel),
+                        ..
+                    }
+                ) {
+                    break Err(RuntimeCallError::ExecutionTimeout);
+                }
+                break Err(RuntimeCallError::RuntimeError(error.detail));
+            }
+            executor::runtime_host::RuntimeHostVm::StorageGet(get) => {
+                let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
+                let storage_value = match storage_value {
+                    Ok(v) => v,
+                    Err(err) => {
+                        runtime_call_lock.unlock(
+                            executor::runtime_host::RuntimeHostVm::StorageGet(get).into_prototype(),
+                        );
+                        break Err(RuntimeCallError::Call(err.to_string()));
+                    }
+                };
+                call = get.inject_value(storage_value.map(|(val, vers)| (iter::once(val), vers)));
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                call = sig.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(_) => {
+                // Off-chain host functions are configured to be emulated.
+                unreachable!()
+            }
+            call @ (executor::runtime_host::RuntimeHostVm::NextKey(_)
+            | executor::runtime_host::RuntimeHostVm::PrefixKeys(_)) => {
+                runtime_call_lock.unlock(call.into_prototype());
+                break Err(RuntimeCallError::ForbiddenHostFunction);
+            }
+        }
+    }
+}
+
+/// Starts all the services of the client.
+///
+/// Returns some of the services that have been started. If these service get shut down, all the
+/// other services will later shut down as well.
+async fn start_services<TPlat: platform::Platform>(
+    log_name: String,
+    spawn_new_task: Arc<
+        dyn Fn(String, Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
+    >,
+    chain_information: chain::chain_information::ValidChainInformation,
+    genesis_block_scale_encoded_header: Vec<u8>,
+    chain_spec: chain_spec::ChainSpec,
+    relay_chain: Option<&ChainServices<TPlat>>,
+    network_noise_key: connection::NoiseKey,
+    network_out_slots: u32,
+    network_reserved_only: bool,
+    network_serve_warp_sync: bool,
+    network_enable_beefy: bool,
+    (network_upload_limiter, network_download_limiter): (
+        Option<Arc<network_service::BandwidthLimiter<TPlat>>>,
+        Option<Arc<network_service::BandwidthLimiter<TPlat>>>,
+    ),
+    (yamux_max_receive_window, max_inbound_substreams): (u64, Option<usize>),
+    warp_sync_resume_progress: Option<sync::all::WarpSyncVerifiedProgress>,
+    sync_mode: sync::all::SyncMode,
+    probabilistic_finality_depth: Option<NonZeroU64>,
+    best_block_policy: sync::all::BestBlockPolicy,
+    runtime_call_cache_size: usize,
+    runtime_call_fuel_limit: Option<u64>,
+    max_cached_runtimes: NonZeroUsize,
+    validate_transactions_locally: bool,
+    wasm_execution: WasmExecution,
+    dns_resolver: Option<Arc<dyn dns::DnsResolver>>,
+    header_verification_workers: usize,
+    requests_hedging_percentile: Option<u8>,
+    max_pinned_blocks: NonZeroUsize,
+) -> ChainServices<TPlat> {
+    let runtime_exec_hint = match wasm_execution {
+        WasmExecution::Interpreter => executor::vm::ExecHint::ForceWasmi,
+        // Falls back to the interpreter on platforms where compiling isn't supported.
+        WasmExecution::Compiled => executor::vm::ExecHint::CompileAheadOfTime,
+    };
+
+    // Since `network_noise_key` is moved out below, use it to build the network identity ahead
+    // of the network service starting.
+    let network_identity =
+        peer_id::PublicKey::Ed25519(*network_noise_key.libp2p_public_ed25519_key()).into_peer_id();
+
+    // The network service is responsible for connecting to the peer-to-peer network.
+    let (network_service, mut network_event_receivers) =
+        network_service::NetworkService::new(network_service::Config {
+            tasks_executor: Box::new({
+                let spawn_new_task = spawn_new_task.clone();
+                move |name, fut| spawn_new_task(name, fut)
+            }),
+            num_events_receivers: 1, // Configures the length of `network_event_receivers`
+            noise_key: network_noise_key,
+            chains: vec![network_service::ConfigChain {
+                log_name: log_name.clone(),
+                has_grandpa_protocol: matches!(
+                    chain_information.as_ref().finality,
+                    chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
+                ),
+                // BEEFY runs on top of GrandPa, and only on relay chains. Peers that don't
+                // support BEEFY simply refuse the substream.
+                has_beefy_protocol: network_enable_beefy
+                    && relay_chain.is_none()
+                    && matches!(
+                        chain_information.as_ref().finality,
+                        chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
+                    ),
+                genesis_block_hash: header::hash_from_scale_encoded_header(
+                    &genesis_block_scale_encoded_header,
+                ),
+                finalized_block_height: chain_information.as_ref().finalized_block_header.number,
+                best_block: (
+                    chain_information.as_ref().finalized_block_header.number,
+                    chain_information
+                        .as_ref()
+                        .finalized_block_header
+                        .hash(chain_spec.block_number_bytes().into()),
+                ),
+                fork_id: chain_spec.fork_id().map(|n| n.to_owned()),
+                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                out_slots: network_out_slots,
+                reserved_only: network_reserved_only,
+                allow_inbound_grandpa_warp_sync_requests: network_serve_warp_sync,
+            }],
+            upload_limiter: network_upload_limiter,
+            download_limiter: network_download_limiter,
+            dns_resolver,
+            yamux_max_receive_window,
+            max_inbound_substreams,
+        })
+        .await;
+
+    let (sync_service, runtime_service) = if let Some(relay_chain) = relay_chain {
+        // Chain is a parachain.
+
+        // The sync service is leveraging the network service, downloads block headers,
+        // and verifies them, to determine what are the best and finalized blocks of the
+        // chain.
+        let sync_service = Arc::new(
+            sync_service::SyncService::new(sync_service::Config {
+                log_name: log_name.clone(),
+                chain_information: chain_information.clone(),
+                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                warp_sync_resume_progress: None,
+                sync_mode,
+                probabilistic_finality_depth: None,
+                best_block_policy,
+                header_verification_workers: 0,
+                requests_hedging_percentile,
+                tasks_executor: Box::new({
+                    let spawn_new_task = spawn_new_task.clone();
+                    move |name, fut| spawn_new_task(name, fut)
+                }),
+                network_service: (network_service.clone(), 0),
+                network_events_receiver: network_event_receivers.pop().unwrap(),
+                parachain: Some(sync_service::ConfigParachain {
+                    parachain_id: chain_spec.relay_chain().unwrap().1,
+                    relay_chain_sync: relay_chain.runtime_service.clone(),
+                    relay_chain_block_number_bytes: relay_chain.block_number_bytes,
+                }),
+            })
+            .await,
+        );
+
+        // The runtime service follows the runtime of the best block of the chain,
+        // and allows performing runtime calls.
+        let runtime_service = Arc::new(
+            runtime_service::RuntimeService::new(runtime_service::Config {
+                log_name: log_name.clone(),
+                tasks_executor: Box::new({
+                    let spawn_new_task = spawn_new_task.clone();
+                    move |name, fut| spawn_new_task(name, fut)
+                }),
+                sync_service: sync_service.clone(),
+                genesis_block_scale_encoded_header,
+                runtime_call_cache_size,
+                exec_hint: runtime_exec_hint,
+                runtime_call_fuel_limit,
+                max_cached_runtimes,
+            })
+            .await,
+        );
+
+        (sync_service, runtime_service)
+    } else {
+        // Chain is a relay chain.
+
+        // The sync service is leveraging the network service, downloads block headers,
         // and verifies them, to determine what are the best and finalized blocks of the
         // chain.
         let sync_service = Arc::new(
@@ -1093,6 +4726,12 @@ async fn start_services<TPlat: platform::Platform>(
                 log_name: log_name.clone(),
                 chain_information: chain_information.clone(),
                 block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                warp_sync_resume_progress,
+                sync_mode,
+                probabilistic_finality_depth,
+                best_block_policy,
+                header_verification_workers,
+                requests_hedging_percentile,
                 tasks_executor: Box::new({
                     let spawn_new_task = spawn_new_task.clone();
                     move |name, fut| spawn_new_task(name, fut)
@@ -1115,6 +4754,10 @@ async fn start_services<TPlat: platform::Platform>(
                 }),
                 sync_service: sync_service.clone(),
                 genesis_block_scale_encoded_header,
+                runtime_call_cache_size,
+                exec_hint: runtime_exec_hint,
+                runtime_call_fuel_limit,
+                max_cached_runtimes,
             })
             .await,
         );
@@ -1128,24 +4771,167 @@ async fn start_services<TPlat: platform::Platform>(
     // transaction will be submitted, the service itself is pretty low cost.
     let transactions_service = Arc::new(
         transactions_service::TransactionsService::new(transactions_service::Config {
-            log_name,
-            tasks_executor: Box::new(move |name, fut| spawn_new_task(name, fut)),
+            log_name: log_name.clone(),
+            tasks_executor: Box::new({
+                let spawn_new_task = spawn_new_task.clone();
+                move |name, fut| spawn_new_task(name, fut)
+            }),
             sync_service: sync_service.clone(),
             runtime_service: runtime_service.clone(),
             network_service: (network_service.clone(), 0),
             max_pending_transactions: NonZeroU32::new(64).unwrap(),
             max_concurrent_downloads: NonZeroU32::new(3).unwrap(),
             max_concurrent_validations: NonZeroU32::new(2).unwrap(),
+            validate_transactions: validate_transactions_locally,
         })
         .await,
     );
 
+    // The `chainHead_follow` state is shared between all the JSON-RPC clients of the chain, in
+    // order to track and pin each block only once.
+    let shared_follow = Arc::new(json_rpc_service::shared_follow::SharedFollow::new(
+        json_rpc_service::shared_follow::Config {
+            log_name,
+            tasks_executor: Box::new(move |name, fut| spawn_new_task(name, fut)),
+            runtime_service: runtime_service.clone(),
+            max_pinned_blocks,
+        },
+    ));
+
     ChainServices {
         network_service,
         network_identity,
         runtime_service,
         sync_service,
         transactions_service,
+        shared_follow,
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{
+        AddChainConfig, AddChainError, BestBlockPolicy, ChainId, Client, ClientConfig, DnsConfig,
+        SyncMode, WasmExecution,
+    };
+    use crate::platform::async_std::AsyncStdTcpWebSocket;
+
+    use alloc::{borrow::ToOwned as _, boxed::Box, string::String, vec, vec::Vec};
+    use core::num::{NonZeroU32, NonZeroUsize};
+
+    const RELAY_CHAIN_SPEC: &str = include_str!("../../demo-chain-specs/westend.json");
+    const PARACHAIN_SPEC: &str = include_str!("../../demo-chain-specs/westend-westmint.json");
+
+    /// Chain specification of a parachain whose relay chain is the chain of [`PARACHAIN_SPEC`].
+    fn nested_parachain_spec() -> String {
+        include_str!("../../demo-chain-specs/tick.json").replacen(
+            "\"relay_chain\": \"rococo_v1_4\"",
+            "\"relay_chain\": \"westmint\"",
+            1,
+        )
+    }
+
+    fn new_client() -> Client<AsyncStdTcpWebSocket, ()> {
+        Client::new(ClientConfig {
+            tasks_spawner: Box::new(move |_, task| {
+                async_std::task::spawn(task);
+            }),
+            system_name: "test".to_owned(),
+            system_version: "0.0.0".to_owned(),
+            max_upload_bps: None,
+            max_download_bps: None,
+            libp2p_key: None,
+            wasm_execution: WasmExecution::Interpreter,
+            database_storage: None,
+            dns: DnsConfig::Platform,
+            header_verification_workers: 1,
+            requests_hedging_percentile: None,
+            yamux_max_receive_window: 4 * 1024 * 1024,
+            max_inbound_substreams: None,
+        })
+    }
+
+    fn add_chain(
+        client: &mut Client<AsyncStdTcpWebSocket, ()>,
+        specification: &str,
+        potential_relay_chains: Vec<ChainId>,
+    ) -> Result<ChainId, AddChainError> {
+        client
+            .add_chain(AddChainConfig {
+                user_data: (),
+                specification,
+                database_content: "",
+                genesis_storage: None,
+                checkpoint: "",
+                potential_relay_chains: potential_relay_chains.into_iter(),
+                disable_json_rpc: true,
+                json_rpc_max_pending_responses: NonZeroU32::new(1).unwrap(),
+                json_rpc_max_subscriptions: 0,
+                json_rpc_max_batch_size: 1,
+                json_rpc_max_parallel_requests: NonZeroU32::new(1).unwrap(),
+                json_rpc_max_parallel_cheap_requests: NonZeroU32::new(1).unwrap(),
+                max_pinned_blocks: NonZeroUsize::new(32).unwrap(),
+                max_block_hash_lookup_depth: 16,
+                archive_fallback_endpoints: Vec::new(),
+                auto_recover: None,
+                reserved_nodes: Vec::new(),
+                reserved_only: false,
+                network_out_slots: 0,
+                serve_warp_sync: false,
+                enable_beefy: false,
+                sync_mode: SyncMode::AllForks {
+                    forks_retention_limit: None,
+                },
+                probabilistic_finality_depth: None,
+                best_block_policy: BestBlockPolicy::MostPrimarySlots,
+                runtime_call_cache_size: 0,
+                runtime_call_fuel_limit: None,
+                max_cached_runtimes: NonZeroUsize::new(1).unwrap(),
+                validate_transactions_locally: false,
+                event_hooks: Default::default(),
+            })
+            .map(|success| success.chain_id)
+    }
+
+    #[test]
+    fn nested_relay_chain_found_through_potential_relay_chain() {
+        let mut client = new_client();
+        let relay = add_chain(&mut client, RELAY_CHAIN_SPEC, Vec::new()).unwrap();
+        let _parachain = add_chain(&mut client, PARACHAIN_SPEC, vec![relay]).unwrap();
+        assert!(add_chain(&mut client, &nested_parachain_spec(), vec![relay]).is_ok());
+    }
+
+    #[test]
+    fn nested_relay_chain_of_other_user_not_found() {
+        // Two users add the same relay chain. Only the first one adds a parachain to it.
+        let mut client = new_client();
+        let relay1 = add_chain(&mut client, RELAY_CHAIN_SPEC, Vec::new()).unwrap();
+        let relay2 = add_chain(&mut client, RELAY_CHAIN_SPEC, Vec::new()).unwrap();
+        let _parachain = add_chain(&mut client, PARACHAIN_SPEC, vec![relay1]).unwrap();
+
+        // The second user must not be able to reach the parachain of the first user, even
+        // though both relay chains are identical.
+        assert!(matches!(
+            add_chain(&mut client, &nested_parachain_spec(), vec![relay2]),
+            Err(AddChainError::NoRelayChainFound)
+        ));
+    }
+
+    #[test]
+    fn nested_relay_chain_not_found_after_relay_chain_removed() {
+        let mut client = new_client();
+        let relay1 = add_chain(&mut client, RELAY_CHAIN_SPEC, Vec::new()).unwrap();
+        let _parachain = add_chain(&mut client, PARACHAIN_SPEC, vec![relay1]).unwrap();
+
+        // Re-adding the relay chain after removing it reuses the same `ChainId`, but must not
+        // make the parachain reachable through that new chain.
+        let () = client.remove_chain(relay1);
+        let relay2 = add_chain(&mut client, RELAY_CHAIN_SPEC, Vec::new()).unwrap();
+        assert_eq!(relay1, relay2);
+        assert!(matches!(
+            add_chain(&mut client, &nested_parachain_spec(), vec![relay2]),
+            Err(AddChainError::NoRelayChainFound)
+        ));
+    }
+}
@@ -36,10 +36,11 @@
 //! [`NetworkService::new`]. These channels inform the foreground about updates to the network
 //! connectivity.
 
-use crate::platform::Platform;
+use crate::{dns, platform::Platform};
 
 use alloc::{
     boxed::Box,
+    collections::VecDeque,
     format,
     string::{String, ToString as _},
     sync::Arc,
@@ -62,10 +63,13 @@ use smoldot::{
 
 pub use service::EncodedMerkleProof;
 
+pub use bandwidth::BandwidthLimiter;
+
+mod bandwidth;
 mod tasks;
 
 /// Configuration for a [`NetworkService`].
-pub struct Config {
+pub struct Config<TPlat: Platform> {
     /// Closure that spawns background tasks.
     pub tasks_executor: Box<dyn FnMut(String, future::BoxFuture<'static, ()>) + Send>,
 
@@ -77,6 +81,24 @@ pub struct Config {
 
     /// List of chains to connect to. Chains are later referred to by their index in this list.
     pub chains: Vec<ConfigChain>,
+
+    /// If `Some`, limits the number of bytes sent per second. The limiter can be shared with
+    /// other network services.
+    pub upload_limiter: Option<Arc<BandwidthLimiter<TPlat>>>,
+
+    /// If `Some`, limits the number of bytes received per second. The limiter can be shared with
+    /// other network services.
+    pub download_limiter: Option<Arc<BandwidthLimiter<TPlat>>>,
+
+    /// If `Some`, the domain names found in the multiaddresses are resolved using this resolver
+    /// before connecting. If `None`, they are passed as-is to [`Platform::connect`].
+    pub dns_resolver: Option<Arc<dyn dns::DnsResolver>>,
+
+    /// See [`service::Config::yamux_max_receive_window`].
+    pub yamux_max_receive_window: u64,
+
+    /// See [`service::Config::max_inbound_substreams`].
+    pub max_inbound_substreams: Option<usize>,
 }
 
 /// See [`Config::chains`].
@@ -111,6 +133,25 @@ pub struct ConfigChain {
 
     /// If true, the chain uses the GrandPa networking protocol.
     pub has_grandpa_protocol: bool,
+
+    /// If true, the chain uses the BEEFY networking protocol, and [`Event::BeefyFinalityProof`]
+    /// events are generated.
+    pub has_beefy_protocol: bool,
+
+    /// Maximum number of peers the network service tries to simultaneously open block announces
+    /// substreams with.
+    pub out_slots: u32,
+
+    /// If `true`, slots are only ever assigned to the peers added through
+    /// [`NetworkService::add_reserved_peer`], and no peer discovery is performed.
+    pub reserved_only: bool,
+
+    /// If `true`, the GrandPa warp sync requests of other peers are answered using the fragments
+    /// added through [`NetworkService::add_grandpa_warp_sync_fragment`].
+    ///
+    /// > **Note**: Answering these requests can consume a lot of bandwidth, as responses can
+    /// >           weigh several megabytes.
+    pub allow_inbound_grandpa_warp_sync_requests: bool,
 }
 
 pub struct NetworkService<TPlat: Platform> {
@@ -130,6 +171,24 @@ struct Shared<TPlat: Platform> {
     /// purposes.
     log_chain_names: Vec<String>,
 
+    /// For each chain, value of [`ConfigChain::reserved_only`].
+    reserved_only: Vec<bool>,
+
+    /// For each chain, value of [`ConfigChain::allow_inbound_grandpa_warp_sync_requests`].
+    allow_inbound_grandpa_warp_sync_requests: Vec<bool>,
+
+    /// Total number of bytes received and sent by all the connections.
+    bandwidth: bandwidth::BandwidthCounters,
+
+    /// Value of [`Config::upload_limiter`].
+    upload_limiter: Option<Arc<BandwidthLimiter<TPlat>>>,
+
+    /// Value of [`Config::download_limiter`].
+    download_limiter: Option<Arc<BandwidthLimiter<TPlat>>>,
+
+    /// Value of [`Config::dns_resolver`].
+    dns_resolver: Option<Arc<dyn dns::DnsResolver>>,
+
     /// Event to notify when the background task needs to be waken up.
     ///
     /// Waking up this event guarantees a full loop of the background task. In other words,
@@ -152,6 +211,12 @@ struct SharedGuarded<TPlat: Platform> {
     // TODO: use SipHasher
     slots_assign_backoff: HashMap<(PeerId, usize), TPlat::Instant, fnv::FnvBuildHasher>,
 
+    /// List of peer and chain index tuples that have been added through
+    /// [`NetworkService::add_reserved_peer`], with their addresses. Reserved peers are assigned
+    /// an outbound slot in priority.
+    // TODO: use SipHasher
+    reserved_peers: HashMap<(PeerId, usize), Vec<Multiaddr>, fnv::FnvBuildHasher>,
+
     messages_from_connections_tx:
         mpsc::Sender<(service::ConnectionId, service::ConnectionToCoordinator)>,
 
@@ -195,15 +260,72 @@ struct SharedGuarded<TPlat: Platform> {
 
     kademlia_discovery_operations:
         HashMap<service::KademliaOperationId, usize, fnv::FnvBuildHasher>,
+
+    /// List of senders created with [`NetworkService::subscribe`].
+    events_subscriptions: Vec<mpsc::Sender<Event>>,
+
+    /// Number of bytes received and sent for each peer we have at least one connection with,
+    /// including through connections that have been closed since then.
+    peers_bandwidth: HashMap<PeerId, Arc<bandwidth::BandwidthCounters>, fnv::FnvBuildHasher>,
+
+    /// For each chain, GrandPa warp sync fragments added through
+    /// [`NetworkService::add_grandpa_warp_sync_fragment`]. Used to answer the GrandPa warp sync
+    /// requests of other peers. `None` if no fragment has been added yet.
+    grandpa_warp_sync_proofs: Vec<Option<GrandpaWarpSyncProof>>,
+}
+
+/// See [`SharedGuarded::grandpa_warp_sync_proofs`].
+struct GrandpaWarpSyncProof {
+    /// Hash of the block the warp syncing has started from, as passed to
+    /// [`NetworkService::add_grandpa_warp_sync_fragment`].
+    sync_start_block_hash: [u8; 32],
+
+    /// Hash of the block the first fragment starts from.
+    start_block_hash: [u8; 32],
+
+    /// List of fragments, ordered by ascending block height. Each fragment starts from the block
+    /// of the previous fragment.
+    ///
+    /// The total size of the fragments is capped to [`MAX_GRANDPA_WARP_SYNC_PROOF_SIZE`]. Beyond
+    /// this limit, the oldest fragments are discarded and
+    /// [`GrandpaWarpSyncProof::start_block_hash`] is updated accordingly.
+    fragments: VecDeque<GrandpaWarpSyncFragment>,
+
+    /// Sum of the sizes of the headers and justifications in
+    /// [`GrandpaWarpSyncProof::fragments`].
+    fragments_size: usize,
+
+    /// If the last fragment leads to the current GrandPa authorities set of the chain, contains
+    /// the identifier of this set. Set through
+    /// [`NetworkService::finish_grandpa_warp_sync_proof`], and reset to `None` when the set
+    /// passed to [`NetworkService::set_local_grandpa_state`] changes, as the fragments then
+    /// no longer lead to the head of the chain.
+    finished_set_id: Option<u64>,
+}
+
+/// See [`GrandpaWarpSyncProof::fragments`].
+struct GrandpaWarpSyncFragment {
+    /// Hash of [`GrandpaWarpSyncFragment::scale_encoded_header`].
+    block_hash: [u8; 32],
+    scale_encoded_header: Vec<u8>,
+    scale_encoded_justification: Vec<u8>,
 }
 
+/// Maximum size, in bytes, of the fragments sent back in response to a GrandPa warp sync
+/// request. Substrate also caps its responses to this size.
+const MAX_GRANDPA_WARP_SYNC_RESPONSE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Maximum size, in bytes, of the GrandPa warp sync fragments kept in memory for each chain in
+/// order to answer the requests of other peers.
+const MAX_GRANDPA_WARP_SYNC_PROOF_SIZE: usize = 32 * 1024 * 1024;
+
 impl<TPlat: Platform> NetworkService<TPlat> {
     /// Initializes the network service with the given configuration.
     ///
     /// Returns the networking service, plus a list of receivers on which events are pushed.
     /// All of these receivers must be polled regularly to prevent the networking service from
     /// slowing down.
-    pub async fn new(config: Config) -> (Arc<Self>, Vec<stream::BoxStream<'static, Event>>) {
+    pub async fn new(config: Config<TPlat>) -> (Arc<Self>, Vec<stream::BoxStream<'static, Event>>) {
         let (event_senders, event_receivers): (Vec<_>, Vec<_>) = (0..config.num_events_receivers)
             .map(|_| mpsc::channel(16))
             .unzip();
@@ -211,11 +333,13 @@ impl<TPlat: Platform> NetworkService<TPlat> {
         let num_chains = config.chains.len();
         let mut chains = Vec::with_capacity(num_chains);
         let mut log_chain_names = Vec::with_capacity(num_chains);
+        let mut reserved_only = Vec::with_capacity(num_chains);
+        let mut allow_inbound_grandpa_warp_sync_requests = Vec::with_capacity(num_chains);
 
         for chain in config.chains {
             chains.push(service::ChainConfig {
                 in_slots: 3,
-                out_slots: 4,
+                out_slots: chain.out_slots,
                 grandpa_protocol_config: if chain.has_grandpa_protocol {
                     // TODO: dummy values
                     Some(service::GrandpaState {
@@ -226,6 +350,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 } else {
                     None
                 },
+                has_beefy_protocol: chain.has_beefy_protocol,
                 fork_id: chain.fork_id.clone(),
                 block_number_bytes: chain.block_number_bytes,
                 best_hash: chain.best_block.1,
@@ -233,9 +358,15 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 genesis_hash: chain.genesis_block_hash,
                 role: protocol::Role::Light,
                 allow_inbound_block_requests: false,
+                allow_inbound_kademlia_requests: false,
+                allow_inbound_grandpa_warp_sync_requests: chain
+                    .allow_inbound_grandpa_warp_sync_requests,
             });
 
             log_chain_names.push(chain.log_name);
+            reserved_only.push(chain.reserved_only);
+            allow_inbound_grandpa_warp_sync_requests
+                .push(chain.allow_inbound_grandpa_warp_sync_requests);
         }
 
         let mut abort_handles = Vec::new();
@@ -250,11 +381,14 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                     connections_capacity: 32,
                     peers_capacity: 8,
                     max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
+                    max_inbound_substreams: config.max_inbound_substreams,
+                    yamux_max_receive_window: config.yamux_max_receive_window,
                     noise_key: config.noise_key,
                     handshake_timeout: Duration::from_secs(8),
                     randomness_seed: rand::random(),
                 }),
                 slots_assign_backoff: HashMap::with_capacity_and_hasher(32, Default::default()),
+                reserved_peers: HashMap::with_capacity_and_hasher(0, Default::default()),
                 important_nodes: HashSet::with_capacity_and_hasher(16, Default::default()),
                 active_connections: HashMap::with_capacity_and_hasher(32, Default::default()),
                 messages_from_connections_tx,
@@ -271,8 +405,17 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                     2,
                     Default::default(),
                 ),
+                events_subscriptions: Vec::new(),
+                peers_bandwidth: HashMap::with_capacity_and_hasher(8, Default::default()),
+                grandpa_warp_sync_proofs: (0..num_chains).map(|_| None).collect(),
             }),
             log_chain_names,
+            reserved_only,
+            allow_inbound_grandpa_warp_sync_requests,
+            bandwidth: Default::default(),
+            upload_limiter: config.upload_limiter,
+            download_limiter: config.download_limiter,
+            dns_resolver: config.dns_resolver,
             wake_up_main_background_task: event_listener::Event::new(),
         });
 
@@ -303,7 +446,24 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                         next_discovery = cmp::min(next_discovery * 2, Duration::from_secs(120));
 
                         let mut guarded = shared.guarded.lock().await;
+
+                        // Reserved peers might have been removed from the k-buckets in favour of
+                        // other peers. Insert them back.
+                        let now = TPlat::now();
+                        for ((peer_id, chain_index), addrs) in guarded
+                            .reserved_peers
+                            .iter()
+                            .map(|(k, a)| (k.clone(), a.clone()))
+                            .collect::<Vec<_>>()
+                        {
+                            guarded.network.discover(&now, chain_index, peer_id, addrs);
+                        }
+
                         for chain_index in 0..shared.log_chain_names.len() {
+                            if shared.reserved_only[chain_index] {
+                                continue;
+                            }
+
                             let operation_id = guarded
                                 .network
                                 .start_kademlia_discovery_round(TPlat::now(), chain_index);
@@ -548,10 +708,17 @@ impl<TPlat: Platform> NetworkService<TPlat> {
 
         // TODO: log the list of peers we sent the packet to
 
-        self.shared
-            .guarded
-            .lock()
-            .await
+        let mut guarded = self.shared.guarded.lock().await;
+
+        // A change of authorities set means that the GrandPa warp sync fragments no longer
+        // lead to the head of the chain.
+        if let Some(proof) = &mut guarded.grandpa_warp_sync_proofs[chain_index] {
+            if proof.finished_set_id != Some(grandpa_state.set_id) {
+                proof.finished_set_id = None;
+            }
+        }
+
+        guarded
             .network
             .set_local_grandpa_state(chain_index, grandpa_state)
     }
@@ -798,6 +965,197 @@ impl<TPlat: Platform> NetworkService<TPlat> {
         self.shared.wake_up_main_background_task.notify(1);
     }
 
+    /// Adds a GrandPa warp sync fragment whose finality has been verified to the fragments used
+    /// to answer the GrandPa warp sync requests of other peers.
+    ///
+    /// `start_block_hash` is the hash of the block the warp syncing has started from. Fragments
+    /// must be added in order. Adding a fragment with a different `start_block_hash` than the
+    /// previously-added ones discards the previously-added fragments.
+    ///
+    /// Has no effect if [`ConfigChain::allow_inbound_grandpa_warp_sync_requests`] was `false`,
+    /// in which case the caller can avoid building the fragment. See
+    /// [`NetworkService::serves_grandpa_warp_sync`].
+    pub async fn add_grandpa_warp_sync_fragment(
+        &self,
+        chain_index: usize,
+        start_block_hash: [u8; 32],
+        scale_encoded_header: Vec<u8>,
+        scale_encoded_justification: Vec<u8>,
+    ) {
+        if !self.shared.allow_inbound_grandpa_warp_sync_requests[chain_index] {
+            return;
+        }
+
+        let mut guarded = self.shared.guarded.lock().await;
+        let proof = &mut guarded.grandpa_warp_sync_proofs[chain_index];
+
+        // Note that `start_block_hash` is compared with the block the warp syncing has started
+        // from rather than with `GrandpaWarpSyncProof::start_block_hash`, as the latter changes
+        // when fragments are discarded.
+        if proof
+            .as_ref()
+            .map_or(true, |p| p.sync_start_block_hash != start_block_hash)
+        {
+            *proof = Some(GrandpaWarpSyncProof {
+                sync_start_block_hash: start_block_hash,
+                start_block_hash,
+                fragments: VecDeque::new(),
+                fragments_size: 0,
+                finished_set_id: None,
+            });
+        }
+
+        let proof = proof.as_mut().unwrap();
+        proof.fragments_size += scale_encoded_header.len() + scale_encoded_justification.len();
+        proof.finished_set_id = None;
+        proof.fragments.push_back(GrandpaWarpSyncFragment {
+            block_hash: header::hash_from_scale_encoded_header(&scale_encoded_header),
+            scale_encoded_header,
+            scale_encoded_justification,
+        });
+
+        // Discard the oldest fragments if the proof is too large. The proof then starts from
+        // the block of the last discarded fragment.
+        while proof.fragments_size > MAX_GRANDPA_WARP_SYNC_PROOF_SIZE && proof.fragments.len() > 1 {
+            let discarded = proof.fragments.pop_front().unwrap();
+            proof.fragments_size -=
+                discarded.scale_encoded_header.len() + discarded.scale_encoded_justification.len();
+            proof.start_block_hash = discarded.block_hash;
+        }
+    }
+
+    /// Indicates that the GrandPa warp sync fragments added through
+    /// [`NetworkService::add_grandpa_warp_sync_fragment`] lead to the finalized block of the
+    /// chain, whose GrandPa authorities set identifier is `set_id`.
+    ///
+    /// The warp sync responses sent to other peers that contain the last fragment are marked as
+    /// finished, until [`NetworkService::set_local_grandpa_state`] is called with a different
+    /// set identifier or another fragment is added.
+    pub async fn finish_grandpa_warp_sync_proof(&self, chain_index: usize, set_id: u64) {
+        if let Some(proof) =
+            &mut self.shared.guarded.lock().await.grandpa_warp_sync_proofs[chain_index]
+        {
+            proof.finished_set_id = Some(set_id);
+        }
+    }
+
+    /// Returns the value of [`ConfigChain::allow_inbound_grandpa_warp_sync_requests`] for the
+    /// given chain.
+    pub fn serves_grandpa_warp_sync(&self, chain_index: usize) -> bool {
+        self.shared.allow_inbound_grandpa_warp_sync_requests[chain_index]
+    }
+
+    /// Adds a peer to the list of reserved peers of the given chain, and to the list of nodes
+    /// to connect to.
+    ///
+    /// Reserved peers are assigned an outbound slot in priority. If [`ConfigChain::reserved_only`]
+    /// is `true`, only reserved peers are assigned slots.
+    ///
+    /// If the peer is already a reserved peer, the given addresses are added to the list of its
+    /// known addresses.
+    pub async fn add_reserved_peer(
+        &self,
+        chain_index: usize,
+        peer_id: PeerId,
+        addrs: impl IntoIterator<Item = Multiaddr>,
+    ) {
+        log::debug!(
+            target: "connections",
+            "Chain({}) <= AddReservedPeer({})",
+            self.shared.log_chain_names[chain_index],
+            peer_id
+        );
+
+        let mut guarded = self.shared.guarded.lock().await;
+
+        let known_addrs = guarded
+            .reserved_peers
+            .entry((peer_id.clone(), chain_index))
+            .or_insert_with(Vec::new);
+        for addr in addrs {
+            if !known_addrs.contains(&addr) {
+                known_addrs.push(addr);
+            }
+        }
+
+        let addrs = known_addrs.clone();
+        guarded.important_nodes.insert(peer_id.clone());
+        guarded
+            .network
+            .discover(&TPlat::now(), chain_index, peer_id, addrs);
+
+        self.shared.wake_up_main_background_task.notify(1);
+    }
+
+    /// Inserts back the reserved peers of the given chain in the list of nodes to connect to,
+    /// in case they have been removed from it in favour of other peers.
+    ///
+    /// This is also done periodically in the background.
+    pub async fn discover_reserved_peers(&self, chain_index: usize) {
+        let mut guarded = self.shared.guarded.lock().await;
+
+        let now = TPlat::now();
+        for ((peer_id, _), addrs) in guarded
+            .reserved_peers
+            .iter()
+            .filter(|((_, c), _)| *c == chain_index)
+            .map(|(k, a)| (k.clone(), a.clone()))
+            .collect::<Vec<_>>()
+        {
+            guarded.network.discover(&now, chain_index, peer_id, addrs);
+        }
+
+        self.shared.wake_up_main_background_task.notify(1);
+    }
+
+    /// Removes a peer from the list of reserved peers of the given chain.
+    ///
+    /// Returns `false` if the peer wasn't a reserved peer.
+    ///
+    /// If [`ConfigChain::reserved_only`] is `true`, the slot of the peer is unassigned. Otherwise,
+    /// the peer is from now on treated like any other peer.
+    pub async fn remove_reserved_peer(&self, chain_index: usize, peer_id: &PeerId) -> bool {
+        log::debug!(
+            target: "connections",
+            "Chain({}) <= RemoveReservedPeer({})",
+            self.shared.log_chain_names[chain_index],
+            peer_id
+        );
+
+        let mut guarded = self.shared.guarded.lock().await;
+
+        if guarded
+            .reserved_peers
+            .remove(&(peer_id.clone(), chain_index))
+            .is_none()
+        {
+            return false;
+        }
+
+        // The peer is no longer note-worthy, unless it is still a reserved peer of another chain.
+        if !guarded.reserved_peers.keys().any(|(p, _)| p == peer_id) {
+            guarded.important_nodes.remove(peer_id);
+        }
+
+        if self.shared.reserved_only[chain_index] {
+            if let Some(slot_ty) = guarded.network.unassign_slot(chain_index, peer_id) {
+                log::debug!(
+                    target: "connections",
+                    "{}Slots({}) ∌ {}",
+                    match slot_ty {
+                        service::SlotTy::Inbound => "In",
+                        service::SlotTy::Outbound => "Out",
+                    },
+                    &self.shared.log_chain_names[chain_index],
+                    peer_id
+                );
+            }
+            self.shared.wake_up_main_background_task.notify(1);
+        }
+
+        true
+    }
+
     /// Returns a list of nodes (their [`PeerId`] and multiaddresses) that we know are part of
     /// the network.
     ///
@@ -822,6 +1180,49 @@ impl<TPlat: Platform> NetworkService<TPlat> {
             .into_iter()
     }
 
+    /// Subscribes to the events of the network service.
+    ///
+    /// Contrary to the receivers returned by [`NetworkService::new`], the network service doesn't
+    /// wait for the receiver to be ready. Only up to `buffer_size` events are buffered in the
+    /// channel. If the channel is full when a new event is attempted to be pushed, the channel
+    /// gets closed.
+    pub async fn subscribe(&self, buffer_size: usize) -> mpsc::Receiver<Event> {
+        let (tx, rx) = mpsc::channel(buffer_size);
+        self.shared
+            .guarded
+            .lock()
+            .await
+            .events_subscriptions
+            .push(tx);
+        rx
+    }
+
+    /// Returns the total number of bytes received and sent by the network service since it has
+    /// been created.
+    pub fn total_bandwidth(&self) -> (u64, u64) {
+        self.shared.bandwidth.get()
+    }
+
+    /// Returns the number of bytes received and sent for each peer we have at least one
+    /// connection with.
+    ///
+    /// The counters of a peer start when a connection with this peer is established, and are
+    /// discarded when the last connection with this peer is closed.
+    pub async fn peers_bandwidth(&self) -> impl Iterator<Item = (PeerId, u64, u64)> {
+        self.shared
+            .guarded
+            .lock()
+            .await
+            .peers_bandwidth
+            .iter()
+            .map(|(peer_id, counters)| {
+                let (received, sent) = counters.get();
+                (peer_id.clone(), received, sent)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
     /// Returns an iterator to the list of [`PeerId`]s that we have an established connection
     /// with.
     pub async fn peers_list(&self) -> impl Iterator<Item = PeerId> {
@@ -835,6 +1236,42 @@ impl<TPlat: Platform> NetworkService<TPlat> {
             .collect::<Vec<_>>()
             .into_iter()
     }
+
+    /// Returns the list of [`PeerId`]s that we have an established connection with, and the
+    /// addresses of these connections.
+    pub async fn peers_connections_addresses(
+        &self,
+    ) -> impl Iterator<Item = (PeerId, Vec<Multiaddr>)> {
+        let guarded = self.shared.guarded.lock().await;
+        guarded
+            .network
+            .peers_list()
+            .map(|peer_id| {
+                (
+                    peer_id.clone(),
+                    guarded
+                        .network
+                        .peer_connections_addresses(peer_id)
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns the list of outgoing connection attempts that are in progress.
+    pub async fn pending_dials(&self) -> impl Iterator<Item = (PeerId, Multiaddr)> {
+        self.shared
+            .guarded
+            .lock()
+            .await
+            .network
+            .pending_connections()
+            .map(|(peer_id, address)| (peer_id.clone(), address.clone()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
 }
 
 impl<TPlat: Platform> Drop for NetworkService<TPlat> {
@@ -870,6 +1307,18 @@ pub enum Event {
         chain_index: usize,
         message: service::EncodedGrandpaCommitMessage,
     },
+    /// Received a GrandPa neighbor packet from the network.
+    GrandpaNeighborPacket {
+        peer_id: PeerId,
+        chain_index: usize,
+        state: service::GrandpaState,
+    },
+    /// Received a BEEFY finality proof from the network. The proof hasn't been verified.
+    BeefyFinalityProof {
+        peer_id: PeerId,
+        chain_index: usize,
+        proof: service::EncodedBeefyFinalityProof,
+    },
 }
 
 /// Error returned by [`NetworkService::blocks_request`].
@@ -1225,6 +1674,76 @@ async fn update_round<TPlat: Platform>(
                     guarded.network.respond_identify(request_id, "smoldot");
                 }
                 service::Event::BlocksRequestIn { .. } => unreachable!(),
+                service::Event::KademliaFindNodeRequestIn { .. } => unreachable!(),
+                service::Event::GrandpaWarpSyncRequestIn {
+                    peer_id,
+                    chain_index,
+                    begin_hash,
+                    request_id,
+                } => {
+                    log::debug!(
+                        target: "network",
+                        "Connection({}) => GrandpaWarpSyncRequest(chain={}, begin={})",
+                        peer_id,
+                        &shared.log_chain_names[chain_index],
+                        HashDisplay(&begin_hash)
+                    );
+
+                    let guarded = &mut *guarded;
+                    let response = guarded.grandpa_warp_sync_proofs[chain_index]
+                        .as_ref()
+                        .and_then(|proof| {
+                            let first_fragment = if proof.start_block_hash == begin_hash {
+                                0
+                            } else {
+                                proof
+                                    .fragments
+                                    .iter()
+                                    .position(|f| f.block_hash == begin_hash)?
+                                    + 1
+                            };
+
+                            // Peers that are already at the end of the fragments are refused
+                            // unless the fragments lead to the head of the chain, as they would
+                            // otherwise receive an empty and unfinished response.
+                            if first_fragment == proof.fragments.len()
+                                && proof.finished_set_id.is_none()
+                            {
+                                return None;
+                            }
+
+                            // At least one fragment is always sent back, even if it is
+                            // larger than the limit.
+                            let mut total_size = 0;
+                            let fragments = proof
+                                .fragments
+                                .range(first_fragment..)
+                                .take_while(|f| {
+                                    let fragment_size = f.scale_encoded_header.len()
+                                        + f.scale_encoded_justification.len();
+                                    let include = total_size == 0
+                                        || total_size + fragment_size
+                                            <= MAX_GRANDPA_WARP_SYNC_RESPONSE_SIZE;
+                                    total_size += fragment_size;
+                                    include
+                                })
+                                .map(|f| protocol::GrandpaWarpSyncResponseFragment {
+                                    scale_encoded_header: &f.scale_encoded_header,
+                                    scale_encoded_justification: &f.scale_encoded_justification,
+                                })
+                                .collect::<Vec<_>>();
+
+                            Some(protocol::GrandpaWarpSyncResponse {
+                                is_finished: proof.finished_set_id.is_some()
+                                    && first_fragment + fragments.len() == proof.fragments.len(),
+                                fragments,
+                            })
+                        });
+
+                    guarded
+                        .network
+                        .respond_grandpa_warp_sync(request_id, response);
+                }
                 service::Event::RequestInCancel { .. } => {
                     // All incoming requests are immediately answered.
                     unreachable!()
@@ -1243,6 +1762,11 @@ async fn update_round<TPlat: Platform>(
                         state.set_id,
                         state.commit_finalized_height,
                     );
+                    break Event::GrandpaNeighborPacket {
+                        chain_index,
+                        peer_id,
+                        state,
+                    };
                 }
                 service::Event::GrandpaCommitMessage {
                     chain_index,
@@ -1262,6 +1786,26 @@ async fn update_round<TPlat: Platform>(
                         message,
                     };
                 }
+                service::Event::BeefyFinalityProof {
+                    chain_index,
+                    peer_id,
+                    proof,
+                } => {
+                    let decoded = proof.decode();
+                    log::debug!(
+                        target: "network",
+                        "Connection({}, {}) => BeefyFinalityProof(block_number={}, validator_set_id={})",
+                        peer_id,
+                        &shared.log_chain_names[chain_index],
+                        decoded.commitment.block_number,
+                        decoded.commitment.validator_set_id,
+                    );
+                    break Event::BeefyFinalityProof {
+                        chain_index,
+                        peer_id,
+                        proof,
+                    };
+                }
                 service::Event::ProtocolError { peer_id, error } => {
                     // TODO: handle properly?
                     log::warn!(
@@ -1281,6 +1825,11 @@ async fn update_round<TPlat: Platform>(
 
         // Dispatch the event to the various senders.
 
+        // Subscriptions whose channel is full are closed rather than waited for.
+        guarded
+            .events_subscriptions
+            .retain_mut(|sender| sender.try_send(event.clone()).is_ok());
+
         // Because the tasks processing the receivers might be waiting to acquire the lock, we
         // need to unlock the lock before sending. This guarantees that the sending finishes at
         // some point in the future.
@@ -1314,15 +1863,27 @@ async fn update_round<TPlat: Platform>(
             .retain(|_, expiration| *expiration > now);
 
         loop {
-            let peer_id = guarded
-                .network
-                .slots_to_assign(chain_index)
-                .find(|peer_id| {
-                    !guarded
-                        .slots_assign_backoff
-                        .contains_key(&((**peer_id).clone(), chain_index)) // TODO: spurious cloning
-                })
-                .cloned();
+            // Reserved peers are assigned a slot in priority. If the chain is in reserved-only
+            // mode, other peers are never assigned a slot.
+            let peer_id = {
+                let mut reserved = None;
+                let mut non_reserved = None;
+                for peer_id in guarded.network.slots_to_assign(chain_index) {
+                    // TODO: spurious cloning
+                    let key = (peer_id.clone(), chain_index);
+                    if guarded.slots_assign_backoff.contains_key(&key) {
+                        continue;
+                    }
+                    if guarded.reserved_peers.contains_key(&key) {
+                        reserved = Some(peer_id);
+                        break;
+                    }
+                    if non_reserved.is_none() && !shared.reserved_only[chain_index] {
+                        non_reserved = Some(peer_id);
+                    }
+                }
+                reserved.or(non_reserved).cloned()
+            };
 
             let Some(peer_id) = peer_id else { break };
             log::debug!(
@@ -1354,11 +1915,17 @@ async fn update_round<TPlat: Platform>(
         );
 
         // Perform the connection process in a separate task.
-        let task = tasks::connection_task(
-            start_connect,
-            shared.clone(),
-            guarded.messages_from_connections_tx.clone(),
-            is_important,
+        let task = crate::util::instrument!(
+            tasks::connection_task(
+                start_connect,
+                shared.clone(),
+                guarded.messages_from_connections_tx.clone(),
+                is_important,
+            ),
+            "connection",
+            chains = ?shared.log_chain_names,
+            peer_id = %start_connect.expected_peer_id,
+            address = %start_connect.multiaddr
         );
 
         // Sending the new task might fail in case a shutdown is happening, in which case
@@ -0,0 +1,134 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Bandwidth accounting and limiting.
+//!
+//! The [`BandwidthCounters`] keep track of the number of bytes that have been sent and received.
+//! They are updated by the connection tasks.
+//!
+//! The [`BandwidthLimiter`] is a token bucket shared between all the connections of all the
+//! network services of the client. Before reading or writing data on a connection, the
+//! connection tasks ask the limiter how many bytes can be transferred, and report afterwards how
+//! many bytes have actually been transferred.
+
+use crate::platform::Platform;
+
+use core::{
+    cmp,
+    num::NonZeroU32,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use futures::lock::Mutex;
+
+/// Duration after which a connection task whose transfers have been throttled by a
+/// [`BandwidthLimiter`] should try again.
+pub const THROTTLE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Number of bytes received and sent.
+#[derive(Debug, Default)]
+pub struct BandwidthCounters {
+    received: AtomicU64,
+    sent: AtomicU64,
+}
+
+impl BandwidthCounters {
+    /// Adds the given number of bytes to the counters.
+    pub fn add(&self, received: usize, sent: usize) {
+        if received != 0 {
+            self.received
+                .fetch_add(u64::try_from(received).unwrap(), Ordering::Relaxed);
+        }
+        if sent != 0 {
+            self.sent
+                .fetch_add(u64::try_from(sent).unwrap(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the total number of bytes received and sent.
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.received.load(Ordering::Relaxed),
+            self.sent.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Limits the number of bytes transferred per second.
+///
+/// Up to one second worth of bytes can be transferred in a burst.
+pub struct BandwidthLimiter<TPlat: Platform> {
+    max_bytes_per_sec: NonZeroU32,
+
+    /// Moment when [`Bucket::available`] was last refilled, and number of bytes that can be
+    /// transferred.
+    bucket: Mutex<Bucket<TPlat::Instant>>,
+}
+
+struct Bucket<TInstant> {
+    last_refill: TInstant,
+    available: u64,
+}
+
+impl<TPlat: Platform> BandwidthLimiter<TPlat> {
+    /// Initializes a new limiter.
+    pub fn new(max_bytes_per_sec: NonZeroU32) -> Self {
+        BandwidthLimiter {
+            max_bytes_per_sec,
+            bucket: Mutex::new(Bucket {
+                last_refill: TPlat::now(),
+                available: u64::from(max_bytes_per_sec.get()),
+            }),
+        }
+    }
+
+    /// Returns the number of bytes that can be transferred right now.
+    ///
+    /// If `0` is returned, the caller should try again after [`THROTTLE_RETRY_DELAY`].
+    pub async fn available(&self) -> usize {
+        let mut bucket = self.bucket.lock().await;
+
+        let now = TPlat::now();
+        let max = u64::from(self.max_bytes_per_sec.get());
+        let refill = u64::try_from(
+            (now.clone() - bucket.last_refill.clone()).as_micros() * u128::from(max) / 1_000_000,
+        )
+        .unwrap_or(u64::max_value());
+
+        // The moment of the last refill is only updated if bytes have actually been added.
+        // Otherwise, calling this function very frequently would prevent the bucket from ever
+        // being refilled.
+        if refill != 0 {
+            bucket.available = cmp::min(bucket.available.saturating_add(refill), max);
+            bucket.last_refill = now;
+        }
+
+        usize::try_from(bucket.available).unwrap_or(usize::max_value())
+    }
+
+    /// Reports that the given number of bytes have been transferred.
+    pub async fn consume(&self, num_bytes: usize) {
+        if num_bytes == 0 {
+            return;
+        }
+
+        let mut bucket = self.bucket.lock().await;
+        bucket.available = bucket
+            .available
+            .saturating_sub(u64::try_from(num_bytes).unwrap());
+    }
+}
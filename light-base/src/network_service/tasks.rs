@@ -15,14 +15,23 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::Shared;
-use crate::platform::{Platform, PlatformConnection, PlatformSubstreamDirection, ReadBuffer};
+use super::{bandwidth, Shared};
+use crate::{
+    dns,
+    platform::{
+        ConnectError, Platform, PlatformConnection, PlatformSubstreamDirection, ReadBuffer,
+    },
+};
 
-use alloc::{string::ToString as _, sync::Arc, vec, vec::Vec};
+use alloc::{format, string::ToString as _, sync::Arc, vec, vec::Vec};
 use core::{cmp, iter, pin::Pin};
 use futures::{channel::mpsc, prelude::*};
 use smoldot::{
-    libp2p::{collection::SubstreamFate, read_write::ReadWrite},
+    libp2p::{
+        collection::SubstreamFate,
+        multiaddr::{Multiaddr, ProtocolRef},
+        read_write::ReadWrite,
+    },
     network::service,
 };
 
@@ -38,7 +47,9 @@ pub(super) async fn connection_task<TPlat: Platform>(
     is_important: bool,
 ) {
     // Convert the `multiaddr` (typically of the form `/ip4/a.b.c.d/tcp/d/ws`)
-    // into a `Future<dyn Output = Result<TcpStream, ...>>`.
+    // into a `Future<dyn Output = Result<TcpStream, ...>>`. If a DNS resolver is configured, the
+    // domain name of the multiaddr, if any, is resolved as part of this future so that the
+    // resolution is subject to the same timeout as the connection.
     let socket = {
         log::debug!(
             target: "connections",
@@ -46,7 +57,15 @@ pub(super) async fn connection_task<TPlat: Platform>(
             start_connect.id, start_connect.expected_peer_id,
             start_connect.multiaddr
         );
-        TPlat::connect(&start_connect.multiaddr.to_string())
+        let dns_resolver = shared.dns_resolver.clone();
+        let multiaddr = start_connect.multiaddr.clone();
+        async move {
+            let multiaddr = match dns_resolver {
+                Some(dns_resolver) => resolve_domain_name(&*dns_resolver, &multiaddr).await?,
+                None => multiaddr,
+            };
+            TPlat::connect(&multiaddr.to_string()).await
+        }
     };
 
     let socket = {
@@ -163,6 +182,12 @@ pub(super) async fn connection_task<TPlat: Platform>(
         .insert(connection_id, coordinator_to_connection_tx);
     debug_assert!(_prev_value.is_none());
 
+    let peer_bandwidth = guarded
+        .peers_bandwidth
+        .entry(start_connect.expected_peer_id.clone())
+        .or_insert_with(Default::default)
+        .clone();
+
     drop(guarded);
 
     match socket_and_task {
@@ -174,6 +199,7 @@ pub(super) async fn connection_task<TPlat: Platform>(
                 task,
                 coordinator_to_connection_rx,
                 connection_to_coordinator_tx,
+                &peer_bandwidth,
             )
             .await
         }
@@ -185,10 +211,21 @@ pub(super) async fn connection_task<TPlat: Platform>(
                 task,
                 coordinator_to_connection_rx,
                 connection_to_coordinator_tx,
+                &peer_bandwidth,
             )
             .await
         }
     }
+
+    // Discard the bandwidth counters of the peer if this was the last connection with it.
+    // Counters are only ever cloned while the lock is held, meaning that there's no race
+    // condition here.
+    let mut guarded = shared.guarded.lock().await;
+    if Arc::strong_count(&peer_bandwidth) == 2 {
+        guarded
+            .peers_bandwidth
+            .remove(&start_connect.expected_peer_id);
+    }
 }
 
 /// Asynchronous task managing a specific single-stream connection after it's been open.
@@ -203,6 +240,7 @@ async fn single_stream_connection_task<TPlat: Platform>(
         service::ConnectionId,
         service::ConnectionToCoordinator,
     )>,
+    peer_bandwidth: &bandwidth::BandwidthCounters,
 ) {
     // We need to use `peek()` on this future later down this function.
     let mut coordinator_to_connection = coordinator_to_connection.peekable();
@@ -231,10 +269,15 @@ async fn single_stream_connection_task<TPlat: Platform>(
         let now = TPlat::now();
 
         let (read_bytes, written_bytes, wake_up_after) = if !connection_task.is_reset_called() {
+            let (download_allowance, upload_allowance) = bandwidth_allowance(&shared).await;
+
             let write_side_was_open = write_buffer.is_some();
             let writable_bytes = cmp::min(
-                TPlat::writable_bytes(&mut connection),
-                write_buffer.as_ref().map_or(0, |b| b.len()),
+                cmp::min(
+                    TPlat::writable_bytes(&mut connection),
+                    write_buffer.as_ref().map_or(0, |b| b.len()),
+                ),
+                upload_allowance,
             );
 
             let incoming_buffer = match TPlat::read_buffer(&mut connection) {
@@ -242,7 +285,7 @@ async fn single_stream_connection_task<TPlat: Platform>(
                     connection_task.reset();
                     continue;
                 }
-                ReadBuffer::Open(b) => Some(b),
+                ReadBuffer::Open(b) => Some(&b[..cmp::min(b.len(), download_allowance)]),
                 ReadBuffer::Closed => None,
             };
 
@@ -268,9 +311,17 @@ async fn single_stream_connection_task<TPlat: Platform>(
             let write_size_closed = write_side_was_open && read_write.outgoing_buffer.is_none();
             let written_bytes = read_write.written_bytes;
             debug_assert!(written_bytes <= writable_bytes);
-            let wake_up_after = read_write.wake_up_after.clone();
+            let mut wake_up_after = read_write.wake_up_after.clone();
             drop(read_write);
 
+            // If the transfers have been throttled, try again later.
+            if download_allowance == 0 || upload_allowance == 0 {
+                let retry = now.clone() + bandwidth::THROTTLE_RETRY_DELAY;
+                if wake_up_after.as_ref().map_or(true, |w| *w > retry) {
+                    wake_up_after = Some(retry);
+                }
+            }
+
             // Now update the connection.
             if written_bytes != 0 {
                 // `written_bytes`non-zero when the writing side has been closed before
@@ -287,6 +338,8 @@ async fn single_stream_connection_task<TPlat: Platform>(
             }
             TPlat::advance_read_cursor(&mut connection, read_bytes);
 
+            report_bandwidth(&shared, peer_bandwidth, read_bytes, written_bytes).await;
+
             (read_bytes, written_bytes, wake_up_after)
         } else {
             (0, 0, None)
@@ -407,6 +460,7 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
         service::ConnectionId,
         service::ConnectionToCoordinator,
     )>,
+    peer_bandwidth: &bandwidth::BandwidthCounters,
 ) {
     // We need to use `peek()` on this future later down this function.
     let mut coordinator_to_connection = coordinator_to_connection.peekable();
@@ -470,16 +524,22 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
         // time. This variable stores the earliest time when we should be waking up.
         let mut wake_up_after = None;
 
+        // Number of bytes that can still be read and written according to the bandwidth limits.
+        let (mut download_allowance, mut upload_allowance) = bandwidth_allowance(&shared).await;
+
         // Perform a read-write on all substreams.
         // TODO: trying to read/write every single substream every single time is suboptimal, but making this not suboptimal is very complicated
         for substream_id in open_substreams.iter().map(|(id, _)| id).collect::<Vec<_>>() {
             loop {
                 let (substream, write_side_was_open) = &mut open_substreams[substream_id];
 
-                let writable_bytes = cmp::min(TPlat::writable_bytes(substream), write_buffer.len());
+                let writable_bytes = cmp::min(
+                    cmp::min(TPlat::writable_bytes(substream), write_buffer.len()),
+                    upload_allowance,
+                );
 
                 let incoming_buffer = match TPlat::read_buffer(substream) {
-                    ReadBuffer::Open(buf) => buf,
+                    ReadBuffer::Open(buf) => &buf[..cmp::min(buf.len(), download_allowance)],
                     ReadBuffer::Closed => panic!(), // Forbidden for WebRTC.
                     ReadBuffer::Reset => {
                         // Inform the connection task. The substream is now considered dead.
@@ -527,6 +587,10 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
                 }
                 TPlat::advance_read_cursor(substream, read_bytes);
 
+                download_allowance -= read_bytes;
+                upload_allowance -= written_bytes;
+                report_bandwidth(&shared, peer_bandwidth, read_bytes, written_bytes).await;
+
                 // If the `connection_task` requires this substream to be killed, we drop the
                 // `Stream` object.
                 if matches!(substream_fate, SubstreamFate::Reset) {
@@ -540,6 +604,14 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
             }
         }
 
+        // If the transfers have been throttled, try again later.
+        if download_allowance == 0 || upload_allowance == 0 {
+            let retry = now.clone() + bandwidth::THROTTLE_RETRY_DELAY;
+            if wake_up_after.as_ref().map_or(true, |w| *w > retry) {
+                wake_up_after = Some(retry);
+            }
+        }
+
         // Try pull message to send to the coordinator.
         {
             // Calling this method takes ownership of the task and returns that task if it has
@@ -650,3 +722,90 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
         }
     }
 }
+
+/// Returns the number of bytes that can be read and written according to the bandwidth limiters
+/// of the network service.
+async fn bandwidth_allowance<TPlat: Platform>(shared: &Shared<TPlat>) -> (usize, usize) {
+    let download = match &shared.download_limiter {
+        Some(limiter) => limiter.available().await,
+        None => usize::max_value(),
+    };
+    let upload = match &shared.upload_limiter {
+        Some(limiter) => limiter.available().await,
+        None => usize::max_value(),
+    };
+    (download, upload)
+}
+
+/// Reports to the bandwidth counters and limiters that data has been transferred.
+async fn report_bandwidth<TPlat: Platform>(
+    shared: &Shared<TPlat>,
+    peer_bandwidth: &bandwidth::BandwidthCounters,
+    read_bytes: usize,
+    written_bytes: usize,
+) {
+    shared.bandwidth.add(read_bytes, written_bytes);
+    peer_bandwidth.add(read_bytes, written_bytes);
+    if let Some(limiter) = &shared.download_limiter {
+        limiter.consume(read_bytes).await;
+    }
+    if let Some(limiter) = &shared.upload_limiter {
+        limiter.consume(written_bytes).await;
+    }
+}
+
+/// Replaces the domain name found in the given multiaddr, if any, with the first compatible IP
+/// address that the resolver returns.
+///
+/// `/dnsaddr` multiaddrs are left untouched, as they resolve to other multiaddrs rather than to
+/// IP addresses. Multiaddrs that use TLS (such as `/dns/example.com/tcp/443/wss`) are also left
+/// untouched, as the domain name is necessary in order to verify the certificate of the remote.
+async fn resolve_domain_name(
+    dns_resolver: &dyn dns::DnsResolver,
+    multiaddr: &Multiaddr,
+) -> Result<Multiaddr, ConnectError> {
+    if multiaddr
+        .iter()
+        .any(|p| matches!(p, ProtocolRef::Tls | ProtocolRef::Wss))
+    {
+        return Ok(multiaddr.clone());
+    }
+
+    let (domain_name, accept_ipv4, accept_ipv6) = match multiaddr.iter().find_map(|p| match p {
+        ProtocolRef::Dns(name) => Some((name.to_string(), true, true)),
+        ProtocolRef::Dns4(name) => Some((name.to_string(), true, false)),
+        ProtocolRef::Dns6(name) => Some((name.to_string(), false, true)),
+        _ => None,
+    }) {
+        Some(v) => v,
+        None => return Ok(multiaddr.clone()),
+    };
+
+    let addresses = dns_resolver
+        .resolve(&domain_name)
+        .await
+        .map_err(|err| ConnectError {
+            message: format!("Failed to resolve {domain_name}: {err}"),
+            is_bad_addr: false,
+        })?;
+
+    let ip = addresses
+        .into_iter()
+        .find_map(|ip| match ip {
+            dns::IpAddr::V4(ip) if accept_ipv4 => Some(ProtocolRef::Ip4(ip)),
+            dns::IpAddr::V6(ip) if accept_ipv6 => Some(ProtocolRef::Ip6(ip)),
+            _ => None,
+        })
+        .ok_or_else(|| ConnectError {
+            message: format!("No suitable IP address found for {domain_name}"),
+            is_bad_addr: false,
+        })?;
+
+    Ok(multiaddr
+        .iter()
+        .map(|p| match p {
+            ProtocolRef::Dns(_) | ProtocolRef::Dns4(_) | ProtocolRef::Dns6(_) => ip.clone(),
+            p => p,
+        })
+        .collect())
+}
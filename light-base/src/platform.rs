@@ -20,6 +20,11 @@ use core::{ops, str, time::Duration};
 use futures::prelude::*;
 
 pub mod async_std;
+pub mod embedded;
+pub mod tokio;
+pub mod wasi;
+
+mod tcp_websocket;
 
 /// Access to a platform's capabilities.
 pub trait Platform: Send + 'static {
@@ -0,0 +1,646 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Building blocks for implementing the [`Platform`](super::Platform) trait on environments
+//! that don't have an operating system, such as microcontrollers running a real-time operating
+//! system.
+//!
+//! This module doesn't depend on the `std` feature, and is enabled with the `embedded` feature.
+//! It provides:
+//!
+//! - A [`TimerQueue`], driven by the environment, that can be used to implement
+//! [`Platform::sleep`](super::Platform::sleep) and
+//! [`Platform::sleep_until`](super::Platform::sleep_until).
+//! - A [`Stream`] that can be used as [`Platform::Stream`](super::Platform::Stream). Each
+//! [`Stream`] is paired with a [`StreamDriver`] through which the network stack of the
+//! environment transfers data.
+//! - An [`Executor`] that runs the background tasks of the client on a single thread, and whose
+//! [`Executor::spawn`] method can be used for
+//! [`ClientConfig::tasks_spawner`](crate::ClientConfig::tasks_spawner).
+//!
+//! Because the functions of the [`Platform`](super::Platform) trait don't have a `self`
+//! parameter, the [`TimerQueue`] is typically stored in a `static`.
+//!
+//! The state of these building blocks is only ever accessed within a critical section, as
+//! provided by the [`critical_section`] crate. The environment must thus provide an
+//! implementation of critical sections, which on a single-core microcontroller typically
+//! disables interrupts. [`TimerQueue::advance`], the methods of [`StreamDriver`], and the
+//! wakers of the [`Executor`] can safely be called from within an interrupt handler.
+//!
+//! Reference counting is done through [`portable_atomic_util::Arc`], which also works on
+//! targets that don't support atomic operations natively if the `critical-section` feature of
+//! the `portable-atomic` crate is enabled.
+//!
+//! See the `embedded` example for how these building blocks fit together.
+
+#![cfg(feature = "embedded")]
+#![cfg_attr(docsrs, doc(cfg(feature = "embedded")))]
+
+use super::ReadBuffer;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use futures::future::BoxFuture;
+use portable_atomic_util::{task::Wake, Arc};
+
+/// Mutex whose content is accessed with [`with`].
+type Mutex<T> = critical_section::Mutex<RefCell<T>>;
+
+/// Queue of timers whose expiration is driven by the environment.
+///
+/// The environment must call [`TimerQueue::advance`] whenever time passes, and at the latest at
+/// the instant returned by [`TimerQueue::next_deadline`].
+pub struct TimerQueue<TInstant> {
+    inner: Mutex<Timers<TInstant>>,
+}
+
+struct Timers<TInstant> {
+    /// Value passed to the latest call to [`TimerQueue::advance`].
+    now: TInstant,
+    /// List of timers that haven't expired yet. Indexed by deadline and by a unique identifier.
+    pending: BTreeMap<(TInstant, u64), Option<Waker>>,
+    /// Identifier to assign to the next timer.
+    next_id: u64,
+}
+
+impl<TInstant: Clone + Ord> TimerQueue<TInstant> {
+    /// Creates a new empty queue. `now` is the current time.
+    pub fn new(now: TInstant) -> Self {
+        TimerQueue {
+            inner: Mutex::new(RefCell::new(Timers {
+                now,
+                pending: Default::default(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Returns the value passed to the latest call to [`TimerQueue::advance`], or to
+    /// [`TimerQueue::new`].
+    pub fn now(&self) -> TInstant {
+        with(&self.inner, |timers| timers.now.clone())
+    }
+
+    /// Updates the current time and wakes up all the timers whose deadline has been reached.
+    ///
+    /// Has no effect if `now` is inferior to the current time.
+    ///
+    /// Can be called from within an interrupt handler.
+    pub fn advance(&self, now: TInstant) {
+        let wakers = with(&self.inner, |timers| {
+            let mut wakers = Vec::new();
+            if now <= timers.now {
+                return wakers;
+            }
+            timers.now = now.clone();
+
+            while let Some(entry) = timers.pending.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                if let Some(waker) = entry.remove() {
+                    wakers.push(waker);
+                }
+            }
+            wakers
+        });
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Returns the earliest deadline of the timers in the queue, or `None` if the queue is
+    /// empty.
+    ///
+    /// The environment can use this value in order to configure a hardware timer.
+    pub fn next_deadline(&self) -> Option<TInstant> {
+        with(&self.inner, |timers| {
+            timers.pending.keys().next().map(|(when, _)| when.clone())
+        })
+    }
+
+    /// Returns a future that becomes ready once [`TimerQueue::advance`] has been called with an
+    /// instant superior or equal to `when`.
+    pub fn sleep_until(&'static self, when: TInstant) -> Delay<TInstant> {
+        let key = with(&self.inner, |timers| {
+            if when <= timers.now {
+                return None;
+            }
+
+            let key = (when, timers.next_id);
+            timers.next_id += 1;
+            timers.pending.insert(key.clone(), None);
+            Some(key)
+        });
+
+        Delay { queue: self, key }
+    }
+}
+
+/// Future returned by [`TimerQueue::sleep_until`].
+pub struct Delay<TInstant: Clone + Ord + 'static> {
+    queue: &'static TimerQueue<TInstant>,
+    /// Entry in [`Timers::pending`]. `None` if the timer has expired.
+    key: Option<(TInstant, u64)>,
+}
+
+// The `Delay` never relies on its address being stable.
+impl<TInstant: Clone + Ord + 'static> Unpin for Delay<TInstant> {}
+
+impl<TInstant: Clone + Ord> Future for Delay<TInstant> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let Some(key) = self.key.clone() else {
+            return Poll::Ready(());
+        };
+
+        let is_pending = with(&self.queue.inner, |timers| {
+            match timers.pending.get_mut(&key) {
+                Some(waker) => {
+                    *waker = Some(cx.waker().clone());
+                    true
+                }
+                None => false,
+            }
+        });
+
+        if is_pending {
+            Poll::Pending
+        } else {
+            self.key = None;
+            Poll::Ready(())
+        }
+    }
+}
+
+impl<TInstant: Clone + Ord + 'static> Drop for Delay<TInstant> {
+    fn drop(&mut self) {
+        if let Some(key) = &self.key {
+            with(&self.queue.inner, |timers| timers.pending.remove(key));
+        }
+    }
+}
+
+/// Creates a new [`Stream`] and its corresponding [`StreamDriver`].
+///
+/// `read_buffer_capacity` is the maximum number of bytes that the [`Stream`] holds in its read
+/// buffer. `write_buffer_capacity` is the maximum number of bytes that have been sent on the
+/// [`Stream`] but not retrieved yet through [`StreamDriver::read_outgoing`].
+///
+/// # Panic
+///
+/// Panics if `read_buffer_capacity` or `write_buffer_capacity` is 0.
+///
+pub fn stream(read_buffer_capacity: usize, write_buffer_capacity: usize) -> (Stream, StreamDriver) {
+    assert_ne!(read_buffer_capacity, 0);
+    assert_ne!(write_buffer_capacity, 0);
+
+    let shared = Arc::new(Mutex::new(RefCell::new(StreamShared {
+        incoming: VecDeque::new(),
+        incoming_closed: false,
+        reset: false,
+        outgoing: VecDeque::with_capacity(write_buffer_capacity),
+        outgoing_capacity: write_buffer_capacity,
+        outgoing_closed: false,
+        stream_dropped: false,
+        stream_waker: None,
+        driver_waker: None,
+    })));
+
+    let stream = Stream {
+        shared: shared.clone(),
+        read_buffer: Vec::with_capacity(read_buffer_capacity),
+        read_buffer_capacity,
+        read_cursor: 0,
+        read_closed: false,
+        reset: false,
+        pending_send: Vec::new(),
+        writable_bytes: write_buffer_capacity,
+        close_send_requested: false,
+    };
+
+    (stream, StreamDriver { shared })
+}
+
+/// Single-stream connection or substream, to use as [`Platform::Stream`](super::Platform::Stream).
+///
+/// The methods of this type correspond to the stream-related functions of the
+/// [`Platform`](super::Platform) trait, and implementations of these functions can simply call
+/// them.
+///
+/// As required by the [`Platform`](super::Platform) trait, the state of the stream is only
+/// updated when [`Stream::update`] is called.
+pub struct Stream {
+    shared: Arc<Mutex<StreamShared>>,
+    /// Data received and not processed yet, starting at `read_cursor`.
+    read_buffer: Vec<u8>,
+    read_buffer_capacity: usize,
+    read_cursor: usize,
+    read_closed: bool,
+    reset: bool,
+    /// Data passed to [`Stream::send`] and not transferred yet to [`StreamShared::outgoing`].
+    pending_send: Vec<u8>,
+    writable_bytes: usize,
+    close_send_requested: bool,
+}
+
+/// State shared between a [`Stream`] and its [`StreamDriver`].
+struct StreamShared {
+    incoming: VecDeque<u8>,
+    incoming_closed: bool,
+    reset: bool,
+    outgoing: VecDeque<u8>,
+    outgoing_capacity: usize,
+    outgoing_closed: bool,
+    stream_dropped: bool,
+    stream_waker: Option<Waker>,
+    driver_waker: Option<Waker>,
+}
+
+impl Stream {
+    /// See [`Platform::update_stream`](super::Platform::update_stream).
+    pub fn update(&mut self) -> StreamUpdate<'_> {
+        StreamUpdate { stream: self }
+    }
+
+    /// See [`Platform::read_buffer`](super::Platform::read_buffer).
+    pub fn read_buffer(&self) -> ReadBuffer {
+        if self.reset {
+            ReadBuffer::Reset
+        } else if self.read_closed {
+            ReadBuffer::Closed
+        } else {
+            ReadBuffer::Open(&self.read_buffer[self.read_cursor..])
+        }
+    }
+
+    /// See [`Platform::advance_read_cursor`](super::Platform::advance_read_cursor).
+    ///
+    /// # Panic
+    ///
+    /// Panics if there aren't enough bytes to discard in the buffer.
+    ///
+    pub fn advance_read_cursor(&mut self, bytes: usize) {
+        assert!(self.read_cursor + bytes <= self.read_buffer.len());
+        self.read_cursor += bytes;
+    }
+
+    /// See [`Platform::writable_bytes`](super::Platform::writable_bytes).
+    pub fn writable_bytes(&self) -> usize {
+        if self.reset || self.close_send_requested {
+            0
+        } else {
+            self.writable_bytes
+        }
+    }
+
+    /// See [`Platform::send`](super::Platform::send).
+    ///
+    /// # Panic
+    ///
+    /// Panics if `data.is_empty()`.
+    /// Panics if `data.len()` is superior to the value returned by [`Stream::writable_bytes`].
+    ///
+    pub fn send(&mut self, data: &[u8]) {
+        assert!(!data.is_empty());
+        assert!(data.len() <= self.writable_bytes());
+        self.writable_bytes -= data.len();
+        self.pending_send.extend_from_slice(data);
+    }
+
+    /// See [`Platform::close_send`](super::Platform::close_send).
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`Stream::close_send`] has already been called.
+    ///
+    pub fn close_send(&mut self) {
+        assert!(!self.close_send_requested);
+        self.close_send_requested = true;
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let waker = with(&self.shared, |shared| {
+            shared.stream_dropped = true;
+            shared.driver_waker.take()
+        });
+        wake(waker);
+    }
+}
+
+/// Future returned by [`Stream::update`].
+pub struct StreamUpdate<'a> {
+    stream: &'a mut Stream,
+}
+
+impl<'a> Future for StreamUpdate<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let stream = &mut *self.stream;
+        if stream.reset {
+            // The future never becomes ready again after the stream has been reset.
+            return Poll::Pending;
+        }
+
+        let (ready, driver_waker) = with(&stream.shared, |shared| {
+            if shared.reset {
+                stream.reset = true;
+                return (true, None);
+            }
+
+            let mut ready = false;
+            let mut wake_driver = false;
+
+            // Transfer the incoming data, but only once the data in the read buffer has been
+            // entirely processed.
+            if !stream.read_closed && stream.read_cursor == stream.read_buffer.len() {
+                if !shared.incoming.is_empty() {
+                    let num_bytes = shared.incoming.len().min(stream.read_buffer_capacity);
+                    stream.read_buffer.clear();
+                    stream
+                        .read_buffer
+                        .extend(shared.incoming.drain(..num_bytes));
+                    stream.read_cursor = 0;
+                    ready = true;
+                } else if shared.incoming_closed {
+                    stream.read_closed = true;
+                    ready = true;
+                }
+            }
+
+            // Transfer the outgoing data.
+            if !stream.pending_send.is_empty() {
+                shared.outgoing.extend(stream.pending_send.drain(..));
+                wake_driver = true;
+            }
+            if stream.close_send_requested && !shared.outgoing_closed {
+                shared.outgoing_closed = true;
+                wake_driver = true;
+            }
+            let writable_bytes = shared.outgoing_capacity - shared.outgoing.len();
+            if writable_bytes > stream.writable_bytes {
+                stream.writable_bytes = writable_bytes;
+                ready = true;
+            }
+
+            if !ready {
+                shared.stream_waker = Some(cx.waker().clone());
+            }
+
+            let driver_waker = if wake_driver {
+                shared.driver_waker.take()
+            } else {
+                None
+            };
+            (ready, driver_waker)
+        });
+
+        wake(driver_waker);
+
+        if ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Counterpart of a [`Stream`], used by the network stack of the environment in order to
+/// transfer data between the [`Stream`] and the actual connection.
+pub struct StreamDriver {
+    shared: Arc<Mutex<StreamShared>>,
+}
+
+impl StreamDriver {
+    /// Adds data received from the remote to the [`Stream`].
+    ///
+    /// The number of bytes waiting to be transferred to the [`Stream`] isn't bounded. The
+    /// environment can use [`StreamDriver::incoming_len`] in order to apply back-pressure.
+    pub fn inject_received(&self, data: &[u8]) {
+        let waker = with(&self.shared, |shared| {
+            debug_assert!(!shared.incoming_closed);
+            shared.incoming.extend(data.iter().copied());
+            shared.stream_waker.take()
+        });
+        wake(waker);
+    }
+
+    /// Returns the number of bytes passed to [`StreamDriver::inject_received`] that haven't been
+    /// transferred to the [`Stream`] yet.
+    pub fn incoming_len(&self) -> usize {
+        with(&self.shared, |shared| shared.incoming.len())
+    }
+
+    /// Indicates that the remote has closed its writing side.
+    pub fn close_received(&self) {
+        let waker = with(&self.shared, |shared| {
+            shared.incoming_closed = true;
+            shared.stream_waker.take()
+        });
+        wake(waker);
+    }
+
+    /// Indicates that the connection has been abruptly closed.
+    pub fn reset(&self) {
+        let waker = with(&self.shared, |shared| {
+            shared.reset = true;
+            shared.stream_waker.take()
+        });
+        wake(waker);
+    }
+
+    /// Moves data sent on the [`Stream`] to `buffer`. Returns the number of bytes written to
+    /// `buffer`.
+    pub fn read_outgoing(&self, buffer: &mut [u8]) -> usize {
+        let (num_bytes, waker) = with(&self.shared, |shared| {
+            let num_bytes = buffer.len().min(shared.outgoing.len());
+            for (out, byte) in buffer.iter_mut().zip(shared.outgoing.drain(..num_bytes)) {
+                *out = byte;
+            }
+            let waker = if num_bytes != 0 {
+                shared.stream_waker.take()
+            } else {
+                None
+            };
+            (num_bytes, waker)
+        });
+        wake(waker);
+        num_bytes
+    }
+
+    /// Returns `true` if the writing side of the [`Stream`] has been closed and all the data
+    /// sent on it has been retrieved with [`StreamDriver::read_outgoing`]. The environment
+    /// should then close the writing side of the actual connection.
+    pub fn is_send_closed(&self) -> bool {
+        with(&self.shared, |shared| {
+            shared.outgoing_closed && shared.outgoing.is_empty()
+        })
+    }
+
+    /// Returns `true` if the [`Stream`] has been destroyed. The environment should then close
+    /// the actual connection.
+    pub fn is_stream_dropped(&self) -> bool {
+        with(&self.shared, |shared| shared.stream_dropped)
+    }
+
+    /// Registers a waker that is woken up when data is sent on the [`Stream`], when its writing
+    /// side is closed, or when it is destroyed.
+    ///
+    /// Only the latest registered waker is woken up.
+    pub fn register_waker(&self, waker: &Waker) {
+        with(&self.shared, |shared| {
+            shared.driver_waker = Some(waker.clone())
+        });
+    }
+}
+
+/// Runs tasks on the thread that calls [`Executor::run_until_stalled`].
+pub struct Executor {
+    tasks: Mutex<slab::Slab<Option<BoxFuture<'static, ()>>>>,
+    woken: Arc<WokenTasks>,
+}
+
+struct WokenTasks {
+    queue: Mutex<VecDeque<usize>>,
+    on_wake: Box<dyn Fn() + Send + Sync>,
+}
+
+struct TaskWaker {
+    task_id: usize,
+    woken: Arc<WokenTasks>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(this: Arc<Self>) {
+        Self::wake_by_ref(&this);
+    }
+
+    fn wake_by_ref(this: &Arc<Self>) {
+        with(&this.woken.queue, |queue| queue.push_back(this.task_id));
+        (this.woken.on_wake)();
+    }
+}
+
+impl Executor {
+    /// Creates a new executor without any task.
+    ///
+    /// `on_wake` is called whenever a task is spawned or woken up, and can for example be used
+    /// in order to signal the main loop of the environment that
+    /// [`Executor::run_until_stalled`] should be called. It might be called from within
+    /// [`Executor::run_until_stalled`], from within an interrupt handler if a waker is invoked
+    /// from there, or from any thread.
+    pub fn new(on_wake: Box<dyn Fn() + Send + Sync>) -> Self {
+        Executor {
+            tasks: Mutex::new(RefCell::new(slab::Slab::new())),
+            woken: Arc::new(WokenTasks {
+                queue: Mutex::new(RefCell::new(VecDeque::new())),
+                on_wake,
+            }),
+        }
+    }
+
+    /// Adds a task to the executor. The task will be polled during the next call to
+    /// [`Executor::run_until_stalled`].
+    ///
+    /// The name of the task is ignored. It exists so that this method has the signature
+    /// expected by [`ClientConfig::tasks_spawner`](crate::ClientConfig::tasks_spawner).
+    pub fn spawn(&self, _name: String, task: BoxFuture<'static, ()>) {
+        let task_id = with(&self.tasks, |tasks| tasks.insert(Some(task)));
+        with(&self.woken.queue, |queue| queue.push_back(task_id));
+        (self.woken.on_wake)();
+    }
+
+    /// Returns the number of tasks that haven't finished yet.
+    pub fn num_tasks(&self) -> usize {
+        with(&self.tasks, |tasks| tasks.len())
+    }
+
+    /// Returns `true` if no task has been spawned or woken up since the latest call to
+    /// [`Executor::run_until_stalled`].
+    pub fn is_stalled(&self) -> bool {
+        with(&self.woken.queue, |queue| queue.is_empty())
+    }
+
+    /// Polls all the tasks that have been spawned or woken up, until none of them is woken up
+    /// anymore.
+    ///
+    /// Must not be called from within a task.
+    pub fn run_until_stalled(&self) {
+        loop {
+            let Some(task_id) = with(&self.woken.queue, |queue| queue.pop_front()) else {
+                break;
+            };
+
+            // The task is extracted from the list while it is being polled, so that it can
+            // spawn other tasks.
+            let Some(mut task) = with(&self.tasks, |tasks| {
+                tasks.get_mut(task_id).and_then(Option::take)
+            }) else {
+                continue;
+            };
+
+            let waker = Waker::from(Arc::new(TaskWaker {
+                task_id,
+                woken: self.woken.clone(),
+            }));
+
+            match task.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(()) => {
+                    with(&self.tasks, |tasks| tasks.remove(task_id));
+                }
+                Poll::Pending => {
+                    with(&self.tasks, |tasks| tasks[task_id] = Some(task));
+                }
+            }
+        }
+    }
+}
+
+/// Calls `f` with exclusive access to the content of the given mutex.
+///
+/// The content is accessed within a critical section. Contrary to spinning until a lock is
+/// released, this can't deadlock if the lock holder is interrupted by an interrupt handler that
+/// accesses the same content.
+///
+/// `f` must not call [`with`] on the same mutex, and must not wake up wakers, as waking up a
+/// waker can run arbitrary code. Wakers are instead returned from the critical section and
+/// passed to [`wake`].
+fn with<T, R>(mutex: &Mutex<T>, f: impl FnOnce(&mut T) -> R) -> R {
+    critical_section::with(|cs| f(&mut mutex.borrow_ref_mut(cs)))
+}
+
+/// Wakes up the given waker, if any.
+fn wake(waker: Option<Waker>) {
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
@@ -0,0 +1,410 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Code shared between the platforms that provide TCP and WebSocket connections on top of an
+//! asynchronous runtime, such as [`super::async_std`] and [`super::tokio`].
+//!
+//! These platforms only differ in the way they spawn timers and open TCP sockets. Everything
+//! else, such as parsing multiaddresses, negotiating WebSocket, and buffering the data of the
+//! streams, is implemented in this module.
+
+#![cfg(any(feature = "std", feature = "tokio"))]
+
+use super::{ConnectError, PlatformConnection, ReadBuffer};
+
+use alloc::collections::VecDeque;
+use core::{ops, pin::Pin, str, task::Poll};
+use futures::prelude::*;
+use smoldot::libp2p::{
+    multiaddr::{Multiaddr, ProtocolRef},
+    websocket,
+};
+use std::{
+    io::{self, IoSlice},
+    net::{IpAddr, SocketAddr},
+};
+
+/// Address to open a TCP connection to, passed to the function that opens TCP sockets in
+/// [`connect`].
+pub enum TcpAddress {
+    /// IP address and port.
+    SocketAddr(SocketAddr),
+    /// DNS name and port, to resolve through the runtime.
+    Dns(String, u16),
+}
+
+/// Implementation of `Platform::connect`.
+///
+/// `tcp_connect` is called in order to open a TCP socket to the given address.
+pub async fn connect<T, F>(
+    multiaddr: &str,
+    tcp_connect: impl FnOnce(TcpAddress) -> F,
+) -> Result<PlatformConnection<Stream<T>, std::convert::Infallible>, ConnectError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    F: Future<Output = io::Result<T>>,
+{
+    let addr = multiaddr.parse::<Multiaddr>().map_err(|_| ConnectError {
+        is_bad_addr: true,
+        message: "Failed to parse address".to_string(),
+    })?;
+
+    // WebRTC connections are handled by the networking service, but this platform
+    // doesn't implement the underlying WebRTC stack. Report a clear error rather than a
+    // generic one.
+    if addr
+        .iter()
+        .any(|proto| matches!(proto, ProtocolRef::WebRtcDirect))
+    {
+        return Err(ConnectError {
+            is_bad_addr: true,
+            message: "WebRTC isn't supported by this platform".to_string(),
+        });
+    }
+    if addr
+        .iter()
+        .any(|proto| matches!(proto, ProtocolRef::Quic | ProtocolRef::QuicV1))
+    {
+        return Err(ConnectError {
+            is_bad_addr: true,
+            message: "QUIC isn't supported by this platform".to_string(),
+        });
+    }
+
+    let mut iter = addr.iter().fuse();
+    let proto1 = iter.next().ok_or(ConnectError {
+        is_bad_addr: true,
+        message: "Unknown protocols combination".to_string(),
+    })?;
+    let proto2 = iter.next().ok_or(ConnectError {
+        is_bad_addr: true,
+        message: "Unknown protocols combination".to_string(),
+    })?;
+    let proto3 = iter.next();
+
+    if iter.next().is_some() {
+        return Err(ConnectError {
+            is_bad_addr: true,
+            message: "Unknown protocols combination".to_string(),
+        });
+    }
+
+    // TODO: doesn't support WebSocket secure connections
+
+    // Ensure ahead of time that the multiaddress is supported.
+    let (addr, host_if_websocket) = match (&proto1, &proto2, &proto3) {
+        (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), None) => (
+            TcpAddress::SocketAddr(SocketAddr::new(IpAddr::V4((*ip).into()), *port)),
+            None,
+        ),
+        (ProtocolRef::Ip6(ip), ProtocolRef::Tcp(port), None) => (
+            TcpAddress::SocketAddr(SocketAddr::new(IpAddr::V6((*ip).into()), *port)),
+            None,
+        ),
+        (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Ws)) => {
+            let addr = SocketAddr::new(IpAddr::V4((*ip).into()), *port);
+            (TcpAddress::SocketAddr(addr), Some(addr.to_string()))
+        }
+        (ProtocolRef::Ip6(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Ws)) => {
+            let addr = SocketAddr::new(IpAddr::V6((*ip).into()), *port);
+            (TcpAddress::SocketAddr(addr), Some(addr.to_string()))
+        }
+
+        // TODO: we don't care about the differences between Dns, Dns4, and Dns6
+        (
+            ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr),
+            ProtocolRef::Tcp(port),
+            None,
+        ) => (TcpAddress::Dns(addr.to_string(), *port), None),
+        (
+            ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr),
+            ProtocolRef::Tcp(port),
+            Some(ProtocolRef::Ws),
+        ) => (
+            TcpAddress::Dns(addr.to_string(), *port),
+            Some(format!("{}:{}", addr, *port)),
+        ),
+
+        _ => {
+            return Err(ConnectError {
+                is_bad_addr: true,
+                message: "Unknown protocols combination".to_string(),
+            })
+        }
+    };
+
+    let tcp_socket = tcp_connect(addr).await;
+
+    let socket = match (tcp_socket, host_if_websocket) {
+        (Ok(tcp_socket), Some(host)) => future::Either::Right(
+            websocket::websocket_client_handshake(websocket::Config {
+                tcp_socket,
+                host: &host,
+                url: "/",
+            })
+            .await
+            .map_err(|err| ConnectError {
+                message: format!("Failed to negotiate WebSocket: {err}"),
+                is_bad_addr: false,
+            })?,
+        ),
+        (Ok(tcp_socket), None) => future::Either::Left(tcp_socket),
+        (Err(err), _) => {
+            return Err(ConnectError {
+                is_bad_addr: false,
+                message: format!("Failed to reach peer: {err}"),
+            })
+        }
+    };
+
+    Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(
+        Stream {
+            socket,
+            buffers: Some((
+                StreamReadBuffer::Open {
+                    buffer: vec![0; 16384],
+                    cursor: 0..0,
+                },
+                StreamWriteBuffer::Open {
+                    buffer: VecDeque::with_capacity(16384),
+                    must_close: false,
+                    must_flush: false,
+                },
+            )),
+        },
+    ))
+}
+
+/// Implementation of `Platform::update_stream`.
+pub fn update_stream<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: &'_ mut Stream<T>,
+) -> future::BoxFuture<'_, ()> {
+    Box::pin(future::poll_fn(|cx| {
+        let Some((read_buffer, write_buffer)) = stream.buffers.as_mut() else {
+            return Poll::Pending;
+        };
+
+        // Whether the future returned by `update_stream` should return `Ready` or `Pending`.
+        let mut update_stream_future_ready = false;
+
+        if let StreamReadBuffer::Open {
+            buffer: ref mut buf,
+            ref mut cursor,
+        } = read_buffer
+        {
+            // When reading data from the socket, `poll_read` might return "EOF". In that
+            // situation, we transition to the `Closed` state, which would discard the data
+            // currently in the buffer. For this reason, we only try to read if there is no
+            // data left in the buffer.
+            if cursor.start == cursor.end {
+                if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_read(cx, buf) {
+                    update_stream_future_ready = true;
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            stream.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(0) => {
+                            // EOF.
+                            *read_buffer = StreamReadBuffer::Closed;
+                        }
+                        Ok(bytes) => {
+                            *cursor = 0..bytes;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let StreamWriteBuffer::Open {
+            buffer: ref mut buf,
+            must_flush,
+            must_close,
+        } = write_buffer
+        {
+            while !buf.is_empty() {
+                let write_queue_slices = buf.as_slices();
+                if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_write_vectored(
+                    cx,
+                    &[
+                        IoSlice::new(write_queue_slices.0),
+                        IoSlice::new(write_queue_slices.1),
+                    ],
+                ) {
+                    if !*must_close {
+                        // In the situation where the API user wants to close the writing
+                        // side, simply sending the buffered data isn't enough to justify
+                        // making the future ready.
+                        update_stream_future_ready = true;
+                    }
+
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            stream.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(bytes) => {
+                            *must_flush = true;
+                            for _ in 0..bytes {
+                                buf.pop_front();
+                            }
+                        }
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            if buf.is_empty() && *must_close {
+                if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_close(cx) {
+                    update_stream_future_ready = true;
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            stream.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(()) => {
+                            *write_buffer = StreamWriteBuffer::Closed;
+                        }
+                    }
+                }
+            } else if *must_flush {
+                if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_flush(cx) {
+                    update_stream_future_ready = true;
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            stream.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(()) => {
+                            *must_flush = false;
+                        }
+                    }
+                }
+            }
+        }
+
+        if update_stream_future_ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }))
+}
+
+/// Implementation of `Platform::read_buffer`.
+pub fn read_buffer<T>(stream: &mut Stream<T>) -> ReadBuffer {
+    match stream.buffers.as_ref().map(|(r, _)| r) {
+        None => ReadBuffer::Reset,
+        Some(StreamReadBuffer::Closed) => ReadBuffer::Closed,
+        Some(StreamReadBuffer::Open { buffer, cursor }) => {
+            ReadBuffer::Open(&buffer[cursor.clone()])
+        }
+    }
+}
+
+/// Implementation of `Platform::advance_read_cursor`.
+pub fn advance_read_cursor<T>(stream: &mut Stream<T>, extra_bytes: usize) {
+    let Some(StreamReadBuffer::Open { ref mut cursor, .. }) =
+        stream.buffers.as_mut().map(|(r, _)| r)
+    else {
+        assert_eq!(extra_bytes, 0);
+        return;
+    };
+
+    assert!(cursor.start + extra_bytes <= cursor.end);
+    cursor.start += extra_bytes;
+}
+
+/// Implementation of `Platform::writable_bytes`.
+pub fn writable_bytes<T>(stream: &mut Stream<T>) -> usize {
+    let Some(StreamWriteBuffer::Open {
+        ref mut buffer,
+        must_close: false,
+        ..
+    }) = stream.buffers.as_mut().map(|(_, w)| w)
+    else {
+        return 0;
+    };
+    buffer.capacity() - buffer.len()
+}
+
+/// Implementation of `Platform::send`.
+pub fn send<T>(stream: &mut Stream<T>, data: &[u8]) {
+    debug_assert!(!data.is_empty());
+
+    // Because `writable_bytes` returns 0 if the writing side is closed, and because `data`
+    // must always have a size inferior or equal to `writable_bytes`, we know for sure that
+    // the writing side isn't closed.
+    let Some(StreamWriteBuffer::Open { ref mut buffer, .. }) =
+        stream.buffers.as_mut().map(|(_, w)| w)
+    else {
+        panic!()
+    };
+    buffer.reserve(data.len());
+    buffer.extend(data.iter().copied());
+}
+
+/// Implementation of `Platform::close_send`.
+pub fn close_send<T>(stream: &mut Stream<T>) {
+    // It is not illegal to call this on an already-reset stream.
+    let Some((_, write_buffer)) = stream.buffers.as_mut() else {
+        return;
+    };
+
+    match write_buffer {
+        StreamWriteBuffer::Open {
+            must_close: must_close @ false,
+            ..
+        } => *must_close = true,
+        _ => {
+            // However, it is illegal to call this on a stream that was already close
+            // attempted.
+            panic!()
+        }
+    }
+}
+
+/// TCP connection, or WebSocket connection on top of a TCP connection, and its buffers.
+///
+/// `T` is the type of the TCP socket of the runtime.
+pub struct Stream<T> {
+    socket: future::Either<T, websocket::Connection<T>>,
+    /// Read and write buffers of the connection, or `None` if the socket has been reset.
+    buffers: Option<(StreamReadBuffer, StreamWriteBuffer)>,
+}
+
+enum StreamReadBuffer {
+    Open {
+        buffer: Vec<u8>,
+        cursor: ops::Range<usize>,
+    },
+    Closed,
+}
+
+enum StreamWriteBuffer {
+    Open {
+        buffer: VecDeque<u8>,
+        must_flush: bool,
+        must_close: bool,
+    },
+    Closed,
+}
@@ -0,0 +1,133 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(feature = "tokio")]
+#![cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+
+use super::{
+    tcp_websocket, ConnectError, Platform, PlatformConnection, PlatformSubstreamDirection,
+    ReadBuffer,
+};
+
+use core::time::Duration;
+use futures::prelude::*;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt as _};
+
+/// Implementation of the [`Platform`] trait that uses the `tokio` library and provides TCP
+/// and WebSocket connections.
+///
+/// The functions of this platform must be called from within the context of a `tokio` runtime
+/// with the I/O and time drivers enabled.
+pub struct TokioTcpWebSocket;
+
+impl Platform for TokioTcpWebSocket {
+    type Delay = future::BoxFuture<'static, ()>;
+    type Yield = future::Ready<()>;
+    type Instant = std::time::Instant;
+    type Connection = std::convert::Infallible;
+    type Stream = Stream;
+    type ConnectFuture = future::BoxFuture<
+        'static,
+        Result<PlatformConnection<Self::Stream, Self::Connection>, ConnectError>,
+    >;
+    type StreamUpdateFuture<'a> = future::BoxFuture<'a, ()>;
+    type NextSubstreamFuture<'a> =
+        future::Pending<Option<(Self::Stream, PlatformSubstreamDirection)>>;
+
+    fn now_from_unix_epoch() -> Duration {
+        // Intentionally panic if the time is configured earlier than the UNIX EPOCH.
+        std::time::UNIX_EPOCH.elapsed().unwrap()
+    }
+
+    fn now() -> Self::Instant {
+        std::time::Instant::now()
+    }
+
+    fn sleep(duration: Duration) -> Self::Delay {
+        tokio::time::sleep(duration).boxed()
+    }
+
+    fn sleep_until(when: Self::Instant) -> Self::Delay {
+        tokio::time::sleep_until(tokio::time::Instant::from_std(when)).boxed()
+    }
+
+    fn yield_after_cpu_intensive() -> Self::Yield {
+        // No-op.
+        future::ready(())
+    }
+
+    fn connect(multiaddr: &str) -> Self::ConnectFuture {
+        // We simply copy the address to own it. We could be more zero-cost here, but doing so
+        // would considerably complicate the implementation.
+        let multiaddr = multiaddr.to_owned();
+
+        Box::pin(async move {
+            tcp_websocket::connect(&multiaddr, |addr| async move {
+                let tcp_socket = match addr {
+                    tcp_websocket::TcpAddress::SocketAddr(socket_addr) => {
+                        tokio::net::TcpStream::connect(socket_addr).await
+                    }
+                    tcp_websocket::TcpAddress::Dns(dns, port) => {
+                        tokio::net::TcpStream::connect((&dns[..], port)).await
+                    }
+                }?;
+                let _ = tcp_socket.set_nodelay(true);
+                Ok(tcp_socket.compat())
+            })
+            .await
+        })
+    }
+
+    fn open_out_substream(c: &mut Self::Connection) {
+        // This function can only be called with so-called "multi-stream" connections. We never
+        // open such connection.
+        match *c {}
+    }
+
+    fn next_substream(c: &'_ mut Self::Connection) -> Self::NextSubstreamFuture<'_> {
+        // This function can only be called with so-called "multi-stream" connections. We never
+        // open such connection.
+        match *c {}
+    }
+
+    fn update_stream(stream: &'_ mut Self::Stream) -> Self::StreamUpdateFuture<'_> {
+        tcp_websocket::update_stream(stream)
+    }
+
+    fn read_buffer(stream: &mut Self::Stream) -> ReadBuffer {
+        tcp_websocket::read_buffer(stream)
+    }
+
+    fn advance_read_cursor(stream: &mut Self::Stream, extra_bytes: usize) {
+        tcp_websocket::advance_read_cursor(stream, extra_bytes)
+    }
+
+    fn writable_bytes(stream: &mut Self::Stream) -> usize {
+        tcp_websocket::writable_bytes(stream)
+    }
+
+    fn send(stream: &mut Self::Stream, data: &[u8]) {
+        tcp_websocket::send(stream, data)
+    }
+
+    fn close_send(stream: &mut Self::Stream) {
+        tcp_websocket::close_send(stream)
+    }
+}
+
+/// Implementation detail of [`TokioTcpWebSocket`].
+pub type Stream = tcp_websocket::Stream<Compat<tokio::net::TcpStream>>;
@@ -0,0 +1,544 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Implementation of the [`Platform`] trait for the `wasm32-wasip2` target, using the
+//! `wasi:sockets` and `wasi:clocks` interfaces.
+//!
+//! WASI components are single-threaded, and waiting for a socket or a timer is done by calling
+//! the blocking `wasi:io/poll` function. As such, the tasks of the client must be spawned on
+//! an [`Executor`], and the component must call [`block_on`], which runs the tasks of the
+//! executor and waits for the sockets and the timers whenever all the tasks are idle.
+//!
+//! Only TCP connections are supported. DNS names are resolved using `wasi:sockets`.
+
+#![cfg(all(feature = "wasi", target_os = "wasi"))]
+#![cfg_attr(docsrs, doc(cfg(all(feature = "wasi", target_os = "wasi"))))]
+
+use super::{
+    embedded::{self, Executor},
+    ConnectError, Platform, PlatformConnection, PlatformSubstreamDirection, ReadBuffer,
+};
+
+use alloc::sync::Arc;
+use core::{
+    cell::RefCell,
+    future::Future,
+    pin::pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures::{channel::oneshot, prelude::*, task::ArcWake};
+use smoldot::libp2p::multiaddr::{Multiaddr, ProtocolRef};
+use std::sync::OnceLock;
+use wasi::{
+    clocks::{monotonic_clock, wall_clock},
+    io::{poll::Pollable, streams},
+    sockets::{
+        instance_network, ip_name_lookup,
+        network::{self, ErrorCode, IpAddress, IpSocketAddress},
+        tcp::{ShutdownType, TcpSocket},
+        tcp_create_socket,
+    },
+};
+
+/// Size of the read and write buffers of each connection.
+const BUFFER_SIZE: usize = 16384;
+
+/// Implementation of the [`Platform`] trait for WASI components. See [the module-level
+/// documentation](self).
+pub struct WasiPlatform;
+
+impl Platform for WasiPlatform {
+    type Delay = embedded::Delay<Duration>;
+    type Yield = future::Ready<()>;
+    type Instant = Duration;
+    type Connection = std::convert::Infallible;
+    type Stream = embedded::Stream;
+    type ConnectFuture = future::BoxFuture<
+        'static,
+        Result<PlatformConnection<Self::Stream, Self::Connection>, ConnectError>,
+    >;
+    type StreamUpdateFuture<'a> = embedded::StreamUpdate<'a>;
+    type NextSubstreamFuture<'a> =
+        future::Pending<Option<(Self::Stream, PlatformSubstreamDirection)>>;
+
+    fn now_from_unix_epoch() -> Duration {
+        let now = wall_clock::now();
+        Duration::new(now.seconds, now.nanoseconds)
+    }
+
+    fn now() -> Self::Instant {
+        Duration::from_nanos(monotonic_clock::now())
+    }
+
+    fn sleep(duration: Duration) -> Self::Delay {
+        timers().sleep_until(Self::now() + duration)
+    }
+
+    fn sleep_until(when: Self::Instant) -> Self::Delay {
+        timers().sleep_until(when)
+    }
+
+    fn yield_after_cpu_intensive() -> Self::Yield {
+        // No-op.
+        future::ready(())
+    }
+
+    fn connect(multiaddr: &str) -> Self::ConnectFuture {
+        let target = match parse_multiaddr(multiaddr) {
+            Ok(target) => target,
+            Err(err) => return future::ready(Err(err)).boxed(),
+        };
+
+        let (result_tx, result_rx) = oneshot::channel();
+        REACTOR.with(|reactor| {
+            let mut reactor = reactor.borrow_mut();
+            let state = match target {
+                Target::Ip(address) => start_connect(&reactor.network, address),
+                Target::Dns(name, port) => {
+                    match ip_name_lookup::resolve_addresses(&reactor.network, &name) {
+                        Ok(addresses) => Ok(ConnectionState::Resolving { addresses, port }),
+                        Err(err) => Err(err),
+                    }
+                }
+            };
+
+            match state {
+                Ok(state) => reactor.connections.push(Connection {
+                    state,
+                    result_tx: Some(result_tx),
+                }),
+                Err(err) => {
+                    let _ = result_tx.send(Err(connect_error(err)));
+                }
+            }
+        });
+
+        Box::pin(async move {
+            let stream = result_rx.await.map_err(|_| ConnectError {
+                message: "Connection attempt aborted".to_owned(),
+                is_bad_addr: false,
+            })??;
+            Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(
+                stream,
+            ))
+        })
+    }
+
+    fn open_out_substream(c: &mut Self::Connection) {
+        // This function can only be called with so-called "multi-stream" connections. We never
+        // open such connection.
+        match *c {}
+    }
+
+    fn next_substream(c: &'_ mut Self::Connection) -> Self::NextSubstreamFuture<'_> {
+        // This function can only be called with so-called "multi-stream" connections. We never
+        // open such connection.
+        match *c {}
+    }
+
+    fn update_stream(stream: &'_ mut Self::Stream) -> Self::StreamUpdateFuture<'_> {
+        stream.update()
+    }
+
+    fn read_buffer(stream: &mut Self::Stream) -> ReadBuffer {
+        stream.read_buffer()
+    }
+
+    fn advance_read_cursor(stream: &mut Self::Stream, bytes: usize) {
+        stream.advance_read_cursor(bytes)
+    }
+
+    fn writable_bytes(stream: &mut Self::Stream) -> usize {
+        stream.writable_bytes()
+    }
+
+    fn send(stream: &mut Self::Stream, data: &[u8]) {
+        stream.send(data)
+    }
+
+    fn close_send(stream: &mut Self::Stream) {
+        stream.close_send()
+    }
+}
+
+/// Runs `future`, the tasks of `executor`, the timers, and the connections of the
+/// [`WasiPlatform`], until `future` has finished.
+///
+/// All the tasks passed to [`ClientConfig::tasks_spawner`](crate::ClientConfig::tasks_spawner)
+/// must be spawned on `executor`.
+pub fn block_on<T>(executor: &Executor, future: impl Future<Output = T>) -> T {
+    let mut future = pin!(future);
+
+    let main_woken = Arc::new(MainWaker {
+        woken: AtomicBool::new(true),
+    });
+    let main_waker = futures::task::waker(main_woken.clone());
+
+    loop {
+        if main_woken.woken.swap(false, Ordering::Relaxed) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&main_waker))
+            {
+                return output;
+            }
+        }
+
+        executor.run_until_stalled();
+
+        timers().advance(WasiPlatform::now());
+        let progress = REACTOR.with(|reactor| reactor.borrow_mut().pump());
+
+        if progress || !executor.is_stalled() || main_woken.woken.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        // Nothing can progress anymore until a socket or a timer is ready.
+        REACTOR.with(|reactor| reactor.borrow().wait(timers().next_deadline()));
+    }
+}
+
+struct MainWaker {
+    woken: AtomicBool,
+}
+
+impl ArcWake for MainWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.woken.store(true, Ordering::Relaxed);
+    }
+}
+
+fn timers() -> &'static embedded::TimerQueue<Duration> {
+    static TIMERS: OnceLock<embedded::TimerQueue<Duration>> = OnceLock::new();
+    TIMERS.get_or_init(|| embedded::TimerQueue::new(WasiPlatform::now()))
+}
+
+std::thread_local! {
+    // WASI components are single-threaded, and the resources of `wasi` can't be shared between
+    // threads anyway.
+    static REACTOR: RefCell<Reactor> = RefCell::new(Reactor {
+        network: instance_network::instance_network(),
+        connections: Vec::new(),
+    });
+}
+
+/// Connections of the [`WasiPlatform`].
+struct Reactor {
+    network: network::Network,
+    connections: Vec<Connection>,
+}
+
+struct Connection {
+    state: ConnectionState,
+    /// Sender for the outcome of the connection attempt. `None` once the connection is open.
+    result_tx: Option<oneshot::Sender<Result<embedded::Stream, ConnectError>>>,
+}
+
+enum ConnectionState {
+    Resolving {
+        addresses: ip_name_lookup::ResolveAddressStream,
+        port: u16,
+    },
+    Connecting {
+        /// Always `Some`, except transiently while the connection is being opened.
+        socket: Option<TcpSocket>,
+    },
+    Open {
+        // Note that the streams must be destroyed before the socket, and thus must be declared
+        // before it.
+        input: streams::InputStream,
+        output: streams::OutputStream,
+        socket: TcpSocket,
+        driver: embedded::StreamDriver,
+        /// Data retrieved from the driver and not written to `output` yet.
+        pending_out: Vec<u8>,
+        read_closed: bool,
+        write_closed: bool,
+    },
+}
+
+impl Reactor {
+    /// Transfers data between the sockets and the [`embedded::Stream`]s and advances the
+    /// connection attempts, without blocking. Returns `true` if anything has happened.
+    fn pump(&mut self) -> bool {
+        let mut progress = false;
+
+        for index in (0..self.connections.len()).rev() {
+            match self.pump_connection(index) {
+                Ok(connection_progress) => progress |= connection_progress,
+                Err(error) => {
+                    progress = true;
+                    let connection = self.connections.swap_remove(index);
+                    if let Some(result_tx) = connection.result_tx {
+                        let _ = result_tx.send(Err(error));
+                    } else if let ConnectionState::Open { driver, .. } = &connection.state {
+                        driver.reset();
+                    }
+                }
+            }
+        }
+
+        progress
+    }
+
+    /// Advances the given connection. An `Err` is returned if the connection must be removed.
+    fn pump_connection(&mut self, index: usize) -> Result<bool, ConnectError> {
+        let connection = &mut self.connections[index];
+
+        if connection
+            .result_tx
+            .as_ref()
+            .map_or(false, |tx| tx.is_canceled())
+        {
+            return Err(connect_error(ErrorCode::ConnectionAborted));
+        }
+
+        match &mut connection.state {
+            ConnectionState::Resolving { addresses, port } => {
+                match addresses.resolve_next_address() {
+                    Ok(Some(address)) => {
+                        connection.state =
+                            start_connect(&self.network, ip_socket_address(address, *port))
+                                .map_err(connect_error)?;
+                        Ok(true)
+                    }
+                    Ok(None) => Err(ConnectError {
+                        message: "No address found".to_owned(),
+                        is_bad_addr: false,
+                    }),
+                    Err(ErrorCode::WouldBlock) => Ok(false),
+                    Err(err) => Err(connect_error(err)),
+                }
+            }
+            ConnectionState::Connecting { socket } => {
+                match socket.as_ref().unwrap().finish_connect() {
+                    Ok((input, output)) => {
+                        let (stream, driver) = embedded::stream(BUFFER_SIZE, BUFFER_SIZE);
+                        connection.state = ConnectionState::Open {
+                            input,
+                            output,
+                            socket: socket.take().unwrap(),
+                            driver,
+                            pending_out: Vec::new(),
+                            read_closed: false,
+                            write_closed: false,
+                        };
+                        if let Some(result_tx) = connection.result_tx.take() {
+                            let _ = result_tx.send(Ok(stream));
+                        }
+                        Ok(true)
+                    }
+                    Err(ErrorCode::WouldBlock) => Ok(false),
+                    Err(err) => Err(connect_error(err)),
+                }
+            }
+            ConnectionState::Open {
+                input,
+                output,
+                socket,
+                driver,
+                pending_out,
+                read_closed,
+                write_closed,
+            } => {
+                if driver.is_stream_dropped() {
+                    // Dropping the socket closes the connection.
+                    return Err(connect_error(ErrorCode::ConnectionAborted));
+                }
+
+                let mut progress = false;
+
+                if !*read_closed && driver.incoming_len() < BUFFER_SIZE {
+                    match input.read(BUFFER_SIZE as u64) {
+                        Ok(data) if data.is_empty() => {}
+                        Ok(data) => {
+                            driver.inject_received(&data);
+                            progress = true;
+                        }
+                        Err(streams::StreamError::Closed) => {
+                            driver.close_received();
+                            *read_closed = true;
+                            progress = true;
+                        }
+                        Err(streams::StreamError::LastOperationFailed(err)) => {
+                            return Err(ConnectError {
+                                message: err.to_debug_string(),
+                                is_bad_addr: false,
+                            })
+                        }
+                    }
+                }
+
+                if pending_out.is_empty() {
+                    let mut buffer = [0; BUFFER_SIZE];
+                    let num_bytes = driver.read_outgoing(&mut buffer);
+                    pending_out.extend_from_slice(&buffer[..num_bytes]);
+                }
+
+                if !pending_out.is_empty() {
+                    let writable = output.check_write().map_err(|_| ConnectError {
+                        message: "Failed to write on socket".to_owned(),
+                        is_bad_addr: false,
+                    })?;
+                    let num_bytes = usize::try_from(writable)
+                        .unwrap_or(usize::MAX)
+                        .min(pending_out.len());
+                    if num_bytes != 0 {
+                        output
+                            .write(&pending_out[..num_bytes])
+                            .map_err(|_| ConnectError {
+                                message: "Failed to write on socket".to_owned(),
+                                is_bad_addr: false,
+                            })?;
+                        pending_out.drain(..num_bytes);
+                        progress = true;
+                    }
+                } else if !*write_closed && driver.is_send_closed() {
+                    let _ = socket.shutdown(ShutdownType::Send);
+                    *write_closed = true;
+                    progress = true;
+                }
+
+                Ok(progress)
+            }
+        }
+    }
+
+    /// Blocks until one of the sockets is ready or `deadline` is reached.
+    fn wait(&self, deadline: Option<Duration>) {
+        let mut pollables: Vec<Pollable> = Vec::with_capacity(self.connections.len() + 1);
+
+        if let Some(deadline) = deadline {
+            pollables.push(monotonic_clock::subscribe_instant(
+                u64::try_from(deadline.as_nanos()).unwrap_or(u64::MAX),
+            ));
+        }
+
+        for connection in &self.connections {
+            match &connection.state {
+                ConnectionState::Resolving { addresses, .. } => {
+                    pollables.push(addresses.subscribe())
+                }
+                ConnectionState::Connecting { socket } => {
+                    pollables.push(socket.as_ref().unwrap().subscribe())
+                }
+                ConnectionState::Open {
+                    input,
+                    output,
+                    driver,
+                    pending_out,
+                    read_closed,
+                    ..
+                } => {
+                    if !*read_closed && driver.incoming_len() < BUFFER_SIZE {
+                        pollables.push(input.subscribe());
+                    }
+                    if !pending_out.is_empty() {
+                        pollables.push(output.subscribe());
+                    }
+                }
+            }
+        }
+
+        if pollables.is_empty() {
+            return;
+        }
+
+        let pollables = pollables.iter().collect::<Vec<_>>();
+        wasi::io::poll::poll(&pollables);
+    }
+}
+
+/// Address to connect to.
+enum Target {
+    Ip(IpSocketAddress),
+    Dns(String, u16),
+}
+
+fn parse_multiaddr(multiaddr: &str) -> Result<Target, ConnectError> {
+    let bad_addr = || ConnectError {
+        is_bad_addr: true,
+        message: "Unknown protocols combination".to_owned(),
+    };
+
+    let addr = multiaddr.parse::<Multiaddr>().map_err(|_| ConnectError {
+        is_bad_addr: true,
+        message: "Failed to parse address".to_owned(),
+    })?;
+
+    let mut iter = addr.iter().fuse();
+    let (Some(proto1), Some(ProtocolRef::Tcp(port)), None) =
+        (iter.next(), iter.next(), iter.next())
+    else {
+        return Err(bad_addr());
+    };
+
+    match proto1 {
+        ProtocolRef::Ip4(ip) => Ok(Target::Ip(ip_socket_address(
+            IpAddress::Ipv4((ip[0], ip[1], ip[2], ip[3])),
+            port,
+        ))),
+        ProtocolRef::Ip6(ip) => {
+            let ip = std::net::Ipv6Addr::from(ip).segments();
+            Ok(Target::Ip(ip_socket_address(
+                IpAddress::Ipv6((ip[0], ip[1], ip[2], ip[3], ip[4], ip[5], ip[6], ip[7])),
+                port,
+            )))
+        }
+        // TODO: we don't care about the differences between Dns, Dns4, and Dns6
+        ProtocolRef::Dns(name) | ProtocolRef::Dns4(name) | ProtocolRef::Dns6(name) => {
+            Ok(Target::Dns(name.to_string(), port))
+        }
+        _ => Err(bad_addr()),
+    }
+}
+
+fn ip_socket_address(address: IpAddress, port: u16) -> IpSocketAddress {
+    match address {
+        IpAddress::Ipv4(address) => {
+            IpSocketAddress::Ipv4(network::Ipv4SocketAddress { port, address })
+        }
+        IpAddress::Ipv6(address) => IpSocketAddress::Ipv6(network::Ipv6SocketAddress {
+            port,
+            flow_info: 0,
+            address,
+            scope_id: 0,
+        }),
+    }
+}
+
+fn start_connect(
+    network: &network::Network,
+    address: IpSocketAddress,
+) -> Result<ConnectionState, ErrorCode> {
+    let family = match address {
+        IpSocketAddress::Ipv4(_) => network::IpAddressFamily::Ipv4,
+        IpSocketAddress::Ipv6(_) => network::IpAddressFamily::Ipv6,
+    };
+    let socket = tcp_create_socket::create_tcp_socket(family)?;
+    socket.start_connect(network, address)?;
+    Ok(ConnectionState::Connecting {
+        socket: Some(socket),
+    })
+}
+
+fn connect_error(error: ErrorCode) -> ConnectError {
+    ConnectError {
+        message: format!("{error:?}"),
+        is_bad_addr: false,
+    }
+}
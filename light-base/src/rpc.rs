@@ -0,0 +1,573 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Strongly-typed JSON-RPC client built on top of the JSON-RPC service of a chain.
+//!
+//! Sending JSON-RPC requests through [`Client::json_rpc_request`](crate::Client::json_rpc_request)
+//! requires building JSON requests by hand, allocating request identifiers, and matching the
+//! responses and notifications pulled from [`JsonRpcResponses`] with the requests that have
+//! been sent. The [`RpcClient`] of this module takes care of all of this.
+//!
+//! # Usage
+//!
+//! Call [`RpcClient::new`], passing the [`JsonRpcResponses`] of a chain. This returns the
+//! [`RpcClient`] itself, plus a background task that must be spawned. The background task
+//! automatically ends when the chain is removed.
+//!
+//! The [`RpcClient`] takes ownership of the [`JsonRpcResponses`]. It is still possible to send
+//! requests using [`Client::json_rpc_request`](crate::Client::json_rpc_request), but the
+//! responses to these requests are silently discarded.
+
+use crate::JsonRpcResponses;
+
+use alloc::{
+    borrow::ToOwned as _,
+    format,
+    string::{String, ToString as _},
+    vec::Vec,
+};
+use core::{fmt, marker::PhantomData};
+use futures::{
+    channel::{mpsc, oneshot},
+    lock::Mutex,
+    prelude::*,
+};
+use hashbrown::HashMap;
+use smoldot::json_rpc::methods;
+
+/// Strongly-typed JSON-RPC client connected to the JSON-RPC service of a chain.
+///
+/// See [the module-level documentation](self).
+pub struct RpcClient {
+    /// Channel to the background task.
+    to_background: Mutex<mpsc::Sender<ToBackground>>,
+}
+
+impl RpcClient {
+    /// Initializes a new [`RpcClient`] from the stream of responses of a chain.
+    ///
+    /// Returns the client and a background task. The background task must be spawned, otherwise
+    /// no request will be answered. It ends when the chain is removed.
+    pub fn new(
+        json_rpc_responses: JsonRpcResponses,
+    ) -> (RpcClient, impl Future<Output = ()> + Send + 'static) {
+        let (to_background, from_foreground) = mpsc::channel(16);
+        let client = RpcClient {
+            to_background: Mutex::new(to_background),
+        };
+        (client, run_background(json_rpc_responses, from_foreground))
+    }
+
+    /// Sends a request to the given JSON-RPC method, and waits for the response.
+    ///
+    /// `params_json` must be the JSON-formatted list or object of parameters. On success,
+    /// returns the JSON-formatted result of the request.
+    pub async fn request(&self, method: &str, params_json: &str) -> Result<String, RpcError> {
+        let (send_back, rx) = oneshot::channel();
+        self.send_to_background(ToBackground::Request {
+            method: method.to_owned(),
+            params_json: params_json.to_owned(),
+            send_back,
+        })
+        .await?;
+        rx.await.map_err(|_| RpcError::ChainRemoved)?
+    }
+
+    /// Sends a request to the given JSON-RPC subscription method, and waits for the subscription
+    /// to be confirmed.
+    ///
+    /// `params_json` must be the JSON-formatted list or object of parameters. The notifications
+    /// of the subscription are yielded by the returned [`Subscription`] in their JSON form.
+    ///
+    /// `unsubscribe_method` is the JSON-RPC method called in order to unsubscribe when the
+    /// [`Subscription`] is destroyed.
+    pub async fn subscribe(
+        &self,
+        method: &str,
+        params_json: &str,
+        unsubscribe_method: &str,
+    ) -> Result<Subscription<String>, RpcError> {
+        self.subscribe_inner(method, params_json, unsubscribe_method, |json| {
+            Ok(json.to_owned())
+        })
+        .await
+    }
+
+    /// Calls the `system_health` JSON-RPC method.
+    pub async fn system_health(&self) -> Result<SystemHealth, RpcError> {
+        let result = self.request("system_health", "[]").await?;
+        decode(&result)
+    }
+
+    /// Calls the `state_getStorage` JSON-RPC method.
+    ///
+    /// If `block_hash` is `None`, the storage of the current best block is queried. Returns
+    /// `None` if there is no storage value associated with this key.
+    pub async fn state_get_storage(
+        &self,
+        key: &[u8],
+        block_hash: Option<&[u8; 32]>,
+    ) -> Result<Option<Vec<u8>>, RpcError> {
+        let params = match block_hash {
+            Some(hash) => format!(r#"["0x{}","0x{}"]"#, hex::encode(key), hex::encode(hash)),
+            None => format!(r#"["0x{}"]"#, hex::encode(key)),
+        };
+
+        let result = self.request("state_getStorage", &params).await?;
+        let value: Option<methods::HexString> = decode(&result)?;
+        Ok(value.map(|v| v.0))
+    }
+
+    /// Calls the `chain_subscribeFinalizedHeads` JSON-RPC method.
+    pub async fn subscribe_finalized_heads(&self) -> Result<Subscription<BlockHeader>, RpcError> {
+        self.subscribe_inner(
+            "chain_subscribeFinalizedHeads",
+            "[]",
+            "chain_unsubscribeFinalizedHeads",
+            |json| decode::<SerdeHeader>(json).and_then(BlockHeader::try_from),
+        )
+        .await
+    }
+
+    /// Calls the `chain_subscribeNewHeads` JSON-RPC method.
+    pub async fn subscribe_new_heads(&self) -> Result<Subscription<BlockHeader>, RpcError> {
+        self.subscribe_inner(
+            "chain_subscribeNewHeads",
+            "[]",
+            "chain_unsubscribeNewHeads",
+            |json| decode::<SerdeHeader>(json).and_then(BlockHeader::try_from),
+        )
+        .await
+    }
+
+    async fn subscribe_inner<T>(
+        &self,
+        method: &str,
+        params_json: &str,
+        unsubscribe_method: &str,
+        decode: fn(&str) -> Result<T, RpcError>,
+    ) -> Result<Subscription<T>, RpcError> {
+        let (send_back, rx) = oneshot::channel();
+        self.send_to_background(ToBackground::Subscribe {
+            method: method.to_owned(),
+            params_json: params_json.to_owned(),
+            unsubscribe_method: unsubscribe_method.to_owned(),
+            send_back,
+        })
+        .await?;
+        let notifications = rx.await.map_err(|_| RpcError::ChainRemoved)??;
+        Ok(Subscription {
+            notifications,
+            decode,
+            marker: PhantomData,
+        })
+    }
+
+    async fn send_to_background(&self, message: ToBackground) -> Result<(), RpcError> {
+        self.to_background
+            .lock()
+            .await
+            .send(message)
+            .await
+            .map_err(|_| RpcError::ChainRemoved)
+    }
+}
+
+impl fmt::Debug for RpcClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RpcClient").finish()
+    }
+}
+
+/// Active JSON-RPC subscription. See [`RpcClient::subscribe`].
+///
+/// Destroying this object unsubscribes. The unsubscription request is sent the next time a
+/// notification concerning this subscription is received.
+pub struct Subscription<T> {
+    /// Receives the JSON-formatted notifications from the background task.
+    notifications: mpsc::Receiver<String>,
+    /// Function that decodes a JSON-formatted notification.
+    decode: fn(&str) -> Result<T, RpcError>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Subscription<T> {
+    /// Returns the next notification of the subscription.
+    ///
+    /// Returns `None` if the subscription has been closed, either because the chain has been
+    /// removed or because notifications weren't pulled quickly enough.
+    pub async fn next(&mut self) -> Option<Result<T, RpcError>> {
+        let notification = self.notifications.next().await?;
+        Some((self.decode)(&notification))
+    }
+}
+
+/// Error potentially returned by the methods of [`RpcClient`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum RpcError {
+    /// The chain has been removed.
+    #[display(fmt = "The chain has been removed")]
+    ChainRemoved,
+    /// The JSON-RPC service is too busy to accept the request.
+    #[display(fmt = "The JSON-RPC service is overloaded")]
+    Overloaded,
+    /// The JSON-RPC server has returned an error.
+    #[display(fmt = "JSON-RPC error {code}: {message}")]
+    Server {
+        /// Error code returned by the server.
+        code: i64,
+        /// Error message returned by the server.
+        message: String,
+    },
+    /// Failed to decode the response or notification sent by the JSON-RPC server.
+    #[display(fmt = "Failed to decode JSON-RPC response: {_0}")]
+    Decode(String),
+}
+
+/// See [`RpcClient::system_health`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct SystemHealth {
+    /// `true` if the client is still performing its initial synchronization.
+    #[serde(rename = "isSyncing")]
+    pub is_syncing: bool,
+    /// Number of peers the client is connected to.
+    pub peers: u64,
+    /// `true` if the chain is expected to have peers.
+    #[serde(rename = "shouldHavePeers")]
+    pub should_have_peers: bool,
+}
+
+/// Header of a block, as reported by [`RpcClient::subscribe_finalized_heads`] and
+/// [`RpcClient::subscribe_new_heads`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    /// Hash of the parent of this block.
+    pub parent_hash: [u8; 32],
+    /// Height of the block.
+    pub number: u64,
+    /// Merkle value of the root of the storage trie of the block.
+    pub state_root: [u8; 32],
+    /// Merkle value of the root of the trie of the extrinsics of the block.
+    pub extrinsics_root: [u8; 32],
+    /// SCALE-encoded digest log items of the block.
+    pub digest_logs: Vec<Vec<u8>>,
+}
+
+#[derive(serde::Deserialize)]
+struct SerdeHeader {
+    #[serde(rename = "parentHash")]
+    parent_hash: methods::HashHexString,
+    number: String,
+    #[serde(rename = "stateRoot")]
+    state_root: methods::HashHexString,
+    #[serde(rename = "extrinsicsRoot")]
+    extrinsics_root: methods::HashHexString,
+    digest: methods::HeaderDigest,
+}
+
+impl TryFrom<SerdeHeader> for BlockHeader {
+    type Error = RpcError;
+
+    fn try_from(header: SerdeHeader) -> Result<Self, Self::Error> {
+        let number = header
+            .number
+            .strip_prefix("0x")
+            .and_then(|n| u64::from_str_radix(n, 16).ok())
+            .ok_or_else(|| RpcError::Decode(format!("invalid block number {:?}", header.number)))?;
+
+        Ok(BlockHeader {
+            parent_hash: header.parent_hash.0,
+            number,
+            state_root: header.state_root.0,
+            extrinsics_root: header.extrinsics_root.0,
+            digest_logs: header.digest.logs.into_iter().map(|l| l.0).collect(),
+        })
+    }
+}
+
+fn decode<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, RpcError> {
+    serde_json::from_str(json).map_err(|err| RpcError::Decode(err.to_string()))
+}
+
+/// Message sent from the [`RpcClient`] to the background task.
+enum ToBackground {
+    /// See [`RpcClient::request`].
+    Request {
+        method: String,
+        params_json: String,
+        send_back: oneshot::Sender<Result<String, RpcError>>,
+    },
+    /// See [`RpcClient::subscribe`].
+    Subscribe {
+        method: String,
+        params_json: String,
+        unsubscribe_method: String,
+        send_back: oneshot::Sender<Result<mpsc::Receiver<String>, RpcError>>,
+    },
+}
+
+/// Request sent to the JSON-RPC service whose response hasn't been received yet.
+enum PendingRequest {
+    /// See [`ToBackground::Request`].
+    Request(oneshot::Sender<Result<String, RpcError>>),
+    /// See [`ToBackground::Subscribe`].
+    Subscribe {
+        unsubscribe_method: String,
+        send_back: oneshot::Sender<Result<mpsc::Receiver<String>, RpcError>>,
+    },
+    /// The response must be ignored. Used for unsubscription requests.
+    Ignore,
+}
+
+/// Active subscription within the background task.
+struct ActiveSubscription {
+    /// Sending side of [`Subscription::notifications`].
+    notifications: mpsc::Sender<String>,
+    /// Name of the method to call in order to unsubscribe.
+    unsubscribe_method: String,
+}
+
+/// Message received from the JSON-RPC service, either a response or a notification.
+#[derive(serde::Deserialize)]
+struct SerdeMessage {
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<SerdeErrorObject>,
+    #[serde(default)]
+    params: Option<SerdeNotificationParams>,
+}
+
+#[derive(serde::Deserialize)]
+struct SerdeErrorObject {
+    code: i64,
+    message: String,
+}
+
+#[derive(serde::Deserialize)]
+struct SerdeNotificationParams {
+    subscription: serde_json::Value,
+    result: serde_json::Value,
+}
+
+/// Runs the background task of an [`RpcClient`].
+async fn run_background(
+    json_rpc_responses: JsonRpcResponses,
+    mut from_foreground: mpsc::Receiver<ToBackground>,
+) {
+    // Identifiers of the requests are JSON strings, in order to avoid any ambiguity when the
+    // identifier is parsed back.
+    let mut next_request_id: u64 = 0;
+    let mut pending_requests = HashMap::<String, PendingRequest, _>::with_capacity_and_hasher(
+        8,
+        fnv::FnvBuildHasher::default(),
+    );
+    let mut active_subscriptions =
+        HashMap::<String, ActiveSubscription, _>::with_capacity_and_hasher(
+            8,
+            fnv::FnvBuildHasher::default(),
+        );
+
+    // The frontend is cloned in order to be able to send requests while responses are being
+    // waited for. It is dropped when the chain is removed.
+    let Some(frontend) = json_rpc_responses.inner.clone() else {
+        return;
+    };
+
+    // We wrap the JSON-RPC responses stream into a proper stream in order to be able to guarantee
+    // that `next()` always operates on the same future.
+    let mut responses = stream::unfold(json_rpc_responses, |mut json_rpc_responses| async {
+        let response = json_rpc_responses.next().await?;
+        Some((response, json_rpc_responses))
+    })
+    .boxed();
+
+    let mut queue_request = |method: &str, params_json: &str, pending: PendingRequest| {
+        let request_id = format!("\"smoldot-rpc-{next_request_id}\"");
+        next_request_id += 1;
+
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","id":{request_id},"method":{},"params":{params_json}}}"#,
+            serde_json::to_string(method).unwrap()
+        );
+
+        match frontend.queue_rpc_request(request) {
+            Ok(()) => Some((request_id, pending)),
+            Err(crate::HandleRpcError::Overloaded { .. }) => {
+                match pending {
+                    PendingRequest::Request(send_back) => {
+                        let _ = send_back.send(Err(RpcError::Overloaded));
+                    }
+                    PendingRequest::Subscribe { send_back, .. } => {
+                        let _ = send_back.send(Err(RpcError::Overloaded));
+                    }
+                    PendingRequest::Ignore => {}
+                }
+                None
+            }
+            Err(
+                err @ (crate::HandleRpcError::MalformedJsonRpc(_)
+                | crate::HandleRpcError::BatchTooLarge { .. }),
+            ) => {
+                match pending {
+                    PendingRequest::Request(send_back) => {
+                        let _ = send_back.send(Err(RpcError::Decode(err.to_string())));
+                    }
+                    PendingRequest::Subscribe { send_back, .. } => {
+                        let _ = send_back.send(Err(RpcError::Decode(err.to_string())));
+                    }
+                    PendingRequest::Ignore => {}
+                }
+                None
+            }
+        }
+    };
+
+    loop {
+        match future::select(from_foreground.next(), responses.next()).await {
+            future::Either::Left((None, _)) => {
+                // The `RpcClient` has been destroyed.
+                return;
+            }
+            future::Either::Left((
+                Some(ToBackground::Request {
+                    method,
+                    params_json,
+                    send_back,
+                }),
+                _,
+            )) => {
+                if let Some((id, pending)) =
+                    queue_request(&method, &params_json, PendingRequest::Request(send_back))
+                {
+                    pending_requests.insert(id, pending);
+                }
+            }
+            future::Either::Left((
+                Some(ToBackground::Subscribe {
+                    method,
+                    params_json,
+                    unsubscribe_method,
+                    send_back,
+                }),
+                _,
+            )) => {
+                if let Some((id, pending)) = queue_request(
+                    &method,
+                    &params_json,
+                    PendingRequest::Subscribe {
+                        unsubscribe_method,
+                        send_back,
+                    },
+                ) {
+                    pending_requests.insert(id, pending);
+                }
+            }
+            future::Either::Right((None, _)) => {
+                // The chain has been removed. All the pending requests and subscriptions are
+                // dropped, which notifies the `RpcClient`.
+                return;
+            }
+            future::Either::Right((Some(response), _)) => {
+                let Ok(message) = serde_json::from_str::<SerdeMessage>(&response) else {
+                    log::warn!(target: "json-rpc-client", "Failed to decode {}", response);
+                    continue;
+                };
+
+                // Notifications.
+                if let Some(params) = message.params {
+                    let subscription_id = params.subscription.to_string();
+                    let mut unsubscribe = false;
+                    if let Some(subscription) = active_subscriptions.get_mut(&subscription_id) {
+                        // The subscription is closed if the notifications aren't pulled quickly
+                        // enough.
+                        unsubscribe = subscription
+                            .notifications
+                            .try_send(params.result.to_string())
+                            .is_err();
+                    }
+
+                    if unsubscribe {
+                        let subscription = active_subscriptions.remove(&subscription_id).unwrap();
+                        if let Some((id, pending)) = queue_request(
+                            &subscription.unsubscribe_method,
+                            &format!("[{subscription_id}]"),
+                            PendingRequest::Ignore,
+                        ) {
+                            pending_requests.insert(id, pending);
+                        }
+                    }
+
+                    continue;
+                }
+
+                // Responses.
+                let Some(request_id) = message.id.map(|id| id.to_string()) else {
+                    continue;
+                };
+                let Some(pending) = pending_requests.remove(&request_id) else {
+                    continue;
+                };
+
+                let result = match (message.result, message.error) {
+                    (Some(result), _) => Ok(result),
+                    (None, Some(error)) => Err(RpcError::Server {
+                        code: error.code,
+                        message: error.message,
+                    }),
+                    (None, None) => Ok(serde_json::Value::Null),
+                };
+
+                match pending {
+                    PendingRequest::Request(send_back) => {
+                        let _ = send_back.send(result.map(|r| r.to_string()));
+                    }
+                    PendingRequest::Subscribe {
+                        unsubscribe_method,
+                        send_back,
+                    } => match result {
+                        Ok(subscription_id) => {
+                            let subscription_id = subscription_id.to_string();
+                            let (tx, rx) = mpsc::channel(32);
+                            if send_back.send(Ok(rx)).is_ok() {
+                                active_subscriptions.insert(
+                                    subscription_id,
+                                    ActiveSubscription {
+                                        notifications: tx,
+                                        unsubscribe_method,
+                                    },
+                                );
+                            } else if let Some((id, pending)) = queue_request(
+                                &unsubscribe_method,
+                                &format!("[{subscription_id}]"),
+                                PendingRequest::Ignore,
+                            ) {
+                                pending_requests.insert(id, pending);
+                            }
+                        }
+                        Err(err) => {
+                            let _ = send_back.send(Err(err));
+                        }
+                    },
+                    PendingRequest::Ignore => {}
+                }
+            }
+        }
+    }
+}
@@ -66,6 +66,7 @@ use alloc::{
     vec::Vec,
 };
 use core::{
+    cell::Cell,
     iter, mem,
     num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
@@ -101,6 +102,29 @@ pub struct Config<TPlat: Platform> {
 
     /// Header of the genesis block of the chain, in SCALE encoding.
     pub genesis_block_scale_encoded_header: Vec<u8>,
+
+    /// Maximum number of runtime calls whose call proof and output are kept in memory. Identical
+    /// calls made against the same block re-use the call proof and output stored in this cache
+    /// rather than downloading a call proof again.
+    ///
+    /// If 0, no cache is used.
+    pub runtime_call_cache_size: usize,
+
+    /// Hint passed to the virtual machine when compiling the runtimes of the chain.
+    pub exec_hint: executor::vm::ExecHint,
+
+    /// Maximum amount of fuel that each runtime call is allowed to consume before being
+    /// interrupted. See [`executor::host::Config::fuel_limit`].
+    ///
+    /// If `None`, runtime calls can run for an unlimited amount of time.
+    pub runtime_call_fuel_limit: Option<u64>,
+
+    /// Maximum number of recently-used compiled runtimes that are kept in memory, in order to
+    /// avoid compiling the same runtime again when performing calls against historical blocks.
+    ///
+    /// Runtimes still in use by a block of the chain or by a pinned block are always kept in
+    /// memory regardless of this limit.
+    pub max_cached_runtimes: NonZeroUsize,
 }
 
 /// Identifies a runtime currently pinned within a [`RuntimeService`].
@@ -115,6 +139,11 @@ pub struct RuntimeService<TPlat: Platform> {
     /// Fields behind a `Mutex`. Should only be locked for short-lived operations.
     guarded: Arc<Mutex<Guarded<TPlat>>>,
 
+    /// Cache of the call proofs and outputs of the recent runtime calls. `None` if
+    /// [`Config::runtime_call_cache_size`] is 0. Should only be locked for short-lived
+    /// operations.
+    call_cache: Option<Arc<Mutex<CallCache>>>,
+
     /// Handle to abort the background task.
     background_task_abort: future::AbortHandle,
 }
@@ -160,6 +189,12 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
             best_near_head_of_chain,
             tree,
             runtimes: slab::Slab::with_capacity(2),
+            recent_runtimes: lru::LruCache::with_hasher(
+                config.max_cached_runtimes,
+                Default::default(),
+            ),
+            exec_hint: config.exec_hint,
+            runtime_call_fuel_limit: config.runtime_call_fuel_limit,
         }));
 
         // Spawns a task that runs in the background and updates the content of the mutex.
@@ -177,6 +212,12 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
         RuntimeService {
             sync_service: config.sync_service,
             guarded,
+            call_cache: NonZeroUsize::new(config.runtime_call_cache_size).map(|cache_size| {
+                Arc::new(Mutex::new(lru::LruCache::with_hasher(
+                    cache_size,
+                    Default::default(),
+                )))
+            }),
             background_task_abort,
         }
     }
@@ -335,6 +376,11 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
                 } else {
                     None
                 },
+                new_runtime_code_hash: if !Arc::ptr_eq(&runtime, &parent_runtime) {
+                    runtime.runtime_code_hash
+                } else {
+                    None
+                },
             });
         }
 
@@ -359,6 +405,7 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
                 .as_ref()
                 .map(|rt| rt.runtime_spec.clone())
                 .map_err(|err| err.clone()),
+            finalized_block_runtime_code_hash: tree.finalized_async_user_data().runtime_code_hash,
             non_finalized_blocks_ancestry_order,
             new_blocks: Subscription {
                 subscription_id,
@@ -474,6 +521,7 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
 
         Ok(RuntimeLock {
             sync_service: self.sync_service.clone(),
+            call_cache: self.call_cache.clone(),
             hash: block_hash,
             runtime: pinned_block.runtime,
             block_number: pinned_block.block_number,
@@ -500,6 +548,7 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
     ) -> RuntimeLock<TPlat> {
         RuntimeLock {
             sync_service: self.sync_service.clone(),
+            call_cache: self.call_cache.clone(),
             hash: block_hash,
             runtime: pinned_runtime_id.0,
             block_number,
@@ -519,24 +568,27 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
         let mut guarded = self.guarded.lock().await;
 
         // Try to find an existing identical runtime.
-        let existing_runtime = guarded
-            .runtimes
-            .iter()
-            .filter_map(|(_, rt)| rt.upgrade())
-            .find(|rt| rt.runtime_code == storage_code && rt.heap_pages == storage_heap_pages);
+        let runtime_code_hash = storage_code.as_deref().map(runtime_code_hash);
+        let existing_runtime = guarded.find_runtime(&runtime_code_hash, &storage_heap_pages);
 
         let runtime = if let Some(existing_runtime) = existing_runtime {
             existing_runtime
         } else {
             // No identical runtime was found. Try compiling the new runtime.
-            let runtime =
-                SuccessfulRuntime::from_storage::<TPlat>(&storage_code, &storage_heap_pages).await;
+            let runtime = SuccessfulRuntime::from_storage::<TPlat>(
+                &storage_code,
+                &storage_heap_pages,
+                guarded.exec_hint,
+                guarded.runtime_call_fuel_limit,
+            )
+            .await;
             let runtime = Arc::new(Runtime {
                 heap_pages: storage_heap_pages,
-                runtime_code: storage_code,
+                runtime_code_hash,
                 runtime,
+                metadata: Mutex::new(None),
             });
-            guarded.runtimes.insert(Arc::downgrade(&runtime));
+            guarded.insert_runtime(&runtime);
             runtime
         };
 
@@ -562,6 +614,19 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
     pub async fn is_near_head_of_chain_heuristic(&self) -> bool {
         is_near_head_of_chain_heuristic(&self.sync_service, &self.guarded).await
     }
+
+    /// Returns the hash of the runtime code of the current finalized block.
+    ///
+    /// Returns `None` if the runtime of the finalized block isn't known yet, or if the finalized
+    /// block has no runtime code.
+    pub async fn finalized_runtime_code_hash(&self) -> Option<[u8; 32]> {
+        match &self.guarded.lock().await.tree {
+            GuardedInner::FinalizedBlockRuntimeKnown { tree, .. } => {
+                tree.finalized_async_user_data().runtime_code_hash
+            }
+            GuardedInner::FinalizedBlockRuntimeUnknown { .. } => None,
+        }
+    }
 }
 
 impl<TPlat: Platform> Drop for RuntimeService<TPlat> {
@@ -578,6 +643,10 @@ pub struct SubscribeAll<TPlat: Platform> {
     /// If the runtime of the finalized block is known, contains the information about it.
     pub finalized_block_runtime: Result<executor::CoreVersion, RuntimeError>,
 
+    /// BLAKE2 hash of the `:code` storage item of the finalized block. `None` if the storage
+    /// item is empty.
+    pub finalized_block_runtime_code_hash: Option<[u8; 32]>,
+
     /// List of all known non-finalized blocks at the time of subscription.
     ///
     /// Only one element in this list has [`BlockNotification::is_new_best`] equal to true.
@@ -711,6 +780,11 @@ pub struct BlockNotification {
     /// If the runtime of the block is different from its parent, contains the information about
     /// the new runtime.
     pub new_runtime: Option<Result<executor::CoreVersion, RuntimeError>>,
+
+    /// If [`BlockNotification::new_runtime`] is `Some`, contains the BLAKE2 hash of the `:code`
+    /// storage item of the block. `None` if [`BlockNotification::new_runtime`] is `None` or if
+    /// the storage item is empty.
+    pub new_runtime_code_hash: Option<[u8; 32]>,
 }
 
 async fn is_near_head_of_chain_heuristic<TPlat: Platform>(
@@ -747,6 +821,7 @@ pub enum PinnedBlockRuntimeLockError {
 #[must_use]
 pub struct RuntimeLock<TPlat: Platform> {
     sync_service: Arc<sync_service::SyncService<TPlat>>,
+    call_cache: Option<Arc<Mutex<CallCache>>>,
 
     block_number: u64,
     block_state_root_hash: [u8; 32],
@@ -768,6 +843,40 @@ impl<TPlat: Platform> RuntimeLock<TPlat> {
         }
     }
 
+    /// Returns the metadata of the runtime, without its length prefix, if it has previously
+    /// been stored with [`RuntimeLock::cache_metadata`].
+    pub async fn cached_metadata(&self) -> Option<Arc<[u8]>> {
+        self.runtime.metadata.lock().await.clone()
+    }
+
+    /// Stores the metadata of the runtime, without its length prefix, so that it can later be
+    /// retrieved with [`RuntimeLock::cached_metadata`].
+    ///
+    /// The metadata only depends on the runtime. It is thus shared between all the blocks that
+    /// use this runtime, and is kept in memory for as long as the runtime is.
+    pub async fn cache_metadata(&self, metadata: Arc<[u8]>) {
+        *self.runtime.metadata.lock().await = Some(metadata);
+    }
+
+    /// Returns the output of a call to the given function with the given parameters, if an
+    /// identical call has recently been performed against the same block and its output is
+    /// still in the cache of the runtime service.
+    ///
+    /// See [`RuntimeCallLock::cache_output`].
+    pub async fn cached_output(
+        &self,
+        method: &str,
+        parameter_vectored: impl Iterator<Item = impl AsRef<[u8]>>,
+    ) -> Option<Vec<u8>> {
+        let call_cache = self.call_cache.as_ref()?;
+        let cache_key = CallCacheKey::new(self.hash, method, parameter_vectored);
+        call_cache
+            .lock()
+            .await
+            .get(&cache_key)
+            .and_then(|entry| entry.output.clone())
+    }
+
     pub async fn start<'b>(
         &'b self,
         method: &'b str,
@@ -778,34 +887,60 @@ impl<TPlat: Platform> RuntimeLock<TPlat> {
     ) -> Result<(RuntimeCallLock<'b>, executor::host::HostVmPrototype), RuntimeCallError> {
         // TODO: DRY :-/ this whole thing is messy
 
-        // Perform the call proof request.
-        // Note that `guarded` is not locked.
-        // TODO: there's no way to verify that the call proof is actually correct; we have to ban the peer and restart the whole call process if it turns out that it's not
-        // TODO: also, an empty proof will be reported as an error right now, which is weird
-        let call_proof = self
-            .sync_service
-            .clone()
-            .call_proof_query(
-                self.block_number,
-                protocol::CallProofRequestConfig {
-                    block_hash: self.hash,
-                    method,
-                    parameter_vectored: parameter_vectored.clone(),
-                },
-                total_attempts,
-                timeout_per_request,
-                max_parallel,
+        let call_cache = self.call_cache.as_ref().map(|call_cache| {
+            (
+                call_cache.clone(),
+                CallCacheKey::new(self.hash, method, parameter_vectored.clone()),
             )
-            .await
-            .map_err(RuntimeCallError::CallProof);
+        });
 
-        let call_proof = call_proof.and_then(|call_proof| {
-            proof_decode::decode_and_verify_proof(proof_decode::Config {
-                proof: call_proof.decode().to_owned(), // TODO: to_owned() inefficiency, need some help from the networking to obtain the owned data
-                trie_root_hash: &self.block_state_root_hash,
+        // If an identical call has recently been made, re-use its call proof.
+        let cached_call_proof = if let Some((call_cache, cache_key)) = &call_cache {
+            call_cache
+                .lock()
+                .await
+                .get(cache_key)
+                .map(|entry| entry.call_proof.clone())
+        } else {
+            None
+        };
+
+        let call_proof_from_cache = cached_call_proof.is_some();
+        let call_proof = if let Some(call_proof) = cached_call_proof {
+            Ok(call_proof)
+        } else {
+            // Perform the call proof request.
+            // Note that `guarded` is not locked.
+            // TODO: there's no way to verify that the call proof is actually correct; we have to ban the peer and restart the whole call process if it turns out that it's not
+            // TODO: also, an empty proof will be reported as an error right now, which is weird
+            let call_proof = self
+                .sync_service
+                .clone()
+                .call_proof_query(
+                    self.block_number,
+                    protocol::CallProofRequestConfig {
+                        block_hash: self.hash,
+                        method,
+                        parameter_vectored: parameter_vectored.clone(),
+                    },
+                    total_attempts,
+                    timeout_per_request,
+                    max_parallel,
+                )
+                .await
+                .map_err(RuntimeCallError::CallProof);
+
+            // Note that the call proof isn't stored in the cache yet, as it might turn out to be
+            // missing entries. It is stored when the lock is released.
+            call_proof.and_then(|call_proof| {
+                proof_decode::decode_and_verify_proof(proof_decode::Config {
+                    proof: call_proof.decode().to_owned(), // TODO: to_owned() inefficiency, need some help from the networking to obtain the owned data
+                    trie_root_hash: &self.block_state_root_hash,
+                })
+                .map(Arc::new)
+                .map_err(RuntimeCallError::StorageRetrieval)
             })
-            .map_err(RuntimeCallError::StorageRetrieval)
-        });
+        };
 
         let (guarded, virtual_machine) = match self.runtime.runtime.as_ref() {
             Ok(r) => {
@@ -822,6 +957,9 @@ impl<TPlat: Platform> RuntimeLock<TPlat> {
             guarded,
             block_state_root_hash: self.block_state_root_hash,
             call_proof,
+            call_proof_from_cache,
+            missing_proof_entry: Cell::new(false),
+            call_cache,
         };
 
         Ok((lock, virtual_machine))
@@ -833,7 +971,17 @@ impl<TPlat: Platform> RuntimeLock<TPlat> {
 pub struct RuntimeCallLock<'a> {
     guarded: MutexGuard<'a, Option<executor::host::HostVmPrototype>>,
     block_state_root_hash: [u8; 32],
-    call_proof: Result<trie::proof_decode::DecodedTrieProof<Vec<u8>>, RuntimeCallError>,
+    call_proof: Result<Arc<trie::proof_decode::DecodedTrieProof<Vec<u8>>>, RuntimeCallError>,
+    /// `true` if [`RuntimeCallLock::call_proof`] has been found in the cache of the runtime
+    /// service rather than downloaded from the network.
+    call_proof_from_cache: bool,
+    /// Set to `true` if an entry has been found missing from [`RuntimeCallLock::call_proof`].
+    /// Such a call proof must not be stored in the cache, as it would make all the identical
+    /// calls fail.
+    missing_proof_entry: Cell<bool>,
+    /// Cache of the runtime service, and key of the call within this cache. `None` if the
+    /// runtime service doesn't have any cache.
+    call_cache: Option<(Arc<Mutex<CallCache>>, CallCacheKey)>,
 }
 
 impl<'a> RuntimeCallLock<'a> {
@@ -858,7 +1006,10 @@ impl<'a> RuntimeCallLock<'a> {
 
         match call_proof.storage_value(requested_key) {
             Some(v) => Ok(v),
-            None => Err(RuntimeCallError::MissingProofEntry),
+            None => {
+                self.missing_proof_entry.set(true);
+                Err(RuntimeCallError::MissingProofEntry)
+            }
         }
     }
 
@@ -885,9 +1036,10 @@ impl<'a> RuntimeCallLock<'a> {
         };
 
         while let Some(key) = to_find.pop_front() {
-            let node_info = call_proof
-                .trie_node_info(&key)
-                .ok_or(RuntimeCallError::MissingProofEntry)?;
+            let Some(node_info) = call_proof.trie_node_info(&key) else {
+                self.missing_proof_entry.set(true);
+                return Err(RuntimeCallError::MissingProofEntry);
+            };
 
             if matches!(
                 node_info.storage_value,
@@ -905,6 +1057,7 @@ impl<'a> RuntimeCallLock<'a> {
                 match child {
                     proof_decode::Child::NoChild => continue,
                     proof_decode::Child::AbsentFromProof => {
+                        self.missing_proof_entry.set(true);
                         return Err(RuntimeCallError::MissingProofEntry);
                     }
                     proof_decode::Child::InProof { child_key } => {
@@ -919,12 +1072,66 @@ impl<'a> RuntimeCallLock<'a> {
         Ok(output.into_iter())
     }
 
+    /// Stores the output of the call in the cache of the runtime service, so that identical calls
+    /// against the same block can later be answered with [`RuntimeLock::cached_output`].
+    ///
+    /// Must only be called if the call has successfully finished.
+    ///
+    /// > **Note**: For simplicity, the output isn't stored if the cache is being accessed by
+    /// >           another call at the same time.
+    pub fn cache_output(&self, output: &[u8]) {
+        let (Some((call_cache, cache_key)), Ok(call_proof)) = (&self.call_cache, &self.call_proof)
+        else {
+            return;
+        };
+        let Some(mut call_cache) = call_cache.try_lock() else {
+            return;
+        };
+
+        call_cache.put(
+            cache_key.clone(),
+            CallCacheEntry {
+                call_proof: call_proof.clone(),
+                output: Some(output.to_vec()),
+            },
+        );
+    }
+
     /// End the runtime call.
     ///
     /// This method **must** be called.
+    ///
+    /// If no entry has been found missing from the call proof, the call proof is stored in the
+    /// cache of the runtime service. If on the other hand an entry is missing, the call proof is
+    /// removed from the cache.
+    ///
+    /// > **Note**: For simplicity, the cache isn't updated if it is being accessed by another
+    /// >           call at the same time.
     pub fn unlock(mut self, vm: executor::host::HostVmPrototype) {
         debug_assert!(self.guarded.is_none());
         *self.guarded = Some(vm);
+
+        let (Some((call_cache, cache_key)), Ok(call_proof)) = (&self.call_cache, &self.call_proof)
+        else {
+            return;
+        };
+        let Some(mut call_cache) = call_cache.try_lock() else {
+            return;
+        };
+
+        if self.missing_proof_entry.get() {
+            if self.call_proof_from_cache {
+                call_cache.pop(cache_key);
+            }
+        } else if !call_cache.contains(cache_key) {
+            call_cache.put(
+                cache_key.clone(),
+                CallCacheEntry {
+                    call_proof: call_proof.clone(),
+                    output: None,
+                },
+            );
+        }
     }
 }
 
@@ -985,6 +1192,42 @@ pub enum RuntimeError {
     Build(executor::host::NewErr),
 }
 
+/// See [`RuntimeService::call_cache`].
+type CallCache = lru::LruCache<CallCacheKey, CallCacheEntry, fnv::FnvBuildHasher>;
+
+/// Key of an entry of [`CallCache`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CallCacheKey {
+    block_hash: [u8; 32],
+    function_name: String,
+    parameter: Vec<u8>,
+}
+
+impl CallCacheKey {
+    fn new(
+        block_hash: [u8; 32],
+        function_name: &str,
+        parameter_vectored: impl Iterator<Item = impl AsRef<[u8]>>,
+    ) -> Self {
+        CallCacheKey {
+            block_hash,
+            function_name: function_name.to_owned(),
+            parameter: parameter_vectored.fold(Vec::new(), |mut parameter, chunk| {
+                parameter.extend_from_slice(chunk.as_ref());
+                parameter
+            }),
+        }
+    }
+}
+
+/// Value of an entry of [`CallCache`].
+struct CallCacheEntry {
+    /// Call proof that has been downloaded from the network and successfully verified.
+    call_proof: Arc<trie::proof_decode::DecodedTrieProof<Vec<u8>>>,
+    /// Output of the call, if known. Set by [`RuntimeCallLock::cache_output`].
+    output: Option<Vec<u8>>,
+}
+
 struct Guarded<TPlat: Platform> {
     /// Identifier of the next subscription for
     /// [`GuardedInner::FinalizedBlockRuntimeKnown::all_blocks_subscriptions`].
@@ -1007,11 +1250,62 @@ struct Guarded<TPlat: Platform> {
     /// the elements.
     runtimes: slab::Slab<Weak<Runtime>>,
 
+    /// Runtimes that have recently been compiled or used, indexed by
+    /// [`Runtime::runtime_code_hash`] and [`Runtime::heap_pages`]. Holding a strong reference to
+    /// these runtimes guarantees that they stay in [`Guarded::runtimes`] after they stop being
+    /// used, so that calls against historical blocks don't need to compile them again.
+    ///
+    /// The capacity of this cache is [`Config::max_cached_runtimes`].
+    recent_runtimes: lru::LruCache<RuntimeKey, Arc<Runtime>, fnv::FnvBuildHasher>,
+
+    /// See [`Config::exec_hint`].
+    exec_hint: executor::vm::ExecHint,
+
+    /// See [`Config::runtime_call_fuel_limit`].
+    runtime_call_fuel_limit: Option<u64>,
+
     /// Tree of blocks received from the sync service. Keeps track of which block has been
     /// reported to the outer API.
     tree: GuardedInner<TPlat>,
 }
 
+impl<TPlat: Platform> Guarded<TPlat> {
+    /// Tries to find a runtime within [`Guarded::runtimes`] that has the given `:code` hash and
+    /// heap pages. If one is found, it is marked as recently used in
+    /// [`Guarded::recent_runtimes`].
+    ///
+    /// This function is `O(n)`, but given that we expect [`Guarded::runtimes`] to be very small,
+    /// this is not a problem.
+    fn find_runtime(
+        &mut self,
+        runtime_code_hash: &Option<[u8; 32]>,
+        heap_pages: &Option<Vec<u8>>,
+    ) -> Option<Arc<Runtime>> {
+        let runtime = self
+            .runtimes
+            .iter()
+            .filter_map(|(_, rt)| rt.upgrade())
+            .find(|rt| {
+                rt.runtime_code_hash == *runtime_code_hash && rt.heap_pages == *heap_pages
+            })?;
+        self.recent_runtimes.put(
+            (runtime.runtime_code_hash, runtime.heap_pages.clone()),
+            runtime.clone(),
+        );
+        Some(runtime)
+    }
+
+    /// Inserts a newly-created runtime in [`Guarded::runtimes`] and [`Guarded::recent_runtimes`].
+    /// The least recently used runtime of [`Guarded::recent_runtimes`] is evicted if necessary.
+    fn insert_runtime(&mut self, runtime: &Arc<Runtime>) {
+        self.runtimes.insert(Arc::downgrade(runtime));
+        self.recent_runtimes.put(
+            (runtime.runtime_code_hash, runtime.heap_pages.clone()),
+            runtime.clone(),
+        );
+    }
+}
+
 enum GuardedInner<TPlat: Platform> {
     FinalizedBlockRuntimeKnown {
         /// Tree of blocks. Holds the state of the download of everything. Always `Some` when the
@@ -1150,8 +1444,12 @@ async fn run_background<TPlat: Platform>(
                 .unwrap();
 
                 let runtime = Arc::new(Runtime {
-                    runtime_code: finalized_block_runtime.storage_code,
+                    runtime_code_hash: finalized_block_runtime
+                        .storage_code
+                        .as_deref()
+                        .map(runtime_code_hash),
                     heap_pages: finalized_block_runtime.storage_heap_pages,
+                    metadata: Mutex::new(None),
                     runtime: Ok(SuccessfulRuntime {
                         runtime_spec: finalized_block_runtime
                             .virtual_machine
@@ -1191,6 +1489,8 @@ async fn run_background<TPlat: Platform>(
                     when_known.notify(usize::max_value());
                 }
 
+                lock.insert_runtime(&runtime);
+
                 lock.tree = GuardedInner::FinalizedBlockRuntimeKnown {
                     all_blocks_subscriptions: hashbrown::HashMap::with_capacity_and_hasher(
                         32,
@@ -1528,20 +1828,20 @@ impl<TPlat: Platform> Background<TPlat> {
         let mut guarded = self.guarded.lock().await;
 
         // Try to find an existing runtime identical to the one that has just been downloaded.
-        // This loop is `O(n)`, but given that we expect this list to very small (at most 1 or
-        // 2 elements), this is not a problem.
-        let existing_runtime = guarded
-            .runtimes
-            .iter()
-            .filter_map(|(_, rt)| rt.upgrade())
-            .find(|rt| rt.runtime_code == storage_code && rt.heap_pages == storage_heap_pages);
+        let runtime_code_hash = storage_code.as_deref().map(runtime_code_hash);
+        let existing_runtime = guarded.find_runtime(&runtime_code_hash, &storage_heap_pages);
 
         // If no identical runtime was found, try compiling the runtime.
         let runtime = if let Some(existing_runtime) = existing_runtime {
             existing_runtime
         } else {
-            let runtime =
-                SuccessfulRuntime::from_storage::<TPlat>(&storage_code, &storage_heap_pages).await;
+            let runtime = SuccessfulRuntime::from_storage::<TPlat>(
+                &storage_code,
+                &storage_heap_pages,
+                guarded.exec_hint,
+                guarded.runtime_call_fuel_limit,
+            )
+            .await;
             match &runtime {
                 Ok(runtime) => {
                     log::info!(
@@ -1564,11 +1864,12 @@ impl<TPlat: Platform> Background<TPlat> {
 
             let runtime = Arc::new(Runtime {
                 heap_pages: storage_heap_pages,
-                runtime_code: storage_code,
+                runtime_code_hash,
                 runtime,
+                metadata: Mutex::new(None),
             });
 
-            guarded.runtimes.insert(Arc::downgrade(&runtime));
+            guarded.insert_runtime(&runtime);
             runtime
         };
 
@@ -1712,6 +2013,12 @@ impl<TPlat: Platform> Background<TPlat> {
                             } else {
                                 None
                             },
+                            new_runtime_code_hash: if !Arc::ptr_eq(&parent_runtime, &block_runtime)
+                            {
+                                block_runtime.runtime_code_hash
+                            } else {
+                                None
+                            },
                         });
 
                         let mut to_remove = Vec::new();
@@ -1983,13 +2290,12 @@ struct Runtime {
     /// happened, including a problem when obtaining the runtime specs.
     runtime: Result<SuccessfulRuntime, RuntimeError>,
 
-    /// Undecoded storage value of `:code` corresponding to the [`Runtime::runtime`]
-    /// field.
+    /// Hash of the undecoded storage value of `:code` corresponding to the
+    /// [`Runtime::runtime`] field. See [`runtime_code_hash`].
     ///
     /// Can be `None` if the storage is empty, in which case the runtime will have failed to
     /// build.
-    // TODO: consider storing hash instead
-    runtime_code: Option<Vec<u8>>,
+    runtime_code_hash: Option<[u8; 32]>,
 
     /// Undecoded storage value of `:heappages` corresponding to the
     /// [`Runtime::runtime`] field.
@@ -1998,6 +2304,19 @@ struct Runtime {
     /// build.
     // TODO: consider storing hash instead
     heap_pages: Option<Vec<u8>>,
+
+    /// Metadata of the runtime, without its length prefix, if it has already been obtained.
+    /// See [`RuntimeLock::cache_metadata`].
+    metadata: Mutex<Option<Arc<[u8]>>>,
+}
+
+/// Key of [`Guarded::recent_runtimes`]. Contains a [`Runtime::runtime_code_hash`] and a
+/// [`Runtime::heap_pages`].
+type RuntimeKey = (Option<[u8; 32]>, Option<Vec<u8>>);
+
+/// Returns the hash of the given `:code`, used to identify runtimes.
+fn runtime_code_hash(runtime_code: &[u8]) -> [u8; 32] {
+    <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], runtime_code).as_bytes()).unwrap()
 }
 
 struct SuccessfulRuntime {
@@ -2014,6 +2333,8 @@ impl SuccessfulRuntime {
     async fn from_storage<TPlat: Platform>(
         code: &Option<Vec<u8>>,
         heap_pages: &Option<Vec<u8>>,
+        exec_hint: executor::vm::ExecHint,
+        fuel_limit: Option<u64>,
     ) -> Result<Self, RuntimeError> {
         // Since compiling the runtime is a CPU-intensive operation, we yield once before.
         TPlat::yield_after_cpu_intensive().await;
@@ -2022,7 +2343,6 @@ impl SuccessfulRuntime {
         let module = code.as_ref().ok_or(RuntimeError::CodeNotFound)?;
         let heap_pages = executor::storage_heap_pages_to_value(heap_pages.as_deref())
             .map_err(RuntimeError::InvalidHeapPages)?;
-        let exec_hint = executor::vm::ExecHint::CompileAheadOfTime;
 
         // We try once with `allow_unresolved_imports: false`. If this fails due to unresolved
         // import, we try again but with `allowed_unresolved_imports: true`.
@@ -2034,12 +2354,13 @@ impl SuccessfulRuntime {
             heap_pages,
             exec_hint,
             allow_unresolved_imports: false,
+            fuel_limit,
         }) {
             Ok(vm) => {
                 return Ok(SuccessfulRuntime {
                     runtime_spec: vm.runtime_version().clone(),
                     virtual_machine: Mutex::new(Some(vm)),
-                })
+                });
             }
             Err(executor::host::NewErr::VirtualMachine(
                 executor::vm::NewErr::UnresolvedFunctionImport {
@@ -2052,6 +2373,7 @@ impl SuccessfulRuntime {
                     heap_pages,
                     exec_hint,
                     allow_unresolved_imports: true,
+                    fuel_limit,
                 }) {
                     Ok(vm) => {
                         log::warn!(
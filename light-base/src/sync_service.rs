@@ -28,8 +28,12 @@
 
 use crate::{network_service, platform::Platform, runtime_service};
 
-use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec::Vec};
-use core::{fmt, num::NonZeroU32, time::Duration};
+use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    cmp, fmt,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    time::Duration,
+};
 use futures::{
     channel::{mpsc, oneshot},
     lock::Mutex,
@@ -38,11 +42,14 @@ use futures::{
 use smoldot::{
     chain,
     executor::host,
+    header,
     libp2p::PeerId,
     network::{protocol, service},
+    sync::all,
     trie::{self, prefix_proof, proof_decode},
 };
 
+mod hedging;
 mod parachain;
 mod standalone;
 
@@ -60,6 +67,37 @@ pub struct Config<TPlat: Platform> {
     /// Number of bytes of the block number in the networking protocol.
     pub block_number_bytes: usize,
 
+    /// If `Some`, the GrandPa warp syncing resumes from the given progress, as previously
+    /// returned by [`SyncService::warp_sync_progress`]. Ignored for parachains.
+    pub warp_sync_resume_progress: Option<all::WarpSyncVerifiedProgress>,
+
+    /// Strategy to use in order to synchronize the chain once the GrandPa warp syncing is over.
+    /// Ignored for parachains.
+    pub sync_mode: all::SyncMode,
+
+    /// If `Some`, and if the chain doesn't have any finality mechanism, the blocks that are at
+    /// least this number of blocks below the best block are considered as finalized. Ignored
+    /// for parachains.
+    pub probabilistic_finality_depth: Option<NonZeroU64>,
+
+    /// Rule used to determine which block among the non-finalized blocks is the best block.
+    ///
+    /// For parachains, whose best block is derived from the best block of their relay chain,
+    /// only [`all::BestBlockPolicy::FollowFinalityOnly`] has an effect.
+    pub best_block_policy: all::BestBlockPolicy,
+
+    /// Number of background tasks to spawn in order to verify the signatures of block headers
+    /// ahead of time and in parallel. If 0, the signatures are verified one by one by the main
+    /// syncing task. Ignored for parachains.
+    pub header_verification_workers: usize,
+
+    /// If `Some`, the storage proof, call proof, and blocks requests that take longer than the
+    /// given percentile, between 1 and 100, of the duration of the recent requests of the same
+    /// kind are also sent to a second peer, and the first valid answer is used. Failed requests
+    /// count as having taken as long as their timeout.
+    /// If `None`, a request is only sent to another peer after the previous one has failed.
+    pub requests_hedging_percentile: Option<u8>,
+
     /// Closure that spawns background tasks.
     pub tasks_executor: Box<dyn FnMut(String, future::BoxFuture<'static, ()>) + Send>,
 
@@ -102,12 +140,145 @@ pub struct SyncService<TPlat: Platform> {
     /// Sender of messages towards the background task.
     to_background: Mutex<mpsc::Sender<ToBackground>>,
 
+    /// See [`SyncService::starting_block_number`].
+    starting_block_number: u64,
+
     /// See [`Config::network_service`].
     network_service: Arc<network_service::NetworkService<TPlat>>,
     /// See [`Config::network_service`].
     network_chain_index: usize,
     /// See [`Config::block_number_bytes`].
     block_number_bytes: usize,
+
+    /// Storage proof requests that are about to be sent, and that concurrent storage queries
+    /// targeting the same trie of the same block can join instead of sending their own request.
+    pending_storage_queries: Mutex<PendingStorageQueries>,
+
+    /// See [`Config::requests_hedging_percentile`].
+    requests_hedging_percentile: Option<u8>,
+    /// Durations of the recent successful blocks requests.
+    blocks_requests_latencies: Mutex<hedging::Latencies>,
+    /// Durations of the recent successful storage proof requests.
+    storage_proof_requests_latencies: Mutex<hedging::Latencies>,
+    /// Durations of the recent successful call proof requests.
+    call_proof_requests_latencies: Mutex<hedging::Latencies>,
+}
+
+/// Maximum number of keys that a single storage proof request resulting from coalescing
+/// multiple storage queries can contain.
+const MAX_COALESCED_STORAGE_QUERY_KEYS: usize = 128;
+
+/// Duration during which the first storage query of a batch waits for other storage queries to
+/// join the batch before the storage proof request is sent.
+const STORAGE_QUERY_COALESCING_DELAY: Duration = Duration::from_millis(5);
+
+/// See [`SyncService::pending_storage_queries`].
+struct PendingStorageQueries {
+    /// Batches that are about to be sent, indexed by the parameters of the request.
+    batches: hashbrown::HashMap<StorageQueryBatchKey, StorageQueryBatch, fnv::FnvBuildHasher>,
+    /// Identifier to assign to the next batch inserted in [`PendingStorageQueries::batches`].
+    next_batch_id: u64,
+}
+
+/// Block hash, child trie, trie root hash, number of attempts, and timeout per request of a
+/// storage proof request. Only storage queries with identical parameters are merged together.
+type StorageQueryBatchKey = ([u8; 32], Option<Vec<u8>>, [u8; 32], u32, Duration);
+
+/// Outcome of a storage proof request shared between all the storage queries of a batch.
+type StorageQueryBatchOutcome =
+    Result<Arc<proof_decode::DecodedTrieProof<Vec<u8>>>, StorageQueryError>;
+
+struct StorageQueryBatch {
+    /// Identifier of the batch, used to differentiate it from batches with the same key that
+    /// are inserted later.
+    id: u64,
+    /// List of keys to request. Can contain duplicates.
+    keys: Vec<Vec<u8>>,
+    /// Number of storage queries that have added their keys to the batch.
+    num_queries: usize,
+    /// Future yielding the outcome of the request. Yields an error if the storage query in
+    /// charge of sending the request has been dropped.
+    outcome: future::Shared<oneshot::Receiver<StorageQueryBatchOutcome>>,
+}
+
+/// Role of a storage query within its batch. See [`PendingStorageQueries::join`].
+enum StorageQueryRole {
+    /// The storage query has created a new batch and is in charge of sending the request.
+    Leader(u64, oneshot::Sender<StorageQueryBatchOutcome>),
+    /// The storage query has added its keys to an existing batch.
+    Follower(
+        u64,
+        future::Shared<oneshot::Receiver<StorageQueryBatchOutcome>>,
+    ),
+    /// The storage query can't be merged and must send a request of its own.
+    Alone,
+}
+
+impl PendingStorageQueries {
+    /// Adds the given keys to the batch with the given key, or creates a new batch if there is
+    /// no such batch.
+    fn join(
+        &mut self,
+        batch_key: &StorageQueryBatchKey,
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]>> + Clone,
+    ) -> StorageQueryRole {
+        let num_requested_keys = requested_keys.clone().count();
+
+        match self.batches.get_mut(batch_key) {
+            Some(batch)
+                if batch.keys.len() + num_requested_keys <= MAX_COALESCED_STORAGE_QUERY_KEYS =>
+            {
+                batch
+                    .keys
+                    .extend(requested_keys.map(|k| k.as_ref().to_vec()));
+                batch.num_queries += 1;
+                StorageQueryRole::Follower(batch.id, batch.outcome.clone())
+            }
+            Some(_) => StorageQueryRole::Alone,
+            None if num_requested_keys >= MAX_COALESCED_STORAGE_QUERY_KEYS => {
+                StorageQueryRole::Alone
+            }
+            None => {
+                let id = self.next_batch_id;
+                self.next_batch_id += 1;
+                let (tx, rx) = oneshot::channel();
+                self.batches.insert(
+                    batch_key.clone(),
+                    StorageQueryBatch {
+                        id,
+                        keys: requested_keys.map(|k| k.as_ref().to_vec()).collect(),
+                        num_queries: 1,
+                        outcome: rx.shared(),
+                    },
+                );
+                StorageQueryRole::Leader(id, tx)
+            }
+        }
+    }
+
+    /// Removes the batch that the leader has created, and returns its keys, sorted and
+    /// deduplicated, and the number of storage queries that have joined it.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the batch doesn't exist, which can only happen if the sender of the leader has
+    /// been dropped.
+    ///
+    fn take(&mut self, batch_key: &StorageQueryBatchKey, id: u64) -> (Vec<Vec<u8>>, usize) {
+        let batch = self.batches.remove(batch_key).unwrap();
+        debug_assert_eq!(batch.id, id);
+        let mut keys = batch.keys;
+        keys.sort_unstable();
+        keys.dedup();
+        (keys, batch.num_queries)
+    }
+
+    /// Removes the given batch, if it is still there, after its leader has been dropped.
+    fn remove_abandoned(&mut self, batch_key: &StorageQueryBatchKey, id: u64) {
+        if self.batches.get(batch_key).map_or(false, |b| b.id == id) {
+            self.batches.remove(batch_key);
+        }
+    }
 }
 
 impl<TPlat: Platform> SyncService<TPlat> {
@@ -115,6 +286,11 @@ impl<TPlat: Platform> SyncService<TPlat> {
         let (to_background, from_foreground) = mpsc::channel(16);
 
         let log_target = format!("sync-service-{}", config.log_name);
+        let starting_block_number = config
+            .chain_information
+            .as_ref()
+            .finalized_block_header
+            .number;
 
         if let Some(config_parachain) = config.parachain {
             (config.tasks_executor)(
@@ -126,18 +302,42 @@ impl<TPlat: Platform> SyncService<TPlat> {
                     config_parachain.relay_chain_sync.clone(),
                     config_parachain.relay_chain_block_number_bytes,
                     config_parachain.parachain_id,
+                    config.best_block_policy,
                     from_foreground,
                     config.network_service.1,
                     config.network_events_receiver,
                 )),
             );
         } else {
+            // Spawn the tasks that verify the signatures of headers ahead of time on behalf of
+            // the main syncing task.
+            let (verified_signatures_tx, verified_signatures_rx) = mpsc::channel(64);
+            let verification_workers = (0..config.header_verification_workers)
+                .map(|worker_index| {
+                    let (tx, rx) = mpsc::channel(64);
+                    (config.tasks_executor)(
+                        format!("{}-verify-{}", log_target, worker_index),
+                        Box::pin(standalone::start_verification_worker::<TPlat>(
+                            rx,
+                            verified_signatures_tx.clone(),
+                        )),
+                    );
+                    tx
+                })
+                .collect::<Vec<_>>();
+
             (config.tasks_executor)(
                 log_target.clone(),
                 Box::pin(standalone::start_standalone_chain(
                     log_target,
                     config.chain_information,
                     config.block_number_bytes,
+                    config.warp_sync_resume_progress,
+                    config.sync_mode,
+                    config.probabilistic_finality_depth,
+                    config.best_block_policy,
+                    verification_workers,
+                    verified_signatures_rx,
                     from_foreground,
                     config.network_service.0.clone(),
                     config.network_service.1,
@@ -148,9 +348,18 @@ impl<TPlat: Platform> SyncService<TPlat> {
 
         SyncService {
             to_background: Mutex::new(to_background),
+            starting_block_number,
             network_service: config.network_service.0,
             network_chain_index: config.network_service.1,
             block_number_bytes: config.block_number_bytes,
+            pending_storage_queries: Mutex::new(PendingStorageQueries {
+                batches: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+                next_batch_id: 0,
+            }),
+            requests_hedging_percentile: config.requests_hedging_percentile,
+            blocks_requests_latencies: Mutex::new(hedging::Latencies::new()),
+            storage_proof_requests_latencies: Mutex::new(hedging::Latencies::new()),
+            call_proof_requests_latencies: Mutex::new(hedging::Latencies::new()),
         }
     }
 
@@ -179,6 +388,74 @@ impl<TPlat: Platform> SyncService<TPlat> {
         rx.await.unwrap()
     }
 
+    /// If the GrandPa warp syncing is in progress, returns the highest block whose finality has
+    /// been verified so far. Can be passed back through [`Config::warp_sync_resume_progress`] in
+    /// order to not start the warp syncing again from scratch.
+    ///
+    /// Returns `None` if the warp syncing isn't in progress or hasn't made any progress yet.
+    pub async fn warp_sync_progress(&self) -> Option<all::WarpSyncVerifiedProgress> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .lock()
+            .await
+            .send(ToBackground::WarpSyncProgress { send_back })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Subscribes to the progress of the initial synchronization of the chain.
+    ///
+    /// The receiver immediately yields the current progress, then yields a new item whenever
+    /// the progress changes. The channel is closed after [`SyncProgress::Finished`] has been
+    /// sent. It is also closed if its buffer is full, in which case the API user should
+    /// subscribe again.
+    ///
+    /// See [`SyncProgress`].
+    pub async fn subscribe_sync_progress(
+        &self,
+        buffer_size: usize,
+    ) -> mpsc::Receiver<SyncProgress> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .lock()
+            .await
+            .send(ToBackground::SubscribeSyncProgress {
+                send_back,
+                buffer_size,
+            })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Returns the number of the finalized block of the chain when the sync service was
+    /// created, in other words the block the synchronization started from.
+    pub fn starting_block_number(&self) -> u64 {
+        self.starting_block_number
+    }
+
+    /// Returns the numbers of the current finalized and best blocks of the chain.
+    ///
+    /// Contrary to [`SyncService::subscribe_all`], this information is available while the
+    /// chain is being warp synced.
+    pub async fn block_numbers(&self) -> (u64, u64) {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .lock()
+            .await
+            .send(ToBackground::BlockNumbers { send_back })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
     /// Subscribes to the state of the chain: the current state and the new blocks.
     ///
     /// All new blocks are reported. Only up to `buffer_size` block notifications are buffered
@@ -285,7 +562,18 @@ impl<TPlat: Platform> SyncService<TPlat> {
         rx.await.unwrap().into_iter()
     }
 
-    // TODO: doc; explain the guarantees
+    /// Sends blocks requests to the peers that are assumed to know the given block, until one of
+    /// them succeeds.
+    ///
+    /// If `Ok`, the hash of the returned block is guaranteed to be equal to `hash`. If the
+    /// header was requested, it is guaranteed to be present and to match `hash`. If the body was
+    /// requested, it is guaranteed to be present. If both the header and the body were
+    /// requested, the body is also guaranteed to match the extrinsics root found in the header.
+    /// A body requested without its header, however, can't be and isn't verified. Neither are
+    /// justifications.
+    ///
+    /// > **Note**: These guarantees are upheld by the networking service, which verifies the
+    /// >           response before returning it.
     pub async fn block_query(
         self: Arc<Self>,
         block_number: u64,
@@ -305,33 +593,34 @@ impl<TPlat: Platform> SyncService<TPlat> {
 
         // TODO: handle max_parallel
         // TODO: better peers selection ; don't just take the first 3
-        for target in self
+        let targets = self
             .peers_assumed_know_blocks(block_number, &hash)
             .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
-        {
-            let mut result = match self
-                .network_service
-                .clone()
-                .blocks_request(
-                    target,
-                    self.network_chain_index,
-                    request_config.clone(),
-                    timeout_per_request,
-                )
-                .await
-            {
-                Ok(b) => b,
-                Err(_) => continue,
-            };
-
-            return Ok(result.remove(0));
-        }
+            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+
+        let mut result = self
+            .hedged_requests(
+                &self.blocks_requests_latencies,
+                timeout_per_request,
+                targets,
+                |target| {
+                    self.network_service.clone().blocks_request(
+                        target,
+                        self.network_chain_index,
+                        request_config.clone(),
+                        timeout_per_request,
+                    )
+                },
+            )
+            .await
+            .map_err(|_| ())?;
 
-        Err(())
+        Ok(result.remove(0))
     }
 
-    // TODO: doc; explain the guarantees
+    /// Similar to [`SyncService::block_query`], except that the number of the block isn't known,
+    /// and the requests are thus sent to peers regardless of whether they are assumed to know
+    /// the block. Provides the same guarantees as [`SyncService::block_query`].
     pub async fn block_query_unknown_number(
         self: Arc<Self>,
         hash: [u8; 32],
@@ -350,31 +639,122 @@ impl<TPlat: Platform> SyncService<TPlat> {
 
         // TODO: handle max_parallel
         // TODO: better peers selection ; don't just take the first
-        for target in self
+        let targets = self
             .network_service
             .peers_list()
             .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
-        {
-            let mut result = match self
-                .network_service
-                .clone()
-                .blocks_request(
+            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+
+        let mut result = self
+            .hedged_requests(
+                &self.blocks_requests_latencies,
+                timeout_per_request,
+                targets,
+                |target| {
+                    self.network_service.clone().blocks_request(
+                        target,
+                        self.network_chain_index,
+                        request_config.clone(),
+                        timeout_per_request,
+                    )
+                },
+            )
+            .await
+            .map_err(|_| ())?;
+
+        Ok(result.remove(0))
+    }
+
+    /// Sends blocks requests to the peers that are assumed to know the given block, in order to
+    /// download the headers of this block and of its ancestors.
+    ///
+    /// If `Ok`, returns a list of `(hash, scale_encoded_header)` ordered by decreasing block
+    /// number. The first entry is always the requested block, and each entry is the parent of the
+    /// previous one. The list contains at most `num_blocks` entries, but might contain fewer if
+    /// the peer answered with fewer blocks.
+    ///
+    /// Assuming that `hash` has been verified, all the returned headers are guaranteed to be the
+    /// ones of the ancestors of `hash`.
+    pub async fn block_ancestry_query(
+        self: Arc<Self>,
+        block_number: u64,
+        hash: [u8; 32],
+        num_blocks: NonZeroU32,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> Result<Vec<([u8; 32], Vec<u8>)>, ()> {
+        let request_config = protocol::BlocksRequestConfig {
+            start: protocol::BlocksRequestConfigStart::Hash(hash),
+            desired_count: num_blocks,
+            direction: protocol::BlocksRequestDirection::Descending,
+            fields: protocol::BlocksRequestFields {
+                header: true,
+                body: false,
+                justifications: false,
+            },
+        };
+
+        // TODO: better peers selection ; don't just take the first ones
+        let targets = self
+            .peers_assumed_know_blocks(block_number, &hash)
+            .await
+            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+
+        let block_number_bytes = self.block_number_bytes;
+        self.hedged_requests(
+            &self.blocks_requests_latencies,
+            timeout_per_request,
+            targets,
+            |target| {
+                let request = self.network_service.clone().blocks_request(
                     target,
                     self.network_chain_index,
                     request_config.clone(),
                     timeout_per_request,
-                )
-                .await
-            {
-                Ok(b) => b,
-                Err(_) => continue,
-            };
+                );
+
+                async move {
+                    let result = request.await.map_err(|_| ())?;
+
+                    // The networking service guarantees that the first block is the requested one
+                    // and that each header matches its hash. What remains to verify is that each
+                    // block is the parent of the previous one.
+                    let num_received = cmp::min(
+                        result.len(),
+                        usize::try_from(num_blocks.get()).unwrap_or(usize::max_value()),
+                    );
+                    let mut out = Vec::with_capacity(num_received);
+                    let mut expected = (block_number, hash);
+                    for block in result.into_iter().take(num_received) {
+                        let scale_encoded_header = block.header.unwrap();
+                        let Ok(decoded) = header::decode(&scale_encoded_header, block_number_bytes)
+                        else {
+                            break;
+                        };
+                        if block.hash != expected.1 || decoded.number != expected.0 {
+                            break;
+                        }
 
-            return Ok(result.remove(0));
-        }
+                        let next_expected = (decoded.number.checked_sub(1), *decoded.parent_hash);
+                        out.push((block.hash, scale_encoded_header));
+                        match next_expected {
+                            (Some(number), parent_hash) => expected = (number, parent_hash),
+                            (None, _) => break,
+                        }
+                    }
 
-        Err(())
+                    // A peer that sends back blocks that aren't each other's parents is
+                    // misbehaving. The response is entirely ignored in that case.
+                    if out.len() != num_received {
+                        return Err(());
+                    }
+
+                    Ok(out)
+                }
+            },
+        )
+        .await
+        .map_err(|_| ())
     }
 
     /// Performs one or more storage proof requests in order to find the value of the given
@@ -401,64 +781,299 @@ impl<TPlat: Platform> SyncService<TPlat> {
         requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
         total_attempts: u32,
         timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        self.storage_query_inner(
+            block_number,
+            block_hash,
+            None,
+            storage_trie_root,
+            requested_keys,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    /// Similar to [`SyncService::storage_query`], but queries the keys in the given child trie.
+    ///
+    /// `child_trie` is the key of the child trie, without the `:child_storage:default:` prefix,
+    /// and `child_trie_root` is the Merkle value of the root node of this child trie, which can
+    /// be found in the main trie.
+    pub async fn child_storage_query(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: &[u8],
+        child_trie_root: &[u8; 32],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        self.storage_query_inner(
+            block_number,
+            block_hash,
+            Some(child_trie),
+            child_trie_root,
+            requested_keys,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    /// Similar to [`SyncService::storage_query`], but returns the list of entries of the
+    /// storage proof rather than the storage values.
+    ///
+    /// The proof is verified against `storage_trie_root` and is guaranteed to contain an entry
+    /// for each of the requested keys.
+    pub async fn storage_read_proof(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        storage_trie_root: &[u8; 32],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        let decoded = self
+            .storage_proof_query(
+                block_number,
+                block_hash,
+                None,
+                storage_trie_root,
+                requested_keys,
+                total_attempts,
+                timeout_per_request,
+            )
+            .await?;
+
+        Ok(decoded
+            .proof_entries()
+            .map(|entry| entry.to_vec())
+            .collect())
+    }
+
+    /// Similar to [`SyncService::storage_query`], but returns, for each of the requested keys,
+    /// the Merkle value of the closest descendant of this key in the trie, including the key
+    /// itself. `None` is returned for keys that have no descendant in the trie.
+    ///
+    /// If `child_trie` is `Some`, the keys are queried in the given child trie, in which case
+    /// `storage_trie_root` must be the Merkle value of the root node of this child trie. See
+    /// [`SyncService::child_storage_query`].
+    pub async fn closest_descendant_merkle_value_query(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        storage_trie_root: &[u8; 32],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        // The proof of a key contains all the nodes that are traversed when looking up this
+        // key, which includes its closest descendant.
+        let decoded = self
+            .storage_proof_query(
+                block_number,
+                block_hash,
+                child_trie,
+                storage_trie_root,
+                requested_keys.clone(),
+                total_attempts,
+                timeout_per_request,
+            )
+            .await?;
+
+        requested_keys
+            .map(|key| {
+                let key = trie::bytes_to_nibbles(key.as_ref().iter().copied()).collect::<Vec<_>>();
+                decoded
+                    .closest_descendant_merkle_value(&key)
+                    .map(|merkle_value| merkle_value.map(|v| v.as_ref().to_vec()))
+                    .map_err(|_| StorageQueryError {
+                        errors: vec![StorageQueryErrorDetail::MissingProofEntry],
+                    })
+            })
+            .collect()
+    }
+
+    async fn storage_query_inner(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        storage_trie_root: &[u8; 32],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
         _max_parallel: NonZeroU32,
     ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
-        let mut outcome_errors =
-            Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+        // Storage queries targeting the same trie of the same block that are started
+        // concurrently are merged into a single storage proof request. The first query to
+        // arrive inserts a batch in `pending_storage_queries`, waits a bit in order to give the
+        // opportunity to other queries to add their keys to the batch, then sends the request
+        // and shares the decoded proof with everyone else.
+        let batch_key = (
+            *block_hash,
+            child_trie.map(|c| c.to_vec()),
+            *storage_trie_root,
+            total_attempts,
+            timeout_per_request,
+        );
+        let num_requested_keys = requested_keys.clone().count();
+
+        let role = self
+            .pending_storage_queries
+            .lock()
+            .await
+            .join(&batch_key, requested_keys.clone());
+
+        let shared_outcome = match role {
+            StorageQueryRole::Leader(id, send_back) => {
+                TPlat::sleep(STORAGE_QUERY_COALESCING_DELAY).await;
+
+                let (keys, num_queries) = self
+                    .pending_storage_queries
+                    .lock()
+                    .await
+                    .take(&batch_key, id);
+
+                let outcome = self
+                    .storage_proof_query(
+                        block_number,
+                        block_hash,
+                        child_trie,
+                        storage_trie_root,
+                        keys.iter(),
+                        total_attempts,
+                        timeout_per_request,
+                    )
+                    .await;
+                let _ = send_back.send(outcome.clone());
+
+                // If no other query has joined the batch, there is no point in trying again.
+                if num_queries == 1 {
+                    Some(outcome?)
+                } else {
+                    outcome.ok()
+                }
+            }
+            StorageQueryRole::Follower(id, outcome) => match outcome.await {
+                Ok(outcome) => outcome.ok(),
+                Err(oneshot::Canceled) => {
+                    // The query in charge of sending the request has been dropped.
+                    self.pending_storage_queries
+                        .lock()
+                        .await
+                        .remove_abandoned(&batch_key, id);
+                    None
+                }
+            },
+            StorageQueryRole::Alone => None,
+        };
 
+        // If the request of the batch has failed, which might be caused by the keys of the other
+        // queries of the batch, the keys of this query are requested separately.
+        let decoded = match shared_outcome {
+            Some(decoded) => decoded,
+            None => {
+                self.storage_proof_query(
+                    block_number,
+                    block_hash,
+                    child_trie,
+                    storage_trie_root,
+                    requested_keys.clone(),
+                    total_attempts,
+                    timeout_per_request,
+                )
+                .await?
+            }
+        };
+
+        let mut result = Vec::with_capacity(num_requested_keys);
+        for key in requested_keys {
+            result.push(
+                decoded
+                    .storage_value(key.as_ref())
+                    .ok_or_else(|| StorageQueryError {
+                        errors: vec![StorageQueryErrorDetail::MissingProofEntry],
+                    })?
+                    .map(|(v, _)| v.to_owned()),
+            );
+        }
+        debug_assert_eq!(result.len(), result.capacity());
+        Ok(result)
+    }
+
+    /// Sends a storage proof request concerning the given keys, and returns the decoded proof.
+    ///
+    /// The proof is guaranteed to contain an entry for each of the requested keys.
+    async fn storage_proof_query(
+        &self,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        storage_trie_root: &[u8; 32],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> Result<Arc<proof_decode::DecodedTrieProof<Vec<u8>>>, StorageQueryError> {
         // TODO: better peers selection ; don't just take the first
         // TODO: handle max_parallel
-        for target in self
+        let targets = self
             .peers_assumed_know_blocks(block_number, block_hash)
             .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
-        {
-            let result = self
-                .network_service
-                .clone()
-                .storage_proof_request(
+            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+
+        self.hedged_requests(
+            &self.storage_proof_requests_latencies,
+            timeout_per_request,
+            targets,
+            |target| {
+                let request = self.network_service.clone().storage_proof_request(
                     self.network_chain_index,
                     target,
                     protocol::StorageProofRequestConfig {
                         block_hash: *block_hash,
                         keys: requested_keys.clone(),
+                        child_trie: child_trie.map(|c| c.to_vec()),
                     },
                     timeout_per_request,
-                )
-                .await
-                .map_err(StorageQueryErrorDetail::Network)
-                .and_then(|outcome| {
-                    let decoded = outcome.decode();
-                    let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
-                        proof: decoded,
+                );
+                let requested_keys = requested_keys.clone();
+
+                async move {
+                    let outcome = request.await.map_err(StorageQueryErrorDetail::Network)?;
+
+                    let config = proof_decode::Config {
+                        proof: outcome.decode().to_vec(),
                         trie_root_hash: storage_trie_root,
-                    })
+                    };
+                    // Proofs concerning a child trie also contain the entries of the main trie.
+                    let decoded = if child_trie.is_some() {
+                        proof_decode::decode_and_verify_proof_allow_unused(config)
+                    } else {
+                        proof_decode::decode_and_verify_proof(config)
+                    }
                     .map_err(StorageQueryErrorDetail::ProofVerification)?;
 
-                    let mut result = Vec::with_capacity(requested_keys.clone().count());
-                    for key in requested_keys.clone() {
-                        result.push(
-                            decoded
-                                .storage_value(key.as_ref())
-                                .ok_or(StorageQueryErrorDetail::MissingProofEntry)?
-                                .map(|(v, _)| v.to_owned()),
-                        );
+                    if requested_keys
+                        .clone()
+                        .any(|key| decoded.storage_value(key.as_ref()).is_none())
+                    {
+                        return Err(StorageQueryErrorDetail::MissingProofEntry);
                     }
-                    debug_assert_eq!(result.len(), result.capacity());
-                    Ok(result)
-                });
-
-            match result {
-                Ok(values) => return Ok(values),
-                Err(err) => {
-                    outcome_errors.push(err);
-                }
-            }
-        }
 
-        Err(StorageQueryError {
-            errors: outcome_errors,
-        })
+                    Ok(Arc::new(decoded))
+                }
+            },
+        )
+        .await
+        .map_err(|errors| StorageQueryError { errors })
     }
 
     pub async fn storage_prefix_keys_query(
@@ -469,11 +1084,139 @@ impl<TPlat: Platform> SyncService<TPlat> {
         storage_trie_root: &[u8; 32],
         total_attempts: u32,
         timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        self.storage_prefix_keys_query_inner(
+            block_number,
+            block_hash,
+            None,
+            prefix,
+            None,
+            None,
+            storage_trie_root,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    /// Similar to [`SyncService::storage_prefix_keys_query`], but only returns at most
+    /// `max_keys` keys that are strictly superior to `start_key`.
+    ///
+    /// The returned keys are in lexicographic order. The last key of the list can be passed as
+    /// `start_key` in order to continue the enumeration. The enumeration is over if fewer than
+    /// `max_keys` keys are returned.
+    pub async fn storage_prefix_keys_paged_query(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        prefix: &[u8],
+        start_key: Option<&[u8]>,
+        max_keys: NonZeroUsize,
+        storage_trie_root: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        self.storage_prefix_keys_query_inner(
+            block_number,
+            block_hash,
+            None,
+            prefix,
+            start_key,
+            Some(max_keys),
+            storage_trie_root,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    /// Similar to [`SyncService::storage_prefix_keys_query`], but scans the given child trie.
+    ///
+    /// See [`SyncService::child_storage_query`] for the meaning of `child_trie` and
+    /// `child_trie_root`.
+    pub async fn child_storage_prefix_keys_query(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: &[u8],
+        prefix: &[u8],
+        child_trie_root: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        self.storage_prefix_keys_query_inner(
+            block_number,
+            block_hash,
+            Some(child_trie),
+            prefix,
+            None,
+            None,
+            child_trie_root,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    /// Similar to [`SyncService::storage_prefix_keys_paged_query`], but scans the given child
+    /// trie.
+    ///
+    /// See [`SyncService::child_storage_query`] for the meaning of `child_trie` and
+    /// `child_trie_root`.
+    pub async fn child_storage_prefix_keys_paged_query(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: &[u8],
+        prefix: &[u8],
+        start_key: Option<&[u8]>,
+        max_keys: NonZeroUsize,
+        child_trie_root: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        self.storage_prefix_keys_query_inner(
+            block_number,
+            block_hash,
+            Some(child_trie),
+            prefix,
+            start_key,
+            Some(max_keys),
+            child_trie_root,
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+        )
+        .await
+    }
+
+    async fn storage_prefix_keys_query_inner(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        prefix: &[u8],
+        start_key: Option<&[u8]>,
+        max_keys: Option<NonZeroUsize>,
+        storage_trie_root: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
         _max_parallel: NonZeroU32,
     ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
         let mut prefix_scan = prefix_proof::prefix_scan(prefix_proof::Config {
             prefix,
             trie_root_hash: *storage_trie_root,
+            // Proofs concerning a child trie also contain the entries of the main trie.
+            allow_unused_proof_entries: child_trie.is_some(),
+            start_key,
+            max_keys,
         });
 
         'main_scan: loop {
@@ -499,6 +1242,7 @@ impl<TPlat: Platform> SyncService<TPlat> {
                             keys: prefix_scan.requested_keys().map(|nibbles| {
                                 trie::nibbles_to_bytes_suffix_extend(nibbles).collect::<Vec<_>>()
                             }),
+                            child_trie: child_trie.map(|c| c.to_vec()),
                         },
                         timeout_per_request,
                     )
@@ -554,46 +1298,75 @@ impl<TPlat: Platform> SyncService<TPlat> {
         timeout_per_request: Duration,
         _max_parallel: NonZeroU32,
     ) -> Result<network_service::EncodedMerkleProof, CallProofQueryError> {
-        let mut outcome_errors =
-            Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
-
         // TODO: better peers selection ; don't just take the first
         // TODO: handle max_parallel
-        for target in self
+        let targets = self
             .peers_assumed_know_blocks(block_number, &config.block_hash)
             .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
-        {
-            let result = self
-                .network_service
-                .clone()
-                .call_proof_request(
+            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+
+        self.hedged_requests(
+            &self.call_proof_requests_latencies,
+            timeout_per_request,
+            targets,
+            |target| {
+                let request = self.network_service.clone().call_proof_request(
                     self.network_chain_index,
                     target,
                     config.clone(),
                     timeout_per_request,
-                )
-                .await;
-
-            match result {
-                Ok(value) if !value.decode().is_empty() => return Ok(value),
-                // TODO: this check of emptiness is a bit of a hack; it is necessary because Substrate responds to requests about blocks it doesn't know with an empty proof
-                Ok(_) => outcome_errors.push(network_service::CallProofRequestError::Request(
-                    service::CallProofRequestError::Request(
-                        smoldot::libp2p::peers::RequestError::Substream(
-                            smoldot::libp2p::connection::established::RequestError::SubstreamClosed,
-                        ),
-                    ),
-                )),
-                Err(err) => {
-                    outcome_errors.push(err);
+                );
+
+                async move {
+                    match request.await {
+                        Ok(value) if !value.decode().is_empty() => Ok(value),
+                        // TODO: this check of emptiness is a bit of a hack; it is necessary because Substrate responds to requests about blocks it doesn't know with an empty proof
+                        Ok(_) => Err(network_service::CallProofRequestError::Request(
+                            service::CallProofRequestError::Request(
+                                smoldot::libp2p::peers::RequestError::Substream(
+                                    smoldot::libp2p::connection::established::RequestError::SubstreamClosed,
+                                ),
+                            ),
+                        )),
+                        Err(err) => Err(err),
+                    }
                 }
-            }
-        }
+            },
+        )
+        .await
+        .map_err(|errors| CallProofQueryError { errors })
+    }
 
-        Err(CallProofQueryError {
-            errors: outcome_errors,
-        })
+    /// Sends the requests built by `request` to `targets` through [`hedging::run`], and records
+    /// the duration of the requests in `latencies`. See [`hedging::Latencies::record_outcome`].
+    ///
+    /// If [`Config::requests_hedging_percentile`] is `Some`, the requests that take longer than
+    /// this percentile of the durations found in `latencies` are hedged.
+    async fn hedged_requests<T, E, F>(
+        &self,
+        latencies: &Mutex<hedging::Latencies>,
+        timeout_per_request: Duration,
+        targets: impl Iterator<Item = PeerId>,
+        request: impl FnMut(PeerId) -> F,
+    ) -> Result<T, Vec<E>>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let hedge_after = match self.requests_hedging_percentile {
+            Some(percentile) => latencies.lock().await.percentile(percentile),
+            None => None,
+        };
+
+        let outcome = hedging::run::<TPlat, _, _, _>(targets, hedge_after, request).await;
+
+        latencies
+            .lock()
+            .await
+            .record_outcome(&outcome, timeout_per_request);
+        match outcome.success {
+            Some((response, _)) => Ok(response),
+            None => Err(outcome.errors.into_iter().map(|(error, _)| error).collect()),
+        }
     }
 }
 
@@ -800,6 +1573,73 @@ pub struct BlockNotification {
     pub parent_hash: [u8; 32],
 }
 
+/// Progress of the initial synchronization of a chain. See
+/// [`SyncService::subscribe_sync_progress`].
+///
+/// The warp syncing goes through, in order, [`SyncProgress::Fragments`],
+/// [`SyncProgress::RuntimeDownload`], [`SyncProgress::RuntimeCompile`],
+/// [`SyncProgress::ChainInfoDownload`], then [`SyncProgress::Finished`]. It might go back to
+/// [`SyncProgress::Fragments`] if the peer the fragments have been downloaded from disconnects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncProgress {
+    /// GrandPa warp sync fragments are being downloaded and verified.
+    Fragments {
+        /// Height of the finalized block when the chain was added, in other words the block
+        /// the synchronization has started from.
+        start: u64,
+        /// Height of the highest block whose finality has been verified so far. Always superior
+        /// or equal to `start`.
+        verified: u64,
+        /// Height of the highest best block reported by the peers, which the warp syncing is
+        /// expected to approach. `None` if no peer is known. Always superior or equal to
+        /// `verified`.
+        total: Option<u64>,
+    },
+    /// All fragments have been verified, and the runtime of the finalized block is being
+    /// downloaded.
+    RuntimeDownload,
+    /// The runtime of the finalized block is being compiled.
+    RuntimeCompile,
+    /// The information about the chain, such as the consensus parameters, is being downloaded
+    /// and built.
+    ChainInfoDownload,
+    /// The warp syncing is over or hasn't been performed at all. The chain is now synchronized
+    /// block by block.
+    Finished,
+}
+
+impl SyncProgress {
+    /// Returns an estimation, between 0 and 100, of how much of the initial synchronization has
+    /// been performed.
+    ///
+    /// The downloading and verification of the fragments accounts for the first 90%, as it is
+    /// by far the longest step. Its progress is relative to the block the synchronization has
+    /// started from rather than to the genesis block, so that starting from a recent checkpoint
+    /// doesn't immediately report a high percentage.
+    pub fn percentage(&self) -> u8 {
+        match *self {
+            SyncProgress::Fragments { total: None, .. } => 0,
+            SyncProgress::Fragments {
+                start,
+                verified,
+                total: Some(total),
+            } => {
+                let done = verified.min(total).saturating_sub(start);
+                let to_do = total.saturating_sub(start);
+                if to_do == 0 {
+                    0
+                } else {
+                    u8::try_from(u128::from(done) * 90 / u128::from(to_do)).unwrap()
+                }
+            }
+            SyncProgress::RuntimeDownload => 90,
+            SyncProgress::RuntimeCompile => 95,
+            SyncProgress::ChainInfoDownload => 97,
+            SyncProgress::Finished => 100,
+        }
+    }
+}
+
 enum ToBackground {
     /// See [`SyncService::is_near_head_of_chain_heuristic`].
     IsNearHeadOfChainHeuristic { send_back: oneshot::Sender<bool> },
@@ -823,4 +1663,179 @@ enum ToBackground {
     SerializeChainInformation {
         send_back: oneshot::Sender<Option<chain::chain_information::ValidChainInformation>>,
     },
+    /// See [`SyncService::warp_sync_progress`].
+    WarpSyncProgress {
+        send_back: oneshot::Sender<Option<all::WarpSyncVerifiedProgress>>,
+    },
+    /// See [`SyncService::subscribe_sync_progress`].
+    SubscribeSyncProgress {
+        send_back: oneshot::Sender<mpsc::Receiver<SyncProgress>>,
+        buffer_size: usize,
+    },
+    /// See [`SyncService::block_numbers`].
+    BlockNumbers {
+        send_back: oneshot::Sender<(u64, u64)>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        PendingStorageQueries, StorageQueryRole, SyncProgress, MAX_COALESCED_STORAGE_QUERY_KEYS,
+    };
+    use core::time::Duration;
+
+    fn empty() -> PendingStorageQueries {
+        PendingStorageQueries {
+            batches: Default::default(),
+            next_batch_id: 0,
+        }
+    }
+
+    fn batch_key(trie_root: u8, total_attempts: u32) -> super::StorageQueryBatchKey {
+        (
+            [0; 32],
+            None,
+            [trie_root; 32],
+            total_attempts,
+            Duration::from_secs(8),
+        )
+    }
+
+    #[test]
+    fn queries_merged() {
+        let mut pending = empty();
+        let key = batch_key(1, 3);
+
+        let StorageQueryRole::Leader(id, _send_back) = pending.join(&key, [[3], [1]].iter()) else {
+            panic!()
+        };
+        assert!(matches!(
+            pending.join(&key, [[2], [1]].iter()),
+            StorageQueryRole::Follower(i, _) if i == id
+        ));
+
+        let (keys, num_queries) = pending.take(&key, id);
+        assert_eq!(keys, vec![vec![1], vec![2], vec![3]]);
+        assert_eq!(num_queries, 2);
+
+        // The batch has been sent. New queries create a new batch.
+        assert!(matches!(
+            pending.join(&key, [[1]].iter()),
+            StorageQueryRole::Leader(i, _) if i != id
+        ));
+    }
+
+    #[test]
+    fn different_parameters_not_merged() {
+        let mut pending = empty();
+
+        let StorageQueryRole::Leader(id1, _send_back1) =
+            pending.join(&batch_key(1, 3), [[1]].iter())
+        else {
+            panic!()
+        };
+        let StorageQueryRole::Leader(id2, _send_back2) =
+            pending.join(&batch_key(2, 3), [[1]].iter())
+        else {
+            panic!()
+        };
+        let StorageQueryRole::Leader(id3, _send_back3) =
+            pending.join(&batch_key(1, 1), [[1]].iter())
+        else {
+            panic!()
+        };
+
+        assert_eq!(pending.take(&batch_key(1, 3), id1).1, 1);
+        assert_eq!(pending.take(&batch_key(2, 3), id2).1, 1);
+        assert_eq!(pending.take(&batch_key(1, 1), id3).1, 1);
+    }
+
+    #[test]
+    fn too_many_keys_not_merged() {
+        let mut pending = empty();
+        let key = batch_key(1, 3);
+        let many_keys = (0..MAX_COALESCED_STORAGE_QUERY_KEYS)
+            .map(|n| n.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        assert!(matches!(
+            pending.join(&key, many_keys.iter()),
+            StorageQueryRole::Alone
+        ));
+
+        let StorageQueryRole::Leader(id, _send_back) = pending.join(&key, [[0xff]].iter()) else {
+            panic!()
+        };
+        assert!(matches!(
+            pending.join(&key, many_keys.iter()),
+            StorageQueryRole::Alone
+        ));
+        assert_eq!(pending.take(&key, id), (vec![vec![0xff]], 1));
+    }
+
+    #[test]
+    fn abandoned_batch_removed() {
+        let mut pending = empty();
+        let key = batch_key(1, 3);
+
+        let StorageQueryRole::Leader(id, send_back) = pending.join(&key, [[1]].iter()) else {
+            panic!()
+        };
+        let StorageQueryRole::Follower(_, outcome) = pending.join(&key, [[2]].iter()) else {
+            panic!()
+        };
+
+        drop(send_back);
+        assert!(futures::executor::block_on(outcome).is_err());
+
+        // Removing a batch with a different identifier does nothing.
+        pending.remove_abandoned(&key, id + 1);
+        assert!(pending.batches.contains_key(&key));
+        pending.remove_abandoned(&key, id);
+        assert!(pending.batches.is_empty());
+    }
+
+    #[test]
+    fn sync_progress_relative_to_start() {
+        let fragments = |verified, total| SyncProgress::Fragments {
+            start: 15_000_000,
+            verified,
+            total,
+        };
+
+        assert_eq!(fragments(15_000_000, Some(18_000_000)).percentage(), 0);
+        assert_eq!(fragments(16_500_000, Some(18_000_000)).percentage(), 45);
+        assert_eq!(fragments(18_000_000, Some(18_000_000)).percentage(), 90);
+    }
+
+    #[test]
+    fn sync_progress_edge_cases() {
+        // No peer, hence no known target.
+        let progress = SyncProgress::Fragments {
+            start: 10,
+            verified: 20,
+            total: None,
+        };
+        assert_eq!(progress.percentage(), 0);
+
+        // Peers aren't ahead of the starting block.
+        let progress = SyncProgress::Fragments {
+            start: 10,
+            verified: 10,
+            total: Some(5),
+        };
+        assert_eq!(progress.percentage(), 0);
+
+        // Starting from the genesis block.
+        let progress = SyncProgress::Fragments {
+            start: 0,
+            verified: 50,
+            total: Some(100),
+        };
+        assert_eq!(progress.percentage(), 45);
+
+        assert_eq!(SyncProgress::RuntimeDownload.percentage(), 90);
+        assert_eq!(SyncProgress::Finished.percentage(), 100);
+    }
 }
@@ -0,0 +1,438 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Retrying and hedging of the requests sent to peers.
+//!
+//! The requests performed on behalf of the [`super::SyncService`] are sent to one peer after the
+//! other until one of them answers successfully. Because waiting for a slow peer to answer can
+//! considerably delay the outcome of a request, the same request can additionally be sent to a
+//! second peer if the first one takes longer than usual to answer. This is known as *hedging*.
+//!
+//! What "longer than usual" means is determined by keeping track of the duration of the most
+//! recent requests of each kind, see [`Latencies`].
+
+use crate::platform::Platform;
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{cmp, time::Duration};
+use futures::{prelude::*, stream::FuturesUnordered};
+use smoldot::libp2p::PeerId;
+
+/// Maximum number of requests that are simultaneously in progress for the same query.
+const MAX_IN_PROGRESS: usize = 2;
+
+/// Number of durations of requests that [`Latencies`] remembers.
+const MAX_SAMPLES: usize = 64;
+
+/// Minimum number of durations of requests that must be known before requests start being
+/// hedged.
+const MIN_SAMPLES: usize = 16;
+
+/// Durations of the most recent requests of a certain kind.
+///
+/// Failed requests are recorded as well, so that peers that fail to answer raise the durations
+/// rather than being ignored. See [`Latencies::record_outcome`].
+pub(super) struct Latencies {
+    /// Durations, from the oldest to the most recent.
+    samples: VecDeque<Duration>,
+}
+
+impl Latencies {
+    /// Creates a new empty list.
+    pub(super) fn new() -> Self {
+        Latencies {
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+        }
+    }
+
+    /// Records the duration of a request. The oldest sample is forgotten if the list is full.
+    pub(super) fn record(&mut self, duration: Duration) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(duration);
+    }
+
+    /// Records the durations of all the requests that have finished during a call to [`run`].
+    ///
+    /// Failed requests are recorded with the duration they took to fail, capped to `timeout`.
+    /// Only the requests that have timed out are thus recorded as having taken `timeout`, while
+    /// the requests that have failed quickly, for example because the peer has immediately
+    /// refused them, don't prevent the following requests from being hedged.
+    pub(super) fn record_outcome<T, E>(&mut self, outcome: &Outcome<T, E>, timeout: Duration) {
+        for (_, duration) in &outcome.errors {
+            self.record(cmp::min(*duration, timeout));
+        }
+        if let Some((_, duration)) = &outcome.success {
+            self.record(*duration);
+        }
+    }
+
+    /// Returns the duration that the given percentage of the recent requests didn't exceed.
+    /// `percentile` is clamped between 1 and 100.
+    ///
+    /// Returns `None` if not enough requests have been recorded yet for this value to be
+    /// meaningful.
+    pub(super) fn percentile(&self, percentile: u8) -> Option<Duration> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let percentile = usize::from(percentile.clamp(1, 100));
+        let index = (sorted.len() * percentile).div_ceil(100) - 1;
+        Some(sorted[index])
+    }
+}
+
+/// Outcome of [`run`].
+pub(super) struct Outcome<T, E> {
+    /// Response of the request that has succeeded and how long it took to obtain it. `None` if
+    /// all the requests have failed.
+    pub success: Option<(T, Duration)>,
+    /// Errors of the requests that have failed and how long it took to obtain them, in the order
+    /// in which they have finished. Can be non-empty even if [`Outcome::success`] is `Some`.
+    pub errors: Vec<(E, Duration)>,
+}
+
+/// Sends the requests built by `request` to the peers yielded by `targets`, one after the other,
+/// until one of them succeeds or `targets` is exhausted.
+///
+/// If `hedge_after` is `Some` and a request takes longer than this duration to finish, a request
+/// to the next peer is started without cancelling the slow one, and the first of the two to
+/// succeed is used. If one of the two fails, it is replaced with a request to the next peer.
+///
+/// See [`Outcome`] for the return value.
+pub(super) async fn run<TPlat: Platform, T, E, F>(
+    mut targets: impl Iterator<Item = PeerId>,
+    hedge_after: Option<Duration>,
+    mut request: impl FnMut(PeerId) -> F,
+) -> Outcome<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let mut errors = Vec::new();
+    let mut in_progress = FuturesUnordered::new();
+
+    // Each iteration of this loop starts a new request, then waits until either a request has
+    // finished or the hedging delay has elapsed.
+    loop {
+        let mut hedge_timer: Option<TPlat::Delay> = None;
+
+        if let Some(target) = targets.next() {
+            let request = request(target);
+            in_progress.push(async move {
+                let start = TPlat::now();
+                let outcome = request.await;
+                (outcome, TPlat::now() - start)
+            });
+
+            if in_progress.len() < MAX_IN_PROGRESS {
+                hedge_timer = hedge_after.map(TPlat::sleep);
+            }
+        }
+
+        if in_progress.is_empty() {
+            return Outcome {
+                success: None,
+                errors,
+            };
+        }
+
+        let finished = {
+            let next_finished = in_progress.select_next_some();
+            let hedge = async {
+                match hedge_timer.as_mut() {
+                    Some(timer) => timer.await,
+                    None => future::pending::<()>().await,
+                }
+            };
+            futures::pin_mut!(hedge);
+            match future::select(next_finished, hedge).await {
+                future::Either::Left((finished, _)) => Some(finished),
+                future::Either::Right(((), _)) => None,
+            }
+        };
+
+        match finished {
+            Some((Ok(response), duration)) => {
+                return Outcome {
+                    success: Some((response, duration)),
+                    errors,
+                }
+            }
+            Some((Err(error), duration)) => errors.push((error, duration)),
+            None => {
+                // The hedging delay has elapsed.
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Latencies, Outcome, MAX_SAMPLES, MIN_SAMPLES};
+    use alloc::vec;
+    use core::time::Duration;
+
+    #[test]
+    fn percentile_requires_min_samples() {
+        let mut latencies = Latencies::new();
+        for n in 0..MIN_SAMPLES - 1 {
+            latencies.record(Duration::from_millis(u64::try_from(n).unwrap()));
+            assert!(latencies.percentile(50).is_none());
+        }
+        latencies.record(Duration::from_millis(1));
+        assert!(latencies.percentile(50).is_some());
+    }
+
+    #[test]
+    fn percentile_index() {
+        let mut latencies = Latencies::new();
+        // Recorded in a non-sorted order on purpose.
+        for n in (1..=16).rev() {
+            latencies.record(Duration::from_millis(n));
+        }
+
+        assert_eq!(latencies.percentile(50), Some(Duration::from_millis(8)));
+        assert_eq!(latencies.percentile(95), Some(Duration::from_millis(16)));
+        assert_eq!(latencies.percentile(100), Some(Duration::from_millis(16)));
+        assert_eq!(latencies.percentile(1), Some(Duration::from_millis(1)));
+
+        // Out of range values are clamped.
+        assert_eq!(latencies.percentile(0), Some(Duration::from_millis(1)));
+        assert_eq!(latencies.percentile(255), Some(Duration::from_millis(16)));
+    }
+
+    #[test]
+    fn failures_recorded_up_to_timeout() {
+        let timeout = Duration::from_secs(10);
+        let mut latencies = Latencies::new();
+        for _ in 0..MIN_SAMPLES {
+            latencies.record_outcome(
+                &Outcome::<(), ()> {
+                    success: None,
+                    errors: vec![
+                        ((), Duration::from_millis(5)),
+                        ((), Duration::from_secs(12)),
+                    ],
+                },
+                timeout,
+            );
+        }
+
+        assert_eq!(latencies.percentile(50), Some(Duration::from_millis(5)));
+        assert_eq!(latencies.percentile(100), Some(timeout));
+    }
+
+    #[test]
+    fn oldest_samples_forgotten() {
+        let mut latencies = Latencies::new();
+        for _ in 0..MAX_SAMPLES {
+            latencies.record(Duration::from_secs(10));
+        }
+        for _ in 0..MAX_SAMPLES {
+            latencies.record(Duration::from_millis(5));
+        }
+
+        assert_eq!(latencies.percentile(100), Some(Duration::from_millis(5)));
+    }
+
+    #[cfg(feature = "std")]
+    mod run {
+        use super::super::{run, Latencies, Outcome, MAX_IN_PROGRESS, MIN_SAMPLES};
+        use crate::platform::{async_std::AsyncStdTcpWebSocket, Platform as _};
+
+        use alloc::{sync::Arc, vec::Vec};
+        use core::time::Duration;
+        use futures::{executor::block_on, lock::Mutex};
+        use smoldot::libp2p::{peer_id, PeerId};
+
+        fn peers(num: u8) -> Vec<PeerId> {
+            (0..num)
+                .map(|n| PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32])))
+                .collect()
+        }
+
+        /// Calls [`run`] with the given behaviour of each peer: how long it takes to answer,
+        /// and whether it succeeds. Returns the outcome and the indices of the peers that have
+        /// been sent a request, in order.
+        fn test_run(
+            behaviours: &[(Duration, bool)],
+            hedge_after: Option<Duration>,
+        ) -> (Outcome<usize, usize>, Vec<usize>) {
+            let peers = peers(u8::try_from(behaviours.len()).unwrap());
+            let started = Arc::new(Mutex::new(Vec::new()));
+
+            let outcome = block_on(run::<AsyncStdTcpWebSocket, _, _, _>(
+                peers.clone().into_iter(),
+                hedge_after,
+                |target| {
+                    let index = peers.iter().position(|p| *p == target).unwrap();
+                    let (delay, success) = behaviours[index];
+                    let started = started.clone();
+                    async move {
+                        started.lock().await.push(index);
+                        AsyncStdTcpWebSocket::sleep(delay).await;
+                        if success {
+                            Ok(index)
+                        } else {
+                            Err(index)
+                        }
+                    }
+                },
+            ));
+
+            let started = block_on(started.lock()).clone();
+            (outcome, started)
+        }
+
+        fn errors(outcome: &Outcome<usize, usize>) -> Vec<usize> {
+            outcome.errors.iter().map(|(e, _)| *e).collect()
+        }
+
+        #[test]
+        fn first_success_returned() {
+            let (outcome, started) =
+                test_run(&[(Duration::ZERO, true), (Duration::ZERO, true)], None);
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(0));
+            assert!(outcome.errors.is_empty());
+            assert_eq!(started, [0]);
+        }
+
+        #[test]
+        fn failures_replaced() {
+            let (outcome, started) = test_run(
+                &[
+                    (Duration::ZERO, false),
+                    (Duration::ZERO, false),
+                    (Duration::ZERO, true),
+                    (Duration::ZERO, true),
+                ],
+                None,
+            );
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(2));
+            assert_eq!(errors(&outcome), [0, 1]);
+            assert_eq!(started, [0, 1, 2]);
+        }
+
+        #[test]
+        fn all_failures() {
+            let (outcome, started) = test_run(
+                &[(Duration::ZERO, false), (Duration::ZERO, false)],
+                Some(Duration::from_secs(10)),
+            );
+            assert!(outcome.success.is_none());
+            assert_eq!(errors(&outcome), [0, 1]);
+            assert_eq!(started, [0, 1]);
+        }
+
+        #[test]
+        fn slow_request_hedged() {
+            let (outcome, started) = test_run(
+                &[
+                    (Duration::from_secs(5), true),
+                    (Duration::from_millis(10), true),
+                ],
+                Some(Duration::from_millis(50)),
+            );
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(1));
+            assert_eq!(started, [0, 1]);
+        }
+
+        #[test]
+        fn no_hedging_without_delay() {
+            let (outcome, started) = test_run(
+                &[(Duration::from_millis(200), true), (Duration::ZERO, true)],
+                None,
+            );
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(0));
+            assert_eq!(started, [0]);
+        }
+
+        #[test]
+        fn in_progress_capped() {
+            let (outcome, started) = test_run(
+                &[
+                    (Duration::from_millis(500), true),
+                    (Duration::from_millis(500), true),
+                    (Duration::ZERO, true),
+                    (Duration::ZERO, true),
+                ],
+                Some(Duration::from_millis(10)),
+            );
+            // The third peer is never contacted, as the two slow requests are in progress
+            // until the first one succeeds.
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(0));
+            assert_eq!(started.len(), MAX_IN_PROGRESS);
+        }
+
+        #[test]
+        fn failed_hedged_request_replaced() {
+            let (outcome, started) = test_run(
+                &[
+                    (Duration::from_secs(5), true),
+                    (Duration::from_millis(100), false),
+                    (Duration::from_millis(10), true),
+                ],
+                Some(Duration::from_millis(20)),
+            );
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(2));
+            assert_eq!(errors(&outcome), [1]);
+            assert_eq!(started, [0, 1, 2]);
+        }
+
+        #[test]
+        fn hedging_active_after_fast_failures() {
+            let timeout = Duration::from_secs(10);
+
+            let mut latencies = Latencies::new();
+            for _ in 0..MIN_SAMPLES {
+                latencies.record(Duration::from_millis(20));
+            }
+
+            // Many more requests fail immediately than succeed.
+            for _ in 0..4 {
+                let (outcome, _) = test_run(
+                    &[
+                        (Duration::ZERO, false),
+                        (Duration::ZERO, false),
+                        (Duration::ZERO, false),
+                        (Duration::from_millis(20), true),
+                    ],
+                    latencies.percentile(90),
+                );
+                assert_eq!(outcome.success.as_ref().map(|(r, _)| *r), Some(3));
+                latencies.record_outcome(&outcome, timeout);
+            }
+
+            let hedge_after = latencies.percentile(90).unwrap();
+            assert!(hedge_after < Duration::from_secs(1));
+            let (outcome, started) = test_run(
+                &[
+                    (Duration::from_secs(5), true),
+                    (Duration::from_millis(10), true),
+                ],
+                Some(hedge_after),
+            );
+            assert_eq!(outcome.success.map(|(r, _)| r), Some(1));
+            assert_eq!(started, [0, 1]);
+        }
+    }
+}
@@ -20,7 +20,7 @@ use crate::{network_service, platform::Platform, runtime_service};
 
 use alloc::{borrow::ToOwned as _, string::String, sync::Arc, vec::Vec};
 use core::{
-    iter, mem,
+    cmp, iter, mem,
     num::{NonZeroU32, NonZeroUsize},
     time::Duration,
 };
@@ -34,7 +34,7 @@ use smoldot::{
     informant::HashDisplay,
     libp2p::PeerId,
     network::protocol,
-    sync::{all_forks::sources, para},
+    sync::{all, all_forks::sources, para},
 };
 
 /// Starts a sync service background task to synchronize a parachain.
@@ -45,6 +45,7 @@ pub(super) async fn start_parachain<TPlat: Platform>(
     relay_chain_sync: Arc<runtime_service::RuntimeService<TPlat>>,
     relay_chain_block_number_bytes: usize,
     parachain_id: u32,
+    best_block_policy: all::BestBlockPolicy,
     from_foreground: mpsc::Receiver<ToBackground>,
     network_chain_index: usize,
     from_network_service: stream::BoxStream<'static, network_service::Event>,
@@ -61,6 +62,10 @@ pub(super) async fn start_parachain<TPlat: Platform>(
             block_number_bytes,
             relay_chain_block_number_bytes,
             parachain_id,
+            follow_finality_only: matches!(
+                best_block_policy,
+                all::BestBlockPolicy::FollowFinalityOnly
+            ),
             network_chain_index,
             from_network_service: from_network_service.fuse(),
             sync_sources: sources::AllForksSources::new(
@@ -112,6 +117,10 @@ struct ParachainBackgroundTask<TPlat: Platform> {
     /// Id of the parachain registered within the relay chain. Chosen by the user.
     parachain_id: u32,
 
+    /// If `true`, the best block of the relay chain is ignored and the best parachain block is
+    /// always the finalized parachain block. See [`all::BestBlockPolicy::FollowFinalityOnly`].
+    follow_finality_only: bool,
+
     /// Index of the chain within the associated network service.
     ///
     /// Used to filter events from [`ParachainBackgroundTask::from_network_service`].
@@ -193,7 +202,7 @@ struct ParachainBackgroundTaskAfterSubscription<TPlat: Platform> {
     /// The set of blocks in this tree whose parachain block hasn't been fetched yet is the same
     /// as the set of blocks that is maintained pinned on the runtime service. Blocks are unpinned
     /// when their parachain head fetching succeeds or when they are removed from the tree.
-    async_tree: async_tree::AsyncTree<TPlat::Instant, [u8; 32], Option<Vec<u8>>>,
+    async_tree: async_tree::AsyncTree<TPlat::Instant, [u8; 32], Option<Parahead>>,
 
     /// List of in-progress parachain head fetching operations.
     ///
@@ -202,13 +211,38 @@ struct ParachainBackgroundTaskAfterSubscription<TPlat: Platform> {
     /// alive for longer than this container, and by the fact that we unpin block after a
     /// fetching operation has finished and that we never fetch twice for the same block.
     in_progress_paraheads: stream::FuturesUnordered<
-        future::BoxFuture<'static, (async_tree::AsyncOpId, Result<Vec<u8>, ParaheadError>)>,
+        future::BoxFuture<'static, (async_tree::AsyncOpId, Result<Parahead, ParaheadError>)>,
     >,
 
     /// Future that is ready when we need to start a new parachain head fetch operation.
     next_start_parahead_fetch: future::Either<future::Fuse<TPlat::Delay>, future::Pending<()>>,
 }
 
+/// Parachain head corresponding to a relay chain block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Parahead {
+    /// SCALE-encoded header of the parachain block, as found in the persisted validation data of
+    /// the relay chain block.
+    scale_encoded_header: Vec<u8>,
+
+    /// SCALE-encoded headers of the ancestors of [`Parahead::scale_encoded_header`] that have
+    /// been included in the same relay chain block, ordered from the oldest to the newest.
+    ///
+    /// Always empty unless the parachain is assigned multiple cores of the relay chain (in other
+    /// words, uses elastic scaling).
+    included_ancestors: Vec<Vec<u8>>,
+}
+
+impl Parahead {
+    /// Returns the list of parachain blocks of this parachain head, ordered from the oldest to
+    /// the newest. The last item is always [`Parahead::scale_encoded_header`].
+    fn parablocks(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.included_ancestors
+            .iter()
+            .chain(iter::once(&self.scale_encoded_header))
+    }
+}
+
 impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
     async fn run(mut self) {
         loop {
@@ -372,8 +406,14 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     runtime_subscription.async_tree.finalized_async_user_data()
                 {
                     // Finalized parahead is known.
+                    let finalized_parahash = header::hash_from_scale_encoded_header(
+                        &finalized_parahead.scale_encoded_header,
+                    );
+
                     let _ = send_back.send(super::SubscribeAll {
-                        finalized_block_scale_encoded_header: finalized_parahead.clone(),
+                        finalized_block_scale_encoded_header: finalized_parahead
+                            .scale_encoded_header
+                            .clone(),
                         finalized_block_runtime: None,
                         non_finalized_blocks_ancestry_order: {
                             let mut list =
@@ -386,67 +426,83 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                             for relay_block in
                                 runtime_subscription.async_tree.input_iter_ancestry_order()
                             {
-                                let parablock = match relay_block.async_op_user_data {
+                                let parahead = match relay_block.async_op_user_data {
                                     Some(b) => b.as_ref().unwrap(),
                                     None => continue,
                                 };
 
-                                let parablock_hash =
-                                    header::hash_from_scale_encoded_header(parablock);
+                                let parablock_hash = header::hash_from_scale_encoded_header(
+                                    &parahead.scale_encoded_header,
+                                );
 
-                                if let Some((_, entry)) =
-                                    list.iter_mut().find(|(h, _)| *h == parablock_hash)
-                                {
-                                    if relay_block.is_output_best {
-                                        entry.is_new_best = true;
-                                    }
-                                } else {
-                                    let parent_hash = runtime_subscription
-                                        .async_tree
-                                        .ancestors(relay_block.id)
-                                        .find_map(|idx| {
-                                            let hash = header::hash_from_scale_encoded_header(
-                                                runtime_subscription
-                                                    .async_tree
-                                                    .block_async_user_data(idx)
-                                                    .unwrap()
-                                                    .as_ref()
-                                                    .unwrap(),
-                                            );
-                                            if hash != parablock_hash {
-                                                Some(hash)
-                                            } else {
-                                                None
-                                            }
-                                        })
-                                        .or_else(|| {
-                                            let finalized_parahash =
-                                                header::hash_from_scale_encoded_header(
-                                                    finalized_parahead,
-                                                );
-                                            if finalized_parahash != parablock_hash {
-                                                Some(finalized_parahash)
-                                            } else {
-                                                None
-                                            }
-                                        });
-
-                                    // `parent_hash` is `None` if the parablock is
-                                    // the same as the finalized parablock.
-                                    if let Some(parent_hash) = parent_hash {
-                                        debug_assert_eq!(
-                                            list.iter().filter(|(h, _)| *h == parent_hash).count(),
-                                            1
+                                let parent_hash = runtime_subscription
+                                    .async_tree
+                                    .ancestors(relay_block.id)
+                                    .find_map(|idx| {
+                                        let hash = header::hash_from_scale_encoded_header(
+                                            &runtime_subscription
+                                                .async_tree
+                                                .block_async_user_data(idx)
+                                                .unwrap()
+                                                .as_ref()
+                                                .unwrap()
+                                                .scale_encoded_header,
                                         );
-                                        list.push((
-                                            parablock_hash,
-                                            super::BlockNotification {
-                                                is_new_best: relay_block.is_output_best,
-                                                scale_encoded_header: parablock.clone(),
-                                                parent_hash,
-                                            },
-                                        ));
+                                        if hash != parablock_hash {
+                                            Some(hash)
+                                        } else {
+                                            None
+                                        }
+                                    })
+                                    .or({
+                                        if finalized_parahash != parablock_hash {
+                                            Some(finalized_parahash)
+                                        } else {
+                                            None
+                                        }
+                                    });
+
+                                // `parent_hash` is `None` if the parablock is the same as the
+                                // finalized parablock.
+                                let Some(mut parent_hash) = parent_hash else {
+                                    continue;
+                                };
+
+                                // If multiple parablocks have been included in the relay chain
+                                // block, they are each reported, the last one being the one
+                                // that can become the best block.
+                                let num_parablocks = parahead.included_ancestors.len() + 1;
+                                for (index, parablock) in parahead.parablocks().enumerate() {
+                                    let is_last = index == num_parablocks - 1;
+                                    let hash = header::hash_from_scale_encoded_header(parablock);
+                                    let parablock_parent_hash =
+                                        mem::replace(&mut parent_hash, hash);
+
+                                    if let Some((_, entry)) =
+                                        list.iter_mut().find(|(h, _)| *h == hash)
+                                    {
+                                        if is_last && relay_block.is_output_best {
+                                            entry.is_new_best = true;
+                                        }
+                                        continue;
                                     }
+
+                                    debug_assert!(
+                                        parablock_parent_hash == finalized_parahash
+                                            || list
+                                                .iter()
+                                                .filter(|(h, _)| *h == parablock_parent_hash)
+                                                .count()
+                                                == 1
+                                    );
+                                    list.push((
+                                        hash,
+                                        super::BlockNotification {
+                                            is_new_best: is_last && relay_block.is_output_best,
+                                            scale_encoded_header: parablock.clone(),
+                                            parent_hash: parablock_parent_hash,
+                                        },
+                                    ));
                                 }
                             }
 
@@ -513,6 +569,39 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
             (ToBackground::SerializeChainInformation { send_back }, _) => {
                 let _ = send_back.send(None);
             }
+            (ToBackground::WarpSyncProgress { send_back }, _) => {
+                // Parachains don't warp sync.
+                let _ = send_back.send(None);
+            }
+            (ToBackground::SubscribeSyncProgress { send_back, .. }, _) => {
+                // Parachains don't warp sync. The sender is destroyed immediately after having
+                // sent `Finished`, which closes the channel.
+                let (mut tx, rx) = mpsc::channel(0);
+                let _ = tx.try_send(super::SyncProgress::Finished);
+                let _ = send_back.send(rx);
+            }
+            (ToBackground::BlockNumbers { send_back }, subscription_state) => {
+                let finalized_block_number = self.sync_sources.finalized_block_height();
+
+                // The best block is the parachain head of the best relay chain block, if known.
+                let best_block_number = match subscription_state {
+                    ParachainBackgroundState::Subscribed(sub) => sub
+                        .async_tree
+                        .best_block_index()
+                        .and_then(|(_, parahead)| parahead.as_ref())
+                        .and_then(|parahead| {
+                            header::decode(&parahead.scale_encoded_header, self.block_number_bytes)
+                                .ok()
+                        })
+                        .map_or(finalized_block_number, |header| header.number),
+                    ParachainBackgroundState::NotSubscribed { .. } => finalized_block_number,
+                };
+
+                let _ = send_back.send((
+                    finalized_block_number,
+                    cmp::max(finalized_block_number, best_block_number),
+                ));
+            }
         }
     }
 
@@ -610,22 +699,58 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                         HashDisplay(op.block_user_data),
                     );
 
+                    let block_hash = *op.block_user_data;
+                    let block_index = op.block_index;
+                    let async_op_id = op.id;
+
+                    // Hash of the parachain head of the parent of the relay chain block, if
+                    // known. Used in order to determine whether multiple parachain blocks might
+                    // have been included in this relay chain block.
+                    // If no finalized parachain head is known yet, no parachain block is
+                    // reported before the finalized one, and there is no point in knowing this.
+                    let relay_parent_parahead_hash =
+                        match runtime_subscription.async_tree.parent(block_index) {
+                            Some(parent) => Some(
+                                runtime_subscription
+                                    .async_tree
+                                    .block_async_user_data(parent)
+                                    .and_then(|parahead| parahead.as_ref())
+                                    .map(|parahead| {
+                                        header::hash_from_scale_encoded_header(
+                                            &parahead.scale_encoded_header,
+                                        )
+                                    }),
+                            ),
+                            None => runtime_subscription
+                                .async_tree
+                                .finalized_async_user_data()
+                                .as_ref()
+                                .map(|parahead| {
+                                    Some(header::hash_from_scale_encoded_header(
+                                        &parahead.scale_encoded_header,
+                                    ))
+                                }),
+                        };
+
                     runtime_subscription.in_progress_paraheads.push({
                         let relay_chain_sync = self.relay_chain_sync.clone();
                         let subscription_id = runtime_subscription.relay_chain_subscribe_all.id();
-                        let block_hash = *op.block_user_data;
-                        let async_op_id = op.id;
                         let relay_chain_block_number_bytes = self.relay_chain_block_number_bytes;
+                        let block_number_bytes = self.block_number_bytes;
                         let parachain_id = self.parachain_id;
+                        let log_target = self.log_target.clone();
                         async move {
                             (
                                 async_op_id,
                                 parahead(
+                                    &log_target,
                                     &relay_chain_sync,
                                     relay_chain_block_number_bytes,
+                                    block_number_bytes,
                                     subscription_id,
                                     parachain_id,
                                     &block_hash,
+                                    relay_parent_parahead_hash,
                                 )
                                 .await,
                             )
@@ -640,7 +765,7 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
     async fn process_parahead_fetch_result(
         &mut self,
         async_op_id: async_tree::AsyncOpId,
-        parahead_result: Result<Vec<u8>, ParaheadError>,
+        parahead_result: Result<Parahead, ParaheadError>,
     ) {
         let runtime_subscription = match &mut self.subscription_state {
             ParachainBackgroundState::NotSubscribed { .. } => return,
@@ -651,8 +776,9 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
             Ok(parahead) => {
                 log::debug!(
                     target: &self.log_target,
-                    "ParaheadFetchOperations => Parahead(hash={}, relay_blocks={})",
-                    HashDisplay(blake2_rfc::blake2b::blake2b(32, b"", &parahead).as_bytes()),
+                    "ParaheadFetchOperations => Parahead(hash={}, included_ancestors={}, relay_blocks={})",
+                    HashDisplay(blake2_rfc::blake2b::blake2b(32, b"", &parahead.scale_encoded_header).as_bytes()),
+                    parahead.included_ancestors.len(),
                     runtime_subscription.async_tree.async_op_blocks(async_op_id).map(|b| HashDisplay(b)).join(",")
                 );
 
@@ -751,10 +877,17 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     }
 
                     let hash = header::hash_from_scale_encoded_header(
-                        new_finalized_parahead.as_ref().unwrap(),
+                        &new_finalized_parahead
+                            .as_ref()
+                            .unwrap()
+                            .scale_encoded_header,
                     );
 
-                    self.obsolete_finalized_parahead = new_finalized_parahead.clone().unwrap();
+                    self.obsolete_finalized_parahead = new_finalized_parahead
+                        .as_ref()
+                        .unwrap()
+                        .scale_encoded_header
+                        .clone();
 
                     if let Ok(header) =
                         header::decode(&self.obsolete_finalized_parahead, self.block_number_bytes)
@@ -789,7 +922,9 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                         .async_tree
                         .best_block_index()
                         .map(|(_, parahead)| {
-                            header::hash_from_scale_encoded_header(parahead.as_ref().unwrap())
+                            header::hash_from_scale_encoded_header(
+                                &parahead.as_ref().unwrap().scale_encoded_header,
+                            )
                         })
                         .unwrap_or(hash);
                     runtime_subscription.reported_best_parahead_hash = Some(best_block_hash);
@@ -820,11 +955,12 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     // Calculate hash of the parablock corresponding to the new best relay
                     // chain block.
                     let parahash = header::hash_from_scale_encoded_header(
-                        runtime_subscription
+                        &runtime_subscription
                             .async_tree
                             .best_block_index()
                             .map(|(_, b)| b.as_ref().unwrap())
-                            .unwrap_or(finalized_parahead),
+                            .unwrap_or(finalized_parahead)
+                            .scale_encoded_header,
                     );
 
                     if runtime_subscription.reported_best_parahead_hash.as_ref() != Some(&parahash)
@@ -853,8 +989,7 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     // `block` borrows `async_tree`. We need to mutably access `async_tree`
                     // below, so deconstruct `block` beforehand.
                     let is_new_best = block.is_new_best;
-                    let scale_encoded_header: Vec<u8> = block.async_op_user_data.clone().unwrap();
-                    let parahash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+                    let parahead: Parahead = block.async_op_user_data.clone().unwrap();
                     let block_index = block.index;
 
                     // Do not report anything to subscriptions if no finalized parahead is
@@ -865,61 +1000,9 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                             None => continue,
                         };
 
-                    // Do not report the new block if it has already been reported in the
-                    // past. This covers situations where the parahead is identical to the
-                    // relay chain's parent's parahead, but also situations where multiple
-                    // sibling relay chain blocks have the same parahead.
-                    if *finalized_parahead == scale_encoded_header
-                        || runtime_subscription
-                            .async_tree
-                            .input_iter_unordered()
-                            .filter(|item| item.id != block_index)
-                            .filter_map(|item| item.async_op_user_data)
-                            .any(|item| item.as_ref() == Some(&scale_encoded_header))
-                    {
-                        // While the parablock has already been reported, it is possible that
-                        // it becomes the new best block while it wasn't before, in which
-                        // case we should send a notification.
-                        if is_new_best
-                            && runtime_subscription.reported_best_parahead_hash.as_ref()
-                                != Some(&parahash)
-                        {
-                            runtime_subscription.reported_best_parahead_hash = Some(parahash);
-
-                            log::debug!(
-                                target: &self.log_target,
-                                "Subscriptions <= BestBlockChanged(hash={})",
-                                HashDisplay(&parahash)
-                            );
-
-                            // Elements in `all_subscriptions` are removed one by one and
-                            // inserted back if the channel is still open.
-                            for index in (0..runtime_subscription.all_subscriptions.len()).rev() {
-                                let mut sender =
-                                    runtime_subscription.all_subscriptions.swap_remove(index);
-                                let notif =
-                                    super::Notification::BestBlockChanged { hash: parahash };
-                                if sender.try_send(notif).is_ok() {
-                                    runtime_subscription.all_subscriptions.push(sender);
-                                }
-                            }
-                        }
-
-                        continue;
-                    }
-
-                    log::debug!(
-                        target: &self.log_target,
-                        "Subscriptions <= NewParablock(hash={})",
-                        HashDisplay(&parahash)
-                    );
-
-                    if is_new_best {
-                        runtime_subscription.reported_best_parahead_hash = Some(parahash);
-                    }
-
-                    let parent_hash = header::hash_from_scale_encoded_header(
-                        runtime_subscription
+                    // Hash of the parent of the first parablock of `parahead`.
+                    let mut parent_hash = header::hash_from_scale_encoded_header(
+                        &runtime_subscription
                             .async_tree
                             .parent(block_index)
                             .map(|idx| {
@@ -930,20 +1013,89 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                                     .as_ref()
                                     .unwrap()
                             })
-                            .unwrap_or(&finalized_parahead),
+                            .unwrap_or(finalized_parahead)
+                            .scale_encoded_header,
                     );
 
-                    // Elements in `all_subscriptions` are removed one by one and
-                    // inserted back if the channel is still open.
-                    for index in (0..runtime_subscription.all_subscriptions.len()).rev() {
-                        let mut sender = runtime_subscription.all_subscriptions.swap_remove(index);
-                        let notif = super::Notification::Block(super::BlockNotification {
-                            is_new_best,
-                            parent_hash,
-                            scale_encoded_header: scale_encoded_header.clone(),
-                        });
-                        if sender.try_send(notif).is_ok() {
-                            runtime_subscription.all_subscriptions.push(sender);
+                    // If the parachain is assigned multiple cores, multiple parablocks might
+                    // have been included in the same relay chain block. They are reported one
+                    // by one, from the oldest to the newest. Only the newest can become the new
+                    // best block.
+                    let num_parablocks = parahead.included_ancestors.len() + 1;
+                    for (index, scale_encoded_header) in parahead.parablocks().enumerate() {
+                        let is_new_best = is_new_best && index == num_parablocks - 1;
+                        let parahash = header::hash_from_scale_encoded_header(scale_encoded_header);
+                        let parablock_parent_hash = mem::replace(&mut parent_hash, parahash);
+
+                        // Do not report the new block if it has already been reported in the
+                        // past. This covers situations where the parahead is identical to the
+                        // relay chain's parent's parahead, but also situations where multiple
+                        // sibling relay chain blocks have the same parahead or have included
+                        // the same parablocks.
+                        if finalized_parahead.scale_encoded_header == *scale_encoded_header
+                            || runtime_subscription
+                                .async_tree
+                                .input_iter_unordered()
+                                .filter(|item| item.id != block_index)
+                                .filter_map(|item| item.async_op_user_data)
+                                .filter_map(|item| item.as_ref())
+                                .any(|item| item.parablocks().any(|b| b == scale_encoded_header))
+                        {
+                            // While the parablock has already been reported, it is possible that
+                            // it becomes the new best block while it wasn't before, in which
+                            // case we should send a notification.
+                            if is_new_best
+                                && runtime_subscription.reported_best_parahead_hash.as_ref()
+                                    != Some(&parahash)
+                            {
+                                runtime_subscription.reported_best_parahead_hash = Some(parahash);
+
+                                log::debug!(
+                                    target: &self.log_target,
+                                    "Subscriptions <= BestBlockChanged(hash={})",
+                                    HashDisplay(&parahash)
+                                );
+
+                                // Elements in `all_subscriptions` are removed one by one and
+                                // inserted back if the channel is still open.
+                                for index in (0..runtime_subscription.all_subscriptions.len()).rev()
+                                {
+                                    let mut sender =
+                                        runtime_subscription.all_subscriptions.swap_remove(index);
+                                    let notif =
+                                        super::Notification::BestBlockChanged { hash: parahash };
+                                    if sender.try_send(notif).is_ok() {
+                                        runtime_subscription.all_subscriptions.push(sender);
+                                    }
+                                }
+                            }
+
+                            continue;
+                        }
+
+                        log::debug!(
+                            target: &self.log_target,
+                            "Subscriptions <= NewParablock(hash={})",
+                            HashDisplay(&parahash)
+                        );
+
+                        if is_new_best {
+                            runtime_subscription.reported_best_parahead_hash = Some(parahash);
+                        }
+
+                        // Elements in `all_subscriptions` are removed one by one and
+                        // inserted back if the channel is still open.
+                        for index in (0..runtime_subscription.all_subscriptions.len()).rev() {
+                            let mut sender =
+                                runtime_subscription.all_subscriptions.swap_remove(index);
+                            let notif = super::Notification::Block(super::BlockNotification {
+                                is_new_best,
+                                parent_hash: parablock_parent_hash,
+                                scale_encoded_header: scale_encoded_header.clone(),
+                            });
+                            if sender.try_send(notif).is_ok() {
+                                runtime_subscription.all_subscriptions.push(sender);
+                            }
                         }
                     }
                 }
@@ -984,9 +1136,14 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     .find(|b| *b.user_data == best_block_hash)
                     .unwrap()
                     .id;
-                runtime_subscription
-                    .async_tree
-                    .input_finalize(finalized, best);
+                runtime_subscription.async_tree.input_finalize(
+                    finalized,
+                    if self.follow_finality_only {
+                        finalized
+                    } else {
+                        best
+                    },
+                );
             }
             runtime_service::Notification::Block(block) => {
                 let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
@@ -1007,7 +1164,7 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     hash,
                     parent,
                     false,
-                    block.is_new_best,
+                    block.is_new_best && !self.follow_finality_only,
                 );
             }
             runtime_service::Notification::BestBlockChanged { hash } => {
@@ -1017,6 +1174,10 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     HashDisplay(&hash)
                 );
 
+                if self.follow_finality_only {
+                    return;
+                }
+
                 let node_idx = runtime_subscription
                     .async_tree
                     .input_iter_unordered()
@@ -1063,7 +1224,12 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
                     .find(|b| *b.user_data == block.parent_hash)
                     .map(|b| b.id)
                     .unwrap_or(finalized_index);
-                async_tree.input_insert_block(hash, Some(parent), false, block.is_new_best);
+                async_tree.input_insert_block(
+                    hash,
+                    Some(parent),
+                    false,
+                    block.is_new_best && !self.follow_finality_only,
+                );
             }
             async_tree
         };
@@ -1085,15 +1251,130 @@ impl<TPlat: Platform> ParachainBackgroundTask<TPlat> {
     }
 }
 
+/// Fetches the parachain head corresponding to the given relay chain block.
+///
+/// `relay_parent_parahead_hash` must be `Some(Some(_))` if the parachain head of the parent of
+/// the relay chain block is known, `Some(None)` if it hasn't been fetched yet, and `None` if the
+/// included ancestors of the parachain head aren't needed.
+///
+/// If the candidate events of the relay chain block can't be obtained, the included ancestors
+/// are left empty, in which case only the parachain head found in the persisted validation data
+/// is reported.
 async fn parahead<TPlat: Platform>(
+    log_target: &str,
     relay_chain_sync: &Arc<runtime_service::RuntimeService<TPlat>>,
     relay_chain_block_number_bytes: usize,
+    block_number_bytes: usize,
     subscription_id: runtime_service::SubscriptionId,
     parachain_id: u32,
     block_hash: &[u8; 32],
-) -> Result<Vec<u8>, ParaheadError> {
+    relay_parent_parahead_hash: Option<Option<[u8; 32]>>,
+) -> Result<Parahead, ParaheadError> {
     // For each relay chain block, call `ParachainHost_persisted_validation_data` in
     // order to know where the parachains are.
+    let output = runtime_call(
+        relay_chain_sync,
+        subscription_id,
+        block_hash,
+        para::PERSISTED_VALIDATION_FUNCTION_NAME,
+        para::persisted_validation_data_parameters(
+            parachain_id,
+            para::OccupiedCoreAssumption::TimedOut,
+        ),
+    )
+    .await?;
+
+    // Try decode the result of the runtime call.
+    // If this fails, it indicates an incompatibility between smoldot and the relay chain.
+    let scale_encoded_header = match para::decode_persisted_validation_data_return_value(
+        &output,
+        relay_chain_block_number_bytes,
+    ) {
+        Ok(Some(pvd)) => pvd.parent_head.to_vec(),
+        Ok(None) => return Err(ParaheadError::NoCore),
+        Err(error) => return Err(ParaheadError::InvalidRuntimeOutput(error)),
+    };
+
+    // If the parachain is assigned multiple cores of the relay chain (elastic scaling), multiple
+    // parachain blocks might have been included in this relay chain block, and the persisted
+    // validation data only contains the latest of them.
+    // The other blocks are found in the candidate events of the relay chain block. In order to
+    // avoid an additional runtime call, the candidate events are only fetched if the parachain
+    // head doesn't directly follow the parachain head of the relay chain parent, or if the
+    // parachain head of the relay chain parent isn't known yet.
+    let needs_candidate_events = match relay_parent_parahead_hash {
+        None => false,
+        Some(None) => true,
+        Some(Some(relay_parent_parahead_hash)) => {
+            header::hash_from_scale_encoded_header(&scale_encoded_header)
+                != relay_parent_parahead_hash
+                && header::decode(&scale_encoded_header, block_number_bytes)
+                    .map_or(false, |h| *h.parent_hash != relay_parent_parahead_hash)
+        }
+    };
+
+    let included_ancestors = if needs_candidate_events {
+        let events_result = match runtime_call(
+            relay_chain_sync,
+            subscription_id,
+            block_hash,
+            para::CANDIDATE_EVENTS_FUNCTION_NAME,
+            iter::empty::<Vec<u8>>(),
+        )
+        .await
+        {
+            Ok(output) => para::decode_candidate_events_return_value(&output)
+                .map(|events| {
+                    para::included_ancestors(
+                        parachain_id,
+                        &scale_encoded_header,
+                        &events,
+                        block_number_bytes,
+                    )
+                    .into_iter()
+                    .map(|head| head.to_vec())
+                    .collect::<Vec<_>>()
+                })
+                .map_err(ParaheadError::InvalidRuntimeOutput),
+            Err(ParaheadError::ObsoleteSubscription) => {
+                return Err(ParaheadError::ObsoleteSubscription)
+            }
+            Err(error) => Err(error),
+        };
+
+        // Failing to obtain the candidate events isn't fatal, as the persisted validation data
+        // is enough to follow the parachain. Only the latest parachain block included in the
+        // relay chain block is reported in that situation.
+        match events_result {
+            Ok(included_ancestors) => included_ancestors,
+            Err(error) => {
+                log::debug!(
+                    target: log_target,
+                    "ParaheadFetchOperations => CandidateEventsError(relay_block={}, error={})",
+                    HashDisplay(block_hash),
+                    error
+                );
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    Ok(Parahead {
+        scale_encoded_header,
+        included_ancestors,
+    })
+}
+
+/// Performs a runtime call against the given relay chain block.
+async fn runtime_call<TPlat: Platform>(
+    relay_chain_sync: &Arc<runtime_service::RuntimeService<TPlat>>,
+    subscription_id: runtime_service::SubscriptionId,
+    block_hash: &[u8; 32],
+    function_to_call: &str,
+    parameter: impl Iterator<Item = impl AsRef<[u8]>> + Clone,
+) -> Result<Vec<u8>, ParaheadError> {
     let precall = match relay_chain_sync
         .pinned_block_runtime_lock(subscription_id, block_hash)
         .await
@@ -1106,11 +1387,8 @@ async fn parahead<TPlat: Platform>(
 
     let (runtime_call_lock, virtual_machine) = precall
         .start(
-            para::PERSISTED_VALIDATION_FUNCTION_NAME,
-            para::persisted_validation_data_parameters(
-                parachain_id,
-                para::OccupiedCoreAssumption::TimedOut,
-            ),
+            function_to_call,
+            parameter.clone(),
             6,
             Duration::from_secs(10),
             NonZeroU32::new(2).unwrap(),
@@ -1118,15 +1396,10 @@ async fn parahead<TPlat: Platform>(
         .await
         .map_err(ParaheadError::Call)?;
 
-    // TODO: move the logic below in the `para` module
-
     let mut runtime_call = match read_only_runtime_host::run(read_only_runtime_host::Config {
         virtual_machine,
-        function_to_call: para::PERSISTED_VALIDATION_FUNCTION_NAME,
-        parameter: para::persisted_validation_data_parameters(
-            parachain_id,
-            para::OccupiedCoreAssumption::TimedOut,
-        ),
+        function_to_call,
+        parameter,
         max_log_level: 0,
     }) {
         Ok(vm) => vm,
@@ -1136,12 +1409,12 @@ async fn parahead<TPlat: Platform>(
         }
     };
 
-    let output = loop {
+    loop {
         match runtime_call {
             read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
                 let output = success.virtual_machine.value().as_ref().to_owned();
                 runtime_call_lock.unlock(success.virtual_machine.into_prototype());
-                break output;
+                break Ok(output);
             }
             read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
                 runtime_call_lock.unlock(error.prototype);
@@ -1173,17 +1446,6 @@ async fn parahead<TPlat: Platform>(
                 runtime_call = sig.verify_and_resume();
             }
         }
-    };
-
-    // Try decode the result of the runtime call.
-    // If this fails, it indicates an incompatibility between smoldot and the relay chain.
-    match para::decode_persisted_validation_data_return_value(
-        &output,
-        relay_chain_block_number_bytes,
-    ) {
-        Ok(Some(pvd)) => Ok(pvd.parent_head.to_vec()),
-        Ok(None) => Err(ParaheadError::NoCore),
-        Err(error) => Err(ParaheadError::InvalidRuntimeOutput(error)),
     }
 }
 
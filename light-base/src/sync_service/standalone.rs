@@ -15,7 +15,10 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::{BlockNotification, FinalizedBlockRuntime, Notification, SubscribeAll, ToBackground};
+use super::{
+    BlockNotification, FinalizedBlockRuntime, Notification, SubscribeAll, SyncProgress,
+    ToBackground,
+};
 use crate::{network_service, platform::Platform};
 
 use alloc::{borrow::ToOwned as _, string::String, sync::Arc, vec::Vec};
@@ -33,6 +36,7 @@ use smoldot::{
     libp2p,
     network::{self, protocol},
     sync::all,
+    verify::precheck,
 };
 
 /// Starts a sync service background task to synchronize a standalone chain (relay chain or not).
@@ -40,41 +44,74 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
     log_target: String,
     chain_information: chain::chain_information::ValidChainInformation,
     block_number_bytes: usize,
+    warp_sync_resume_progress: Option<all::WarpSyncVerifiedProgress>,
+    sync_mode: all::SyncMode,
+    probabilistic_finality_depth: Option<NonZeroU64>,
+    best_block_policy: all::BestBlockPolicy,
+    verification_workers: Vec<mpsc::Sender<precheck::SignaturesCheck>>,
+    from_verification_workers: mpsc::Receiver<precheck::VerifiedSignatures>,
     mut from_foreground: mpsc::Receiver<ToBackground>,
     network_service: Arc<network_service::NetworkService<TPlat>>,
     network_chain_index: usize,
     from_network_service: stream::BoxStream<'static, network_service::Event>,
 ) {
+    let sync_start_block_number = chain_information.as_ref().finalized_block_header.number;
+
+    let sync = all::AllSync::new(all::Config {
+        chain_information,
+        block_number_bytes,
+        allow_unknown_consensus_engines: true,
+        best_block_policy,
+        sources_capacity: 32,
+        blocks_capacity: {
+            // This is the maximum number of blocks between two consecutive justifications.
+            1024
+        },
+        max_disjoint_headers: 1024,
+        max_requests_per_block: NonZeroU32::new(3).unwrap(),
+        // Downloading the warp sync proof from multiple peers at the same time prevents a
+        // single peer that is slow to answer from slowing down the start-up time.
+        max_parallel_warp_sync_requests: NonZeroU32::new(3).unwrap(),
+        download_ahead_blocks: {
+            // Verifying a block mostly consists in:
+            //
+            // - Verifying a sr25519 signature for each block, plus a VRF output when the
+            // block is claiming a primary BABE slot.
+            // - Verifying one ed25519 signature per authority for every justification.
+            //
+            // At the time of writing, the speed of these operations hasn't been benchmarked.
+            // It is likely that it varies quite a bit between the various environments (the
+            // different browser engines, and NodeJS).
+            //
+            // Assuming a maximum verification speed of 5k blocks/sec and a 95% latency of one
+            // second, the number of blocks to download ahead of time in order to not block
+            // is 5k.
+            NonZeroU32::new(5000).unwrap()
+        },
+        sync_mode,
+        probabilistic_finality_depth,
+        full: None,
+        warp_sync_resume_progress,
+    });
+
+    // The GrandPa warp sync fragments verified by the sync state machine start either from the
+    // finalized block or from the block the warp syncing has resumed from.
+    let warp_sync_start_block_hash = match sync.warp_sync_verified_progress() {
+        Some(progress) => {
+            let hash = progress.header.hash(block_number_bytes);
+            log::debug!(
+                target: &log_target,
+                "Sync => WarpSyncResumed(block=#{}, hash={})",
+                progress.header.number,
+                HashDisplay(&hash)
+            );
+            hash
+        }
+        None => sync.finalized_block_header().hash(block_number_bytes),
+    };
+
     let mut task = Task {
-        sync: all::AllSync::new(all::Config {
-            chain_information,
-            block_number_bytes,
-            allow_unknown_consensus_engines: true,
-            sources_capacity: 32,
-            blocks_capacity: {
-                // This is the maximum number of blocks between two consecutive justifications.
-                1024
-            },
-            max_disjoint_headers: 1024,
-            max_requests_per_block: NonZeroU32::new(3).unwrap(),
-            download_ahead_blocks: {
-                // Verifying a block mostly consists in:
-                //
-                // - Verifying a sr25519 signature for each block, plus a VRF output when the
-                // block is claiming a primary BABE slot.
-                // - Verifying one ed25519 signature per authority for every justification.
-                //
-                // At the time of writing, the speed of these operations hasn't been benchmarked.
-                // It is likely that it varies quite a bit between the various environments (the
-                // different browser engines, and NodeJS).
-                //
-                // Assuming a maximum verification speed of 5k blocks/sec and a 95% latency of one
-                // second, the number of blocks to download ahead of time in order to not block
-                // is 5k.
-                NonZeroU32::new(5000).unwrap()
-            },
-            full: None,
-        }),
+        sync,
         network_up_to_date_best: true,
         network_up_to_date_finalized: true,
         known_finalized_runtime: None,
@@ -86,11 +123,18 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
             Duration::from_secs(10),
         ))
         .fuse(),
+        warp_sync_start_block_hash,
         all_notifications: Vec::<mpsc::Sender<Notification>>::new(),
+        sync_progress_subscriptions: Vec::new(),
+        reported_sync_progress: None,
+        sync_start_block_number,
         log_target,
         network_service,
         network_chain_index,
         peers_source_id_map: HashMap::with_capacity_and_hasher(0, Default::default()),
+        verification_workers,
+        next_verification_worker: 0,
+        from_verification_workers,
         platform: PhantomData,
     };
 
@@ -189,6 +233,9 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
             task.network_up_to_date_finalized = true;
         }
 
+        // Any of the steps above might have changed the progress of the initial synchronization.
+        task.update_sync_progress();
+
         // Now waiting for some event to happen: a network event, a request from the frontend
         // of the sync service, or a request being finished.
         let response_outcome = futures::select! {
@@ -219,6 +266,14 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                 // `result` is an error if the block request got cancelled by the sync state
                 // machine.
                 if let Ok(result) = result {
+                    // Hand over the signatures of the downloaded headers to the verification
+                    // workers before the headers get queued for verification.
+                    if let Ok(blocks) = &result {
+                        task.precheck_signatures(
+                            blocks.iter().filter_map(|block| block.header.as_deref())
+                        );
+                    }
+
                     // Inject the result of the request into the sync state machine.
                     task.sync.blocks_request_response(
                         request_id,
@@ -308,6 +363,13 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                 }
             },
 
+            verified = task.from_verification_workers.select_next_some() => {
+                // The signatures of a header have been successfully verified ahead of time by
+                // one of the verification workers.
+                task.sync.insert_prechecked_signatures(verified);
+                continue;
+            },
+
             () = &mut task.warp_sync_taking_long_time_warning => {
                 match task.sync.status() {
                     all::Status::Sync => {},
@@ -389,12 +451,27 @@ struct Task<TPlat: Platform> {
     /// All event subscribers that are interested in events about the chain.
     all_notifications: Vec<mpsc::Sender<Notification>>,
 
+    /// All subscribers that are interested in the progress of the initial synchronization.
+    sync_progress_subscriptions: Vec<mpsc::Sender<SyncProgress>>,
+
+    /// Latest value sent to the elements of [`Task::sync_progress_subscriptions`]. `None` if
+    /// nothing has been sent yet.
+    reported_sync_progress: Option<SyncProgress>,
+
+    /// Height of the finalized block at the time when the sync service has started.
+    sync_start_block_number: u64,
+
     /// Contains a `Delay` after which we print a warning about GrandPa warp sync taking a long
     /// time. Set to `Pending` after the warp sync has finished, so that future remains pending
     /// forever.
     warp_sync_taking_long_time_warning:
         future::Fuse<future::Either<TPlat::Delay, future::Pending<()>>>,
 
+    /// Hash of the block the GrandPa warp sync fragments verified by [`Task::sync`] start from.
+    /// This is the finalized block at the time when the sync service has started, or the block
+    /// the warp syncing has resumed from.
+    warp_sync_start_block_hash: [u8; 32],
+
     /// Network service. Used to send out requests to peers.
     network_service: Arc<network_service::NetworkService<TPlat>>,
     /// Index within the network service of the chain we are interested in. Must be indicated to
@@ -448,10 +525,49 @@ struct Task<TPlat: Platform> {
         >,
     >,
 
+    /// Channels towards the tasks that verify the signatures of headers ahead of time. Empty if
+    /// signatures are only ever verified by this task.
+    verification_workers: Vec<mpsc::Sender<precheck::SignaturesCheck>>,
+    /// Index within [`Task::verification_workers`] of the worker the next signatures check is
+    /// sent to.
+    next_verification_worker: usize,
+    /// Receives the outcome of the checks sent to [`Task::verification_workers`].
+    from_verification_workers: mpsc::Receiver<precheck::VerifiedSignatures>,
+
     platform: PhantomData<fn() -> TPlat>,
 }
 
 impl<TPlat: Platform> Task<TPlat> {
+    /// Sends the signatures of the given headers to the verification workers, in order for them
+    /// to be verified ahead of time and in parallel.
+    ///
+    /// The headers are later verified in order by [`Task::process_one_verification_queue`],
+    /// which doesn't verify again the signatures that have successfully been verified ahead of
+    /// time. Checks that can't be sent because the workers are busy are simply discarded.
+    fn precheck_signatures<'a>(&mut self, scale_encoded_headers: impl Iterator<Item = &'a [u8]>) {
+        if self.verification_workers.is_empty() {
+            return;
+        }
+
+        for scale_encoded_header in scale_encoded_headers {
+            let decoded = match header::decode(scale_encoded_header, self.sync.block_number_bytes())
+            {
+                Ok(h) => h,
+                Err(_) => continue,
+            };
+
+            let check = match self.sync.signatures_check(decoded) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            // Checks are distributed between the workers in a round-robin fashion.
+            let worker = self.next_verification_worker;
+            self.next_verification_worker = (worker + 1) % self.verification_workers.len();
+            let _ = self.verification_workers[worker].try_send(check);
+        }
+    }
+
     /// Starts one network request if any is necessary.
     ///
     /// Returns `true` if a request has been started.
@@ -529,15 +645,21 @@ impl<TPlat: Platform> Task<TPlat> {
             } => {
                 let peer_id = self.sync[source_id].0.clone(); // TODO: why does this require cloning? weird borrow chk issue
 
-                let grandpa_request = self.network_service.clone().grandpa_warp_sync_request(
-                    peer_id,
-                    self.network_chain_index,
-                    sync_start_block_hash,
-                    // The timeout needs to be long enough to potentially download the maximum
-                    // response size of 16 MiB. Assuming a 128 kiB/sec connection, that's
-                    // 128 seconds. Unfortunately, 128 seconds is way too large, and for
-                    // pragmatic reasons we use a lower value.
-                    Duration::from_secs(24),
+                let grandpa_request = crate::util::instrument!(
+                    self.network_service.clone().grandpa_warp_sync_request(
+                        peer_id,
+                        self.network_chain_index,
+                        sync_start_block_hash,
+                        // The timeout needs to be long enough to potentially download the maximum
+                        // response size of 16 MiB. Assuming a 128 kiB/sec connection, that's
+                        // 128 seconds. Unfortunately, 128 seconds is way too large, and for
+                        // pragmatic reasons we use a lower value.
+                        Duration::from_secs(24),
+                    ),
+                    "warp-sync-fragments-download",
+                    chain = %self.log_target,
+                    peer_id = %peer_id,
+                    start_block = %HashDisplay(&sync_start_block_hash)
                 );
 
                 let (grandpa_request, abort) = future::abortable(grandpa_request);
@@ -562,18 +684,24 @@ impl<TPlat: Platform> Task<TPlat> {
                     network::protocol::StorageProofRequestConfig {
                         block_hash,
                         keys: keys.clone().into_iter(),
+                        child_trie: None,
                     },
                     Duration::from_secs(16),
                 );
 
-                let storage_request = async move {
-                    if let Ok(outcome) = storage_request.await {
-                        // TODO: log what happens
-                        Ok(outcome.decode().to_vec()) // TODO: no to_vec() here, needs some API change on the networking
-                    } else {
-                        Err(())
-                    }
-                };
+                let storage_request = crate::util::instrument!(
+                    async move {
+                        if let Ok(outcome) = storage_request.await {
+                            // TODO: log what happens
+                            Ok(outcome.decode().to_vec()) // TODO: no to_vec() here, needs some API change on the networking
+                        } else {
+                            Err(())
+                        }
+                    },
+                    "warp-sync-storage-proof-download",
+                    chain = %self.log_target,
+                    block = %HashDisplay(&block_hash)
+                );
 
                 let (storage_request, abort) = future::abortable(storage_request);
                 let request_id = self
@@ -596,23 +724,29 @@ impl<TPlat: Platform> Task<TPlat> {
                 let parameter_vectored = parameter_vectored.clone();
                 let function_name = function_name.clone();
 
-                let call_proof_request = async move {
-                    let rq = network_service.call_proof_request(
-                        network_chain_index,
-                        peer_id,
-                        network::protocol::CallProofRequestConfig {
-                            block_hash,
-                            method: &function_name,
-                            parameter_vectored: iter::once(parameter_vectored),
-                        },
-                        Duration::from_secs(16),
-                    );
+                let call_proof_request = crate::util::instrument!(
+                    async move {
+                        let rq = network_service.call_proof_request(
+                            network_chain_index,
+                            peer_id,
+                            network::protocol::CallProofRequestConfig {
+                                block_hash,
+                                method: &function_name,
+                                parameter_vectored: iter::once(parameter_vectored),
+                            },
+                            Duration::from_secs(16),
+                        );
 
-                    match rq.await {
-                        Ok(p) => Ok(p),
-                        Err(_) => Err(()),
-                    }
-                };
+                        match rq.await {
+                            Ok(p) => Ok(p),
+                            Err(_) => Err(()),
+                        }
+                    },
+                    "warp-sync-call-proof-download",
+                    chain = %self.log_target,
+                    block = %HashDisplay(&block_hash),
+                    function = %function_name
+                );
 
                 let (call_proof_request, abort) = future::abortable(call_proof_request);
                 let request_id = self
@@ -683,6 +817,21 @@ impl<TPlat: Platform> Task<TPlat> {
                 // must be cleared.
                 self.all_notifications.clear();
 
+                // The warp sync fragments reported to the network service now lead to the
+                // finalized block.
+                if let chain::chain_information::ChainInformationFinalityRef::Grandpa {
+                    after_finalized_block_authorities_set_id,
+                    ..
+                } = self.sync.as_chain_information().as_ref().finality
+                {
+                    self.network_service
+                        .finish_grandpa_warp_sync_proof(
+                            self.network_chain_index,
+                            after_finalized_block_authorities_set_id,
+                        )
+                        .await;
+                }
+
                 return (self, true);
             }
 
@@ -690,9 +839,44 @@ impl<TPlat: Platform> Task<TPlat> {
                 // Grandpa warp sync fragment to verify.
                 let sender_peer_id = verify.proof_sender().1 .0.clone(); // TODO: unnecessary cloning most of the time
 
-                let (sync, result) = verify.perform(rand::random());
+                // The fragment is only copied if the network service uses it.
+                let fragment = if self
+                    .network_service
+                    .serves_grandpa_warp_sync(self.network_chain_index)
+                {
+                    verify.fragment().map(|f| {
+                        (
+                            f.scale_encoded_header.clone(),
+                            f.scale_encoded_justification.clone(),
+                        )
+                    })
+                } else {
+                    None
+                };
+
+                let (sync, result) = crate::util::in_span!(
+                    verify.perform(rand::random()),
+                    "warp-sync-fragment-verify",
+                    chain = %self.log_target,
+                    peer_id = %sender_peer_id
+                );
                 self.sync = sync;
 
+                if let (Ok(()), Some((scale_encoded_header, scale_encoded_justification))) =
+                    (&result, fragment)
+                {
+                    // Verified fragments are reported to the network service, which uses them
+                    // to answer the warp sync requests of other peers.
+                    self.network_service
+                        .add_grandpa_warp_sync_fragment(
+                            self.network_chain_index,
+                            self.warp_sync_start_block_hash,
+                            scale_encoded_header,
+                            scale_encoded_justification,
+                        )
+                        .await;
+                }
+
                 if let Err(err) = result {
                     let maybe_forced_change = matches!(err, all::WarpSyncFragmentError::Verify(_));
                     log::warn!(
@@ -952,6 +1136,13 @@ impl<TPlat: Platform> Task<TPlat> {
                 let _ = send_back.send(self.sync.is_near_head_of_chain_heuristic());
             }
 
+            ToBackground::BlockNumbers { send_back } => {
+                let _ = send_back.send((
+                    self.sync.finalized_block_header().number,
+                    self.sync.best_block_number(),
+                ));
+            }
+
             ToBackground::SubscribeAll {
                 send_back,
                 buffer_size,
@@ -1036,6 +1227,28 @@ impl<TPlat: Platform> Task<TPlat> {
             ToBackground::SerializeChainInformation { send_back } => {
                 let _ = send_back.send(Some(self.sync.as_chain_information().into()));
             }
+            ToBackground::WarpSyncProgress { send_back } => {
+                let _ = send_back.send(self.sync.warp_sync_verified_progress());
+            }
+            ToBackground::SubscribeSyncProgress {
+                send_back,
+                buffer_size,
+            } => {
+                // Bring the existing subscribers up to date first, so that all subscribers have
+                // been sent the same latest value.
+                self.update_sync_progress();
+                let progress = self.sync_progress();
+                self.reported_sync_progress = Some(progress.clone());
+
+                let (mut tx, rx) = mpsc::channel(buffer_size.saturating_sub(1));
+                let _ = tx.try_send(progress.clone());
+                // The channel is closed immediately if the synchronization is already over.
+                if progress != SyncProgress::Finished {
+                    self.sync_progress_subscriptions.push(tx);
+                }
+
+                let _ = send_back.send(rx);
+            }
         }
     }
 
@@ -1116,7 +1329,7 @@ impl<TPlat: Platform> Task<TPlat> {
 
                 match self.sync.block_announce(
                     sync_source_id,
-                    decoded.scale_encoded_header.to_owned(),
+                    decoded.scale_encoded_header,
                     decoded.is_best,
                 ) {
                     all::BlockAnnounceOutcome::HeaderVerify
@@ -1199,11 +1412,76 @@ impl<TPlat: Platform> Task<TPlat> {
             }
 
             _ => {
-                // Different chain index.
+                // Uninteresting message or different chain index.
             }
         }
     }
 
+    /// Returns the current progress of the initial synchronization.
+    fn sync_progress(&self) -> SyncProgress {
+        match self.sync.warp_sync_stage() {
+            None => SyncProgress::Finished,
+            Some(all::WarpSyncStage::Fragments) => {
+                let verified = match self.sync.status() {
+                    all::Status::WarpSyncFragments {
+                        finalized_block_number,
+                        ..
+                    }
+                    | all::Status::WarpSyncChainInformation {
+                        finalized_block_number,
+                        ..
+                    } => finalized_block_number,
+                    all::Status::Sync => unreachable!(),
+                };
+                let total = self
+                    .sync
+                    .sources()
+                    .map(|src| self.sync.source_best_block(src).0)
+                    .max()
+                    .map(|total| total.max(verified));
+                SyncProgress::Fragments {
+                    start: self.sync_start_block_number.min(verified),
+                    verified,
+                    total,
+                }
+            }
+            Some(all::WarpSyncStage::RuntimeDownload) => SyncProgress::RuntimeDownload,
+            Some(all::WarpSyncStage::RuntimeCompilation) => SyncProgress::RuntimeCompile,
+            Some(all::WarpSyncStage::ChainInformationDownload) => SyncProgress::ChainInfoDownload,
+        }
+    }
+
+    /// Sends the progress of the initial synchronization to the elements of
+    /// [`Task::sync_progress_subscriptions`] if it has changed since the last time.
+    fn update_sync_progress(&mut self) {
+        if self.sync_progress_subscriptions.is_empty() {
+            return;
+        }
+
+        let progress = self.sync_progress();
+        if self.reported_sync_progress.as_ref() == Some(&progress) {
+            return;
+        }
+
+        // Elements are removed one by one and inserted back if the channel is still open and
+        // not full. Subscribers whose channel is full are expected to subscribe again.
+        for index in (0..self.sync_progress_subscriptions.len()).rev() {
+            let mut subscription = self.sync_progress_subscriptions.swap_remove(index);
+            if subscription.try_send(progress.clone()).is_err() {
+                continue;
+            }
+
+            self.sync_progress_subscriptions.push(subscription);
+        }
+
+        // The progress can't change anymore once the synchronization is over.
+        if progress == SyncProgress::Finished {
+            self.sync_progress_subscriptions.clear();
+        }
+
+        self.reported_sync_progress = Some(progress);
+    }
+
     /// Sends a notification to all the notification receivers.
     fn dispatch_all_subscribers(&mut self, notification: Notification) {
         // Elements in `all_notifications` are removed one by one and inserted back if the
@@ -1218,3 +1496,25 @@ impl<TPlat: Platform> Task<TPlat> {
         }
     }
 }
+
+/// Starts a task that verifies the signatures of headers on behalf of the task started with
+/// [`start_standalone_chain`], which it communicates with through the given channels.
+///
+/// The task ends when either channel is closed.
+pub(super) async fn start_verification_worker<TPlat: Platform>(
+    mut to_verify: mpsc::Receiver<precheck::SignaturesCheck>,
+    mut verified: mpsc::Sender<precheck::VerifiedSignatures>,
+) {
+    while let Some(check) = to_verify.next().await {
+        // Failures are ignored, as the header will fail to verify later anyway.
+        if let Ok(success) = check.verify() {
+            if verified.send(success).await.is_err() {
+                return;
+            }
+        }
+
+        // As explained in the documentation of `yield_after_cpu_intensive`, we should yield
+        // after a CPU-intensive operation.
+        TPlat::yield_after_cpu_intensive().await;
+    }
+}
@@ -83,7 +83,12 @@ use core::{
     num::{NonZeroU32, NonZeroUsize},
     time::Duration,
 };
-use futures::{channel::mpsc, lock::Mutex, prelude::*, stream::FuturesUnordered};
+use futures::{
+    channel::{mpsc, oneshot},
+    lock::Mutex,
+    prelude::*,
+    stream::FuturesUnordered,
+};
 use itertools::Itertools as _;
 use smoldot::{
     header,
@@ -127,6 +132,15 @@ pub struct Config<TPlat: Platform> {
 
     /// Maximum number of transaction validations that can be performed in parallel.
     pub max_concurrent_validations: NonZeroU32,
+
+    /// If `true`, transactions are validated against the best block before being gossiped, and
+    /// transactions that are invalid are immediately dropped with [`DropReason::Invalid`].
+    ///
+    /// If `false`, transactions are gossiped without being validated. No
+    /// [`TransactionStatus::Validated`] status is generated, and transactions can never be
+    /// dropped with [`DropReason::Invalid`], [`DropReason::ValidateError`], or
+    /// [`DropReason::Mortality`].
+    pub validate_transactions: bool,
 }
 
 /// See [the module-level documentation](..).
@@ -158,6 +172,7 @@ impl<TPlat: Platform> TransactionsService<TPlat> {
                     .unwrap_or(usize::max_value()),
                 usize::try_from(config.max_concurrent_validations.get())
                     .unwrap_or(usize::max_value()),
+                config.validate_transactions,
             )),
         );
 
@@ -214,6 +229,28 @@ impl<TPlat: Platform> TransactionsService<TPlat> {
             .await
             .unwrap();
     }
+
+    /// Returns the highest nonce used by the given account among the transactions of the
+    /// service, or `None` if the service doesn't contain any transaction of this account.
+    ///
+    /// `account` must be the SCALE-encoded account id.
+    ///
+    /// The nonce of a transaction is found by looking at the tags that it provides according to
+    /// its most recent successful validation. Substrate runtimes use the SCALE encoding of the
+    /// tuple `(account, nonce)` as one of these tags. Transactions that haven't been validated
+    /// yet are ignored.
+    pub async fn highest_pending_nonce(&self, account: Vec<u8>) -> Option<u64> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .lock()
+            .await
+            .send(ToBackground::HighestPendingNonce { account, send_back })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
 }
 
 /// Update on the state of a transaction in the service.
@@ -225,6 +262,12 @@ impl<TPlat: Platform> TransactionsService<TPlat> {
 /// >           is the default state.
 #[derive(Debug, Clone)]
 pub enum TransactionStatus {
+    /// Transaction has been successfully validated for the first time.
+    ///
+    /// This is always the first message sent back by the channel reporting the status, unless
+    /// the transaction is dropped beforehand.
+    Validated,
+
     /// Transaction has been broadcasted to the given peers.
     Broadcast(Vec<PeerId>),
 
@@ -263,6 +306,12 @@ pub enum DropReason {
 
     /// Transaction has been dropped because we have failed to validate it.
     ValidateError(ValidateTransactionError),
+
+    /// Transaction has been dropped because its longevity, as reported by its most recent
+    /// validation, has expired, and it hasn't been included in the finalized chain in time.
+    ///
+    /// In practice, this happens to mortal transactions whose era has ended.
+    Mortality,
 }
 
 /// Failed to check the validity of a transaction.
@@ -297,6 +346,10 @@ enum ToBackground {
         transaction_bytes: Vec<u8>,
         updates_report: Option<mpsc::Sender<TransactionStatus>>,
     },
+    HighestPendingNonce {
+        account: Vec<u8>,
+        send_back: oneshot::Sender<Option<u64>>,
+    },
 }
 
 /// Background task running in parallel of the front service.
@@ -310,6 +363,7 @@ async fn background_task<TPlat: Platform>(
     max_concurrent_downloads: usize,
     max_pending_transactions: usize,
     max_concurrent_validations: usize,
+    validate_transactions: bool,
 ) {
     let transactions_capacity = cmp::min(8, max_pending_transactions);
     let blocks_capacity = 32;
@@ -317,6 +371,7 @@ async fn background_task<TPlat: Platform>(
     let mut worker = Worker {
         sync_service,
         runtime_service,
+        network_events: network_service.subscribe(32).await,
         network_service,
         network_chain_index,
         pending_transactions: light_pool::LightPool::new(light_pool::Config {
@@ -329,6 +384,7 @@ async fn background_task<TPlat: Platform>(
         next_reannounce: FuturesUnordered::new(),
         max_concurrent_downloads,
         max_pending_transactions,
+        validate_transactions,
     };
 
     // TODO: must periodically re-send transactions that aren't included in block yet
@@ -413,7 +469,9 @@ async fn background_task<TPlat: Platform>(
             }
 
             // Start the validation process of transactions that need to be validated.
-            while worker.validations_in_progress.len() < max_concurrent_validations {
+            while worker.validate_transactions
+                && worker.validations_in_progress.len() < max_concurrent_validations
+            {
                 // Find a transaction that needs to be validated.
                 //
                 // While this is an `O(n)` process, in practice we pick the first transaction not
@@ -586,6 +644,8 @@ async fn background_task<TPlat: Platform>(
             }
 
             // Remove finalized blocks from the pool when possible.
+            // Number of the highest block that has been removed, if any.
+            let mut pruned_finalized_block_number = None;
             for block in worker.pending_transactions.prune_finalized_with_body() {
                 // All blocks in `pending_transactions` are pinned within the runtime service.
                 // Unpin them when they're removed.
@@ -606,6 +666,13 @@ async fn background_task<TPlat: Platform>(
                 );
 
                 debug_assert!(!block.user_data.downloading);
+                if let Ok(header) = header::decode(
+                    &block.user_data.scale_encoded_header,
+                    worker.sync_service.block_number_bytes(),
+                ) {
+                    pruned_finalized_block_number = Some(header.number);
+                }
+
                 for mut tx in block.included_transactions {
                     // We assume that there's no more than 2<<32 transactions per block.
                     let body_index = u32::try_from(tx.index_in_block).unwrap();
@@ -618,6 +685,36 @@ async fn background_task<TPlat: Platform>(
                 }
             }
 
+            // Remove transactions whose longevity has expired.
+            // All the finalized blocks up to `pruned_finalized_block_number` have had their body
+            // inspected, and the transactions included in them have been removed above. The
+            // transactions that remain can thus no longer be included if their longevity ends
+            // at or before this block.
+            if let Some(finalized_block_number) = pruned_finalized_block_number {
+                let expired = worker
+                    .pending_transactions
+                    .transactions_iter()
+                    .filter(|(_, tx)| {
+                        tx.mortality_end
+                            .map_or(false, |end| end <= finalized_block_number)
+                    })
+                    .map(|(tx_id, _)| tx_id)
+                    .collect::<Vec<_>>();
+
+                for tx_id in expired {
+                    let (tx_body, mut transaction) =
+                        worker.pending_transactions.remove_transaction(tx_id);
+
+                    log::debug!(
+                        target: &log_target,
+                        "Discarded(tx_hash={}, error=Mortality)",
+                        HashDisplay(&blake2_hash(&tx_body)),
+                    );
+
+                    transaction.update_status(TransactionStatus::Dropped(DropReason::Mortality));
+                }
+            }
+
             futures::select! {
                 notification = subscribe_all.new_blocks.next().fuse() => {
                     match notification {
@@ -725,7 +822,8 @@ async fn background_task<TPlat: Platform>(
                     // TODO: if best block changes, we would need to reset all the re-announce period of all transactions, awkward!
                     // TODO: also, if this is false, then the transaction might never be re-announced ever again
                     if worker.pending_transactions.is_included_best_chain(maybe_reannounce_tx_id) ||
-                        !worker.pending_transactions.is_valid_against_best_block(maybe_reannounce_tx_id)
+                        (worker.validate_transactions &&
+                            !worker.pending_transactions.is_valid_against_best_block(maybe_reannounce_tx_id))
                     {
                         continue;
                     }
@@ -826,6 +924,22 @@ async fn background_task<TPlat: Platform>(
                                 maybe_validated_tx_id
                             }.boxed());
 
+                            // Block against which the transaction has been validated. Always
+                            // present, as checked above.
+                            let validated_block_number = header::decode(
+                                &worker.pending_transactions.block_user_data(&block_hash).unwrap().scale_encoded_header,
+                                worker.sync_service.block_number_bytes(),
+                            ).map(|h| h.number);
+
+                            let tx = worker.pending_transactions.transaction_user_data_mut(maybe_validated_tx_id).unwrap();
+                            tx.mortality_end = validated_block_number
+                                .ok()
+                                .and_then(|n| n.checked_add(result.longevity.get()));
+                            tx.provides = result.provides.clone();
+                            if tx.latest_status.is_none() {
+                                tx.update_status(TransactionStatus::Validated);
+                            }
+
                             Ok(result)
                         }
                         Err(ValidationError::ObsoleteSubscription) => {
@@ -850,6 +964,22 @@ async fn background_task<TPlat: Platform>(
                                 error,
                             );
 
+                            // If the transaction has never been successfully validated, it has
+                            // never been gossiped either. Report it as invalid immediately
+                            // rather than waiting for the block it was validated against to be
+                            // finalized.
+                            if worker.pending_transactions
+                                .transaction_user_data(maybe_validated_tx_id)
+                                .unwrap()
+                                .latest_status
+                                .is_none()
+                            {
+                                let (_, mut transaction) =
+                                    worker.pending_transactions.remove_transaction(maybe_validated_tx_id);
+                                transaction.update_status(TransactionStatus::Dropped(DropReason::Invalid(error)));
+                                continue;
+                            }
+
                             Err(InvalidOrError::Invalid(error))
                         }
                         Err(ValidationError::InvalidOrError(InvalidOrError::ValidateError(error))) => {
@@ -879,6 +1009,48 @@ async fn background_task<TPlat: Platform>(
                         .set_validation_result(maybe_validated_tx_id, &block_hash, validation_result);
                 },
 
+                event = worker.network_events.next() => {
+                    match event {
+                        Some(network_service::Event::Connected { peer_id, chain_index, .. })
+                            if chain_index == worker.network_chain_index =>
+                        {
+                            // A new peer has connected. Re-announce all the pending
+                            // transactions, so that this peer learns about them without
+                            // waiting for their next periodic re-announce.
+                            log::debug!(
+                                target: &log_target,
+                                "NetworkService => Connected(peer={})",
+                                peer_id
+                            );
+
+                            // The transactions substream with this peer might not be open yet.
+                            // Wait a bit before re-announcing.
+                            let when = TPlat::now() + Duration::from_secs(1);
+                            let tx_ids = worker
+                                .pending_transactions
+                                .transactions_iter()
+                                .map(|(tx_id, _)| tx_id)
+                                .collect::<Vec<_>>();
+                            for tx_id in tx_ids {
+                                let tx = worker.pending_transactions.transaction_user_data_mut(tx_id).unwrap();
+                                if tx.when_reannounce > when {
+                                    tx.when_reannounce = when.clone();
+                                }
+                                worker.next_reannounce.push(async move {
+                                    TPlat::sleep(Duration::from_secs(1)).await;
+                                    tx_id
+                                }.boxed());
+                            }
+                        }
+                        Some(_) => {}
+                        None => {
+                            // The subscription has been closed by the network service because
+                            // the channel was full. Subscribe again.
+                            worker.network_events = worker.network_service.subscribe(32).await;
+                        }
+                    }
+                },
+
                 message = from_foreground.next().fuse() => {
                     let message = match message {
                         Some(msg) => msg,
@@ -915,7 +1087,7 @@ async fn background_task<TPlat: Platform>(
                             }
 
                             // Success path. Inserting in pool.
-                            worker
+                            let tx_id = worker
                                 .pending_transactions
                                 .add_unvalidated(transaction_bytes, PendingTransaction {
                                     when_reannounce: TPlat::now(),
@@ -928,7 +1100,36 @@ async fn background_task<TPlat: Platform>(
                                     },
                                     latest_status: None,
                                     validation_in_progress: None,
+                                    mortality_end: None,
+                                    provides: Vec::new(),
                                 });
+
+                            // If transactions aren't validated, schedule the announcement
+                            // immediately. Otherwise, it is scheduled after the validation.
+                            if !worker.validate_transactions {
+                                worker.next_reannounce.push(async move { tx_id }.boxed());
+                            }
+                        }
+                        ToBackground::HighestPendingNonce { account, send_back } => {
+                            let highest = worker
+                                .pending_transactions
+                                .transactions_iter()
+                                .flat_map(|(_, tx)| tx.provides.iter())
+                                .filter_map(|tag| {
+                                    // The tag is expected to be the SCALE encoding of
+                                    // `(account, nonce)`, where the nonce is either a `u32` or
+                                    // a `u64`.
+                                    let nonce = tag.strip_prefix(&account[..])?;
+                                    if let Ok(nonce) = <[u8; 4]>::try_from(nonce) {
+                                        Some(u64::from(u32::from_le_bytes(nonce)))
+                                    } else if let Ok(nonce) = <[u8; 8]>::try_from(nonce) {
+                                        Some(u64::from_le_bytes(nonce))
+                                    } else {
+                                        None
+                                    }
+                                })
+                                .max();
+                            let _ = send_back.send(highest);
                         }
                     }
                 }
@@ -948,6 +1149,10 @@ struct Worker<TPlat: Platform> {
     /// How to gossip transactions.
     network_service: Arc<network_service::NetworkService<TPlat>>,
 
+    /// Events coming from [`Worker::network_service`]. Used in order to re-announce the pending
+    /// transactions to newly-connected peers.
+    network_events: mpsc::Receiver<network_service::Event>,
+
     /// Which chain to use in combination with the [`Worker::network_service`].
     network_chain_index: usize,
 
@@ -993,6 +1198,9 @@ struct Worker<TPlat: Platform> {
     /// See [`Config::max_concurrent_downloads`]. Maximum number of elements in
     /// [`Worker::block_downloads`].
     max_concurrent_downloads: usize,
+
+    /// See [`Config::validate_transactions`].
+    validate_transactions: bool,
 }
 
 impl<TPlat: Platform> Worker<TPlat> {
@@ -1078,6 +1286,15 @@ struct PendingTransaction<TPlat: Platform> {
             Result<validate::ValidTransaction, ValidationError>,
         )>,
     >,
+
+    /// Number of the last block in which the transaction can be included, according to its most
+    /// recent successful validation. `None` if the transaction hasn't been successfully
+    /// validated yet, or if it never expires.
+    mortality_end: Option<u64>,
+
+    /// Tags provided by the transaction according to its most recent successful validation.
+    /// Empty if the transaction hasn't been successfully validated yet.
+    provides: Vec<Vec<u8>>,
 }
 
 impl<TPlat: Platform> PendingTransaction<TPlat> {
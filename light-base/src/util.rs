@@ -45,3 +45,47 @@ pub fn truncated_str<'a>(
 
     Iter(input, limit)
 }
+
+/// Wraps the future passed as first parameter in a `tracing` span built from the rest of the
+/// parameters, which are passed as-is to `tracing::info_span!`. Returns the future unchanged if
+/// the `tracing` feature is disabled.
+///
+/// The span is built before the future expression is evaluated, so that its fields can refer to
+/// values that the future expression moves.
+#[cfg(feature = "tracing")]
+macro_rules! instrument {
+    ($future:expr, $($span:tt)+) => {{
+        let span = tracing::info_span!($($span)+);
+        tracing::Instrument::instrument($future, span)
+    }};
+}
+
+/// See the other definition.
+#[cfg(not(feature = "tracing"))]
+macro_rules! instrument {
+    ($future:expr, $($span:tt)+) => {
+        $future
+    };
+}
+
+/// Evaluates the expression passed as first parameter within a `tracing` span built from the
+/// rest of the parameters, which are passed as-is to `tracing::info_span!`. Simply evaluates the
+/// expression if the `tracing` feature is disabled.
+///
+/// Must only be used for expressions that don't contain any `await`.
+#[cfg(feature = "tracing")]
+macro_rules! in_span {
+    ($body:expr, $($span:tt)+) => {
+        tracing::info_span!($($span)+).in_scope(|| $body)
+    };
+}
+
+/// See the other definition.
+#[cfg(not(feature = "tracing"))]
+macro_rules! in_span {
+    ($body:expr, $($span:tt)+) => {
+        $body
+    };
+}
+
+pub(crate) use {in_span, instrument};
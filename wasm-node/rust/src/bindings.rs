@@ -483,7 +483,8 @@ pub extern "C" fn chain_error_ptr(chain_id: u32) -> u32 {
 ///
 /// This function returns:
 /// - 0 on success.
-/// - 1 if the request couldn't be parsed as a valid JSON-RPC request.
+/// - 1 if the request couldn't be parsed as a valid JSON-RPC request, or is a batch containing
+/// too many requests.
 /// - 2 if the chain is currently overloaded with JSON-RPC requests and refuses to queue another
 /// one.
 ///
@@ -205,6 +205,18 @@ pub(crate) fn init<TPlat: smoldot_light::platform::Platform, TChain>(
         }),
         system_name: env!("CARGO_PKG_NAME").into(),
         system_version: env!("CARGO_PKG_VERSION").into(),
+        max_upload_bps: None,
+        max_download_bps: None,
+        libp2p_key: None,
+        // Compiling to native code isn't possible within a WebAssembly virtual machine.
+        wasm_execution: smoldot_light::WasmExecution::Interpreter,
+        database_storage: None,
+        dns: smoldot_light::DnsConfig::Platform,
+        // All the tasks run on the same thread.
+        header_verification_workers: 0,
+        requests_hedging_percentile: Some(95),
+        yamux_max_receive_window: 4 * 1024 * 1024,
+        max_inbound_substreams: None,
     });
 
     Client {
@@ -22,6 +22,7 @@
 
 use core::{
     cmp::Ordering,
+    num::{NonZeroU32, NonZeroUsize},
     ops::{Add, Sub},
     pin::Pin,
     slice, str,
@@ -252,8 +253,37 @@ fn add_chain(
             user_data: (),
             specification: str::from_utf8(&chain_spec).unwrap(),
             database_content: str::from_utf8(&database_content).unwrap(),
+            genesis_storage: None,
+            checkpoint: "",
             disable_json_rpc: json_rpc_running == 0,
             potential_relay_chains: potential_relay_chains.into_iter(),
+            json_rpc_max_pending_responses: NonZeroU32::new(128).unwrap(),
+            // Note: the PolkadotJS UI is very heavy in terms of subscriptions.
+            json_rpc_max_subscriptions: 1024,
+            json_rpc_max_batch_size: 64,
+            json_rpc_max_parallel_requests: NonZeroU32::new(24).unwrap(),
+            json_rpc_max_parallel_cheap_requests: NonZeroU32::new(4).unwrap(),
+            max_pinned_blocks: NonZeroUsize::new(32).unwrap(),
+            max_block_hash_lookup_depth: 16384,
+            archive_fallback_endpoints: Vec::new(),
+            auto_recover: None,
+            reserved_nodes: Vec::new(),
+            reserved_only: false,
+            network_out_slots: 4,
+            serve_warp_sync: false,
+            enable_beefy: true,
+            sync_mode: smoldot_light::SyncMode::AllForks {
+                forks_retention_limit: None,
+            },
+            probabilistic_finality_depth: None,
+            best_block_policy: smoldot_light::BestBlockPolicy::MostPrimarySlots,
+            runtime_call_cache_size: 32,
+            // Legitimate runtime calls consume at most a few billion units of fuel, which is
+            // well below this limit.
+            runtime_call_fuel_limit: Some(100_000_000_000),
+            max_cached_runtimes: NonZeroUsize::new(4).unwrap(),
+            validate_transactions_locally: true,
+            event_hooks: Default::default(),
         }) {
         Ok(c) => c,
         Err(error) => {
@@ -435,7 +465,7 @@ fn json_rpc_send(ptr: u32, len: u32, chain_id: u32) -> u32 {
         .json_rpc_request(json_rpc_request, client_chain_id)
     {
         Ok(()) => 0,
-        Err(HandleRpcError::MalformedJsonRpc(_)) => 1,
+        Err(HandleRpcError::MalformedJsonRpc(_) | HandleRpcError::BatchTooLarge { .. }) => 1,
         Err(HandleRpcError::Overloaded { .. }) => 2,
     }
 }